@@ -0,0 +1,249 @@
+//! Benchmarks of atlas growth, which re-uploads every cached glyph into the new texture.
+//!
+//! Each iteration starts from an atlas filled as close to capacity as the packer allows, then
+//! prepares the one glyph that no longer fits. The measurement is dominated by the grow; the
+//! single glyph prepared alongside it is negligible in comparison.
+
+use cosmic_text::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, SwashCache};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use metalglyph::{
+    Cache, ContentType, CustomGlyph, RasterizeCustomGlyphRequest, RasterizedCustomGlyph,
+    Resolution, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2_metal::MTLPixelFormat;
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
+
+mod state;
+
+/// The simulated cost of rasterizing a custom glyph, e.g. an SVG icon.
+const RASTERIZER_COST: Duration = Duration::from_micros(5);
+
+#[derive(Clone, Copy)]
+enum Fill {
+    /// Glyphs of the bundled font at increasing sizes. Only possible for the mask atlas, since
+    /// no color font is bundled.
+    Text,
+    /// Custom glyphs of varied sizes, rasterized at the given cost.
+    Custom(Duration),
+}
+
+/// The glyphs used to fill an atlas, one per text area so the fill can stop at any glyph.
+struct Glyphs {
+    empty: Buffer,
+    buffers: Vec<Buffer>,
+    custom_glyphs: Vec<CustomGlyph>,
+}
+
+impl Glyphs {
+    fn new(font_system: &mut FontSystem) -> Self {
+        Self {
+            empty: Buffer::new(font_system, Metrics::new(16.0, 20.0)),
+            buffers: Vec::new(),
+            custom_glyphs: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, font_system: &mut FontSystem, fill: Fill) {
+        let i = self.len();
+
+        match fill {
+            Fill::Text => {
+                // Cycle through printable ASCII, increasing the font size after each round
+                let c = char::from(b'!' + (i % 94) as u8);
+                let font_size = 8.0 + (i / 94) as f32 * 2.0;
+
+                let mut buffer =
+                    Buffer::new(font_system, Metrics::new(font_size, font_size * 1.25));
+                buffer.set_text(
+                    font_system,
+                    &c.to_string(),
+                    &Attrs::new().family(Family::Name("Inter")),
+                    Shaping::Basic,
+                );
+                buffer.shape_until_scroll(font_system, false);
+                self.buffers.push(buffer);
+            }
+            Fill::Custom(_) => self.custom_glyphs.push(CustomGlyph {
+                id: i as u16,
+                left: 0.0,
+                top: 0.0,
+                width: (16 + i * 37 % 113) as f32,
+                height: (16 + i * 53 % 97) as f32,
+                color: None,
+                snap_to_physical_pixel: true,
+                metadata: 0,
+            }),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.buffers.len().max(self.custom_glyphs.len())
+    }
+
+    fn text_areas(&self, range: std::ops::Range<usize>) -> Vec<TextArea<'_>> {
+        range
+            .map(|i| TextArea {
+                default_color: Color::rgb(0, 0, 0),
+                custom_glyphs: self.custom_glyphs.get(i..i + 1).unwrap_or(&[]),
+                ..TextArea::new(self.buffers.get(i).unwrap_or(&self.empty))
+            })
+            .collect()
+    }
+}
+
+fn rasterizer(
+    content_type: ContentType,
+    cost: Duration,
+) -> impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph> {
+    move |request| {
+        let start = Instant::now();
+        while start.elapsed() < cost {
+            std::hint::spin_loop();
+        }
+
+        let channels = match content_type {
+            ContentType::Mask => 1,
+            ContentType::Color => 4,
+        };
+
+        Some(RasterizedCustomGlyph {
+            data: vec![255; request.width as usize * request.height as usize * channels],
+            content_type,
+        })
+    }
+}
+
+fn run_bench(ctx: &mut Criterion) {
+    let state = state::State::new();
+
+    let mut font_system = state.font_system();
+    let mut swash_cache = SwashCache::new();
+    let cache = Cache::new(&state.device);
+    let mut viewport = Viewport::new(&state.device);
+    viewport.update(Resolution {
+        width: 1000,
+        height: 1000,
+    });
+
+    let mut group = ctx.benchmark_group("Atlas Grow");
+    // Filling large atlases takes a while, even though it is not measured
+    group.sample_size(10);
+
+    for (name, content_type, fill) in [
+        ("Mask - Text", ContentType::Mask, Fill::Text),
+        (
+            "Mask - Custom",
+            ContentType::Mask,
+            Fill::Custom(Duration::ZERO),
+        ),
+        (
+            "Mask - Costly Custom",
+            ContentType::Mask,
+            Fill::Custom(RASTERIZER_COST),
+        ),
+        (
+            "Color - Custom",
+            ContentType::Color,
+            Fill::Custom(Duration::ZERO),
+        ),
+        (
+            "Color - Costly Custom",
+            ContentType::Color,
+            Fill::Custom(RASTERIZER_COST),
+        ),
+    ] {
+        let cost = match fill {
+            Fill::Text => Duration::ZERO,
+            Fill::Custom(cost) => cost,
+        };
+        let mut glyphs = Glyphs::new(&mut font_system);
+
+        for (from, to) in [(512, 1024), (2048, 4096), (8192, 16384)] {
+            // Find the glyph that makes the atlas grow from `from` to `to`, adding glyphs one at a
+            // time so the fill stops as close to capacity as possible. Packing is deterministic,
+            // so preparing the same glyphs at once later reproduces the same layout.
+            let mut atlas = TextAtlas::new(&state.device, &cache, MTLPixelFormat::BGRA8Unorm);
+            let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+            let mut trigger = 0;
+
+            loop {
+                if trigger == glyphs.len() {
+                    glyphs.push(&mut font_system, fill);
+                }
+
+                text_renderer
+                    .prepare_with_custom(
+                        &mut font_system,
+                        &mut atlas,
+                        &viewport,
+                        glyphs.text_areas(trigger..trigger + 1),
+                        &mut swash_cache,
+                        rasterizer(content_type, Duration::ZERO),
+                    )
+                    .unwrap();
+
+                if atlas.size(content_type) >= to {
+                    break;
+                }
+
+                trigger += 1;
+            }
+
+            assert_eq!(atlas.size(content_type), to, "Glyph grew the atlas twice");
+            drop((atlas, text_renderer));
+
+            group.bench_function(format!("{name} - {from} to {to}"), |b| {
+                // Both the setup and the routine prepare text
+                let shared = RefCell::new((&mut font_system, &mut swash_cache));
+
+                b.iter_batched(
+                    || {
+                        let (font_system, swash_cache) = &mut *shared.borrow_mut();
+                        let mut atlas =
+                            TextAtlas::new(&state.device, &cache, MTLPixelFormat::BGRA8Unorm);
+                        let mut text_renderer =
+                            TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+                        text_renderer
+                            .prepare_with_custom(
+                                &mut **font_system,
+                                &mut atlas,
+                                &viewport,
+                                glyphs.text_areas(0..trigger),
+                                swash_cache,
+                                rasterizer(content_type, Duration::ZERO),
+                            )
+                            .unwrap();
+                        assert_eq!(atlas.size(content_type), from);
+
+                        (atlas, text_renderer)
+                    },
+                    |(mut atlas, mut text_renderer)| {
+                        let (font_system, swash_cache) = &mut *shared.borrow_mut();
+                        text_renderer
+                            .prepare_with_custom(
+                                &mut **font_system,
+                                &mut atlas,
+                                &viewport,
+                                glyphs.text_areas(trigger..trigger + 1),
+                                swash_cache,
+                                rasterizer(content_type, cost),
+                            )
+                            .unwrap();
+
+                        (atlas, text_renderer)
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, run_bench);
+criterion_main!(benches);
@@ -24,8 +24,7 @@ fn run_bench(ctx: &mut Criterion) {
         MTLPixelFormat::BGRA8Unorm,
         ColorMode::Web,
     );
-    let mut text_renderer =
-        TextRenderer::new(&mut atlas, &state.device, MTLPixelFormat::Depth32Float, 1);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Depth32Float, 1);
 
     let attrs = Attrs::new()
         .family(Family::SansSerif)
@@ -77,10 +76,6 @@ fn run_bench(ctx: &mut Criterion) {
                 let text_areas: Vec<TextArea> = buffers
                     .iter()
                     .map(|b| TextArea {
-                        buffer: b,
-                        left: 0.0,
-                        top: 0.0,
-                        scale: 1.0,
                         bounds: TextBounds {
                             left: 0,
                             top: 0,
@@ -88,14 +83,13 @@ fn run_bench(ctx: &mut Criterion) {
                             bottom: 1000,
                         },
                         default_color: Color::rgb(0, 0, 0),
-                        custom_glyphs: &[],
+                        ..TextArea::new(b)
                     })
                     .collect();
 
                 std::hint::black_box(
                     text_renderer
                         .prepare(
-                            &state.device,
                             &mut font_system,
                             &mut atlas,
                             &viewport,
@@ -0,0 +1,337 @@
+//! Benchmarks of `TextRenderer::prepare` with large, realistic workloads.
+//!
+//! Throughput is reported in glyphs per second. The number of bytes each workload uploads into a
+//! cold atlas is printed before it runs, and the number of vertex bytes it writes every frame
+//! before its warm benchmark, next to the bytes the vertices took up before their flags were
+//! packed into spare bits. Cold atlases are benchmarked with and without a CPU shadow of their
+//! textures, see `TextAtlas::set_cpu_shadow`.
+
+use cosmic_text::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, SwashCache};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use metalglyph::{
+    Cache, ContentType, CustomGlyph, RasterizeCustomGlyphRequest, RasterizedCustomGlyph,
+    Resolution, TextArea, TextAtlas, TextBounds, TextRenderer, Viewport,
+};
+use objc2_metal::MTLPixelFormat;
+use std::collections::HashSet;
+
+mod state;
+
+const SIZE: u32 = 2048;
+
+/// The bytes of vertex data per glyph of untransformed text.
+const VERTEX_SIZE: usize = 24;
+
+/// The bytes of vertex data per glyph before flags were packed into spare bits, the baseline of
+/// the vertex bytes per frame.
+const BASELINE_VERTEX_SIZE: usize = 28;
+
+struct Workload {
+    name: &'static str,
+    buffers: Vec<Buffer>,
+    custom_glyphs: Vec<CustomGlyph>,
+}
+
+impl Workload {
+    fn text_areas(&self) -> Vec<TextArea<'_>> {
+        let custom_glyphs_per_area = self.custom_glyphs.len().div_ceil(self.buffers.len());
+
+        self.buffers
+            .iter()
+            .enumerate()
+            .map(|(i, buffer)| {
+                // Lay small areas out in a grid, large ones fill the viewport
+                let (left, top) = if self.buffers.len() > 1 {
+                    ((i % 10) as f32 * 200.0, (i / 10) as f32 * 100.0)
+                } else {
+                    (0.0, 0.0)
+                };
+
+                TextArea {
+                    left,
+                    top,
+                    bounds: TextBounds {
+                        left: 0,
+                        top: 0,
+                        right: SIZE as i32,
+                        bottom: SIZE as i32,
+                    },
+                    default_color: Color::rgb(0, 0, 0),
+                    custom_glyphs: self
+                        .custom_glyphs
+                        .chunks(custom_glyphs_per_area.max(1))
+                        .nth(i)
+                        .unwrap_or(&[]),
+                    ..TextArea::new(buffer)
+                }
+            })
+            .collect()
+    }
+
+    fn glyph_count(&self) -> u64 {
+        let text_glyphs: usize = self
+            .buffers
+            .iter()
+            .flat_map(|buffer| buffer.layout_runs())
+            .map(|run| run.glyphs.len())
+            .sum();
+
+        (text_glyphs + self.custom_glyphs.len()) as u64
+    }
+
+    /// The number of bytes rasterized glyphs take up in a cold atlas.
+    fn bytes_uploaded(&self, font_system: &mut FontSystem, swash_cache: &mut SwashCache) -> usize {
+        let mut seen = HashSet::new();
+        let mut bytes = 0;
+
+        for area in self.text_areas() {
+            for run in area.buffer.layout_runs() {
+                for glyph in run.glyphs {
+                    let physical = glyph.physical((area.left, area.top), area.scale);
+
+                    if seen.insert(physical.cache_key) {
+                        bytes += swash_cache
+                            .get_image_uncached(font_system, physical.cache_key)
+                            .map_or(0, |image| image.data.len());
+                    }
+                }
+            }
+        }
+
+        let custom: HashSet<_> = self
+            .custom_glyphs
+            .iter()
+            .map(|glyph| (glyph.id, glyph.width as u16, glyph.height as u16))
+            .collect();
+
+        bytes
+            + custom
+                .into_iter()
+                .map(|(id, width, height)| {
+                    width as usize * height as usize * if id % 2 == 0 { 1 } else { 4 }
+                })
+                .sum::<usize>()
+    }
+}
+
+/// Rasterizes even ids as mask glyphs and odd ids as color glyphs.
+fn rasterize(request: RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph> {
+    let pixels = request.width as usize * request.height as usize;
+
+    Some(if request.id % 2 == 0 {
+        RasterizedCustomGlyph {
+            data: vec![255; pixels],
+            content_type: ContentType::Mask,
+        }
+    } else {
+        RasterizedCustomGlyph {
+            data: vec![255; pixels * 4],
+            content_type: ContentType::Color,
+        }
+    })
+}
+
+fn buffer(font_system: &mut FontSystem, text: &str, family: Family, width: f32) -> Buffer {
+    let mut buffer = Buffer::new(font_system, Metrics::new(16.0, 20.0));
+    buffer.set_size(font_system, Some(width), None);
+    buffer.set_text(
+        font_system,
+        text,
+        &Attrs::new().family(family),
+        Shaping::Advanced,
+    );
+    buffer.shape_until_scroll(font_system, false);
+    buffer
+}
+
+fn workloads(font_system: &mut FontSystem) -> Vec<Workload> {
+    // 5000 unique ideographs, starting at the beginning of the CJK Unified Ideographs block
+    let cjk: String = (0x4E00..0x4E00 + 5000)
+        .filter_map(char::from_u32)
+        .collect::<Vec<_>>()
+        .chunks(100)
+        .map(|line| line.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let latin = include_str!("../samples/latin.txt").repeat(10);
+    let latin_lines: Vec<&str> = latin.lines().filter(|line| !line.is_empty()).collect();
+
+    let small_areas = (0..200)
+        .map(|i| {
+            buffer(
+                font_system,
+                latin_lines[i % latin_lines.len()],
+                Family::Name("Inter"),
+                200.0,
+            )
+        })
+        .collect();
+
+    let custom_glyphs = (0..100)
+        .map(|i| CustomGlyph {
+            id: i,
+            left: (i % 10) as f32 * 40.0,
+            top: (i / 10) as f32 * 40.0,
+            width: 16.0 + (i % 5) as f32 * 4.0,
+            height: 16.0 + (i % 7) as f32 * 4.0,
+            color: None,
+            snap_to_physical_pixel: true,
+            metadata: 0,
+        })
+        .collect();
+
+    vec![
+        Workload {
+            name: "CJK - 5k Unique Glyphs",
+            buffers: vec![buffer(
+                font_system,
+                &cjk,
+                Family::Name("Hiragino Sans GB"),
+                SIZE as f32,
+            )],
+            custom_glyphs: Vec::new(),
+        },
+        Workload {
+            name: "Latin - 200 Small Text Areas",
+            buffers: small_areas,
+            custom_glyphs: Vec::new(),
+        },
+        Workload {
+            name: "Latin - 1 Large Text Area",
+            buffers: vec![buffer(
+                font_system,
+                &latin_lines[..200].join("\n"),
+                Family::Name("Inter"),
+                SIZE as f32,
+            )],
+            custom_glyphs: Vec::new(),
+        },
+        Workload {
+            // Only the first hundred or so of the 4000 lines fit in the viewport
+            name: "Latin - Long Document",
+            buffers: vec![buffer(
+                font_system,
+                &latin_lines.repeat(4000_usize.div_ceil(latin_lines.len()))[..4000].join("\n"),
+                Family::Name("Inter"),
+                SIZE as f32,
+            )],
+            custom_glyphs: Vec::new(),
+        },
+        Workload {
+            name: "Latin - 100 Custom Glyphs",
+            buffers: vec![buffer(
+                font_system,
+                &latin_lines[..20].join("\n"),
+                Family::Name("Inter"),
+                SIZE as f32,
+            )],
+            custom_glyphs,
+        },
+    ]
+}
+
+fn run_bench(ctx: &mut Criterion) {
+    let state = state::State::new();
+
+    let mut font_system = state.font_system();
+    let mut swash_cache = SwashCache::new();
+    let cache = Cache::new(&state.device);
+    let mut viewport = Viewport::new(&state.device);
+    viewport.update(Resolution {
+        width: SIZE,
+        height: SIZE,
+    });
+
+    let workloads = workloads(&mut font_system);
+
+    let mut group = ctx.benchmark_group("Prepare Workloads");
+    group.noise_threshold(0.02);
+
+    for workload in &workloads {
+        println!(
+            "{}: {} glyphs, {} bytes uploaded into a cold atlas",
+            workload.name,
+            workload.glyph_count(),
+            workload.bytes_uploaded(&mut font_system, &mut swash_cache)
+        );
+
+        group.throughput(Throughput::Elements(workload.glyph_count()));
+
+        // Every iteration starts with an empty atlas, so all glyphs are rasterized and uploaded
+        for (suffix, cpu_shadow) in [("Cold", false), ("Cold, CPU Shadow", true)] {
+            group.bench_function(format!("{} - {suffix}", workload.name), |b| {
+                b.iter_batched(
+                    || {
+                        let mut atlas =
+                            TextAtlas::new(&state.device, &cache, MTLPixelFormat::BGRA8Unorm);
+                        atlas.set_cpu_shadow(cpu_shadow);
+                        let text_renderer =
+                            TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+                        (atlas, text_renderer)
+                    },
+                    |(mut atlas, mut text_renderer)| {
+                        text_renderer
+                            .prepare_with_custom(
+                                &mut font_system,
+                                &mut atlas,
+                                &viewport,
+                                workload.text_areas(),
+                                &mut swash_cache,
+                                rasterize,
+                            )
+                            .unwrap();
+
+                        (atlas, text_renderer)
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+        }
+
+        // Every iteration re-prepares the same content, so all glyphs are cached
+        let mut atlas = TextAtlas::new(&state.device, &cache, MTLPixelFormat::BGRA8Unorm);
+        let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+        text_renderer
+            .prepare_with_custom(
+                &mut font_system,
+                &mut atlas,
+                &viewport,
+                workload.text_areas(),
+                &mut swash_cache,
+                rasterize,
+            )
+            .unwrap();
+        let bytes = text_renderer.vertex_bytes();
+        let glyphs = bytes / VERTEX_SIZE;
+        println!(
+            "{name}: {bytes} vertex bytes per frame ({VERTEX_SIZE} per glyph), baseline {baseline} \
+             ({BASELINE_VERTEX_SIZE} per glyph)",
+            name = workload.name,
+            baseline = glyphs * BASELINE_VERTEX_SIZE,
+        );
+
+        group.bench_function(format!("{} - Warm", workload.name), |b| {
+            b.iter(|| {
+                text_renderer
+                    .prepare_with_custom(
+                        &mut font_system,
+                        &mut atlas,
+                        &viewport,
+                        workload.text_areas(),
+                        &mut swash_cache,
+                        rasterize,
+                    )
+                    .unwrap();
+
+                atlas.trim();
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, run_bench);
+criterion_main!(benches);
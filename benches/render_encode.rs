@@ -0,0 +1,176 @@
+//! Benchmarks of the CPU time `TextRenderer::render` takes to encode commands, as the number of
+//! text areas grows while the total number of glyphs stays constant.
+//!
+//! "Single Render" prepares all areas with one renderer and renders once. "Per-Area Render" uses
+//! one renderer per area and renders each of them, which is what per-area rendering costs today.
+//! Only encoding is measured; command buffers are never committed.
+
+use cosmic_text::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, SwashCache};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use metalglyph::{Cache, Resolution, TextArea, TextAtlas, TextRenderer, Viewport};
+use objc2_metal::{
+    MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue as _, MTLDevice as _,
+    MTLLoadAction, MTLPixelFormat, MTLRenderPassDescriptor, MTLStorageMode, MTLStoreAction,
+    MTLTextureDescriptor, MTLTextureUsage,
+};
+use std::time::{Duration, Instant};
+
+mod state;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+const SIZE: u32 = 1024;
+const TOTAL_GLYPHS: usize = 10_000;
+
+fn buffers(font_system: &mut FontSystem, area_count: usize) -> Vec<Buffer> {
+    let text: String = "abcdefghijklmnopqrstuvwxyz"
+        .chars()
+        .cycle()
+        .take(TOTAL_GLYPHS / area_count)
+        .collect();
+
+    (0..area_count)
+        .map(|_| {
+            let mut buffer = Buffer::new(font_system, Metrics::new(12.0, 16.0));
+            buffer.set_size(font_system, Some(SIZE as f32), None);
+            buffer.set_text(
+                font_system,
+                &text,
+                &Attrs::new().family(Family::Name("Inter")),
+                Shaping::Basic,
+            );
+            buffer.shape_until_scroll(font_system, false);
+            buffer
+        })
+        .collect()
+}
+
+fn text_area(buffer: &Buffer, i: usize) -> TextArea<'_> {
+    TextArea {
+        top: (i % 64) as f32 * 16.0,
+        default_color: Color::rgb(0, 0, 0),
+        ..TextArea::new(buffer)
+    }
+}
+
+fn run_bench(ctx: &mut Criterion) {
+    let state = state::State::new();
+    let queue = state
+        .device
+        .newCommandQueue()
+        .expect("Create command queue");
+
+    let mut font_system = state.font_system();
+    let mut swash_cache = SwashCache::new();
+    let cache = Cache::new(&state.device);
+    let mut atlas = TextAtlas::new(&state.device, &cache, FORMAT);
+    let mut viewport = Viewport::new(&state.device);
+    viewport.update(Resolution {
+        width: SIZE,
+        height: SIZE,
+    });
+
+    let descriptor = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            FORMAT,
+            SIZE as usize,
+            SIZE as usize,
+            false,
+        )
+    };
+    descriptor.setUsage(MTLTextureUsage::RenderTarget);
+    descriptor.setStorageMode(MTLStorageMode::Private);
+    let target = state
+        .device
+        .newTextureWithDescriptor(&descriptor)
+        .expect("Create render target");
+
+    let render_pass_descriptor = MTLRenderPassDescriptor::new();
+    let color_attachment = unsafe {
+        render_pass_descriptor
+            .colorAttachments()
+            .objectAtIndexedSubscript(0)
+    };
+    color_attachment.setTexture(Some(&target));
+    color_attachment.setLoadAction(MTLLoadAction::Clear);
+    color_attachment.setStoreAction(MTLStoreAction::Store);
+
+    // Measures the encoding of `renderers`, excluding the creation of the command buffer and the
+    // encoder
+    let encode = |renderers: &[TextRenderer], atlas: &TextAtlas, iters: u64| {
+        let mut total = Duration::ZERO;
+
+        for _ in 0..iters {
+            let command_buffer = queue.commandBuffer().expect("Create command buffer");
+            let encoder = command_buffer
+                .renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+                .expect("Create render command encoder");
+
+            let start = Instant::now();
+            for renderer in renderers {
+                renderer.render(atlas, &viewport, &encoder);
+            }
+            total += start.elapsed();
+
+            encoder.endEncoding();
+        }
+
+        total
+    };
+
+    let mut group = ctx.benchmark_group("Render Encode");
+    group.throughput(Throughput::Elements(TOTAL_GLYPHS as u64));
+
+    for area_count in [1, 10, 100, 1000] {
+        let buffers = buffers(&mut font_system, area_count);
+
+        let mut single = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+        single
+            .prepare(
+                &mut font_system,
+                &mut atlas,
+                &viewport,
+                buffers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, buffer)| text_area(buffer, i)),
+                &mut swash_cache,
+            )
+            .unwrap();
+
+        let per_area: Vec<TextRenderer> = buffers
+            .iter()
+            .enumerate()
+            .map(|(i, buffer)| {
+                let mut renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+                renderer
+                    .prepare(
+                        &mut font_system,
+                        &mut atlas,
+                        &viewport,
+                        [text_area(buffer, i)],
+                        &mut swash_cache,
+                    )
+                    .unwrap();
+                renderer
+            })
+            .collect();
+
+        group.bench_with_input(
+            BenchmarkId::new("Single Render", area_count),
+            &area_count,
+            |b, _| b.iter_custom(|iters| encode(std::slice::from_ref(&single), &atlas, iters)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("Per-Area Render", area_count),
+            &area_count,
+            |b, _| b.iter_custom(|iters| encode(&per_area, &atlas, iters)),
+        );
+
+        atlas.trim();
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, run_bench);
+criterion_main!(benches);
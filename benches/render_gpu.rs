@@ -0,0 +1,177 @@
+//! Benchmarks of the GPU time of rendering prepared text, read from the start and end times of
+//! committed command buffers.
+//!
+//! "Mask Only" renders plain text. "Mixed" additionally places a color custom glyph in the space
+//! after every word, so that the frame samples both the mask and the color atlas. Each command
+//! buffer renders the same text several times to dominate the fixed cost of the render pass.
+//! Compare against a baseline saved with `--save-baseline` to measure changes of the shaders.
+
+use cosmic_text::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, SwashCache};
+use criterion::{criterion_group, criterion_main, Criterion};
+use metalglyph::{
+    Cache, ContentType, CustomGlyph, RasterizeCustomGlyphRequest, RasterizedCustomGlyph,
+    Resolution, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2_metal::{
+    MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue as _, MTLDevice as _,
+    MTLLoadAction, MTLPixelFormat, MTLRenderPassDescriptor, MTLStorageMode, MTLStoreAction,
+    MTLTextureDescriptor, MTLTextureUsage,
+};
+use std::time::Duration;
+
+mod state;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+const SIZE: u32 = 2048;
+const RENDERS_PER_FRAME: usize = 16;
+
+fn buffer(font_system: &mut FontSystem) -> Buffer {
+    let mut buffer = Buffer::new(font_system, Metrics::new(16.0, 20.0));
+    buffer.set_size(font_system, Some(SIZE as f32), Some(SIZE as f32));
+    buffer.set_text(
+        font_system,
+        &include_str!("../samples/latin.txt").repeat(20),
+        &Attrs::new().family(Family::Name("Inter")),
+        Shaping::Advanced,
+    );
+    buffer.shape_until_scroll(font_system, false);
+    buffer
+}
+
+/// Fills the space after every word of `buffer` with a color glyph, without overlapping the text
+/// so that glyphs can be grouped by content type.
+fn custom_glyphs(buffer: &Buffer) -> Vec<CustomGlyph> {
+    buffer
+        .layout_runs()
+        .flat_map(|run| {
+            let line_top = run.line_top;
+            run.glyphs
+                .windows(2)
+                .filter(|pair| pair[0].end != pair[1].start)
+                .map(move |pair| {
+                    let left = (pair[0].x + pair[0].w).ceil();
+                    (left, (pair[1].x.floor() - left), line_top)
+                })
+                .filter(|&(_, width, _)| width >= 1.0)
+                .collect::<Vec<_>>()
+        })
+        .map(|(left, width, top)| CustomGlyph {
+            id: width as u16,
+            left,
+            top: top + 4.0,
+            width,
+            height: 12.0,
+            color: None,
+            snap_to_physical_pixel: true,
+            metadata: 0,
+        })
+        .collect()
+}
+
+fn rasterize(request: RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph> {
+    let pixels = request.width as usize * request.height as usize;
+
+    Some(RasterizedCustomGlyph {
+        data: vec![255; pixels * 4],
+        content_type: ContentType::Color,
+    })
+}
+
+fn run_bench(ctx: &mut Criterion) {
+    let state = state::State::new();
+    let queue = state
+        .device
+        .newCommandQueue()
+        .expect("Create command queue");
+
+    let mut font_system = state.font_system();
+    let mut swash_cache = SwashCache::new();
+    let cache = Cache::new(&state.device);
+    let mut atlas = TextAtlas::new(&state.device, &cache, FORMAT);
+    let mut viewport = Viewport::new(&state.device);
+    viewport.update(Resolution {
+        width: SIZE,
+        height: SIZE,
+    });
+
+    let descriptor = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            FORMAT,
+            SIZE as usize,
+            SIZE as usize,
+            false,
+        )
+    };
+    descriptor.setUsage(MTLTextureUsage::RenderTarget);
+    descriptor.setStorageMode(MTLStorageMode::Private);
+    let target = state
+        .device
+        .newTextureWithDescriptor(&descriptor)
+        .expect("Create render target");
+
+    let render_pass_descriptor = MTLRenderPassDescriptor::new();
+    let color_attachment = unsafe {
+        render_pass_descriptor
+            .colorAttachments()
+            .objectAtIndexedSubscript(0)
+    };
+    color_attachment.setTexture(Some(&target));
+    color_attachment.setLoadAction(MTLLoadAction::Clear);
+    color_attachment.setStoreAction(MTLStoreAction::Store);
+
+    let buffer = buffer(&mut font_system);
+    let custom_glyphs = custom_glyphs(&buffer);
+
+    let mut group = ctx.benchmark_group("Render GPU");
+
+    for (name, custom_glyphs) in [("Mask Only", &[][..]), ("Mixed", &custom_glyphs[..])] {
+        let mut renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+        renderer
+            .prepare_with_custom(
+                &mut font_system,
+                &mut atlas,
+                &viewport,
+                [TextArea {
+                    default_color: Color::rgb(0, 0, 0),
+                    custom_glyphs,
+                    ..TextArea::new(&buffer)
+                }],
+                &mut swash_cache,
+                rasterize,
+            )
+            .unwrap();
+
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                let mut total = Duration::ZERO;
+
+                for _ in 0..iters {
+                    let command_buffer = queue.commandBuffer().expect("Create command buffer");
+                    let encoder = command_buffer
+                        .renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+                        .expect("Create render command encoder");
+
+                    for _ in 0..RENDERS_PER_FRAME {
+                        renderer.render(&atlas, &viewport, &encoder);
+                    }
+                    encoder.endEncoding();
+
+                    command_buffer.commit();
+                    command_buffer.waitUntilCompleted();
+
+                    let gpu_time = command_buffer.GPUEndTime() - command_buffer.GPUStartTime();
+                    total += Duration::from_secs_f64(gpu_time);
+                }
+
+                total
+            })
+        });
+
+        atlas.trim();
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, run_bench);
+criterion_main!(benches);
@@ -1,6 +1,12 @@
+use cosmic_text::{fontdb, FontSystem};
 use objc2::{rc::Retained, runtime::ProtocolObject};
 use objc2_metal::{MTLCreateSystemDefaultDevice, MTLDevice};
 
+const FONT: &[u8] = include_bytes!("../examples/Inter-Bold.ttf");
+
+/// A CJK font shipped with every version of macOS. Override it with `METALGLYPH_BENCH_CJK_FONT`.
+const DEFAULT_CJK_FONT: &str = "/System/Library/Fonts/Hiragino Sans GB.ttc";
+
 pub struct State {
     pub device: Retained<ProtocolObject<dyn MTLDevice>>,
 }
@@ -11,4 +17,20 @@ impl State {
 
         Self { device }
     }
+
+    /// Creates a [`FontSystem`] without system fonts, so results are comparable across machines.
+    ///
+    /// It contains the bundled Inter font and a CJK font for the CJK workloads.
+    #[allow(dead_code)]
+    pub fn font_system(&self) -> FontSystem {
+        let mut db = fontdb::Database::new();
+        db.load_font_data(FONT.to_vec());
+
+        let cjk_font = std::env::var("METALGLYPH_BENCH_CJK_FONT")
+            .unwrap_or_else(|_| DEFAULT_CJK_FONT.to_owned());
+        db.load_font_file(&cjk_font)
+            .unwrap_or_else(|err| panic!("Load CJK font {cjk_font}: {err}"));
+
+        FontSystem::new_with_locale_and_db("en-US".to_owned(), db)
+    }
 }
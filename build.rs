@@ -0,0 +1,10 @@
+fn main() {
+    // The `os_signpost` API consists of macros, which the shim wraps
+    #[cfg(feature = "signposts")]
+    if std::env::var("CARGO_CFG_TARGET_VENDOR").as_deref() == Ok("apple") {
+        println!("cargo:rerun-if-changed=src/signpost.c");
+        cc::Build::new()
+            .file("src/signpost.c")
+            .compile("metalglyph_signpost");
+    }
+}
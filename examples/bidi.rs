@@ -0,0 +1,317 @@
+//! Right-to-left and bidirectional text.
+//!
+//! Renders Arabic and Hebrew paragraphs, lines mixing both directions, and an Arabic line clipped
+//! by `TextBounds` in the middle of a run. The clipped line is drawn over a tinted rectangle that
+//! shows its bounds, so glyphs must stop exactly at its edges. Shaping, including Arabic joining
+//! and the bidi reordering of mixed lines, is done by cosmic-text with `Shaping::Advanced`.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Color, ContentType, CustomGlyph, Family, FontSystem, Metrics,
+    RasterizeCustomGlyphRequest, RasterizedCustomGlyph, Resolution, Shaping, SwashCache, TextArea,
+    TextAtlas, TextBounds, TextRenderer, Viewport,
+};
+use objc2::{
+    rc::{autoreleasepool, Retained},
+    runtime::ProtocolObject,
+};
+use objc2_app_kit::NSView;
+use objc2_core_foundation::CGSize;
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCreateSystemDefaultDevice, MTLDevice, MTLLoadAction, MTLPixelFormat,
+    MTLRenderPassDescriptor, MTLStoreAction,
+};
+use objc2_quartz_core::{CAMetalDrawable, CAMetalLayer};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::sync::Arc;
+use winit::{dpi::LogicalSize, event::WindowEvent, event_loop::EventLoop, window::Window};
+
+const ARABIC: &str = "الخط العربي فن من الفنون الإسلامية، وتتصل حروفه ببعضها في الكلمة الواحدة، \
+                      فيتغير شكل الحرف بحسب موضعه في أولها أو وسطها أو آخرها.";
+const HEBREW: &str = "עברית נכתבת מימין לשמאל, והאותיות אינן מתחברות זו לזו. \
+                      לחמש אותיות יש צורה סופית בסוף המילה: ך ם ן ף ץ.";
+const MIXED: &str = "Mixed lines: the word مرحبا means hello, and שלום means peace.\n\
+                     أطلق الإصدار 2.5 من metalglyph في 12 مايو مع دعم Metal 4.\n\
+                     הגרסה 0.9 של metalglyph תומכת ב־Metal 4 ובטקסט דו־כיווני.";
+const CLIPPED: &str = "هذا السطر مقصوص في منتصف الكلمات ليظهر حدود القص بوضوح";
+
+/// Layout of the clipped area, in logical pixels.
+const CLIP_TOP: f32 = 420.0;
+const CLIP_LEFT: f32 = 180.0;
+const CLIP_WIDTH: f32 = 300.0;
+const CLIP_HEIGHT: f32 = 28.0;
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+/// Rasterizes the solid rectangle showing the clipped bounds.
+fn rasterize(request: RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph> {
+    Some(RasterizedCustomGlyph {
+        data: vec![255; request.width as usize * request.height as usize],
+        content_type: ContentType::Mask,
+    })
+}
+
+struct WindowState {
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+
+    surface: Retained<CAMetalLayer>,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+    paragraphs: Vec<Buffer>,
+    clipped: Buffer,
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the Metal layer is dropped.
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(window: Arc<Window>) -> Self {
+        let physical_size = window.inner_size();
+
+        let view = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+        let queue = device.newCommandQueue().expect("Create command queue");
+
+        let surface = CAMetalLayer::new();
+        surface.setDevice(Some(&device));
+        surface.setPixelFormat(MTLPixelFormat::BGRA8Unorm);
+        surface.setPresentsWithTransaction(false);
+
+        surface.setDrawableSize(CGSize {
+            width: physical_size.width as f64,
+            height: physical_size.height as f64,
+        });
+
+        view.setWantsLayer(true);
+        view.setLayer(Some(&surface));
+
+        // Set up text renderer
+        let mut font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        let cache = Cache::new(&device);
+        let viewport = Viewport::new(&device);
+        let mut atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+        // Buffers are laid out in logical pixels, the viewport applies the scale factor. With a
+        // width, RTL paragraphs are aligned to the right edge.
+        let mut buffer = |text: &str, width: Option<f32>| {
+            let mut buffer = Buffer::new(&mut font_system, Metrics::new(20.0, 28.0));
+            buffer.set_size(&mut font_system, width, None);
+            buffer.set_text(
+                &mut font_system,
+                text,
+                &Attrs::new().family(Family::SansSerif),
+                Shaping::Advanced,
+            );
+            buffer.shape_until_scroll(&mut font_system, false);
+            buffer
+        };
+
+        let paragraphs = [ARABIC, HEBREW, MIXED]
+            .into_iter()
+            .map(|text| buffer(text, Some(660.0)))
+            .collect();
+        // Without a width, the line is laid out in full and only the bounds clip it
+        let clipped = buffer(CLIPPED, None);
+
+        Self {
+            queue,
+
+            surface,
+
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            paragraphs,
+            clipped,
+
+            window,
+        }
+    }
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        // Set up window
+        let (width, height) = (700, 480);
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_title("metalglyph bidirectional text");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(WindowState::new(window));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        let WindowState {
+            window,
+            queue,
+            surface,
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            paragraphs,
+            clipped,
+            ..
+        } = state;
+
+        match event {
+            WindowEvent::Resized(size) => {
+                surface.setDrawableSize(CGSize {
+                    width: size.width as f64,
+                    height: size.height as f64,
+                });
+                window.request_redraw();
+            }
+
+            WindowEvent::RedrawRequested => {
+                autoreleasepool(|_| {
+                    let drawable = match surface.nextDrawable() {
+                        Some(drawable) => drawable,
+                        None => panic!("Failed to get next drawable"),
+                    };
+
+                    let resolution = Resolution {
+                        width: surface.drawableSize().width as u32,
+                        height: surface.drawableSize().height as u32,
+                    };
+
+                    viewport.update_with_scale(resolution, window.scale_factor() as f32);
+
+                    // Stack the paragraphs vertically
+                    let mut top = 20.0;
+                    let mut text_areas: Vec<TextArea> = paragraphs
+                        .iter()
+                        .map(|buffer| {
+                            let text_area = TextArea {
+                                left: 20.0,
+                                top,
+                                ..TextArea::new(buffer)
+                            };
+                            top += buffer.layout_runs().count() as f32 * 28.0 + 24.0;
+                            text_area
+                        })
+                        .collect();
+
+                    // Shift the line so that it overflows both edges of the bounds, which clip
+                    // it in the middle of words. The bounds are tinted by a rectangle drawn
+                    // before the text, placed relative to the shifted area.
+                    let line_width = clipped.layout_runs().next().map_or(0.0, |run| run.line_w);
+                    let overflow = (line_width - CLIP_WIDTH).max(0.0) / 2.0;
+                    let background = [CustomGlyph {
+                        id: 0,
+                        left: overflow,
+                        top: 0.0,
+                        width: CLIP_WIDTH,
+                        height: CLIP_HEIGHT,
+                        color: Some(Color::rgb(50, 50, 90)),
+                        snap_to_physical_pixel: true,
+                        metadata: 0,
+                    }];
+                    text_areas.push(TextArea {
+                        left: CLIP_LEFT - overflow,
+                        top: CLIP_TOP,
+                        bounds: TextBounds {
+                            left: CLIP_LEFT as i32,
+                            top: CLIP_TOP as i32,
+                            right: (CLIP_LEFT + CLIP_WIDTH) as i32,
+                            bottom: (CLIP_TOP + CLIP_HEIGHT) as i32,
+                        },
+                        default_color: Color::rgb(255, 200, 120),
+                        custom_glyphs: &background,
+                        ..TextArea::new(clipped)
+                    });
+
+                    text_renderer
+                        .prepare_with_custom(
+                            font_system,
+                            atlas,
+                            viewport,
+                            text_areas,
+                            swash_cache,
+                            rasterize,
+                        )
+                        .unwrap();
+
+                    let render_pass_descriptor = MTLRenderPassDescriptor::new();
+                    let color_attachment = unsafe {
+                        render_pass_descriptor
+                            .colorAttachments()
+                            .objectAtIndexedSubscript(0)
+                    };
+
+                    color_attachment.setTexture(Some(&drawable.texture()));
+                    color_attachment.setLoadAction(MTLLoadAction::Clear);
+                    color_attachment.setClearColor(MTLClearColor {
+                        red: 0.0,
+                        green: 0.0,
+                        blue: 0.0,
+                        alpha: 1.0,
+                    });
+                    color_attachment.setStoreAction(MTLStoreAction::Store);
+
+                    let Some(buffer) = queue.commandBuffer() else {
+                        return;
+                    };
+
+                    let Some(render_encoder) =
+                        buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+                    else {
+                        return;
+                    };
+
+                    text_renderer.render(atlas, viewport, &render_encoder);
+
+                    render_encoder.endEncoding();
+
+                    buffer.presentDrawable(drawable.as_ref());
+                    buffer.commit();
+                    atlas.trim();
+                });
+            }
+
+            WindowEvent::CloseRequested => event_loop.exit(),
+
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,391 @@
+//! CJK text at many sizes, the worst case for the glyph atlas.
+//!
+//! Chinese and Japanese paragraphs use hundreds of unique glyphs, and every font size needs its
+//! own copy of each of them. Every size is prepared on every frame, including the ones that are
+//! scrolled out of the window, so all of their glyphs have to fit in the atlas at once.
+//!
+//! Press `+` to add a larger size and `-` to remove the largest. The overlay shows the
+//! statistics of the atlas: its size grows until it reaches the maximum, after which `prepare`
+//! fails with `PrepareError::AtlasFull` and the text is no longer drawn. Remove a size to recover.
+//! Evictions only happen when the atlas has no room for a new glyph and glyphs from previous
+//! frames can be discarded, which is never the case here since every glyph is used every frame.
+//!
+//! The overlay uses its own atlas, so it stays visible when the main atlas is full.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Color, ContentType, Family, FontSystem, Metrics, PrepareError,
+    Resolution, Shaping, SwashCache, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2::{
+    rc::{autoreleasepool, Retained},
+    runtime::ProtocolObject,
+};
+use objc2_app_kit::NSView;
+use objc2_core_foundation::CGSize;
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCreateSystemDefaultDevice, MTLDevice, MTLLoadAction, MTLPixelFormat,
+    MTLRenderPassDescriptor, MTLStoreAction,
+};
+use objc2_quartz_core::{CAMetalDrawable, CAMetalLayer};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::sync::Arc;
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, WindowEvent},
+    event_loop::EventLoop,
+    keyboard::Key,
+    window::Window,
+};
+
+const TEXT: &str = "\
+春眠不觉晓，处处闻啼鸟。夜来风雨声，花落知多少。床前明月光，疑是地上霜。举头望明月，低头思故乡。\
+白日依山尽，黄河入海流。欲穷千里目，更上一层楼。千山鸟飞绝，万径人踪灭。孤舟蓑笠翁，独钓寒江雪。\
+文字渲染需要为每一个字形在图集中分配空间，而中文常用字有数千个，几乎没有重复。\n\
+古池や蛙飛び込む水の音。閑さや岩にしみ入る蝉の声。五月雨をあつめて早し最上川。\
+日本語の文章は漢字、平仮名、片仮名を組み合わせて書かれるため、使われる字形の種類がとても多い。\
+東京、大阪、京都、北海道、沖縄。春夏秋冬、朝昼晩、東西南北、上下左右。\n\
+한국어 문장도 섞어 봅니다. 글리프 아틀라스가 가득 차면 어떻게 되는지 확인해 보세요.";
+
+/// The font sizes rendered at startup, in logical pixels.
+const INITIAL_SIZES: [f32; 4] = [12.0, 16.0, 24.0, 36.0];
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+struct WindowState {
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+
+    surface: Retained<CAMetalLayer>,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+    overlay_atlas: TextAtlas,
+    overlay_renderer: TextRenderer,
+    /// One buffer per font size.
+    buffers: Vec<Buffer>,
+    overlay: Buffer,
+    /// The error of the most recent `prepare`, if it failed.
+    error: Option<PrepareError>,
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the Metal layer is dropped.
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(window: Arc<Window>) -> Self {
+        let physical_size = window.inner_size();
+
+        let view = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+        let queue = device.newCommandQueue().expect("Create command queue");
+
+        let surface = CAMetalLayer::new();
+        surface.setDevice(Some(&device));
+        surface.setPixelFormat(MTLPixelFormat::BGRA8Unorm);
+        surface.setPresentsWithTransaction(false);
+
+        surface.setDrawableSize(CGSize {
+            width: physical_size.width as f64,
+            height: physical_size.height as f64,
+        });
+
+        view.setWantsLayer(true);
+        view.setLayer(Some(&surface));
+
+        // Set up text renderers
+        let mut font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        let cache = Cache::new(&device);
+        let viewport = Viewport::new(&device);
+        let mut atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+        let mut overlay_atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
+        let overlay_renderer = TextRenderer::new(&mut overlay_atlas, MTLPixelFormat::Invalid, 1);
+
+        let overlay = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+
+        let mut state = Self {
+            queue,
+
+            surface,
+
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            overlay_atlas,
+            overlay_renderer,
+            buffers: Vec::new(),
+            overlay,
+            error: None,
+
+            window,
+        };
+
+        for size in INITIAL_SIZES {
+            state.add_size(size);
+        }
+
+        state
+    }
+
+    fn add_size(&mut self, font_size: f32) {
+        let width = self
+            .window
+            .inner_size()
+            .to_logical(self.window.scale_factor())
+            .width;
+
+        let mut buffer = Buffer::new(
+            &mut self.font_system,
+            Metrics::new(font_size, font_size * 1.4),
+        );
+        buffer.set_size(&mut self.font_system, Some(width), None);
+        buffer.set_text(
+            &mut self.font_system,
+            TEXT,
+            &Attrs::new().family(Family::SansSerif),
+            Shaping::Advanced,
+        );
+        buffer.shape_until_scroll(&mut self.font_system, false);
+
+        self.buffers.push(buffer);
+    }
+
+    fn resize(&mut self) {
+        let size = self.window.inner_size();
+        self.surface.setDrawableSize(CGSize {
+            width: size.width as f64,
+            height: size.height as f64,
+        });
+
+        let width = size.to_logical(self.window.scale_factor()).width;
+        for buffer in &mut self.buffers {
+            buffer.set_size(&mut self.font_system, Some(width), None);
+            buffer.shape_until_scroll(&mut self.font_system, false);
+        }
+
+        self.window.request_redraw();
+    }
+
+    fn update_overlay(&mut self) {
+        let mut text = format!(
+            "sizes: {:?}\n",
+            self.buffers
+                .iter()
+                .map(|buffer| buffer.metrics().font_size)
+                .collect::<Vec<_>>()
+        );
+
+        for (name, content_type) in [("mask", ContentType::Mask), ("color", ContentType::Color)] {
+            let stats = self.atlas.stats(content_type);
+            text += &format!(
+                "{name} atlas: {size}x{size} px, {occupancy:.1}% occupied, {glyphs} glyphs, \
+                 {grows} grows, {evictions} evictions, {hit_rate:.1}% hits, \
+                 {rerasterizations} re-rasterized\n",
+                size = stats.size,
+                occupancy = stats.occupancy * 100.0,
+                glyphs = stats.glyph_count,
+                grows = stats.grows,
+                evictions = stats.evictions,
+                hit_rate = stats.hit_rate() * 100.0,
+                rerasterizations = stats.rerasterizations,
+            );
+        }
+
+        match &self.error {
+            Some(error) => text += &format!("prepare failed: {error}"),
+            None => text += "+ add a size, - remove the largest",
+        }
+
+        self.overlay.set_text(
+            &mut self.font_system,
+            &text,
+            &Attrs::new().family(Family::Monospace),
+            Shaping::Basic,
+        );
+        self.overlay
+            .shape_until_scroll(&mut self.font_system, false);
+    }
+
+    fn redraw(&mut self) {
+        autoreleasepool(|_| {
+            let Some(drawable) = self.surface.nextDrawable() else {
+                return;
+            };
+
+            let resolution = Resolution {
+                width: self.surface.drawableSize().width as u32,
+                height: self.surface.drawableSize().height as u32,
+            };
+
+            self.viewport
+                .update_with_scale(resolution, self.window.scale_factor() as f32);
+
+            // Stack the sizes below the overlay. All of them are prepared, even the ones below
+            // the bottom of the window, since the bounds do not clip them.
+            let mut top = 120.0;
+            let text_areas: Vec<TextArea> = self
+                .buffers
+                .iter()
+                .map(|buffer| {
+                    let text_area = TextArea {
+                        left: 10.0,
+                        top,
+                        ..TextArea::new(buffer)
+                    };
+                    top += buffer.layout_runs().count() as f32 * buffer.metrics().line_height;
+                    text_area
+                })
+                .collect();
+
+            // A failed prepare leaves the renderer with incomplete geometry, so it is not
+            // rendered until a prepare succeeds again
+            self.error = self
+                .text_renderer
+                .prepare(
+                    &mut self.font_system,
+                    &mut self.atlas,
+                    &self.viewport,
+                    text_areas,
+                    &mut self.swash_cache,
+                )
+                .err();
+
+            self.update_overlay();
+            self.overlay_renderer
+                .prepare(
+                    &mut self.font_system,
+                    &mut self.overlay_atlas,
+                    &self.viewport,
+                    [TextArea {
+                        left: 10.0,
+                        top: 10.0,
+                        default_color: Color::rgb(255, 210, 80),
+                        ..TextArea::new(&self.overlay)
+                    }],
+                    &mut self.swash_cache,
+                )
+                .unwrap();
+
+            let render_pass_descriptor = MTLRenderPassDescriptor::new();
+            let color_attachment = unsafe {
+                render_pass_descriptor
+                    .colorAttachments()
+                    .objectAtIndexedSubscript(0)
+            };
+
+            color_attachment.setTexture(Some(&drawable.texture()));
+            color_attachment.setLoadAction(MTLLoadAction::Clear);
+            color_attachment.setClearColor(MTLClearColor {
+                red: 0.0,
+                green: 0.0,
+                blue: 0.0,
+                alpha: 1.0,
+            });
+            color_attachment.setStoreAction(MTLStoreAction::Store);
+
+            let Some(buffer) = self.queue.commandBuffer() else {
+                return;
+            };
+
+            let Some(render_encoder) =
+                buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            else {
+                return;
+            };
+
+            if self.error.is_none() {
+                self.text_renderer
+                    .render(&self.atlas, &self.viewport, &render_encoder);
+            }
+            self.overlay_renderer
+                .render(&self.overlay_atlas, &self.viewport, &render_encoder);
+
+            render_encoder.endEncoding();
+
+            buffer.presentDrawable(drawable.as_ref());
+            buffer.commit();
+            self.atlas.trim();
+            self.overlay_atlas.trim();
+        });
+    }
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        // Set up window
+        let (width, height) = (1000, 800);
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_title("metalglyph CJK atlas pressure");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(WindowState::new(window));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        match event {
+            WindowEvent::Resized(_) => state.resize(),
+
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+                match event.logical_key.as_ref() {
+                    Key::Character("+" | "=") => {
+                        // Each new size is 50% larger than the largest one
+                        let largest = state
+                            .buffers
+                            .last()
+                            .map_or(12.0, |buffer| buffer.metrics().font_size);
+                        state.add_size((largest * 1.5).round());
+                    }
+                    Key::Character("-") => {
+                        state.buffers.pop();
+                    }
+                    _ => return,
+                }
+
+                state.window.request_redraw();
+            }
+
+            WindowEvent::RedrawRequested => state.redraw(),
+
+            WindowEvent::CloseRequested => event_loop.exit(),
+
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! Text drawn with a custom fragment function for a retro look.
+//!
+//! The function is compiled along with the built-in shaders of metalglyph, so that it can sample
+//! glyphs with `sample_glyph` like the built-in fragment function does, and then quantizes their
+//! colors and darkens every other pair of rows into scanlines. The [`Cache`] it is registered
+//! with creates every pipeline of the text renderer with it.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Color, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache,
+    TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2::{
+    rc::{autoreleasepool, Retained},
+    runtime::ProtocolObject,
+};
+use objc2_app_kit::NSView;
+use objc2_core_foundation::CGSize;
+use objc2_foundation::NSString;
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCreateSystemDefaultDevice, MTLDevice, MTLLoadAction, MTLPixelFormat,
+    MTLRenderPassDescriptor, MTLStoreAction,
+};
+use objc2_quartz_core::{CAMetalDrawable as _, CAMetalLayer};
+use raw_window_handle::{HasWindowHandle as _, RawWindowHandle};
+use std::sync::Arc;
+use winit::{dpi::LogicalSize, event::WindowEvent, event_loop::EventLoop, window::Window};
+
+/// Follows the contract of `Cache::with_custom_fragment`, reusing the declarations of the
+/// built-in shaders it is compiled with.
+const RETRO_FRAGMENT: &str = r#"
+fragment float4 retro_fragment(
+    VertexOutput in_frag [[stage_in]],
+    constant Params& params [[buffer(0)]],
+    texture2d<float> color_atlas_texture [[texture(0), function_constant(uses_color_atlas)]],
+    texture2d<float> mask_atlas_texture [[texture(1), function_constant(uses_mask_atlas)]]
+) {
+    float4 color = sample_glyph(in_frag, params, color_atlas_texture, mask_atlas_texture);
+
+    // Four levels per channel
+    color.rgb = floor(color.rgb * 3.0 + 0.5) / 3.0;
+
+    // Scanlines, two pixels high
+    if ((uint(in_frag.position.y) / 2u) % 2u == 1u) {
+        color.rgb *= 0.55;
+    }
+
+    return color;
+}
+"#;
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+struct WindowState {
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+
+    surface: Retained<CAMetalLayer>,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+    text_buffer: Buffer,
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the Metal layer is dropped.
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(window: Arc<Window>) -> Self {
+        let physical_size = window.inner_size();
+
+        let view = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+        let queue = device.newCommandQueue().expect("Create command queue");
+
+        let surface = CAMetalLayer::new();
+        surface.setDevice(Some(&device));
+        surface.setPixelFormat(MTLPixelFormat::BGRA8Unorm);
+        surface.setPresentsWithTransaction(false);
+        surface.setDrawableSize(CGSize {
+            width: physical_size.width as f64,
+            height: physical_size.height as f64,
+        });
+
+        view.setWantsLayer(true);
+        view.setLayer(Some(&surface));
+
+        // Compile the custom fragment function along with the built-in shaders
+        let source = format!("{}\n{RETRO_FRAGMENT}", Cache::shader_source());
+        let library = device
+            .newLibraryWithSource_options_error(&NSString::from_str(&source), None)
+            .expect("Create custom shader library");
+
+        // Set up text renderer with the custom fragment function
+        let mut font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        let cache = Cache::with_custom_fragment(&device, &library, "retro_fragment");
+        let viewport = Viewport::new(&device);
+        let mut atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
+        let text_renderer = TextRenderer::builder(&mut atlas)
+            .build()
+            .unwrap_or_else(|error| panic!("{error}"));
+
+        let mut text_buffer = Buffer::new(&mut font_system, Metrics::new(48.0, 60.0));
+        text_buffer.set_size(&mut font_system, Some(760.0), None);
+        text_buffer.set_text(
+            &mut font_system,
+            "READY.\nLOAD \"METALGLYPH\",8,1\nRUN 🕹️",
+            &Attrs::new()
+                .family(Family::Monospace)
+                .color(Color::rgb(120, 220, 255)),
+            Shaping::Advanced,
+        );
+        text_buffer.shape_until_scroll(&mut font_system, false);
+
+        Self {
+            queue,
+
+            surface,
+
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            text_buffer,
+
+            window,
+        }
+    }
+
+    fn redraw(&mut self) {
+        autoreleasepool(|_| {
+            let Some(drawable) = self.surface.nextDrawable() else {
+                return;
+            };
+
+            let resolution = Resolution {
+                width: self.surface.drawableSize().width as u32,
+                height: self.surface.drawableSize().height as u32,
+            };
+
+            self.viewport
+                .update_with_scale(resolution, self.window.scale_factor() as f32);
+
+            self.text_renderer
+                .prepare(
+                    &mut self.font_system,
+                    &mut self.atlas,
+                    &self.viewport,
+                    [TextArea {
+                        left: 20.0,
+                        top: 20.0,
+                        ..TextArea::new(&self.text_buffer)
+                    }],
+                    &mut self.swash_cache,
+                )
+                .unwrap();
+
+            let render_pass_descriptor = MTLRenderPassDescriptor::new();
+            let color_attachment = unsafe {
+                render_pass_descriptor
+                    .colorAttachments()
+                    .objectAtIndexedSubscript(0)
+            };
+
+            color_attachment.setTexture(Some(&drawable.texture()));
+            color_attachment.setLoadAction(MTLLoadAction::Clear);
+            color_attachment.setClearColor(MTLClearColor {
+                red: 0.1,
+                green: 0.1,
+                blue: 0.3,
+                alpha: 1.0,
+            });
+            color_attachment.setStoreAction(MTLStoreAction::Store);
+
+            let Some(buffer) = self.queue.commandBuffer() else {
+                return;
+            };
+
+            let Some(render_encoder) =
+                buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            else {
+                return;
+            };
+
+            self.text_renderer
+                .render(&self.atlas, &self.viewport, &render_encoder);
+
+            render_encoder.endEncoding();
+
+            buffer.presentDrawable(drawable.as_ref());
+            buffer.commit();
+            self.atlas.trim();
+        });
+    }
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        // Set up window
+        let (width, height) = (800, 300);
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_title("metalglyph custom fragment");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(WindowState::new(window));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        match event {
+            WindowEvent::Resized(size) => {
+                state.surface.setDrawableSize(CGSize {
+                    width: size.width as f64,
+                    height: size.height as f64,
+                });
+                state.window.request_redraw();
+            }
+
+            WindowEvent::RedrawRequested => state.redraw(),
+
+            WindowEvent::CloseRequested => event_loop.exit(),
+
+            _ => {}
+        }
+    }
+}
@@ -31,7 +31,6 @@ fn main() {
 }
 
 struct WindowState {
-    device: Retained<ProtocolObject<dyn MTLDevice>>,
     queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
 
     surface: Retained<CAMetalLayer>,
@@ -81,7 +80,7 @@ impl WindowState {
         let cache = Cache::new(&device);
         let viewport = Viewport::new(&device);
         let mut atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
-        let text_renderer = TextRenderer::new(&mut atlas, &device, MTLPixelFormat::Depth32Float, 1);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
         let mut text_buffer = Buffer::new(&mut font_system, Metrics::new(30.0, 42.0));
 
         view.setWantsLayer(true);
@@ -146,7 +145,6 @@ impl WindowState {
             };
 
         Self {
-            device,
             queue,
 
             font_system,
@@ -195,7 +193,6 @@ impl winit::application::ApplicationHandler for Application {
 
         let WindowState {
             window,
-            device,
             queue,
             surface,
             font_system,
@@ -233,22 +230,18 @@ impl winit::application::ApplicationHandler for Application {
 
                     text_renderer
                         .prepare_with_custom(
-                            device,
                             font_system,
                             atlas,
                             viewport,
                             [TextArea {
-                                buffer: text_buffer,
                                 left: 10.0,
                                 top: 10.0,
-                                scale: 1.0,
                                 bounds: TextBounds {
                                     left: 0,
                                     top: 0,
                                     right: 650,
                                     bottom: 180,
                                 },
-                                default_color: Color::rgb(255, 255, 255),
                                 custom_glyphs: &[
                                     CustomGlyph {
                                         id: 0,
@@ -291,6 +284,7 @@ impl winit::application::ApplicationHandler for Application {
                                         metadata: 0,
                                     },
                                 ],
+                                ..TextArea::new(text_buffer)
                             }],
                             swash_cache,
                             rasterize_svg,
@@ -0,0 +1,405 @@
+//! Rendering text from a `CADisplayLink` on a dedicated render thread.
+//!
+//! Instead of redrawing on demand, the example draws a frame every time the display refreshes,
+//! animating the position and color of the text. Threads share the work as follows:
+//!
+//! - The main thread owns the window and its view. It creates the display link, which is an
+//!   `NSView` method (macOS 14 or later), and sends resizes to the render thread over a channel.
+//! - The render thread owns everything `prepare` needs: the `FontSystem`, `SwashCache`,
+//!   `TextAtlas`, `TextRenderer` and `Viewport`, which are `Send`. It adds the display link to its
+//!   own run loop, so `prepare` and `render` run in the display link callback, without blocking
+//!   the main thread or being blocked by it.
+//!
+//! The layer presents without Core Animation transactions (`presentsWithTransaction` off), and
+//! each drawable is presented at the target timestamp of the display link, so that frames are
+//! paced by the display rather than by the event loop.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Color, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache,
+    TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2::{
+    define_class, msg_send,
+    rc::{autoreleasepool, Retained},
+    runtime::{NSObject, NSObjectProtocol, ProtocolObject},
+    sel, AllocAnyThread, DefinedClass,
+};
+use objc2_app_kit::NSView;
+use objc2_core_foundation::CGSize;
+use objc2_foundation::{NSDefaultRunLoopMode, NSRunLoop};
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCreateSystemDefaultDevice, MTLDevice, MTLLoadAction, MTLPixelFormat,
+    MTLRenderPassDescriptor, MTLStoreAction,
+};
+use objc2_quartz_core::{CADisplayLink, CAMetalDrawable, CAMetalLayer};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::{
+    f32::consts::TAU,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+use winit::{dpi::LogicalSize, event::WindowEvent, event_loop::EventLoop, window::Window};
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+/// Sent by the main thread to the render thread.
+enum Message {
+    /// The window was resized to `width`×`height` physical pixels.
+    Resize {
+        width: u32,
+        height: u32,
+        scale_factor: f32,
+    },
+    /// The window is closing: the render thread invalidates the display link, which stops its
+    /// run loop.
+    Stop,
+}
+
+/// The layer of the view, moved to the render thread.
+struct RenderLayer(Retained<CAMetalLayer>);
+
+// SAFETY: Once the main thread has attached the layer to the view, only the render thread uses
+// it. `CAMetalLayer` may hand out drawables and be resized from any thread.
+unsafe impl Send for RenderLayer {}
+
+/// The display link of the view, moved to the render thread.
+struct RenderDisplayLink(Retained<CADisplayLink>);
+
+// SAFETY: The main thread only creates the display link. It is scheduled on, fired on and
+// invalidated on the render thread.
+unsafe impl Send for RenderDisplayLink {}
+
+impl RenderDisplayLink {
+    /// Fires the display link on the current thread until it is invalidated.
+    fn run(self) {
+        let run_loop = NSRunLoop::currentRunLoop();
+        unsafe { self.0.addToRunLoop_forMode(&run_loop, NSDefaultRunLoopMode) };
+
+        // Returns once the display link, the only source of the run loop, is invalidated
+        run_loop.run();
+    }
+}
+
+/// The text rendering state, owned by the render thread.
+///
+/// Only the layer needs a wrapper to be moved there, all of the metalglyph and cosmic-text types
+/// are `Send`.
+struct RenderState {
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+    layer: RenderLayer,
+    messages: Receiver<Message>,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+    text_buffer: Buffer,
+    scale_factor: f32,
+    /// The target timestamp of the first frame, which the animation starts at.
+    start: Option<f64>,
+}
+
+impl RenderState {
+    fn new(
+        device: Retained<ProtocolObject<dyn MTLDevice>>,
+        layer: RenderLayer,
+        messages: Receiver<Message>,
+    ) -> Self {
+        // Loading the system fonts takes a while, which the render thread does instead of the
+        // main thread
+        let mut font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        let cache = Cache::new(&device);
+        let viewport = Viewport::new(&device);
+        let mut atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+        // Laid out in points, and scaled to pixels by the text area
+        let mut text_buffer = Buffer::new(&mut font_system, Metrics::new(30.0, 42.0));
+        text_buffer.set_text(
+            &mut font_system,
+            "Hello from the display link! 🖥️\nPrepared and rendered on the render thread.",
+            &Attrs::new().family(Family::SansSerif),
+            Shaping::Advanced,
+        );
+        text_buffer.shape_until_scroll(&mut font_system, false);
+
+        Self {
+            queue: device.newCommandQueue().expect("Create command queue"),
+            layer,
+            messages,
+
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            text_buffer,
+            scale_factor: 1.0,
+            start: None,
+        }
+    }
+
+    fn resize(&mut self, width: u32, height: u32, scale_factor: f32) {
+        self.scale_factor = scale_factor;
+        self.layer.0.setDrawableSize(CGSize {
+            width: width as f64,
+            height: height as f64,
+        });
+        self.viewport.update(Resolution { width, height });
+    }
+
+    /// Handles the messages of the main thread and draws a frame, called by the display link.
+    fn step(&mut self, display_link: &CADisplayLink) {
+        while let Ok(message) = self.messages.try_recv() {
+            match message {
+                Message::Resize {
+                    width,
+                    height,
+                    scale_factor,
+                } => self.resize(width, height, scale_factor),
+                Message::Stop => {
+                    display_link.invalidate();
+                    return;
+                }
+            }
+        }
+
+        // Animate with the time the frame is displayed at rather than the current time, so that
+        // the motion stays smooth when a callback runs late
+        let timestamp = display_link.targetTimestamp();
+        let time = (timestamp - *self.start.get_or_insert(timestamp)) as f32;
+
+        let Some(drawable) = self.layer.0.nextDrawable() else {
+            return;
+        };
+
+        let scale = self.scale_factor;
+        let resolution = self.viewport.resolution();
+        let center_x = resolution.width as f32 / scale / 2.0 - 260.0;
+        let center_y = resolution.height as f32 / scale / 2.0 - 40.0;
+
+        self.text_renderer
+            .prepare(
+                &mut self.font_system,
+                &mut self.atlas,
+                &self.viewport,
+                [TextArea {
+                    left: (center_x + 120.0 * (time * 0.8).cos()) * scale,
+                    top: (center_y + 80.0 * (time * 1.6).sin()) * scale,
+                    scale,
+                    default_color: hue_to_color(time * 0.1),
+                    ..TextArea::new(&self.text_buffer)
+                }],
+                &mut self.swash_cache,
+            )
+            .unwrap();
+
+        let render_pass_descriptor = MTLRenderPassDescriptor::new();
+        let color_attachment = unsafe {
+            render_pass_descriptor
+                .colorAttachments()
+                .objectAtIndexedSubscript(0)
+        };
+
+        color_attachment.setTexture(Some(&drawable.texture()));
+        color_attachment.setLoadAction(MTLLoadAction::Clear);
+        color_attachment.setClearColor(MTLClearColor {
+            red: 0.0,
+            green: 0.0,
+            blue: 0.0,
+            alpha: 1.0,
+        });
+        color_attachment.setStoreAction(MTLStoreAction::Store);
+
+        let Some(buffer) = self.queue.commandBuffer() else {
+            return;
+        };
+
+        let Some(render_encoder) =
+            buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+        else {
+            return;
+        };
+
+        self.text_renderer
+            .render(&self.atlas, &self.viewport, &render_encoder);
+
+        render_encoder.endEncoding();
+
+        // Without transactions, the drawable is shown at the refresh the animation was computed
+        // for, independently of the main thread
+        buffer.presentDrawable_atTime(drawable.as_ref(), timestamp);
+        buffer.commit();
+        self.atlas.trim();
+    }
+}
+
+/// Returns the fully saturated color of `hue`, in turns.
+fn hue_to_color(hue: f32) -> Color {
+    let channel = |offset: f32| {
+        let value = 0.5 + 0.5 * (TAU * (hue + offset)).cos();
+        (value * 255.0).round() as u8
+    };
+
+    Color::rgb(channel(0.0), channel(2.0 / 3.0), channel(1.0 / 3.0))
+}
+
+define_class!(
+    // SAFETY:
+    // - The superclass NSObject does not have any subclassing requirements.
+    // - `FrameTarget` does not implement `Drop`.
+    #[unsafe(super(NSObject))]
+    #[name = "MetalglyphDisplayLinkTarget"]
+    #[ivars = Mutex<Option<RenderState>>]
+    struct FrameTarget;
+
+    unsafe impl NSObjectProtocol for FrameTarget {}
+
+    impl FrameTarget {
+        #[unsafe(method(step:))]
+        fn step(&self, display_link: &CADisplayLink) {
+            autoreleasepool(|_| {
+                if let Some(state) = self.ivars().lock().unwrap().as_mut() {
+                    state.step(display_link);
+                }
+            });
+        }
+    }
+);
+
+// SAFETY: The render state is `Send` and behind a mutex, and `NSObject` may be retained and
+// released from any thread.
+unsafe impl Send for FrameTarget {}
+unsafe impl Sync for FrameTarget {}
+
+impl FrameTarget {
+    fn new() -> Retained<Self> {
+        let this = Self::alloc().set_ivars(Mutex::new(None));
+        unsafe { msg_send![super(this), init] }
+    }
+}
+
+struct WindowState {
+    messages: Sender<Message>,
+    render_thread: Option<JoinHandle<()>>,
+
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(window: Arc<Window>) -> Self {
+        let view = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+        let layer = CAMetalLayer::new();
+        layer.setDevice(Some(&device));
+        layer.setPixelFormat(MTLPixelFormat::BGRA8Unorm);
+        layer.setPresentsWithTransaction(false);
+        view.setWantsLayer(true);
+        view.setLayer(Some(&layer));
+
+        let (messages, receiver) = mpsc::channel();
+        let size = window.inner_size();
+        messages
+            .send(Message::Resize {
+                width: size.width,
+                height: size.height,
+                scale_factor: window.scale_factor() as f32,
+            })
+            .unwrap();
+
+        // The display link keeps a strong reference to its target until it is invalidated. It
+        // follows the refresh rate of the display the view is on.
+        let target = FrameTarget::new();
+        let display_link =
+            RenderDisplayLink(unsafe { view.displayLinkWithTarget_selector(&target, sel!(step:)) });
+        let layer = RenderLayer(layer);
+
+        let render_thread = thread::Builder::new()
+            .name("render".to_owned())
+            .spawn(move || {
+                *target.ivars().lock().unwrap() = Some(RenderState::new(device, layer, receiver));
+                display_link.run();
+            })
+            .expect("Spawn render thread");
+
+        Self {
+            messages,
+            render_thread: Some(render_thread),
+            window,
+        }
+    }
+
+    /// Stops the display link and waits for the render thread to finish its frame.
+    fn stop(&mut self) {
+        if let Some(render_thread) = self.render_thread.take() {
+            let _ = self.messages.send(Message::Stop);
+            render_thread.join().expect("Join render thread");
+        }
+    }
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(800.0, 600.0))
+            .with_title("metalglyph display link");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(WindowState::new(window));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        // Frames are drawn by the display link, so redraw requests are not needed
+        match event {
+            WindowEvent::Resized(size) => {
+                let _ = state.messages.send(Message::Resize {
+                    width: size.width,
+                    height: size.height,
+                    scale_factor: state.window.scale_factor() as f32,
+                });
+            }
+
+            WindowEvent::CloseRequested => {
+                state.stop();
+                event_loop.exit();
+            }
+
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,643 @@
+//! A minimal text editor built on `cosmic_text::Editor`.
+//!
+//! It supports keyboard input and navigation (hold Shift to select), mouse click and drag
+//! selection, scrolling, a blinking caret, and IME composition. Selection highlights, the caret
+//! and the composition underline are drawn as solid custom glyphs, so everything is rendered by a
+//! single `TextRenderer`.
+
+use metalglyph::{
+    cosmic_text::{Motion, Selection},
+    Action, Attrs, Buffer, Cache, Color, ContentType, Cursor, CustomGlyph, CustomGlyphId, Edit,
+    Editor, Family, FontSystem, Metrics, RasterizeCustomGlyphRequest, RasterizedCustomGlyph,
+    Resolution, Shaping, SwashCache, TextArea, TextAtlas, TextBounds, TextRenderer, Viewport,
+};
+use objc2::{
+    rc::{autoreleasepool, Retained},
+    runtime::ProtocolObject,
+};
+use objc2_app_kit::NSView;
+use objc2_core_foundation::CGSize;
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCreateSystemDefaultDevice, MTLDevice, MTLLoadAction, MTLPixelFormat,
+    MTLRenderPassDescriptor, MTLStoreAction,
+};
+use objc2_quartz_core::{CAMetalDrawable, CAMetalLayer};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use winit::{
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
+    event::{ElementState, Ime, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{Key, ModifiersState, NamedKey},
+    window::Window,
+};
+
+/// Margin around the editor, in logical pixels.
+const PADDING: f32 = 16.0;
+const CARET_WIDTH: f32 = 2.0;
+const CARET_BLINK: Duration = Duration::from_millis(530);
+
+const TEXT_COLOR: Color = Color::rgb(230, 230, 230);
+const CARET_COLOR: Color = Color::rgb(255, 255, 255);
+const SELECTION_COLOR: Color = Color::rgb(60, 90, 150);
+const PREEDIT_BACKGROUND_COLOR: Color = Color::rgb(40, 40, 40);
+
+/// The id of the solid rectangle custom glyph.
+const RECT: CustomGlyphId = 0;
+
+const TEXT: &str = "Welcome to the metalglyph editor!\n\nClick and drag to select, use the arrow keys \
+                    with Shift to extend the selection, and scroll with the mouse wheel or \
+                    trackpad. Switch to an input method such as Pinyin or Kana to see the \
+                    composition text underlined at the caret.\n\nこんにちは世界 — مرحبا بالعالم — 🦅🦁\n";
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+/// Rasterizes the solid rectangle used for the caret, selections and underlines.
+fn rasterize(request: RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph> {
+    (request.id == RECT).then(|| RasterizedCustomGlyph {
+        data: vec![255; request.width as usize * request.height as usize],
+        content_type: ContentType::Mask,
+    })
+}
+
+/// A solid rectangle, in physical pixels relative to its text area.
+fn rect(left: f32, top: f32, width: f32, height: f32, color: Color) -> CustomGlyph {
+    CustomGlyph {
+        id: RECT,
+        left,
+        top,
+        width: width.max(1.0),
+        height: height.max(1.0),
+        color: Some(color),
+        snap_to_physical_pixel: true,
+        metadata: 0,
+    }
+}
+
+/// The composition text of the input method, and the byte range of its cursor or selection.
+struct Preedit {
+    buffer: Buffer,
+    range: Option<(usize, usize)>,
+}
+
+struct WindowState {
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+
+    surface: Retained<CAMetalLayer>,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+    editor: Editor<'static>,
+    preedit: Option<Preedit>,
+
+    modifiers: ModifiersState,
+    ime_enabled: bool,
+    mouse_position: PhysicalPosition<f64>,
+    mouse_down: bool,
+    caret_visible: bool,
+    next_blink: Instant,
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the Metal layer is dropped.
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(window: Arc<Window>) -> Self {
+        let physical_size = window.inner_size();
+        let scale_factor = window.scale_factor() as f32;
+
+        let view = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+        let queue = device.newCommandQueue().expect("Create command queue");
+
+        let surface = CAMetalLayer::new();
+        surface.setDevice(Some(&device));
+        surface.setPixelFormat(MTLPixelFormat::BGRA8Unorm);
+        surface.setPresentsWithTransaction(false);
+
+        surface.setDrawableSize(CGSize {
+            width: physical_size.width as f64,
+            height: physical_size.height as f64,
+        });
+
+        view.setWantsLayer(true);
+        view.setLayer(Some(&surface));
+
+        // Set up text renderer
+        let mut font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        let cache = Cache::new(&device);
+        let viewport = Viewport::new(&device);
+        let mut atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+        // The buffer is laid out in physical pixels, so text areas use a scale of 1
+        let mut buffer = Buffer::new(
+            &mut font_system,
+            Metrics::new(18.0 * scale_factor, 26.0 * scale_factor),
+        );
+        buffer.set_text(
+            &mut font_system,
+            TEXT,
+            &Attrs::new().family(Family::SansSerif),
+            Shaping::Advanced,
+        );
+
+        let mut editor = Editor::new(buffer);
+        editor.set_cursor(Cursor::new(0, 0));
+
+        window.set_ime_allowed(true);
+
+        let mut state = Self {
+            queue,
+
+            surface,
+
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            editor,
+            preedit: None,
+
+            modifiers: ModifiersState::default(),
+            ime_enabled: false,
+            mouse_position: PhysicalPosition::default(),
+            mouse_down: false,
+            caret_visible: true,
+            next_blink: Instant::now() + CARET_BLINK,
+
+            window,
+        };
+        state.resize(physical_size);
+
+        state
+    }
+
+    fn padding(&self) -> f32 {
+        PADDING * self.window.scale_factor() as f32
+    }
+
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.surface.setDrawableSize(CGSize {
+            width: size.width as f64,
+            height: size.height as f64,
+        });
+
+        let padding = self.padding();
+        let font_system = &mut self.font_system;
+        self.editor.with_buffer_mut(|buffer| {
+            buffer.set_size(
+                font_system,
+                Some(size.width as f32 - padding * 2.0),
+                Some(size.height as f32 - padding * 2.0),
+            )
+        });
+    }
+
+    /// Makes the caret visible and restarts its blink cycle, so it does not disappear while the
+    /// user is typing.
+    fn reset_blink(&mut self) {
+        self.caret_visible = true;
+        self.next_blink = Instant::now() + CARET_BLINK;
+        self.window.request_redraw();
+    }
+
+    fn action(&mut self, action: Action) {
+        self.editor.action(&mut self.font_system, action);
+        self.reset_blink();
+    }
+
+    /// Applies a caret motion, extending the selection while Shift is held.
+    fn motion(&mut self, motion: Motion) {
+        if self.modifiers.shift_key() {
+            if self.editor.selection() == Selection::None {
+                self.editor
+                    .set_selection(Selection::Normal(self.editor.cursor()));
+            }
+        } else {
+            self.editor.set_selection(Selection::None);
+        }
+
+        self.action(Action::Motion(motion));
+    }
+
+    fn keyboard_input(&mut self, event: KeyEvent) {
+        if event.state != ElementState::Pressed {
+            return;
+        }
+
+        match event.logical_key {
+            Key::Named(NamedKey::ArrowLeft) => self.motion(Motion::Left),
+            Key::Named(NamedKey::ArrowRight) => self.motion(Motion::Right),
+            Key::Named(NamedKey::ArrowUp) => self.motion(Motion::Up),
+            Key::Named(NamedKey::ArrowDown) => self.motion(Motion::Down),
+            Key::Named(NamedKey::Home) => self.motion(Motion::Home),
+            Key::Named(NamedKey::End) => self.motion(Motion::End),
+            Key::Named(NamedKey::PageUp) => self.motion(Motion::PageUp),
+            Key::Named(NamedKey::PageDown) => self.motion(Motion::PageDown),
+            Key::Named(NamedKey::Backspace) => self.action(Action::Backspace),
+            Key::Named(NamedKey::Delete) => self.action(Action::Delete),
+            Key::Named(NamedKey::Enter) => self.action(Action::Enter),
+            Key::Named(NamedKey::Escape) => self.action(Action::Escape),
+            Key::Named(NamedKey::Tab) if self.modifiers.shift_key() => {
+                self.action(Action::Unindent)
+            }
+            Key::Named(NamedKey::Tab) => self.action(Action::Indent),
+            Key::Character(c) if self.modifiers.super_key() && c.as_str() == "a" => {
+                self.editor
+                    .set_selection(Selection::Normal(Cursor::new(0, 0)));
+                self.action(Action::Motion(Motion::BufferEnd));
+            }
+            _ => {
+                // With an input method active, text arrives through `Ime::Commit` instead
+                if self.ime_enabled || self.modifiers.super_key() || self.modifiers.control_key() {
+                    return;
+                }
+
+                if let Some(text) = event.text {
+                    for c in text.chars().filter(|c| !c.is_control()) {
+                        self.action(Action::Insert(c));
+                    }
+                }
+            }
+        }
+    }
+
+    fn ime(&mut self, ime: Ime) {
+        match ime {
+            Ime::Enabled => self.ime_enabled = true,
+            Ime::Disabled => {
+                self.ime_enabled = false;
+                self.preedit = None;
+            }
+            Ime::Preedit(text, range) => {
+                self.preedit = (!text.is_empty()).then(|| {
+                    let metrics = self.editor.with_buffer(|buffer| buffer.metrics());
+                    let mut buffer = Buffer::new(&mut self.font_system, metrics);
+                    buffer.set_text(
+                        &mut self.font_system,
+                        &text,
+                        &Attrs::new().family(Family::SansSerif),
+                        Shaping::Advanced,
+                    );
+                    buffer.shape_until_scroll(&mut self.font_system, false);
+
+                    Preedit { buffer, range }
+                });
+            }
+            Ime::Commit(text) => {
+                self.preedit = None;
+                self.editor.insert_string(&text, None);
+            }
+        }
+
+        self.reset_blink();
+    }
+
+    /// Returns the mouse position relative to the editor buffer.
+    fn buffer_position(&self) -> (i32, i32) {
+        let padding = self.padding() as f64;
+
+        (
+            (self.mouse_position.x - padding) as i32,
+            (self.mouse_position.y - padding) as i32,
+        )
+    }
+
+    fn scroll(&mut self, delta: MouseScrollDelta) {
+        let line_height = self
+            .editor
+            .with_buffer(|buffer| buffer.metrics().line_height);
+        let pixels = match delta {
+            MouseScrollDelta::LineDelta(_, lines) => -lines * line_height,
+            MouseScrollDelta::PixelDelta(position) => -position.y as f32,
+        };
+
+        let font_system = &mut self.font_system;
+        self.editor.with_buffer_mut(|buffer| {
+            let mut scroll = buffer.scroll();
+            scroll.vertical += pixels;
+            buffer.set_scroll(scroll);
+
+            // Normalizes the scroll position and clamps it to the document
+            buffer.shape_until_scroll(font_system, false);
+        });
+        self.window.request_redraw();
+    }
+
+    fn redraw(&mut self) {
+        let WindowState {
+            window,
+            queue,
+            surface,
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            editor,
+            preedit,
+            caret_visible,
+            ..
+        } = self;
+
+        editor.shape_as_needed(font_system, true);
+
+        let padding = PADDING * window.scale_factor() as f32;
+        let selection = editor.selection_bounds();
+        let caret = editor.cursor_position();
+
+        autoreleasepool(|_| {
+            let drawable = match surface.nextDrawable() {
+                Some(drawable) => drawable,
+                None => panic!("Failed to get next drawable"),
+            };
+
+            let resolution = Resolution {
+                width: surface.drawableSize().width as u32,
+                height: surface.drawableSize().height as u32,
+            };
+
+            viewport.update(resolution);
+
+            let bounds = TextBounds {
+                left: padding as i32,
+                top: padding as i32,
+                right: resolution.width as i32 - padding as i32,
+                bottom: resolution.height as i32 - padding as i32,
+            };
+
+            editor.with_buffer(|buffer| {
+                let line_height = buffer.metrics().line_height;
+
+                // Selection highlights and the caret are drawn before the text of the area
+                let mut rects = Vec::new();
+                if let Some((start, end)) = selection {
+                    for run in buffer.layout_runs() {
+                        if let Some((x, width)) = run.highlight(start, end) {
+                            rects.push(rect(
+                                x,
+                                run.line_top,
+                                width,
+                                run.line_height,
+                                SELECTION_COLOR,
+                            ));
+                        }
+                    }
+                }
+
+                // The caret is hidden while composing, the composition shows its own
+                if let (Some((x, y)), true) = (caret, *caret_visible && preedit.is_none()) {
+                    rects.push(rect(
+                        x as f32,
+                        y as f32,
+                        CARET_WIDTH * window.scale_factor() as f32,
+                        line_height,
+                        CARET_COLOR,
+                    ));
+                }
+
+                let mut text_areas = vec![TextArea {
+                    left: padding,
+                    top: padding,
+                    bounds,
+                    default_color: TEXT_COLOR,
+                    custom_glyphs: &rects,
+                    ..TextArea::new(buffer)
+                }];
+
+                // The composition text is drawn over the text at the caret, on top of an opaque
+                // background, and underlined. The cursor or selection within it is underlined
+                // with a thicker line.
+                let mut preedit_rects = Vec::new();
+                if let (Some(preedit), Some((x, y))) = (preedit.as_ref(), caret) {
+                    let thin = window.scale_factor() as f32;
+                    let thick = thin * 2.0;
+
+                    if let Some(run) = preedit.buffer.layout_runs().next() {
+                        preedit_rects.push(rect(
+                            0.0,
+                            0.0,
+                            run.line_w,
+                            line_height,
+                            PREEDIT_BACKGROUND_COLOR,
+                        ));
+                        preedit_rects.push(rect(
+                            0.0,
+                            line_height - thick,
+                            run.line_w,
+                            thin,
+                            TEXT_COLOR,
+                        ));
+
+                        if let Some((start, end)) = preedit.range {
+                            let start = Cursor::new(0, start);
+                            let end = Cursor::new(0, end);
+
+                            match run.highlight(start, end) {
+                                Some((x, width)) if width > 0.0 => preedit_rects.push(rect(
+                                    x,
+                                    line_height - thick,
+                                    width,
+                                    thick,
+                                    TEXT_COLOR,
+                                )),
+                                _ => {
+                                    // An empty range is the caret within the composition
+                                    let x = run
+                                        .glyphs
+                                        .iter()
+                                        .find(|glyph| glyph.start >= start.index)
+                                        .map_or(run.line_w, |glyph| glyph.x);
+                                    preedit_rects.push(rect(
+                                        x,
+                                        0.0,
+                                        CARET_WIDTH * thin,
+                                        line_height,
+                                        CARET_COLOR,
+                                    ));
+                                }
+                            }
+                        }
+                    }
+
+                    text_areas.push(TextArea {
+                        left: padding + x as f32,
+                        top: padding + y as f32,
+                        bounds,
+                        default_color: TEXT_COLOR,
+                        custom_glyphs: &preedit_rects,
+                        ..TextArea::new(&preedit.buffer)
+                    });
+                }
+
+                text_renderer
+                    .prepare_with_custom(
+                        font_system,
+                        atlas,
+                        viewport,
+                        text_areas,
+                        swash_cache,
+                        rasterize,
+                    )
+                    .unwrap();
+            });
+
+            let render_pass_descriptor = MTLRenderPassDescriptor::new();
+            let color_attachment = unsafe {
+                render_pass_descriptor
+                    .colorAttachments()
+                    .objectAtIndexedSubscript(0)
+            };
+
+            color_attachment.setTexture(Some(&drawable.texture()));
+            color_attachment.setLoadAction(MTLLoadAction::Clear);
+            color_attachment.setClearColor(MTLClearColor {
+                red: 0.08,
+                green: 0.08,
+                blue: 0.08,
+                alpha: 1.0,
+            });
+            color_attachment.setStoreAction(MTLStoreAction::Store);
+
+            let Some(buffer) = queue.commandBuffer() else {
+                return;
+            };
+
+            let Some(render_encoder) =
+                buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            else {
+                return;
+            };
+
+            text_renderer.render(atlas, viewport, &render_encoder);
+
+            render_encoder.endEncoding();
+
+            buffer.presentDrawable(drawable.as_ref());
+            buffer.commit();
+            atlas.trim();
+        });
+
+        // Place the candidate window of the input method next to the caret
+        if let Some((x, y)) = caret {
+            let line_height = editor.with_buffer(|buffer| buffer.metrics().line_height);
+            window.set_ime_cursor_area(
+                PhysicalPosition::new(padding as f64 + x as f64, padding as f64 + y as f64),
+                PhysicalSize::new(1.0, line_height as f64),
+            );
+        }
+    }
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        // Set up window
+        let (width, height) = (800, 600);
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_title("metalglyph editor");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(WindowState::new(window));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        match event {
+            WindowEvent::Resized(size) => {
+                state.resize(size);
+                state.window.request_redraw();
+            }
+
+            WindowEvent::ModifiersChanged(modifiers) => state.modifiers = modifiers.state(),
+
+            WindowEvent::KeyboardInput { event, .. } => state.keyboard_input(event),
+
+            WindowEvent::Ime(ime) => state.ime(ime),
+
+            WindowEvent::CursorMoved { position, .. } => {
+                state.mouse_position = position;
+
+                if state.mouse_down {
+                    let (x, y) = state.buffer_position();
+                    state.action(Action::Drag { x, y });
+                }
+            }
+
+            WindowEvent::MouseInput {
+                state: button_state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                state.mouse_down = button_state == ElementState::Pressed;
+
+                if state.mouse_down {
+                    let (x, y) = state.buffer_position();
+                    state.action(Action::Click { x, y });
+                }
+            }
+
+            WindowEvent::MouseWheel { delta, .. } => state.scroll(delta),
+
+            WindowEvent::RedrawRequested => state.redraw(),
+
+            WindowEvent::CloseRequested => event_loop.exit(),
+
+            _ => {}
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        // Blink the caret
+        if Instant::now() >= state.next_blink {
+            state.caret_visible = !state.caret_visible;
+            state.next_blink = Instant::now() + CARET_BLINK;
+            state.window.request_redraw();
+        }
+
+        event_loop.set_control_flow(ControlFlow::WaitUntil(state.next_blink));
+    }
+}
@@ -0,0 +1,421 @@
+//! A grid of emoji at several sizes, exercising the color atlas and cluster handling.
+//!
+//! The grid mixes single code point emoji with sequences that must be shaped into one glyph:
+//!
+//! - ZWJ sequences, e.g. families and professions joined with U+200D ZERO WIDTH JOINER.
+//! - Skin tone modifiers, U+1F3FB to U+1F3FF following a base emoji.
+//! - Flags, pairs of regional indicators or a tag sequence.
+//! - Keycaps, a digit followed by U+FE0F and U+20E3.
+//!
+//! The overlay compares the number of emoji in the grid with the number of glyphs they were
+//! shaped into. A sequence rendered as its separate parts shows up as more glyphs than emoji.
+//! It also shows the number of entries in the color atlas: every size rasterizes its own copy of
+//! each emoji, so it is expected to be the number of unique glyphs times the number of sizes.
+//!
+//! Press Space to switch between `ColorMode::Accurate` and `ColorMode::Web`. The color mode of an
+//! atlas is fixed, so each mode has its own atlas and renderer, and the drawable switches between
+//! an sRGB and a linear format to match.
+//!
+//! With `--features embedded-fonts` and `METALGLYPH_EMBEDDED_FONTS=1`, the grid renders with only
+//! the fonts embedded in metalglyph, whose color test font covers a few of the single code point
+//! emoji, so the other emoji render as missing glyphs.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, ColorMode, ContentType, Family, FontSystem, Metrics, Resolution, Shaping,
+    SwashCache, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2::{
+    rc::{autoreleasepool, Retained},
+    runtime::ProtocolObject,
+};
+use objc2_app_kit::NSView;
+use objc2_core_foundation::CGSize;
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCreateSystemDefaultDevice, MTLDevice, MTLLoadAction, MTLPixelFormat,
+    MTLRenderPassDescriptor, MTLStoreAction,
+};
+use objc2_quartz_core::{CAMetalDrawable, CAMetalLayer};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::sync::Arc;
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, WindowEvent},
+    event_loop::EventLoop,
+    keyboard::{Key, NamedKey},
+    window::Window,
+};
+
+/// The emoji of the grid, one per entry.
+const EMOJI: &[&str] = &[
+    // Single code points
+    "😀",
+    "🎉",
+    "🦀",
+    "🚀",
+    "🌈",
+    "🍕",
+    "❤️",
+    "⭐",
+    // ZWJ sequences
+    "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}", // family
+    "\u{1F469}\u{200D}\u{1F4BB}",                                   // woman technologist
+    "\u{1F3F3}\u{FE0F}\u{200D}\u{1F308}",                           // rainbow flag
+    "\u{1F9D1}\u{200D}\u{1F91D}\u{200D}\u{1F9D1}",                  // people holding hands
+    "\u{1F43B}\u{200D}\u{2744}\u{FE0F}",                            // polar bear
+    "\u{2764}\u{FE0F}\u{200D}\u{1F525}",                            // heart on fire
+    // Skin tone modifiers, including inside a ZWJ sequence
+    "\u{1F44B}\u{1F3FB}",
+    "\u{1F44B}\u{1F3FC}",
+    "\u{1F44B}\u{1F3FD}",
+    "\u{1F44B}\u{1F3FE}",
+    "\u{1F44B}\u{1F3FF}",
+    "\u{1F469}\u{1F3FD}\u{200D}\u{1F680}", // woman astronaut, medium skin tone
+    "\u{1F9D1}\u{1F3FB}\u{200D}\u{1F91D}\u{200D}\u{1F9D1}\u{1F3FF}", // mixed skin tones
+    // Flags
+    "🇯🇵",
+    "🇫🇷",
+    "🇧🇷",
+    "🇺🇦",
+    "🇨🇦",
+    "🇰🇷",
+    "\u{1F3F4}\u{E0067}\u{E0062}\u{E0073}\u{E0063}\u{E0074}\u{E007F}", // Scotland
+    // Keycaps
+    "1\u{FE0F}\u{20E3}",
+    "#\u{FE0F}\u{20E3}",
+];
+
+/// The sizes the grid is rendered at, in logical pixels.
+const SIZES: [f32; 4] = [16.0, 24.0, 40.0, 64.0];
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+/// Creates the font system of the example, containing only the fonts embedded in metalglyph if
+/// `METALGLYPH_EMBEDDED_FONTS` is set and the `embedded-fonts` feature is enabled.
+fn font_system() -> FontSystem {
+    #[cfg(feature = "embedded-fonts")]
+    if std::env::var_os("METALGLYPH_EMBEDDED_FONTS").is_some() {
+        return metalglyph::test_font_system();
+    }
+    FontSystem::new()
+}
+
+/// An atlas and renderer for one color mode.
+struct ModeRenderer {
+    mode: ColorMode,
+    format: MTLPixelFormat,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+}
+
+impl ModeRenderer {
+    fn new(
+        device: &ProtocolObject<dyn MTLDevice>,
+        cache: &Cache,
+        mode: ColorMode,
+        format: MTLPixelFormat,
+    ) -> Self {
+        let mut atlas = TextAtlas::with_color_mode(device, cache, format, mode);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+        Self {
+            mode,
+            format,
+            atlas,
+            text_renderer,
+        }
+    }
+}
+
+struct WindowState {
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+
+    surface: Retained<CAMetalLayer>,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    /// The renderer for each color mode, the first one is in use.
+    renderers: [ModeRenderer; 2],
+    /// One buffer per size.
+    grids: Vec<Buffer>,
+    overlay: Buffer,
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the Metal layer is dropped.
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(window: Arc<Window>) -> Self {
+        let view = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+        let queue = device.newCommandQueue().expect("Create command queue");
+
+        // Accurate blends in linear space and needs an sRGB target, Web blends sRGB values
+        // directly in a linear target
+        let cache = Cache::new(&device);
+        let renderers = [
+            ModeRenderer::new(
+                &device,
+                &cache,
+                ColorMode::Accurate,
+                MTLPixelFormat::BGRA8Unorm_sRGB,
+            ),
+            ModeRenderer::new(&device, &cache, ColorMode::Web, MTLPixelFormat::BGRA8Unorm),
+        ];
+
+        let surface = CAMetalLayer::new();
+        surface.setDevice(Some(&device));
+        surface.setPixelFormat(renderers[0].format);
+        surface.setPresentsWithTransaction(false);
+
+        view.setWantsLayer(true);
+        view.setLayer(Some(&surface));
+
+        let mut font_system = font_system();
+        let swash_cache = SwashCache::new();
+        let viewport = Viewport::new(&device);
+
+        let text = EMOJI.join(" ");
+        let grids = SIZES
+            .into_iter()
+            .map(|size| {
+                let mut buffer = Buffer::new(&mut font_system, Metrics::new(size, size * 1.3));
+                buffer.set_text(
+                    &mut font_system,
+                    &text,
+                    &Attrs::new().family(Family::SansSerif),
+                    Shaping::Advanced,
+                );
+                buffer
+            })
+            .collect();
+
+        let overlay = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+
+        let mut state = Self {
+            queue,
+
+            surface,
+
+            font_system,
+            swash_cache,
+            viewport,
+            renderers,
+            grids,
+            overlay,
+
+            window,
+        };
+        state.resize();
+
+        state
+    }
+
+    fn resize(&mut self) {
+        let size = self.window.inner_size();
+        self.surface.setDrawableSize(CGSize {
+            width: size.width as f64,
+            height: size.height as f64,
+        });
+
+        // Buffers are laid out in logical pixels, the viewport applies the scale factor
+        let width = size.to_logical::<f32>(self.window.scale_factor()).width - 20.0;
+        for buffer in &mut self.grids {
+            buffer.set_size(&mut self.font_system, Some(width), None);
+            buffer.shape_until_scroll(&mut self.font_system, false);
+        }
+        self.overlay
+            .set_size(&mut self.font_system, Some(width), None);
+
+        self.window.request_redraw();
+    }
+
+    fn toggle_mode(&mut self) {
+        self.renderers.swap(0, 1);
+        self.surface.setPixelFormat(self.renderers[0].format);
+        self.window.request_redraw();
+    }
+
+    fn update_overlay(&mut self) {
+        // Whitespace between the emoji is shaped too, only the glyphs of emoji are counted
+        let shaped = self
+            .grids
+            .iter()
+            .flat_map(|buffer| buffer.layout_runs())
+            .flat_map(|run| {
+                run.glyphs
+                    .iter()
+                    .filter(|glyph| !run.text[glyph.start..glyph.end].trim().is_empty())
+            })
+            .count();
+
+        let renderer = &self.renderers[0];
+        let stats = renderer.atlas.stats(ContentType::Color);
+
+        let text = format!(
+            "{mode:?} color mode (Space to switch)\n\
+             {emoji} emoji at {sizes} sizes shaped into {shaped} glyphs, expected {expected}\n\
+             color atlas: {size}x{size} px, {entries} entries, {occupancy:.1}% occupied",
+            mode = renderer.mode,
+            emoji = EMOJI.len(),
+            sizes = SIZES.len(),
+            expected = EMOJI.len() * SIZES.len(),
+            size = stats.size,
+            entries = stats.glyph_count,
+            occupancy = stats.occupancy * 100.0,
+        );
+
+        self.overlay.set_text(
+            &mut self.font_system,
+            &text,
+            &Attrs::new().family(Family::Monospace),
+            Shaping::Basic,
+        );
+        self.overlay
+            .shape_until_scroll(&mut self.font_system, false);
+    }
+
+    fn redraw(&mut self) {
+        // The atlas statistics describe the previous frame
+        self.update_overlay();
+
+        autoreleasepool(|_| {
+            let Some(drawable) = self.surface.nextDrawable() else {
+                return;
+            };
+
+            let resolution = Resolution {
+                width: self.surface.drawableSize().width as u32,
+                height: self.surface.drawableSize().height as u32,
+            };
+
+            self.viewport
+                .update_with_scale(resolution, self.window.scale_factor() as f32);
+
+            let mut top = 10.0;
+            let mut text_areas = Vec::with_capacity(self.grids.len() + 1);
+            for buffer in [&self.overlay].into_iter().chain(&self.grids) {
+                text_areas.push(TextArea {
+                    left: 10.0,
+                    top,
+                    ..TextArea::new(buffer)
+                });
+                top += buffer.layout_runs().count() as f32 * buffer.metrics().line_height + 16.0;
+            }
+
+            let ModeRenderer {
+                atlas,
+                text_renderer,
+                ..
+            } = &mut self.renderers[0];
+
+            text_renderer
+                .prepare(
+                    &mut self.font_system,
+                    atlas,
+                    &self.viewport,
+                    text_areas,
+                    &mut self.swash_cache,
+                )
+                .unwrap();
+
+            let render_pass_descriptor = MTLRenderPassDescriptor::new();
+            let color_attachment = unsafe {
+                render_pass_descriptor
+                    .colorAttachments()
+                    .objectAtIndexedSubscript(0)
+            };
+
+            color_attachment.setTexture(Some(&drawable.texture()));
+            color_attachment.setLoadAction(MTLLoadAction::Clear);
+            color_attachment.setClearColor(MTLClearColor {
+                red: 0.15,
+                green: 0.15,
+                blue: 0.2,
+                alpha: 1.0,
+            });
+            color_attachment.setStoreAction(MTLStoreAction::Store);
+
+            let Some(buffer) = self.queue.commandBuffer() else {
+                return;
+            };
+
+            let Some(render_encoder) =
+                buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            else {
+                return;
+            };
+
+            text_renderer.render(atlas, &self.viewport, &render_encoder);
+
+            render_encoder.endEncoding();
+
+            buffer.presentDrawable(drawable.as_ref());
+            buffer.commit();
+            atlas.trim();
+        });
+    }
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        // Set up window
+        let (width, height) = (900, 700);
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_title("metalglyph emoji");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(WindowState::new(window));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        match event {
+            WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => state.resize(),
+
+            WindowEvent::KeyboardInput { event, .. }
+                if event.state == ElementState::Pressed
+                    && event.logical_key.as_ref() == Key::Named(NamedKey::Space) =>
+            {
+                state.toggle_mode();
+            }
+
+            WindowEvent::RedrawRequested => state.redraw(),
+
+            WindowEvent::CloseRequested => event_loop.exit(),
+
+            _ => {}
+        }
+    }
+}
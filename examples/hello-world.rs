@@ -1,6 +1,6 @@
 use metalglyph::{
-    Attrs, Buffer, Cache, Color, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache,
-    TextArea, TextAtlas, TextBounds, TextRenderer, Viewport,
+    Attrs, Buffer, Cache, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache, TextArea,
+    TextAtlas, TextBounds, TextRenderer, Viewport,
 };
 use objc2::{
     rc::{autoreleasepool, Retained},
@@ -25,8 +25,17 @@ fn main() {
         .unwrap();
 }
 
+/// Creates the font system of the example, containing only the fonts embedded in metalglyph if
+/// `METALGLYPH_EMBEDDED_FONTS` is set and the `embedded-fonts` feature is enabled.
+fn font_system() -> FontSystem {
+    #[cfg(feature = "embedded-fonts")]
+    if std::env::var_os("METALGLYPH_EMBEDDED_FONTS").is_some() {
+        return metalglyph::test_font_system();
+    }
+    FontSystem::new()
+}
+
 struct WindowState {
-    device: Retained<ProtocolObject<dyn MTLDevice>>,
     queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
 
     surface: Retained<CAMetalLayer>,
@@ -71,12 +80,12 @@ impl WindowState {
         });
 
         // Set up text renderer
-        let mut font_system = FontSystem::new();
+        let mut font_system = font_system();
         let swash_cache = SwashCache::new();
         let cache = Cache::new(&device);
         let viewport = Viewport::new(&device);
         let mut atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
-        let text_renderer = TextRenderer::new(&mut atlas, &device, MTLPixelFormat::Depth32Float, 1);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
         let mut text_buffer = Buffer::new(&mut font_system, Metrics::new(30.0, 42.0));
 
         view.setWantsLayer(true);
@@ -94,7 +103,6 @@ impl WindowState {
         text_buffer.shape_until_scroll(&mut font_system, false);
 
         Self {
-            device,
             queue,
 
             font_system,
@@ -142,7 +150,6 @@ impl winit::application::ApplicationHandler for Application {
 
         let WindowState {
             window,
-            device,
             queue,
             surface,
             font_system,
@@ -179,23 +186,19 @@ impl winit::application::ApplicationHandler for Application {
 
                     text_renderer
                         .prepare(
-                            device,
                             font_system,
                             atlas,
                             viewport,
                             [TextArea {
-                                buffer: text_buffer,
                                 left: 10.0,
                                 top: 10.0,
-                                scale: 1.0,
                                 bounds: TextBounds {
                                     left: 0,
                                     top: 0,
                                     right: 600,
                                     bottom: 160,
                                 },
-                                default_color: Color::rgb(255, 255, 255),
-                                custom_glyphs: &[],
+                                ..TextArea::new(text_buffer)
                             }],
                             swash_cache,
                         )
@@ -0,0 +1,324 @@
+//! Rendering text on iOS and tvOS, into a `CAMetalLayer` driven by a `CADisplayLink`.
+//!
+//! winit creates the `UIApplication` and the window. The example adds a `CAMetalLayer` to the
+//! view of the window and renders a frame every time the display link fires:
+//!
+//! - The layer frame is in points, its drawable size is in pixels. Text is laid out in points and
+//!   `Viewport::update_with_scale` maps it to pixels with the scale factor of the screen.
+//! - The display link calls `step:` on a target object that owns the text rendering state.
+//!
+//! Build it for a device or the simulator with one of:
+//!
+//! ```sh
+//! cargo build --example ios --target aarch64-apple-ios
+//! cargo build --example ios --target aarch64-apple-ios-sim
+//! ```
+//!
+//! and wrap the binary in an app bundle to run it. On other platforms the example only prints a
+//! message.
+
+#[cfg(any(target_os = "ios", target_os = "tvos"))]
+mod ios {
+    use metalglyph::{
+        Attrs, Buffer, Cache, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache,
+        TextArea, TextAtlas, TextRenderer, Viewport,
+    };
+    use objc2::{
+        define_class, msg_send,
+        rc::{autoreleasepool, Retained},
+        runtime::{NSObject, NSObjectProtocol, ProtocolObject},
+        sel, DefinedClass, MainThreadMarker, MainThreadOnly,
+    };
+    use objc2_core_foundation::CGSize;
+    use objc2_foundation::{NSDefaultRunLoopMode, NSRunLoop};
+    use objc2_metal::{
+        MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+        MTLCreateSystemDefaultDevice, MTLDevice, MTLLoadAction, MTLPixelFormat,
+        MTLRenderPassDescriptor, MTLStoreAction,
+    };
+    use objc2_quartz_core::{CADisplayLink, CAMetalDrawable, CAMetalLayer};
+    use objc2_ui_kit::UIView;
+    use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+    use std::{cell::RefCell, sync::Arc};
+    use winit::{event::WindowEvent, event_loop::EventLoop, window::Window};
+
+    pub fn main() {
+        let event_loop = EventLoop::new().unwrap();
+        event_loop
+            .run_app(&mut Application { window_state: None })
+            .unwrap();
+    }
+
+    /// The text rendering state, owned by the display link target.
+    struct TextState {
+        queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+
+        layer: Retained<CAMetalLayer>,
+
+        font_system: FontSystem,
+        swash_cache: SwashCache,
+        viewport: Viewport,
+        atlas: TextAtlas,
+        text_renderer: TextRenderer,
+        text_buffer: Buffer,
+        scale_factor: f32,
+    }
+
+    impl TextState {
+        fn new(
+            device: Retained<ProtocolObject<dyn MTLDevice>>,
+            layer: Retained<CAMetalLayer>,
+        ) -> Self {
+            let mut font_system = FontSystem::new();
+            let swash_cache = SwashCache::new();
+            let cache = Cache::new(&device);
+            let viewport = Viewport::new(&device);
+            let mut atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
+            let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+            let mut text_buffer = Buffer::new(&mut font_system, Metrics::new(24.0, 32.0));
+            text_buffer.set_text(
+                &mut font_system,
+                "Hello from UIKit! 📱\nThis text is prepared and rendered every time the \
+                 CADisplayLink fires.",
+                &Attrs::new().family(Family::SansSerif),
+                Shaping::Advanced,
+            );
+
+            Self {
+                queue: device.newCommandQueue().expect("Create command queue"),
+
+                layer,
+
+                font_system,
+                swash_cache,
+                viewport,
+                atlas,
+                text_renderer,
+                text_buffer,
+                scale_factor: 1.0,
+            }
+        }
+
+        /// Matches the layer to the bounds of `view` and rewraps the text to its width.
+        fn resize(&mut self, view: &UIView, scale_factor: f32) {
+            let bounds = view.bounds();
+            self.scale_factor = scale_factor;
+            self.layer.setFrame(bounds);
+            self.layer.setContentsScale(scale_factor as f64);
+            self.layer.setDrawableSize(CGSize {
+                width: bounds.size.width * scale_factor as f64,
+                height: bounds.size.height * scale_factor as f64,
+            });
+
+            self.text_buffer.set_size(
+                &mut self.font_system,
+                Some(bounds.size.width as f32 - 40.0),
+                None,
+            );
+            self.text_buffer
+                .shape_until_scroll(&mut self.font_system, false);
+        }
+
+        fn draw(&mut self) {
+            let Some(drawable) = self.layer.nextDrawable() else {
+                return;
+            };
+
+            let resolution = Resolution {
+                width: self.layer.drawableSize().width as u32,
+                height: self.layer.drawableSize().height as u32,
+            };
+
+            self.viewport
+                .update_with_scale(resolution, self.scale_factor);
+
+            self.text_renderer
+                .prepare(
+                    &mut self.font_system,
+                    &mut self.atlas,
+                    &self.viewport,
+                    [TextArea {
+                        left: 20.0,
+                        top: 60.0,
+                        ..TextArea::new(&self.text_buffer)
+                    }],
+                    &mut self.swash_cache,
+                )
+                .unwrap();
+
+            let render_pass_descriptor = MTLRenderPassDescriptor::new();
+            let color_attachment = unsafe {
+                render_pass_descriptor
+                    .colorAttachments()
+                    .objectAtIndexedSubscript(0)
+            };
+
+            color_attachment.setTexture(Some(&drawable.texture()));
+            color_attachment.setLoadAction(MTLLoadAction::Clear);
+            color_attachment.setClearColor(MTLClearColor {
+                red: 0.1,
+                green: 0.1,
+                blue: 0.12,
+                alpha: 1.0,
+            });
+            color_attachment.setStoreAction(MTLStoreAction::Store);
+
+            let Some(buffer) = self.queue.commandBuffer() else {
+                return;
+            };
+
+            let Some(render_encoder) =
+                buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            else {
+                return;
+            };
+
+            self.text_renderer
+                .render(&self.atlas, &self.viewport, &render_encoder);
+
+            render_encoder.endEncoding();
+
+            buffer.presentDrawable(drawable.as_ref());
+            buffer.commit();
+            self.atlas.trim();
+        }
+    }
+
+    define_class!(
+        // SAFETY:
+        // - The superclass NSObject does not have any subclassing requirements.
+        // - `FrameTarget` does not implement `Drop`.
+        #[unsafe(super(NSObject))]
+        #[thread_kind = MainThreadOnly]
+        #[name = "MetalglyphFrameTarget"]
+        #[ivars = RefCell<TextState>]
+        struct FrameTarget;
+
+        unsafe impl NSObjectProtocol for FrameTarget {}
+
+        impl FrameTarget {
+            #[unsafe(method(step:))]
+            fn step(&self, _display_link: &CADisplayLink) {
+                autoreleasepool(|_| self.ivars().borrow_mut().draw());
+            }
+        }
+    );
+
+    impl FrameTarget {
+        fn new(mtm: MainThreadMarker, text_state: TextState) -> Retained<Self> {
+            let this = Self::alloc(mtm).set_ivars(RefCell::new(text_state));
+            unsafe { msg_send![super(this), init] }
+        }
+    }
+
+    struct WindowState {
+        view: Retained<UIView>,
+        display_link: Retained<CADisplayLink>,
+        target: Retained<FrameTarget>,
+
+        // Make sure that the winit window is last in the struct so that
+        // it is dropped after the view is dropped.
+        window: Arc<Window>,
+    }
+
+    impl WindowState {
+        fn new(window: Arc<Window>) -> Self {
+            let mtm = MainThreadMarker::new().expect("Run on the main thread");
+
+            let view = match window.window_handle().expect("Window handle").as_raw() {
+                RawWindowHandle::UiKit(uikit_handle) => unsafe {
+                    Retained::retain(uikit_handle.ui_view.as_ptr() as *mut UIView).unwrap()
+                },
+                _ => panic!("Unsupported platform"),
+            };
+
+            let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+            let layer = CAMetalLayer::new();
+            layer.setDevice(Some(&device));
+            layer.setPixelFormat(MTLPixelFormat::BGRA8Unorm);
+            view.layer().addSublayer(&layer);
+
+            let target = FrameTarget::new(mtm, TextState::new(device, layer));
+            target
+                .ivars()
+                .borrow_mut()
+                .resize(&view, window.scale_factor() as f32);
+
+            // The display link keeps a strong reference to its target until it is invalidated
+            let display_link =
+                unsafe { CADisplayLink::displayLinkWithTarget_selector(&target, sel!(step:)) };
+            unsafe {
+                display_link.addToRunLoop_forMode(&NSRunLoop::mainRunLoop(), NSDefaultRunLoopMode)
+            };
+
+            Self {
+                view,
+                display_link,
+                target,
+                window,
+            }
+        }
+    }
+
+    impl Drop for WindowState {
+        fn drop(&mut self) {
+            self.display_link.invalidate();
+        }
+    }
+
+    struct Application {
+        window_state: Option<WindowState>,
+    }
+
+    impl winit::application::ApplicationHandler for Application {
+        fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+            if self.window_state.is_some() {
+                return;
+            }
+
+            // The window always fills the screen
+            let window = Arc::new(
+                event_loop
+                    .create_window(Window::default_attributes())
+                    .unwrap(),
+            );
+
+            self.window_state = Some(WindowState::new(window));
+        }
+
+        fn window_event(
+            &mut self,
+            _event_loop: &winit::event_loop::ActiveEventLoop,
+            _window_id: winit::window::WindowId,
+            event: WindowEvent,
+        ) {
+            let Some(state) = &mut self.window_state else {
+                return;
+            };
+
+            // Rotating the device resizes the window. Frames are drawn by the display link, so
+            // redraw requests are not needed.
+            if let WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } = event {
+                state
+                    .target
+                    .ivars()
+                    .borrow_mut()
+                    .resize(&state.view, state.window.scale_factor() as f32);
+            }
+        }
+    }
+}
+
+#[cfg(any(target_os = "ios", target_os = "tvos"))]
+fn main() {
+    ios::main();
+}
+
+#[cfg(not(any(target_os = "ios", target_os = "tvos")))]
+fn main() {
+    eprintln!(
+        "This example only runs on iOS and tvOS, see the documentation at the top of the file"
+    );
+}
@@ -0,0 +1,559 @@
+//! Text baked into a texture and mapped onto a spinning quad in a 3D scene.
+//!
+//! The label is rendered once with an `OffscreenRenderer` into a texture with a transparent
+//! background, then copied into a mipmapped texture that the quad samples from.
+//!
+//! - Textures rendered by `OffscreenRenderer` use premultiplied alpha, so the quad is blended
+//!   with `One, OneMinusSourceAlpha`. Press `A` to blend it as straight alpha instead, which
+//!   multiplies the colors by alpha a second time and darkens the antialiased edges of glyphs.
+//! - Averaging premultiplied texels is also what makes the mipmaps correct: the transparent
+//!   texels around glyphs have no color to bleed into the edges of smaller levels.
+//! - The quad is mostly seen at an angle and smaller than the texture, which aliases without
+//!   mipmaps. Press `M` to sample only the first level with bilinear filtering for comparison.
+//!
+//! The label texture is sRGB, so filtering and mipmap generation happen in linear space.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Color, Family, FontSystem, Metrics, OffscreenRenderer, Resolution,
+    Shaping, SwashCache, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2::{
+    rc::{autoreleasepool, Retained},
+    runtime::ProtocolObject,
+};
+use objc2_app_kit::NSView;
+use objc2_core_foundation::CGSize;
+use objc2_foundation::{ns_string, NSString};
+use objc2_metal::{
+    MTLBlendFactor, MTLBlitCommandEncoder as _, MTLClearColor, MTLCommandBuffer as _,
+    MTLCommandEncoder as _, MTLCommandQueue, MTLCreateSystemDefaultDevice, MTLDevice, MTLLibrary,
+    MTLLoadAction, MTLOrigin, MTLPixelFormat, MTLPrimitiveType, MTLRenderCommandEncoder as _,
+    MTLRenderPassDescriptor, MTLRenderPipelineDescriptor, MTLRenderPipelineState,
+    MTLSamplerDescriptor, MTLSamplerMinMagFilter, MTLSamplerMipFilter, MTLSamplerState, MTLSize,
+    MTLStorageMode, MTLStoreAction, MTLTexture, MTLTextureDescriptor, MTLTextureUsage,
+};
+use objc2_quartz_core::{CAMetalDrawable, CAMetalLayer};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::{mem::size_of, ptr::NonNull, sync::Arc, time::Instant};
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, WindowEvent},
+    event_loop::EventLoop,
+    keyboard::Key,
+    window::Window,
+};
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm_sRGB;
+
+/// The size of the label texture, with the same 4:1 aspect ratio as the quad.
+const LABEL_WIDTH: u32 = 1024;
+const LABEL_HEIGHT: u32 = 256;
+
+/// Draws a quad 4 units wide and 1 unit high, centered on the origin, with the label texture.
+const QUAD_SHADER: &str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct VertexOut {
+    float4 position [[position]];
+    float2 uv;
+};
+
+vertex VertexOut quad_vertex(uint vertex_id [[vertex_id]],
+                             constant float4x4 &mvp [[buffer(0)]]) {
+    float2 corner = float2(vertex_id & 1, vertex_id >> 1);
+
+    VertexOut out;
+    out.position = mvp * float4((corner.x - 0.5) * 4.0, corner.y - 0.5, 0.0, 1.0);
+    out.uv = float2(corner.x, 1.0 - corner.y);
+    return out;
+}
+
+fragment float4 quad_fragment(VertexOut in [[stage_in]],
+                              texture2d<float> label [[texture(0)]],
+                              sampler label_sampler [[sampler(0)]]) {
+    return label.sample(label_sampler, in.uv);
+}
+"#;
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+/// A column-major 4x4 matrix, matching the layout of `float4x4`.
+type Mat4 = [[f32; 4]; 4];
+
+fn mul(a: Mat4, b: Mat4) -> Mat4 {
+    let mut result = [[0.0; 4]; 4];
+    for (column, result_column) in result.iter_mut().enumerate() {
+        for (row, value) in result_column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+    result
+}
+
+/// A right-handed perspective projection mapping depth to Metal's `0..1` range.
+fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Mat4 {
+    let f = 1.0 / (fov_y / 2.0).tan();
+    [
+        [f / aspect, 0.0, 0.0, 0.0],
+        [0.0, f, 0.0, 0.0],
+        [0.0, 0.0, far / (near - far), -1.0],
+        [0.0, 0.0, near * far / (near - far), 0.0],
+    ]
+}
+
+fn translation(x: f32, y: f32, z: f32) -> Mat4 {
+    [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [x, y, z, 1.0],
+    ]
+}
+
+fn rotation_x(angle: f32) -> Mat4 {
+    let (sin, cos) = angle.sin_cos();
+    [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, cos, sin, 0.0],
+        [0.0, -sin, cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+fn rotation_y(angle: f32) -> Mat4 {
+    let (sin, cos) = angle.sin_cos();
+    [
+        [cos, 0.0, -sin, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [sin, 0.0, cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+/// Creates the pipeline of the quad, blending the label as premultiplied or straight alpha.
+fn quad_pipeline(
+    device: &ProtocolObject<dyn MTLDevice>,
+    library: &ProtocolObject<dyn MTLLibrary>,
+    premultiplied: bool,
+) -> Retained<ProtocolObject<dyn MTLRenderPipelineState>> {
+    let descriptor = MTLRenderPipelineDescriptor::new();
+    descriptor.setVertexFunction(
+        library
+            .newFunctionWithName(ns_string!("quad_vertex"))
+            .as_deref(),
+    );
+    descriptor.setFragmentFunction(
+        library
+            .newFunctionWithName(ns_string!("quad_fragment"))
+            .as_deref(),
+    );
+
+    let attachment = unsafe { descriptor.colorAttachments().objectAtIndexedSubscript(0) };
+    attachment.setPixelFormat(FORMAT);
+    attachment.setBlendingEnabled(true);
+    let source = if premultiplied {
+        MTLBlendFactor::One
+    } else {
+        MTLBlendFactor::SourceAlpha
+    };
+    attachment.setSourceRGBBlendFactor(source);
+    attachment.setSourceAlphaBlendFactor(source);
+    attachment.setDestinationRGBBlendFactor(MTLBlendFactor::OneMinusSourceAlpha);
+    attachment.setDestinationAlphaBlendFactor(MTLBlendFactor::OneMinusSourceAlpha);
+
+    device
+        .newRenderPipelineStateWithDescriptor_error(&descriptor)
+        .expect("Create quad pipeline")
+}
+
+/// Creates a trilinear, anisotropic sampler, or a bilinear one that only samples the first level.
+fn label_sampler(
+    device: &ProtocolObject<dyn MTLDevice>,
+    mipmapped: bool,
+) -> Retained<ProtocolObject<dyn MTLSamplerState>> {
+    let descriptor = MTLSamplerDescriptor::new();
+    descriptor.setMinFilter(MTLSamplerMinMagFilter::Linear);
+    descriptor.setMagFilter(MTLSamplerMinMagFilter::Linear);
+    if mipmapped {
+        descriptor.setMipFilter(MTLSamplerMipFilter::Linear);
+        descriptor.setMaxAnisotropy(8);
+    } else {
+        descriptor.setMipFilter(MTLSamplerMipFilter::NotMipmapped);
+    }
+
+    device
+        .newSamplerStateWithDescriptor(&descriptor)
+        .expect("Create sampler")
+}
+
+/// Renders the label with a transparent background and copies it into a mipmapped texture.
+fn bake_label(
+    device: &Retained<ProtocolObject<dyn MTLDevice>>,
+    queue: &Retained<ProtocolObject<dyn MTLCommandQueue>>,
+    cache: &Cache,
+    font_system: &mut FontSystem,
+    swash_cache: &mut SwashCache,
+) -> Retained<ProtocolObject<dyn MTLTexture>> {
+    let mut buffer = Buffer::new(font_system, Metrics::new(120.0, 150.0));
+    buffer.set_size(font_system, Some(LABEL_WIDTH as f32), None);
+    buffer.set_text(
+        font_system,
+        "Signpost 🦀",
+        &Attrs::new().family(Family::SansSerif),
+        Shaping::Advanced,
+    );
+    buffer.shape_until_scroll(font_system, false);
+
+    let mut offscreen = OffscreenRenderer::new(device, queue, cache, FORMAT);
+    let rendered = offscreen
+        .render(
+            font_system,
+            swash_cache,
+            [TextArea {
+                left: 40.0,
+                top: 50.0,
+                ..TextArea::new(&buffer)
+            }],
+            LABEL_WIDTH,
+            LABEL_HEIGHT,
+            Color::rgba(0, 0, 0, 0),
+        )
+        .expect("Render label");
+
+    let descriptor = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            FORMAT,
+            LABEL_WIDTH as usize,
+            LABEL_HEIGHT as usize,
+            true,
+        )
+    };
+    descriptor.setUsage(MTLTextureUsage::ShaderRead);
+    descriptor.setStorageMode(MTLStorageMode::Private);
+    let label = device
+        .newTextureWithDescriptor(&descriptor)
+        .expect("Create label texture");
+
+    let command_buffer = queue.commandBuffer().expect("Create command buffer");
+    let blit_encoder = command_buffer
+        .blitCommandEncoder()
+        .expect("Create blit encoder");
+    unsafe {
+        blit_encoder.copyFromTexture_sourceSlice_sourceLevel_sourceOrigin_sourceSize_toTexture_destinationSlice_destinationLevel_destinationOrigin(
+            &rendered,
+            0,
+            0,
+            MTLOrigin { x: 0, y: 0, z: 0 },
+            MTLSize {
+                width: LABEL_WIDTH as usize,
+                height: LABEL_HEIGHT as usize,
+                depth: 1,
+            },
+            &label,
+            0,
+            0,
+            MTLOrigin { x: 0, y: 0, z: 0 },
+        );
+    }
+    blit_encoder.generateMipmapsForTexture(&label);
+    blit_encoder.endEncoding();
+    command_buffer.commit();
+
+    label
+}
+
+struct WindowState {
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+
+    surface: Retained<CAMetalLayer>,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+    status: Buffer,
+
+    label: Retained<ProtocolObject<dyn MTLTexture>>,
+    premultiplied_pipeline: Retained<ProtocolObject<dyn MTLRenderPipelineState>>,
+    straight_pipeline: Retained<ProtocolObject<dyn MTLRenderPipelineState>>,
+    mipmapped_sampler: Retained<ProtocolObject<dyn MTLSamplerState>>,
+    bilinear_sampler: Retained<ProtocolObject<dyn MTLSamplerState>>,
+    /// Whether the quad is blended as straight alpha, which is wrong for the label.
+    straight: bool,
+    mipmapped: bool,
+    start: Instant,
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the Metal layer is dropped.
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(window: Arc<Window>) -> Self {
+        let physical_size = window.inner_size();
+
+        let view = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+        let queue = device.newCommandQueue().expect("Create command queue");
+
+        let surface = CAMetalLayer::new();
+        surface.setDevice(Some(&device));
+        surface.setPixelFormat(FORMAT);
+        surface.setPresentsWithTransaction(false);
+
+        surface.setDrawableSize(CGSize {
+            width: physical_size.width as f64,
+            height: physical_size.height as f64,
+        });
+
+        view.setWantsLayer(true);
+        view.setLayer(Some(&surface));
+
+        // Set up text renderer for the status line, drawn on top of the scene
+        let mut font_system = FontSystem::new();
+        let mut swash_cache = SwashCache::new();
+        let cache = Cache::new(&device);
+        let viewport = Viewport::new(&device);
+        let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+        let status = Buffer::new(&mut font_system, Metrics::new(16.0, 22.0));
+
+        // Set up the scene
+        let label = bake_label(&device, &queue, &cache, &mut font_system, &mut swash_cache);
+        let library = device
+            .newLibraryWithSource_options_error(&NSString::from_str(QUAD_SHADER), None)
+            .expect("Create quad shader library");
+
+        let mut state = Self {
+            premultiplied_pipeline: quad_pipeline(&device, &library, true),
+            straight_pipeline: quad_pipeline(&device, &library, false),
+            mipmapped_sampler: label_sampler(&device, true),
+            bilinear_sampler: label_sampler(&device, false),
+
+            queue,
+
+            surface,
+
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            status,
+
+            label,
+            straight: false,
+            mipmapped: true,
+            start: Instant::now(),
+
+            window,
+        };
+        state.update_status();
+
+        state
+    }
+
+    fn update_status(&mut self) {
+        let text = format!(
+            "A: blending {}\nM: {}",
+            if self.straight {
+                "as straight alpha (wrong, dark fringes)"
+            } else {
+                "as premultiplied alpha (correct)"
+            },
+            if self.mipmapped {
+                "trilinear anisotropic filtering with mipmaps"
+            } else {
+                "bilinear filtering without mipmaps (aliasing)"
+            },
+        );
+
+        self.status.set_text(
+            &mut self.font_system,
+            &text,
+            &Attrs::new().family(Family::SansSerif),
+            Shaping::Advanced,
+        );
+        self.status.shape_until_scroll(&mut self.font_system, false);
+    }
+
+    /// The model-view-projection matrix of the quad, spinning around the vertical axis.
+    fn mvp(&self, aspect: f32) -> Mat4 {
+        let angle = self.start.elapsed().as_secs_f32() * 0.6;
+        let projection = perspective(45f32.to_radians(), aspect, 0.1, 100.0);
+        let model = mul(rotation_x(-0.35), rotation_y(angle));
+
+        mul(projection, mul(translation(0.0, 0.0, -7.0), model))
+    }
+
+    fn redraw(&mut self) {
+        autoreleasepool(|_| {
+            let Some(drawable) = self.surface.nextDrawable() else {
+                return;
+            };
+
+            let resolution = Resolution {
+                width: self.surface.drawableSize().width as u32,
+                height: self.surface.drawableSize().height as u32,
+            };
+
+            self.viewport
+                .update_with_scale(resolution, self.window.scale_factor() as f32);
+
+            self.text_renderer
+                .prepare(
+                    &mut self.font_system,
+                    &mut self.atlas,
+                    &self.viewport,
+                    [TextArea {
+                        left: 10.0,
+                        top: 10.0,
+                        ..TextArea::new(&self.status)
+                    }],
+                    &mut self.swash_cache,
+                )
+                .unwrap();
+
+            let render_pass_descriptor = MTLRenderPassDescriptor::new();
+            let color_attachment = unsafe {
+                render_pass_descriptor
+                    .colorAttachments()
+                    .objectAtIndexedSubscript(0)
+            };
+
+            // A bright background makes dark fringes easy to spot
+            color_attachment.setTexture(Some(&drawable.texture()));
+            color_attachment.setLoadAction(MTLLoadAction::Clear);
+            color_attachment.setClearColor(MTLClearColor {
+                red: 0.45,
+                green: 0.65,
+                blue: 0.85,
+                alpha: 1.0,
+            });
+            color_attachment.setStoreAction(MTLStoreAction::Store);
+
+            let Some(buffer) = self.queue.commandBuffer() else {
+                return;
+            };
+
+            let Some(render_encoder) =
+                buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            else {
+                return;
+            };
+
+            let mvp = self.mvp(resolution.width as f32 / resolution.height.max(1) as f32);
+            let (pipeline, sampler) = (
+                if self.straight {
+                    &self.straight_pipeline
+                } else {
+                    &self.premultiplied_pipeline
+                },
+                if self.mipmapped {
+                    &self.mipmapped_sampler
+                } else {
+                    &self.bilinear_sampler
+                },
+            );
+
+            render_encoder.setRenderPipelineState(pipeline);
+            unsafe {
+                render_encoder.setVertexBytes_length_atIndex(
+                    NonNull::from(&mvp).cast(),
+                    size_of::<Mat4>(),
+                    0,
+                );
+                render_encoder.setFragmentTexture_atIndex(Some(&self.label), 0);
+                render_encoder.setFragmentSamplerState_atIndex(Some(sampler), 0);
+                render_encoder.drawPrimitives_vertexStart_vertexCount(
+                    MTLPrimitiveType::TriangleStrip,
+                    0,
+                    4,
+                );
+            }
+
+            self.text_renderer
+                .render(&self.atlas, &self.viewport, &render_encoder);
+
+            render_encoder.endEncoding();
+
+            buffer.presentDrawable(drawable.as_ref());
+            buffer.commit();
+            self.atlas.trim();
+        });
+
+        // Keep spinning
+        self.window.request_redraw();
+    }
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        // Set up window
+        let (width, height) = (900, 600);
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_title("metalglyph label texture");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(WindowState::new(window));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        match event {
+            WindowEvent::Resized(size) => {
+                state.surface.setDrawableSize(CGSize {
+                    width: size.width as f64,
+                    height: size.height as f64,
+                });
+            }
+
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+                match event.logical_key.as_ref() {
+                    Key::Character("a") => state.straight = !state.straight,
+                    Key::Character("m") => state.mipmapped = !state.mipmapped,
+                    _ => return,
+                }
+
+                state.update_status();
+            }
+
+            WindowEvent::RedrawRequested => state.redraw(),
+
+            WindowEvent::CloseRequested => event_loop.exit(),
+
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Rendering text into an `MTKView` from an `MTKViewDelegate`.
+//!
+//! The view owns the drawable and drives the frame loop, so all text work happens in the delegate:
+//!
+//! - `mtkView:drawableSizeWillChange:` updates the [`Viewport`] resolution and relayouts text.
+//! - `drawInMTKView:` prepares text, renders it into the view's `currentRenderPassDescriptor`,
+//!   presents `currentDrawable`, and trims the atlas once the frame is encoded.
+//!
+//! The drawable size is in physical pixels, while text is laid out in points. The ratio between
+//! the two is the scale factor, which is passed to the text areas.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Color, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache,
+    TextArea, TextAtlas, TextBounds, TextRenderer, Viewport,
+};
+use objc2::{
+    define_class, msg_send,
+    rc::{autoreleasepool, Retained},
+    runtime::{NSObject, NSObjectProtocol, ProtocolObject},
+    DefinedClass, MainThreadMarker, MainThreadOnly,
+};
+use objc2_app_kit::{NSAutoresizingMaskOptions, NSView};
+use objc2_core_foundation::CGSize;
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCreateSystemDefaultDevice, MTLDevice, MTLPixelFormat,
+};
+use objc2_metal_kit::{MTKView, MTKViewDelegate};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::{cell::RefCell, sync::Arc};
+use winit::{dpi::LogicalSize, event::WindowEvent, event_loop::EventLoop, window::Window};
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+/// The text rendering state, owned by the view delegate.
+struct TextState {
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+    title: Buffer,
+    body: Buffer,
+    scale_factor: f32,
+}
+
+impl TextState {
+    fn new(device: &Retained<ProtocolObject<dyn MTLDevice>>) -> Self {
+        let mut font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        let cache = Cache::new(device);
+        let viewport = Viewport::new(device);
+        let mut atlas = TextAtlas::new(device, &cache, MTLPixelFormat::BGRA8Unorm);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+        // Buffers are laid out in points and scaled to pixels by their text areas
+        let mut title = Buffer::new(&mut font_system, Metrics::new(32.0, 40.0));
+        title.set_text(
+            &mut font_system,
+            "Hello from MTKView 👋",
+            &Attrs::new().family(Family::SansSerif),
+            Shaping::Advanced,
+        );
+
+        let mut body = Buffer::new(&mut font_system, Metrics::new(16.0, 22.0));
+        body.set_text(
+            &mut font_system,
+            "This text is prepared and rendered from drawInMTKView:, using the render pass \
+             descriptor and drawable of the view. Resize the window or move it to a display \
+             with a different scale factor: the viewport follows mtkView:drawableSizeWillChange: \
+             and the text wraps to the new width.",
+            &Attrs::new().family(Family::Serif),
+            Shaping::Advanced,
+        );
+
+        Self {
+            queue: device.newCommandQueue().expect("Create command queue"),
+
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            title,
+            body,
+            scale_factor: 1.0,
+        }
+    }
+
+    fn resize(&mut self, drawable_size: CGSize, scale_factor: f32) {
+        self.scale_factor = scale_factor;
+        self.viewport.update(Resolution {
+            width: drawable_size.width as u32,
+            height: drawable_size.height as u32,
+        });
+
+        // Wrap the body to the width of the view, in points
+        let width = drawable_size.width as f32 / scale_factor - 40.0;
+        self.title
+            .set_size(&mut self.font_system, Some(width), None);
+        self.body.set_size(&mut self.font_system, Some(width), None);
+        self.title.shape_until_scroll(&mut self.font_system, false);
+        self.body.shape_until_scroll(&mut self.font_system, false);
+    }
+
+    fn draw(&mut self, view: &MTKView) {
+        // Both are only available while the view is on screen
+        let (Some(render_pass_descriptor), Some(drawable)) =
+            (view.currentRenderPassDescriptor(), view.currentDrawable())
+        else {
+            return;
+        };
+
+        let scale = self.scale_factor;
+        let resolution = self.viewport.resolution();
+        let bounds = TextBounds {
+            left: 0,
+            top: 0,
+            right: resolution.width as i32,
+            bottom: resolution.height as i32,
+        };
+
+        self.text_renderer
+            .prepare(
+                &mut self.font_system,
+                &mut self.atlas,
+                &self.viewport,
+                [
+                    TextArea {
+                        left: 20.0 * scale,
+                        top: 20.0 * scale,
+                        scale,
+                        bounds,
+                        ..TextArea::new(&self.title)
+                    },
+                    TextArea {
+                        left: 20.0 * scale,
+                        top: 72.0 * scale,
+                        scale,
+                        bounds,
+                        default_color: Color::rgb(200, 200, 200),
+                        ..TextArea::new(&self.body)
+                    },
+                ],
+                &mut self.swash_cache,
+            )
+            .unwrap();
+
+        let Some(command_buffer) = self.queue.commandBuffer() else {
+            return;
+        };
+
+        // The descriptor clears the drawable to the clear color of the view
+        let Some(render_encoder) =
+            command_buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+        else {
+            return;
+        };
+
+        self.text_renderer
+            .render(&self.atlas, &self.viewport, &render_encoder);
+
+        render_encoder.endEncoding();
+
+        command_buffer.presentDrawable(drawable.as_ref());
+        command_buffer.commit();
+
+        // Glyphs of this frame are no longer in use once it is encoded, so the next frame may
+        // evict them if it runs out of atlas space
+        self.atlas.trim();
+    }
+}
+
+define_class!(
+    // SAFETY:
+    // - The superclass NSObject does not have any subclassing requirements.
+    // - `Delegate` does not implement `Drop`.
+    #[unsafe(super(NSObject))]
+    #[thread_kind = MainThreadOnly]
+    #[name = "MetalglyphMTKViewDelegate"]
+    #[ivars = RefCell<TextState>]
+    struct Delegate;
+
+    unsafe impl NSObjectProtocol for Delegate {}
+
+    unsafe impl MTKViewDelegate for Delegate {
+        #[unsafe(method(mtkView:drawableSizeWillChange:))]
+        fn drawable_size_will_change(&self, view: &MTKView, size: CGSize) {
+            // The drawable is scaled from the bounds of the view by the backing scale factor
+            let points = view.bounds().size.width;
+            let scale_factor = if points > 0.0 {
+                (size.width / points) as f32
+            } else {
+                1.0
+            };
+
+            self.ivars().borrow_mut().resize(size, scale_factor);
+        }
+
+        #[unsafe(method(drawInMTKView:))]
+        fn draw_in_mtk_view(&self, view: &MTKView) {
+            autoreleasepool(|_| self.ivars().borrow_mut().draw(view));
+        }
+    }
+);
+
+impl Delegate {
+    fn new(mtm: MainThreadMarker, text_state: TextState) -> Retained<Self> {
+        let this = Self::alloc(mtm).set_ivars(RefCell::new(text_state));
+        unsafe { msg_send![super(this), init] }
+    }
+}
+
+struct WindowState {
+    _view: Retained<MTKView>,
+    _delegate: Retained<Delegate>,
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the view is dropped.
+    _window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(window: Arc<Window>) -> Self {
+        let mtm = MainThreadMarker::new().expect("Run on the main thread");
+
+        let parent = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+        // The view fills the window and follows its size
+        let view = unsafe {
+            MTKView::initWithFrame_device(MTKView::alloc(mtm), parent.bounds(), Some(&device))
+        };
+        view.setColorPixelFormat(MTLPixelFormat::BGRA8Unorm);
+        view.setClearColor(MTLClearColor {
+            red: 0.1,
+            green: 0.1,
+            blue: 0.12,
+            alpha: 1.0,
+        });
+        view.setAutoresizingMask(
+            NSAutoresizingMaskOptions::ViewWidthSizable
+                | NSAutoresizingMaskOptions::ViewHeightSizable,
+        );
+
+        let delegate = Delegate::new(mtm, TextState::new(&device));
+
+        // The delegate is only notified of size changes after it is set, so apply the initial size
+        // explicitly
+        delegate
+            .ivars()
+            .borrow_mut()
+            .resize(view.drawableSize(), window.scale_factor() as f32);
+
+        unsafe { view.setDelegate(Some(ProtocolObject::from_ref(&*delegate))) };
+        parent.addSubview(&view);
+
+        Self {
+            _view: view,
+            _delegate: delegate,
+            _window: window,
+        }
+    }
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        // Set up window
+        let (width, height) = (800, 600);
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_title("metalglyph MTKView");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(WindowState::new(window));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        // The view draws on its own schedule, so the window only needs to be closable
+        if let WindowEvent::CloseRequested = event {
+            event_loop.exit();
+        }
+    }
+}
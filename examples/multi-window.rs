@@ -0,0 +1,335 @@
+//! Two windows rendering independent text with shared resources.
+//!
+//! What is shared and what is not:
+//!
+//! - The `Cache` (shaders and pipelines) is reference counted and shared by cloning it.
+//! - The `FontSystem` and `SwashCache` are not tied to a device or window, so one of each serves
+//!   every window.
+//! - Each window has its own `Viewport`, `TextAtlas` and `TextRenderer`. A viewport describes a
+//!   single render target, and an atlas per window keeps `trim` local to the window that
+//!   rendered.
+//!
+//! Each window scales its text by its own scale factor, so moving one of them to a display with
+//! a different scale factor only affects that window. The second window also applies a zoom on
+//! top of its scale factor.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache, TextArea,
+    TextAtlas, TextBounds, TextRenderer, Viewport,
+};
+use objc2::{
+    rc::{autoreleasepool, Retained},
+    runtime::ProtocolObject,
+};
+use objc2_app_kit::NSView;
+use objc2_core_foundation::CGSize;
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCreateSystemDefaultDevice, MTLDevice, MTLLoadAction, MTLPixelFormat,
+    MTLRenderPassDescriptor, MTLStoreAction,
+};
+use objc2_quartz_core::{CAMetalDrawable, CAMetalLayer};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::{collections::HashMap, sync::Arc};
+use winit::{
+    dpi::{LogicalPosition, LogicalSize},
+    event::WindowEvent,
+    event_loop::{ActiveEventLoop, EventLoop},
+    window::{Window, WindowId},
+};
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application {
+            shared: None,
+            windows: HashMap::new(),
+        })
+        .unwrap();
+}
+
+/// Resources shared by all windows.
+struct Shared {
+    device: Retained<ProtocolObject<dyn MTLDevice>>,
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+    cache: Cache,
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+}
+
+struct WindowState {
+    surface: Retained<CAMetalLayer>,
+
+    viewport: Viewport,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+    text_buffer: Buffer,
+    /// A zoom applied on top of the scale factor of the window.
+    zoom: f32,
+    background: MTLClearColor,
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the Metal layer is dropped.
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(
+        shared: &mut Shared,
+        window: Arc<Window>,
+        text: &str,
+        zoom: f32,
+        background: MTLClearColor,
+    ) -> Self {
+        let physical_size = window.inner_size();
+
+        let view = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let surface = CAMetalLayer::new();
+        surface.setDevice(Some(&shared.device));
+        surface.setPixelFormat(MTLPixelFormat::BGRA8Unorm);
+        surface.setPresentsWithTransaction(false);
+        surface.setDrawableSize(CGSize {
+            width: physical_size.width as f64,
+            height: physical_size.height as f64,
+        });
+
+        view.setWantsLayer(true);
+        view.setLayer(Some(&surface));
+
+        // Per-window text rendering state, built from the shared cache
+        let viewport = Viewport::new(&shared.device);
+        let mut atlas = TextAtlas::new(&shared.device, &shared.cache, MTLPixelFormat::BGRA8Unorm);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+        // The buffer is laid out in logical pixels and scaled by its text area
+        let font_system = &mut shared.font_system;
+        let mut text_buffer = Buffer::new(font_system, Metrics::new(24.0, 32.0));
+        text_buffer.set_text(
+            font_system,
+            text,
+            &Attrs::new().family(Family::SansSerif),
+            Shaping::Advanced,
+        );
+
+        let mut state = Self {
+            surface,
+
+            viewport,
+            atlas,
+            text_renderer,
+            text_buffer,
+            zoom,
+            background,
+
+            window,
+        };
+        state.resize(font_system);
+
+        state
+    }
+
+    fn scale(&self) -> f32 {
+        self.window.scale_factor() as f32 * self.zoom
+    }
+
+    /// Matches the drawable to the window and rewraps the text to its width.
+    fn resize(&mut self, font_system: &mut FontSystem) {
+        let size = self.window.inner_size();
+        self.surface.setDrawableSize(CGSize {
+            width: size.width as f64,
+            height: size.height as f64,
+        });
+
+        let scale = self.scale();
+        self.text_buffer.set_size(
+            font_system,
+            Some((size.width as f32 - 20.0) / scale),
+            Some((size.height as f32 - 20.0) / scale),
+        );
+        self.text_buffer.shape_until_scroll(font_system, false);
+    }
+
+    fn redraw(&mut self, shared: &mut Shared) {
+        autoreleasepool(|_| {
+            let Some(drawable) = self.surface.nextDrawable() else {
+                return;
+            };
+
+            let resolution = Resolution {
+                width: self.surface.drawableSize().width as u32,
+                height: self.surface.drawableSize().height as u32,
+            };
+
+            self.viewport.update(resolution);
+
+            self.text_renderer
+                .prepare(
+                    &mut shared.font_system,
+                    &mut self.atlas,
+                    &self.viewport,
+                    [TextArea {
+                        left: 10.0,
+                        top: 10.0,
+                        scale: self.scale(),
+                        bounds: TextBounds {
+                            left: 0,
+                            top: 0,
+                            right: resolution.width as i32,
+                            bottom: resolution.height as i32,
+                        },
+                        ..TextArea::new(&self.text_buffer)
+                    }],
+                    &mut shared.swash_cache,
+                )
+                .unwrap();
+
+            let render_pass_descriptor = MTLRenderPassDescriptor::new();
+            let color_attachment = unsafe {
+                render_pass_descriptor
+                    .colorAttachments()
+                    .objectAtIndexedSubscript(0)
+            };
+
+            color_attachment.setTexture(Some(&drawable.texture()));
+            color_attachment.setLoadAction(MTLLoadAction::Clear);
+            color_attachment.setClearColor(self.background);
+            color_attachment.setStoreAction(MTLStoreAction::Store);
+
+            let Some(buffer) = shared.queue.commandBuffer() else {
+                return;
+            };
+
+            let Some(render_encoder) =
+                buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            else {
+                return;
+            };
+
+            self.text_renderer
+                .render(&self.atlas, &self.viewport, &render_encoder);
+
+            render_encoder.endEncoding();
+
+            buffer.presentDrawable(drawable.as_ref());
+            buffer.commit();
+
+            // Only this window's glyphs are in this atlas, so other windows are unaffected
+            self.atlas.trim();
+        });
+    }
+}
+
+struct Application {
+    shared: Option<Shared>,
+    windows: HashMap<WindowId, WindowState>,
+}
+
+impl Application {
+    fn open_window(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        title: &str,
+        position: LogicalPosition<f64>,
+        text: &str,
+        zoom: f32,
+        background: MTLClearColor,
+    ) {
+        let shared = self.shared.get_or_insert_with(|| {
+            let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+            Shared {
+                queue: device.newCommandQueue().expect("Create command queue"),
+                cache: Cache::new(&device),
+                font_system: FontSystem::new(),
+                swash_cache: SwashCache::new(),
+                device,
+            }
+        });
+
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(500.0, 300.0))
+            .with_position(position)
+            .with_title(title);
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.windows.insert(
+            window.id(),
+            WindowState::new(shared, window, text, zoom, background),
+        );
+    }
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if !self.windows.is_empty() {
+            return;
+        }
+
+        self.open_window(
+            event_loop,
+            "metalglyph window 1",
+            LogicalPosition::new(100.0, 100.0),
+            "Window 1\nShares its Cache and FontSystem with window 2, \
+             but has its own atlas and renderer.",
+            1.0,
+            MTLClearColor {
+                red: 0.1,
+                green: 0.1,
+                blue: 0.3,
+                alpha: 1.0,
+            },
+        );
+        self.open_window(
+            event_loop,
+            "metalglyph window 2",
+            LogicalPosition::new(650.0, 100.0),
+            "Window 2 🦅\nZoomed to 150% on top of its scale factor. \
+             Drag a window to another display to change its scale factor alone.",
+            1.5,
+            MTLClearColor {
+                red: 0.3,
+                green: 0.1,
+                blue: 0.1,
+                alpha: 1.0,
+            },
+        );
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
+        event: WindowEvent,
+    ) {
+        let (Some(shared), Some(state)) = (&mut self.shared, self.windows.get_mut(&window_id))
+        else {
+            return;
+        };
+
+        match event {
+            // A scale factor change is followed by a resize to the new physical size
+            WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
+                state.resize(&mut shared.font_system);
+                state.window.request_redraw();
+            }
+
+            WindowEvent::RedrawRequested => state.redraw(shared),
+
+            WindowEvent::CloseRequested => {
+                self.windows.remove(&window_id);
+
+                if self.windows.is_empty() {
+                    event_loop.exit();
+                }
+            }
+
+            _ => {}
+        }
+    }
+}
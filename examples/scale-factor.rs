@@ -0,0 +1,342 @@
+//! Handling scale factor changes, e.g. when moving a window between a 1x and a 2x display.
+//!
+//! Text is laid out in logical units and the viewport converts them to physical pixels:
+//!
+//! - The drawable always matches the physical size of the window.
+//! - Buffer sizes and `TextArea` positions, scales and bounds are in logical units.
+//! - `Viewport::update_with_scale` receives the physical resolution and the scale factor, so
+//!   glyphs are rasterized at their physical size.
+//!
+//! Press Space to switch to the wrong way: rendering at 1x into a drawable of the logical size,
+//! which the compositor then stretches, producing blurry text on high density displays.
+//!
+//! Press `+` and `-` to apply a zoom on top of the scale factor of the window. It produces
+//! fractional scale factors such as 1.25 or 2.5, which are handled the same way.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Color, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache,
+    TextArea, TextAtlas, TextBounds, TextRenderer, Viewport,
+};
+use objc2::{
+    rc::{autoreleasepool, Retained},
+    runtime::ProtocolObject,
+};
+use objc2_app_kit::NSView;
+use objc2_core_foundation::CGSize;
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCreateSystemDefaultDevice, MTLDevice, MTLLoadAction, MTLPixelFormat,
+    MTLRenderPassDescriptor, MTLStoreAction,
+};
+use objc2_quartz_core::{CAMetalDrawable, CAMetalLayer};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::sync::Arc;
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, WindowEvent},
+    event_loop::EventLoop,
+    keyboard::{Key, NamedKey},
+    window::Window,
+};
+
+const BODY: &str = "The quick brown fox jumps over the lazy dog. Sphinx of black quartz, judge \
+                    my vow! Thin strokes and small sizes show blurriness first: \
+                    ilIl1|/\\ .,:;' 0O";
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+struct WindowState {
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+
+    surface: Retained<CAMetalLayer>,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+    status: Buffer,
+    body: Buffer,
+
+    /// Whether to ignore the scale factor and let the compositor stretch the result.
+    wrong: bool,
+    zoom: f32,
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the Metal layer is dropped.
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(window: Arc<Window>) -> Self {
+        let view = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+        let queue = device.newCommandQueue().expect("Create command queue");
+
+        let surface = CAMetalLayer::new();
+        surface.setDevice(Some(&device));
+        surface.setPixelFormat(MTLPixelFormat::BGRA8Unorm);
+        surface.setPresentsWithTransaction(false);
+
+        view.setWantsLayer(true);
+        view.setLayer(Some(&surface));
+
+        // Set up text renderer
+        let mut font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        let cache = Cache::new(&device);
+        let viewport = Viewport::new(&device);
+        let mut atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+        // Metrics are in logical units, they do not change with the scale factor
+        let status = Buffer::new(&mut font_system, Metrics::new(16.0, 22.0));
+        let mut body = Buffer::new(&mut font_system, Metrics::new(20.0, 28.0));
+        body.set_text(
+            &mut font_system,
+            BODY,
+            &Attrs::new().family(Family::SansSerif),
+            Shaping::Advanced,
+        );
+
+        let mut state = Self {
+            queue,
+
+            surface,
+
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            status,
+            body,
+
+            wrong: false,
+            zoom: 1.0,
+
+            window,
+        };
+        state.resize();
+
+        state
+    }
+
+    /// The scale factor text is rendered at.
+    fn scale_factor(&self) -> f32 {
+        if self.wrong {
+            self.zoom
+        } else {
+            self.window.scale_factor() as f32 * self.zoom
+        }
+    }
+
+    /// Matches the drawable and the viewport to the window, and rewraps text to its logical
+    /// width. Called on resizes and scale factor changes, which change the physical size of the
+    /// window without necessarily changing its logical size.
+    fn resize(&mut self) {
+        let physical_size = self.window.inner_size();
+        let logical_size: LogicalSize<f64> = physical_size.to_logical(self.window.scale_factor());
+
+        let drawable_size = if self.wrong {
+            // Wrong: too few pixels for the display, the compositor upscales them
+            CGSize {
+                width: logical_size.width,
+                height: logical_size.height,
+            }
+        } else {
+            CGSize {
+                width: physical_size.width as f64,
+                height: physical_size.height as f64,
+            }
+        };
+        self.surface.setDrawableSize(drawable_size);
+
+        self.viewport.update_with_scale(
+            Resolution {
+                width: drawable_size.width as u32,
+                height: drawable_size.height as u32,
+            },
+            self.scale_factor(),
+        );
+
+        // Buffer sizes are in logical units. Zooming leaves less logical room for text.
+        let width = logical_size.width as f32 / self.zoom - 40.0;
+        self.body.set_size(&mut self.font_system, Some(width), None);
+        self.body.shape_until_scroll(&mut self.font_system, false);
+
+        let status = format!(
+            "Window scale factor: {:.2}\nZoom: {:.2} (+/-)\nEffective scale factor: {:.2}\n\
+             Drawable: {}x{} px\nMode: {} (Space)",
+            self.window.scale_factor(),
+            self.zoom,
+            self.scale_factor(),
+            drawable_size.width,
+            drawable_size.height,
+            if self.wrong {
+                "wrong, rendered at 1x and stretched"
+            } else {
+                "correct"
+            }
+        );
+        self.status
+            .set_size(&mut self.font_system, Some(width), None);
+        self.status.set_text(
+            &mut self.font_system,
+            &status,
+            &Attrs::new().family(Family::Monospace),
+            Shaping::Basic,
+        );
+        self.status.shape_until_scroll(&mut self.font_system, false);
+
+        self.window.request_redraw();
+    }
+
+    fn redraw(&mut self) {
+        autoreleasepool(|_| {
+            let Some(drawable) = self.surface.nextDrawable() else {
+                return;
+            };
+
+            // Everything here is in logical units, the viewport scales it
+            let logical_size: LogicalSize<f32> = self
+                .window
+                .inner_size()
+                .to_logical(self.window.scale_factor());
+            let bounds = TextBounds {
+                left: 0,
+                top: 0,
+                right: (logical_size.width / self.zoom) as i32,
+                bottom: (logical_size.height / self.zoom) as i32,
+            };
+
+            self.text_renderer
+                .prepare(
+                    &mut self.font_system,
+                    &mut self.atlas,
+                    &self.viewport,
+                    [
+                        TextArea {
+                            left: 20.0,
+                            top: 20.0,
+                            bounds,
+                            default_color: Color::rgb(255, 210, 80),
+                            ..TextArea::new(&self.status)
+                        },
+                        TextArea {
+                            left: 20.0,
+                            top: 150.0,
+                            bounds,
+                            ..TextArea::new(&self.body)
+                        },
+                    ],
+                    &mut self.swash_cache,
+                )
+                .unwrap();
+
+            let render_pass_descriptor = MTLRenderPassDescriptor::new();
+            let color_attachment = unsafe {
+                render_pass_descriptor
+                    .colorAttachments()
+                    .objectAtIndexedSubscript(0)
+            };
+
+            color_attachment.setTexture(Some(&drawable.texture()));
+            color_attachment.setLoadAction(MTLLoadAction::Clear);
+            color_attachment.setClearColor(MTLClearColor {
+                red: 0.0,
+                green: 0.0,
+                blue: 0.0,
+                alpha: 1.0,
+            });
+            color_attachment.setStoreAction(MTLStoreAction::Store);
+
+            let Some(buffer) = self.queue.commandBuffer() else {
+                return;
+            };
+
+            let Some(render_encoder) =
+                buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            else {
+                return;
+            };
+
+            self.text_renderer
+                .render(&self.atlas, &self.viewport, &render_encoder);
+
+            render_encoder.endEncoding();
+
+            buffer.presentDrawable(drawable.as_ref());
+            buffer.commit();
+            self.atlas.trim();
+        });
+    }
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        // Set up window
+        let (width, height) = (700, 400);
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_title("metalglyph scale factor");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(WindowState::new(window));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        match event {
+            // winit follows a scale factor change with a resize when the physical size changes,
+            // but not when it does not (e.g. the window was resized to compensate), so both are
+            // handled
+            WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => state.resize(),
+
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+                match event.logical_key.as_ref() {
+                    Key::Named(NamedKey::Space) => state.wrong = !state.wrong,
+                    Key::Character("+" | "=") => state.zoom = (state.zoom + 0.25).min(4.0),
+                    Key::Character("-") => state.zoom = (state.zoom - 0.25).max(0.5),
+                    _ => return,
+                }
+
+                state.resize();
+            }
+
+            WindowEvent::RedrawRequested => state.redraw(),
+
+            WindowEvent::CloseRequested => event_loop.exit(),
+
+            _ => {}
+        }
+    }
+}
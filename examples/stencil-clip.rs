@@ -0,0 +1,410 @@
+//! Text clipped to a circle with a stencil test.
+//!
+//! A circle following the cursor is drawn first, marking its pixels in the stencil buffer with a
+//! reference value of 1. The text is then rendered with a depth stencil state that only passes
+//! where the stencil buffer equals the reference value, so only the text inside of the circle is
+//! visible.
+//!
+//! - The text renderer is created with the stencil format of the render pass, so that its
+//!   pipelines match the stencil attachment.
+//! - `render` sets neither a depth stencil state nor a stencil reference value, so the ones set
+//!   before rendering apply to text.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache, TextArea,
+    TextAtlas, TextRenderer, Viewport,
+};
+use objc2::{
+    rc::{autoreleasepool, Retained},
+    runtime::ProtocolObject,
+};
+use objc2_app_kit::NSView;
+use objc2_core_foundation::CGSize;
+use objc2_foundation::{ns_string, NSString};
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCompareFunction, MTLCreateSystemDefaultDevice, MTLDepthStencilDescriptor,
+    MTLDepthStencilState, MTLDevice, MTLLibrary, MTLLoadAction, MTLPixelFormat, MTLPrimitiveType,
+    MTLRenderCommandEncoder as _, MTLRenderPassDescriptor, MTLRenderPipelineDescriptor,
+    MTLRenderPipelineState, MTLStencilDescriptor, MTLStencilOperation, MTLStorageMode,
+    MTLStoreAction, MTLTexture, MTLTextureDescriptor, MTLTextureUsage,
+};
+use objc2_quartz_core::{CAMetalDrawable, CAMetalLayer};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::{mem::size_of, ptr::NonNull, sync::Arc};
+use winit::{dpi::LogicalSize, event::WindowEvent, event_loop::EventLoop, window::Window};
+
+const COLOR_FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+const STENCIL_FORMAT: MTLPixelFormat = MTLPixelFormat::Stencil8;
+
+/// The value the circle writes into the stencil buffer, which text is tested against.
+const CLIP_REFERENCE: u32 = 1;
+
+const CIRCLE_SHADER: &str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct Circle {
+    float2 center;
+    float radius;
+};
+
+vertex float4 circle_vertex(uint vertex_id [[vertex_id]]) {
+    // A triangle covering the whole target
+    float2 position = float2((vertex_id << 1) & 2, vertex_id & 2);
+    return float4(position * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fragment float4 circle_fragment(float4 position [[position]],
+                                constant Circle &circle [[buffer(0)]]) {
+    // Pixels outside of the circle are discarded, and keep their stencil value
+    if (distance(position.xy, circle.center) > circle.radius) {
+        discard_fragment();
+    }
+    return float4(0.12, 0.16, 0.28, 1.0);
+}
+"#;
+
+const TEXT: &str = "Text rendered by metalglyph takes part in the stencil test of the render \
+pass. Move the cursor around to move the circle, which is the only place the text shows \
+through. The circle writes the stencil buffer, the text tests it, and the renderer leaves the \
+depth stencil state and the stencil reference value to the app. ";
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+/// The circle the text is clipped to, in pixels, matching `Circle` in the shader.
+#[repr(C)]
+struct Circle {
+    center: [f32; 2],
+    radius: f32,
+}
+
+fn stencil_state(
+    device: &ProtocolObject<dyn MTLDevice>,
+    compare: MTLCompareFunction,
+    pass_operation: MTLStencilOperation,
+) -> Retained<ProtocolObject<dyn MTLDepthStencilState>> {
+    let stencil = MTLStencilDescriptor::new();
+    stencil.setStencilCompareFunction(compare);
+    stencil.setDepthStencilPassOperation(pass_operation);
+
+    let descriptor = MTLDepthStencilDescriptor::new();
+    descriptor.setFrontFaceStencil(Some(&stencil));
+    descriptor.setBackFaceStencil(Some(&stencil));
+
+    device
+        .newDepthStencilStateWithDescriptor(&descriptor)
+        .expect("Create depth stencil state")
+}
+
+struct WindowState {
+    device: Retained<ProtocolObject<dyn MTLDevice>>,
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+
+    surface: Retained<CAMetalLayer>,
+    stencil_texture: Retained<ProtocolObject<dyn MTLTexture>>,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+    text_buffer: Buffer,
+
+    circle_pipeline: Retained<ProtocolObject<dyn MTLRenderPipelineState>>,
+    /// Writes the reference value wherever the circle is drawn.
+    mark_clip: Retained<ProtocolObject<dyn MTLDepthStencilState>>,
+    /// Passes only where the stencil buffer equals the reference value.
+    test_clip: Retained<ProtocolObject<dyn MTLDepthStencilState>>,
+    /// The center of the circle, in pixels.
+    center: [f32; 2],
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the Metal layer is dropped.
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(window: Arc<Window>) -> Self {
+        let view = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+        let queue = device.newCommandQueue().expect("Create command queue");
+
+        let surface = CAMetalLayer::new();
+        surface.setDevice(Some(&device));
+        surface.setPixelFormat(COLOR_FORMAT);
+        surface.setPresentsWithTransaction(false);
+
+        view.setWantsLayer(true);
+        view.setLayer(Some(&surface));
+
+        // Set up text renderer for render passes with a stencil attachment
+        let mut font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        let cache = Cache::new(&device);
+        let viewport = Viewport::new(&device);
+        let mut atlas = TextAtlas::new(&device, &cache, COLOR_FORMAT);
+        let text_renderer = TextRenderer::new(&mut atlas, STENCIL_FORMAT, 1);
+
+        let mut text_buffer = Buffer::new(&mut font_system, Metrics::new(28.0, 38.0));
+        text_buffer.set_text(
+            &mut font_system,
+            &TEXT.repeat(8),
+            &Attrs::new().family(Family::SansSerif),
+            Shaping::Advanced,
+        );
+
+        // Set up the circle
+        let library = device
+            .newLibraryWithSource_options_error(&NSString::from_str(CIRCLE_SHADER), None)
+            .expect("Create circle shader library");
+
+        let descriptor = MTLRenderPipelineDescriptor::new();
+        descriptor.setVertexFunction(
+            library
+                .newFunctionWithName(ns_string!("circle_vertex"))
+                .as_deref(),
+        );
+        descriptor.setFragmentFunction(
+            library
+                .newFunctionWithName(ns_string!("circle_fragment"))
+                .as_deref(),
+        );
+        descriptor.setStencilAttachmentPixelFormat(STENCIL_FORMAT);
+        unsafe { descriptor.colorAttachments().objectAtIndexedSubscript(0) }
+            .setPixelFormat(COLOR_FORMAT);
+        let circle_pipeline = device
+            .newRenderPipelineStateWithDescriptor_error(&descriptor)
+            .expect("Create circle pipeline");
+
+        let mut state = Self {
+            mark_clip: stencil_state(
+                &device,
+                MTLCompareFunction::Always,
+                MTLStencilOperation::Replace,
+            ),
+            test_clip: stencil_state(
+                &device,
+                MTLCompareFunction::Equal,
+                MTLStencilOperation::Keep,
+            ),
+            circle_pipeline,
+            center: [0.0; 2],
+            stencil_texture: create_stencil_texture(&device, 1, 1),
+
+            device,
+            queue,
+
+            surface,
+
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            text_buffer,
+
+            window,
+        };
+        state.resize();
+
+        state
+    }
+
+    fn resize(&mut self) {
+        let size = self.window.inner_size();
+        self.surface.setDrawableSize(CGSize {
+            width: size.width as f64,
+            height: size.height as f64,
+        });
+        self.stencil_texture = create_stencil_texture(&self.device, size.width, size.height);
+        self.center = [size.width as f32 / 2.0, size.height as f32 / 2.0];
+
+        let scale_factor = self.window.scale_factor() as f32;
+        self.text_buffer.set_size(
+            &mut self.font_system,
+            Some(size.width as f32 / scale_factor - 40.0),
+            None,
+        );
+        self.text_buffer
+            .shape_until_scroll(&mut self.font_system, false);
+    }
+
+    fn redraw(&mut self) {
+        autoreleasepool(|_| {
+            let Some(drawable) = self.surface.nextDrawable() else {
+                return;
+            };
+
+            let resolution = Resolution {
+                width: self.surface.drawableSize().width as u32,
+                height: self.surface.drawableSize().height as u32,
+            };
+
+            let scale_factor = self.window.scale_factor() as f32;
+            self.viewport.update_with_scale(resolution, scale_factor);
+
+            self.text_renderer
+                .prepare(
+                    &mut self.font_system,
+                    &mut self.atlas,
+                    &self.viewport,
+                    [TextArea {
+                        left: 20.0,
+                        top: 20.0,
+                        ..TextArea::new(&self.text_buffer)
+                    }],
+                    &mut self.swash_cache,
+                )
+                .unwrap();
+
+            let render_pass_descriptor = MTLRenderPassDescriptor::new();
+            let color_attachment = unsafe {
+                render_pass_descriptor
+                    .colorAttachments()
+                    .objectAtIndexedSubscript(0)
+            };
+
+            color_attachment.setTexture(Some(&drawable.texture()));
+            color_attachment.setLoadAction(MTLLoadAction::Clear);
+            color_attachment.setClearColor(MTLClearColor {
+                red: 0.05,
+                green: 0.05,
+                blue: 0.06,
+                alpha: 1.0,
+            });
+            color_attachment.setStoreAction(MTLStoreAction::Store);
+
+            let stencil_attachment = render_pass_descriptor.stencilAttachment();
+            stencil_attachment.setTexture(Some(&self.stencil_texture));
+            stencil_attachment.setLoadAction(MTLLoadAction::Clear);
+            stencil_attachment.setClearStencil(0);
+            stencil_attachment.setStoreAction(MTLStoreAction::DontCare);
+
+            let Some(buffer) = self.queue.commandBuffer() else {
+                return;
+            };
+
+            let Some(render_encoder) =
+                buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            else {
+                return;
+            };
+
+            // The circle first, marking the clip shape in the stencil buffer
+            let circle = Circle {
+                center: self.center,
+                radius: 180.0 * scale_factor,
+            };
+            render_encoder.setRenderPipelineState(&self.circle_pipeline);
+            render_encoder.setDepthStencilState(Some(&self.mark_clip));
+            render_encoder.setStencilReferenceValue(CLIP_REFERENCE);
+            unsafe {
+                render_encoder.setFragmentBytes_length_atIndex(
+                    NonNull::from(&circle).cast(),
+                    size_of::<Circle>(),
+                    0,
+                );
+                render_encoder.drawPrimitives_vertexStart_vertexCount(
+                    MTLPrimitiveType::Triangle,
+                    0,
+                    3,
+                );
+            }
+
+            // Then the text, tested against the clip shape with the same reference value
+            render_encoder.setDepthStencilState(Some(&self.test_clip));
+            self.text_renderer
+                .render(&self.atlas, &self.viewport, &render_encoder);
+
+            render_encoder.endEncoding();
+
+            buffer.presentDrawable(drawable.as_ref());
+            buffer.commit();
+            self.atlas.trim();
+        });
+    }
+}
+
+fn create_stencil_texture(
+    device: &ProtocolObject<dyn MTLDevice>,
+    width: u32,
+    height: u32,
+) -> Retained<ProtocolObject<dyn MTLTexture>> {
+    let descriptor = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            STENCIL_FORMAT,
+            width.max(1) as usize,
+            height.max(1) as usize,
+            false,
+        )
+    };
+    descriptor.setUsage(MTLTextureUsage::RenderTarget);
+    descriptor.setStorageMode(MTLStorageMode::Private);
+
+    device
+        .newTextureWithDescriptor(&descriptor)
+        .expect("Create stencil texture")
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        // Set up window
+        let (width, height) = (900, 600);
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_title("metalglyph stencil clip");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(WindowState::new(window));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        match event {
+            WindowEvent::Resized(_) => {
+                state.resize();
+                state.window.request_redraw();
+            }
+
+            WindowEvent::CursorMoved { position, .. } => {
+                state.center = [position.x as f32, position.y as f32];
+                state.window.request_redraw();
+            }
+
+            WindowEvent::RedrawRequested => state.redraw(),
+
+            WindowEvent::CloseRequested => event_loop.exit(),
+
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Rendering the same prepared text for both eyes, as a visionOS app using Compositor Services
+//! does.
+//!
+//! A Compositor Services frame provides an `MTLDevice`, a color texture and a view per eye. Text
+//! renders into any texture, so no `CAMetalLayer` is involved:
+//!
+//! - The viewport has one parameter slot per eye. Here the slots only differ by their origin,
+//!   offsetting the text horizontally in opposite directions so that it appears at a distance.
+//! - Text is prepared once per frame, with the slot of the left eye active. Both eyes share the
+//!   resolution and scale factor that glyphs are rasterized at.
+//! - Each eye is rendered in its own render pass with `render_with_slot`, without preparing
+//!   again.
+//!
+//! To run on every platform, the eye textures are created here rather than taken from a
+//! `cp_drawable`, and the example renders a few frames and exits. Eye textures of Compositor
+//! Services default to `RGBA16Float`, which the atlas is created for.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache, TextArea,
+    TextAtlas, TextRenderer, Viewport,
+};
+use objc2::{
+    rc::{autoreleasepool, Retained},
+    runtime::ProtocolObject,
+};
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCreateSystemDefaultDevice, MTLDevice, MTLLoadAction, MTLPixelFormat,
+    MTLRenderPassDescriptor, MTLStorageMode, MTLStoreAction, MTLTexture, MTLTextureDescriptor,
+    MTLTextureUsage,
+};
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::RGBA16Float;
+const EYE_RESOLUTION: Resolution = Resolution {
+    width: 1888,
+    height: 1824,
+};
+/// Half of the horizontal disparity between the eyes, in physical pixels.
+const HALF_DISPARITY: i32 = 12;
+const FRAMES: usize = 3;
+
+fn main() {
+    let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+    let queue = device.newCommandQueue().expect("Create command queue");
+
+    // In a Compositor Services app, these come from the drawable of every frame
+    let eyes: Vec<_> = (0..2).map(|_| create_eye_texture(&device)).collect();
+
+    // Set up text renderer
+    let mut font_system = FontSystem::new();
+    let mut swash_cache = SwashCache::new();
+    let cache = Cache::new(&device);
+    let mut viewport = Viewport::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    // Text is laid out in points, scaled to pixels by the viewport
+    let scale_factor = 2.0;
+    let mut text_buffer = Buffer::new(&mut font_system, Metrics::new(40.0, 52.0));
+    text_buffer.set_size(
+        &mut font_system,
+        Some(EYE_RESOLUTION.width as f32 / scale_factor - 80.0),
+        None,
+    );
+    text_buffer.set_text(
+        &mut font_system,
+        "Hello, visionOS 🥽\nPrepared once, rendered for each eye.",
+        &Attrs::new().family(Family::SansSerif),
+        Shaping::Advanced,
+    );
+    text_buffer.shape_until_scroll(&mut font_system, false);
+
+    // One slot per eye, all created before preparing
+    for (slot, offset) in [(0, HALF_DISPARITY), (1, -HALF_DISPARITY)] {
+        viewport
+            .set_active_slot(slot)
+            .expect("Create viewport slot");
+        viewport.update_with_scale(EYE_RESOLUTION, scale_factor);
+        viewport.set_origin(offset, 0);
+    }
+    viewport.set_active_slot(0).expect("Create viewport slot");
+
+    for frame in 0..FRAMES {
+        autoreleasepool(|_| {
+            text_renderer
+                .prepare(
+                    &mut font_system,
+                    &mut atlas,
+                    &viewport,
+                    [TextArea {
+                        left: 40.0,
+                        top: 40.0,
+                        ..TextArea::new(&text_buffer)
+                    }],
+                    &mut swash_cache,
+                )
+                .unwrap();
+
+            let command_buffer = queue.commandBuffer().expect("Create command buffer");
+
+            for (slot, eye) in eyes.iter().enumerate() {
+                let render_pass_descriptor = MTLRenderPassDescriptor::new();
+                let color_attachment = unsafe {
+                    render_pass_descriptor
+                        .colorAttachments()
+                        .objectAtIndexedSubscript(0)
+                };
+
+                // Passthrough apps clear to transparent so that the surroundings stay visible
+                color_attachment.setTexture(Some(eye));
+                color_attachment.setLoadAction(MTLLoadAction::Clear);
+                color_attachment.setClearColor(MTLClearColor {
+                    red: 0.0,
+                    green: 0.0,
+                    blue: 0.0,
+                    alpha: 0.0,
+                });
+                color_attachment.setStoreAction(MTLStoreAction::Store);
+
+                let render_encoder = command_buffer
+                    .renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+                    .expect("Create render command encoder");
+
+                text_renderer.render_with_slot(&atlas, &viewport, &render_encoder, slot);
+
+                render_encoder.endEncoding();
+            }
+
+            command_buffer.commit();
+            command_buffer.waitUntilCompleted();
+            atlas.trim();
+        });
+
+        println!("Rendered frame {} for both eyes", frame + 1);
+    }
+}
+
+fn create_eye_texture(
+    device: &ProtocolObject<dyn MTLDevice>,
+) -> Retained<ProtocolObject<dyn MTLTexture>> {
+    let descriptor = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            FORMAT,
+            EYE_RESOLUTION.width as usize,
+            EYE_RESOLUTION.height as usize,
+            false,
+        )
+    };
+    descriptor.setUsage(MTLTextureUsage::RenderTarget | MTLTextureUsage::ShaderRead);
+    descriptor.setStorageMode(MTLStorageMode::Private);
+
+    device
+        .newTextureWithDescriptor(&descriptor)
+        .expect("Create eye texture")
+}
@@ -0,0 +1,514 @@
+//! Thousands of moving labels and a few large paragraphs, with live statistics.
+//!
+//! Every label is a text area of its own, moving and bouncing off the edges of the window with a
+//! color of its own, on top of large paragraphs drifting sideways. All of them are prepared on
+//! every frame, which stresses the per-area work of `prepare`, the reuse of the vertex buffer and
+//! the culling of glyphs outside of the window, rather than rasterization: labels share a small
+//! pool of buffers, so the atlas fills up in the first frames.
+//!
+//! Press `+` to double the number of labels and `-` to halve it. The overlay, rendered by
+//! metalglyph with its own atlas, shows the frame rate, the duration of `prepare` and of its
+//! phases, the number of drawn and culled glyphs, the occupancy of the atlas and the bytes
+//! uploaded per frame. It is a shared scenario to quote numbers from when measuring changes to
+//! `prepare`: run it with `--release` and compare a fixed label count.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Color, ContentType, Family, FontSystem, Metrics, Resolution, Shaping,
+    SwashCache, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2::{
+    rc::{autoreleasepool, Retained},
+    runtime::ProtocolObject,
+};
+use objc2_app_kit::NSView;
+use objc2_core_foundation::CGSize;
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCreateSystemDefaultDevice, MTLDevice, MTLLoadAction, MTLPixelFormat,
+    MTLRenderPassDescriptor, MTLStoreAction,
+};
+use objc2_quartz_core::{CAMetalDrawable, CAMetalLayer};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, WindowEvent},
+    event_loop::EventLoop,
+    keyboard::Key,
+    window::Window,
+};
+
+const PARAGRAPH: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do \
+    eiusmod tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis \
+    nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat. Duis aute irure \
+    dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat nulla pariatur. \
+    Excepteur sint occaecat cupidatat non proident, sunt in culpa qui officia deserunt mollit \
+    anim id est laborum.";
+
+/// The number of labels at startup.
+const INITIAL_LABELS: usize = 1000;
+/// The number of distinct label buffers shared by the labels.
+const LABEL_BUFFERS: usize = 64;
+/// The number of large paragraphs.
+const PARAGRAPHS: usize = 4;
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+/// A label moving across the window, in logical pixels.
+struct Label {
+    buffer: usize,
+    position: [f32; 2],
+    velocity: [f32; 2],
+    color: Color,
+}
+
+/// A xorshift generator, so that runs with the same label count are comparable.
+struct Rng(u32);
+
+impl Rng {
+    /// Returns a number between `0.0` and `1.0`.
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32
+    }
+
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next()
+    }
+}
+
+/// The frame rate, averaged over half a second.
+struct FrameRate {
+    since: Instant,
+    frames: u32,
+    fps: f32,
+}
+
+impl FrameRate {
+    fn tick(&mut self) {
+        self.frames += 1;
+
+        let elapsed = self.since.elapsed();
+        if elapsed >= Duration::from_millis(500) {
+            self.fps = self.frames as f32 / elapsed.as_secs_f32();
+            self.frames = 0;
+            self.since = Instant::now();
+        }
+    }
+}
+
+struct WindowState {
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+
+    surface: Retained<CAMetalLayer>,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+    overlay_atlas: TextAtlas,
+    overlay_renderer: TextRenderer,
+    label_buffers: Vec<Buffer>,
+    paragraphs: Vec<Buffer>,
+    overlay: Buffer,
+
+    labels: Vec<Label>,
+    rng: Rng,
+    start: Instant,
+    last_frame: Instant,
+    frame_rate: FrameRate,
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the Metal layer is dropped.
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(window: Arc<Window>) -> Self {
+        let physical_size = window.inner_size();
+
+        let view = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+        let queue = device.newCommandQueue().expect("Create command queue");
+
+        let surface = CAMetalLayer::new();
+        surface.setDevice(Some(&device));
+        surface.setPixelFormat(MTLPixelFormat::BGRA8Unorm);
+        surface.setPresentsWithTransaction(false);
+
+        surface.setDrawableSize(CGSize {
+            width: physical_size.width as f64,
+            height: physical_size.height as f64,
+        });
+
+        view.setWantsLayer(true);
+        view.setLayer(Some(&surface));
+
+        // Set up text renderers
+        let mut font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        let cache = Cache::new(&device);
+        let viewport = Viewport::new(&device);
+        let mut atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
+        let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+        text_renderer.set_phase_timings(true);
+        let mut overlay_atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
+        let overlay_renderer = TextRenderer::new(&mut overlay_atlas, MTLPixelFormat::Invalid, 1);
+
+        let label_buffers = (0..LABEL_BUFFERS)
+            .map(|i| {
+                let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 18.0));
+                buffer.set_text(
+                    &mut font_system,
+                    &format!("label #{i}"),
+                    &Attrs::new().family(Family::SansSerif),
+                    Shaping::Advanced,
+                );
+                buffer.shape_until_scroll(&mut font_system, false);
+                buffer
+            })
+            .collect();
+
+        let paragraphs = (0..PARAGRAPHS)
+            .map(|i| {
+                let font_size = 18.0 + 6.0 * i as f32;
+                let mut buffer =
+                    Buffer::new(&mut font_system, Metrics::new(font_size, font_size * 1.3));
+                buffer.set_size(&mut font_system, Some(480.0), None);
+                buffer.set_text(
+                    &mut font_system,
+                    PARAGRAPH,
+                    &Attrs::new().family(Family::Serif),
+                    Shaping::Advanced,
+                );
+                buffer.shape_until_scroll(&mut font_system, false);
+                buffer
+            })
+            .collect();
+
+        let overlay = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+
+        let mut state = Self {
+            queue,
+
+            surface,
+
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            overlay_atlas,
+            overlay_renderer,
+            label_buffers,
+            paragraphs,
+            overlay,
+
+            labels: Vec::new(),
+            rng: Rng(0x9e37_79b9),
+            start: Instant::now(),
+            last_frame: Instant::now(),
+            frame_rate: FrameRate {
+                since: Instant::now(),
+                frames: 0,
+                fps: 0.0,
+            },
+
+            window,
+        };
+
+        state.set_label_count(INITIAL_LABELS);
+
+        state
+    }
+
+    fn logical_size(&self) -> LogicalSize<f32> {
+        self.window
+            .inner_size()
+            .to_logical(self.window.scale_factor())
+    }
+
+    fn set_label_count(&mut self, count: usize) {
+        let LogicalSize { width, height } = self.logical_size();
+
+        self.labels.truncate(count);
+        while self.labels.len() < count {
+            let rng = &mut self.rng;
+            let speed = rng.range(20.0, 200.0);
+            let angle = rng.range(0.0, std::f32::consts::TAU);
+
+            self.labels.push(Label {
+                buffer: self.labels.len() % LABEL_BUFFERS,
+                position: [rng.range(0.0, width), rng.range(0.0, height)],
+                velocity: [speed * angle.cos(), speed * angle.sin()],
+                color: Color::rgb(
+                    rng.range(64.0, 255.0) as u8,
+                    rng.range(64.0, 255.0) as u8,
+                    rng.range(64.0, 255.0) as u8,
+                ),
+            });
+        }
+    }
+
+    fn resize(&mut self) {
+        let size = self.window.inner_size();
+        self.surface.setDrawableSize(CGSize {
+            width: size.width as f64,
+            height: size.height as f64,
+        });
+    }
+
+    /// Moves the labels by `dt` seconds, bouncing them off the edges of the window.
+    fn animate(&mut self, dt: f32) {
+        let LogicalSize { width, height } = self.logical_size();
+
+        for label in &mut self.labels {
+            for (axis, max) in [(0, width - 60.0), (1, height - 18.0)] {
+                label.position[axis] += label.velocity[axis] * dt;
+
+                if label.position[axis] < 0.0 || label.position[axis] > max {
+                    label.position[axis] = label.position[axis].clamp(0.0, max.max(0.0));
+                    label.velocity[axis] = -label.velocity[axis];
+                }
+            }
+        }
+    }
+
+    fn update_overlay(&mut self, prepare_time: Duration) {
+        let timings = self.text_renderer.phase_timings().unwrap_or_default();
+        let glyphs = self.text_renderer.vertex_bytes() / 24;
+        let ms = |duration: Duration| duration.as_secs_f32() * 1000.0;
+
+        let mut text = format!(
+            "{labels} labels, {paragraphs} paragraphs: {fps:.1} fps\n\
+             prepare: {prepare:.2} ms (layout {layout:.2}, lookup {lookup:.2}, \
+             rasterization {rasterization:.2}, upload {upload:.2}, vertices {vertices:.2}, \
+             buffer write {buffer_write:.2})\n\
+             glyphs: {glyphs} drawn, {vertex_bytes} vertex bytes\n",
+            labels = self.labels.len(),
+            paragraphs = self.paragraphs.len(),
+            fps = self.frame_rate.fps,
+            prepare = ms(prepare_time),
+            layout = ms(timings.layout),
+            lookup = ms(timings.lookup),
+            rasterization = ms(timings.rasterization),
+            upload = ms(timings.atlas_upload),
+            vertices = ms(timings.vertex_generation),
+            buffer_write = ms(timings.buffer_write),
+            vertex_bytes = self.text_renderer.vertex_bytes(),
+        );
+
+        // The counters are reset after every frame, so they are per frame
+        for (name, content_type) in [("mask", ContentType::Mask), ("color", ContentType::Color)] {
+            let stats = self.atlas.stats(content_type);
+            text += &format!(
+                "{name} atlas: {size}x{size} px, {occupancy:.1}% occupied, {cached} glyphs, \
+                 {culled} culled, {uploaded} bytes uploaded in {writes} writes\n",
+                size = stats.size,
+                occupancy = stats.occupancy * 100.0,
+                cached = stats.glyph_count,
+                culled = stats.culled,
+                uploaded = stats.uploaded_bytes,
+                writes = stats.texture_writes,
+            );
+        }
+
+        text += "+ double the labels, - halve them";
+
+        self.overlay.set_text(
+            &mut self.font_system,
+            &text,
+            &Attrs::new().family(Family::Monospace),
+            Shaping::Basic,
+        );
+        self.overlay
+            .shape_until_scroll(&mut self.font_system, false);
+    }
+
+    fn redraw(&mut self) {
+        let now = Instant::now();
+        let dt = (now - self.last_frame).as_secs_f32().min(0.1);
+        self.last_frame = now;
+        self.frame_rate.tick();
+        self.animate(dt);
+
+        autoreleasepool(|_| {
+            let Some(drawable) = self.surface.nextDrawable() else {
+                return;
+            };
+
+            let resolution = Resolution {
+                width: self.surface.drawableSize().width as u32,
+                height: self.surface.drawableSize().height as u32,
+            };
+
+            self.viewport
+                .update_with_scale(resolution, self.window.scale_factor() as f32);
+
+            // The paragraphs drift sideways, half of them partly out of the window, where their
+            // glyphs are culled
+            let time = self.start.elapsed().as_secs_f32();
+            let paragraphs = self
+                .paragraphs
+                .iter()
+                .enumerate()
+                .map(|(i, buffer)| TextArea {
+                    left: 40.0 + 160.0 * (time * 0.3 + i as f32).sin() + 120.0 * i as f32,
+                    top: 140.0 + 110.0 * i as f32,
+                    default_color: Color::rgba(160, 160, 170, 200),
+                    ..TextArea::new(buffer)
+                });
+            let labels = self.labels.iter().map(|label| TextArea {
+                left: label.position[0],
+                top: label.position[1],
+                default_color: label.color,
+                ..TextArea::new(&self.label_buffers[label.buffer])
+            });
+
+            let prepare_start = Instant::now();
+            self.text_renderer
+                .prepare(
+                    &mut self.font_system,
+                    &mut self.atlas,
+                    &self.viewport,
+                    paragraphs.chain(labels),
+                    &mut self.swash_cache,
+                )
+                .unwrap();
+            let prepare_time = prepare_start.elapsed();
+
+            self.update_overlay(prepare_time);
+            self.overlay_renderer
+                .prepare(
+                    &mut self.font_system,
+                    &mut self.overlay_atlas,
+                    &self.viewport,
+                    [TextArea {
+                        left: 10.0,
+                        top: 10.0,
+                        default_color: Color::rgb(255, 210, 80),
+                        ..TextArea::new(&self.overlay)
+                    }],
+                    &mut self.swash_cache,
+                )
+                .unwrap();
+
+            let render_pass_descriptor = MTLRenderPassDescriptor::new();
+            let color_attachment = unsafe {
+                render_pass_descriptor
+                    .colorAttachments()
+                    .objectAtIndexedSubscript(0)
+            };
+
+            color_attachment.setTexture(Some(&drawable.texture()));
+            color_attachment.setLoadAction(MTLLoadAction::Clear);
+            color_attachment.setClearColor(MTLClearColor {
+                red: 0.0,
+                green: 0.0,
+                blue: 0.0,
+                alpha: 1.0,
+            });
+            color_attachment.setStoreAction(MTLStoreAction::Store);
+
+            let Some(buffer) = self.queue.commandBuffer() else {
+                return;
+            };
+
+            let Some(render_encoder) =
+                buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            else {
+                return;
+            };
+
+            self.text_renderer
+                .render(&self.atlas, &self.viewport, &render_encoder);
+            self.overlay_renderer
+                .render(&self.overlay_atlas, &self.viewport, &render_encoder);
+
+            render_encoder.endEncoding();
+
+            buffer.presentDrawable(drawable.as_ref());
+            buffer.commit();
+            self.atlas.trim();
+            self.overlay_atlas.trim();
+            self.atlas.reset_stats();
+        });
+
+        // Draw continuously, paced by the drawables of the layer
+        self.window.request_redraw();
+    }
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        // Set up window
+        let (width, height) = (1200, 800);
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_title("metalglyph stress test");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(WindowState::new(window));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        match event {
+            WindowEvent::Resized(_) => state.resize(),
+
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+                match event.logical_key.as_ref() {
+                    Key::Character("+" | "=") => {
+                        let count = (state.labels.len() * 2).max(1);
+                        state.set_label_count(count);
+                    }
+                    Key::Character("-") => {
+                        let count = state.labels.len() / 2;
+                        state.set_label_count(count);
+                    }
+                    _ => {}
+                }
+            }
+
+            WindowEvent::RedrawRequested => state.redraw(),
+
+            WindowEvent::CloseRequested => event_loop.exit(),
+
+            _ => {}
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use metalglyph::{
-    Attrs, Buffer, Cache, Color, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache,
-    TextArea, TextAtlas, TextBounds, TextRenderer, Viewport, Weight,
+    measure, Attrs, Buffer, Cache, Color, Family, FontSystem, Metrics, Resolution, Shaping,
+    SwashCache, TextArea, TextAtlas, TextBounds, TextRenderer, Viewport, Weight,
 };
 use objc2::{
     rc::{autoreleasepool, Retained},
@@ -42,7 +42,6 @@ fn main() {
 }
 
 struct WindowState {
-    device: Retained<ProtocolObject<dyn MTLDevice>>,
     queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
 
     surface: Retained<CAMetalLayer>,
@@ -93,7 +92,7 @@ impl WindowState {
         let cache = Cache::new(&device);
         let viewport = Viewport::new(&device);
         let mut atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
-        let text_renderer = TextRenderer::new(&mut atlas, &device, MTLPixelFormat::Depth32Float, 1);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
 
         view.setWantsLayer(true);
         view.setLayer(Some(&surface));
@@ -125,7 +124,6 @@ impl WindowState {
             .collect();
 
         Self {
-            device,
             queue,
 
             surface,
@@ -176,7 +174,6 @@ impl winit::application::ApplicationHandler for Application {
 
         let WindowState {
             window,
-            device,
             queue,
             surface,
             font_system,
@@ -236,7 +233,6 @@ impl winit::application::ApplicationHandler for Application {
                         .iter()
                         .map(|b| {
                             let a = TextArea {
-                                buffer: b,
                                 left,
                                 top,
                                 scale: scale_factor,
@@ -247,29 +243,17 @@ impl winit::application::ApplicationHandler for Application {
                                     bottom: top.floor() as i32 + physical_size.height,
                                 },
                                 default_color: FONT_COLOR,
-                                custom_glyphs: &[],
+                                ..TextArea::new(b)
                             };
 
-                            let total_lines = b
-                                .layout_runs()
-                                .fold(0usize, |total_lines, _| total_lines + 1);
-
-                            top +=
-                                (total_lines as f32 * b.metrics().line_height + 5.0) * scale_factor;
+                            top += (measure(b).height + 5.0) * scale_factor;
 
                             a
                         })
                         .collect();
 
                     text_renderer
-                        .prepare(
-                            device,
-                            font_system,
-                            atlas,
-                            viewport,
-                            text_areas,
-                            swash_cache,
-                        )
+                        .prepare(font_system, atlas, viewport, text_areas, swash_cache)
                         .unwrap();
 
                     let render_pass_descriptor = MTLRenderPassDescriptor::new();
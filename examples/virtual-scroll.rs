@@ -0,0 +1,355 @@
+//! Smooth scrolling through a 100,000 line document.
+//!
+//! Only the visible slice of the document is ever shaped and prepared:
+//!
+//! - The buffer is sized to the window, so its layout runs only cover the lines on screen.
+//! - Scrolling moves `Buffer::scroll` and calls `shape_until_scroll` with pruning, which shapes
+//!   the lines that scroll in and drops the layout of the lines that scroll out.
+//! - `prepare` then only sees the visible layout runs.
+//!
+//! The overlay in the corner shows the atlas sizes and the number of cached glyphs. Since the
+//! lines keep using the same characters, the counts settle after the first few screens instead
+//! of growing with the distance scrolled: trimming every frame lets the LRU cache evict glyphs
+//! that scrolled away, and glyphs that are still used stay cached.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Color, ContentType, Family, FontSystem, Metrics, Resolution, Shaping,
+    SwashCache, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2::{
+    rc::{autoreleasepool, Retained},
+    runtime::ProtocolObject,
+};
+use objc2_app_kit::NSView;
+use objc2_core_foundation::CGSize;
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCreateSystemDefaultDevice, MTLDevice, MTLLoadAction, MTLPixelFormat,
+    MTLRenderPassDescriptor, MTLStoreAction,
+};
+use objc2_quartz_core::{CAMetalDrawable, CAMetalLayer};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::{fmt::Write as _, sync::Arc};
+use winit::{
+    dpi::LogicalSize,
+    event::{MouseScrollDelta, WindowEvent},
+    event_loop::EventLoop,
+    window::Window,
+};
+
+const LINE_COUNT: usize = 100_000;
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+/// Generates a log file with `LINE_COUNT` lines.
+fn generate_document() -> String {
+    const LEVELS: [&str; 4] = ["DEBUG", "INFO ", "WARN ", "ERROR"];
+    const MESSAGES: [&str; 5] = [
+        "request completed",
+        "cache miss, fetching from upstream",
+        "retrying after timeout",
+        "connection reset by peer",
+        "flushed write-ahead log",
+    ];
+
+    let mut document = String::with_capacity(LINE_COUNT * 80);
+    for i in 0..LINE_COUNT {
+        let millis = i * 37;
+        writeln!(
+            document,
+            "{:02}:{:02}:{:02}.{:03} {} worker-{} #{:06} {} in {} ms",
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            millis % 1000,
+            LEVELS[i * 7 % LEVELS.len()],
+            i % 8,
+            i,
+            MESSAGES[i * 13 % MESSAGES.len()],
+            i * 31 % 500,
+        )
+        .unwrap();
+    }
+
+    document
+}
+
+struct WindowState {
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+
+    surface: Retained<CAMetalLayer>,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+    document: Buffer,
+    stats: Buffer,
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the Metal layer is dropped.
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(window: Arc<Window>) -> Self {
+        let physical_size = window.inner_size();
+        let scale_factor = window.scale_factor() as f32;
+
+        let view = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+        let queue = device.newCommandQueue().expect("Create command queue");
+
+        let surface = CAMetalLayer::new();
+        surface.setDevice(Some(&device));
+        surface.setPixelFormat(MTLPixelFormat::BGRA8Unorm);
+        surface.setPresentsWithTransaction(false);
+
+        view.setWantsLayer(true);
+        view.setLayer(Some(&surface));
+
+        // Set up text renderer
+        let mut font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        let cache = Cache::new(&device);
+        let viewport = Viewport::new(&device);
+        let mut atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+        // Buffers are laid out in physical pixels, so text areas use a scale of 1. Setting the
+        // text only splits it into lines, shaping happens in `shape_until_scroll`.
+        let mut document = Buffer::new(
+            &mut font_system,
+            Metrics::new(14.0 * scale_factor, 20.0 * scale_factor),
+        );
+        document.set_text(
+            &mut font_system,
+            &generate_document(),
+            &Attrs::new().family(Family::Monospace),
+            Shaping::Basic,
+        );
+
+        let stats = Buffer::new(
+            &mut font_system,
+            Metrics::new(13.0 * scale_factor, 18.0 * scale_factor),
+        );
+
+        let mut state = Self {
+            queue,
+
+            surface,
+
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            document,
+            stats,
+
+            window,
+        };
+        state.resize();
+
+        state
+    }
+
+    fn resize(&mut self) {
+        let size = self.window.inner_size();
+        self.surface.setDrawableSize(CGSize {
+            width: size.width as f64,
+            height: size.height as f64,
+        });
+
+        // The height limits layout runs to the lines on screen
+        self.document.set_size(
+            &mut self.font_system,
+            Some(size.width as f32),
+            Some(size.height as f32),
+        );
+        self.document
+            .shape_until_scroll(&mut self.font_system, true);
+    }
+
+    fn scroll(&mut self, delta: MouseScrollDelta) {
+        let pixels = match delta {
+            MouseScrollDelta::LineDelta(_, lines) => -lines * self.document.metrics().line_height,
+            MouseScrollDelta::PixelDelta(position) => -position.y as f32,
+        };
+
+        let mut scroll = self.document.scroll();
+        scroll.vertical += pixels;
+        self.document.set_scroll(scroll);
+
+        // Normalizes the scroll position into a line and an offset within it, clamps it to the
+        // document, and shapes the lines that became visible. Pruning drops the layout of lines
+        // that are no longer visible, so memory use does not grow with the distance scrolled.
+        self.document
+            .shape_until_scroll(&mut self.font_system, true);
+        self.window.request_redraw();
+    }
+
+    fn update_stats(&mut self) {
+        let scroll = self.document.scroll();
+        let visible_lines = self.document.layout_runs().count();
+
+        let text = format!(
+            "line {line} of {LINE_COUNT}, {visible_lines} visible\n\
+             mask atlas: {mask_size}x{mask_size} px, {mask_glyphs} glyphs\n\
+             color atlas: {color_size}x{color_size} px, {color_glyphs} glyphs",
+            line = scroll.line + 1,
+            mask_size = self.atlas.size(ContentType::Mask),
+            mask_glyphs = self.atlas.glyph_count(ContentType::Mask),
+            color_size = self.atlas.size(ContentType::Color),
+            color_glyphs = self.atlas.glyph_count(ContentType::Color),
+        );
+
+        self.stats.set_text(
+            &mut self.font_system,
+            &text,
+            &Attrs::new().family(Family::Monospace),
+            Shaping::Basic,
+        );
+        self.stats.shape_until_scroll(&mut self.font_system, false);
+    }
+
+    fn redraw(&mut self) {
+        // The stats describe the atlas as left by the previous frame
+        self.update_stats();
+
+        autoreleasepool(|_| {
+            let Some(drawable) = self.surface.nextDrawable() else {
+                return;
+            };
+
+            let resolution = Resolution {
+                width: self.surface.drawableSize().width as u32,
+                height: self.surface.drawableSize().height as u32,
+            };
+
+            self.viewport.update(resolution);
+
+            let scale_factor = self.window.scale_factor() as f32;
+            let stats_width = 320.0 * scale_factor;
+            let stats_left = resolution.width as f32 - stats_width;
+
+            self.text_renderer
+                .prepare(
+                    &mut self.font_system,
+                    &mut self.atlas,
+                    &self.viewport,
+                    [
+                        TextArea {
+                            left: 8.0 * scale_factor,
+                            default_color: Color::rgb(210, 210, 210),
+                            ..TextArea::new(&self.document)
+                        },
+                        TextArea {
+                            left: stats_left,
+                            top: 8.0 * scale_factor,
+                            default_color: Color::rgb(255, 210, 80),
+                            ..TextArea::new(&self.stats)
+                        },
+                    ],
+                    &mut self.swash_cache,
+                )
+                .unwrap();
+
+            let render_pass_descriptor = MTLRenderPassDescriptor::new();
+            let color_attachment = unsafe {
+                render_pass_descriptor
+                    .colorAttachments()
+                    .objectAtIndexedSubscript(0)
+            };
+
+            color_attachment.setTexture(Some(&drawable.texture()));
+            color_attachment.setLoadAction(MTLLoadAction::Clear);
+            color_attachment.setClearColor(MTLClearColor {
+                red: 0.05,
+                green: 0.05,
+                blue: 0.05,
+                alpha: 1.0,
+            });
+            color_attachment.setStoreAction(MTLStoreAction::Store);
+
+            let Some(buffer) = self.queue.commandBuffer() else {
+                return;
+            };
+
+            let Some(render_encoder) =
+                buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            else {
+                return;
+            };
+
+            self.text_renderer
+                .render(&self.atlas, &self.viewport, &render_encoder);
+
+            render_encoder.endEncoding();
+
+            buffer.presentDrawable(drawable.as_ref());
+            buffer.commit();
+            self.atlas.trim();
+        });
+    }
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        // Set up window
+        let (width, height) = (900, 700);
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_title("metalglyph virtual scroll");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(WindowState::new(window));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        match event {
+            WindowEvent::Resized(_) => {
+                state.resize();
+                state.window.request_redraw();
+            }
+
+            WindowEvent::MouseWheel { delta, .. } => state.scroll(delta),
+
+            WindowEvent::RedrawRequested => state.redraw(),
+
+            WindowEvent::CloseRequested => event_loop.exit(),
+
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,639 @@
+//! Depth-tested labels attached to objects in a 3D scene.
+//!
+//! A ring of cubes spins in front of the camera, with a label above each of them. The labels are
+//! positioned at the projected world coordinates of their anchors and given the projected depth
+//! of that anchor, so they are hidden by cubes that are closer to the camera.
+//!
+//! - The depth of every label is passed through glyph metadata: each label buffer uses its index
+//!   as the metadata of its text, which `prepare_with_depth` maps to the depth of that label.
+//! - The text renderer is created with the depth format of the render pass. It does not set a
+//!   depth stencil state, so the one set before `render` applies to text.
+//! - Labels are drawn after the opaque cubes and sorted back to front, so labels that overlap
+//!   each other blend in the right order without writing depth.
+//!
+//! A translucent pane is drawn after the labels, testing depth without writing it. Press `D` to
+//! make the labels write depth: the whole quad of each glyph then writes depth, including its
+//! transparent pixels, and the pane gets rectangular holes where it passes behind labels.
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache, TextArea,
+    TextAtlas, TextRenderer, Viewport,
+};
+use objc2::{
+    rc::{autoreleasepool, Retained},
+    runtime::ProtocolObject,
+};
+use objc2_app_kit::NSView;
+use objc2_core_foundation::CGSize;
+use objc2_foundation::{ns_string, NSString};
+use objc2_metal::{
+    MTLBlendFactor, MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue,
+    MTLCompareFunction, MTLCreateSystemDefaultDevice, MTLDepthStencilDescriptor,
+    MTLDepthStencilState, MTLDevice, MTLLibrary, MTLLoadAction, MTLPixelFormat, MTLPrimitiveType,
+    MTLRenderCommandEncoder as _, MTLRenderPassDescriptor, MTLRenderPipelineDescriptor,
+    MTLRenderPipelineState, MTLStorageMode, MTLStoreAction, MTLTexture, MTLTextureDescriptor,
+    MTLTextureUsage,
+};
+use objc2_quartz_core::{CAMetalDrawable, CAMetalLayer};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::{mem::size_of, ptr::NonNull, sync::Arc, time::Instant};
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, WindowEvent},
+    event_loop::EventLoop,
+    keyboard::Key,
+    window::Window,
+};
+
+const COLOR_FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+const DEPTH_FORMAT: MTLPixelFormat = MTLPixelFormat::Depth32Float;
+
+/// The names and colors of the cubes.
+const CUBES: [(&str, [f32; 3]); 6] = [
+    ("Red", [0.85, 0.25, 0.2]),
+    ("Orange", [0.9, 0.55, 0.15]),
+    ("Yellow", [0.85, 0.8, 0.2]),
+    ("Green", [0.3, 0.7, 0.3]),
+    ("Blue", [0.25, 0.45, 0.85]),
+    ("Purple", [0.6, 0.3, 0.75]),
+];
+
+const SCENE_SHADER: &str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct Instance {
+    float4x4 mvp;
+    float4 color;
+};
+
+struct SceneOut {
+    float4 position [[position]];
+    float4 color;
+};
+
+// Corners are numbered by their x, y and z bits
+constant uchar CUBE_INDICES[36] = {
+    0, 2, 1, 1, 2, 3,
+    4, 5, 6, 6, 5, 7,
+    0, 1, 4, 4, 1, 5,
+    2, 6, 3, 3, 6, 7,
+    0, 4, 2, 2, 4, 6,
+    1, 3, 5, 5, 3, 7,
+};
+
+vertex SceneOut cube_vertex(uint vertex_id [[vertex_id]],
+                            constant Instance &instance [[buffer(0)]]) {
+    uint corner = CUBE_INDICES[vertex_id];
+    float3 position = float3(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1) - 0.5;
+    float shade = 1.0 - 0.1 * float(vertex_id / 6);
+
+    SceneOut out;
+    out.position = instance.mvp * float4(position, 1.0);
+    out.color = float4(instance.color.rgb * shade, instance.color.a);
+    return out;
+}
+
+vertex SceneOut pane_vertex(uint vertex_id [[vertex_id]],
+                            constant Instance &instance [[buffer(0)]]) {
+    float2 corner = float2(vertex_id & 1, vertex_id >> 1) - 0.5;
+
+    SceneOut out;
+    out.position = instance.mvp * float4(corner, 0.0, 1.0);
+    out.color = instance.color;
+    return out;
+}
+
+fragment float4 scene_fragment(SceneOut in [[stage_in]]) {
+    return in.color;
+}
+"#;
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop
+        .run_app(&mut Application { window_state: None })
+        .unwrap();
+}
+
+/// A column-major 4x4 matrix, matching the layout of `float4x4`.
+type Mat4 = [[f32; 4]; 4];
+
+/// The uniforms of a cube or the pane, matching `Instance` in the shader.
+#[repr(C)]
+struct Instance {
+    mvp: Mat4,
+    color: [f32; 4],
+}
+
+fn mul(a: Mat4, b: Mat4) -> Mat4 {
+    let mut result = [[0.0; 4]; 4];
+    for (column, result_column) in result.iter_mut().enumerate() {
+        for (row, value) in result_column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+    result
+}
+
+fn transform(m: Mat4, v: [f32; 4]) -> [f32; 4] {
+    let mut result = [0.0; 4];
+    for (row, value) in result.iter_mut().enumerate() {
+        *value = (0..4).map(|k| m[k][row] * v[k]).sum();
+    }
+    result
+}
+
+/// A right-handed perspective projection mapping depth to Metal's `0..1` range.
+fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Mat4 {
+    let f = 1.0 / (fov_y / 2.0).tan();
+    [
+        [f / aspect, 0.0, 0.0, 0.0],
+        [0.0, f, 0.0, 0.0],
+        [0.0, 0.0, far / (near - far), -1.0],
+        [0.0, 0.0, near * far / (near - far), 0.0],
+    ]
+}
+
+fn translation(x: f32, y: f32, z: f32) -> Mat4 {
+    [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [x, y, z, 1.0],
+    ]
+}
+
+fn scale(x: f32, y: f32, z: f32) -> Mat4 {
+    [
+        [x, 0.0, 0.0, 0.0],
+        [0.0, y, 0.0, 0.0],
+        [0.0, 0.0, z, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+fn rotation_x(angle: f32) -> Mat4 {
+    let (sin, cos) = angle.sin_cos();
+    [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, cos, sin, 0.0],
+        [0.0, -sin, cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+fn rotation_y(angle: f32) -> Mat4 {
+    let (sin, cos) = angle.sin_cos();
+    [
+        [cos, 0.0, -sin, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [sin, 0.0, cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+fn scene_pipeline(
+    device: &ProtocolObject<dyn MTLDevice>,
+    library: &ProtocolObject<dyn MTLLibrary>,
+    vertex_function: &NSString,
+    blending: bool,
+) -> Retained<ProtocolObject<dyn MTLRenderPipelineState>> {
+    let descriptor = MTLRenderPipelineDescriptor::new();
+    descriptor.setVertexFunction(library.newFunctionWithName(vertex_function).as_deref());
+    descriptor.setFragmentFunction(
+        library
+            .newFunctionWithName(ns_string!("scene_fragment"))
+            .as_deref(),
+    );
+    descriptor.setDepthAttachmentPixelFormat(DEPTH_FORMAT);
+
+    let attachment = unsafe { descriptor.colorAttachments().objectAtIndexedSubscript(0) };
+    attachment.setPixelFormat(COLOR_FORMAT);
+    if blending {
+        attachment.setBlendingEnabled(true);
+        attachment.setSourceRGBBlendFactor(MTLBlendFactor::SourceAlpha);
+        attachment.setSourceAlphaBlendFactor(MTLBlendFactor::SourceAlpha);
+        attachment.setDestinationRGBBlendFactor(MTLBlendFactor::OneMinusSourceAlpha);
+        attachment.setDestinationAlphaBlendFactor(MTLBlendFactor::OneMinusSourceAlpha);
+    }
+
+    device
+        .newRenderPipelineStateWithDescriptor_error(&descriptor)
+        .expect("Create scene pipeline")
+}
+
+fn depth_state(
+    device: &ProtocolObject<dyn MTLDevice>,
+    write: bool,
+) -> Retained<ProtocolObject<dyn MTLDepthStencilState>> {
+    let descriptor = MTLDepthStencilDescriptor::new();
+    descriptor.setDepthCompareFunction(MTLCompareFunction::Less);
+    descriptor.setDepthWriteEnabled(write);
+
+    device
+        .newDepthStencilStateWithDescriptor(&descriptor)
+        .expect("Create depth stencil state")
+}
+
+struct WindowState {
+    device: Retained<ProtocolObject<dyn MTLDevice>>,
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+
+    surface: Retained<CAMetalLayer>,
+    depth_texture: Retained<ProtocolObject<dyn MTLTexture>>,
+
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    text_renderer: TextRenderer,
+    /// One buffer per cube, whose text has the index of the cube plus one as metadata.
+    labels: Vec<Buffer>,
+    /// The status line, whose text has a metadata of zero.
+    status: Buffer,
+
+    cube_pipeline: Retained<ProtocolObject<dyn MTLRenderPipelineState>>,
+    pane_pipeline: Retained<ProtocolObject<dyn MTLRenderPipelineState>>,
+    depth_write: Retained<ProtocolObject<dyn MTLDepthStencilState>>,
+    depth_test_only: Retained<ProtocolObject<dyn MTLDepthStencilState>>,
+    /// Whether labels write depth, which punches holes in the pane.
+    labels_write_depth: bool,
+    start: Instant,
+
+    // Make sure that the winit window is last in the struct so that
+    // it is dropped after the Metal layer is dropped.
+    window: Arc<Window>,
+}
+
+impl WindowState {
+    fn new(window: Arc<Window>) -> Self {
+        let view = match window.window_handle().expect("Window handle").as_raw() {
+            RawWindowHandle::AppKit(appkit_handle) => unsafe {
+                Retained::retain(appkit_handle.ns_view.as_ptr() as *mut NSView).unwrap()
+            },
+            _ => panic!("Unsupported platform"),
+        };
+
+        let device = MTLCreateSystemDefaultDevice().expect("Create MTL device");
+
+        let queue = device.newCommandQueue().expect("Create command queue");
+
+        let surface = CAMetalLayer::new();
+        surface.setDevice(Some(&device));
+        surface.setPixelFormat(COLOR_FORMAT);
+        surface.setPresentsWithTransaction(false);
+
+        view.setWantsLayer(true);
+        view.setLayer(Some(&surface));
+
+        // Set up text renderer for render passes with a depth attachment
+        let mut font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        let cache = Cache::new(&device);
+        let viewport = Viewport::new(&device);
+        let mut atlas = TextAtlas::new(&device, &cache, COLOR_FORMAT);
+        let text_renderer = TextRenderer::new(&mut atlas, DEPTH_FORMAT, 1);
+
+        let labels = CUBES
+            .iter()
+            .enumerate()
+            .map(|(index, (name, _))| {
+                let mut buffer = Buffer::new(&mut font_system, Metrics::new(20.0, 26.0));
+                buffer.set_text(
+                    &mut font_system,
+                    name,
+                    &Attrs::new().family(Family::SansSerif).metadata(index + 1),
+                    Shaping::Advanced,
+                );
+                buffer.shape_until_scroll(&mut font_system, false);
+                buffer
+            })
+            .collect();
+
+        let status = Buffer::new(&mut font_system, Metrics::new(16.0, 22.0));
+
+        // Set up the scene
+        let library = device
+            .newLibraryWithSource_options_error(&NSString::from_str(SCENE_SHADER), None)
+            .expect("Create scene shader library");
+
+        let mut state = Self {
+            cube_pipeline: scene_pipeline(&device, &library, ns_string!("cube_vertex"), false),
+            pane_pipeline: scene_pipeline(&device, &library, ns_string!("pane_vertex"), true),
+            depth_write: depth_state(&device, true),
+            depth_test_only: depth_state(&device, false),
+            depth_texture: create_depth_texture(&device, 1, 1),
+
+            device,
+            queue,
+
+            surface,
+
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            text_renderer,
+            labels,
+            status,
+
+            labels_write_depth: false,
+            start: Instant::now(),
+
+            window,
+        };
+        state.resize();
+        state.update_status();
+
+        state
+    }
+
+    fn resize(&mut self) {
+        let size = self.window.inner_size();
+        self.surface.setDrawableSize(CGSize {
+            width: size.width as f64,
+            height: size.height as f64,
+        });
+        self.depth_texture = create_depth_texture(&self.device, size.width, size.height);
+    }
+
+    fn update_status(&mut self) {
+        let text = if self.labels_write_depth {
+            "D: labels write depth (the pane has holes behind them)"
+        } else {
+            "D: labels test depth without writing it"
+        };
+
+        self.status.set_text(
+            &mut self.font_system,
+            text,
+            &Attrs::new().family(Family::SansSerif),
+            Shaping::Advanced,
+        );
+        self.status.shape_until_scroll(&mut self.font_system, false);
+    }
+
+    fn redraw(&mut self) {
+        autoreleasepool(|_| {
+            let Some(drawable) = self.surface.nextDrawable() else {
+                return;
+            };
+
+            let resolution = Resolution {
+                width: self.surface.drawableSize().width as u32,
+                height: self.surface.drawableSize().height as u32,
+            };
+
+            let scale_factor = self.window.scale_factor() as f32;
+            self.viewport.update_with_scale(resolution, scale_factor);
+            let logical_width = resolution.width as f32 / scale_factor;
+            let logical_height = resolution.height as f32 / scale_factor;
+
+            // The camera looks down at the ring of cubes, which spins around the vertical axis
+            let angle = self.start.elapsed().as_secs_f32() * 0.4;
+            let projection = perspective(
+                45f32.to_radians(),
+                logical_width / logical_height.max(1.0),
+                0.1,
+                100.0,
+            );
+            let view_projection = mul(
+                projection,
+                mul(
+                    translation(0.0, -0.5, -9.0),
+                    mul(rotation_x(0.35), rotation_y(angle)),
+                ),
+            );
+
+            let cubes: Vec<([f32; 3], Instance)> = CUBES
+                .iter()
+                .enumerate()
+                .map(|(index, (_, color))| {
+                    let theta = index as f32 / CUBES.len() as f32 * std::f32::consts::TAU;
+                    let position = [3.0 * theta.cos(), 0.0, 3.0 * theta.sin()];
+                    let model = translation(position[0], position[1], position[2]);
+                    let instance = Instance {
+                        mvp: mul(view_projection, model),
+                        color: [color[0], color[1], color[2], 1.0],
+                    };
+
+                    (position, instance)
+                })
+                .collect();
+
+            // Project the anchor above each cube. The depth of a label is the depth of its
+            // anchor, the same value the cubes write to the depth buffer.
+            let mut label_depths = vec![0.0; CUBES.len() + 1];
+            let mut labels = Vec::with_capacity(CUBES.len());
+            for (index, ((position, _), buffer)) in cubes.iter().zip(&self.labels).enumerate() {
+                let clip = transform(
+                    view_projection,
+                    [position[0], position[1] + 0.9, position[2], 1.0],
+                );
+                if clip[3] <= 0.0 {
+                    continue;
+                }
+
+                let [x, y, depth] = [clip[0] / clip[3], clip[1] / clip[3], clip[2] / clip[3]];
+                label_depths[index + 1] = depth;
+
+                let width = buffer.layout_runs().map(|run| run.line_w).sum::<f32>();
+                labels.push((
+                    depth,
+                    TextArea {
+                        left: (x * 0.5 + 0.5) * logical_width - width / 2.0,
+                        top: (0.5 - y * 0.5) * logical_height - buffer.metrics().line_height,
+                        ..TextArea::new(buffer)
+                    },
+                ));
+            }
+
+            // Back to front, so that overlapping labels blend in order
+            labels.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+            let mut text_areas: Vec<TextArea> =
+                labels.into_iter().map(|(_, text_area)| text_area).collect();
+
+            // The status line has a depth of zero, in front of everything
+            text_areas.push(TextArea {
+                left: 10.0,
+                top: 10.0,
+                ..TextArea::new(&self.status)
+            });
+
+            self.text_renderer
+                .prepare_with_depth(
+                    &mut self.font_system,
+                    &mut self.atlas,
+                    &self.viewport,
+                    text_areas,
+                    &mut self.swash_cache,
+                    |metadata| label_depths[metadata],
+                )
+                .unwrap();
+
+            let render_pass_descriptor = MTLRenderPassDescriptor::new();
+            let color_attachment = unsafe {
+                render_pass_descriptor
+                    .colorAttachments()
+                    .objectAtIndexedSubscript(0)
+            };
+
+            color_attachment.setTexture(Some(&drawable.texture()));
+            color_attachment.setLoadAction(MTLLoadAction::Clear);
+            color_attachment.setClearColor(MTLClearColor {
+                red: 0.1,
+                green: 0.1,
+                blue: 0.12,
+                alpha: 1.0,
+            });
+            color_attachment.setStoreAction(MTLStoreAction::Store);
+
+            let depth_attachment = render_pass_descriptor.depthAttachment();
+            depth_attachment.setTexture(Some(&self.depth_texture));
+            depth_attachment.setLoadAction(MTLLoadAction::Clear);
+            depth_attachment.setClearDepth(1.0);
+            depth_attachment.setStoreAction(MTLStoreAction::DontCare);
+
+            let Some(buffer) = self.queue.commandBuffer() else {
+                return;
+            };
+
+            let Some(render_encoder) =
+                buffer.renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            else {
+                return;
+            };
+
+            // Opaque cubes first, writing depth
+            render_encoder.setRenderPipelineState(&self.cube_pipeline);
+            render_encoder.setDepthStencilState(Some(&self.depth_write));
+            for (_, instance) in &cubes {
+                unsafe {
+                    render_encoder.setVertexBytes_length_atIndex(
+                        NonNull::from(instance).cast(),
+                        size_of::<Instance>(),
+                        0,
+                    );
+                    render_encoder.drawPrimitives_vertexStart_vertexCount(
+                        MTLPrimitiveType::Triangle,
+                        0,
+                        36,
+                    );
+                }
+            }
+
+            // Then the labels, with the depth stencil state set before rendering them
+            render_encoder.setDepthStencilState(Some(if self.labels_write_depth {
+                &self.depth_write
+            } else {
+                &self.depth_test_only
+            }));
+            self.text_renderer
+                .render(&self.atlas, &self.viewport, &render_encoder);
+
+            // Then the translucent pane, which cuts through the ring of cubes
+            let pane = Instance {
+                mvp: mul(
+                    view_projection,
+                    mul(translation(0.0, 0.6, 0.0), scale(7.0, 2.4, 1.0)),
+                ),
+                color: [0.6, 0.85, 1.0, 0.35],
+            };
+            render_encoder.setRenderPipelineState(&self.pane_pipeline);
+            render_encoder.setDepthStencilState(Some(&self.depth_test_only));
+            unsafe {
+                render_encoder.setVertexBytes_length_atIndex(
+                    NonNull::from(&pane).cast(),
+                    size_of::<Instance>(),
+                    0,
+                );
+                render_encoder.drawPrimitives_vertexStart_vertexCount(
+                    MTLPrimitiveType::TriangleStrip,
+                    0,
+                    4,
+                );
+            }
+
+            render_encoder.endEncoding();
+
+            buffer.presentDrawable(drawable.as_ref());
+            buffer.commit();
+            self.atlas.trim();
+        });
+
+        // Keep spinning
+        self.window.request_redraw();
+    }
+}
+
+fn create_depth_texture(
+    device: &ProtocolObject<dyn MTLDevice>,
+    width: u32,
+    height: u32,
+) -> Retained<ProtocolObject<dyn MTLTexture>> {
+    let descriptor = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            DEPTH_FORMAT,
+            width.max(1) as usize,
+            height.max(1) as usize,
+            false,
+        )
+    };
+    descriptor.setUsage(MTLTextureUsage::RenderTarget);
+    descriptor.setStorageMode(MTLStorageMode::Private);
+
+    device
+        .newTextureWithDescriptor(&descriptor)
+        .expect("Create depth texture")
+}
+
+struct Application {
+    window_state: Option<WindowState>,
+}
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        // Set up window
+        let (width, height) = (900, 600);
+        let window_attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_title("metalglyph world labels");
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+
+        self.window_state = Some(WindowState::new(window));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(state) = &mut self.window_state else {
+            return;
+        };
+
+        match event {
+            WindowEvent::Resized(_) => state.resize(),
+
+            WindowEvent::KeyboardInput { event, .. }
+                if event.state == ElementState::Pressed
+                    && event.logical_key.as_ref() == Key::Character("d") =>
+            {
+                state.labels_write_depth = !state.labels_write_depth;
+                state.update_status();
+            }
+
+            WindowEvent::RedrawRequested => state.redraw(),
+
+            WindowEvent::CloseRequested => event_loop.exit(),
+
+            _ => {}
+        }
+    }
+}
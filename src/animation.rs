@@ -0,0 +1,77 @@
+use crate::Color;
+
+/// A glyph about to be emitted by [`crate::TextRenderer::prepare_with_animation`], passed to its
+/// callback.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphAnimContext {
+    /// The index of the text area of the glyph among the text areas being prepared.
+    pub area: usize,
+    /// The [`crate::TextArea::id`] of the text area of the glyph, or its index if it has none.
+    pub area_id: u64,
+    /// The index of the line of the glyph in its buffer, see `LayoutRun::line_i`.
+    pub line: usize,
+    /// The index of the glyph in its layout run.
+    pub index: usize,
+    /// The byte offset of the start of the cluster of the glyph in the text of its line.
+    pub cluster: usize,
+    /// The physical position of the glyph origin on its baseline, before any transform.
+    pub position: (f32, f32),
+}
+
+/// How a glyph quad is moved, scaled, rotated and colored, e.g. by the callback of
+/// [`crate::TextRenderer::prepare_with_animation`].
+///
+/// Transforms only change the quad of the glyph: the glyph is rasterized and cached as usual, and
+/// a scaled or rotated quad stretches and turns the rasterized glyph instead of rasterizing it
+/// again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphTransform {
+    /// The physical offset the quad is moved by, which may be a fraction of a pixel.
+    pub offset: (f32, f32),
+    /// The factor the quad is scaled by around the pivot. Glyphs scaled to zero are culled.
+    pub scale: f32,
+    /// The angle the quad is rotated by around the pivot, in radians, clockwise on screen.
+    ///
+    /// Rotated quads are culled against the bounds of their area but not clipped to them.
+    pub rotation: f32,
+    /// The physical point the quad is scaled and rotated around, relative to the glyph origin on
+    /// its baseline, or `None` for the center of the quad.
+    pub pivot: Option<(f32, f32)>,
+    /// The color of the glyph instead of its own, or `None` to keep it.
+    pub color: Option<Color>,
+    /// The alpha of the glyph instead of its own, applied after `color`, or `None` to keep it.
+    pub alpha: Option<u8>,
+}
+
+impl GlyphTransform {
+    /// The transform leaving the glyph as it is.
+    pub const IDENTITY: Self = Self {
+        offset: (0.0, 0.0),
+        scale: 1.0,
+        rotation: 0.0,
+        pivot: None,
+        color: None,
+        alpha: None,
+    };
+
+    /// Returns whether the transform moves, scales or rotates the quad.
+    pub(crate) fn moves_quad(&self) -> bool {
+        self.offset != (0.0, 0.0) || self.scale != 1.0 || self.rotation != 0.0
+    }
+
+    /// Returns `color` with the overrides of the transform applied.
+    pub(crate) fn apply_color(&self, color: Color) -> Color {
+        let color = self.color.unwrap_or(color);
+
+        match self.alpha {
+            Some(alpha) => Color::rgba(color.r(), color.g(), color.b(), alpha),
+            None => color,
+        }
+    }
+}
+
+impl Default for GlyphTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{CustomGlyph, TextArea};
+use std::{iter::FusedIterator, mem::ManuallyDrop, ops::Range, slice};
+
+/// Text areas collected for `prepare` in storage that is reused across frames, instead of a
+/// `Vec` of text areas and a slice of custom glyphs per area allocated every frame.
+///
+/// Pass `&batch` wherever `prepare` takes text areas. The custom glyphs of every area are copied
+/// into a single `Vec` owned by the batch.
+///
+/// The batch borrows the buffers of its text areas. To keep it across frames whose buffers are
+/// borrowed anew, store it as a `TextAreaBatch<'static>` and hand its storage to the borrows of
+/// each frame with [`TextAreaBatch::recycle`]:
+///
+/// ```ignore
+/// let mut batch = mem::take(&mut self.batch).recycle();
+/// for label in &self.labels {
+///     batch.push_area(label.text_area()).with_custom_glyphs(&label.icons);
+/// }
+/// text_renderer.prepare(font_system, atlas, viewport, &batch, swash_cache)?;
+/// self.batch = batch.recycle();
+/// ```
+#[derive(Clone, Default)]
+pub struct TextAreaBatch<'a> {
+    /// The text areas without their custom glyphs, along with the range of `custom_glyphs`
+    /// holding them.
+    areas: Vec<(TextArea<'a>, Range<usize>)>,
+    custom_glyphs: Vec<CustomGlyph>,
+}
+
+impl<'a> TextAreaBatch<'a> {
+    /// Creates an empty batch, which allocates once areas are pushed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes every text area, keeping the storage for the next ones.
+    pub fn clear(&mut self) {
+        self.areas.clear();
+        self.custom_glyphs.clear();
+    }
+
+    /// Adds `area`, copying its custom glyphs into the batch, and returns it to add more custom
+    /// glyphs with [`BatchedTextArea::with_custom_glyphs`].
+    pub fn push_area(&mut self, area: TextArea<'a>) -> BatchedTextArea<'_, 'a> {
+        let start = self.custom_glyphs.len();
+        self.custom_glyphs.extend_from_slice(area.custom_glyphs);
+
+        self.areas.push((
+            TextArea {
+                custom_glyphs: &[],
+                ..area
+            },
+            start..self.custom_glyphs.len(),
+        ));
+
+        BatchedTextArea { batch: self }
+    }
+
+    /// Returns the number of text areas in the batch.
+    pub fn len(&self) -> usize {
+        self.areas.len()
+    }
+
+    /// Returns whether the batch holds no text areas.
+    pub fn is_empty(&self) -> bool {
+        self.areas.is_empty()
+    }
+
+    /// Returns an iterator over the text areas of the batch, along with their custom glyphs.
+    pub fn iter(&self) -> TextAreaBatchIter<'_, 'a> {
+        TextAreaBatchIter {
+            areas: self.areas.iter(),
+            custom_glyphs: &self.custom_glyphs,
+        }
+    }
+
+    /// Removes every text area and returns the storage of the batch as a batch of text areas
+    /// borrowing buffers for another lifetime, without reallocating.
+    pub fn recycle<'b>(mut self) -> TextAreaBatch<'b> {
+        self.areas.clear();
+        self.custom_glyphs.clear();
+
+        let mut areas = ManuallyDrop::new(self.areas);
+        let (ptr, capacity) = (areas.as_mut_ptr(), areas.capacity());
+
+        // SAFETY: The vector is empty, and text areas only differ in their lifetime, which does
+        // not change their layout, so the allocation can hold text areas of any lifetime.
+        let areas =
+            unsafe { Vec::from_raw_parts(ptr.cast::<(TextArea<'b>, Range<usize>)>(), 0, capacity) };
+
+        TextAreaBatch {
+            areas,
+            custom_glyphs: self.custom_glyphs,
+        }
+    }
+}
+
+impl<'b, 'a: 'b> IntoIterator for &'b TextAreaBatch<'a> {
+    type Item = TextArea<'b>;
+    type IntoIter = TextAreaBatchIter<'b, 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A text area just added to a [`TextAreaBatch`], see [`TextAreaBatch::push_area`].
+pub struct BatchedTextArea<'b, 'a> {
+    batch: &'b mut TextAreaBatch<'a>,
+}
+
+impl BatchedTextArea<'_, '_> {
+    /// Adds `custom_glyphs` to the custom glyphs of the area, copying them into the batch.
+    pub fn with_custom_glyphs(self, custom_glyphs: &[CustomGlyph]) -> Self {
+        let batch = &mut *self.batch;
+        batch.custom_glyphs.extend_from_slice(custom_glyphs);
+
+        // The custom glyphs of the last area are the last ones of the batch
+        if let Some((_, range)) = batch.areas.last_mut() {
+            range.end = batch.custom_glyphs.len();
+        }
+
+        self
+    }
+}
+
+/// An iterator over the text areas of a [`TextAreaBatch`], see [`TextAreaBatch::iter`].
+#[derive(Clone)]
+pub struct TextAreaBatchIter<'b, 'a> {
+    areas: slice::Iter<'b, (TextArea<'a>, Range<usize>)>,
+    custom_glyphs: &'b [CustomGlyph],
+}
+
+impl<'b, 'a: 'b> Iterator for TextAreaBatchIter<'b, 'a> {
+    type Item = TextArea<'b>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (area, range) = self.areas.next()?;
+
+        Some(TextArea {
+            custom_glyphs: &self.custom_glyphs[range.clone()],
+            ..area.clone()
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.areas.size_hint()
+    }
+}
+
+impl<'b, 'a: 'b> ExactSizeIterator for TextAreaBatchIter<'b, 'a> {}
+
+impl<'b, 'a: 'b> FusedIterator for TextAreaBatchIter<'b, 'a> {}
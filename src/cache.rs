@@ -1,105 +1,549 @@
-use objc2::{rc::Retained, runtime::ProtocolObject};
-use objc2_foundation::ns_string;
+#[cfg(feature = "dev-tools")]
+use crate::CacheError;
+use crate::{resource_label, ContentType, StereoPath, DEFAULT_LABEL};
+use objc2::{rc::Retained, runtime::ProtocolObject, Message as _};
+use objc2_foundation::{ns_string, NSError, NSString};
 use objc2_metal::{
-    MTLBlendFactor, MTLDevice, MTLLibrary, MTLPixelFormat, MTLRenderPipelineDescriptor,
-    MTLRenderPipelineState,
+    MTLBlendFactor, MTLDataType, MTLDevice, MTLFunction, MTLFunctionConstantValues, MTLLibrary,
+    MTLPixelFormat, MTLPrimitiveTopologyClass, MTLRenderPipelineDescriptor, MTLRenderPipelineState,
 };
+#[cfg(feature = "dev-tools")]
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::{
     ops::Deref,
+    ptr::NonNull,
     sync::{Arc, Mutex},
 };
 
+/// The Metal source of the built-in shaders.
+const SHADER_SOURCE: &str = include_str!("./shader.metal");
+
+/// The functions of the built-in shaders, which a reloaded library must define.
+#[cfg(feature = "dev-tools")]
+const SHADER_FUNCTIONS: [&str; 11] = [
+    "vertex_main",
+    "vertex_layered",
+    "vertex_stereo",
+    "vertex_stereo_views",
+    "fragment_main",
+    "fragment_premultiplied",
+    "fragment_coverage",
+    "fragment_luminance",
+    "fragment_mask_only",
+    "vertex_resolve",
+    "fragment_resolve",
+];
+
 /// A cache to share common resources (e.g., pipelines, shaders) between multiple text
 /// renderers.
+///
+/// A `Cache` is `Send` and `Sync`: clones can be used from any number of threads at once.
 #[derive(Debug, Clone)]
 pub struct Cache(Arc<Inner>);
 
 #[derive(Debug)]
 struct Inner {
+    /// The library of the built-in shaders, only replaced while the pipeline cache is locked.
+    library: Mutex<Retained<ProtocolObject<dyn MTLLibrary>>>,
     pipeline_descriptor: Retained<MTLRenderPipelineDescriptor>,
-    cache: Mutex<
-        Vec<(
-            MTLPixelFormat,
-            MTLPixelFormat,
-            usize,
-            Retained<ProtocolObject<dyn MTLRenderPipelineState>>,
-        )>,
-    >,
+    /// Pipelines are cached per `Cache`, so a cached pipeline always uses the custom fragment
+    /// function of its cache.
+    custom_fragment: Option<CustomFragment>,
+    cache: Mutex<Vec<(PipelineKey, Retained<ProtocolObject<dyn MTLRenderPipelineState>>)>>,
+    /// The number of times the shaders were reloaded, see [`Cache::shader_generation`].
+    #[cfg(feature = "dev-tools")]
+    shader_generation: AtomicU64,
+}
+
+/// A fragment function registered with [`Cache::with_custom_fragment`].
+#[derive(Debug)]
+struct CustomFragment {
+    library: Retained<ProtocolObject<dyn MTLLibrary>>,
+    name: Retained<NSString>,
+}
+
+// SAFETY: Metal libraries and pipeline states are immutable and thread-safe. The pipeline
+// descriptor is only mutated while the pipeline cache is locked.
+unsafe impl Send for Inner {}
+unsafe impl Sync for Inner {}
+
+/// What is written to a single-channel render target (e.g. `R8Unorm`).
+///
+/// Color glyphs are reduced to their alpha channel in [`SingleChannelOutput::Coverage`] mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SingleChannelOutput {
+    /// The coverage of the glyph, multiplied by the alpha of its color.
+    #[default]
+    Coverage,
+    /// The coverage of the glyph, multiplied by the luminance and alpha of its color.
+    Luminance,
+}
+
+/// How the alpha channel of rendered text is encoded in the render target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AlphaMode {
+    /// Colors are blended with straight (non-premultiplied) alpha.
+    ///
+    /// This is correct when rendering directly onto an opaque target.
+    #[default]
+    Straight,
+    /// The shader emits premultiplied colors that are blended with `One` /
+    /// `OneMinusSourceAlpha`.
+    ///
+    /// Use this when rendering into a transparent intermediate texture that is later composited
+    /// with the standard premultiplied "over" operator, otherwise the composite shows dark halos
+    /// around glyph edges.
+    Premultiplied,
+}
+
+/// How rendered text is combined with the contents of the render target, see
+/// [`crate::TextRendererBuilder::blend_mode`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum BlendMode {
+    /// Text is drawn over the target, blended with the [`AlphaMode`] of the renderer.
+    #[default]
+    Over,
+    /// Text erases the target where it covers it, blending color and alpha with `Zero` /
+    /// `OneMinusSourceAlpha` ("destination out"), e.g. to punch glyph-shaped holes that a later
+    /// pass fills with a blurred background.
+    ///
+    /// Mask and color glyphs both erase by their coverage times the alpha of their color, so the
+    /// [`AlphaMode`] makes no difference.
+    DestinationOut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct PipelineKey {
+    pub pixel_format: MTLPixelFormat,
+    pub depth_format: MTLPixelFormat,
+    pub sample_count: usize,
+    pub single_channel_output: SingleChannelOutput,
+    pub alpha_mode: AlphaMode,
+    pub blend_mode: BlendMode,
+    /// The content type the pipeline is specialized for, or `None` for a pipeline that draws
+    /// both types and needs both atlases bound.
+    pub content_type: Option<ContentType>,
+    /// Whether the pipeline writes the coverage of glyphs only, see
+    /// [`crate::TextRenderer::render_mask_only`].
+    pub mask_only: bool,
+    /// The vertex function of the pipeline.
+    pub vertex_stage: VertexStage,
+}
+
+/// The options of a [`crate::TextRenderer`] its pipelines are created for, besides those of its
+/// atlas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RendererOptions {
+    pub depth_format: MTLPixelFormat,
+    pub sample_count: usize,
+    pub alpha_mode: AlphaMode,
+    pub blend_mode: BlendMode,
+}
+
+/// The vertex function of a pipeline, which also selects the layers of layered render targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum VertexStage {
+    /// Draws into the render target, or its first layer.
+    Single,
+    /// Draws into the layer selected per draw, see [`crate::TextRenderer::render_to_layer`].
+    Layered,
+    /// Draws into both views of a layered target, see [`crate::TextRenderer::render_stereo`].
+    Stereo(StereoPath),
 }
 
 impl Cache {
     /// Creates a new `Cache` with the given `device`.
-    pub fn new(device: &Retained<ProtocolObject<dyn MTLDevice>>) -> Self {
+    pub fn new(device: &ProtocolObject<dyn MTLDevice>) -> Self {
+        Self::with_label(device, DEFAULT_LABEL)
+    }
+
+    /// Creates a new `Cache` with the given `device`, labeling the shader library and every
+    /// pipeline state it creates with `label` (e.g. `"UI Text"` produces `"UI Text - Pipeline
+    /// State"`).
+    pub fn with_label(device: &ProtocolObject<dyn MTLDevice>, label: &str) -> Self {
+        Self::build(device, label, None)
+    }
+
+    /// Creates a new `Cache` with the given `device`, drawing text with the fragment function
+    /// `name` of `library` instead of the built-in one, e.g. for custom text effects.
+    ///
+    /// The function replaces the built-in fragment function of [`crate::TextRenderer::render`]
+    /// (but not of [`crate::TextRenderer::render_mask_only`]), and must follow its contract:
+    ///
+    /// - It takes the `VertexOutput` of `vertex_main` as `[[stage_in]]`, the viewport `Params`
+    ///   at `[[buffer(0)]]`, the color atlas at `[[texture(0)]]` and the mask atlas at
+    ///   `[[texture(1)]]`.
+    /// - It is specialized with the content type of the glyphs it draws as the `uint` function
+    ///   constant at index `0` (`0` for color, `1` for mask), if it declares one. Otherwise both
+    ///   atlases are bound.
+    /// - It returns the color of the pixel with straight alpha with [`AlphaMode::Straight`], and
+    ///   premultiplied with [`AlphaMode::Premultiplied`] or on single-channel targets, where the
+    ///   red channel holds the output.
+    ///
+    /// The simplest way to follow it is to compile the function along with
+    /// [`Cache::shader_source`], which declares these types and the `sample_glyph` function
+    /// the built-in fragment functions use. Pipelines are created with the
+    /// [`crate::TextRenderer`]s, which fail to build with [`crate::BuildError::PipelineCreation`]
+    /// if the function is missing or does not link with `vertex_main`.
+    pub fn with_custom_fragment(
+        device: &ProtocolObject<dyn MTLDevice>,
+        library: &ProtocolObject<dyn MTLLibrary>,
+        name: &str,
+    ) -> Self {
+        Self::build(
+            device,
+            DEFAULT_LABEL,
+            Some(CustomFragment {
+                library: library.retain(),
+                name: NSString::from_str(name),
+            }),
+        )
+    }
+
+    /// Returns the Metal source of the built-in shaders, to compile custom fragment functions
+    /// with (see [`Cache::with_custom_fragment`]).
+    pub fn shader_source() -> &'static str {
+        SHADER_SOURCE
+    }
+
+    fn build(
+        device: &ProtocolObject<dyn MTLDevice>,
+        label: &str,
+        custom_fragment: Option<CustomFragment>,
+    ) -> Self {
         let library = device
-            .newLibraryWithSource_options_error(ns_string!(include_str!("./shader.metal")), None)
+            .newLibraryWithSource_options_error(&NSString::from_str(SHADER_SOURCE), None)
             .expect("Failed to create shader library.");
-        library.setLabel(Some(ns_string!("Metalglyph - Shader Library")));
+        library.setLabel(Some(&resource_label(label, "Shader Library")));
 
         let descriptor = MTLRenderPipelineDescriptor::new();
-        descriptor.setLabel(Some(ns_string!("Metalglyph - Pipeline State")));
-
-        let vertex_function = library.newFunctionWithName(ns_string!("vertex_main"));
-        descriptor.setVertexFunction(vertex_function.as_deref());
-
-        let fragment_function = library.newFunctionWithName(ns_string!("fragment_main"));
-        descriptor.setFragmentFunction(fragment_function.as_deref());
+        descriptor.setLabel(Some(&resource_label(label, "Pipeline State")));
+        // Allows encoding text draws into indirect command buffers
+        descriptor.setSupportIndirectCommandBuffers(true);
 
         let attachment = unsafe { descriptor.colorAttachments().objectAtIndexedSubscript(0) };
 
         attachment.setPixelFormat(MTLPixelFormat::BGRA8Unorm);
         attachment.setBlendingEnabled(true);
-        attachment.setSourceRGBBlendFactor(MTLBlendFactor::SourceAlpha);
-        attachment.setDestinationRGBBlendFactor(MTLBlendFactor::OneMinusSourceAlpha);
-        attachment.setSourceAlphaBlendFactor(MTLBlendFactor::SourceAlpha);
-        attachment.setDestinationAlphaBlendFactor(MTLBlendFactor::OneMinusSourceAlpha);
 
         Self(Arc::new(Inner {
+            library: Mutex::new(library),
             pipeline_descriptor: descriptor,
+            custom_fragment,
             cache: Mutex::new(Vec::new()),
+            #[cfg(feature = "dev-tools")]
+            shader_generation: AtomicU64::new(0),
         }))
     }
 
+    /// Returns the shader library, which also holds the functions of helper passes.
+    pub(crate) fn library(&self) -> Retained<ProtocolObject<dyn MTLLibrary>> {
+        self.0.library.lock().expect("Read shader library").clone()
+    }
+
+    /// Compiles `source` into the library of the built-in shaders, replacing the pipelines
+    /// created from the current library, e.g. to iterate on an edited copy of
+    /// [`Cache::shader_source`] without restarting the application.
+    ///
+    /// `source` must define every function of the built-in shaders, with the same signatures.
+    /// Every pipeline created so far is created again from the new library before anything is
+    /// replaced, so if `source` does not compile, lacks a function or does not link, the error
+    /// is returned and the current shaders stay in use. Otherwise, [`crate::TextRenderer`]s
+    /// using the cache pick up the new pipelines on their next `render`, and indirect command
+    /// buffers once re-encoded after the next `prepare`. Helper passes such as
+    /// [`crate::Supersampler`] keep the shaders they were created with.
+    ///
+    /// `device` must be the device the cache was created with.
+    #[cfg(feature = "dev-tools")]
+    pub fn reload_shader_from_source(
+        &self,
+        device: &ProtocolObject<dyn MTLDevice>,
+        source: &str,
+    ) -> Result<(), CacheError> {
+        let library = device
+            .newLibraryWithSource_options_error(&NSString::from_str(source), None)
+            .map_err(|error| CacheError::Compilation(error.to_string()))?;
+
+        for name in SHADER_FUNCTIONS {
+            if library
+                .newFunctionWithName(&NSString::from_str(name))
+                .is_none()
+            {
+                return Err(CacheError::MissingFunction(name.to_owned()));
+            }
+        }
+
+        let mut cache = self.0.cache.lock().expect("Write pipeline cache");
+        let mut current_library = self.0.library.lock().expect("Write shader library");
+        library.setLabel(current_library.label().as_deref());
+
+        let pipelines = cache
+            .iter()
+            .map(|&(key, _)| {
+                self.create_pipeline(device, &library, key)
+                    .map(|pipeline| (key, pipeline))
+                    .map_err(|error| CacheError::PipelineCreation(error.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(pipelines = pipelines.len(), "reloaded text shaders");
+
+        *current_library = library;
+        *cache = pipelines;
+        self.0.shader_generation.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Returns the number of times the shaders were reloaded with
+    /// [`Cache::reload_shader_from_source`].
+    #[cfg(feature = "dev-tools")]
+    pub fn shader_generation(&self) -> u64 {
+        self.0.shader_generation.load(Ordering::Relaxed)
+    }
+
+    /// Returns the pipeline of `key`, creating it if needed, or the Metal error if the custom
+    /// fragment function does not link.
     pub(crate) fn get_or_create_pipeline(
         &self,
-        device: &Retained<ProtocolObject<dyn MTLDevice>>,
-        pixel_format: MTLPixelFormat,
-        depth_format: MTLPixelFormat,
-        sample_count: usize,
-    ) -> Retained<ProtocolObject<dyn MTLRenderPipelineState>> {
+        device: &ProtocolObject<dyn MTLDevice>,
+        key: PipelineKey,
+    ) -> Result<Retained<ProtocolObject<dyn MTLRenderPipelineState>>, Retained<NSError>> {
+        let mut cache = self.0.cache.lock().expect("Write pipeline cache");
+
+        if let Some((_, pipeline)) = cache.iter().find(|(k, _)| k == &key) {
+            return Ok(pipeline.clone());
+        }
+
+        let library = self.library();
+        let pipeline = match self.create_pipeline(device, &library, key) {
+            Ok(pipeline) => pipeline,
+            Err(error) if self.0.custom_fragment.is_some() && !key.mask_only => return Err(error),
+            Err(error) => panic!("Failed to create pipeline state: {error}"),
+        };
+        cache.push((key, pipeline.clone()));
+
+        Ok(pipeline)
+    }
+
+    /// Creates the pipeline of `key` from the built-in shaders of `library`, or returns the
+    /// Metal error if a function does not link. Must be called with the pipeline cache locked,
+    /// which guards the pipeline descriptor.
+    fn create_pipeline(
+        &self,
+        device: &ProtocolObject<dyn MTLDevice>,
+        library: &ProtocolObject<dyn MTLLibrary>,
+        key: PipelineKey,
+    ) -> Result<Retained<ProtocolObject<dyn MTLRenderPipelineState>>, Retained<NSError>> {
         let Inner {
             pipeline_descriptor,
-            cache,
+            custom_fragment,
             ..
         } = self.0.deref();
 
-        let mut cache = cache.lock().expect("Write pipeline cache");
+        // Combined formats are set as both attachments, like in the render pass
+        let (depth_format, stencil_format) = depth_stencil_formats(key.depth_format);
+        pipeline_descriptor.setDepthAttachmentPixelFormat(depth_format);
+        pipeline_descriptor.setStencilAttachmentPixelFormat(stencil_format);
+        pipeline_descriptor.setRasterSampleCount(key.sample_count);
 
-        cache
-            .iter()
-            .find(|(pixel_fmt, depth_fmt, count, _)| {
-                pixel_fmt == &pixel_format && depth_fmt == &depth_format && count == &sample_count
-            })
-            .map(|(_, _, _, p)| p.clone())
-            .unwrap_or_else(|| {
-                pipeline_descriptor.setDepthAttachmentPixelFormat(depth_format);
-                pipeline_descriptor.setRasterSampleCount(sample_count);
+        let attachment = unsafe {
+            pipeline_descriptor
+                .colorAttachments()
+                .objectAtIndexedSubscript(0)
+        };
 
-                let attachment = unsafe {
-                    pipeline_descriptor
-                        .colorAttachments()
-                        .objectAtIndexedSubscript(0)
-                };
+        attachment.setPixelFormat(key.pixel_format);
 
-                attachment.setPixelFormat(pixel_format);
+        // Layered rendering needs the primitive topology when the pipeline is created
+        let (vertex_name, amplification_count, topology) = match key.vertex_stage {
+            VertexStage::Single => (
+                ns_string!("vertex_main"),
+                1,
+                MTLPrimitiveTopologyClass::Unspecified,
+            ),
+            VertexStage::Layered => (
+                ns_string!("vertex_layered"),
+                1,
+                MTLPrimitiveTopologyClass::Triangle,
+            ),
+            VertexStage::Stereo(StereoPath::VertexAmplification) => (
+                ns_string!("vertex_stereo"),
+                2,
+                MTLPrimitiveTopologyClass::Triangle,
+            ),
+            VertexStage::Stereo(StereoPath::DrawPerView) => (
+                ns_string!("vertex_stereo_views"),
+                1,
+                MTLPrimitiveTopologyClass::Triangle,
+            ),
+        };
+        // The amplification count of 2 is only chosen when the device supports it, see
+        // `StereoPath::for_device`
+        unsafe {
+            pipeline_descriptor.setMaxVertexAmplificationCount(amplification_count);
+            pipeline_descriptor.setInputPrimitiveTopology(topology);
+        }
 
-                let pipeline = device
-                    .newRenderPipelineStateWithDescriptor_error(&pipeline_descriptor)
-                    .expect("Failed to create pipeline state");
+        let vertex_function = try_new_function(library, vertex_name, key.content_type)?;
+        pipeline_descriptor.setVertexFunction(Some(&vertex_function));
 
-                cache.push((pixel_format, depth_format, sample_count, pipeline.clone()));
+        if key.mask_only {
+            // Coverage is accumulated with the "over" operator, like on single-channel
+            // targets
+            let function =
+                try_new_function(library, ns_string!("fragment_mask_only"), key.content_type)?;
+            pipeline_descriptor.setFragmentFunction(Some(&function));
+            attachment.setSourceRGBBlendFactor(MTLBlendFactor::One);
+            attachment.setSourceAlphaBlendFactor(MTLBlendFactor::One);
+        } else if is_single_channel_format(key.pixel_format) {
+            // Single-channel targets have no alpha to blend with, so coverage is
+            // accumulated with the "over" operator on premultiplied values.
+            let name = match key.single_channel_output {
+                SingleChannelOutput::Coverage => ns_string!("fragment_coverage"),
+                SingleChannelOutput::Luminance => ns_string!("fragment_luminance"),
+            };
+            let function = try_new_function(library, name, key.content_type)?;
+            pipeline_descriptor.setFragmentFunction(Some(&function));
+            attachment.setSourceRGBBlendFactor(MTLBlendFactor::One);
+            attachment.setSourceAlphaBlendFactor(MTLBlendFactor::One);
+        } else {
+            match key.alpha_mode {
+                AlphaMode::Straight => {
+                    let function =
+                        try_new_function(library, ns_string!("fragment_main"), key.content_type)?;
+                    pipeline_descriptor.setFragmentFunction(Some(&function));
+                    attachment.setSourceRGBBlendFactor(MTLBlendFactor::SourceAlpha);
+                    attachment.setSourceAlphaBlendFactor(MTLBlendFactor::SourceAlpha);
+                }
+                AlphaMode::Premultiplied => {
+                    let function = try_new_function(
+                        library,
+                        ns_string!("fragment_premultiplied"),
+                        key.content_type,
+                    )?;
+                    pipeline_descriptor.setFragmentFunction(Some(&function));
+                    attachment.setSourceRGBBlendFactor(MTLBlendFactor::One);
+                    attachment.setSourceAlphaBlendFactor(MTLBlendFactor::One);
+                }
+            }
+        }
+        if key.blend_mode == BlendMode::DestinationOut {
+            // Only the alpha of the source is used, to scale the destination down
+            attachment.setSourceRGBBlendFactor(MTLBlendFactor::Zero);
+            attachment.setSourceAlphaBlendFactor(MTLBlendFactor::Zero);
+        }
+        attachment.setDestinationRGBBlendFactor(MTLBlendFactor::OneMinusSourceAlpha);
+        attachment.setDestinationAlphaBlendFactor(MTLBlendFactor::OneMinusSourceAlpha);
 
-                pipeline
-            })
-            .clone()
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            pixel_format = ?key.pixel_format,
+            depth_format = ?key.depth_format,
+            sample_count = key.sample_count,
+            single_channel_output = ?key.single_channel_output,
+            alpha_mode = ?key.alpha_mode,
+            blend_mode = ?key.blend_mode,
+            content_type = ?key.content_type,
+            mask_only = key.mask_only,
+            vertex_stage = ?key.vertex_stage,
+            "creating text pipeline state"
+        );
+
+        // The custom fragment function replaces the built-in one, keeping its blending
+        let custom_fragment = custom_fragment.as_ref().filter(|_| !key.mask_only);
+        if let Some(custom_fragment) = custom_fragment {
+            let function = try_new_function(
+                &custom_fragment.library,
+                &custom_fragment.name,
+                key.content_type,
+            )?;
+            pipeline_descriptor.setFragmentFunction(Some(&function));
+        }
+
+        device.newRenderPipelineStateWithDescriptor_error(pipeline_descriptor)
+    }
+}
+
+/// Creates the shader function `name`, specialized for drawing glyphs of `content_type` only if
+/// it is `Some`, or returns the Metal error if the function is missing.
+fn try_new_function(
+    library: &ProtocolObject<dyn MTLLibrary>,
+    name: &NSString,
+    content_type: Option<ContentType>,
+) -> Result<Retained<ProtocolObject<dyn MTLFunction>>, Retained<NSError>> {
+    let constant_values = MTLFunctionConstantValues::new();
+
+    if let Some(content_type) = content_type {
+        // Matches the `content_type` values read by the shader
+        let value: u32 = match content_type {
+            ContentType::Color => 0,
+            ContentType::Mask => 1,
+        };
+        unsafe {
+            constant_values.setConstantValue_type_atIndex(
+                NonNull::from(&value).cast(),
+                MTLDataType::UInt,
+                0,
+            );
+        }
     }
+
+    library.newFunctionWithName_constantValues_error(name, &constant_values)
+}
+
+/// Returns `true` if render targets of `format` store linear colors: sRGB formats, which encode
+/// the linear colors written to them, and float formats. Other formats store the values written
+/// to them, which are displayed as sRGB.
+pub(crate) fn is_linear_format(format: MTLPixelFormat) -> bool {
+    matches!(
+        format,
+        MTLPixelFormat::R8Unorm_sRGB
+            | MTLPixelFormat::RG8Unorm_sRGB
+            | MTLPixelFormat::RGBA8Unorm_sRGB
+            | MTLPixelFormat::BGRA8Unorm_sRGB
+            | MTLPixelFormat::BGRA10_XR_sRGB
+            | MTLPixelFormat::BGR10_XR_sRGB
+            | MTLPixelFormat::R16Float
+            | MTLPixelFormat::RG16Float
+            | MTLPixelFormat::RGBA16Float
+            | MTLPixelFormat::R32Float
+            | MTLPixelFormat::RG32Float
+            | MTLPixelFormat::RGBA32Float
+            | MTLPixelFormat::RG11B10Float
+            | MTLPixelFormat::RGB9E5Float
+    )
+}
+
+/// Returns the depth and stencil attachment formats of a pipeline for render passes with a
+/// `format` depth and/or stencil attachment, `MTLPixelFormat::Invalid` for the missing aspects.
+fn depth_stencil_formats(format: MTLPixelFormat) -> (MTLPixelFormat, MTLPixelFormat) {
+    match format {
+        MTLPixelFormat::Stencil8 | MTLPixelFormat::X32_Stencil8 | MTLPixelFormat::X24_Stencil8 => {
+            (MTLPixelFormat::Invalid, format)
+        }
+        MTLPixelFormat::Depth32Float_Stencil8 | MTLPixelFormat::Depth24Unorm_Stencil8 => {
+            (format, format)
+        }
+        _ => (format, MTLPixelFormat::Invalid),
+    }
+}
+
+/// Returns `true` if `format` is a color format with a single (red) channel.
+pub(crate) fn is_single_channel_format(format: MTLPixelFormat) -> bool {
+    matches!(
+        format,
+        MTLPixelFormat::R8Unorm
+            | MTLPixelFormat::R8Unorm_sRGB
+            | MTLPixelFormat::R16Unorm
+            | MTLPixelFormat::R16Float
+            | MTLPixelFormat::R32Float
+    )
 }
@@ -0,0 +1,40 @@
+//! Serde support for [`Color`], which is re-exported from `cosmic-text` and therefore cannot
+//! implement the serde traits itself.
+//!
+//! Colors are serialized as their packed `0xAARRGGBB` value. Use this module with
+//! `#[serde(with = "metalglyph::color_serde")]` on `Color` fields, and
+//! [`color_serde::option`](option) on `Option<Color>` fields. The crate's own types use it for
+//! their color fields when the `serde` feature is enabled.
+
+use crate::Color;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Serializes a [`Color`] as its packed `0xAARRGGBB` value.
+pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+    color.0.serialize(serializer)
+}
+
+/// Deserializes a [`Color`] from its packed `0xAARRGGBB` value.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    u32::deserialize(deserializer).map(Color)
+}
+
+/// Serde support for `Option<Color>`.
+pub mod option {
+    use super::*;
+
+    /// Serializes an optional [`Color`] as its packed `0xAARRGGBB` value.
+    pub fn serialize<S: Serializer>(
+        color: &Option<Color>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        color.map(|color| color.0).serialize(serializer)
+    }
+
+    /// Deserializes an optional [`Color`] from its packed `0xAARRGGBB` value.
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Color>, D::Error> {
+        Ok(Option::<u32>::deserialize(deserializer)?.map(Color))
+    }
+}
@@ -5,6 +5,7 @@ pub type CustomGlyphId = u16;
 
 /// A custom glyph to render
 #[derive(Default, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CustomGlyph {
     /// The unique identifier for this glyph
     pub id: CustomGlyphId,
@@ -20,6 +21,7 @@ pub struct CustomGlyph {
     /// type [`ContentType::Mask`])
     ///
     /// Set to `None` to use [`crate::TextArea::default_color`].
+    #[cfg_attr(feature = "serde", serde(with = "crate::color_serde::option"))]
     pub color: Option<Color>,
     /// If `true`, then this glyph will be snapped to the nearest whole physical
     /// pixel and the resulting `SubpixelBin`'s in `RasterizationRequest` will always
@@ -31,6 +33,7 @@ pub struct CustomGlyph {
 
 /// A request to rasterize a custom glyph
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RasterizeCustomGlyphRequest {
     /// The unique identifier of the glyph
     pub id: CustomGlyphId,
@@ -42,11 +45,13 @@ pub struct RasterizeCustomGlyphRequest {
     ///
     /// If `CustomGlyph::snap_to_physical_pixel` was set to `true`, then this
     /// will always be `Zero`.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_defs::SubpixelBinDef"))]
     pub x_bin: SubpixelBin,
     /// Binning of fractional Y offset
     ///
     /// If `CustomGlyph::snap_to_physical_pixel` was set to `true`, then this
     /// will always be `Zero`.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_defs::SubpixelBinDef"))]
     pub y_bin: SubpixelBin,
     /// The scaling factor applied to the text area (Note that `width` and
     /// `height` are already scaled by this factor.)
@@ -55,6 +60,7 @@ pub struct RasterizeCustomGlyphRequest {
 
 /// A rasterized custom glyph
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RasterizedCustomGlyph {
     /// The raw image data
     pub data: Vec<u8>,
@@ -98,7 +104,9 @@ pub struct CustomGlyphCacheKey {
 }
 
 /// The type of image data contained in a rasterized glyph
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum ContentType {
     /// Each pixel contains 32 bits of rgba data
     Color,
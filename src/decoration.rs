@@ -0,0 +1,114 @@
+use crate::{Cursor, GlyphPlacement, LayoutGlyph, TextArea};
+use std::ops::Range;
+
+/// A rectangle covering the glyphs of a byte range of a text area on one line, see
+/// [`range_rects`].
+///
+/// Positions are physical, like the quads `prepare` emits before the origin and view transform
+/// of the viewport are applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RangeRect {
+    /// The index of the range in the ranges passed to [`range_rects`].
+    pub range: usize,
+    /// The index of the line of the buffer, see `LayoutRun::line_i`.
+    pub line: usize,
+    /// The position of the left edge of the rectangle.
+    pub left: i32,
+    /// The position of the top edge of the rectangle, the top of the laid-out line.
+    pub top: i32,
+    /// The position of the right edge of the rectangle.
+    pub right: i32,
+    /// The position of the bottom edge of the rectangle, the bottom of the laid-out line.
+    pub bottom: i32,
+    /// The position of the baseline of the line, which glyphs are placed on.
+    pub baseline: i32,
+    /// Whether the covered glyphs are right-to-left.
+    pub rtl: bool,
+}
+
+/// Appends the rectangles covering each of `ranges` in `text_area` to `rects`, e.g. to draw
+/// underlines or outlines around search matches.
+///
+/// Each range goes from a cursor to another, like a selection, and covers the glyphs of the
+/// clusters it overlaps. Ranges are split into a rectangle per laid-out line, and per run of
+/// glyphs of the same direction within a line, from left to right. `scale_factor` is the scale
+/// factor of the viewport times its render scale, which positions are multiplied by as in
+/// `prepare`. Glyph edges are rounded down to whole pixels, the way `prepare` places glyph
+/// origins, and baselines are those of the glyphs of the line.
+///
+/// Only the laid-out lines of the buffer are covered, and the bounds of the area are ignored.
+pub fn range_rects(
+    text_area: &TextArea,
+    scale_factor: f32,
+    ranges: &[Range<Cursor>],
+    rects: &mut Vec<RangeRect>,
+) {
+    // Scrolling only translates the text, as in `prepare`
+    let left = (text_area.left - text_area.scroll.0) * scale_factor;
+    let top = (text_area.top - text_area.scroll.1) * scale_factor;
+    let scale = text_area.scale * scale_factor;
+    let edge = |x: f32| (left + x * scale).floor() as i32;
+
+    let mut glyphs: Vec<&LayoutGlyph> = Vec::new();
+
+    for run in text_area.buffer.layout_runs() {
+        glyphs.clear();
+        glyphs.extend(run.glyphs);
+        glyphs.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+        let Some(first) = glyphs.first() else {
+            continue;
+        };
+        let placement = GlyphPlacement::from_layout_glyph(first, run.line_y, (left, top), scale);
+        let baseline = (placement.line_y * scale).round() as i32 + placement.y;
+        let line_top = (top + run.line_top * scale).floor() as i32;
+        let line_bottom = (top + (run.line_top + run.line_height) * scale).floor() as i32;
+
+        for (range_index, range) in ranges.iter().enumerate() {
+            if run.line_i < range.start.line || run.line_i > range.end.line {
+                continue;
+            }
+
+            let start = if run.line_i == range.start.line {
+                range.start.index
+            } else {
+                0
+            };
+            let end = if run.line_i == range.end.line {
+                range.end.index
+            } else {
+                usize::MAX
+            };
+
+            let mut current: Option<RangeRect> = None;
+            for glyph in &glyphs {
+                if glyph.start >= end || glyph.end <= start {
+                    rects.extend(current.take());
+                    continue;
+                }
+
+                let rtl = glyph.level.is_rtl();
+                let (glyph_left, glyph_right) = (edge(glyph.x), edge(glyph.x + glyph.w));
+
+                match &mut current {
+                    Some(rect) if rect.rtl == rtl => rect.right = rect.right.max(glyph_right),
+                    _ => {
+                        rects.extend(current.take());
+                        current = Some(RangeRect {
+                            range: range_index,
+                            line: run.line_i,
+                            left: glyph_left,
+                            top: line_top,
+                            right: glyph_right,
+                            bottom: line_bottom,
+                            baseline,
+                            rtl,
+                        });
+                    }
+                }
+            }
+
+            rects.extend(current);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Text areas repeated within a `prepare`, see [`crate::TextRenderer::set_skip_duplicate_areas`].
+
+use crate::{glyph_allocator::Hasher, CachePriority, FontSynthesis, TextArea};
+use std::collections::HashSet;
+
+/// The number of text areas of the most recent `prepare` that repeated an earlier area, see
+/// [`crate::TextRenderer::duplicate_areas`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DuplicateAreas {
+    /// Text areas identical to an earlier area, which were skipped (see
+    /// [`crate::TextRenderer::set_skip_duplicate_areas`]).
+    pub skipped: usize,
+    /// Text areas whose vertices were copied from an earlier area showing the same buffer at
+    /// another position (see [`crate::TextRenderer::set_share_buffer_geometry`]).
+    pub translated: usize,
+}
+
+/// Everything a text area is prepared from, with its buffer and custom glyphs identified by
+/// their address.
+#[derive(PartialEq, Eq, Hash)]
+struct AreaKey {
+    buffer: usize,
+    position: [u32; 5],
+    bounds: [i32; 4],
+    color: u32,
+    custom_glyphs: (usize, usize),
+    cache_priority: CachePriority,
+    font_synthesis: FontSynthesis,
+    background_hint: Option<u32>,
+}
+
+impl AreaKey {
+    fn new(area: &TextArea) -> Self {
+        Self {
+            buffer: area.buffer as *const _ as usize,
+            position: [
+                area.left.to_bits(),
+                area.top.to_bits(),
+                area.scale.to_bits(),
+                area.scroll.0.to_bits(),
+                area.scroll.1.to_bits(),
+            ],
+            bounds: [
+                area.bounds.left,
+                area.bounds.top,
+                area.bounds.right,
+                area.bounds.bottom,
+            ],
+            color: area.default_color.0,
+            custom_glyphs: (
+                area.custom_glyphs.as_ptr() as usize,
+                area.custom_glyphs.len(),
+            ),
+            cache_priority: area.cache_priority,
+            font_synthesis: area.font_synthesis,
+            background_hint: area.background_hint.map(|hint| hint.0),
+        }
+    }
+}
+
+/// The text areas of the `prepare` in progress.
+#[derive(Default)]
+pub(crate) struct AreaDedup {
+    seen: HashSet<AreaKey, Hasher>,
+    skipped: usize,
+}
+
+impl AreaDedup {
+    /// Records `area`, returning whether an identical area was recorded before.
+    pub fn is_duplicate(&mut self, area: &TextArea) -> bool {
+        let duplicate = !self.seen.insert(AreaKey::new(area));
+        self.skipped += duplicate as usize;
+        duplicate
+    }
+
+    /// Forgets the recorded areas, keeping the allocation of the set, and returns the number of
+    /// duplicates.
+    pub fn finish(&mut self) -> usize {
+        self.seen.clear();
+        std::mem::take(&mut self.skipped)
+    }
+}
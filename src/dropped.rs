@@ -0,0 +1,98 @@
+use crate::{text_render::GlyphonCacheKey, GlyphKey};
+
+/// The number of glyphs that the most recent `prepare` did not draw, by reason, see
+/// [`crate::TextRenderer::dropped_glyphs`].
+///
+/// Glyphs appearing several times are counted each time. Text areas whose geometry is cached
+/// (see [`crate::TextRenderer::set_geometry_cache`]) reuse their vertices without counting
+/// their dropped glyphs again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DroppedGlyphs {
+    /// Glyphs without pixels, like whitespace, and text glyphs that could not be rasterized.
+    pub empty: usize,
+    /// Glyphs entirely outside of the bounds of their area.
+    pub culled: usize,
+    /// Custom glyphs for which the rasterizer returned `None`.
+    pub custom_not_rasterized: usize,
+    /// Glyphs that were not rasterized because the atlas was full: the glyph that did not fit,
+    /// and the missing glyphs after it, which are dropped along with it when `prepare` fails
+    /// with [`crate::PrepareError::AtlasFull`].
+    pub atlas_full: usize,
+    /// Glyphs that were not rasterized because the budget of
+    /// [`crate::TextRenderer::prepare_with_budget`] or the upload budget of the atlas (see
+    /// [`crate::TextAtlas::set_upload_budget`]) was exhausted.
+    pub deferred: usize,
+}
+
+impl DroppedGlyphs {
+    /// Returns the number of glyphs dropped for any reason.
+    pub fn total(&self) -> usize {
+        self.empty + self.culled + self.custom_not_rasterized + self.atlas_full + self.deferred
+    }
+}
+
+/// Why a glyph was not drawn, see [`DroppedGlyphs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DropReason {
+    /// See [`DroppedGlyphs::empty`].
+    Empty,
+    /// See [`DroppedGlyphs::culled`].
+    Culled,
+    /// See [`DroppedGlyphs::custom_not_rasterized`].
+    CustomNotRasterized,
+    /// See [`DroppedGlyphs::atlas_full`].
+    AtlasFull,
+    /// See [`DroppedGlyphs::deferred`].
+    Deferred,
+}
+
+/// A glyph that was not drawn, see [`crate::TextRenderer::dropped_glyph_log`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DroppedGlyph {
+    /// The glyph, including its font and size.
+    pub key: GlyphKey,
+    /// Why the glyph was not drawn.
+    pub reason: DropReason,
+}
+
+/// Counts the glyphs dropped by a `prepare`, and logs the first ones in debug builds.
+#[derive(Debug, Default)]
+pub(crate) struct DropTracker {
+    pub counts: DroppedGlyphs,
+    #[cfg(debug_assertions)]
+    pub log: Vec<DroppedGlyph>,
+    /// The maximum length of `log`, `0` if dropped glyphs are not logged.
+    #[cfg(debug_assertions)]
+    pub log_capacity: usize,
+}
+
+impl DropTracker {
+    pub fn reset(&mut self) {
+        self.counts = DroppedGlyphs::default();
+        #[cfg(debug_assertions)]
+        self.log.clear();
+    }
+
+    /// Counts a drop of `cache_key`, logging it if the log has room.
+    #[inline]
+    pub fn record(&mut self, cache_key: GlyphonCacheKey, reason: DropReason) {
+        let counter = match reason {
+            DropReason::Empty => &mut self.counts.empty,
+            DropReason::Culled => &mut self.counts.culled,
+            DropReason::CustomNotRasterized => &mut self.counts.custom_not_rasterized,
+            DropReason::AtlasFull => &mut self.counts.atlas_full,
+            DropReason::Deferred => &mut self.counts.deferred,
+        };
+        *counter += 1;
+
+        #[cfg(debug_assertions)]
+        if self.log.len() < self.log_capacity {
+            self.log.push(DroppedGlyph {
+                key: cache_key.into(),
+                reason,
+            });
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = cache_key;
+    }
+}
@@ -0,0 +1,41 @@
+//! Fonts embedded in the crate, for rendering that does not depend on the fonts installed on the
+//! host, e.g. in rendering tests or in apps shipping a fixed set of fonts.
+//!
+//! See `fonts/README.md` for the licenses of the fonts.
+
+use crate::{fontdb, FontSystem};
+
+/// Inter Bold, the font of the examples, licensed under the SIL Open Font License 1.1.
+pub const INTER_BOLD: &[u8] = include_bytes!("../examples/Inter-Bold.ttf");
+
+/// The family name of [`INTER_BOLD`].
+pub const INTER_FAMILY: &str = "Inter";
+
+/// A minimal color font drawn for tests, licensed like this crate.
+///
+/// It covers U+1F600 GRINNING FACE, U+2764 HEAVY BLACK HEART and U+1F7E6 LARGE BLUE SQUARE with
+/// layered `COLR` outlines, and U+0020 SPACE. Its glyphs go to the color atlas like those of a
+/// system emoji font, but are simple enough to be compared between machines.
+pub const TEST_EMOJI: &[u8] = include_bytes!("../fonts/MetalglyphTestEmoji.ttf");
+
+/// The family name of [`TEST_EMOJI`].
+pub const TEST_EMOJI_FAMILY: &str = "Metalglyph Test Emoji";
+
+/// Creates a [`FontSystem`] containing only the embedded fonts, ignoring the fonts of the system.
+///
+/// Every generic family, e.g. [`Family::SansSerif`](crate::Family::SansSerif), resolves to
+/// [`INTER_FAMILY`]. Characters Inter does not cover fall back to [`TEST_EMOJI_FAMILY`] where it
+/// has a glyph for them, and render as missing glyphs otherwise. The locale is `en-US`
+/// regardless of the host, since it affects fallback and shaping.
+pub fn test_font_system() -> FontSystem {
+    let mut db = fontdb::Database::new();
+    db.load_font_data(INTER_BOLD.to_vec());
+    db.load_font_data(TEST_EMOJI.to_vec());
+    db.set_serif_family(INTER_FAMILY);
+    db.set_sans_serif_family(INTER_FAMILY);
+    db.set_cursive_family(INTER_FAMILY);
+    db.set_fantasy_family(INTER_FAMILY);
+    db.set_monospace_family(INTER_FAMILY);
+
+    FontSystem::new_with_locale_and_db("en-US".to_owned(), db)
+}
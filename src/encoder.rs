@@ -0,0 +1,343 @@
+use crate::ContentType;
+use objc2::{rc::Retained, runtime::ProtocolObject, sel, Message};
+use objc2_foundation::{NSObjectProtocol as _, NSString};
+#[cfg(feature = "mtl4")]
+use objc2_metal::{
+    MTL4ArgumentTable, MTL4ArgumentTableDescriptor, MTL4CommandEncoder as _,
+    MTL4RenderCommandEncoder, MTLRenderStages,
+};
+use objc2_metal::{
+    MTLBuffer, MTLCommandEncoder as _, MTLDevice, MTLPrimitiveType, MTLRenderCommandEncoder,
+    MTLRenderPipelineState, MTLScissorRect, MTLTexture, MTLViewport,
+};
+#[cfg(feature = "mtl4")]
+use std::cell::OnceCell;
+use std::ptr;
+
+/// The number of vertices the first vertex of a draw advances by per layer, which tells the
+/// layers apart in `vertex_layered` and `vertex_stereo_views`.
+const VERTICES_PER_LAYER: usize = 4;
+
+/// Returns `true` if the Metal 4 APIs (argument tables, Metal 4 command encoders) are available
+/// on `device`.
+///
+/// Every entry point of the crate works with classic Metal alone. The Metal 4 specific ones
+/// (rendering into an `MTL4RenderCommandEncoder`) must only be used when this returns `true`.
+pub fn mtl4_available(device: &ProtocolObject<dyn MTLDevice>) -> bool {
+    device.respondsToSelector(sel!(newArgumentTableWithDescriptor:error:))
+}
+
+/// Returns `true` if residency sets are available on `device`.
+#[cfg(feature = "mtl4")]
+pub(crate) fn residency_sets_available(device: &ProtocolObject<dyn MTLDevice>) -> bool {
+    device.respondsToSelector(sel!(newResidencySetWithDescriptor:error:))
+}
+
+/// The resources bound by a [`crate::TextRenderer`] before drawing.
+#[doc(hidden)]
+pub struct TextBindings<'a> {
+    #[cfg(feature = "mtl4")]
+    pub(crate) device: &'a ProtocolObject<dyn MTLDevice>,
+    pub(crate) params_buffer: &'a ProtocolObject<dyn MTLBuffer>,
+    pub(crate) params_offset: usize,
+    /// The offset of the parameters of the second view of stereo draws, bound at index 2.
+    pub(crate) second_params_offset: Option<usize>,
+    pub(crate) vertex_buffer: &'a ProtocolObject<dyn MTLBuffer>,
+    pub(crate) color_atlas: &'a ProtocolObject<dyn MTLTexture>,
+    pub(crate) mask_atlas: &'a ProtocolObject<dyn MTLTexture>,
+    #[cfg(feature = "mtl4")]
+    pub(crate) argument_table: &'a OnceCell<Retained<ProtocolObject<dyn MTL4ArgumentTable>>>,
+}
+
+impl TextBindings<'_> {
+    /// Returns the atlas texture of `content_type` and the index it is bound at.
+    fn atlas(&self, content_type: ContentType) -> (&ProtocolObject<dyn MTLTexture>, usize) {
+        match content_type {
+            ContentType::Color => (self.color_atlas, 0),
+            ContentType::Mask => (self.mask_atlas, 1),
+        }
+    }
+}
+
+/// A render command encoder that a [`crate::TextRenderer`] can render into.
+///
+/// This is implemented for both the classic `MTLRenderCommandEncoder` and the Metal 4
+/// `MTL4RenderCommandEncoder` (with the `mtl4` feature), so the same `render` method works with
+/// either command buffer API. On the Metal 4 path, resources are bound through an argument table
+/// owned by the renderer, and must be made resident by the caller (see
+/// [`crate::TextRenderer::residency_set`]).
+///
+/// The trait is sealed: its methods are internal to the crate, which implements it for every
+/// supported encoder.
+pub trait TextRenderEncoder: private::Sealed {
+    #[doc(hidden)]
+    fn set_pipeline(&self, pipeline: &ProtocolObject<dyn MTLRenderPipelineState>);
+
+    #[doc(hidden)]
+    fn bind_resources(&self, bindings: &TextBindings<'_>);
+
+    #[doc(hidden)]
+    fn bind_atlas(&self, bindings: &TextBindings<'_>, content_type: ContentType);
+
+    /// Draws the glyphs, into the layer `layer` of layered draws without vertex amplification.
+    #[doc(hidden)]
+    fn draw_glyphs(&self, first_glyph: usize, glyph_count: usize, layer: usize);
+
+    #[doc(hidden)]
+    fn set_vertex_amplification_count(&self, count: usize);
+
+    #[doc(hidden)]
+    fn set_viewport(&self, viewport: MTLViewport);
+
+    #[doc(hidden)]
+    fn set_scissor_rect(&self, rect: MTLScissorRect);
+
+    #[doc(hidden)]
+    fn push_debug_group(&self, label: &NSString);
+
+    #[doc(hidden)]
+    fn pop_debug_group(&self);
+
+    #[doc(hidden)]
+    fn insert_debug_signpost(&self, label: &NSString);
+
+    /// Returns whether the encoder relies on residency sets to make bound resources resident.
+    #[cfg(feature = "mtl4")]
+    #[doc(hidden)]
+    fn uses_residency_sets(&self) -> bool;
+}
+
+mod private {
+    /// Keeps [`super::TextRenderEncoder`] from being implemented outside of the crate.
+    pub trait Sealed {}
+}
+
+impl private::Sealed for ProtocolObject<dyn MTLRenderCommandEncoder> {}
+
+impl TextRenderEncoder for ProtocolObject<dyn MTLRenderCommandEncoder> {
+    fn set_pipeline(&self, pipeline: &ProtocolObject<dyn MTLRenderPipelineState>) {
+        self.setRenderPipelineState(pipeline);
+    }
+
+    fn bind_resources(&self, bindings: &TextBindings<'_>) {
+        unsafe {
+            self.setVertexBuffer_offset_atIndex(
+                Some(bindings.params_buffer),
+                bindings.params_offset,
+                0,
+            );
+            self.setVertexBuffer_offset_atIndex(Some(bindings.vertex_buffer), 0, 1);
+            self.setFragmentBuffer_offset_atIndex(
+                Some(bindings.params_buffer),
+                bindings.params_offset,
+                0,
+            );
+            if let Some(second_params_offset) = bindings.second_params_offset {
+                self.setVertexBuffer_offset_atIndex(
+                    Some(bindings.params_buffer),
+                    second_params_offset,
+                    2,
+                );
+            }
+        }
+    }
+
+    fn bind_atlas(&self, bindings: &TextBindings<'_>, content_type: ContentType) {
+        let (texture, index) = bindings.atlas(content_type);
+
+        unsafe {
+            self.setVertexTexture_atIndex(Some(texture), index);
+            self.setFragmentTexture_atIndex(Some(texture), index);
+        }
+    }
+
+    fn draw_glyphs(&self, first_glyph: usize, glyph_count: usize, layer: usize) {
+        unsafe {
+            self.drawPrimitives_vertexStart_vertexCount_instanceCount_baseInstance(
+                MTLPrimitiveType::TriangleStrip,
+                VERTICES_PER_LAYER * layer,
+                4,
+                glyph_count,
+                first_glyph,
+            );
+        }
+    }
+
+    fn set_vertex_amplification_count(&self, count: usize) {
+        unsafe {
+            self.setVertexAmplificationCount_viewMappings(count, ptr::null());
+        }
+    }
+
+    fn set_viewport(&self, viewport: MTLViewport) {
+        self.setViewport(viewport);
+    }
+
+    fn set_scissor_rect(&self, rect: MTLScissorRect) {
+        self.setScissorRect(rect);
+    }
+
+    fn push_debug_group(&self, label: &NSString) {
+        self.pushDebugGroup(label);
+    }
+
+    fn pop_debug_group(&self) {
+        self.popDebugGroup();
+    }
+
+    fn insert_debug_signpost(&self, label: &NSString) {
+        self.insertDebugSignpost(label);
+    }
+
+    #[cfg(feature = "mtl4")]
+    fn uses_residency_sets(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(feature = "mtl4")]
+impl private::Sealed for ProtocolObject<dyn MTL4RenderCommandEncoder> {}
+
+#[cfg(feature = "mtl4")]
+impl TextRenderEncoder for ProtocolObject<dyn MTL4RenderCommandEncoder> {
+    fn set_pipeline(&self, pipeline: &ProtocolObject<dyn MTLRenderPipelineState>) {
+        self.setRenderPipelineState(pipeline);
+    }
+
+    fn bind_resources(&self, bindings: &TextBindings<'_>) {
+        let argument_table = bindings.argument_table.get_or_init(|| {
+            let descriptor = MTL4ArgumentTableDescriptor::new();
+            descriptor.setMaxBufferBindCount(3);
+            descriptor.setMaxTextureBindCount(2);
+
+            bindings
+                .device
+                .newArgumentTableWithDescriptor_error(&descriptor)
+                .expect("Failed to create argument table")
+        });
+
+        unsafe {
+            argument_table.setAddress_atIndex(
+                bindings.params_buffer.gpuAddress() + bindings.params_offset as u64,
+                0,
+            );
+            argument_table.setAddress_atIndex(bindings.vertex_buffer.gpuAddress(), 1);
+            if let Some(second_params_offset) = bindings.second_params_offset {
+                argument_table.setAddress_atIndex(
+                    bindings.params_buffer.gpuAddress() + second_params_offset as u64,
+                    2,
+                );
+            }
+        }
+
+        self.setArgumentTable_atStages(
+            argument_table,
+            MTLRenderStages::Vertex | MTLRenderStages::Fragment,
+        );
+    }
+
+    fn bind_atlas(&self, bindings: &TextBindings<'_>, content_type: ContentType) {
+        let argument_table = bindings
+            .argument_table
+            .get()
+            .expect("Bind resources before binding an atlas");
+        let (texture, index) = bindings.atlas(content_type);
+
+        // The encoder takes a snapshot of the argument table at each draw, so updating the
+        // table between draws is enough
+        unsafe {
+            argument_table.setTexture_atIndex(texture.gpuResourceID(), index);
+        }
+    }
+
+    fn draw_glyphs(&self, first_glyph: usize, glyph_count: usize, layer: usize) {
+        unsafe {
+            self.drawPrimitives_vertexStart_vertexCount_instanceCount_baseInstance(
+                MTLPrimitiveType::TriangleStrip,
+                VERTICES_PER_LAYER * layer,
+                4,
+                glyph_count,
+                first_glyph,
+            );
+        }
+    }
+
+    fn set_vertex_amplification_count(&self, count: usize) {
+        unsafe {
+            self.setVertexAmplificationCount_viewMappings(count, ptr::null());
+        }
+    }
+
+    fn set_viewport(&self, viewport: MTLViewport) {
+        self.setViewport(viewport);
+    }
+
+    fn set_scissor_rect(&self, rect: MTLScissorRect) {
+        self.setScissorRect(rect);
+    }
+
+    fn push_debug_group(&self, label: &NSString) {
+        self.pushDebugGroup(label);
+    }
+
+    fn pop_debug_group(&self) {
+        self.popDebugGroup();
+    }
+
+    fn insert_debug_signpost(&self, label: &NSString) {
+        self.insertDebugSignpost(label);
+    }
+
+    fn uses_residency_sets(&self) -> bool {
+        true
+    }
+}
+
+impl<T: TextRenderEncoder + Message + ?Sized> private::Sealed for Retained<T> {}
+
+impl<T: TextRenderEncoder + Message + ?Sized> TextRenderEncoder for Retained<T> {
+    fn set_pipeline(&self, pipeline: &ProtocolObject<dyn MTLRenderPipelineState>) {
+        (**self).set_pipeline(pipeline);
+    }
+
+    fn bind_resources(&self, bindings: &TextBindings<'_>) {
+        (**self).bind_resources(bindings);
+    }
+
+    fn bind_atlas(&self, bindings: &TextBindings<'_>, content_type: ContentType) {
+        (**self).bind_atlas(bindings, content_type);
+    }
+
+    fn draw_glyphs(&self, first_glyph: usize, glyph_count: usize, layer: usize) {
+        (**self).draw_glyphs(first_glyph, glyph_count, layer);
+    }
+
+    fn set_vertex_amplification_count(&self, count: usize) {
+        (**self).set_vertex_amplification_count(count);
+    }
+
+    fn set_viewport(&self, viewport: MTLViewport) {
+        (**self).set_viewport(viewport);
+    }
+
+    fn set_scissor_rect(&self, rect: MTLScissorRect) {
+        (**self).set_scissor_rect(rect);
+    }
+
+    fn push_debug_group(&self, label: &NSString) {
+        (**self).push_debug_group(label);
+    }
+
+    fn pop_debug_group(&self) {
+        (**self).pop_debug_group();
+    }
+
+    fn insert_debug_signpost(&self, label: &NSString) {
+        (**self).insert_debug_signpost(label);
+    }
+
+    #[cfg(feature = "mtl4")]
+    fn uses_residency_sets(&self) -> bool {
+        (**self).uses_residency_sets()
+    }
+}
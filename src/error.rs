@@ -1,3 +1,5 @@
+use crate::ContentType;
+use objc2_metal::MTLPixelFormat;
 use std::{
     error::Error,
     fmt::{self, Display, Formatter},
@@ -7,21 +9,185 @@ use std::{
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum PrepareError {
     AtlasFull,
+    /// The device failed to create a texture or buffer, as it does once it is lost (e.g. when an
+    /// external GPU is unplugged). See [`crate::TextAtlas::recreate_on`].
+    DeviceLost,
 }
 
 impl Display for PrepareError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "Prepare error: glyph texture atlas is full")
+        match self {
+            PrepareError::AtlasFull => write!(f, "Prepare error: glyph texture atlas is full"),
+            PrepareError::DeviceLost => write!(
+                f,
+                "Prepare error: the device failed to create a resource, it was likely lost"
+            ),
+        }
     }
 }
 
 impl Error for PrepareError {}
 
+/// An error that occurred while building a [`crate::TextRenderer`] or a [`crate::TextAtlas`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BuildError {
+    /// The device does not support the requested sample count.
+    UnsupportedSampleCount(usize),
+    /// The vertex storage mode is not accessible from the CPU, or is managed on a platform other
+    /// than macOS.
+    UnsupportedVertexStorage,
+    /// The viewport storage mode is not accessible from the CPU, or is managed on a platform
+    /// other than macOS.
+    UnsupportedViewportStorage,
+    /// A render pipeline could not be created, because the custom fragment function of the
+    /// [`crate::Cache`] is missing or does not link. Holds the description of the Metal error.
+    PipelineCreation(String),
+    /// Text cannot be rendered into targets of the pixel format of the atlas, or of the mask
+    /// format of the renderer.
+    UnsupportedPixelFormat {
+        /// The unsupported format.
+        format: MTLPixelFormat,
+        /// Why the format is not supported.
+        reason: UnsupportedFormatReason,
+    },
+    /// The initial size of an atlas is zero or larger than its maximum size, or its maximum size
+    /// exceeds the largest texture supported by the device.
+    InvalidAtlasSize {
+        /// The atlas with the invalid sizes.
+        content_type: ContentType,
+        /// The requested initial size.
+        initial_size: u32,
+        /// The requested maximum size.
+        max_size: u32,
+        /// The largest texture size supported by the device.
+        device_max_size: u32,
+    },
+    /// The device failed to create a texture or buffer, as it does once it is lost.
+    DeviceLost,
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            BuildError::UnsupportedSampleCount(sample_count) => write!(
+                f,
+                "Build error: sample count {sample_count} is not supported by the device"
+            ),
+            BuildError::UnsupportedVertexStorage => write!(
+                f,
+                "Build error: vertex storage must use the shared storage mode, or managed on macOS"
+            ),
+            BuildError::UnsupportedViewportStorage => write!(
+                f,
+                "Build error: viewport storage must use the shared storage mode, or managed on macOS"
+            ),
+            BuildError::PipelineCreation(error) => write!(
+                f,
+                "Build error: failed to create a pipeline with the custom fragment function: {error}"
+            ),
+            BuildError::UnsupportedPixelFormat { format, reason } => write!(
+                f,
+                "Build error: text cannot be rendered into the pixel format {format:?}: {reason}"
+            ),
+            BuildError::InvalidAtlasSize {
+                content_type,
+                initial_size,
+                max_size,
+                device_max_size,
+            } => write!(
+                f,
+                "Build error: invalid {content_type:?} atlas sizes (initial {initial_size}, maximum \
+                 {max_size}): the initial size must be between 1 and the maximum size, which must \
+                 be at most {device_max_size}"
+            ),
+            BuildError::DeviceLost => write!(
+                f,
+                "Build error: the device failed to create a resource, it was likely lost"
+            ),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+/// Why text cannot be rendered into a pixel format, see [`BuildError::UnsupportedPixelFormat`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnsupportedFormatReason {
+    /// The format is a depth or stencil format, which is passed as the depth format of the
+    /// renderer instead.
+    DepthStencil,
+    /// The format is an integer format, which cannot be blended.
+    Integer,
+    /// The format is a compressed, video or other format that cannot be rendered into.
+    NotRenderable,
+    /// The format can be rendered into and blended, but not on this device.
+    UnsupportedByDevice,
+}
+
+impl Display for UnsupportedFormatReason {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            UnsupportedFormatReason::DepthStencil => write!(
+                f,
+                "it is a depth or stencil format, pass it as the depth format instead"
+            ),
+            UnsupportedFormatReason::Integer => {
+                write!(f, "integer formats cannot be blended")
+            }
+            UnsupportedFormatReason::NotRenderable => {
+                write!(f, "it is not a color format that can be rendered into")
+            }
+            UnsupportedFormatReason::UnsupportedByDevice => {
+                write!(f, "the device cannot render into and blend this format")
+            }
+        }
+    }
+}
+
+/// An error that occurred while selecting a parameter slot of a [`crate::Viewport`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SlotError {
+    /// The slot is not below [`crate::Viewport::MAX_SLOTS`].
+    OutOfRange(usize),
+    /// The device failed to create the viewport buffer holding the new slot, as it does once it
+    /// is lost.
+    DeviceLost,
+}
+
+impl Display for SlotError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SlotError::OutOfRange(slot) => write!(
+                f,
+                "Slot error: viewport slot {slot} is out of range, viewports hold at most {} slots",
+                crate::Viewport::MAX_SLOTS
+            ),
+            SlotError::DeviceLost => write!(
+                f,
+                "Slot error: the device failed to create the viewport buffer, it was likely lost"
+            ),
+        }
+    }
+}
+
+impl Error for SlotError {}
+
 /// An error that occurred while rendering text.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum RenderError {
     RemovedFromAtlas,
     ScreenResolutionChanged,
+    /// The render target does not have the pixel format the renderer was created for, see
+    /// [`crate::TextRenderer::check_format`].
+    FormatMismatch {
+        /// The format of the atlas the renderer was created with.
+        expected: MTLPixelFormat,
+        /// The format of the render target.
+        actual: MTLPixelFormat,
+    },
+    /// A `prepare` of the renderer or against its atlas found their device lost, see
+    /// [`crate::TextRenderer::check_device`].
+    DeviceLost,
 }
 
 impl Display for RenderError {
@@ -37,8 +203,87 @@ impl Display for RenderError {
                 f,
                 "Render error: screen resolution changed since last `prepare` call"
             ),
+            RenderError::FormatMismatch { expected, actual } => write!(
+                f,
+                "Render error: the render target format {actual:?} does not match the format \
+                 {expected:?} the text atlas and renderer were created with"
+            ),
+            RenderError::DeviceLost => write!(
+                f,
+                "Render error: the device of the text atlas or renderer was lost, they must be \
+                 recreated on another device"
+            ),
         }
     }
 }
 
 impl Error for RenderError {}
+
+/// An error that occurred while replaying a [`crate::Snapshot`].
+#[cfg(feature = "debug-tools")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SnapshotError {
+    /// The snapshot was captured by a version of the crate with another snapshot format.
+    UnsupportedVersion(u32),
+    /// The snapshot was captured without atlas pixels.
+    MissingAtlasPixels,
+    /// The atlas pixels do not match the atlas sizes.
+    InvalidAtlasPixels,
+}
+
+#[cfg(feature = "debug-tools")]
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "Snapshot error: snapshot version {version} is not supported"
+            ),
+            SnapshotError::MissingAtlasPixels => write!(
+                f,
+                "Snapshot error: the snapshot was captured without atlas pixels"
+            ),
+            SnapshotError::InvalidAtlasPixels => write!(
+                f,
+                "Snapshot error: the atlas pixels do not match the atlas sizes"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "debug-tools")]
+impl Error for SnapshotError {}
+
+/// An error that occurred while reloading the shaders of a [`crate::Cache`].
+#[cfg(feature = "dev-tools")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CacheError {
+    /// The shader source failed to compile, with the compiler output.
+    Compilation(String),
+    /// The shader source does not define a function of the built-in shaders.
+    MissingFunction(String),
+    /// A pipeline failed to be created from the new shaders.
+    PipelineCreation(String),
+}
+
+#[cfg(feature = "dev-tools")]
+impl Display for CacheError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CacheError::Compilation(output) => write!(
+                f,
+                "Cache error: the shader source failed to compile: {output}"
+            ),
+            CacheError::MissingFunction(name) => write!(
+                f,
+                "Cache error: the shader source does not define the function {name}"
+            ),
+            CacheError::PipelineCreation(error) => {
+                write!(f, "Cache error: failed to create a pipeline: {error}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "dev-tools")]
+impl Error for CacheError {}
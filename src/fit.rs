@@ -0,0 +1,85 @@
+use crate::{measure, Attrs, Buffer, FontSystem, Metrics, Shaping, Wrap};
+
+/// The line height of text laid out by [`fit_text`], relative to its font size.
+const LINE_HEIGHT: f32 = 1.2;
+
+/// The font sizes [`fit_text`] stops searching between, in the units of the font size.
+const PRECISION: f32 = 0.25;
+
+/// Text laid out at the largest size fitting a rectangle, see [`fit_text`].
+pub struct FittedText {
+    /// The metrics the text is laid out with.
+    pub metrics: Metrics,
+    /// The buffer laid out with `metrics` and sized to the rectangle, ready for a
+    /// [`crate::TextArea`].
+    pub buffer: Buffer,
+    /// Whether the text fits the rectangle, `false` if it overflows it even at the minimum size,
+    /// at which it is then laid out.
+    pub fits: bool,
+}
+
+/// Lays out `text` at the largest font size between `min_size` and `max_size` at which it fits
+/// in a rectangle of `rect` (width and height), e.g. for badges and buttons.
+///
+/// The font size is binary searched to within a quarter of a unit, with lines 1.2 times the font
+/// size high. The text is shaped once into a single buffer, and only laid out again for each
+/// candidate size. Lines are wrapped to the width of the rectangle with `wrap`, or only broken at
+/// line breaks with [`Wrap::None`]. With [`Wrap::Word`], a word wider than the rectangle makes
+/// the text overflow, so the size shrinks until the longest word fits on its line: use
+/// [`Wrap::WordOrGlyph`] to break such words instead.
+///
+/// Sizes have the units of the buffer metrics, see [`crate::measure`].
+pub fn fit_text(
+    font_system: &mut FontSystem,
+    text: &str,
+    attrs: &Attrs,
+    rect: (f32, f32),
+    min_size: f32,
+    max_size: f32,
+    wrap: Wrap,
+) -> FittedText {
+    let (width, height) = rect;
+    let metrics = |size: f32| Metrics::new(size, size * LINE_HEIGHT);
+
+    let mut buffer = Buffer::new(font_system, metrics(max_size));
+    buffer.set_wrap(font_system, wrap);
+    buffer.set_size(font_system, Some(width), None);
+    buffer.set_text(font_system, text, attrs, Shaping::Advanced);
+
+    let mut fits = |buffer: &mut Buffer, size: f32| {
+        buffer.set_metrics(font_system, metrics(size));
+        buffer.shape_until_scroll(font_system, false);
+
+        let size = measure(buffer);
+        size.width <= width && size.height <= height
+    };
+
+    let (size, text_fits) = if fits(&mut buffer, max_size) {
+        (max_size, true)
+    } else if !fits(&mut buffer, min_size) {
+        (min_size, false)
+    } else {
+        // `low` always fits and `high` never does
+        let (mut low, mut high) = (min_size, max_size);
+        while high - low > PRECISION {
+            let mid = (low + high) / 2.0;
+
+            if fits(&mut buffer, mid) {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        (low, true)
+    };
+
+    buffer.set_metrics_and_size(font_system, metrics(size), Some(width), Some(height));
+    buffer.shape_until_scroll(font_system, false);
+
+    FittedText {
+        metrics: metrics(size),
+        buffer,
+        fits: text_fits,
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{Attrs, Buffer, FontSystem, Metrics, Shaping};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Access to a [`FontSystem`], accepted by [`crate::TextRenderer::prepare`] and its variants.
+///
+/// Implemented for `&mut FontSystem`, which is used directly, and for `&SharedFontSystem`, which
+/// is locked for the whole of `prepare`.
+pub trait FontSystemAccess {
+    /// Calls `f` with exclusive access to the font system.
+    fn with_font_system<R>(self, f: impl FnOnce(&mut FontSystem) -> R) -> R;
+}
+
+impl FontSystemAccess for &mut FontSystem {
+    fn with_font_system<R>(self, f: impl FnOnce(&mut FontSystem) -> R) -> R {
+        f(self)
+    }
+}
+
+impl FontSystemAccess for &SharedFontSystem {
+    fn with_font_system<R>(self, f: impl FnOnce(&mut FontSystem) -> R) -> R {
+        f(&mut self.lock())
+    }
+}
+
+/// A [`FontSystem`] shared between the parts of an application that lay out, edit and render
+/// text, instead of threading `&mut FontSystem` through all of them.
+///
+/// Clones refer to the same font system, behind a single mutex. Every method locks it for the
+/// duration of the call only, so a lock is never held across calls. Do not call methods of a
+/// `SharedFontSystem` from within [`SharedFontSystem::with`], or while holding the guard of
+/// [`SharedFontSystem::lock`], since the mutex is not reentrant and that call would deadlock.
+#[derive(Debug, Clone)]
+pub struct SharedFontSystem(Arc<Mutex<FontSystem>>);
+
+impl SharedFontSystem {
+    /// Shares `font_system`.
+    pub fn new(font_system: FontSystem) -> Self {
+        Self(Arc::new(Mutex::new(font_system)))
+    }
+
+    /// Locks the font system, blocking until no other caller is using it.
+    pub fn lock(&self) -> MutexGuard<'_, FontSystem> {
+        self.0.lock().expect("Lock shared font system")
+    }
+
+    /// Calls `f` with the locked font system, e.g. to call methods of `cosmic-text` that take
+    /// `&mut FontSystem`.
+    pub fn with<R>(&self, f: impl FnOnce(&mut FontSystem) -> R) -> R {
+        f(&mut self.lock())
+    }
+
+    /// Loads a font from `data` into the font database.
+    pub fn load_font_data(&self, data: Vec<u8>) {
+        self.lock().db_mut().load_font_data(data);
+    }
+
+    /// Creates a new [`Buffer`] with the given `metrics`, see [`Buffer::new`].
+    pub fn new_buffer(&self, metrics: Metrics) -> Buffer {
+        Buffer::new(&mut self.lock(), metrics)
+    }
+
+    /// Sets the text of `buffer`, see [`Buffer::set_text`].
+    pub fn set_text(&self, buffer: &mut Buffer, text: &str, attrs: &Attrs, shaping: Shaping) {
+        buffer.set_text(&mut self.lock(), text, attrs, shaping);
+    }
+
+    /// Sets the size of `buffer`, see [`Buffer::set_size`].
+    pub fn set_size(&self, buffer: &mut Buffer, width: Option<f32>, height: Option<f32>) {
+        buffer.set_size(&mut self.lock(), width, height);
+    }
+
+    /// Shapes `buffer` until its scroll position, see [`Buffer::shape_until_scroll`].
+    pub fn shape_until_scroll(&self, buffer: &mut Buffer, prune: bool) {
+        buffer.shape_until_scroll(&mut self.lock(), prune);
+    }
+}
+
+impl Default for SharedFontSystem {
+    /// Shares a new [`FontSystem`] with the system fonts loaded, see [`FontSystem::new`].
+    fn default() -> Self {
+        Self::new(FontSystem::new())
+    }
+}
+
+impl From<FontSystem> for SharedFontSystem {
+    fn from(font_system: FontSystem) -> Self {
+        Self::new(font_system)
+    }
+}
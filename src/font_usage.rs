@@ -0,0 +1,123 @@
+use crate::{synthesis::ResolvedFace, FaceMismatch};
+use cosmic_text::{fontdb, CacheKey, FontSystem};
+use std::path::PathBuf;
+
+/// The fonts that rendered the glyphs of a text area in the most recent `prepare`, see
+/// [`crate::TextRenderer::font_usage`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AreaFontUsage {
+    /// The [`crate::TextArea::id`] of the area, or its index among the areas of the `prepare`
+    /// if it has none.
+    pub area_id: u64,
+    /// The distinct fonts of the glyphs of the area, in the order they first appear.
+    pub fonts: Vec<UsedFont>,
+    /// The number of glyphs that fell through to the `.notdef` glyph of their font, which is
+    /// usually drawn as an empty box ("tofu"), because no font covers their character.
+    pub notdef_glyphs: usize,
+    /// The distinct faces that differ from the face requested for glyphs of the area, e.g. a
+    /// regular face drawing text requested in bold, in the order they first appear.
+    pub face_mismatches: Vec<FaceMismatch>,
+}
+
+impl AreaFontUsage {
+    /// Returns whether some characters of the area are not covered by any font.
+    pub fn has_missing_coverage(&self) -> bool {
+        self.notdef_glyphs > 0
+    }
+
+    /// Returns whether some glyphs of the area are drawn with another face than requested.
+    pub fn has_face_mismatches(&self) -> bool {
+        !self.face_mismatches.is_empty()
+    }
+
+    /// Counts a glyph drawn with `face`, if it differs from the requested face.
+    pub(crate) fn record_face(&mut self, face: &ResolvedFace) {
+        if !face.mismatches() {
+            return;
+        }
+
+        let emboldened = face.embolden > 0.0;
+        let index = match self.face_mismatches.iter().position(|mismatch| {
+            mismatch.font == face.font
+                && mismatch.requested == face.requested
+                && mismatch.emboldened == emboldened
+        }) {
+            Some(index) => index,
+            None => {
+                self.face_mismatches.push(FaceMismatch {
+                    font: face.font,
+                    requested: face.requested,
+                    matched: face.matched,
+                    glyphs: 0,
+                    emboldened,
+                });
+                self.face_mismatches.len() - 1
+            }
+        };
+
+        self.face_mismatches[index].glyphs += 1;
+    }
+
+    pub(crate) fn record(&mut self, cache_key: &CacheKey, font_system: &FontSystem) {
+        let notdef = cache_key.glyph_id == 0;
+        if notdef {
+            self.notdef_glyphs += 1;
+        }
+
+        let index = match self
+            .fonts
+            .iter()
+            .position(|font| font.id == cache_key.font_id)
+        {
+            Some(index) => index,
+            None => {
+                self.fonts
+                    .push(UsedFont::new(cache_key.font_id, font_system));
+                self.fonts.len() - 1
+            }
+        };
+
+        let font = &mut self.fonts[index];
+        font.glyphs += 1;
+        if notdef {
+            font.notdef_glyphs += 1;
+        }
+    }
+}
+
+/// A font that rendered glyphs of a text area, see [`AreaFontUsage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsedFont {
+    /// The ID of the font in the database of the [`FontSystem`].
+    pub id: fontdb::ID,
+    /// The first family name of the font, or `None` if it is not in the database anymore.
+    pub family: Option<String>,
+    /// The PostScript name of the font, e.g. `Inter-Bold`.
+    pub post_script_name: Option<String>,
+    /// The path of the font file, or `None` if the font was loaded from memory.
+    pub path: Option<PathBuf>,
+    /// The number of glyphs of the area drawn with the font, including `.notdef` glyphs.
+    pub glyphs: usize,
+    /// The number of glyphs of the area that fell through to the `.notdef` glyph of the font.
+    pub notdef_glyphs: usize,
+}
+
+impl UsedFont {
+    fn new(id: fontdb::ID, font_system: &FontSystem) -> Self {
+        let face = font_system.db().face(id);
+
+        Self {
+            id,
+            family: face.and_then(|face| face.families.first().map(|(name, _)| name.clone())),
+            post_script_name: face.map(|face| face.post_script_name.clone()),
+            path: face.and_then(|face| match &face.source {
+                fontdb::Source::File(path) | fontdb::Source::SharedFile(path, _) => {
+                    Some(path.clone())
+                }
+                _ => None,
+            }),
+            glyphs: 0,
+            notdef_glyphs: 0,
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Checks that `prepare` and `render` of a renderer are called in a sensible order, see
+//! [`FrameValidation`].
+
+use std::cell::Cell;
+
+/// Which misuses of the frame sequence of a [`crate::TextRenderer`] panic, see
+/// [`crate::TextRenderer::set_frame_validation`].
+///
+/// The checks only run in builds with debug assertions, or with the `frame-validation` feature.
+/// Otherwise they are compiled out and these settings have no effect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameValidation {
+    /// Panic when rendering before anything was prepared, which draws nothing.
+    pub render_before_prepare: bool,
+    /// Panic when preparing again before the previously prepared text was rendered, whose
+    /// frame is then lost. Disabled by default, since frames are legitimately skipped (e.g.
+    /// while a window is occluded) and text is prepared without rendering to measure or warm it.
+    pub prepare_without_render: bool,
+    /// Panic when rendering after the atlas was trimmed and then prepared against again (e.g. by
+    /// another renderer) since the text was prepared, which may have overwritten the glyphs of
+    /// the text. Trimming alone does not, see [`crate::TextAtlas::trim`].
+    pub trim_before_render: bool,
+}
+
+impl Default for FrameValidation {
+    fn default() -> Self {
+        Self {
+            render_before_prepare: true,
+            prepare_without_render: false,
+            trim_before_render: true,
+        }
+    }
+}
+
+/// The frame sequence of a renderer as far as [`FrameValidation`] checks it.
+#[derive(Debug, Default)]
+pub(crate) struct FrameState {
+    pub validation: FrameValidation,
+    /// Whether anything was prepared yet.
+    prepared: bool,
+    /// Whether the prepared text was rendered at least once.
+    rendered: Cell<bool>,
+    /// The reuse epoch of the [`crate::TextAtlas`] the text was prepared in.
+    reuse_epoch: u64,
+}
+
+impl FrameState {
+    /// Checks that the previously prepared text was rendered, then records that text is prepared
+    /// in the atlas reuse epoch `reuse_epoch`.
+    ///
+    /// Text counts as prepared even if preparing fails, since what was prepared until then is
+    /// rendered.
+    pub fn prepare(&mut self, reuse_epoch: u64) {
+        #[cfg(any(debug_assertions, feature = "frame-validation"))]
+        assert!(
+            !self.validation.prepare_without_render || !self.prepared || self.rendered.get(),
+            "The previously prepared text was never rendered, render it before preparing again"
+        );
+
+        self.prepared = true;
+        self.rendered.set(false);
+        self.reuse_epoch = reuse_epoch;
+    }
+
+    /// Checks that text was prepared and the atlas, in the reuse epoch `reuse_epoch`, did not
+    /// reuse the space of released glyphs since, then records that the text was rendered.
+    pub fn render(&self, reuse_epoch: u64) {
+        #[cfg(any(debug_assertions, feature = "frame-validation"))]
+        {
+            assert!(
+                !self.validation.render_before_prepare || self.prepared,
+                "Nothing was prepared, call `prepare` before rendering"
+            );
+            assert!(
+                !self.validation.trim_before_render
+                    || !self.prepared
+                    || self.reuse_epoch == reuse_epoch,
+                "The atlas was trimmed and prepared against between `prepare` and `render`, trim \
+                 it only once every renderer prepared the frame"
+            );
+        }
+        #[cfg(not(any(debug_assertions, feature = "frame-validation")))]
+        let _ = reuse_epoch;
+
+        self.rendered.set(true);
+    }
+}
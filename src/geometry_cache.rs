@@ -0,0 +1,180 @@
+//! Vertices of text areas cached across frames, see [`crate::TextRenderer::set_geometry_cache`].
+
+use crate::{
+    glyph_allocator::Hasher, text_render::GlyphonCacheKey, ColorMode, ContentType, FaceStyle,
+    FontSynthesis, GlyphToRender, TargetColorSpace, TextArea, TextAtlas,
+};
+use cosmic_text::LayoutRun;
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, Hash, Hasher as _},
+    mem,
+};
+
+/// A glyph sampled by cached vertices: its key, the atlas it lies in and its generation, see
+/// [`crate::AtlasGlyph::generation`].
+pub(crate) type AreaGlyph = (GlyphonCacheKey, ContentType, u64);
+
+/// The vertices a text area produced, and the glyphs they sample.
+pub(crate) struct CachedGeometry {
+    /// See [`fingerprint`].
+    fingerprint: u64,
+    /// The integer part of the physical position of the text area when the vertices were
+    /// generated.
+    pub origin: [i32; 2],
+    pub vertices: Vec<GlyphToRender>,
+    pub glyphs: Vec<AreaGlyph>,
+    used: bool,
+}
+
+/// The geometry of the text areas of the last frames, keyed by the address of their buffer.
+///
+/// The address only locates the entry of a buffer. An entry is only reused if the fingerprint
+/// of the text area matches, so a buffer that was dropped and replaced at the same address is
+/// never mistaken for the old one.
+#[derive(Default)]
+pub(crate) struct GeometryCache {
+    entries: HashMap<usize, CachedGeometry, Hasher>,
+}
+
+impl GeometryCache {
+    /// Returns the geometry cached for `buffer`, if it was generated from the same inputs and
+    /// each of its glyphs is still where it was in `atlas`, i.e. has the same generation.
+    ///
+    /// Vertices address the atlas in texels, so growing the atlas does not invalidate them.
+    pub fn get(
+        &mut self,
+        buffer: usize,
+        fingerprint: u64,
+        atlas: &TextAtlas,
+    ) -> Option<&CachedGeometry> {
+        let entry = self.entries.get_mut(&buffer)?;
+
+        if entry.fingerprint != fingerprint {
+            return None;
+        }
+
+        let current = entry.glyphs.iter().all(|(key, content_type, generation)| {
+            atlas.glyph_generation(key, *content_type) == Some(*generation)
+        });
+        if !current {
+            return None;
+        }
+
+        entry.used = true;
+        Some(entry)
+    }
+
+    /// Caches the geometry of `buffer`, reusing the allocations of its previous entry.
+    pub fn insert(
+        &mut self,
+        buffer: usize,
+        fingerprint: u64,
+        origin: [i32; 2],
+        vertices: &[GlyphToRender],
+        glyphs: &[AreaGlyph],
+    ) {
+        let entry = self
+            .entries
+            .entry(buffer)
+            .or_insert_with(|| CachedGeometry {
+                fingerprint,
+                origin,
+                vertices: Vec::new(),
+                glyphs: Vec::new(),
+                used: true,
+            });
+
+        entry.fingerprint = fingerprint;
+        entry.origin = origin;
+        entry.vertices.clear();
+        entry.vertices.extend_from_slice(vertices);
+        entry.glyphs.clear();
+        entry.glyphs.extend_from_slice(glyphs);
+        entry.used = true;
+    }
+
+    /// Drops the entries of the buffers that were not prepared since the last call.
+    pub fn retain_used(&mut self) {
+        self.entries
+            .retain(|_, entry| mem::replace(&mut entry.used, false));
+    }
+
+    /// Drops every entry.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns an estimate of the memory used by the cached geometry.
+    pub fn memory_bytes(&self) -> usize {
+        self.entries.capacity() * mem::size_of::<(usize, CachedGeometry)>()
+            + self
+                .entries
+                .values()
+                .map(|entry| {
+                    entry.vertices.capacity() * mem::size_of::<GlyphToRender>()
+                        + entry.glyphs.capacity() * mem::size_of::<AreaGlyph>()
+                })
+                .sum::<usize>()
+    }
+}
+
+/// Hashes everything the vertices of a text area are derived from, except for the integer part
+/// of its physical position, which only translates them.
+///
+/// `offset` is the fractional part of the physical position, `clip` the effective bounds
+/// relative to its integer part and `runs` the visible layout runs.
+pub(crate) fn fingerprint<'a>(
+    text_area: &TextArea,
+    runs: impl Iterator<Item = LayoutRun<'a>>,
+    offset: (f32, f32),
+    scale: f32,
+    clip: [i32; 4],
+    color_mode: ColorMode,
+    color_space: TargetColorSpace,
+) -> u64 {
+    let mut hasher = Hasher::default().build_hasher();
+
+    offset.0.to_bits().hash(&mut hasher);
+    offset.1.to_bits().hash(&mut hasher);
+    scale.to_bits().hash(&mut hasher);
+    clip.hash(&mut hasher);
+    color_mode.hash(&mut hasher);
+    color_space.hash(&mut hasher);
+    text_area.default_color.hash(&mut hasher);
+    text_area.font_synthesis.hash(&mut hasher);
+    text_area.background_hint.hash(&mut hasher);
+    // Synthesis depends on the requested faces, which change without changing the matched font
+    // of a font without the requested face
+    let requested_faces = text_area.font_synthesis != FontSynthesis::None;
+
+    for glyph in text_area.custom_glyphs {
+        glyph.id.hash(&mut hasher);
+        glyph.left.to_bits().hash(&mut hasher);
+        glyph.top.to_bits().hash(&mut hasher);
+        glyph.width.to_bits().hash(&mut hasher);
+        glyph.height.to_bits().hash(&mut hasher);
+        glyph.color.hash(&mut hasher);
+        glyph.snap_to_physical_pixel.hash(&mut hasher);
+        glyph.metadata.hash(&mut hasher);
+    }
+
+    for run in runs {
+        run.line_y.to_bits().hash(&mut hasher);
+
+        for glyph in run.glyphs {
+            let physical_glyph = glyph.physical(offset, scale);
+
+            physical_glyph.cache_key.hash(&mut hasher);
+            physical_glyph.x.hash(&mut hasher);
+            physical_glyph.y.hash(&mut hasher);
+            glyph.color_opt.hash(&mut hasher);
+            glyph.metadata.hash(&mut hasher);
+            if requested_faces {
+                FaceStyle::requested(text_area.buffer, run.line_i, glyph).hash(&mut hasher);
+            }
+        }
+    }
+
+    hasher.finish()
+}
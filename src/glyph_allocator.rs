@@ -0,0 +1,587 @@
+use crate::{
+    packing::{AtlasPacking, Packer},
+    text_render::GlyphonCacheKey,
+    GlyphDetails, TrimPolicy,
+};
+use etagere::{size2, AllocId, Allocation};
+use lru::LruCache;
+use rustc_hash::FxHasher;
+use std::{
+    collections::{HashMap, HashSet},
+    hash::BuildHasherDefault,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+pub(crate) type Hasher = BuildHasherDefault<FxHasher>;
+
+/// Returns a generation that no cached glyph of any allocator had before, see
+/// [`crate::AtlasGlyph::generation`].
+pub(crate) fn next_generation() -> u64 {
+    static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+    NEXT_GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The outcome of [`GlyphAllocator::allocate`].
+pub(crate) enum AllocationStep {
+    /// The glyph fits, possibly after evicting glyphs.
+    Allocated(Allocation),
+    /// The glyph does not fit until the atlas grows to this size.
+    Grow(u32),
+    /// The glyph does not fit in the atlas at its maximum size, even after evicting every glyph
+    /// that is not in use.
+    Full,
+}
+
+/// The packing and eviction bookkeeping of a glyph atlas.
+///
+/// This does not own the atlas texture, so it can be driven without a Metal device. The texture
+/// operations of [`crate::text_atlas::InnerAtlas`] are a thin layer on top of it.
+pub(crate) struct GlyphAllocator {
+    pub packer: Box<dyn Packer>,
+    pub packing: AtlasPacking,
+    pub size: u32,
+    pub glyph_cache: LruCache<GlyphonCacheKey, GlyphDetails, Hasher>,
+    pub glyphs_in_use: HashSet<GlyphonCacheKey, Hasher>,
+    /// The glyphs of areas with [`crate::CachePriority::Pinned`], which are only evicted when a
+    /// glyph would not fit otherwise.
+    pub pinned: HashSet<GlyphonCacheKey, Hasher>,
+    /// The trim at which each glyph kept by [`TrimPolicy::KeepFrames`] was last in use.
+    pub last_used: HashMap<GlyphonCacheKey, u64, Hasher>,
+    /// The number of trims with [`TrimPolicy::KeepFrames`], which ages the glyphs of
+    /// `last_used`.
+    trims: u64,
+    /// The glyphs evicted since the owner of the allocator last took them to count them.
+    pub evicted: Vec<GlyphonCacheKey>,
+    /// The number of times the atlas grew.
+    pub grows: u32,
+    /// The size the atlas stops growing at.
+    pub max_size: u32,
+}
+
+impl GlyphAllocator {
+    pub const MAX_SIZE: u32 = 16384;
+
+    pub fn new(size: u32, packing: AtlasPacking) -> Self {
+        Self {
+            packer: packing.new_packer(size2(size as i32, size as i32)),
+            packing,
+            size,
+            glyph_cache: LruCache::unbounded_with_hasher(Hasher::default()),
+            glyphs_in_use: HashSet::with_hasher(Hasher::default()),
+            pinned: HashSet::with_hasher(Hasher::default()),
+            last_used: HashMap::with_hasher(Hasher::default()),
+            trims: 0,
+            evicted: Vec::new(),
+            grows: 0,
+            max_size: Self::MAX_SIZE,
+        }
+    }
+
+    /// Allocates a `width` x `height` rectangle the way `prepare` does: evicting least recently
+    /// used glyphs that are neither in use nor pinned, then growing the atlas, and once it has
+    /// reached its maximum size, evicting pinned glyphs that are not in use.
+    ///
+    /// The caller grows the atlas on [`AllocationStep::Grow`] and allocates again.
+    pub fn allocate(&mut self, width: usize, height: usize) -> AllocationStep {
+        if let Some(allocation) = self.try_allocate(width, height, false) {
+            return AllocationStep::Allocated(allocation);
+        }
+
+        if let Some(new_size) = self.next_size() {
+            return AllocationStep::Grow(new_size);
+        }
+
+        // Pinned glyphs are only evicted when the glyph would not fit otherwise
+        match self.try_allocate(width, height, true) {
+            Some(allocation) => AllocationStep::Allocated(allocation),
+            None => AllocationStep::Full,
+        }
+    }
+
+    /// Allocates a `width` x `height` rectangle, evicting least recently used glyphs that are not
+    /// in use until it fits. Returns `None` if it does not fit without evicting glyphs in use.
+    ///
+    /// Pinned glyphs are skipped, unless `evict_pinned` is set because the atlas cannot grow.
+    fn try_allocate(
+        &mut self,
+        width: usize,
+        height: usize,
+        evict_pinned: bool,
+    ) -> Option<Allocation> {
+        let size = size2(width as i32, height as i32);
+
+        loop {
+            let allocation = self.packer.allocate(size);
+
+            if allocation.is_some() {
+                return allocation;
+            }
+
+            // Try to free least recently used allocation, evicting glyphs without an actual size
+            // on the way
+            loop {
+                let (key, atlas_id) = self.least_recently_used(evict_pinned)?;
+
+                // All sized glyphs are in use, cache is full
+                if self.glyphs_in_use.contains(&key) {
+                    return None;
+                }
+
+                self.evict(&key);
+                if let Some(atlas_id) = atlas_id {
+                    self.packer.deallocate(atlas_id);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Returns the least recently used glyph, skipping pinned glyphs unless `include_pinned` is
+    /// set, and its allocation.
+    fn least_recently_used(
+        &self,
+        include_pinned: bool,
+    ) -> Option<(GlyphonCacheKey, Option<AllocId>)> {
+        self.glyph_cache
+            .iter()
+            .rev()
+            .find(|(key, _)| include_pinned || !self.pinned.contains(key))
+            .map(|(key, details)| (*key, details.atlas_id))
+    }
+
+    /// Removes `key` from the cache, without freeing its allocation.
+    fn evict(&mut self, key: &GlyphonCacheKey) {
+        self.glyph_cache.pop(key);
+        self.pinned.remove(key);
+        self.evicted.push(*key);
+    }
+
+    /// Returns the size the atlas should grow to, or `None` if it is already at its maximum.
+    pub fn next_size(&self) -> Option<u32> {
+        if self.size >= self.max_size {
+            return None;
+        }
+
+        // Grow each dimension by a factor of 2. The growth factor was chosen to match the growth
+        // factor of `Vec`.
+        const GROWTH_FACTOR: u32 = 2;
+
+        Some((self.size * GROWTH_FACTOR).min(self.max_size))
+    }
+
+    /// Grows the packer to `new_size`, keeping all existing allocations in place.
+    pub fn grow(&mut self, new_size: u32) {
+        self.packer.grow(size2(new_size as i32, new_size as i32));
+        self.size = new_size;
+        self.grows += 1;
+    }
+
+    /// Returns the area of the glyphs in the atlas, in pixels.
+    pub fn glyph_area(&self) -> u64 {
+        self.glyph_cache
+            .iter()
+            .filter(|(_, details)| details.atlas_id.is_some())
+            .map(|(_, details)| details.width as u64 * details.height as u64)
+            .sum()
+    }
+
+    /// Returns the area of the pinned glyphs in the atlas, in pixels.
+    pub fn pinned_area(&self) -> u64 {
+        self.pinned
+            .iter()
+            .filter_map(|key| self.glyph_cache.peek(key))
+            .filter(|details| details.atlas_id.is_some())
+            .map(|details| details.width as u64 * details.height as u64)
+            .sum()
+    }
+
+    /// Marks glyphs as no longer in use as `policy` decides, evicting glyphs over its byte
+    /// budget with `bytes_per_pixel` texel bytes.
+    pub fn trim(&mut self, policy: TrimPolicy, bytes_per_pixel: usize) {
+        if !matches!(policy, TrimPolicy::KeepFrames(_)) {
+            self.last_used.clear();
+        }
+
+        match policy {
+            TrimPolicy::EveryFrame => self.glyphs_in_use.clear(),
+            TrimPolicy::KeepFrames(frames) => {
+                self.trims += 1;
+                for key in self.glyphs_in_use.drain() {
+                    self.last_used.insert(key, self.trims);
+                }
+
+                let oldest = self.trims.saturating_sub(frames as u64);
+                self.last_used.retain(|_, trim| *trim > oldest);
+                self.glyphs_in_use.extend(self.last_used.keys().copied());
+            }
+            TrimPolicy::ByteBudget(budget) => {
+                self.glyphs_in_use.clear();
+
+                // Pinned glyphs are kept even over the budget
+                let mut bytes = self.glyph_area() * bytes_per_pixel as u64;
+                while bytes > budget as u64 {
+                    let Some((key, atlas_id)) = self.least_recently_used(false) else {
+                        break;
+                    };
+
+                    if let Some(atlas_id) = atlas_id {
+                        let details = self.glyph_cache.peek(&key).unwrap();
+                        bytes -=
+                            details.width as u64 * details.height as u64 * bytes_per_pixel as u64;
+                        self.packer.deallocate(atlas_id);
+                    }
+                    self.evict(&key);
+                }
+            }
+            TrimPolicy::Manual => {}
+        }
+    }
+}
+
+#[cfg(feature = "atlas-invariants")]
+impl GlyphAllocator {
+    /// Panics if the bookkeeping is inconsistent.
+    pub fn check_invariants(&self) {
+        let mut rectangles = Vec::new();
+
+        for (key, details) in self.glyph_cache.iter() {
+            let Some(id) = details.atlas_id else {
+                continue;
+            };
+
+            // Every sized glyph maps to a live allocation inside of the atlas
+            let rectangle = self.packer.get(id);
+            assert!(
+                rectangle.min.x >= 0
+                    && rectangle.min.y >= 0
+                    && rectangle.max.x <= self.size as i32
+                    && rectangle.max.y <= self.size as i32,
+                "Allocation {rectangle:?} of {key:?} is outside of the {}px atlas",
+                self.size
+            );
+            assert!(
+                rectangle.width() >= details.width as i32
+                    && rectangle.height() >= details.height as i32,
+                "Allocation {rectangle:?} of {key:?} is smaller than the glyph"
+            );
+
+            rectangles.push(rectangle);
+        }
+
+        // No two live allocations overlap
+        for (i, a) in rectangles.iter().enumerate() {
+            for b in &rectangles[i + 1..] {
+                assert!(!a.intersects(b), "Allocations {a:?} and {b:?} overlap");
+            }
+        }
+
+        // Glyphs in use are never evicted
+        for key in &self.glyphs_in_use {
+            assert!(
+                self.glyph_cache.contains(key),
+                "Glyph {key:?} was evicted while in use"
+            );
+        }
+
+        // Evicted glyphs are no longer pinned
+        for key in &self.pinned {
+            assert!(
+                self.glyph_cache.contains(key),
+                "Glyph {key:?} is pinned but not cached"
+            );
+        }
+
+        let area = self.size as i64 * self.size as i64;
+        assert!(
+            self.packer.allocated_space() as i64 <= area,
+            "Allocated space exceeds the atlas area"
+        );
+    }
+}
+
+/// A seeded fuzzer driving the allocate, evict, grow and trim cycle of [`GlyphAllocator`]
+/// the same way `prepare` does, checking its invariants after every step.
+#[cfg(feature = "atlas-invariants")]
+#[doc(hidden)]
+pub mod fuzz {
+    use super::{next_generation, AllocationStep, GlyphAllocator};
+    use crate::{
+        custom_glyph::CustomGlyphCacheKey, text_render::GlyphonCacheKey, AtlasPacking,
+        BitmapStrikePolicy, ContentType, GlyphDetails, GpuCacheStatus, TrimPolicy,
+    };
+    use cosmic_text::SubpixelBin;
+
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            // xorshift64*
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    /// Runs `steps` random frames seeded with `seed`, starting from a `initial_size` atlas.
+    pub fn run(seed: u64, steps: usize, initial_size: u32) {
+        run_with_packing(seed, steps, initial_size, AtlasPacking::default());
+    }
+
+    /// Like [`run`], packing glyphs with the given [`AtlasPacking`].
+    pub fn run_with_packing(seed: u64, steps: usize, initial_size: u32, packing: AtlasPacking) {
+        let mut rng = Rng(seed.max(1));
+        let mut allocator = GlyphAllocator::new(initial_size, packing);
+
+        for _ in 0..steps {
+            // A frame uses a random set of glyphs out of a larger vocabulary
+            let glyph_count = 1 + rng.below(64);
+
+            for _ in 0..glyph_count {
+                let id = rng.below(512) as u16;
+                // Sizes are derived from the id, like the rasterization of a glyph
+                let width = ((id as u64 * 7919) % 48) as u16;
+                let height = ((id as u64 * 104_729) % 48) as u16;
+                let key = GlyphonCacheKey::Custom(CustomGlyphCacheKey {
+                    glyph_id: id,
+                    width,
+                    height,
+                    x_bin: SubpixelBin::Zero,
+                    y_bin: SubpixelBin::Zero,
+                });
+
+                // A few glyphs belong to pinned areas
+                let pinned = id % 32 == 0;
+
+                if allocator.glyph_cache.get(&key).is_some() {
+                    allocator.glyphs_in_use.insert(key);
+                    if pinned {
+                        allocator.pinned.insert(key);
+                    }
+                    continue;
+                }
+
+                let (gpu_cache, atlas_id) = if width > 0 && height > 0 {
+                    let allocation = loop {
+                        match allocator.allocate(width as usize, height as usize) {
+                            AllocationStep::Allocated(allocation) => break Some(allocation),
+                            AllocationStep::Grow(new_size) => allocator.grow(new_size),
+                            AllocationStep::Full => break None,
+                        }
+
+                        allocator.check_invariants();
+                    };
+
+                    // The atlas is full, like `PrepareError::AtlasFull`
+                    let Some(allocation) = allocation else {
+                        break;
+                    };
+
+                    (
+                        GpuCacheStatus::InAtlas {
+                            x: allocation.rectangle.min.x as u16,
+                            y: allocation.rectangle.min.y as u16,
+                            content_type: ContentType::Mask,
+                        },
+                        Some(allocation.id),
+                    )
+                } else {
+                    (GpuCacheStatus::SkipRasterization, None)
+                };
+
+                allocator.glyphs_in_use.insert(key);
+                if pinned {
+                    allocator.pinned.insert(key);
+                }
+                allocator.glyph_cache.put(
+                    key,
+                    GlyphDetails {
+                        width,
+                        height,
+                        gpu_cache,
+                        atlas_id,
+                        top: 0,
+                        left: 0,
+                        strike_policy: BitmapStrikePolicy::default(),
+                        generation: next_generation(),
+                    },
+                );
+
+                allocator.check_invariants();
+            }
+
+            allocator.trim(TrimPolicy::EveryFrame, 1);
+            allocator.check_invariants();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_generation, AllocationStep, GlyphAllocator};
+    use crate::{
+        custom_glyph::CustomGlyphCacheKey, text_render::GlyphonCacheKey, AtlasPacking,
+        BitmapStrikePolicy, ContentType, GlyphDetails, GpuCacheStatus, TrimPolicy,
+    };
+    use cosmic_text::SubpixelBin;
+
+    const GLYPH_SIZE: u16 = 16;
+
+    fn key(id: u16) -> GlyphonCacheKey {
+        GlyphonCacheKey::Custom(CustomGlyphCacheKey {
+            glyph_id: id,
+            width: GLYPH_SIZE,
+            height: GLYPH_SIZE,
+            x_bin: SubpixelBin::Zero,
+            y_bin: SubpixelBin::Zero,
+        })
+    }
+
+    /// Allocates and caches glyph `id` in use, the way `prepare` does after rasterizing it.
+    /// Returns the step that did not allocate it otherwise.
+    fn cache(allocator: &mut GlyphAllocator, id: u16) -> Result<(), AllocationStep> {
+        let step = allocator.allocate(GLYPH_SIZE.into(), GLYPH_SIZE.into());
+        let AllocationStep::Allocated(allocation) = step else {
+            return Err(step);
+        };
+
+        allocator.glyph_cache.put(
+            key(id),
+            GlyphDetails {
+                width: GLYPH_SIZE,
+                height: GLYPH_SIZE,
+                gpu_cache: GpuCacheStatus::InAtlas {
+                    x: allocation.rectangle.min.x as u16,
+                    y: allocation.rectangle.min.y as u16,
+                    content_type: ContentType::Mask,
+                },
+                atlas_id: Some(allocation.id),
+                top: 0,
+                left: 0,
+                strike_policy: BitmapStrikePolicy::default(),
+                generation: next_generation(),
+            },
+        );
+        allocator.glyphs_in_use.insert(key(id));
+
+        Ok(())
+    }
+
+    /// Caches glyphs with the ids from `first` on until one does not fit, returning how many
+    /// fit and the step of the one that did not.
+    fn fill(allocator: &mut GlyphAllocator, first: u16) -> (u16, AllocationStep) {
+        let mut id = first;
+        loop {
+            if let Err(step) = cache(allocator, id) {
+                return (id - first, step);
+            }
+            id += 1;
+        }
+    }
+
+    /// Looks up glyph `id` and marks it as in use, the way `prepare` does for cached glyphs.
+    fn use_glyph(allocator: &mut GlyphAllocator, id: u16) {
+        assert!(allocator.glyph_cache.get(&key(id)).is_some());
+        allocator.glyphs_in_use.insert(key(id));
+    }
+
+    /// Returns a full allocator that cannot grow, and the number of glyphs in it.
+    ///
+    /// Its guillotine packer reuses the space of every evicted glyph, while a bucketed packer
+    /// only frees a bucket once all of its glyphs are evicted.
+    fn full_allocator() -> (GlyphAllocator, u16) {
+        let mut allocator = GlyphAllocator::new(64, AtlasPacking::Guillotine);
+        allocator.max_size = 64;
+
+        let (count, step) = fill(&mut allocator, 0);
+        assert!(matches!(step, AllocationStep::Full));
+        assert!(count >= 2);
+
+        (allocator, count)
+    }
+
+    #[test]
+    fn trim_evicts_only_unused_glyphs() {
+        let (mut allocator, count) = full_allocator();
+
+        allocator.trim(TrimPolicy::EveryFrame, 1);
+        assert!(allocator.glyphs_in_use.is_empty());
+
+        // The next frame uses every other glyph
+        let used = (0..count).step_by(2);
+        for id in used.clone() {
+            use_glyph(&mut allocator, id);
+        }
+
+        // A budget of the glyphs in use keeps exactly those
+        let glyph_bytes = usize::from(GLYPH_SIZE * GLYPH_SIZE);
+        allocator.trim(TrimPolicy::ByteBudget(used.len() * glyph_bytes), 1);
+
+        for id in 0..count {
+            assert_eq!(
+                allocator.glyph_cache.contains(&key(id)),
+                id % 2 == 0,
+                "glyph {id}"
+            );
+        }
+        assert_eq!(allocator.evicted.len(), usize::from(count / 2));
+    }
+
+    #[test]
+    fn allocation_evicts_only_unused_glyphs() {
+        let (mut allocator, count) = full_allocator();
+        allocator.trim(TrimPolicy::EveryFrame, 1);
+
+        let used = (0..count).step_by(2);
+        for id in used.clone() {
+            use_glyph(&mut allocator, id);
+        }
+
+        // New glyphs take the place of the unused ones, and then the atlas is full
+        let (added, step) = fill(&mut allocator, count);
+        assert!(matches!(step, AllocationStep::Full));
+        assert_eq!(usize::from(added), usize::from(count) - used.len());
+
+        for id in 0..count {
+            assert_eq!(
+                allocator.glyph_cache.contains(&key(id)),
+                id % 2 == 0,
+                "glyph {id}"
+            );
+        }
+    }
+
+    #[test]
+    fn atlas_grows_before_pinned_glyphs_are_evicted() {
+        let mut allocator = GlyphAllocator::new(32, AtlasPacking::default());
+        allocator.max_size = 64;
+
+        // Glyphs in use are never evicted, so the atlas has to grow
+        let (count, step) = fill(&mut allocator, 0);
+        assert!(count > 0);
+        assert!(matches!(step, AllocationStep::Grow(64)));
+
+        // Unused pinned glyphs are not evicted either while the atlas can grow
+        allocator.trim(TrimPolicy::EveryFrame, 1);
+        allocator.pinned.extend((0..count).map(key));
+        assert!(matches!(
+            allocator.allocate(GLYPH_SIZE.into(), GLYPH_SIZE.into()),
+            AllocationStep::Grow(64)
+        ));
+        assert!(allocator.evicted.is_empty());
+
+        // At its maximum size, the atlas evicts pinned glyphs that are not in use, until only
+        // glyphs in use are left
+        allocator.grow(64);
+        let (_, step) = fill(&mut allocator, count);
+        assert!(matches!(step, AllocationStep::Full));
+        assert!((0..count).all(|id| !allocator.glyph_cache.contains(&key(id))));
+        assert!(allocator.pinned.is_empty());
+        assert_eq!(allocator.evicted.len(), usize::from(count));
+    }
+}
@@ -0,0 +1,118 @@
+//! Unsafe constructors and accessors taking raw Objective-C pointers, for renderers built on
+//! other Metal bindings such as the [`metal`](https://docs.rs/metal) crate.
+//!
+//! Every binding wraps the same Objective-C objects, so a pointer obtained from one of them (e.g.
+//! `metal::Device::as_ptr`) can be passed to the functions of this module, cast to `*mut c_void`.
+//!
+//! # Ownership
+//!
+//! Pointers are borrowed: the caller keeps its own reference and releases it as it would
+//! otherwise. metalglyph retains every object it keeps (e.g. the device of a [`Cache`]) and
+//! releases it when dropped, so the caller may release its reference right after the call.
+//! Objects only used for the duration of a call (e.g. the encoder of
+//! [`TextRenderer::render_raw`]) are neither retained nor released.
+//!
+//! Pointers returned by accessors are borrowed as well. They are valid as long as the object
+//! they were obtained from is alive and unchanged, and must be retained by the caller to be kept
+//! any longer.
+//!
+//! All functions panic if given a null pointer.
+
+use crate::{Cache, ContentType, RenderStats, TextAtlas, TextRenderer, Viewport};
+use objc2::{rc::Retained, runtime::ProtocolObject, Message as _};
+use objc2_metal::{MTLDevice, MTLPixelFormat, MTLRenderCommandEncoder};
+use std::ffi::c_void;
+
+/// Borrows `device`, an `id<MTLDevice>`, as the type expected by the rest of the crate (e.g.
+/// [`TextRenderer::builder`]), without retaining it.
+///
+/// # Safety
+///
+/// `device` must be a valid pointer to an object conforming to `MTLDevice` that outlives `'a`.
+pub unsafe fn device_ref<'a>(device: *mut c_void) -> &'a ProtocolObject<dyn MTLDevice> {
+    assert!(!device.is_null(), "Device pointer is null");
+    unsafe { &*(device as *const ProtocolObject<dyn MTLDevice>) }
+}
+
+/// Retains `device`, an `id<MTLDevice>`, to keep it beyond the lifetime of the reference of the
+/// caller.
+///
+/// The returned reference is released when dropped. The reference of the caller is not consumed.
+///
+/// # Safety
+///
+/// `device` must be a valid pointer to an object conforming to `MTLDevice`.
+pub unsafe fn device_from_raw(device: *mut c_void) -> Retained<ProtocolObject<dyn MTLDevice>> {
+    unsafe { device_ref(device) }.retain()
+}
+
+impl Cache {
+    /// Creates a new [`Cache`] from `device`, an `id<MTLDevice>`.
+    ///
+    /// The device is retained by the cache for as long as it is alive.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be a valid pointer to an object conforming to `MTLDevice`.
+    pub unsafe fn from_raw_device(device: *mut c_void) -> Self {
+        Self::new(unsafe { device_ref(device) })
+    }
+}
+
+impl TextAtlas {
+    /// Creates a new [`TextAtlas`] from `device`, an `id<MTLDevice>`, see [`TextAtlas::new`].
+    ///
+    /// A `metal::MTLPixelFormat` converts to `format` with `MTLPixelFormat(format as usize)`. The
+    /// device is not retained beyond the creation of the atlas textures.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be a valid pointer to an object conforming to `MTLDevice`.
+    pub unsafe fn from_raw(device: *mut c_void, cache: &Cache, format: MTLPixelFormat) -> Self {
+        Self::new(unsafe { device_ref(device) }, cache, format)
+    }
+
+    /// Returns the `id<MTLTexture>` holding the glyphs of `content_type`, without retaining it.
+    ///
+    /// The texture is replaced when the atlas grows, so the pointer must not be kept across calls
+    /// to [`TextRenderer::prepare`] without retaining it.
+    pub fn raw_texture(&self, content_type: ContentType) -> *mut c_void {
+        Retained::as_ptr(&self.inner_for_content(content_type).texture) as *mut c_void
+    }
+}
+
+impl Viewport {
+    /// Creates a new [`Viewport`] from `device`, an `id<MTLDevice>`.
+    ///
+    /// The device is not retained beyond the creation of the parameter buffer.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be a valid pointer to an object conforming to `MTLDevice`.
+    pub unsafe fn from_raw_device(device: *mut c_void) -> Self {
+        Self::new(unsafe { device_ref(device) })
+    }
+}
+
+impl TextRenderer {
+    /// Renders all layouts that were previously provided to `prepare` into `encoder`, an
+    /// `id<MTLRenderCommandEncoder>` (e.g. from `metal::RenderCommandEncoderRef::as_ptr`).
+    ///
+    /// The encoder is only borrowed for the duration of the call and is neither retained nor
+    /// released. Returns what was encoded, like [`TextRenderer::render`].
+    ///
+    /// # Safety
+    ///
+    /// `encoder` must be a valid pointer to an object conforming to `MTLRenderCommandEncoder`
+    /// that has not ended encoding.
+    pub unsafe fn render_raw(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        encoder: *mut c_void,
+    ) -> RenderStats {
+        assert!(!encoder.is_null(), "Encoder pointer is null");
+        let encoder = unsafe { &*(encoder as *const ProtocolObject<dyn MTLRenderCommandEncoder>) };
+        self.render(atlas, viewport, encoder)
+    }
+}
@@ -4,21 +4,104 @@
 //! [cosmic-text]: https://github.com/pop-os/cosmic-text
 //! [etagere]: https://github.com/nical/etagere
 
+mod animation;
+mod batch;
 mod cache;
+#[cfg(feature = "serde")]
+pub mod color_serde;
 mod custom_glyph;
+mod decoration;
+mod dedup;
+mod dropped;
+#[cfg(feature = "embedded-fonts")]
+pub mod embedded_fonts;
+mod encoder;
 mod error;
+mod fit;
+mod font_system;
+mod font_usage;
+mod frame_state;
+mod geometry_cache;
+mod glyph_allocator;
+#[cfg(feature = "atlas-invariants")]
+#[doc(hidden)]
+pub use glyph_allocator::fuzz;
+#[cfg(feature = "ffi-interop")]
+pub mod interop;
+mod measure;
+mod memory;
+mod offscreen;
+mod overdraw;
+mod packing;
+mod path_text;
+mod pixel_format;
+mod profile;
+mod rasterize;
+#[cfg(feature = "serde")]
+mod serde_defs;
+#[cfg(feature = "signposts")]
+mod signpost;
+#[cfg(feature = "debug-tools")]
+mod snapshot;
+mod stereo;
+mod supersample;
+mod synthesis;
 mod text_atlas;
 mod text_render;
+mod trim;
+mod truncate;
+mod upload;
 mod viewport;
 
-pub use cache::Cache;
+pub use animation::{GlyphAnimContext, GlyphTransform};
+pub use batch::{BatchedTextArea, TextAreaBatch, TextAreaBatchIter};
+pub use cache::{AlphaMode, BlendMode, Cache, SingleChannelOutput};
 pub use custom_glyph::{
     ContentType, CustomGlyph, CustomGlyphId, RasterizeCustomGlyphRequest, RasterizedCustomGlyph,
 };
-pub use error::{PrepareError, RenderError};
-pub use text_atlas::{ColorMode, TextAtlas};
-pub use text_render::TextRenderer;
-pub use viewport::Viewport;
+pub use decoration::{range_rects, RangeRect};
+pub use dedup::DuplicateAreas;
+pub use dropped::{DropReason, DroppedGlyph, DroppedGlyphs};
+#[cfg(feature = "embedded-fonts")]
+pub use embedded_fonts::test_font_system;
+pub use encoder::{mtl4_available, TextBindings, TextRenderEncoder};
+#[cfg(feature = "dev-tools")]
+pub use error::CacheError;
+#[cfg(feature = "debug-tools")]
+pub use error::SnapshotError;
+pub use error::{BuildError, PrepareError, RenderError, SlotError, UnsupportedFormatReason};
+pub use fit::{fit_text, FittedText};
+pub use font_system::{FontSystemAccess, SharedFontSystem};
+pub use font_usage::{AreaFontUsage, UsedFont};
+pub use frame_state::FrameValidation;
+pub use measure::{measure, measure_lines, TextSize};
+pub use memory::MemoryUsage;
+#[cfg(feature = "readback")]
+pub use offscreen::Pixels;
+pub use offscreen::{render_to_texture, OffscreenRenderer};
+pub use overdraw::{AreaOverdraw, Overdraw};
+pub use packing::AtlasPacking;
+pub use path_text::{PathOverflow, PathTextArea, TextPath};
+#[cfg(feature = "profiling")]
+pub use profile::FrameProfile;
+pub use profile::PreparePhaseTimings;
+pub use rasterize::{BitmapStrikePolicy, GlyphRasterConfig};
+#[cfg(feature = "debug-tools")]
+pub use snapshot::{Snapshot, SnapshotAtlasPixels, SnapshotGlyph, SnapshotParams, SnapshotQuad};
+pub use stereo::StereoPath;
+pub use supersample::Supersampler;
+pub use synthesis::{FaceMismatch, FaceStyle, FontSynthesis};
+pub use text_atlas::{
+    AtlasGlyph, AtlasStats, CachedGlyph, ColorAtlasFormat, ColorMode, SharedTextAtlas,
+    TargetColorSpace, TextAtlas, TextAtlasBuilder,
+};
+pub use text_render::{
+    AtlasKey, GlyphKey, PrepareProgress, PrewarmStats, RenderStats, TextRenderer,
+    TextRendererBuilder,
+};
+pub use trim::{CachePriority, TrimPolicy};
+pub use truncate::truncate_lines;
+pub use viewport::{EncoderViewport, ViewTransform, Viewport};
 
 // Re-export all top-level types from `cosmic-text` for convenience.
 #[doc(no_inline)]
@@ -31,6 +114,16 @@ pub use cosmic_text::{
 };
 
 use etagere::AllocId;
+use objc2::rc::Retained;
+use objc2_foundation::NSString;
+
+/// The prefix of the labels of all Metal resources created by the crate, unless overridden.
+pub(crate) const DEFAULT_LABEL: &str = "Metalglyph";
+
+/// Builds the label of a Metal resource from the label prefix of its owner.
+pub(crate) fn resource_label(label: &str, resource: &str) -> Retained<NSString> {
+    NSString::from_str(&format!("{label} - {resource}"))
+}
 
 pub(crate) enum GpuCacheStatus {
     InAtlas {
@@ -48,8 +141,29 @@ pub(crate) struct GlyphDetails {
     atlas_id: Option<AllocId>,
     top: i16,
     left: i16,
+    /// The policy the glyph was rasterized with, to rasterize it the same way when the atlas
+    /// grows.
+    strike_policy: BitmapStrikePolicy,
+    /// Unique to this allocation of the glyph, see [`AtlasGlyph::generation`].
+    generation: u64,
 }
 
+/// A glyph quad of the most recent `prepare`, written into the vertex buffer as a
+/// [`GlyphVertex`]. The `transform` of every quad follows the glyphs only if one of them is
+/// transformed, so that untransformed text does not upload them.
+///
+/// Sizes and atlas coordinates are below 16384, so the top bit of each of their 16-bit fields
+/// holds a flag instead: the content type in the width, the color conversion in the height, the
+/// Display P3 flag in the atlas x coordinate and whether the color atlas is an sRGB texture in the
+/// atlas y coordinate. The bit below is left to the background hint of the glyph in the vertex
+/// buffer.
+///
+/// `transform` holds four half floats: the scale of the quad around its center times the cosine
+/// and the sine of its rotation, and the fraction of a pixel the quad is moved by.
+///
+/// `background` holds the [`TextArea::background_hint`] of the area of the glyph: the
+/// sRGB-encoded luminance of the hint in the low 8 bits, and bit 8 set, or `0` without a hint.
+/// The vertex buffer holds the hints once, and each glyph the index of its hint.
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub(crate) struct GlyphToRender {
@@ -57,13 +171,30 @@ pub(crate) struct GlyphToRender {
     dim: [u16; 2],
     uv: [u16; 2],
     color: u32,
-    content_type_with_srgb: [u16; 2],
+    depth: f32,
+    transform: [u16; 4],
+    background: u32,
+}
+
+/// The 24 bytes of GPU data of a glyph, read by `vertex_main`, see [`GlyphToRender`].
+///
+/// Bit 14 of the fields of `uv` and `dim` hold, from the atlas x coordinate to the height, the
+/// index of the background hint of the glyph among the hints of the vertex buffer plus one, or
+/// `0` without a hint.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct GlyphVertex {
+    pos: [i32; 2],
+    dim: [u16; 2],
+    uv: [u16; 2],
+    color: u32,
     depth: f32,
 }
 
 /// The screen resolution to use when rendering text.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Resolution {
     /// The width of the screen in pixels.
     pub width: u32,
@@ -72,13 +203,23 @@ pub struct Resolution {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub(crate) struct Params {
     screen_resolution: Resolution,
+    headroom: f32,
+    origin: [i32; 2],
+    transform: ViewTransform,
+    tint: [f32; 4],
 }
 
 /// Controls the visible area of the text. Any text outside of the visible area will be clipped.
+///
+/// Clipping happens on the CPU in `prepare`: glyphs outside of the bounds are not emitted, and
+/// glyphs crossing them are cut down to the visible part of their quad and atlas region. The
+/// GPU therefore never rasterizes clipped pixels, without scissor rectangles or per-fragment
+/// tests, and text areas with different bounds can be drawn in the same draw call.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TextBounds {
     /// The position of the left edge of the visible area.
     pub left: i32,
@@ -90,6 +231,22 @@ pub struct TextBounds {
     pub bottom: i32,
 }
 
+impl TextBounds {
+    /// Scales the bounds by `factor`, saturating at the edges of the `i32` range.
+    pub(crate) fn scaled(self, factor: f32) -> Self {
+        if factor == 1.0 {
+            return self;
+        }
+
+        Self {
+            left: (self.left as f32 * factor).floor() as i32,
+            top: (self.top as f32 * factor).floor() as i32,
+            right: (self.right as f32 * factor).ceil() as i32,
+            bottom: (self.bottom as f32 * factor).ceil() as i32,
+        }
+    }
+}
+
 /// The default visible area doesn't clip any text.
 impl Default for TextBounds {
     fn default() -> Self {
@@ -103,6 +260,9 @@ impl Default for TextBounds {
 }
 
 /// A text area containing text to be rendered along with its overflow behavior.
+///
+/// Positions and bounds are in physical pixels, unless the [`Viewport`] was updated with a scale
+/// factor (see [`Viewport::update_with_scale`]), in which case they are in logical points.
 #[derive(Clone)]
 pub struct TextArea<'a> {
     /// The buffer containing the text to be rendered.
@@ -116,8 +276,146 @@ pub struct TextArea<'a> {
     /// The visible bounds of the text area. This is used to clip the text and doesn't have to
     /// match the `left` and `top` values.
     pub bounds: TextBounds,
+    /// The offset the text is scrolled by, which moves it left and up relative to `left` and
+    /// `top` while the bounds stay in place, e.g. to scroll a pane without reshaping its buffer.
+    ///
+    /// The offset has the units of `left` and `top`, and is not scaled by `scale`. Layout runs
+    /// scrolled out of the bounds are skipped like any other invisible runs.
+    pub scroll: (f32, f32),
     /// The default color of the text area.
     pub default_color: Color,
     /// Additional custom glyphs to render.
     pub custom_glyphs: &'a [CustomGlyph],
+    /// How the glyphs of the text area are kept in the atlas.
+    pub cache_priority: CachePriority,
+    /// How glyphs whose face is lighter than requested by the attributes of their text are
+    /// drawn, e.g. to embolden a regular face standing in for a missing bold one.
+    ///
+    /// Faces that differ from the requested one are reported in
+    /// [`TextRenderer::font_usage`] either way.
+    pub font_synthesis: FontSynthesis,
+    /// The identifier reported with the information about the area (see
+    /// [`AreaFontUsage::area_id`] and [`GlyphAnimContext::area_id`]), e.g. to match it to the
+    /// widget showing the text across frames whose areas come and go. `None` identifies the area
+    /// by its index among the areas being prepared.
+    pub id: Option<u64>,
+    /// The approximate color behind the text area, which corrects the coverage of its mask
+    /// glyphs when the text is darker than the background, or `None` to draw them as they are.
+    ///
+    /// Small dark text on a light background looks thinner than light text on a dark one,
+    /// especially when it is blended in linear space, into targets that store linear colors
+    /// (see [`ColorMode`]). Like the gamma-corrected masks with contrast of Skia, the hint
+    /// thickens the coverage of darker text, and on targets that store linear colors raises it
+    /// so that blending matches blending sRGB colors. Text lighter than the hint, color glyphs
+    /// and areas without a hint are drawn exactly as without the correction.
+    ///
+    /// Only the luminance of the hint is used. Up to 15 distinct luminances are kept per
+    /// `prepare`; areas with further hints are corrected for the closest one.
+    pub background_hint: Option<Color>,
+}
+
+impl<'a> TextArea<'a> {
+    /// Creates an area showing `buffer` at the origin, with a scale of one, white text and no
+    /// bounds, e.g. to set other fields with `TextArea { left, top, ..TextArea::new(&buffer) }`.
+    pub fn new(buffer: &'a Buffer) -> Self {
+        Self {
+            buffer,
+            left: 0.0,
+            top: 0.0,
+            scale: 1.0,
+            bounds: TextBounds::default(),
+            scroll: (0.0, 0.0),
+            default_color: Color::rgb(255, 255, 255),
+            custom_glyphs: &[],
+            cache_priority: CachePriority::Normal,
+            font_synthesis: FontSynthesis::None,
+            id: None,
+            background_hint: None,
+        }
+    }
+}
+
+/// An area of glyphs placed by the caller, see [`TextRenderer::prepare_glyph_areas`].
+///
+/// Positions, bounds and scale have the same units as those of a [`TextArea`].
+#[derive(Clone)]
+pub struct GlyphArea<'a, G> {
+    /// The glyphs to render, e.g. a `Vec<GlyphPlacement>`.
+    pub glyphs: G,
+    /// The left edge of the area, which custom glyphs are placed relative to.
+    pub left: f32,
+    /// The top edge of the area, which custom glyphs are placed relative to.
+    pub top: f32,
+    /// The scaling that the glyphs were placed with, also applied to custom glyphs.
+    pub scale: f32,
+    /// The visible bounds of the area.
+    pub bounds: TextBounds,
+    /// The color of glyphs without a color of their own.
+    pub default_color: Color,
+    /// Additional custom glyphs to render.
+    pub custom_glyphs: &'a [CustomGlyph],
+    /// How the glyphs of the area are kept in the atlas.
+    pub cache_priority: CachePriority,
+    /// How glyphs whose face is lighter than their [`GlyphPlacement::requested_face`] are
+    /// drawn.
+    pub font_synthesis: FontSynthesis,
+    /// The identifier reported with the information about the area, or `None` for its index,
+    /// see [`TextArea::id`].
+    pub id: Option<u64>,
+    /// The approximate color behind the area, see [`TextArea::background_hint`].
+    pub background_hint: Option<Color>,
+}
+
+/// A glyph placed in physical pixels, as `prepare` derives it from a [`LayoutGlyph`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphPlacement {
+    /// The key of the rasterized glyph, which includes its subpixel offsets.
+    pub cache_key: CacheKey,
+    /// The physical position of the glyph origin, without the subpixel offset.
+    pub x: i32,
+    /// The physical position of the glyph origin relative to its line, without the subpixel
+    /// offset.
+    pub y: i32,
+    /// The baseline of the line of the glyph, see [`LayoutRun::line_y`], which is scaled with
+    /// the scale of the area.
+    pub line_y: f32,
+    /// The color of the glyph, or `None` for the default color of its area.
+    pub color: Option<Color>,
+    /// The metadata of the glyph, passed to `metadata_to_depth`.
+    pub metadata: usize,
+    /// How the quad of the glyph is moved, scaled and colored.
+    pub transform: GlyphTransform,
+    /// The physical width of the advance of the glyph.
+    pub advance: f32,
+    /// The face requested by the attributes of the text of the glyph (see
+    /// [`FaceStyle::requested`]), or `None` if unknown, which draws the glyph without
+    /// [`GlyphArea::font_synthesis`] and leaves it out of [`AreaFontUsage::face_mismatches`].
+    pub requested_face: Option<FaceStyle>,
+}
+
+impl GlyphPlacement {
+    /// Places a glyph of the layout run whose baseline is `line_y`.
+    ///
+    /// `offset` and `scale` are the physical position and scale of the area: its `left`, `top`
+    /// and `scale` multiplied by the scale factor of the [`Viewport`].
+    pub fn from_layout_glyph(
+        glyph: &LayoutGlyph,
+        line_y: f32,
+        offset: (f32, f32),
+        scale: f32,
+    ) -> Self {
+        let physical_glyph = glyph.physical(offset, scale);
+
+        Self {
+            cache_key: physical_glyph.cache_key,
+            x: physical_glyph.x,
+            y: physical_glyph.y,
+            line_y,
+            color: glyph.color_opt,
+            metadata: glyph.metadata,
+            transform: GlyphTransform::IDENTITY,
+            advance: glyph.w * scale,
+            requested_face: None,
+        }
+    }
 }
@@ -0,0 +1,38 @@
+use crate::Buffer;
+
+/// The size of laid-out text, see [`measure`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TextSize {
+    /// The width of the widest line.
+    pub width: f32,
+    /// The distance from the top of the first line to the bottom of the last one.
+    pub height: f32,
+    /// The number of laid-out lines, counting every line a paragraph wraps into.
+    pub line_count: usize,
+}
+
+/// Measures the laid-out text of `buffer`, without rendering it.
+///
+/// Sizes are in the units of the buffer metrics, like the layout runs they are computed from.
+/// Multiply them by [`crate::TextArea::scale`] and the scale factor of the viewport to get
+/// physical pixels. Widths are those of the lines as cosmic-text wraps them, so whitespace at a
+/// wrap point does not count, matching where glyphs are positioned in `prepare`.
+///
+/// Only lines that have been shaped are measured, i.e. up to the scroll position after
+/// `Buffer::shape_until_scroll`. Set the height of the buffer to `None` to measure every line.
+pub fn measure(buffer: &Buffer) -> TextSize {
+    measure_lines(buffer, usize::MAX)
+}
+
+/// Measures the first `max_lines` laid-out lines of `buffer`, e.g. to size a container that
+/// clamps its text, see [`measure`].
+pub fn measure_lines(buffer: &Buffer, max_lines: usize) -> TextSize {
+    buffer
+        .layout_runs()
+        .take(max_lines)
+        .fold(TextSize::default(), |size, run| TextSize {
+            width: size.width.max(run.line_w),
+            height: run.line_top + run.line_height,
+            line_count: size.line_count + 1,
+        })
+}
@@ -0,0 +1,57 @@
+use std::{
+    iter::Sum,
+    ops::{Add, AddAssign},
+};
+
+/// The memory used by the resources of a [`crate::TextAtlas`], [`crate::TextRenderer`] or
+/// [`crate::Viewport`], see their `memory_usage` methods.
+///
+/// GPU figures are the sizes Metal allocated for each resource (`MTLResource::allocatedSize`),
+/// which may exceed the requested sizes. CPU figures are estimates of the heap memory used by
+/// caches and vertex data. Figures reflect the resources at the time of the call, so they follow
+/// atlas growth and vertex buffer reallocations. Usages can be added up, e.g. for a memory HUD.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The bytes allocated for textures.
+    pub texture_bytes: u64,
+    /// The bytes allocated for buffers.
+    pub buffer_bytes: u64,
+    /// The estimated bytes of CPU memory used by glyph caches and vertex data.
+    pub cpu_bytes: u64,
+}
+
+impl MemoryUsage {
+    /// Returns the bytes allocated for textures and buffers.
+    pub fn gpu_bytes(&self) -> u64 {
+        self.texture_bytes + self.buffer_bytes
+    }
+
+    /// Returns the bytes of GPU and CPU memory.
+    pub fn total_bytes(&self) -> u64 {
+        self.gpu_bytes() + self.cpu_bytes
+    }
+}
+
+impl Add for MemoryUsage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            texture_bytes: self.texture_bytes + other.texture_bytes,
+            buffer_bytes: self.buffer_bytes + other.buffer_bytes,
+            cpu_bytes: self.cpu_bytes + other.cpu_bytes,
+        }
+    }
+}
+
+impl AddAssign for MemoryUsage {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Sum for MemoryUsage {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), Add::add)
+    }
+}
@@ -0,0 +1,495 @@
+use crate::{
+    AlphaMode, Cache, Color, ColorMode, FontSystem, PrepareError, RasterizeCustomGlyphRequest,
+    RasterizedCustomGlyph, Resolution, SwashCache, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+#[cfg(feature = "debug-tools")]
+use crate::{GlyphToRender, Params};
+use objc2::{rc::Retained, runtime::ProtocolObject, Message as _};
+use objc2_metal::{
+    MTLClearColor, MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue, MTLDevice,
+    MTLLoadAction, MTLPixelFormat, MTLRenderPassDescriptor, MTLStorageMode, MTLStoreAction,
+    MTLTexture, MTLTextureDescriptor, MTLTextureType, MTLTextureUsage,
+};
+#[cfg(feature = "readback")]
+use {
+    objc2_metal::{
+        MTLBlitCommandEncoder as _, MTLBuffer as _, MTLOrigin, MTLRegion, MTLResource as _,
+        MTLResourceOptions, MTLSize,
+    },
+    std::ptr::NonNull,
+};
+
+/// Renders text into standalone textures, e.g. to bake labels that are drawn many times in a 3D
+/// scene.
+///
+/// An `OffscreenRenderer` owns its own atlas, viewport and renderer, and waits for the GPU to
+/// finish before returning each texture. Rendered textures use premultiplied alpha, so they
+/// composite correctly whether they were cleared to a color or to transparent.
+pub struct OffscreenRenderer {
+    device: Retained<ProtocolObject<dyn MTLDevice>>,
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+    format: MTLPixelFormat,
+    sample_count: usize,
+    atlas: TextAtlas,
+    viewport: Viewport,
+    renderer: TextRenderer,
+}
+
+impl OffscreenRenderer {
+    /// Creates a new `OffscreenRenderer` that renders into textures of the given `format`.
+    pub fn new(
+        device: &ProtocolObject<dyn MTLDevice>,
+        queue: &ProtocolObject<dyn MTLCommandQueue>,
+        cache: &Cache,
+        format: MTLPixelFormat,
+    ) -> Self {
+        Self::with_options(device, queue, cache, format, ColorMode::Accurate, 1)
+    }
+
+    /// Creates a new `OffscreenRenderer` with the given [`ColorMode`] and sample count. With a
+    /// sample count above `1`, text is rendered into a multisample texture that is resolved into
+    /// the returned texture.
+    ///
+    /// Panics if the device does not support `sample_count`.
+    pub fn with_options(
+        device: &ProtocolObject<dyn MTLDevice>,
+        queue: &ProtocolObject<dyn MTLCommandQueue>,
+        cache: &Cache,
+        format: MTLPixelFormat,
+        color_mode: ColorMode,
+        sample_count: usize,
+    ) -> Self {
+        let mut atlas = TextAtlas::with_color_mode(device, cache, format, color_mode);
+        let viewport = Viewport::new(device);
+        let renderer = TextRenderer::builder(&mut atlas)
+            .sample_count(sample_count)
+            .alpha_mode(AlphaMode::Premultiplied)
+            .label("Metalglyph Offscreen")
+            .build()
+            .expect("Failed to create offscreen text renderer");
+
+        Self {
+            device: device.retain(),
+            queue: queue.retain(),
+            format,
+            sample_count,
+            atlas,
+            viewport,
+            renderer,
+        }
+    }
+
+    /// Returns the atlas used by the renderer.
+    pub fn atlas(&self) -> &TextAtlas {
+        &self.atlas
+    }
+
+    /// Returns the atlas used by the renderer, e.g. to change its color space.
+    pub fn atlas_mut(&mut self) -> &mut TextAtlas {
+        &mut self.atlas
+    }
+
+    /// Returns the viewport used by the renderer, e.g. to set a scale factor or tint. Its
+    /// resolution is overwritten by every render.
+    pub fn viewport_mut(&mut self) -> &mut Viewport {
+        &mut self.viewport
+    }
+
+    /// Renders `text_areas` into a new `width` x `height` texture cleared to `clear_color`, and
+    /// waits for the GPU to finish. Pass a fully transparent `clear_color` for a transparent
+    /// background.
+    pub fn render<'a>(
+        &mut self,
+        font_system: &mut FontSystem,
+        swash_cache: &mut SwashCache,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+        width: u32,
+        height: u32,
+        clear_color: Color,
+    ) -> Result<Retained<ProtocolObject<dyn MTLTexture>>, PrepareError> {
+        self.render_with_custom(
+            font_system,
+            swash_cache,
+            text_areas,
+            width,
+            height,
+            clear_color,
+            |_| None,
+        )
+    }
+
+    /// Renders `text_areas` like [`OffscreenRenderer::render`], rasterizing custom glyphs with
+    /// `rasterize_custom_glyph`.
+    pub fn render_with_custom<'a>(
+        &mut self,
+        font_system: &mut FontSystem,
+        swash_cache: &mut SwashCache,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+        width: u32,
+        height: u32,
+        clear_color: Color,
+        rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
+    ) -> Result<Retained<ProtocolObject<dyn MTLTexture>>, PrepareError> {
+        let texture = self.create_texture(width, height, MTLStorageMode::Private);
+
+        self.render_into(
+            font_system,
+            swash_cache,
+            text_areas,
+            &texture,
+            clear_color,
+            rasterize_custom_glyph,
+        )?;
+
+        Ok(texture)
+    }
+
+    pub(crate) fn create_texture(
+        &self,
+        width: u32,
+        height: u32,
+        storage_mode: MTLStorageMode,
+    ) -> Retained<ProtocolObject<dyn MTLTexture>> {
+        let descriptor = unsafe {
+            MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+                self.format,
+                width.max(1) as usize,
+                height.max(1) as usize,
+                false,
+            )
+        };
+        descriptor.setUsage(MTLTextureUsage::RenderTarget | MTLTextureUsage::ShaderRead);
+        descriptor.setStorageMode(storage_mode);
+
+        self.device
+            .newTextureWithDescriptor(&descriptor)
+            .expect("Failed to create offscreen texture")
+    }
+
+    /// Renders `text_areas` into `texture` and waits for the GPU to finish.
+    pub(crate) fn render_into<'a>(
+        &mut self,
+        font_system: &mut FontSystem,
+        swash_cache: &mut SwashCache,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+        texture: &ProtocolObject<dyn MTLTexture>,
+        clear_color: Color,
+        rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
+    ) -> Result<(), PrepareError> {
+        self.viewport.update_with_scale(
+            Resolution {
+                width: texture.width() as u32,
+                height: texture.height() as u32,
+            },
+            self.viewport.scale_factor(),
+        );
+
+        self.renderer.prepare_with_custom(
+            font_system,
+            &mut self.atlas,
+            &self.viewport,
+            text_areas,
+            swash_cache,
+            rasterize_custom_glyph,
+        )?;
+
+        self.draw_into(texture, clear_color);
+
+        Ok(())
+    }
+
+    /// Renders the prepared text into `texture` cleared to `clear_color`, and waits for the GPU
+    /// to finish.
+    fn draw_into(&mut self, texture: &ProtocolObject<dyn MTLTexture>, clear_color: Color) {
+        let render_pass_descriptor = MTLRenderPassDescriptor::new();
+        let color_attachment = unsafe {
+            render_pass_descriptor
+                .colorAttachments()
+                .objectAtIndexedSubscript(0)
+        };
+
+        color_attachment.setLoadAction(MTLLoadAction::Clear);
+        color_attachment.setClearColor(clear_color_for(clear_color, self.format));
+
+        if self.sample_count > 1 {
+            let descriptor = unsafe {
+                MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+                    self.format,
+                    texture.width(),
+                    texture.height(),
+                    false,
+                )
+            };
+            descriptor.setTextureType(MTLTextureType::Type2DMultisample);
+            unsafe { descriptor.setSampleCount(self.sample_count) };
+            descriptor.setUsage(MTLTextureUsage::RenderTarget);
+            descriptor.setStorageMode(MTLStorageMode::Private);
+
+            let multisample_texture = self
+                .device
+                .newTextureWithDescriptor(&descriptor)
+                .expect("Failed to create multisample texture");
+
+            color_attachment.setTexture(Some(&multisample_texture));
+            color_attachment.setResolveTexture(Some(texture));
+            color_attachment.setStoreAction(MTLStoreAction::MultisampleResolve);
+        } else {
+            color_attachment.setTexture(Some(texture));
+            color_attachment.setStoreAction(MTLStoreAction::Store);
+        }
+
+        let command_buffer = self
+            .queue
+            .commandBuffer()
+            .expect("Failed to create command buffer");
+        let render_encoder = command_buffer
+            .renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            .expect("Failed to create render command encoder");
+
+        self.renderer
+            .render(&self.atlas, &self.viewport, &render_encoder);
+        render_encoder.endEncoding();
+
+        command_buffer.commit();
+        command_buffer.waitUntilCompleted();
+
+        self.atlas.trim();
+    }
+}
+
+/// Renders `text_areas` into a new `width` x `height` texture of the given `format`, cleared to
+/// `clear_color`, and waits for the GPU to finish.
+///
+/// This creates a throwaway [`OffscreenRenderer`]. Keep one around instead when rendering many
+/// textures, so that glyphs stay cached.
+pub fn render_to_texture<'a>(
+    device: &ProtocolObject<dyn MTLDevice>,
+    queue: &ProtocolObject<dyn MTLCommandQueue>,
+    cache: &Cache,
+    font_system: &mut FontSystem,
+    swash_cache: &mut SwashCache,
+    text_areas: impl IntoIterator<Item = TextArea<'a>>,
+    width: u32,
+    height: u32,
+    format: MTLPixelFormat,
+    clear_color: Color,
+) -> Result<Retained<ProtocolObject<dyn MTLTexture>>, PrepareError> {
+    OffscreenRenderer::new(device, queue, cache, format).render(
+        font_system,
+        swash_cache,
+        text_areas,
+        width,
+        height,
+        clear_color,
+    )
+}
+
+/// Converts an sRGB `color` into the clear color of a texture of the given `format`, which is
+/// premultiplied and linear for sRGB formats.
+fn clear_color_for(color: Color, format: MTLPixelFormat) -> MTLClearColor {
+    let [r, g, b, a] = color.as_rgba().map(|c| c as f64 / 255.0);
+
+    let is_srgb = matches!(
+        format,
+        MTLPixelFormat::BGRA8Unorm_sRGB | MTLPixelFormat::RGBA8Unorm_sRGB
+    );
+    let convert = |c: f64| {
+        let c = if is_srgb {
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        } else {
+            c
+        };
+
+        c * a
+    };
+
+    MTLClearColor {
+        red: convert(r),
+        green: convert(g),
+        blue: convert(b),
+        alpha: a,
+    }
+}
+
+/// The pixels of a texture read back from the GPU, see [`OffscreenRenderer::render_to_pixels`].
+#[cfg(feature = "readback")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pixels {
+    /// The width of the image in pixels.
+    pub width: u32,
+    /// The height of the image in pixels.
+    pub height: u32,
+    /// The premultiplied RGBA8 pixels, row by row from the top, without padding.
+    pub data: Vec<u8>,
+}
+
+#[cfg(feature = "readback")]
+impl OffscreenRenderer {
+    /// Renders `text_areas` like [`OffscreenRenderer::render_with_custom`] and reads the result
+    /// back into CPU memory, e.g. to compare renders in tests.
+    ///
+    /// The renderer must have been created with an 8-bit RGBA or BGRA format. The output only
+    /// depends on the text areas and:
+    /// - the fonts loaded into `font_system` (system fonts differ between machines, so tests
+    ///   should load their own fonts into a `FontSystem` without system fonts),
+    /// - the format and [`crate::ColorMode`] of the atlas, and the viewport scale factor,
+    /// - the GPU, whose texture filtering and blending may differ in the last bit between
+    ///   vendors.
+    ///
+    /// Rendering the same input twice on the same machine produces identical pixels.
+    pub fn render_to_pixels<'a>(
+        &mut self,
+        font_system: &mut FontSystem,
+        swash_cache: &mut SwashCache,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+        width: u32,
+        height: u32,
+        clear_color: Color,
+        rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
+    ) -> Result<Pixels, PrepareError> {
+        let texture = self.create_readback_texture(width, height);
+
+        self.render_into(
+            font_system,
+            swash_cache,
+            text_areas,
+            &texture,
+            clear_color,
+            rasterize_custom_glyph,
+        )?;
+
+        Ok(self.read_back(&texture))
+    }
+
+    /// Creates a texture that [`OffscreenRenderer::read_back`] can read.
+    fn create_readback_texture(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> Retained<ProtocolObject<dyn MTLTexture>> {
+        // Textures can only use the shared storage mode with unified memory, otherwise the
+        // pixels are blitted through a shared buffer.
+        let storage_mode = if self.device.hasUnifiedMemory() {
+            MTLStorageMode::Shared
+        } else {
+            MTLStorageMode::Private
+        };
+
+        self.create_texture(width, height, storage_mode)
+    }
+
+    /// Reads the pixels of `texture` back into CPU memory.
+    fn read_back(&self, texture: &ProtocolObject<dyn MTLTexture>) -> Pixels {
+        let is_bgra = match self.format {
+            MTLPixelFormat::BGRA8Unorm | MTLPixelFormat::BGRA8Unorm_sRGB => true,
+            MTLPixelFormat::RGBA8Unorm | MTLPixelFormat::RGBA8Unorm_sRGB => false,
+            format => panic!("Cannot read back pixels of format {format:?}"),
+        };
+
+        let (width, height) = (texture.width(), texture.height());
+        let bytes_per_row = width * 4;
+        let mut data = vec![0u8; bytes_per_row * height];
+        let region = MTLRegion {
+            origin: MTLOrigin { x: 0, y: 0, z: 0 },
+            size: MTLSize {
+                width,
+                height,
+                depth: 1,
+            },
+        };
+
+        if texture.storageMode() == MTLStorageMode::Shared {
+            unsafe {
+                texture.getBytes_bytesPerRow_fromRegion_mipmapLevel(
+                    NonNull::from(data.as_mut_slice()).cast(),
+                    bytes_per_row,
+                    region,
+                    0,
+                );
+            }
+        } else {
+            let buffer = self
+                .device
+                .newBufferWithLength_options(data.len(), MTLResourceOptions::StorageModeShared)
+                .expect("Failed to create readback buffer");
+
+            let command_buffer = self
+                .queue
+                .commandBuffer()
+                .expect("Failed to create command buffer");
+            let blit_encoder = command_buffer
+                .blitCommandEncoder()
+                .expect("Failed to create blit command encoder");
+
+            unsafe {
+                blit_encoder.copyFromTexture_sourceSlice_sourceLevel_sourceOrigin_sourceSize_toBuffer_destinationOffset_destinationBytesPerRow_destinationBytesPerImage(
+                    texture,
+                    0,
+                    0,
+                    region.origin,
+                    region.size,
+                    &buffer,
+                    0,
+                    bytes_per_row,
+                    data.len(),
+                );
+            }
+            blit_encoder.endEncoding();
+
+            command_buffer.commit();
+            command_buffer.waitUntilCompleted();
+
+            unsafe {
+                NonNull::from(data.as_mut_slice())
+                    .cast::<u8>()
+                    .copy_from(buffer.contents().cast(), data.len());
+            }
+        }
+
+        if is_bgra {
+            for pixel in data.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Pixels {
+            width: width as u32,
+            height: height as u32,
+            data,
+        }
+    }
+}
+
+#[cfg(feature = "debug-tools")]
+impl OffscreenRenderer {
+    /// Renders `vertices` as prepared against atlases holding `mask_atlas` and `color_atlas`
+    /// (each as its size and texels), with the viewport parameters `params`, and reads the
+    /// result back into CPU memory.
+    pub(crate) fn replay(
+        &mut self,
+        params: Params,
+        vertices: Vec<GlyphToRender>,
+        mask_atlas: (u32, Vec<u8>),
+        color_atlas: (u32, Vec<u8>),
+    ) -> Pixels {
+        let Resolution { width, height } = params.screen_resolution;
+
+        self.viewport.restore_params(params);
+        self.atlas
+            .mask_atlas
+            .restore_texels(&self.device, mask_atlas.0, mask_atlas.1);
+        self.atlas
+            .color_atlas
+            .restore_texels(&self.device, color_atlas.0, color_atlas.1);
+        self.renderer.restore_vertices(vertices, &self.atlas);
+
+        let texture = self.create_readback_texture(width, height);
+        self.draw_into(&texture, Color::rgba(0, 0, 0, 0));
+
+        self.read_back(&texture)
+    }
+}
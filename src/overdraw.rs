@@ -0,0 +1,84 @@
+use crate::GlyphToRender;
+
+/// The pixels covered by the glyph quads of the most recent `prepare`, an estimate of the
+/// fragment work of rendering it, see [`crate::TextRenderer::overdraw`].
+///
+/// Quads are counted after clipping to the bounds of their area and to the viewport, the way the
+/// rasterizer sees them. Overlapping quads are counted once each, so that overlapping areas,
+/// shadows and outlines drawn as separate areas show up as pixels covered more than once. Quads
+/// of glyphs with a [`crate::GlyphTransform`] count with their untransformed size.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overdraw {
+    /// The pixels of the render resolution of the viewport, see
+    /// [`crate::Viewport::render_resolution`].
+    pub viewport_pixels: u64,
+    /// The glyph quads of all areas.
+    pub quads: usize,
+    /// The sum of the pixels covered by the glyph quads of all areas.
+    pub quad_pixels: u64,
+    /// The glyph quads of each text area (or glyph area), in the order the areas were prepared.
+    pub areas: Vec<AreaOverdraw>,
+}
+
+/// The glyph quads of a text area, see [`Overdraw::areas`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AreaOverdraw {
+    /// The [`crate::TextArea::id`] of the area, or its index among the areas of the `prepare`
+    /// if it has none.
+    pub area_id: u64,
+    /// The glyph quads of the area.
+    pub quads: usize,
+    /// The sum of the pixels covered by the glyph quads of the area.
+    pub quad_pixels: u64,
+}
+
+impl Overdraw {
+    /// Returns the pixels covered by all glyph quads relative to the pixels of the viewport,
+    /// e.g. `2.0` if the text covers the viewport twice over, or `0.0` for an empty viewport.
+    pub fn factor(&self) -> f32 {
+        pixel_ratio(self.quad_pixels, self.viewport_pixels)
+    }
+
+    /// Returns the pixels covered by the glyph quads of `area` relative to the pixels of the
+    /// viewport, see [`Overdraw::factor`].
+    pub fn area_factor(&self, area: &AreaOverdraw) -> f32 {
+        pixel_ratio(area.quad_pixels, self.viewport_pixels)
+    }
+
+    /// Returns up to `n` areas covering the most pixels, the largest first. Areas covering as
+    /// many pixels keep the order they were prepared in.
+    pub fn largest_areas(&self, n: usize) -> Vec<AreaOverdraw> {
+        let mut areas = self.areas.clone();
+        areas.sort_by(|a, b| b.quad_pixels.cmp(&a.quad_pixels));
+        areas.truncate(n);
+        areas
+    }
+
+    /// Clears the counts of the previous `prepare`.
+    pub(crate) fn reset(&mut self, viewport_pixels: u64) {
+        self.viewport_pixels = viewport_pixels;
+        self.quads = 0;
+        self.quad_pixels = 0;
+        self.areas.clear();
+    }
+
+    /// Counts `vertex` as a quad of the area at `area` in [`Overdraw::areas`].
+    pub(crate) fn record(&mut self, area: usize, vertex: &GlyphToRender) {
+        let [width, height] = vertex.size().map(u64::from);
+        let pixels = width * height;
+        self.quads += 1;
+        self.quad_pixels += pixels;
+
+        let area = &mut self.areas[area];
+        area.quads += 1;
+        area.quad_pixels += pixels;
+    }
+}
+
+fn pixel_ratio(pixels: u64, viewport_pixels: u64) -> f32 {
+    if viewport_pixels == 0 {
+        return 0.0;
+    }
+
+    (pixels as f64 / viewport_pixels as f64) as f32
+}
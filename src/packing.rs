@@ -0,0 +1,195 @@
+use crate::glyph_allocator::Hasher;
+use etagere::{
+    point2, size2, AllocId, Allocation, AtlasAllocator, BucketedAtlasAllocator, Point, Size,
+};
+use std::collections::HashMap;
+
+/// How glyphs are packed into the texture of an atlas, see [`crate::TextAtlasBuilder::packing`].
+///
+/// Compare strategies on your own content with [`crate::AtlasStats::packing_efficiency`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AtlasPacking {
+    /// Packs glyphs into shelves of rounded-up heights, within columns of the atlas.
+    ///
+    /// Allocation is fast and suits text of a few similar sizes, but glyphs of very different
+    /// sizes waste the space above the smaller ones.
+    #[default]
+    Bucketed,
+    /// Packs glyphs by recursively splitting free rectangles (a guillotine allocator).
+    ///
+    /// Allocation is slower, but mixes of very large and very small glyphs fragment less, so the
+    /// atlas grows later.
+    Guillotine,
+}
+
+/// A rectangle packer backing the [`crate::glyph_allocator::GlyphAllocator`] of an atlas.
+pub(crate) trait Packer: Send {
+    /// Allocates a rectangle of `size`, or returns `None` if it does not fit.
+    fn allocate(&mut self, size: Size) -> Option<Allocation>;
+
+    /// Frees the rectangle of a previous allocation.
+    fn deallocate(&mut self, id: AllocId);
+
+    /// Grows the packed area to `size`, keeping existing allocations in place.
+    fn grow(&mut self, size: Size);
+
+    /// Returns the area taken by allocations, including the padding added by the packer.
+    fn allocated_space(&self) -> i32;
+
+    /// Returns the rectangle of a live allocation.
+    #[cfg(feature = "atlas-invariants")]
+    fn get(&self, id: AllocId) -> etagere::Rectangle;
+}
+
+impl AtlasPacking {
+    /// Creates an empty packer of `size` with this strategy.
+    pub(crate) fn new_packer(self, size: Size) -> Box<dyn Packer> {
+        match self {
+            Self::Bucketed => Box::new(BucketedPacker::new(size)),
+            Self::Guillotine => Box::new(GuillotinePacker::new(size)),
+        }
+    }
+}
+
+/// A shelf packer, see [`AtlasPacking::Bucketed`].
+struct BucketedPacker {
+    allocator: BucketedAtlasAllocator,
+    /// The rectangles of the live allocations, which [`BucketedAtlasAllocator`] does not expose.
+    #[cfg(feature = "atlas-invariants")]
+    rectangles: HashMap<AllocId, etagere::Rectangle, Hasher>,
+}
+
+impl BucketedPacker {
+    fn new(size: Size) -> Self {
+        Self {
+            allocator: BucketedAtlasAllocator::new(size),
+            #[cfg(feature = "atlas-invariants")]
+            rectangles: HashMap::with_hasher(Hasher::default()),
+        }
+    }
+}
+
+impl Packer for BucketedPacker {
+    fn allocate(&mut self, size: Size) -> Option<Allocation> {
+        let allocation = self.allocator.allocate(size)?;
+
+        #[cfg(feature = "atlas-invariants")]
+        self.rectangles.insert(allocation.id, allocation.rectangle);
+
+        Some(allocation)
+    }
+
+    fn deallocate(&mut self, id: AllocId) {
+        #[cfg(feature = "atlas-invariants")]
+        assert!(
+            self.rectangles.remove(&id).is_some(),
+            "Deallocated an allocation that is not live"
+        );
+
+        self.allocator.deallocate(id);
+    }
+
+    fn grow(&mut self, size: Size) {
+        self.allocator.grow(size);
+    }
+
+    fn allocated_space(&self) -> i32 {
+        self.allocator.allocated_space()
+    }
+
+    #[cfg(feature = "atlas-invariants")]
+    fn get(&self, id: AllocId) -> etagere::Rectangle {
+        self.rectangles[&id]
+    }
+}
+
+/// A guillotine packer that can grow.
+///
+/// [`AtlasAllocator`] cannot grow in place, so each area added by growing is packed by an
+/// allocator of its own, offset to its place in the atlas.
+struct GuillotinePacker {
+    /// The allocators of the packed area and the positions of their areas in it.
+    regions: Vec<(AtlasAllocator, Point)>,
+    size: Size,
+    /// The region and allocation of each live allocation by the id handed out for it.
+    allocations: HashMap<u32, (usize, AllocId), Hasher>,
+    next_id: u32,
+}
+
+impl GuillotinePacker {
+    fn new(size: Size) -> Self {
+        Self {
+            regions: vec![(AtlasAllocator::new(size), Point::origin())],
+            size,
+            allocations: HashMap::with_hasher(Hasher::default()),
+            next_id: 0,
+        }
+    }
+}
+
+impl Packer for GuillotinePacker {
+    fn allocate(&mut self, size: Size) -> Option<Allocation> {
+        let (region, allocation, offset) =
+            self.regions
+                .iter_mut()
+                .enumerate()
+                .find_map(|(region, (allocator, offset))| {
+                    allocator
+                        .allocate(size)
+                        .map(|allocation| (region, allocation, *offset))
+                })?;
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.allocations.insert(id, (region, allocation.id));
+
+        Some(Allocation {
+            id: AllocId::deserialize(id),
+            rectangle: allocation.rectangle.translate(offset.to_vector()),
+        })
+    }
+
+    fn deallocate(&mut self, id: AllocId) {
+        let (region, id) = self
+            .allocations
+            .remove(&id.serialize())
+            .expect("Deallocated an allocation that is not live");
+        self.regions[region].0.deallocate(id);
+    }
+
+    fn grow(&mut self, size: Size) {
+        // The added area is split into the strip to the right of the old area and the strip
+        // below both
+        if size.width > self.size.width {
+            self.regions.push((
+                AtlasAllocator::new(size2(size.width - self.size.width, self.size.height)),
+                point2(self.size.width, 0),
+            ));
+        }
+        if size.height > self.size.height {
+            self.regions.push((
+                AtlasAllocator::new(size2(size.width, size.height - self.size.height)),
+                point2(0, self.size.height),
+            ));
+        }
+
+        self.size = size;
+    }
+
+    fn allocated_space(&self) -> i32 {
+        self.regions
+            .iter()
+            .map(|(allocator, _)| allocator.allocated_space())
+            .sum()
+    }
+
+    #[cfg(feature = "atlas-invariants")]
+    fn get(&self, id: AllocId) -> etagere::Rectangle {
+        let (region, id) = self.allocations[&id.serialize()];
+        let (allocator, offset) = &self.regions[region];
+
+        allocator.get(id).translate(offset.to_vector())
+    }
+}
@@ -0,0 +1,316 @@
+use crate::{
+    Buffer, CachePriority, Color, FontSynthesis, GlyphArea, GlyphPlacement, GlyphTransform,
+    LayoutGlyph, TextBounds, Viewport,
+};
+
+/// The number of steps a glyph is moved along a curve by until its chord is as long as its
+/// advance, after which it is placed at the last step.
+const MAX_CHORD_STEPS: usize = 64;
+
+/// A path of straight and cubic Bézier segments that text is laid out along, see
+/// [`PathTextArea`].
+///
+/// Points have the units of the positions of a [`crate::TextArea`]. Cubic segments are flattened
+/// into straight segments when they are added.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextPath {
+    points: Vec<(f32, f32)>,
+    /// The distance along the path of each point.
+    distances: Vec<f32>,
+}
+
+impl TextPath {
+    /// Creates a path starting at `start`.
+    pub fn new(start: (f32, f32)) -> Self {
+        Self {
+            points: vec![start],
+            distances: vec![0.0],
+        }
+    }
+
+    /// Creates a path through `points`, joined by straight segments.
+    pub fn polyline(points: impl IntoIterator<Item = (f32, f32)>) -> Self {
+        let mut points = points.into_iter();
+        let Some(start) = points.next() else {
+            return Self::default();
+        };
+
+        points.fold(Self::new(start), Self::line_to)
+    }
+
+    /// Adds a straight segment from the end of the path to `to`.
+    pub fn line_to(mut self, to: (f32, f32)) -> Self {
+        self.push(to);
+        self
+    }
+
+    /// Adds a cubic Bézier segment from the end of the path to `to`, with the control points
+    /// `control1` and `control2`.
+    pub fn cubic_to(mut self, control1: (f32, f32), control2: (f32, f32), to: (f32, f32)) -> Self {
+        let Some(&from) = self.points.last() else {
+            return Self::new(to);
+        };
+
+        // The control polygon is at least as long as the curve, a step per two units of it keeps
+        // the flattened curve within a fraction of a unit of the curve
+        let polygon =
+            distance(from, control1) + distance(control1, control2) + distance(control2, to);
+        let steps = (polygon / 2.0).ceil().clamp(1.0, 256.0) as usize;
+
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let u = 1.0 - t;
+            let weights = [u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t];
+            let point = [from, control1, control2, to]
+                .iter()
+                .zip(weights)
+                .fold((0.0, 0.0), |(x, y), (point, weight)| {
+                    (x + point.0 * weight, y + point.1 * weight)
+                });
+
+            self.push(point);
+        }
+
+        self
+    }
+
+    fn push(&mut self, point: (f32, f32)) {
+        match self.points.last() {
+            Some(&last) => {
+                let length = self.length() + distance(last, point);
+                self.points.push(point);
+                self.distances.push(length);
+            }
+            None => *self = Self::new(point),
+        }
+    }
+
+    /// Returns the length of the path.
+    pub fn length(&self) -> f32 {
+        self.distances.last().copied().unwrap_or(0.0)
+    }
+
+    /// Returns the point at `distance` along the path, continuing along the direction of its
+    /// first or last segment for distances outside of the path, or `None` for paths without
+    /// segments.
+    pub fn point_at(&self, distance: f32) -> Option<(f32, f32)> {
+        if self.points.len() < 2 {
+            return None;
+        }
+
+        // The segment containing the distance, or the first or last one
+        let end = self
+            .distances
+            .partition_point(|&point_distance| point_distance <= distance)
+            .clamp(1, self.points.len() - 1);
+        let (from, to) = (self.points[end - 1], self.points[end]);
+        let (from_distance, to_distance) = (self.distances[end - 1], self.distances[end]);
+        let t = if to_distance > from_distance {
+            (distance - from_distance) / (to_distance - from_distance)
+        } else {
+            0.0
+        };
+
+        Some((from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t))
+    }
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (b.0 - a.0).hypot(b.1 - a.1)
+}
+
+/// What happens to the glyphs of a [`PathTextArea`] that do not fit on its path.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathOverflow {
+    /// Glyphs that are not entirely on the path are dropped. Rotated glyphs cannot be clipped,
+    /// so text is cut between glyphs.
+    #[default]
+    Drop,
+    /// Glyphs past the ends of the path continue along the direction of the path at that end.
+    Extend,
+}
+
+/// Text laid out along a [`TextPath`], e.g. for map labels or decorative headers.
+///
+/// The glyphs of the first line of the buffer are placed one after another along the path, each
+/// rotated so that its baseline follows the path. Left-to-right text starts at `start` along the
+/// path, and right-to-left text ends at `start` before the end of the path.
+///
+/// Each glyph is placed so that the straight line between the ends of its advance, on the path,
+/// is as long as its advance, which keeps the spacing of glyphs on tight curves instead of
+/// bunching them up. Glyphs on the inside of curves tighter than their height may still overlap.
+/// Glyphs are rasterized like any other, only their quads are rotated, see [`GlyphTransform`].
+///
+/// Prepare path text with [`crate::TextRenderer::prepare_glyph_areas`]:
+///
+/// ```ignore
+/// let area = PathTextArea { start: 12.0, ..PathTextArea::new(&label, &road) };
+/// text_renderer.prepare_glyph_areas(
+///     font_system, atlas, viewport, [area.glyph_area(viewport)], swash_cache, |_| 0.0, |_| None,
+/// )?;
+/// ```
+#[derive(Clone)]
+pub struct PathTextArea<'a> {
+    /// The buffer containing the text to be rendered, of which only the first line is laid out.
+    pub buffer: &'a Buffer,
+    /// The path the text is laid out along.
+    pub path: &'a TextPath,
+    /// The distance along the path from its start to the start of left-to-right text, or from
+    /// its end to the end of right-to-left text.
+    pub start: f32,
+    /// The scaling to apply to the buffer.
+    pub scale: f32,
+    /// The visible bounds of the text, which rotated glyphs are culled against but not clipped to.
+    pub bounds: TextBounds,
+    /// The default color of the text.
+    pub default_color: Color,
+    /// What happens to glyphs that do not fit on the path.
+    pub overflow: PathOverflow,
+}
+
+impl<'a> PathTextArea<'a> {
+    /// Creates an area laying out `buffer` along `path` from its start, with a scale of one,
+    /// white text and no bounds.
+    pub fn new(buffer: &'a Buffer, path: &'a TextPath) -> Self {
+        Self {
+            buffer,
+            path,
+            start: 0.0,
+            scale: 1.0,
+            bounds: TextBounds::default(),
+            default_color: Color::rgb(255, 255, 255),
+            overflow: PathOverflow::default(),
+        }
+    }
+
+    /// Returns the glyphs of the area placed for the scale factor of `viewport`, as an area for
+    /// [`crate::TextRenderer::prepare_glyph_areas`].
+    pub fn glyph_area(&self, viewport: &Viewport) -> GlyphArea<'a, Vec<GlyphPlacement>> {
+        let mut placements = Vec::new();
+        self.place_glyphs(
+            viewport.scale_factor() * viewport.render_scale(),
+            &mut placements,
+        );
+
+        GlyphArea {
+            glyphs: placements,
+            left: 0.0,
+            top: 0.0,
+            scale: self.scale,
+            bounds: self.bounds,
+            default_color: self.default_color,
+            custom_glyphs: &[],
+            cache_priority: CachePriority::Normal,
+            font_synthesis: FontSynthesis::None,
+            id: None,
+            background_hint: None,
+        }
+    }
+
+    /// Appends the placements of the glyphs of the area to `placements`, in physical pixels for
+    /// `scale_factor`, the scale factor of the viewport times its render scale.
+    ///
+    /// Every placement is rotated around the point where the baseline at the start of its
+    /// advance meets the path.
+    pub fn place_glyphs(&self, scale_factor: f32, placements: &mut Vec<GlyphPlacement>) {
+        let Some(run) = self.buffer.layout_runs().next() else {
+            return;
+        };
+        if self.path.length() <= 0.0 {
+            return;
+        }
+
+        // Glyphs are walked in visual order, from the start of the path for left-to-right text
+        // and from its end for right-to-left text
+        let mut glyphs: Vec<&LayoutGlyph> = run.glyphs.iter().collect();
+        glyphs.sort_by(|a, b| a.x.total_cmp(&b.x));
+        if run.rtl {
+            glyphs.reverse();
+        }
+
+        let Some(first) = glyphs.first() else {
+            return;
+        };
+        let (mut cursor, mut edge) = if run.rtl {
+            (self.path.length() - self.start, first.x + first.w)
+        } else {
+            (self.start, first.x)
+        };
+
+        for glyph in glyphs {
+            let advance = glyph.w * self.scale;
+            let (start, end) = if run.rtl {
+                let end = cursor - (edge - (glyph.x + glyph.w)) * self.scale;
+                edge = glyph.x;
+                (self.chord(end, advance, -1.0), end)
+            } else {
+                let start = cursor + (glyph.x - edge) * self.scale;
+                edge = glyph.x + glyph.w;
+                (start, self.chord(start, advance, 1.0))
+            };
+            cursor = if run.rtl { start } else { end };
+
+            let fits = start >= 0.0 && end <= self.path.length();
+            if self.overflow == PathOverflow::Drop && !fits {
+                continue;
+            }
+
+            if let Some(placement) = self.place_glyph(glyph, start, end, scale_factor) {
+                placements.push(placement);
+            }
+        }
+    }
+
+    /// Returns the distance along the path, from `from` in `direction`, at which the straight
+    /// line to the point at `from` is `advance` long.
+    fn chord(&self, from: f32, advance: f32, direction: f32) -> f32 {
+        let Some(point) = self.path.point_at(from).filter(|_| advance > 0.0) else {
+            return from;
+        };
+
+        // The chord is as long as the advance on straight segments, and shorter on curves
+        let step = advance / 8.0;
+        let mut to = from + direction * advance;
+        for _ in 0..MAX_CHORD_STEPS {
+            match self.path.point_at(to) {
+                Some(end) if distance(point, end) < advance => to += direction * step,
+                _ => break,
+            }
+        }
+
+        to
+    }
+
+    /// Places `glyph` with the start of its advance at `start` along the path, rotated towards
+    /// the point at `end`.
+    fn place_glyph(
+        &self,
+        glyph: &LayoutGlyph,
+        start: f32,
+        end: f32,
+        scale_factor: f32,
+    ) -> Option<GlyphPlacement> {
+        let from = self.path.point_at(start)?;
+        let to = if end > start {
+            self.path.point_at(end)?
+        } else {
+            // Glyphs without advance follow the direction of the path
+            self.path.point_at(start + 1.0)?
+        };
+        let rotation = (to.1 - from.1).atan2(to.0 - from.0);
+
+        // The glyph is placed unrotated with the start of its advance on the path
+        let scale = self.scale * scale_factor;
+        let (x, y) = (from.0 * scale_factor, from.1 * scale_factor);
+        let mut placement =
+            GlyphPlacement::from_layout_glyph(glyph, 0.0, (x - glyph.x * scale, y), scale);
+
+        placement.transform = GlyphTransform {
+            rotation,
+            pivot: Some((x - placement.x as f32, y - placement.y as f32)),
+            ..GlyphTransform::IDENTITY
+        };
+
+        Some(placement)
+    }
+}
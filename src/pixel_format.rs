@@ -0,0 +1,88 @@
+//! The pixel formats text can be rendered into, see [`check_render_format`].
+
+use crate::UnsupportedFormatReason;
+use objc2::runtime::ProtocolObject;
+use objc2_metal::{MTLDevice, MTLGPUFamily, MTLPixelFormat};
+
+/// Returns why text cannot be rendered into targets of `format` on `device`, if it cannot.
+///
+/// Text is blended into its target, so the format must be a color format that the device can
+/// render into and blend. The supported formats are:
+///
+/// - 8-bit normalized formats: `R8Unorm`, `RG8Unorm`, `RGBA8Unorm`, `BGRA8Unorm` and their
+///   sRGB variants. `R8Unorm_sRGB` and `RG8Unorm_sRGB` need an Apple GPU.
+/// - 16-bit normalized formats: `R16Unorm`, `RG16Unorm` and `RGBA16Unorm`.
+/// - Packed formats: `RGB10A2Unorm`, `BGR10A2Unorm` and `RG11B10Float`, and `RGB9E5Float` on
+///   Apple GPUs.
+/// - Extended range formats: `BGRA10_XR`, `BGR10_XR` and their sRGB variants, on A10 or later
+///   GPUs (`MTLGPUFamily::Apple3`).
+/// - Float formats: `R16Float`, `RG16Float` and `RGBA16Float`, and `R32Float`, `RG32Float` and
+///   `RGBA32Float` on Macs and A14 or later GPUs (`MTLGPUFamily::Apple7`), which blend 32-bit
+///   floats.
+pub(crate) fn check_render_format(
+    device: &ProtocolObject<dyn MTLDevice>,
+    format: MTLPixelFormat,
+) -> Result<(), UnsupportedFormatReason> {
+    let apple = |family| device.supportsFamily(family);
+
+    let supported = match format {
+        MTLPixelFormat::R8Unorm
+        | MTLPixelFormat::RG8Unorm
+        | MTLPixelFormat::RGBA8Unorm
+        | MTLPixelFormat::RGBA8Unorm_sRGB
+        | MTLPixelFormat::BGRA8Unorm
+        | MTLPixelFormat::BGRA8Unorm_sRGB
+        | MTLPixelFormat::R16Unorm
+        | MTLPixelFormat::RG16Unorm
+        | MTLPixelFormat::RGBA16Unorm
+        | MTLPixelFormat::RGB10A2Unorm
+        | MTLPixelFormat::BGR10A2Unorm
+        | MTLPixelFormat::RG11B10Float
+        | MTLPixelFormat::R16Float
+        | MTLPixelFormat::RG16Float
+        | MTLPixelFormat::RGBA16Float => true,
+        MTLPixelFormat::R8Unorm_sRGB
+        | MTLPixelFormat::RG8Unorm_sRGB
+        | MTLPixelFormat::RGB9E5Float => apple(MTLGPUFamily::Apple1),
+        MTLPixelFormat::BGRA10_XR
+        | MTLPixelFormat::BGRA10_XR_sRGB
+        | MTLPixelFormat::BGR10_XR
+        | MTLPixelFormat::BGR10_XR_sRGB => apple(MTLGPUFamily::Apple3),
+        MTLPixelFormat::R32Float | MTLPixelFormat::RG32Float | MTLPixelFormat::RGBA32Float => {
+            apple(MTLGPUFamily::Apple7) || apple(MTLGPUFamily::Mac2)
+        }
+        MTLPixelFormat::Depth16Unorm
+        | MTLPixelFormat::Depth32Float
+        | MTLPixelFormat::Stencil8
+        | MTLPixelFormat::Depth24Unorm_Stencil8
+        | MTLPixelFormat::Depth32Float_Stencil8
+        | MTLPixelFormat::X32_Stencil8
+        | MTLPixelFormat::X24_Stencil8 => return Err(UnsupportedFormatReason::DepthStencil),
+        MTLPixelFormat::R8Uint
+        | MTLPixelFormat::R8Sint
+        | MTLPixelFormat::RG8Uint
+        | MTLPixelFormat::RG8Sint
+        | MTLPixelFormat::RGBA8Uint
+        | MTLPixelFormat::RGBA8Sint
+        | MTLPixelFormat::R16Uint
+        | MTLPixelFormat::R16Sint
+        | MTLPixelFormat::RG16Uint
+        | MTLPixelFormat::RG16Sint
+        | MTLPixelFormat::RGBA16Uint
+        | MTLPixelFormat::RGBA16Sint
+        | MTLPixelFormat::R32Uint
+        | MTLPixelFormat::R32Sint
+        | MTLPixelFormat::RG32Uint
+        | MTLPixelFormat::RG32Sint
+        | MTLPixelFormat::RGBA32Uint
+        | MTLPixelFormat::RGBA32Sint
+        | MTLPixelFormat::RGB10A2Uint => return Err(UnsupportedFormatReason::Integer),
+        _ => return Err(UnsupportedFormatReason::NotRenderable),
+    };
+
+    if supported {
+        Ok(())
+    } else {
+        Err(UnsupportedFormatReason::UnsupportedByDevice)
+    }
+}
@@ -0,0 +1,364 @@
+//! CPU and GPU timings of the text renderer. [`FrameProfile`] and the GPU timer are only
+//! available with the `profiling` feature, [`PreparePhaseTimings`] are enabled at runtime.
+
+use std::time::{Duration, Instant};
+#[cfg(feature = "profiling")]
+use {
+    block2::RcBlock,
+    objc2::{rc::Retained, runtime::ProtocolObject, Message as _},
+    objc2_foundation::NSRange,
+    objc2_metal::{
+        MTLCommandBuffer, MTLCommonCounterSetTimestamp, MTLCounterDontSample,
+        MTLCounterSampleBuffer, MTLCounterSampleBufferDescriptor, MTLCounterSamplingPoint,
+        MTLCounterSet as _, MTLDevice, MTLRenderPassDescriptor, MTLStorageMode,
+    },
+    std::{
+        cell::Cell,
+        ptr::NonNull,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+    },
+};
+
+/// CPU and GPU timings of the most recent `prepare` and `render` of a
+/// [`crate::TextRenderer`].
+#[cfg(feature = "profiling")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameProfile {
+    /// Time spent iterating text areas, layout runs and glyphs, excluding rasterization and
+    /// atlas uploads.
+    pub shaping: Duration,
+    /// Time spent rasterizing glyphs that were not cached yet.
+    pub rasterization: Duration,
+    /// Time spent uploading glyphs into the atlas, including growing it.
+    pub atlas_upload: Duration,
+    /// Time spent writing vertices into the vertex buffer.
+    pub vertex_write: Duration,
+    /// Time the GPU spent in the most recently completed render pass attached with
+    /// [`crate::TextRenderer::attach_gpu_timer`], or `None` if no attached pass completed yet or
+    /// the device does not support sampling timestamps at stage boundaries.
+    pub gpu: Option<Duration>,
+}
+
+/// CPU timings of the phases of the most recent `prepare` of a [`crate::TextRenderer`], see
+/// [`crate::TextRenderer::set_phase_timings`].
+///
+/// The phases do not overlap, so they add up to the duration of `prepare`, see
+/// [`PreparePhaseTimings::total`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PreparePhaseTimings {
+    /// Time spent iterating text areas, layout runs and glyphs, excluding the other phases.
+    pub layout: Duration,
+    /// Time spent looking glyphs up in the atlas.
+    pub lookup: Duration,
+    /// Time spent rasterizing glyphs that were not cached yet.
+    ///
+    /// High rasterization times on frames showing new text are fixed by preparing the text
+    /// ahead of time (e.g. behind a loading screen), so that its glyphs are cached.
+    pub rasterization: Duration,
+    /// The longest rasterization of a single glyph.
+    pub max_glyph_rasterization: Duration,
+    /// The number of glyphs rasterized, including glyphs without pixels (e.g. whitespace).
+    pub rasterized_glyphs: u32,
+    /// Time spent allocating glyphs in the atlas and uploading them, including growing it.
+    ///
+    /// High upload times on most frames mean that glyphs keep being evicted and rasterized
+    /// again, see [`crate::AtlasStats::rerasterizations`].
+    pub atlas_upload: Duration,
+    /// Time spent turning cached glyphs into clipped quads.
+    pub vertex_generation: Duration,
+    /// Time spent writing the quads into the vertex buffer, including reallocating it.
+    pub buffer_write: Duration,
+}
+
+impl PreparePhaseTimings {
+    /// Returns the duration of `prepare`, the sum of all phases.
+    pub fn total(&self) -> Duration {
+        self.layout
+            + self.lookup
+            + self.rasterization
+            + self.atlas_upload
+            + self.vertex_generation
+            + self.buffer_write
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Phase {
+    Shaping,
+    Lookup,
+    Rasterization,
+    AtlasUpload,
+    VertexGeneration,
+    VertexWrite,
+}
+
+/// A measurement in progress, or `None` if nothing is measured.
+pub(crate) struct Span {
+    start: Option<Instant>,
+}
+
+/// Accumulates the CPU timings of a `prepare`.
+#[derive(Debug, Default)]
+pub(crate) struct Profiler {
+    #[cfg(feature = "profiling")]
+    profile: FrameProfile,
+    /// The phase timings of the current `prepare`, if they are enabled.
+    timings: Option<PreparePhaseTimings>,
+}
+
+impl Profiler {
+    #[inline]
+    pub(crate) fn reset(&mut self) {
+        #[cfg(feature = "profiling")]
+        {
+            self.profile = FrameProfile {
+                gpu: None,
+                ..FrameProfile::default()
+            };
+        }
+
+        if let Some(timings) = &mut self.timings {
+            *timings = PreparePhaseTimings::default();
+        }
+    }
+
+    #[inline]
+    pub(crate) fn start(&self) -> Span {
+        let enabled = cfg!(feature = "profiling") || self.timings.is_some();
+
+        Span {
+            start: enabled.then(Instant::now),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn record(&mut self, phase: Phase, span: Span) {
+        let Some(start) = span.start else {
+            return;
+        };
+        let elapsed = start.elapsed();
+
+        if let Some(timings) = &mut self.timings {
+            match phase {
+                // Shaping encloses the other phases but the vertex write, which are subtracted
+                // from it
+                Phase::Shaping => {
+                    timings.layout = elapsed
+                        .saturating_sub(timings.lookup)
+                        .saturating_sub(timings.rasterization)
+                        .saturating_sub(timings.atlas_upload)
+                        .saturating_sub(timings.vertex_generation)
+                }
+                Phase::Lookup => timings.lookup += elapsed,
+                Phase::Rasterization => {
+                    timings.rasterization += elapsed;
+                    timings.max_glyph_rasterization = timings.max_glyph_rasterization.max(elapsed);
+                    timings.rasterized_glyphs += 1;
+                }
+                Phase::AtlasUpload => timings.atlas_upload += elapsed,
+                Phase::VertexGeneration => timings.vertex_generation += elapsed,
+                Phase::VertexWrite => timings.buffer_write += elapsed,
+            }
+        }
+
+        #[cfg(feature = "profiling")]
+        {
+            let profile = &mut self.profile;
+
+            match phase {
+                // Shaping encloses the other glyph phases, which are subtracted from it
+                Phase::Shaping => {
+                    profile.shaping = elapsed
+                        .saturating_sub(profile.rasterization)
+                        .saturating_sub(profile.atlas_upload)
+                }
+                Phase::Rasterization => profile.rasterization += elapsed,
+                Phase::AtlasUpload => profile.atlas_upload += elapsed,
+                Phase::VertexWrite => profile.vertex_write += elapsed,
+                // Counted as shaping
+                Phase::Lookup | Phase::VertexGeneration => {}
+            }
+        }
+    }
+
+    pub(crate) fn set_timings_enabled(&mut self, enabled: bool) {
+        if enabled != self.timings.is_some() {
+            self.timings = enabled.then(PreparePhaseTimings::default);
+        }
+    }
+
+    pub(crate) fn timings(&self) -> Option<PreparePhaseTimings> {
+        self.timings
+    }
+
+    #[cfg(feature = "profiling")]
+    pub(crate) fn profile(&self) -> FrameProfile {
+        self.profile
+    }
+}
+
+/// Samples GPU timestamps at the start of the vertex stage and the end of the fragment stage of
+/// the render passes attached with [`GpuTimer::attach`].
+///
+/// Apple GPUs only sample at stage boundaries, so the samples are taken by the render pass rather
+/// than encoded around the draws. Each frame in flight samples into a buffer of its own, which is
+/// resolved when its command buffer completes, so a frame never reads samples the GPU is still
+/// writing.
+#[cfg(feature = "profiling")]
+#[derive(Debug)]
+pub(crate) struct GpuTimer {
+    device: Retained<ProtocolObject<dyn MTLDevice>>,
+    sample_buffers: Vec<Retained<ProtocolObject<dyn MTLCounterSampleBuffer>>>,
+    /// The number of attached passes, which selects the sample buffer of the next one.
+    attached: Cell<usize>,
+    /// The GPU duration of the most recently completed pass in nanoseconds, or
+    /// [`GpuTimer::NO_DURATION`].
+    latest: Arc<AtomicU64>,
+    cpu_reference: u64,
+    gpu_reference: u64,
+}
+
+#[cfg(feature = "profiling")]
+impl GpuTimer {
+    /// The number of frames that can be in flight without sampling into a buffer the GPU has
+    /// not completed yet.
+    const FRAMES_IN_FLIGHT: usize = 3;
+    const START_INDEX: usize = 0;
+    const END_INDEX: usize = 1;
+    const NO_DURATION: u64 = u64::MAX;
+
+    /// Creates a timer, or returns `None` if the device cannot sample timestamps at stage
+    /// boundaries.
+    pub(crate) fn new(device: &ProtocolObject<dyn MTLDevice>) -> Option<Self> {
+        if !device.supportsCounterSampling(MTLCounterSamplingPoint::AtStageBoundary) {
+            return None;
+        }
+
+        let counter_sets = device.counterSets()?;
+        let timestamp_set = counter_sets
+            .iter()
+            .find(|set| &*set.name() == unsafe { MTLCommonCounterSetTimestamp })?;
+
+        let descriptor = MTLCounterSampleBufferDescriptor::new();
+        descriptor.setCounterSet(Some(&timestamp_set));
+        descriptor.setStorageMode(MTLStorageMode::Shared);
+        unsafe { descriptor.setSampleCount(2) };
+
+        let sample_buffers = (0..Self::FRAMES_IN_FLIGHT)
+            .map(|_| {
+                device
+                    .newCounterSampleBufferWithDescriptor_error(&descriptor)
+                    .ok()
+            })
+            .collect::<Option<_>>()?;
+
+        let (cpu_reference, gpu_reference) = sample_timestamps(device);
+
+        Some(Self {
+            device: device.retain(),
+            sample_buffers,
+            attached: Cell::new(0),
+            latest: Arc::new(AtomicU64::new(Self::NO_DURATION)),
+            cpu_reference,
+            gpu_reference,
+        })
+    }
+
+    /// Samples the render pass of `descriptor` into the sample buffer of the next frame, and
+    /// resolves the samples when `command_buffer` completes.
+    pub(crate) fn attach(
+        &self,
+        descriptor: &MTLRenderPassDescriptor,
+        command_buffer: &ProtocolObject<dyn MTLCommandBuffer>,
+    ) {
+        let index = self.attached.get() % Self::FRAMES_IN_FLIGHT;
+        self.attached.set(self.attached.get().wrapping_add(1));
+        let sample_buffer = &self.sample_buffers[index];
+
+        let attachment = unsafe {
+            descriptor
+                .sampleBufferAttachments()
+                .objectAtIndexedSubscript(0)
+        };
+        attachment.setSampleBuffer(Some(sample_buffer));
+        unsafe {
+            attachment.setStartOfVertexSampleIndex(Self::START_INDEX);
+            attachment.setEndOfVertexSampleIndex(MTLCounterDontSample);
+            attachment.setStartOfFragmentSampleIndex(MTLCounterDontSample);
+            attachment.setEndOfFragmentSampleIndex(Self::END_INDEX);
+        }
+
+        let device = self.device.clone();
+        let sample_buffer = sample_buffer.clone();
+        let latest = Arc::clone(&self.latest);
+        let (cpu_reference, gpu_reference) = (self.cpu_reference, self.gpu_reference);
+        let handler = RcBlock::new(move |_: NonNull<ProtocolObject<dyn MTLCommandBuffer>>| {
+            if let Some(duration) = resolve(&device, &sample_buffer, cpu_reference, gpu_reference) {
+                latest.store(duration.as_nanos() as u64, Ordering::Relaxed);
+            }
+        });
+        unsafe { command_buffer.addCompletedHandler(RcBlock::as_ptr(&handler)) };
+    }
+
+    /// Returns the GPU duration of the most recently completed pass.
+    pub(crate) fn latest(&self) -> Option<Duration> {
+        let nanos = self.latest.load(Ordering::Relaxed);
+
+        (nanos != Self::NO_DURATION).then(|| Duration::from_nanos(nanos))
+    }
+}
+
+/// Returns a CPU timestamp in nanoseconds and a GPU timestamp in device ticks sampled at the same
+/// time.
+#[cfg(feature = "profiling")]
+fn sample_timestamps(device: &ProtocolObject<dyn MTLDevice>) -> (u64, u64) {
+    let (mut cpu, mut gpu) = (0, 0);
+    unsafe {
+        device.sampleTimestamps_gpuTimestamp(NonNull::from(&mut cpu), NonNull::from(&mut gpu))
+    };
+
+    (cpu, gpu)
+}
+
+/// Reads the duration between the two samples of a completed pass.
+#[cfg(feature = "profiling")]
+fn resolve(
+    device: &ProtocolObject<dyn MTLDevice>,
+    sample_buffer: &ProtocolObject<dyn MTLCounterSampleBuffer>,
+    cpu_reference: u64,
+    gpu_reference: u64,
+) -> Option<Duration> {
+    let data = unsafe { sample_buffer.resolveCounterRange(NSRange::new(0, 2)) }?;
+    let bytes = data.to_vec();
+    let timestamp = |index: usize| {
+        let bytes = bytes.get(index * 8..index * 8 + 8)?;
+        Some(u64::from_ne_bytes(bytes.try_into().ok()?))
+    };
+
+    let (start, end) = (
+        timestamp(GpuTimer::START_INDEX)?,
+        timestamp(GpuTimer::END_INDEX)?,
+    );
+
+    // Unsampled and failed samples are reported as zero and `MTLCounterErrorValue`
+    if start == 0 || end == u64::MAX || end <= start {
+        return None;
+    }
+
+    // GPU timestamps are in device specific ticks, so convert them using CPU nanoseconds sampled
+    // at the same time
+    let (cpu_now, gpu_now) = sample_timestamps(device);
+
+    let cpu_elapsed = cpu_now.checked_sub(cpu_reference)? as f64;
+    let gpu_elapsed = gpu_now.checked_sub(gpu_reference)? as f64;
+    if gpu_elapsed == 0.0 {
+        return None;
+    }
+
+    let nanos = (end - start) as f64 * cpu_elapsed / gpu_elapsed;
+
+    Some(Duration::from_nanos(nanos as u64))
+}
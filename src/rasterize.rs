@@ -0,0 +1,404 @@
+//! Rasterization of text glyphs, see [`GlyphRasterConfig`] and [`BitmapStrikePolicy`].
+
+use cosmic_text::{CacheKey, CacheKeyFlags, FontSystem, SwashCache, SwashContent, SwashImage};
+use std::cell::RefCell;
+use swash::{
+    scale::{Render, ScaleContext, Source, StrikeWith},
+    zeno::{Angle, Format, Transform, Vector},
+};
+
+/// Options of the rasterization of text glyphs, see [`crate::TextRenderer::set_raster_config`].
+///
+/// Glyphs rasterized with different options are cached separately in the atlas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphRasterConfig {
+    /// The strength of synthetic bold, in pixels by which outlines are expanded. `0.0` keeps
+    /// outlines as they are.
+    pub embolden: f32,
+    /// The angle of synthetic italic, in degrees by which glyphs are slanted to the right. `0.0`
+    /// keeps glyphs upright.
+    ///
+    /// This adds to the slant cosmic-text applies to fonts without an italic face.
+    pub oblique: f32,
+    /// Whether outlines are hinted, which aligns them to the pixel grid and keeps small text
+    /// sharp at the cost of its shapes.
+    pub hinting: bool,
+}
+
+impl Default for GlyphRasterConfig {
+    fn default() -> Self {
+        Self {
+            embolden: 0.0,
+            oblique: 0.0,
+            hinting: true,
+        }
+    }
+}
+
+impl GlyphRasterConfig {
+    pub(crate) fn key(self) -> RasterConfigKey {
+        RasterConfigKey {
+            embolden: self.embolden.to_bits(),
+            oblique: self.oblique.to_bits(),
+            hinting: self.hinting,
+        }
+    }
+}
+
+/// A [`GlyphRasterConfig`] as part of the cache key of glyphs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct RasterConfigKey {
+    embolden: u32,
+    oblique: u32,
+    hinting: bool,
+}
+
+impl RasterConfigKey {
+    pub fn config(self) -> GlyphRasterConfig {
+        GlyphRasterConfig {
+            embolden: f32::from_bits(self.embolden),
+            oblique: f32::from_bits(self.oblique),
+            hinting: self.hinting,
+        }
+    }
+}
+
+/// How glyphs of fonts made of bitmap strikes (e.g. Apple Color Emoji) are rasterized at sizes
+/// the font has no strike for.
+///
+/// Such fonts only contain the glyphs at a few sizes. A glyph rendered from a strike of another
+/// size is resampled when it is rasterized, which [`crate::AtlasStats::strike_substitutions`]
+/// counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum BitmapStrikePolicy {
+    /// Uses the smallest strike at least as large as the requested size and shrinks it, or the
+    /// largest strike if all of them are smaller.
+    ///
+    /// Shrinking keeps glyphs sharp, at the cost of rasterizing from larger bitmaps.
+    #[default]
+    NearestLarger,
+    /// Uses the strike closest to the requested size, shrinking or enlarging it.
+    ///
+    /// Glyphs enlarged from a smaller strike look blurry.
+    Nearest,
+    /// Only uses a strike of exactly the requested size. At other sizes, glyphs are rasterized
+    /// as [`SwashCache`] rasterizes them.
+    Exact,
+}
+
+impl BitmapStrikePolicy {
+    /// Returns the index and size of the strike to use at `size` among `strikes`.
+    fn select(
+        self,
+        mut strikes: impl Iterator<Item = (u16, u16)>,
+        size: f32,
+    ) -> Option<(u16, u16)> {
+        let distance = |&(_, ppem): &(u16, u16)| (ppem as f32 - size).abs();
+
+        match self {
+            Self::NearestLarger => {
+                let (larger, smaller): (Vec<_>, Vec<_>) =
+                    strikes.partition(|&(_, ppem)| ppem as f32 >= size);
+
+                larger
+                    .into_iter()
+                    .min_by_key(|&(_, ppem)| ppem)
+                    .or_else(|| smaller.into_iter().max_by_key(|&(_, ppem)| ppem))
+            }
+            Self::Nearest => strikes.min_by(|a, b| distance(a).total_cmp(&distance(b))),
+            Self::Exact => strikes.find(|strike| distance(strike) == 0.0),
+        }
+    }
+}
+
+/// The result of [`text_glyph`].
+pub(crate) struct StrikeImage {
+    pub image: SwashImage,
+    /// Whether the image was resampled from a strike of another size.
+    pub substituted: bool,
+}
+
+thread_local! {
+    static SCALE_CONTEXT: RefCell<ScaleContext> = RefCell::new(ScaleContext::new());
+}
+
+/// Rasterizes the glyph of `cache_key`, selecting the bitmap strike with `policy` if its font
+/// has color bitmap strikes. Other glyphs are rasterized with `cache`, unless `config` differs
+/// from the default.
+pub(crate) fn text_glyph(
+    cache: &mut SwashCache,
+    font_system: &mut FontSystem,
+    cache_key: CacheKey,
+    policy: BitmapStrikePolicy,
+    config: GlyphRasterConfig,
+) -> Option<StrikeImage> {
+    let uncached = |cache: &mut SwashCache, font_system: &mut FontSystem| {
+        let image = if config == GlyphRasterConfig::default() {
+            cache.get_image_uncached(font_system, cache_key)
+        } else {
+            render_with_config(font_system, cache_key, config)
+        };
+
+        image.map(|image| StrikeImage {
+            image,
+            substituted: false,
+        })
+    };
+
+    let size = f32::from_bits(cache_key.font_size_bits);
+    let Some(font) = font_system.get_font(cache_key.font_id) else {
+        return uncached(cache, font_system);
+    };
+
+    let font_ref = font.as_swash();
+    let strikes = font_ref
+        .color_strikes()
+        .enumerate()
+        .map(|(index, strike)| (index as u16, strike.ppem()));
+
+    let Some((index, ppem)) = policy.select(strikes, size) else {
+        return uncached(cache, font_system);
+    };
+
+    if ppem as f32 == size {
+        // Strikes of the requested size are used as they are
+        return uncached(cache, font_system);
+    }
+
+    // Rasterize the strike at its own size, so that swash does not resample it
+    let image = SCALE_CONTEXT.with_borrow_mut(|context| {
+        let mut scaler = context.builder(font_ref).size(ppem as f32).build();
+
+        Render::new(&[Source::ColorBitmap(StrikeWith::Index(index.into()))])
+            .format(Format::Alpha)
+            .render(&mut scaler, cache_key.glyph_id)
+    });
+
+    let Some(mut image) = image.filter(|image| {
+        image.content == SwashContent::Color
+            && image.placement.width > 0
+            && image.placement.height > 0
+    }) else {
+        return uncached(cache, font_system);
+    };
+
+    let scale = size / ppem as f32;
+    let placement = &mut image.placement;
+    let new_width = ((placement.width as f32 * scale).round() as u32).max(1);
+    let new_height = ((placement.height as f32 * scale).round() as u32).max(1);
+
+    image.data = resample(
+        &image.data,
+        placement.width as usize,
+        placement.height as usize,
+        new_width as usize,
+        new_height as usize,
+    );
+    placement.left = (placement.left as f32 * scale).round() as i32;
+    placement.top = (placement.top as f32 * scale).round() as i32;
+    placement.width = new_width;
+    placement.height = new_height;
+
+    Some(StrikeImage {
+        image,
+        substituted: true,
+    })
+}
+
+/// Rasterizes the glyph of `cache_key` the way [`SwashCache`] does, with the options of `config`.
+fn render_with_config(
+    font_system: &mut FontSystem,
+    cache_key: CacheKey,
+    config: GlyphRasterConfig,
+) -> Option<SwashImage> {
+    let font = font_system.get_font(cache_key.font_id)?;
+
+    let mut oblique = config.oblique;
+    if cache_key.flags.contains(CacheKeyFlags::FAKE_ITALIC) {
+        // The slant cosmic-text gives fonts without an italic face
+        oblique += 14.0;
+    }
+
+    SCALE_CONTEXT.with_borrow_mut(|context| {
+        let mut scaler = context
+            .builder(font.as_swash())
+            .size(f32::from_bits(cache_key.font_size_bits))
+            .hint(config.hinting)
+            .build();
+
+        let mut render = Render::new(&[
+            Source::ColorOutline(0),
+            Source::ColorBitmap(StrikeWith::BestFit),
+            Source::Outline,
+        ]);
+        render
+            .format(Format::Alpha)
+            .offset(Vector::new(
+                cache_key.x_bin.as_float(),
+                cache_key.y_bin.as_float(),
+            ))
+            .embolden(config.embolden);
+
+        if oblique != 0.0 {
+            render.transform(Some(Transform::skew(
+                Angle::from_degrees(oblique),
+                Angle::from_degrees(0.0),
+            )));
+        }
+
+        render.render(&mut scaler, cache_key.glyph_id)
+    })
+}
+
+/// The box drawn in place of a glyph missing from its font, see
+/// [`crate::TextRenderer::set_tofu`].
+///
+/// Boxes only depend on their size, so a box is rasterized once per size whatever the font.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct TofuKey {
+    /// The width of the advance of the missing glyph, which the box is centered in.
+    pub advance: u16,
+    /// The height of the box, the cap height of the font.
+    pub height: u16,
+}
+
+impl TofuKey {
+    /// The cap height of fonts that do not specify one, relative to the font size.
+    const DEFAULT_CAP_HEIGHT: f32 = 0.7;
+
+    /// Returns the box of the missing glyph of `cache_key`, whose physical advance is `advance`.
+    pub fn new(font_system: &mut FontSystem, cache_key: CacheKey, advance: f32) -> Self {
+        let cap_height = font_system
+            .get_font(cache_key.font_id)
+            .map(|font| {
+                let metrics = font.as_swash().metrics(&[]);
+                metrics.cap_height / metrics.units_per_em as f32
+            })
+            .filter(|cap_height| *cap_height > 0.0)
+            .unwrap_or(Self::DEFAULT_CAP_HEIGHT);
+        let height = cap_height * f32::from_bits(cache_key.font_size_bits);
+
+        Self {
+            advance: advance.round().clamp(0.0, u16::MAX as f32) as u16,
+            height: height.round().clamp(0.0, u16::MAX as f32) as u16,
+        }
+    }
+
+    /// Returns the space between the box and each end of the advance, which keeps the boxes of
+    /// consecutive missing glyphs apart.
+    pub fn inset(self) -> u16 {
+        (self.advance / 10).max(1).min(self.advance / 2)
+    }
+
+    /// Returns the width of the box.
+    pub fn width(self) -> u16 {
+        self.advance - 2 * self.inset()
+    }
+
+    /// Returns the mask of the outline of the box, one byte per pixel, with a stroke of about a
+    /// twelfth of its height.
+    pub fn image(self) -> Vec<u8> {
+        let (width, height) = (self.width() as usize, self.height as usize);
+        let stroke = (height / 12).max(1);
+
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let edge = x < stroke || y < stroke || x + stroke >= width || y + stroke >= height;
+                if edge {
+                    u8::MAX
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+}
+
+/// Resamples a `width` x `height` RGBA image to `new_width` x `new_height` with a triangle
+/// filter, which interpolates bilinearly when enlarging and averages all covered texels when
+/// shrinking.
+fn resample(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    new_width: usize,
+    new_height: usize,
+) -> Vec<u8> {
+    // Filter premultiplied colors, so that the color of transparent texels does not bleed in
+    let texels: Vec<[f32; 4]> = data
+        .chunks_exact(4)
+        .map(|texel| {
+            let alpha = texel[3] as f32 / 255.0;
+            [
+                texel[0] as f32 * alpha,
+                texel[1] as f32 * alpha,
+                texel[2] as f32 * alpha,
+                texel[3] as f32,
+            ]
+        })
+        .collect();
+
+    let columns = weights(width, new_width);
+    let rows = weights(height, new_height);
+
+    let mut horizontal = vec![[0.0; 4]; new_width * height];
+    for y in 0..height {
+        for (x, (start, weights)) in columns.iter().enumerate() {
+            let source = &texels[y * width + start..];
+            horizontal[y * new_width + x] = weighted_sum(weights, |i| source[i]);
+        }
+    }
+
+    let mut resampled = Vec::with_capacity(new_width * new_height * 4);
+    for (start, weights) in &rows {
+        for x in 0..new_width {
+            let [r, g, b, a] = weighted_sum(weights, |i| horizontal[(start + i) * new_width + x]);
+            let unpremultiply = if a > 0.0 { 255.0 / a } else { 0.0 };
+
+            resampled.extend(
+                [r * unpremultiply, g * unpremultiply, b * unpremultiply, a]
+                    .map(|channel| channel.round().clamp(0.0, 255.0) as u8),
+            );
+        }
+    }
+
+    resampled
+}
+
+/// Returns the first source texel and the normalized filter weights of each of the `new_size`
+/// texels resampled from `size` texels.
+fn weights(size: usize, new_size: usize) -> Vec<(usize, Vec<f32>)> {
+    let scale = new_size as f32 / size as f32;
+    let support = (1.0 / scale).max(1.0);
+
+    (0..new_size)
+        .map(|i| {
+            let center = (i as f32 + 0.5) / scale;
+            let start = (center - support).floor().max(0.0) as usize;
+            let end = ((center + support).ceil() as usize).min(size);
+
+            let mut weights: Vec<f32> = (start..end)
+                .map(|j| (1.0 - ((j as f32 + 0.5 - center) / support).abs()).max(0.0))
+                .collect();
+            let sum: f32 = weights.iter().sum();
+            if sum > 0.0 {
+                weights.iter_mut().for_each(|weight| *weight /= sum);
+            }
+
+            (start, weights)
+        })
+        .collect()
+}
+
+fn weighted_sum(weights: &[f32], texel: impl Fn(usize) -> [f32; 4]) -> [f32; 4] {
+    let mut sum = [0.0; 4];
+
+    for (i, &weight) in weights.iter().enumerate() {
+        for (sum, channel) in sum.iter_mut().zip(texel(i)) {
+            *sum += channel * weight;
+        }
+    }
+
+    sum
+}
@@ -0,0 +1,193 @@
+//! Serde definitions of foreign types used by the crate's own serializable types.
+
+use cosmic_text::SubpixelBin;
+use serde::{Deserialize, Serialize};
+
+/// Mirror of [`SubpixelBin`], which is foreign to this crate.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SubpixelBin", rename_all = "snake_case")]
+pub(crate) enum SubpixelBinDef {
+    Zero,
+    One,
+    Two,
+    Three,
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use crate::{
+        AlphaMode, AtlasPacking, AtlasStats, BlendMode, CachePriority, Color, ColorAtlasFormat,
+        ColorMode, ContentType, CustomGlyph, EncoderViewport, FontSynthesis, FrameValidation,
+        RasterizeCustomGlyphRequest, RasterizedCustomGlyph, Resolution, SingleChannelOutput,
+        TargetColorSpace, TextBounds, TrimPolicy, ViewTransform,
+    };
+    use cosmic_text::SubpixelBin;
+    use serde::{de::DeserializeOwned, Deserialize, Serialize};
+    use std::fmt::Debug;
+
+    #[track_caller]
+    fn round_trip<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: T) -> String {
+        let json = serde_json::to_string(&value).expect("Serialize");
+        let deserialized: T = serde_json::from_str(&json).expect("Deserialize");
+        assert_eq!(deserialized, value, "{json}");
+
+        json
+    }
+
+    #[test]
+    fn plain_structs() {
+        round_trip(Resolution {
+            width: 1920,
+            height: 1080,
+        });
+        round_trip(TextBounds {
+            left: -10,
+            top: 20,
+            right: i32::MAX,
+            bottom: i32::MIN,
+        });
+        round_trip(FrameValidation {
+            render_before_prepare: true,
+            prepare_without_render: false,
+            trim_before_render: true,
+        });
+        round_trip(ViewTransform::scale_translate(2.0, -3.5, 4.25));
+        round_trip(EncoderViewport {
+            origin: (64, 32),
+            scissor: true,
+        });
+    }
+
+    #[test]
+    fn atlas_stats() {
+        let stats = AtlasStats {
+            size: 512,
+            glyph_count: 42,
+            occupancy: 0.25,
+            packing_efficiency: 0.75,
+            grows: 1,
+            evictions: 2,
+            hits: 3,
+            misses: 4,
+            rasterizations: 5,
+            rerasterizations: 6,
+            culled: 7,
+            strike_substitutions: 8,
+            tofu: 9,
+            pinned_glyphs: 10,
+            pinned_area: 11,
+            texture_writes: 12,
+            uploaded_bytes: 13,
+        };
+        let json = round_trip(stats);
+
+        // Fields added after the first release default when missing
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let object = value.as_object_mut().unwrap();
+        for field in [
+            "packing_efficiency",
+            "tofu",
+            "pinned_glyphs",
+            "pinned_area",
+            "uploaded_bytes",
+        ] {
+            object.remove(field);
+        }
+        let stats: AtlasStats = serde_json::from_value(value).unwrap();
+        assert_eq!(stats.tofu, 0);
+        assert_eq!(stats.uploaded_bytes, 0);
+        assert_eq!(stats.texture_writes, 12);
+    }
+
+    #[test]
+    fn enums() {
+        assert_eq!(round_trip(ColorMode::Web), r#""web""#);
+        round_trip(ColorMode::Accurate);
+        assert_eq!(round_trip(ColorAtlasFormat::Bgra8), r#""bgra8""#);
+        round_trip(ColorAtlasFormat::Rgba8);
+        assert_eq!(round_trip(TargetColorSpace::DisplayP3), r#""display_p3""#);
+        round_trip(TargetColorSpace::Srgb);
+        assert_eq!(round_trip(ContentType::Mask), r#""mask""#);
+        round_trip(ContentType::Color);
+        round_trip(SingleChannelOutput::Coverage);
+        round_trip(SingleChannelOutput::Luminance);
+        round_trip(AlphaMode::Straight);
+        assert_eq!(round_trip(AlphaMode::Premultiplied), r#""premultiplied""#);
+        round_trip(BlendMode::Over);
+        assert_eq!(
+            round_trip(BlendMode::DestinationOut),
+            r#""destination_out""#
+        );
+        round_trip(FontSynthesis::None);
+        round_trip(FontSynthesis::Embolden);
+        round_trip(AtlasPacking::Bucketed);
+        round_trip(AtlasPacking::Guillotine);
+        round_trip(CachePriority::Normal);
+        round_trip(CachePriority::Pinned);
+        assert_eq!(round_trip(TrimPolicy::EveryFrame), r#""every_frame""#);
+        assert_eq!(
+            round_trip(TrimPolicy::KeepFrames(3)),
+            r#"{"keep_frames":3}"#
+        );
+        round_trip(TrimPolicy::ByteBudget(1 << 20));
+        round_trip(TrimPolicy::Manual);
+    }
+
+    #[test]
+    fn colors() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Colors {
+            #[serde(with = "crate::color_serde")]
+            color: Color,
+            #[serde(with = "crate::color_serde::option")]
+            some: Option<Color>,
+            #[serde(with = "crate::color_serde::option")]
+            none: Option<Color>,
+        }
+
+        let json = round_trip(Colors {
+            color: Color::rgba(0x12, 0x34, 0x56, 0x78),
+            some: Some(Color::rgb(255, 0, 0)),
+            none: None,
+        });
+        assert_eq!(
+            json,
+            r#"{"color":2014458966,"some":4294901760,"none":null}"#
+        );
+    }
+
+    #[test]
+    fn custom_glyphs() {
+        let json = round_trip(CustomGlyph {
+            id: 7,
+            left: 1.5,
+            top: -2.0,
+            width: 16.0,
+            height: 24.0,
+            color: Some(Color::rgb(0, 128, 255)),
+            snap_to_physical_pixel: true,
+            metadata: 99,
+        });
+        assert!(json.contains(r#""color":4278223103"#), "{json}");
+        round_trip(CustomGlyph::default());
+
+        let json = round_trip(RasterizeCustomGlyphRequest {
+            id: 7,
+            width: 32,
+            height: 48,
+            x_bin: SubpixelBin::Two,
+            y_bin: SubpixelBin::Three,
+            scale: 2.0,
+        });
+        assert!(json.contains(r#""x_bin":"two","y_bin":"three""#), "{json}");
+
+        let glyph = RasterizedCustomGlyph {
+            data: vec![0, 64, 128, 255],
+            content_type: ContentType::Mask,
+        };
+        let json = serde_json::to_string(&glyph).unwrap();
+        let deserialized: RasterizedCustomGlyph = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.data, glyph.data);
+        assert_eq!(deserialized.content_type, glyph.content_type);
+    }
+}
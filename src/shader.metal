@@ -1,24 +1,60 @@
 #include <metal_stdlib>
 using namespace metal;
 
+// The start of the vertex buffer, followed by a `VertexInput` per glyph
+struct VertexHeader {
+    // The offset in bytes of a `QuadTransform` per glyph, or 0 if no quad is transformed
+    uint transform_offset;
+    // The sRGB-encoded luminances of the background hints of the glyphs
+    uint background_hints[15];
+};
+
+// The top bit of each 16-bit half of `dim` and `uv` holds a flag, and the bit below it a bit of
+// the index of the background hint of the glyph, see `GlyphVertex`
 struct VertexInput {
     packed_int2 pos;
     uint dim;
     uint uv;
     uint color;
-    uint content_type_with_srgb;
     float depth;
 };
 
+struct QuadTransform {
+    // Two half floats: the scale of the quad around its center times the cosine and the sine of
+    // its rotation
+    uint linear;
+    // Two half floats: the fraction of a pixel the quad is moved by
+    uint fraction;
+};
+
+// The content type of every glyph of a draw (0 for color, 1 for mask). Pipelines specialized for
+// a content type only declare, and therefore only need, the atlas texture of that type. Without
+// it, both atlases are bound and the content type is read from each glyph.
+constant uint draw_content_type [[function_constant(0)]];
+constant bool is_specialized = is_function_constant_defined(draw_content_type);
+constant bool uses_color_atlas = !is_specialized || draw_content_type == 0u;
+constant bool uses_mask_atlas = !is_specialized || draw_content_type == 1u;
+
 struct VertexOutput {
     float4 position [[position]];
     float4 color;
     float2 uv;
     uint content_type [[flat]];
+    // Bit 0: the target stores linear colors, bit 1: Display P3, bit 2: sRGB color atlas, bit 3:
+    // the coverage is corrected, see `correct_coverage`
+    uint color_flags [[flat]];
+    // The linear luminances of the text and of the background hint of its area
+    float2 luminance [[flat]];
 };
 
 struct Params {
     uint2 screen_resolution;
+    float headroom;
+    packed_int2 origin;
+    packed_float2 transform_x;
+    packed_float2 transform_y;
+    packed_float2 transform_translation;
+    packed_float4 tint;
 };
 
 float srgb_to_linear(float c) {
@@ -29,20 +65,88 @@ float srgb_to_linear(float c) {
     }
 }
 
-vertex VertexOutput vertex_main(
-    uint vertex_idx [[vertex_id]],
-    uint instance_idx [[instance_id]],
-    constant Params& params [[buffer(0)]],
-    constant VertexInput* instances [[buffer(1)]],
-    texture2d<float> color_atlas_texture [[texture(0)]],
-    texture2d<float> mask_atlas_texture [[texture(1)]]
+float linear_to_srgb(float c) {
+    if (c <= 0.0031308) {
+        return c * 12.92;
+    } else {
+        return 1.055 * pow(c, 1.0 / 2.4) - 0.055;
+    }
+}
+
+// The weights of the luminance of linear sRGB colors
+constant float3 LUMINANCE_WEIGHTS = float3(0.2126, 0.7152, 0.0722);
+
+// How much `correct_coverage` thickens text on a white background, the contrast Skia uses
+constant float COVERAGE_CONTRAST = 0.5;
+
+// Corrects the coverage of text darker than the background hinted for its area, given their
+// linear luminances, like the gamma-corrected masks with contrast of Skia. The coverage is first
+// thickened by a contrast that tapers off as the background darkens. Blending into targets that
+// store linear colors thins dark text compared to blending sRGB colors, so the coverage is then
+// raised until the linear blend of the text and the background matches their sRGB blend.
+float correct_coverage(float coverage, float2 luminance, bool linear_target) {
+    float text = luminance.x;
+    float background = luminance.y;
+
+    coverage += (1.0 - coverage) * COVERAGE_CONTRAST * background * coverage;
+    if (!linear_target) {
+        return coverage;
+    }
+
+    float blended =
+        srgb_to_linear(mix(linear_to_srgb(background), linear_to_srgb(text), coverage));
+    return saturate((blended - background) / (text - background));
+}
+
+uint glyph_content_type(uint content_type) {
+    return is_specialized ? draw_content_type : content_type;
+}
+
+float3 srgb_to_linear3(float3 c) {
+    return float3(srgb_to_linear(c.r), srgb_to_linear(c.g), srgb_to_linear(c.b));
+}
+
+float3 linear_to_srgb3(float3 c) {
+    return float3(linear_to_srgb(c.r), linear_to_srgb(c.g), linear_to_srgb(c.b));
+}
+
+// Converts linear sRGB primaries to linear Display P3 primaries. Both color spaces share the
+// same white point and transfer function, so only the primaries need to be remapped.
+float3 linear_srgb_to_display_p3(float3 c) {
+    return float3(
+        dot(float3(0.8224621, 0.1775380, 0.0000000), c),
+        dot(float3(0.0331941, 0.9668058, 0.0000000), c),
+        dot(float3(0.0170827, 0.0723974, 0.9105199), c)
+    );
+}
+
+// Converts an sRGB color to Display P3. `is_linear` tells whether `c` is linear or still
+// encoded with the sRGB transfer function, in which case the result is encoded as well.
+float3 srgb_to_display_p3(float3 c, bool is_linear) {
+    if (is_linear) {
+        return linear_srgb_to_display_p3(c);
+    } else {
+        return linear_to_srgb3(linear_srgb_to_display_p3(srgb_to_linear3(c)));
+    }
+}
+
+VertexOutput glyph_vertex(
+    uint vertex_idx,
+    uint instance_idx,
+    constant Params& params,
+    constant VertexHeader& header,
+    texture2d<float> color_atlas_texture,
+    texture2d<float> mask_atlas_texture
 ) {
-    VertexInput in_vert = instances[instance_idx];
+    VertexInput in_vert = ((constant VertexInput*)(&header + 1))[instance_idx];
     int2 pos = in_vert.pos;
-    uint width = in_vert.dim & 0xffffu;
-    uint height = (in_vert.dim & 0xffff0000u) >> 16u;
+    uint width = in_vert.dim & 0x3fffu;
+    uint height = (in_vert.dim >> 16u) & 0x3fffu;
     uint color = in_vert.color;
-    uint2 uv = uint2(in_vert.uv & 0xffffu, (in_vert.uv & 0xffff0000u) >> 16u);
+    uint2 uv = uint2(in_vert.uv & 0x3fffu, (in_vert.uv >> 16u) & 0x3fffu);
+    // The index of the background hint plus one, or 0 without a hint
+    uint hint = ((in_vert.uv >> 14u) & 1u) | ((in_vert.uv >> 29u) & 2u)
+        | ((in_vert.dim >> 12u) & 4u) | ((in_vert.dim >> 27u) & 8u);
 
     uint2 corner_position = uint2(
         vertex_idx & 1u,
@@ -51,61 +155,308 @@ vertex VertexOutput vertex_main(
     uint2 corner_offset = uint2(width, height) * corner_position;
 
     uv = uv + corner_offset;
-    pos = pos + int2(corner_offset);
+
+    // Scale and rotate the quad around its center, if any quad of the buffer is transformed
+    float2 quad_pos = float2(pos) + float2(corner_offset);
+    if (header.transform_offset != 0u) {
+        constant QuadTransform* transforms =
+            (constant QuadTransform*)((constant uchar*)&header + header.transform_offset);
+        QuadTransform transform = transforms[instance_idx];
+        float2 half_size = float2(width, height) * 0.5;
+        float2 linear = float2(as_type<half2>(transform.linear));
+        float2 local = float2(corner_offset) - half_size;
+        quad_pos = float2(pos) + float2(as_type<half2>(transform.fraction)) + half_size
+            + float2(
+                linear.x * local.x - linear.y * local.y, linear.y * local.x + linear.x * local.y
+            );
+    }
+
+    // Apply the view transform, then the origin offset
+    float2 screen_pos = float2(params.transform_x) * quad_pos.x
+        + float2(params.transform_y) * quad_pos.y
+        + float2(params.transform_translation)
+        + float2(params.origin);
 
     VertexOutput vert_output;
     vert_output.position = float4(
-        2.0 * float2(pos) / float2(params.screen_resolution) - 1.0,
+        2.0 * screen_pos / float2(params.screen_resolution) - 1.0,
         in_vert.depth,
         1.0
     );
     vert_output.position.y *= -1.0;
 
-    uint content_type = in_vert.content_type_with_srgb & 0xffffu;
-    uint srgb = (in_vert.content_type_with_srgb & 0xffff0000u) >> 16u;
+    uint content_type = glyph_content_type((in_vert.dim >> 15u) & 1u);
+    uint srgb = in_vert.dim >> 31u;
+    bool display_p3 = ((in_vert.uv >> 15u) & 1u) != 0u;
+    bool srgb_atlas = (in_vert.uv >> 31u) != 0u;
+    uint color_flags = srgb | (display_p3 ? 2u : 0u) | (srgb_atlas ? 4u : 0u);
 
-    if (srgb == 0u) {
-        vert_output.color = float4(
-            float((color & 0x00ff0000u) >> 16u) / 255.0,
-            float((color & 0x0000ff00u) >> 8u) / 255.0,
-            float(color & 0x000000ffu) / 255.0,
-            float((color & 0xff000000u) >> 24u) / 255.0
-        );
-    } else if (srgb == 1u) {
-        vert_output.color = float4(
-            srgb_to_linear(float((color & 0x00ff0000u) >> 16u) / 255.0),
-            srgb_to_linear(float((color & 0x0000ff00u) >> 8u) / 255.0),
-            srgb_to_linear(float(color & 0x000000ffu) / 255.0),
-            float((color & 0xff000000u) >> 24u) / 255.0
+    float4 text_color = float4(
+        float((color & 0x00ff0000u) >> 16u) / 255.0,
+        float((color & 0x0000ff00u) >> 8u) / 255.0,
+        float(color & 0x000000ffu) / 255.0,
+        float((color & 0xff000000u) >> 24u) / 255.0
+    );
+
+    // Only text darker than the background hint of its area is corrected, with a margin that
+    // keeps the correction stable when both are nearly equal
+    float2 luminance = float2(0.0);
+    if (content_type == 1u && hint != 0u) {
+        luminance = float2(
+            dot(srgb_to_linear3(text_color.rgb), LUMINANCE_WEIGHTS),
+            srgb_to_linear(float(header.background_hints[hint - 1u]) / 255.0)
         );
+        if (luminance.x + 1.0 / 255.0 < luminance.y) {
+            color_flags |= 8u;
+        }
+    }
+    if (content_type == 0u) {
+        // Color glyphs take their color from the atlas and are only tinted
+        text_color = float4(1.0);
+    }
+
+    float4 tint = float4(params.tint);
+    if (srgb == 1u) {
+        text_color.rgb = srgb_to_linear3(text_color.rgb);
+        tint.rgb = srgb_to_linear3(tint.rgb);
+    }
+
+    vert_output.color = text_color * tint;
+
+    if (display_p3) {
+        vert_output.color.rgb = srgb_to_display_p3(vert_output.color.rgb, srgb == 1u);
     }
 
+    vert_output.color.rgb *= params.headroom;
+
     uint2 dim = uint2(0u);
-    if (content_type == 0u) {
+    if (uses_color_atlas && content_type == 0u) {
         dim = uint2(color_atlas_texture.get_width(), color_atlas_texture.get_height());
-    } else if (content_type == 1u) {
+    } else if (uses_mask_atlas && content_type == 1u) {
         dim = uint2(mask_atlas_texture.get_width(), mask_atlas_texture.get_height());
     }
 
     vert_output.content_type = content_type;
+    vert_output.color_flags = color_flags;
+    vert_output.luminance = luminance;
     vert_output.uv = float2(uv) / float2(dim);
 
     return vert_output;
 }
 
-fragment float4 fragment_main(
-    VertexOutput in_frag [[stage_in]],
-    texture2d<float> color_atlas_texture [[texture(0)]],
-    texture2d<float> mask_atlas_texture [[texture(1)]]
+vertex VertexOutput vertex_main(
+    uint vertex_idx [[vertex_id]],
+    uint instance_idx [[instance_id]],
+    constant Params& params [[buffer(0)]],
+    constant VertexHeader& header [[buffer(1)]],
+    texture2d<float> color_atlas_texture [[texture(0), function_constant(uses_color_atlas)]],
+    texture2d<float> mask_atlas_texture [[texture(1), function_constant(uses_mask_atlas)]]
+) {
+    return glyph_vertex(
+        vertex_idx, instance_idx, params, header, color_atlas_texture, mask_atlas_texture
+    );
+}
+
+// A vertex of a layered draw, which also selects the layer of the render target it is drawn
+// into. The fragment functions read the members of `VertexOutput` only.
+struct LayeredVertexOutput {
+    float4 position [[position]];
+    float4 color;
+    float2 uv;
+    uint content_type [[flat]];
+    uint color_flags [[flat]];
+    float2 luminance [[flat]];
+    uint layer [[render_target_array_index]];
+};
+
+LayeredVertexOutput layered_vertex(VertexOutput vert, uint layer) {
+    LayeredVertexOutput layered_output;
+    layered_output.position = vert.position;
+    layered_output.color = vert.color;
+    layered_output.uv = vert.uv;
+    layered_output.content_type = vert.content_type;
+    layered_output.color_flags = vert.color_flags;
+    layered_output.luminance = vert.luminance;
+    layered_output.layer = layer;
+    return layered_output;
+}
+
+// Draws each glyph into both views with vertex amplification, with the parameters of the first
+// view at buffer 0 and those of the second view at buffer 2
+vertex LayeredVertexOutput vertex_stereo(
+    uint vertex_idx [[vertex_id]],
+    uint instance_idx [[instance_id]],
+    ushort view [[amplification_id]],
+    constant Params& params [[buffer(0)]],
+    constant VertexHeader& header [[buffer(1)]],
+    constant Params& second_params [[buffer(2)]],
+    texture2d<float> color_atlas_texture [[texture(0), function_constant(uses_color_atlas)]],
+    texture2d<float> mask_atlas_texture [[texture(1), function_constant(uses_mask_atlas)]]
+) {
+    VertexOutput vert = glyph_vertex(
+        vertex_idx,
+        instance_idx,
+        view == 0 ? params : second_params,
+        header,
+        color_atlas_texture,
+        mask_atlas_texture
+    );
+    return layered_vertex(vert, view);
+}
+
+// Draws into the layer selected by the first vertex of the draw, which starts at four vertices
+// per layer
+vertex LayeredVertexOutput vertex_layered(
+    uint vertex_idx [[vertex_id]],
+    uint instance_idx [[instance_id]],
+    constant Params& params [[buffer(0)]],
+    constant VertexHeader& header [[buffer(1)]],
+    texture2d<float> color_atlas_texture [[texture(0), function_constant(uses_color_atlas)]],
+    texture2d<float> mask_atlas_texture [[texture(1), function_constant(uses_mask_atlas)]]
+) {
+    VertexOutput vert = glyph_vertex(
+        vertex_idx & 3u, instance_idx, params, header, color_atlas_texture, mask_atlas_texture
+    );
+    return layered_vertex(vert, vertex_idx >> 2u);
+}
+
+// Like `vertex_stereo` for devices without vertex amplification, which draw once per view, into
+// the layer selected like in `vertex_layered`
+vertex LayeredVertexOutput vertex_stereo_views(
+    uint vertex_idx [[vertex_id]],
+    uint instance_idx [[instance_id]],
+    constant Params& params [[buffer(0)]],
+    constant VertexHeader& header [[buffer(1)]],
+    constant Params& second_params [[buffer(2)]],
+    texture2d<float> color_atlas_texture [[texture(0), function_constant(uses_color_atlas)]],
+    texture2d<float> mask_atlas_texture [[texture(1), function_constant(uses_mask_atlas)]]
+) {
+    uint view = (vertex_idx >> 2u) & 1u;
+    VertexOutput vert = glyph_vertex(
+        vertex_idx & 3u,
+        instance_idx,
+        view == 0u ? params : second_params,
+        header,
+        color_atlas_texture,
+        mask_atlas_texture
+    );
+    return layered_vertex(vert, view);
+}
+
+float4 sample_glyph(
+    VertexOutput in_frag,
+    constant Params& params,
+    texture2d<float> color_atlas_texture,
+    texture2d<float> mask_atlas_texture
 ) {
     constexpr sampler atlas_sampler(coord::normalized, address::repeat, filter::linear);
 
-    if (in_frag.content_type == 0u) {
-        return color_atlas_texture.sample(atlas_sampler, in_frag.uv, level(0.0));
-    } else if (in_frag.content_type == 1u) {
+    uint content_type = glyph_content_type(in_frag.content_type);
+
+    if (uses_color_atlas && content_type == 0u) {
+        float4 color = color_atlas_texture.sample(atlas_sampler, in_frag.uv, level(0.0));
+
+        // An sRGB color atlas is sampled as linear, convert samples to the space of the target
+        bool linear_target = (in_frag.color_flags & 1u) != 0u;
+        bool srgb_atlas = (in_frag.color_flags & 4u) != 0u;
+        if (linear_target && !srgb_atlas) {
+            color.rgb = srgb_to_linear3(color.rgb);
+        } else if (!linear_target && srgb_atlas) {
+            color.rgb = linear_to_srgb3(color.rgb);
+        }
+
+        if ((in_frag.color_flags & 2u) != 0u) {
+            color.rgb = srgb_to_display_p3(color.rgb, linear_target);
+        }
+        // The vertex color of color glyphs holds the tint and headroom
+        return color * in_frag.color;
+    } else if (uses_mask_atlas && content_type == 1u) {
         float mask = mask_atlas_texture.sample(atlas_sampler, in_frag.uv, level(0.0)).x;
+        if ((in_frag.color_flags & 8u) != 0u) {
+            bool linear_target = (in_frag.color_flags & 1u) != 0u;
+            mask = correct_coverage(mask, in_frag.luminance, linear_target);
+        }
         return float4(in_frag.color.rgb, in_frag.color.a * mask);
     } else {
         return float4(0.0);
     }
 }
+
+fragment float4 fragment_main(
+    VertexOutput in_frag [[stage_in]],
+    constant Params& params [[buffer(0)]],
+    texture2d<float> color_atlas_texture [[texture(0), function_constant(uses_color_atlas)]],
+    texture2d<float> mask_atlas_texture [[texture(1), function_constant(uses_mask_atlas)]]
+) {
+    return sample_glyph(in_frag, params, color_atlas_texture, mask_atlas_texture);
+}
+
+// Emits premultiplied colors for compositing with the premultiplied "over" operator.
+fragment float4 fragment_premultiplied(
+    VertexOutput in_frag [[stage_in]],
+    constant Params& params [[buffer(0)]],
+    texture2d<float> color_atlas_texture [[texture(0), function_constant(uses_color_atlas)]],
+    texture2d<float> mask_atlas_texture [[texture(1), function_constant(uses_mask_atlas)]]
+) {
+    float4 color = sample_glyph(in_frag, params, color_atlas_texture, mask_atlas_texture);
+    return float4(color.rgb * color.a, color.a);
+}
+
+// Writes the coverage of the glyph into the red channel of a single-channel target.
+fragment float4 fragment_coverage(
+    VertexOutput in_frag [[stage_in]],
+    constant Params& params [[buffer(0)]],
+    texture2d<float> color_atlas_texture [[texture(0), function_constant(uses_color_atlas)]],
+    texture2d<float> mask_atlas_texture [[texture(1), function_constant(uses_mask_atlas)]]
+) {
+    float4 color = sample_glyph(in_frag, params, color_atlas_texture, mask_atlas_texture);
+    return float4(color.a, 0.0, 0.0, color.a);
+}
+
+// Writes the coverage of the glyph multiplied by the luminance of its color into the red
+// channel of a single-channel target.
+fragment float4 fragment_luminance(
+    VertexOutput in_frag [[stage_in]],
+    constant Params& params [[buffer(0)]],
+    texture2d<float> color_atlas_texture [[texture(0), function_constant(uses_color_atlas)]],
+    texture2d<float> mask_atlas_texture [[texture(1), function_constant(uses_mask_atlas)]]
+) {
+    float4 color = sample_glyph(in_frag, params, color_atlas_texture, mask_atlas_texture);
+    float luminance = dot(color.rgb, float3(0.2126, 0.7152, 0.0722));
+    return float4(luminance * color.a, 0.0, 0.0, color.a);
+}
+
+// Writes the coverage of the glyph into every channel, see `TextRenderer::render_mask_only`.
+fragment float4 fragment_mask_only(
+    VertexOutput in_frag [[stage_in]],
+    constant Params& params [[buffer(0)]],
+    texture2d<float> color_atlas_texture [[texture(0), function_constant(uses_color_atlas)]],
+    texture2d<float> mask_atlas_texture [[texture(1), function_constant(uses_mask_atlas)]]
+) {
+    float4 color = sample_glyph(in_frag, params, color_atlas_texture, mask_atlas_texture);
+    return float4(color.a);
+}
+
+struct ResolveOutput {
+    float4 position [[position]];
+    float2 uv;
+};
+
+// Covers the target with a single triangle, see `Supersampler`.
+vertex ResolveOutput vertex_resolve(uint vertex_id [[vertex_id]]) {
+    float2 uv = float2((vertex_id << 1) & 2, vertex_id & 2);
+
+    ResolveOutput out;
+    out.position = float4(uv * float2(2.0, -2.0) + float2(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Downsamples the supersampled text, which holds premultiplied colors.
+fragment float4 fragment_resolve(
+    ResolveOutput in_frag [[stage_in]],
+    texture2d<float> texture [[texture(0)]]
+) {
+    constexpr sampler resolve_sampler(filter::linear, address::clamp_to_edge);
+    return texture.sample(resolve_sampler, in_frag.uv);
+}
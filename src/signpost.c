@@ -0,0 +1,26 @@
+// Emits the `os_signpost` intervals of `signpost.rs`, which are macros of the public API.
+//
+// Signpost names must be string literals, so intervals are selected by the value of
+// `signpost::Name`, whose variants are listed in the same order here.
+
+#include <os/signpost.h>
+#include <stdint.h>
+
+#define FOR_EACH_NAME(emit, log, id, name) \
+    switch (name) { \
+    case 0: emit(log, id, "prewarm"); break; \
+    case 1: emit(log, id, "prepare"); break; \
+    case 2: emit(log, id, "upload glyphs"); break; \
+    case 3: emit(log, id, "upload vertices"); break; \
+    case 4: emit(log, id, "rasterize glyph"); break; \
+    case 5: emit(log, id, "grow atlas"); break; \
+    default: break; \
+    }
+
+void metalglyph_signpost_interval_begin(os_log_t log, os_signpost_id_t id, uint32_t name) {
+    FOR_EACH_NAME(os_signpost_interval_begin, log, id, name)
+}
+
+void metalglyph_signpost_interval_end(os_log_t log, os_signpost_id_t id, uint32_t name) {
+    FOR_EACH_NAME(os_signpost_interval_end, log, id, name)
+}
@@ -0,0 +1,68 @@
+//! CPU-side `os_signpost` intervals, shown in the "Points of Interest" track of Instruments.
+//!
+//! The intervals are emitted by the macros of the public `os_signpost` API, through the C shim
+//! in `signpost.c` compiled by the build script.
+
+use std::{
+    ffi::{c_char, c_void},
+    sync::OnceLock,
+};
+
+/// The name of a signpost interval. The shim maps each value to its name in the same order.
+#[derive(Clone, Copy, Debug)]
+#[repr(u32)]
+pub(crate) enum Name {
+    Prewarm,
+    Prepare,
+    UploadGlyphs,
+    UploadVertices,
+    RasterizeGlyph,
+    GrowAtlas,
+}
+
+extern "C" {
+    fn os_log_create(subsystem: *const c_char, category: *const c_char) -> *mut c_void;
+    fn os_signpost_id_generate(log: *mut c_void) -> u64;
+    fn metalglyph_signpost_interval_begin(log: *mut c_void, id: u64, name: Name);
+    fn metalglyph_signpost_interval_end(log: *mut c_void, id: u64, name: Name);
+}
+
+struct Log(*mut c_void);
+
+// SAFETY: `os_log_t` handles are immutable and may be used from any thread.
+unsafe impl Send for Log {}
+unsafe impl Sync for Log {}
+
+fn log() -> &'static Log {
+    static LOG: OnceLock<Log> = OnceLock::new();
+
+    LOG.get_or_init(|| unsafe {
+        Log(os_log_create(
+            c"metalglyph".as_ptr(),
+            c"PointsOfInterest".as_ptr(),
+        ))
+    })
+}
+
+/// A signpost interval that ends when dropped.
+pub(crate) struct Interval {
+    id: u64,
+    name: Name,
+}
+
+/// Begins a signpost interval named `name`.
+pub(crate) fn interval(name: Name) -> Interval {
+    let log = log();
+
+    // The shim does nothing while signposts are disabled
+    let id = unsafe { os_signpost_id_generate(log.0) };
+    unsafe { metalglyph_signpost_interval_begin(log.0, id, name) };
+
+    Interval { id, name }
+}
+
+impl Drop for Interval {
+    fn drop(&mut self) {
+        unsafe { metalglyph_signpost_interval_end(log().0, self.id, self.name) };
+    }
+}
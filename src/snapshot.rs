@@ -0,0 +1,342 @@
+//! Snapshots of the state of a renderer for bug reports, see [`Snapshot`].
+
+use crate::{
+    cache::is_linear_format,
+    text_render::{f16_bits, f16_to_f32, FLAG_BIT},
+    AlphaMode, AtlasStats, Cache, ColorMode, ContentType, GlyphToRender, GpuCacheStatus,
+    OffscreenRenderer, Params, Pixels, Resolution, SingleChannelOutput, SnapshotError,
+    TargetColorSpace, TextAtlas, ViewTransform, Viewport,
+};
+use objc2::runtime::ProtocolObject;
+use objc2_metal::{MTLCommandQueue, MTLDevice, MTLPixelFormat};
+use serde::{Deserialize, Serialize};
+
+/// The state of a [`crate::TextRenderer`], its [`TextAtlas`] and [`Viewport`] after a
+/// `prepare`, captured with [`crate::TextRenderer::capture_snapshot`].
+///
+/// A snapshot turns reports of garbled text into reproductions: the reporter serializes it (e.g.
+/// with [`Snapshot::to_json`]), and the maintainer inspects the glyphs and quads, or replays it
+/// with [`Snapshot::render_to_pixels`], which needs neither the fonts nor the text of the
+/// reporter when the atlas pixels were captured.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The version of the snapshot format, see [`Snapshot::VERSION`].
+    pub version: u32,
+    /// The raw value of the `MTLPixelFormat` of the atlas.
+    pub pixel_format: usize,
+    /// The color mode of the atlas.
+    pub color_mode: ColorMode,
+    /// The target color space of the atlas.
+    pub color_space: TargetColorSpace,
+    /// The alpha mode of the renderer.
+    pub alpha_mode: AlphaMode,
+    /// What the atlas writes to single-channel targets.
+    pub single_channel_output: SingleChannelOutput,
+    /// The glyphs cached in both atlases.
+    pub glyphs: Vec<SnapshotGlyph>,
+    /// The statistics of the mask atlas.
+    pub mask_stats: AtlasStats,
+    /// The statistics of the color atlas.
+    pub color_stats: AtlasStats,
+    /// The quads of the last `prepare`, in drawing order.
+    pub quads: Vec<SnapshotQuad>,
+    /// The parameters of the active slot of the viewport.
+    pub params: SnapshotParams,
+    /// The texels of both atlases, if they were captured.
+    pub atlas_pixels: Option<SnapshotAtlasPixels>,
+}
+
+/// A glyph cached in the atlas, see [`Snapshot::glyphs`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotGlyph {
+    /// The cache key of the glyph, formatted with `Debug`.
+    pub key: String,
+    /// The atlas holding the glyph, or `None` for glyphs that take up no space.
+    pub content_type: Option<ContentType>,
+    /// The position of the glyph in its atlas.
+    pub x: u16,
+    /// The position of the glyph in its atlas.
+    pub y: u16,
+    /// The width of the glyph in pixels.
+    pub width: u16,
+    /// The height of the glyph in pixels.
+    pub height: u16,
+    /// The offset of the left edge of the glyph from its origin.
+    pub left: i16,
+    /// The offset of the top edge of the glyph from its baseline.
+    pub top: i16,
+    /// Whether the glyph was used by a `prepare` since the last trim.
+    pub in_use: bool,
+}
+
+/// A quad emitted by `prepare`, see [`Snapshot::quads`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotQuad {
+    /// The position of the top-left corner of the quad, in pixels.
+    pub pos: [i32; 2],
+    /// The width and height of the quad, in pixels.
+    pub size: [u16; 2],
+    /// The position of the top-left corner of the quad in its atlas.
+    pub uv: [u16; 2],
+    /// The packed sRGB color of the glyph, as in `cosmic_text::Color`.
+    pub color: u32,
+    /// The depth of the quad.
+    pub depth: f32,
+    /// The atlas the quad samples.
+    pub content_type: ContentType,
+    /// Whether the color is converted to linear for the target format.
+    pub convert_to_linear: bool,
+    /// Whether the color is converted to Display P3.
+    pub display_p3: bool,
+    /// Whether the color atlas is an sRGB texture.
+    pub srgb_atlas: bool,
+    /// The scale of the quad around its center times the cosine and the sine of its rotation.
+    #[serde(default = "identity_linear")]
+    pub linear: [f32; 2],
+    /// The fraction of a pixel the quad is moved by.
+    #[serde(default)]
+    pub fraction: [f32; 2],
+    /// The sRGB-encoded luminance of the background hint of the area of the quad, see
+    /// [`crate::TextArea::background_hint`].
+    #[serde(default)]
+    pub background_luminance: Option<u8>,
+}
+
+/// The `linear` part of the transform of quads captured before quads were transformed.
+fn identity_linear() -> [f32; 2] {
+    [1.0, 0.0]
+}
+
+impl SnapshotQuad {
+    fn from_vertex(vertex: &GlyphToRender) -> Self {
+        let flag = |field: u16| field & FLAG_BIT != 0;
+
+        Self {
+            pos: vertex.pos,
+            size: vertex.size(),
+            uv: vertex.uv.map(|c| c & !FLAG_BIT),
+            color: vertex.color,
+            depth: vertex.depth,
+            content_type: if flag(vertex.dim[0]) {
+                ContentType::Mask
+            } else {
+                ContentType::Color
+            },
+            convert_to_linear: flag(vertex.dim[1]),
+            display_p3: flag(vertex.uv[0]),
+            srgb_atlas: flag(vertex.uv[1]),
+            linear: [vertex.transform[0], vertex.transform[1]].map(f16_to_f32),
+            fraction: [vertex.transform[2], vertex.transform[3]].map(f16_to_f32),
+            background_luminance: (vertex.background != 0).then_some(vertex.background as u8),
+        }
+    }
+
+    fn to_vertex(self) -> GlyphToRender {
+        let flag = |value: bool| value as u16 * FLAG_BIT;
+
+        GlyphToRender {
+            pos: self.pos,
+            dim: [
+                self.size[0] | flag(self.content_type == ContentType::Mask),
+                self.size[1] | flag(self.convert_to_linear),
+            ],
+            uv: [
+                self.uv[0] | flag(self.display_p3),
+                self.uv[1] | flag(self.srgb_atlas),
+            ],
+            color: self.color,
+            depth: self.depth,
+            transform: [
+                f16_bits(self.linear[0]),
+                f16_bits(self.linear[1]),
+                f16_bits(self.fraction[0]),
+                f16_bits(self.fraction[1]),
+            ],
+            background: self
+                .background_luminance
+                .map_or(0, |luminance| 0x100 | luminance as u32),
+        }
+    }
+}
+
+/// The viewport parameters read by the shader, see [`Snapshot::params`].
+///
+/// Positions are in pixels of the render resolution, see [`Viewport::set_render_scale`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotParams {
+    /// The resolution text is rendered at.
+    pub screen_resolution: Resolution,
+    /// The headroom for extended dynamic range output.
+    pub headroom: f32,
+    /// The scale factor of the viewport.
+    pub scale_factor: f32,
+    /// The origin of the viewport.
+    pub origin: [i32; 2],
+    /// The view transform.
+    pub transform: ViewTransform,
+    /// The global tint, as linear RGBA from `0.0` to `1.0`.
+    pub tint: [f32; 4],
+}
+
+impl SnapshotParams {
+    fn new(params: Params, scale_factor: f32) -> Self {
+        Self {
+            screen_resolution: params.screen_resolution,
+            headroom: params.headroom,
+            scale_factor,
+            origin: params.origin,
+            transform: params.transform,
+            tint: params.tint,
+        }
+    }
+}
+
+impl From<SnapshotParams> for Params {
+    fn from(params: SnapshotParams) -> Self {
+        Self {
+            screen_resolution: params.screen_resolution,
+            headroom: params.headroom,
+            origin: params.origin,
+            transform: params.transform,
+            tint: params.tint,
+        }
+    }
+}
+
+/// The texels of both atlases, see [`Snapshot::atlas_pixels`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotAtlasPixels {
+    /// The width and height of the mask atlas.
+    pub mask_size: u32,
+    /// The texels of the mask atlas, one byte each, row by row.
+    pub mask: Vec<u8>,
+    /// The width and height of the color atlas.
+    pub color_size: u32,
+    /// The texels of the color atlas, four bytes (RGBA) each, row by row.
+    pub color: Vec<u8>,
+}
+
+impl Snapshot {
+    /// The version of the snapshot format written by this version of the crate.
+    pub const VERSION: u32 = 1;
+
+    pub(crate) fn capture(
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        vertices: &[GlyphToRender],
+        alpha_mode: AlphaMode,
+        atlas_pixels: bool,
+    ) -> Self {
+        let glyphs = [&atlas.mask_atlas, &atlas.color_atlas]
+            .into_iter()
+            .flat_map(|inner| {
+                let allocator = &inner.allocator;
+
+                allocator.glyph_cache.iter().map(|(key, details)| {
+                    let (content_type, x, y) = match details.gpu_cache {
+                        GpuCacheStatus::InAtlas { x, y, content_type } => {
+                            (Some(content_type), x, y)
+                        }
+                        GpuCacheStatus::SkipRasterization => (None, 0, 0),
+                    };
+
+                    SnapshotGlyph {
+                        key: format!("{key:?}"),
+                        content_type,
+                        x,
+                        y,
+                        width: details.width,
+                        height: details.height,
+                        left: details.left,
+                        top: details.top,
+                        in_use: allocator.glyphs_in_use.contains(key),
+                    }
+                })
+            })
+            .collect();
+
+        let atlas_pixels = atlas_pixels.then(|| SnapshotAtlasPixels {
+            mask_size: atlas.mask_atlas.allocator.size,
+            mask: atlas.mask_atlas.read_texels(),
+            color_size: atlas.color_atlas.allocator.size,
+            color: atlas.color_atlas.read_texels(),
+        });
+
+        Self {
+            version: Self::VERSION,
+            pixel_format: atlas.pixel_format.0,
+            color_mode: atlas.color_mode,
+            color_space: atlas.color_space,
+            alpha_mode,
+            single_channel_output: atlas.single_channel_output,
+            glyphs,
+            mask_stats: atlas.stats(ContentType::Mask),
+            color_stats: atlas.stats(ContentType::Color),
+            quads: vertices.iter().map(SnapshotQuad::from_vertex).collect(),
+            params: SnapshotParams::new(
+                viewport.shader_params(viewport.active_slot()),
+                viewport.scale_factor(),
+            ),
+            atlas_pixels,
+        }
+    }
+
+    /// Serializes the snapshot into JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Serialize snapshot")
+    }
+
+    /// Deserializes a snapshot from JSON written by [`Snapshot::to_json`].
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Renders the quads of the snapshot with its atlas pixels and viewport parameters into a
+    /// new texture of the snapshot resolution cleared to transparent, and reads it back.
+    ///
+    /// The pixels are premultiplied, as in [`OffscreenRenderer::render_to_pixels`]. Snapshots of
+    /// formats other than 8-bit RGBA and BGRA formats are rendered into `RGBA8Unorm_sRGB` if
+    /// their format stores linear colors (e.g. `RGBA16Float`), and into `RGBA8Unorm` otherwise,
+    /// so that the colors of the quads, which were converted for the original format, are
+    /// displayed the same.
+    pub fn render_to_pixels(
+        &self,
+        device: &ProtocolObject<dyn MTLDevice>,
+        queue: &ProtocolObject<dyn MTLCommandQueue>,
+        cache: &Cache,
+    ) -> Result<Pixels, SnapshotError> {
+        if self.version != Self::VERSION {
+            return Err(SnapshotError::UnsupportedVersion(self.version));
+        }
+
+        let atlas_pixels = self
+            .atlas_pixels
+            .as_ref()
+            .ok_or(SnapshotError::MissingAtlasPixels)?;
+        let texel_count = |size: u32| size as usize * size as usize;
+        if atlas_pixels.mask.len() != texel_count(atlas_pixels.mask_size)
+            || atlas_pixels.color.len() != texel_count(atlas_pixels.color_size) * 4
+        {
+            return Err(SnapshotError::InvalidAtlasPixels);
+        }
+
+        let format = match MTLPixelFormat(self.pixel_format) {
+            format @ (MTLPixelFormat::RGBA8Unorm
+            | MTLPixelFormat::RGBA8Unorm_sRGB
+            | MTLPixelFormat::BGRA8Unorm
+            | MTLPixelFormat::BGRA8Unorm_sRGB) => format,
+            format if is_linear_format(format) => MTLPixelFormat::RGBA8Unorm_sRGB,
+            _ => MTLPixelFormat::RGBA8Unorm,
+        };
+
+        let mut renderer =
+            OffscreenRenderer::with_options(device, queue, cache, format, self.color_mode, 1);
+
+        Ok(renderer.replay(
+            self.params.into(),
+            self.quads.iter().map(|quad| quad.to_vertex()).collect(),
+            (atlas_pixels.mask_size, atlas_pixels.mask.clone()),
+            (atlas_pixels.color_size, atlas_pixels.color.clone()),
+        ))
+    }
+}
@@ -0,0 +1,26 @@
+//! Rendering the same text into both views of a layered render target, see
+//! [`crate::TextRendererBuilder::stereo`].
+
+use objc2::runtime::ProtocolObject;
+use objc2_metal::MTLDevice;
+
+/// How a [`crate::TextRenderer`] built for stereo draws into both views, see
+/// [`crate::TextRenderer::stereo_path`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StereoPath {
+    /// Each draw writes both views with vertex amplification.
+    VertexAmplification,
+    /// Each draw is encoded once per view, on devices without vertex amplification.
+    DrawPerView,
+}
+
+impl StereoPath {
+    /// Returns the path used on `device`, vertex amplification if it supports two views.
+    pub fn for_device(device: &ProtocolObject<dyn MTLDevice>) -> Self {
+        if device.supportsVertexAmplificationCount(2) {
+            StereoPath::VertexAmplification
+        } else {
+            StereoPath::DrawPerView
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Supersampled text, see [`Supersampler`].
+
+use crate::{resource_label, Cache, MemoryUsage, Resolution, Viewport, DEFAULT_LABEL};
+use objc2::{rc::Retained, runtime::ProtocolObject, Message as _};
+use objc2_foundation::ns_string;
+use objc2_metal::{
+    MTLBlendFactor, MTLClearColor, MTLDevice, MTLLibrary as _, MTLLoadAction, MTLPixelFormat,
+    MTLPrimitiveType, MTLRenderCommandEncoder, MTLRenderPassDescriptor,
+    MTLRenderPipelineDescriptor, MTLRenderPipelineState, MTLResource as _, MTLStorageMode,
+    MTLStoreAction, MTLTexture, MTLTextureDescriptor, MTLTextureUsage,
+};
+
+/// Renders text at the render scale of a [`Viewport`] (see [`Viewport::set_render_scale`]) into
+/// an intermediate texture, and downsamples it into the render target.
+///
+/// Supersampling is opt-in: the intermediate texture takes `render_scale²` times the memory of
+/// the target, and glyphs are rasterized at `render_scale` times their size. Each frame:
+///
+/// 1. Render the text in a pass of [`Supersampler::render_pass_descriptor`], which clears the
+///    intermediate texture to transparent. Text must be rendered with
+///    [`crate::AlphaMode::Premultiplied`], as the downsampled text is composited with the
+///    premultiplied "over" operator.
+/// 2. Call [`Supersampler::resolve`] in the render pass of the target, which filters the
+///    intermediate texture bilinearly and composites it over the target.
+pub struct Supersampler {
+    device: Retained<ProtocolObject<dyn MTLDevice>>,
+    pipeline: Retained<ProtocolObject<dyn MTLRenderPipelineState>>,
+    format: MTLPixelFormat,
+    texture: Option<Retained<ProtocolObject<dyn MTLTexture>>>,
+    label: String,
+}
+
+impl Supersampler {
+    /// Creates a new `Supersampler` for render targets of the given `format`, which is also the
+    /// format of the intermediate texture.
+    pub fn new(
+        device: &ProtocolObject<dyn MTLDevice>,
+        cache: &Cache,
+        format: MTLPixelFormat,
+    ) -> Self {
+        Self::with_label(device, cache, format, DEFAULT_LABEL)
+    }
+
+    /// Creates a new `Supersampler` like [`Supersampler::new`], labeling its pipeline state and
+    /// intermediate texture with `label`.
+    pub fn with_label(
+        device: &ProtocolObject<dyn MTLDevice>,
+        cache: &Cache,
+        format: MTLPixelFormat,
+        label: &str,
+    ) -> Self {
+        let library = cache.library();
+        let vertex_function = library
+            .newFunctionWithName(ns_string!("vertex_resolve"))
+            .expect("Failed to create shader function vertex_resolve");
+        let fragment_function = library
+            .newFunctionWithName(ns_string!("fragment_resolve"))
+            .expect("Failed to create shader function fragment_resolve");
+
+        let descriptor = MTLRenderPipelineDescriptor::new();
+        descriptor.setLabel(Some(&resource_label(label, "Supersampling Pipeline State")));
+        descriptor.setVertexFunction(Some(&vertex_function));
+        descriptor.setFragmentFunction(Some(&fragment_function));
+
+        let attachment = unsafe { descriptor.colorAttachments().objectAtIndexedSubscript(0) };
+        attachment.setPixelFormat(format);
+        attachment.setBlendingEnabled(true);
+        attachment.setSourceRGBBlendFactor(MTLBlendFactor::One);
+        attachment.setSourceAlphaBlendFactor(MTLBlendFactor::One);
+        attachment.setDestinationRGBBlendFactor(MTLBlendFactor::OneMinusSourceAlpha);
+        attachment.setDestinationAlphaBlendFactor(MTLBlendFactor::OneMinusSourceAlpha);
+
+        let pipeline = device
+            .newRenderPipelineStateWithDescriptor_error(&descriptor)
+            .expect("Failed to create pipeline state");
+
+        Self {
+            device: device.retain(),
+            pipeline,
+            format,
+            texture: None,
+            label: label.to_owned(),
+        }
+    }
+
+    /// Returns the intermediate texture for `viewport`, (re)creating it if its size differs from
+    /// the render resolution of `viewport`.
+    pub fn texture(&mut self, viewport: &Viewport) -> &Retained<ProtocolObject<dyn MTLTexture>> {
+        let Resolution { width, height } = viewport.render_resolution();
+        let (width, height) = (width.max(1) as usize, height.max(1) as usize);
+
+        if self
+            .texture
+            .as_ref()
+            .is_some_and(|texture| texture.width() != width || texture.height() != height)
+        {
+            self.texture = None;
+        }
+
+        self.texture.get_or_insert_with(|| {
+            let descriptor = unsafe {
+                MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+                    self.format,
+                    width,
+                    height,
+                    false,
+                )
+            };
+            descriptor.setUsage(MTLTextureUsage::RenderTarget | MTLTextureUsage::ShaderRead);
+            descriptor.setStorageMode(MTLStorageMode::Private);
+
+            let texture = self
+                .device
+                .newTextureWithDescriptor(&descriptor)
+                .expect("Failed to create supersampling texture");
+            texture.setLabel(Some(&resource_label(&self.label, "Supersampling Texture")));
+
+            texture
+        })
+    }
+
+    /// Returns a render pass descriptor that clears the intermediate texture for `viewport` to
+    /// transparent, for rendering the text.
+    pub fn render_pass_descriptor(
+        &mut self,
+        viewport: &Viewport,
+    ) -> Retained<MTLRenderPassDescriptor> {
+        let descriptor = MTLRenderPassDescriptor::new();
+        let attachment = unsafe { descriptor.colorAttachments().objectAtIndexedSubscript(0) };
+
+        attachment.setTexture(Some(self.texture(viewport)));
+        attachment.setLoadAction(MTLLoadAction::Clear);
+        attachment.setClearColor(MTLClearColor {
+            red: 0.0,
+            green: 0.0,
+            blue: 0.0,
+            alpha: 0.0,
+        });
+        attachment.setStoreAction(MTLStoreAction::Store);
+
+        descriptor
+    }
+
+    /// Composites the supersampled text over the target of `encoder`, which must be a render
+    /// pass with a single sample per pixel. Does nothing if no text has been rendered yet.
+    pub fn resolve(&self, encoder: &ProtocolObject<dyn MTLRenderCommandEncoder>) {
+        let Some(texture) = &self.texture else {
+            return;
+        };
+
+        encoder.setRenderPipelineState(&self.pipeline);
+        unsafe {
+            encoder.setFragmentTexture_atIndex(Some(texture), 0);
+            encoder.drawPrimitives_vertexStart_vertexCount(MTLPrimitiveType::Triangle, 0, 3);
+        }
+    }
+
+    /// Returns the memory used by the intermediate texture.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            texture_bytes: self
+                .texture
+                .as_ref()
+                .map_or(0, |texture| texture.allocatedSize() as u64),
+            buffer_bytes: 0,
+            cpu_bytes: 0,
+        }
+    }
+}
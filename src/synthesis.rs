@@ -0,0 +1,138 @@
+//! Faces that do not match the weight or style requested for their text, see
+//! [`crate::TextArea::font_synthesis`].
+
+use crate::GlyphPlacement;
+use cosmic_text::{Buffer, FontSystem, LayoutGlyph, Style, Weight};
+
+/// The difference in weight between the requested and the matched face above which they
+/// mismatch, so that e.g. a semibold face standing in for bold is not reported.
+const WEIGHT_TOLERANCE: u16 = 100;
+
+/// The strength of synthetic bold for a regular face standing in for a bold one, as a fraction
+/// of the font size, which FreeType uses too.
+const EMBOLDEN_STRENGTH: f32 = 1.0 / 24.0;
+
+/// How `prepare` draws glyphs whose face is lighter than the weight requested by the attributes
+/// of their text, e.g. because the font has no bold face.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FontSynthesis {
+    /// Glyphs are drawn with the face they matched.
+    #[default]
+    None,
+    /// Glyphs of a face more than 100 lighter than requested are emboldened as they are
+    /// rasterized, on top of the [`crate::GlyphRasterConfig::embolden`] of the renderer, by up
+    /// to 1/24 of their size for a regular face standing in for a bold one.
+    ///
+    /// Emboldened glyphs are cached separately from the same glyphs drawn as they are.
+    Embolden,
+}
+
+/// The weight and style of a face, see [`FaceMismatch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FaceStyle {
+    /// The weight of the face.
+    pub weight: Weight,
+    /// The style of the face.
+    pub style: Style,
+}
+
+impl FaceStyle {
+    /// Returns the face requested by the attributes of `glyph`, of the line `line_i` of
+    /// `buffer`.
+    pub fn requested(buffer: &Buffer, line_i: usize, glyph: &LayoutGlyph) -> Option<Self> {
+        let attrs = buffer.lines.get(line_i)?.attrs_list().get_span(glyph.start);
+
+        Some(Self {
+            weight: attrs.weight,
+            style: attrs.style,
+        })
+    }
+
+    /// Returns the face of the font `id`, or `None` if it is not in the database anymore.
+    fn of_font(font_system: &FontSystem, id: cosmic_text::fontdb::ID) -> Option<Self> {
+        font_system.db().face(id).map(|face| Self {
+            weight: face.weight,
+            style: face.style,
+        })
+    }
+
+    /// Returns by how much `self` is lighter than `requested`, if by more than the tolerance.
+    fn missing_weight(self, requested: Self) -> Option<u16> {
+        let missing = requested.weight.0.saturating_sub(self.weight.0);
+        (missing > WEIGHT_TOLERANCE).then_some(missing)
+    }
+
+    /// Returns whether `self` differs from `requested` by more than the tolerance.
+    fn mismatches(self, requested: Self) -> bool {
+        let slanted = |style| style != Style::Normal;
+
+        self.weight.0.abs_diff(requested.weight.0) > WEIGHT_TOLERANCE
+            || slanted(self.style) != slanted(requested.style)
+    }
+}
+
+/// A face that differs from the face requested for the glyphs of a text area, see
+/// [`crate::AreaFontUsage::face_mismatches`].
+///
+/// The weights differ by more than 100, or one style is upright while the other is italic or
+/// oblique. Fonts without an italic face are slanted by cosmic-text, so a style mismatch is
+/// still drawn slanted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaceMismatch {
+    /// The ID of the font in the database of the [`FontSystem`].
+    pub font: cosmic_text::fontdb::ID,
+    /// The weight and style requested by the attributes of the text.
+    pub requested: FaceStyle,
+    /// The weight and style of the font.
+    pub matched: FaceStyle,
+    /// The number of glyphs of the area drawn with the font for the requested face.
+    pub glyphs: usize,
+    /// Whether the glyphs were emboldened by [`FontSynthesis::Embolden`].
+    pub emboldened: bool,
+}
+
+/// The face a glyph was requested in and the face it matched, see [`FontSynthesis::resolve`].
+pub(crate) struct ResolvedFace {
+    pub font: cosmic_text::fontdb::ID,
+    pub requested: FaceStyle,
+    pub matched: FaceStyle,
+    /// The strength of synthetic bold applied to the glyph, as a fraction of its size.
+    pub embolden: f32,
+}
+
+impl ResolvedFace {
+    /// Returns whether the matched face differs from the requested one.
+    pub fn mismatches(&self) -> bool {
+        self.matched.mismatches(self.requested)
+    }
+}
+
+impl FontSynthesis {
+    /// Compares the face requested for the glyph at `placement` with the face of its font, or
+    /// returns `None` if the requested face is unknown.
+    pub(crate) fn resolve(
+        self,
+        placement: &GlyphPlacement,
+        font_system: &FontSystem,
+    ) -> Option<ResolvedFace> {
+        let requested = placement.requested_face?;
+        let font = placement.cache_key.font_id;
+        let matched = FaceStyle::of_font(font_system, font)?;
+
+        let embolden = match self {
+            FontSynthesis::None => 0.0,
+            FontSynthesis::Embolden => matched.missing_weight(requested).map_or(0.0, |missing| {
+                EMBOLDEN_STRENGTH * (missing as f32 / 300.0).min(1.0)
+            }),
+        };
+
+        Some(ResolvedFace {
+            font,
+            requested,
+            matched,
+            embolden,
+        })
+    }
+}
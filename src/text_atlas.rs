@@ -1,154 +1,205 @@
 use crate::{
-    text_render::GlyphonCacheKey, Cache, ContentType, FontSystem, GlyphDetails, GpuCacheStatus,
-    RasterizeCustomGlyphRequest, RasterizedCustomGlyph, SwashCache,
+    cache::{PipelineKey, RendererOptions, VertexStage},
+    glyph_allocator::{AllocationStep, GlyphAllocator, Hasher},
+    packing::AtlasPacking,
+    rasterize, resource_label,
+    text_render::GlyphonCacheKey,
+    upload::UploadQueue,
+    AlphaMode, AtlasKey, BlendMode, BuildError, Cache, CacheKey, ContentType, FontSystem,
+    GlyphDetails, GlyphKey, GlyphRasterConfig, GpuCacheStatus, MemoryUsage, PrepareError,
+    RasterizeCustomGlyphRequest, RasterizedCustomGlyph, SingleChannelOutput, SwashCache,
+    TrimPolicy, DEFAULT_LABEL,
 };
-use etagere::{size2, Allocation, BucketedAtlasAllocator};
-use lru::LruCache;
-use objc2::{rc::Retained, runtime::ProtocolObject};
-use objc2_foundation::ns_string;
+use objc2::{rc::Retained, runtime::ProtocolObject, Message as _};
+use objc2_foundation::NSError;
 use objc2_metal::{
-    MTLDevice, MTLOrigin, MTLPixelFormat, MTLRegion, MTLRenderPipelineState, MTLResource as _,
-    MTLSize, MTLTexture, MTLTextureDescriptor, MTLTextureUsage,
+    MTLBlitCommandEncoder, MTLCommandBuffer, MTLDevice, MTLEvent as _, MTLGPUFamily,
+    MTLPixelFormat, MTLRenderPipelineState, MTLResource as _, MTLSharedEvent, MTLTexture,
+    MTLTextureDescriptor, MTLTextureUsage,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    hash::BuildHasher,
+    mem,
+    sync::{Arc, Mutex, MutexGuard},
+};
+#[cfg(feature = "debug-tools")]
+use {
+    objc2_metal::{MTLOrigin, MTLRegion, MTLSize},
+    std::ptr::NonNull,
 };
-use rustc_hash::FxHasher;
-use std::{collections::HashSet, hash::BuildHasherDefault, ptr::NonNull};
 
-type Hasher = BuildHasherDefault<FxHasher>;
+/// The number of `prepare` calls without a trim after which a forgotten trim is reported.
+#[cfg(feature = "tracing")]
+const FORGOTTEN_TRIM_PREPARES: u32 = 1000;
 
 #[allow(dead_code)]
 pub(crate) struct InnerAtlas {
     pub kind: Kind,
     pub texture: Retained<ProtocolObject<dyn MTLTexture>>,
-    pub packer: BucketedAtlasAllocator,
-    pub size: u32,
-    pub glyph_cache: LruCache<GlyphonCacheKey, GlyphDetails, Hasher>,
-    pub glyphs_in_use: HashSet<GlyphonCacheKey, Hasher>,
+    pub allocator: GlyphAllocator,
+    pub counters: AtlasCounters,
+    pub uploads: UploadQueue,
+    pub label: String,
+    /// The number of times the texture was replaced, see [`TextAtlas::texture_generation`].
+    pub texture_generation: u64,
 }
 
 impl InnerAtlas {
     const INITIAL_SIZE: u32 = 256;
-    const MAX_TEXTURE_DIMENSION_2D: u32 = 16384;
-
-    fn new(device: &Retained<ProtocolObject<dyn MTLDevice>>, kind: Kind) -> Self {
-        let size = Self::INITIAL_SIZE;
-        let packer = BucketedAtlasAllocator::new(size2(size as i32, size as i32));
-
-        let descriptor = unsafe {
-            MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
-                kind.texture_format(),
-                size as usize,
-                size as usize,
-                false,
-            )
-        };
-
-        descriptor.setUsage(MTLTextureUsage::ShaderRead);
-
-        let texture = device
-            .newTextureWithDescriptor(&descriptor)
-            .expect("Failed to create texture");
-        texture.setLabel(Some(ns_string!("Metalglyph - Atlas")));
 
-        let glyph_cache = LruCache::unbounded_with_hasher(Hasher::default());
-        let glyphs_in_use = HashSet::with_hasher(Hasher::default());
+    fn new(
+        device: &ProtocolObject<dyn MTLDevice>,
+        kind: Kind,
+        packing: AtlasPacking,
+        initial_size: u32,
+        max_size: u32,
+        label: &str,
+    ) -> Option<Self> {
+        let mut allocator = GlyphAllocator::new(initial_size, packing);
+        allocator.max_size = max_size;
+        let texture = create_texture(device, kind, allocator.size, label)?;
 
-        Self {
+        Some(Self {
             kind,
             texture,
-            packer,
-            size,
-            glyph_cache,
-            glyphs_in_use,
-        }
+            allocator,
+            counters: AtlasCounters::default(),
+            uploads: UploadQueue::default(),
+            label: label.to_owned(),
+            texture_generation: 0,
+        })
     }
 
-    pub(crate) fn try_allocate(&mut self, width: usize, height: usize) -> Option<Allocation> {
-        let size = size2(width as i32, height as i32);
+    /// Returns where the glyph of `details` lies in the texture, or `None` if it takes up no
+    /// space.
+    fn atlas_glyph(&self, details: &GlyphDetails) -> Option<AtlasGlyph> {
+        let GpuCacheStatus::InAtlas { x, y, content_type } = details.gpu_cache else {
+            return None;
+        };
+        let size = self.allocator.size as f32;
+
+        Some(AtlasGlyph {
+            content_type,
+            x,
+            y,
+            width: details.width,
+            height: details.height,
+            left: details.left,
+            top: details.top,
+            uv: [
+                x as f32 / size,
+                y as f32 / size,
+                (x + details.width) as f32 / size,
+                (y + details.height) as f32 / size,
+            ],
+            generation: details.generation,
+            texture_generation: self.texture_generation,
+        })
+    }
 
-        loop {
-            let allocation = self.packer.allocate(size);
+    pub(crate) fn allocate(&mut self, width: usize, height: usize) -> AllocationStep {
+        let step = self.allocator.allocate(width, height);
 
-            if allocation.is_some() {
-                return allocation;
+        #[cfg(feature = "tracing")]
+        {
+            let evicted = self.allocator.evicted.len();
+            if matches!(step, AllocationStep::Allocated(_)) && evicted > 0 {
+                tracing::warn!(
+                    kind = ?self.kind,
+                    size = self.allocator.size,
+                    evicted,
+                    "evicted cached glyphs to make room in the atlas"
+                );
             }
+        }
 
-            // Try to free least recently used allocation
-            let (mut key, mut value) = self.glyph_cache.peek_lru()?;
+        self.count_evictions();
 
-            // Find a glyph with an actual size
-            while value.atlas_id.is_none() {
-                // All sized glyphs are in use, cache is full
-                if self.glyphs_in_use.contains(key) {
-                    return None;
-                }
+        step
+    }
 
-                let _ = self.glyph_cache.pop_lru();
+    /// Counts the glyphs the allocator evicted since it was last asked.
+    fn count_evictions(&mut self) {
+        for key in self.allocator.evicted.drain(..) {
+            self.counters.record_eviction(&key);
+        }
+    }
 
-                (key, value) = self.glyph_cache.peek_lru()?;
-            }
+    fn memory_usage(&self) -> MemoryUsage {
+        // Least recently used entries are linked in both directions
+        const LRU_ENTRY_SIZE: usize =
+            mem::size_of::<(GlyphonCacheKey, GlyphDetails)>() + 2 * mem::size_of::<usize>();
 
-            // All sized glyphs are in use, cache is full
-            if self.glyphs_in_use.contains(key) {
-                return None;
-            }
+        let allocator = &self.allocator;
+        let cpu_bytes = allocator.glyph_cache.len() * LRU_ENTRY_SIZE
+            + allocator.glyphs_in_use.capacity() * mem::size_of::<GlyphonCacheKey>()
+            + allocator.last_used.capacity() * mem::size_of::<(GlyphonCacheKey, u64)>()
+            + self.uploads.memory_bytes();
 
-            let (_, value) = self.glyph_cache.pop_lru().unwrap();
-            self.packer.deallocate(value.atlas_id.unwrap());
+        MemoryUsage {
+            texture_bytes: self.texture.allocatedSize() as u64,
+            buffer_bytes: 0,
+            cpu_bytes: cpu_bytes as u64,
         }
     }
 
-    pub fn num_channels(&self) -> usize {
-        self.kind.num_channels()
-    }
-
+    /// Grows the texture to `new_size`, see [`AllocationStep::Grow`], and uploads the cached
+    /// glyphs into it again.
     pub(crate) fn grow(
         &mut self,
-        device: &Retained<ProtocolObject<dyn MTLDevice>>,
+        device: &ProtocolObject<dyn MTLDevice>,
+        new_size: u32,
         font_system: &mut FontSystem,
         cache: &mut SwashCache,
         scale_factor: f32,
         mut rasterize_custom_glyph: impl FnMut(
             RasterizeCustomGlyphRequest,
         ) -> Option<RasterizedCustomGlyph>,
-    ) -> bool {
-        if self.size >= Self::MAX_TEXTURE_DIMENSION_2D {
-            return false;
-        }
+    ) -> Result<(), PrepareError> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            kind = ?self.kind,
+            old_size = self.allocator.size,
+            new_size,
+            label = %self.label,
+            "growing atlas"
+        );
 
-        // Grow each dimension by a factor of 2. The growth factor was chosen to match the growth
-        // factor of `Vec`.`
-        const GROWTH_FACTOR: u32 = 2;
-        let new_size = (self.size * GROWTH_FACTOR).min(Self::MAX_TEXTURE_DIMENSION_2D);
+        let texture = create_texture(device, self.kind, new_size, &self.label)
+            .ok_or(PrepareError::DeviceLost)?;
+        self.allocator.grow(new_size);
+        self.texture = texture;
+        self.texture_generation += 1;
 
-        self.packer.grow(size2(new_size as i32, new_size as i32));
+        // Queued uploads are lost with the old texture, every cached glyph is uploaded below
+        self.uploads.discard();
 
-        let descriptor = unsafe {
-            MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
-                self.kind.texture_format(),
-                new_size as usize,
-                new_size as usize,
-                false,
-            )
-        };
-
-        descriptor.setUsage(MTLTextureUsage::ShaderRead);
-
-        self.texture = device
-            .newTextureWithDescriptor(&descriptor)
-            .expect("Failed to create texture");
-        self.texture
-            .setLabel(Some(ns_string!("Metalglyph - Atlas")));
+        if self
+            .uploads
+            .grow_shadow(&self.texture, new_size, self.kind.num_channels())
+        {
+            return self.check_uploads();
+        }
 
         // Re-upload glyphs
-        for (&cache_key, glyph) in &self.glyph_cache {
+        for (&cache_key, glyph) in &self.allocator.glyph_cache {
             let (x, y) = match glyph.gpu_cache {
                 GpuCacheStatus::InAtlas { x, y, .. } => (x, y),
                 GpuCacheStatus::SkipRasterization => continue,
             };
 
             let (image_data, width, height) = match cache_key {
-                GlyphonCacheKey::Text(cache_key) => {
-                    let image = cache.get_image_uncached(font_system, cache_key).unwrap();
+                GlyphonCacheKey::Text(cache_key, config) => {
+                    let image = rasterize::text_glyph(
+                        cache,
+                        font_system,
+                        cache_key,
+                        glyph.strike_policy,
+                        config.config(),
+                    )
+                    .unwrap()
+                    .image;
                     let width = image.placement.width as usize;
                     let height = image.placement.height as usize;
 
@@ -177,44 +228,231 @@ impl InnerAtlas {
                         cache_key.height as usize,
                     )
                 }
+                GlyphonCacheKey::Tofu(key) => {
+                    (key.image(), key.width() as usize, key.height as usize)
+                }
             };
 
-            unsafe {
-                self.texture
-                    .replaceRegion_mipmapLevel_withBytes_bytesPerRow(
-                        MTLRegion {
-                            origin: MTLOrigin {
-                                x: x.into(),
-                                y: y.into(),
-                                z: 0,
-                            },
-                            size: MTLSize {
-                                width,
-                                height,
-                                depth: 1,
-                            },
-                        },
-                        0,
-                        NonNull::from(image_data.as_slice()).cast(),
-                        width * self.kind.num_channels(),
-                    );
-            }
+            // The cache is borrowed, so the upload is queued on the fields directly
+            queue_upload(
+                &mut self.uploads,
+                self.kind,
+                x.into(),
+                y.into(),
+                width,
+                height,
+                image_data,
+            );
+        }
+
+        self.flush_uploads()
+    }
+
+    /// Queues `data`, `width` x `height` pixels of the content type of the atlas in RGBA order
+    /// for color atlases, to be written at `x`, `y` into the texture.
+    pub(crate) fn push_upload(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        data: Vec<u8>,
+    ) {
+        queue_upload(&mut self.uploads, self.kind, x, y, width, height, data);
+    }
+
+    pub(crate) fn flush_uploads(&mut self) -> Result<(), PrepareError> {
+        self.uploads.flush(&self.texture, self.kind.num_channels());
+        self.check_uploads()
+    }
+
+    /// Returns an error if a flush failed to create a staging buffer for blit uploads.
+    fn check_uploads(&self) -> Result<(), PrepareError> {
+        if self.uploads.device_lost() {
+            Err(PrepareError::DeviceLost)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Replaces the texture with an empty one on `device`, at the current size clamped to
+    /// `max_texture_size`. Cached glyphs are dropped, the queued uploads too, and the shadow is
+    /// cleared.
+    fn recreate_on(
+        &mut self,
+        device: &ProtocolObject<dyn MTLDevice>,
+        max_texture_size: u32,
+    ) -> Result<(), BuildError> {
+        let max_size = self.allocator.max_size.min(max_texture_size);
+        let size = self.allocator.size.min(max_size);
+        let texture =
+            create_texture(device, self.kind, size, &self.label).ok_or(BuildError::DeviceLost)?;
+
+        self.allocator = GlyphAllocator::new(size, self.allocator.packing);
+        self.allocator.max_size = max_size;
+        self.texture = texture;
+        self.texture_generation += 1;
+
+        let mut uploads = UploadQueue::default();
+        uploads.set_blit(self.uploads.has_blit());
+        if self.uploads.has_shadow() {
+            uploads.set_shadow(Some(&*self.texture), size, self.kind.num_channels());
+        }
+        self.uploads = uploads;
+
+        Ok(())
+    }
+
+    fn set_cpu_shadow(&mut self, enabled: bool) {
+        if enabled == self.uploads.has_shadow() {
+            return;
         }
 
-        self.size = new_size;
+        self.uploads.set_shadow(
+            enabled.then_some(&*self.texture),
+            self.allocator.size,
+            self.kind.num_channels(),
+        );
+    }
+
+    fn trim(&mut self, policy: TrimPolicy) {
+        self.allocator.trim(policy, self.kind.num_channels());
+        self.count_evictions();
+    }
+
+    /// Reads the texels of the atlas texture back, row by row without padding.
+    #[cfg(feature = "debug-tools")]
+    pub(crate) fn read_texels(&self) -> Vec<u8> {
+        let size = self.allocator.size as usize;
+        let bytes_per_row = size * self.kind.num_channels();
+        let mut texels = vec![0; bytes_per_row * size];
+
+        unsafe {
+            self.texture.getBytes_bytesPerRow_fromRegion_mipmapLevel(
+                NonNull::from(texels.as_mut_slice()).cast(),
+                bytes_per_row,
+                MTLRegion {
+                    origin: MTLOrigin { x: 0, y: 0, z: 0 },
+                    size: MTLSize {
+                        width: size,
+                        height: size,
+                        depth: 1,
+                    },
+                },
+                0,
+            );
+        }
+
+        if let Kind::Color {
+            format: ColorAtlasFormat::Bgra8,
+            ..
+        } = self.kind
+        {
+            swap_red_blue(&mut texels);
+        }
+
+        texels
+    }
+
+    /// Replaces the atlas with an empty `size` x `size` atlas whose texture holds `texels`, as
+    /// read by [`InnerAtlas::read_texels`].
+    #[cfg(feature = "debug-tools")]
+    pub(crate) fn restore_texels(
+        &mut self,
+        device: &ProtocolObject<dyn MTLDevice>,
+        size: u32,
+        texels: Vec<u8>,
+    ) {
+        let max_size = self.allocator.max_size;
+        self.allocator = GlyphAllocator::new(size, self.allocator.packing);
+        self.allocator.max_size = max_size;
+        self.texture =
+            create_texture(device, self.kind, size, &self.label).expect("Failed to create texture");
+        self.texture_generation += 1;
 
-        true
+        self.uploads.discard();
+        self.uploads
+            .set_shadow(None, size, self.kind.num_channels());
+        self.push_upload(0, 0, size as usize, size as usize, texels);
+        self.flush_uploads()
+            .expect("Failed to create upload staging buffer");
     }
 
-    fn trim(&mut self) {
-        self.glyphs_in_use.clear();
+    fn set_label(&mut self, label: &str) {
+        self.label = label.to_owned();
+        self.texture
+            .setLabel(Some(&resource_label(&self.label, "Atlas")));
+    }
+}
+
+/// Queues an upload of `data` into `uploads`, see [`InnerAtlas::push_upload`].
+fn queue_upload(
+    uploads: &mut UploadQueue,
+    kind: Kind,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    mut data: Vec<u8>,
+) {
+    if let Kind::Color {
+        format: ColorAtlasFormat::Bgra8,
+        ..
+    } = kind
+    {
+        swap_red_blue(&mut data);
+    }
+
+    uploads.push(x, y, width, height, data, kind.num_channels());
+}
+
+/// Swaps the red and blue channels of 4-channel `pixels`, converting between RGBA and BGRA.
+fn swap_red_blue(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+}
+
+/// Returns the maximum width and height of 2D textures on `device`. Macs and A9 or later GPUs
+/// support 16384, earlier iOS and tvOS GPUs are limited to 8192.
+fn max_texture_size(device: &ProtocolObject<dyn MTLDevice>) -> u32 {
+    if device.supportsFamily(MTLGPUFamily::Apple3) || device.supportsFamily(MTLGPUFamily::Mac2) {
+        GlyphAllocator::MAX_SIZE
+    } else {
+        8192
     }
 }
 
+fn create_texture(
+    device: &ProtocolObject<dyn MTLDevice>,
+    kind: Kind,
+    size: u32,
+    label: &str,
+) -> Option<Retained<ProtocolObject<dyn MTLTexture>>> {
+    let descriptor = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            kind.texture_format(),
+            size as usize,
+            size as usize,
+            false,
+        )
+    };
+
+    descriptor.setUsage(MTLTextureUsage::ShaderRead);
+
+    let texture = device.newTextureWithDescriptor(&descriptor)?;
+    texture.setLabel(Some(&resource_label(label, "Atlas")));
+
+    Some(texture)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum Kind {
     Mask,
-    Color { srgb: bool },
+    Color {
+        srgb: bool,
+        format: ColorAtlasFormat,
+    },
 }
 
 impl Kind {
@@ -228,13 +466,12 @@ impl Kind {
     fn texture_format(self) -> MTLPixelFormat {
         match self {
             Kind::Mask => MTLPixelFormat::R8Unorm,
-            Kind::Color { srgb } => {
-                if srgb {
-                    MTLPixelFormat::RGBA8Unorm_sRGB
-                } else {
-                    MTLPixelFormat::RGBA8Unorm
-                }
-            }
+            Kind::Color { srgb, format } => match (format, srgb) {
+                (ColorAtlasFormat::Rgba8, true) => MTLPixelFormat::RGBA8Unorm_sRGB,
+                (ColorAtlasFormat::Rgba8, false) => MTLPixelFormat::RGBA8Unorm,
+                (ColorAtlasFormat::Bgra8, true) => MTLPixelFormat::BGRA8Unorm_sRGB,
+                (ColorAtlasFormat::Bgra8, false) => MTLPixelFormat::BGRA8Unorm,
+            },
         }
     }
 
@@ -247,12 +484,24 @@ impl Kind {
 }
 
 /// The color mode of a [`TextAtlas`].
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Glyph colors and color glyph bitmaps are specified in sRGB. Both modes convert them for the
+/// format of the render target: targets that store linear colors (sRGB formats such as
+/// `BGRA8Unorm_sRGB`, which encode the colors written to them, and float formats) receive
+/// linear colors, and other targets (e.g. `BGRA8Unorm`) receive sRGB colors. A solid color is
+/// therefore displayed the same in either mode, on either kind of target. Blending happens in
+/// the space of the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum ColorMode {
     /// Accurate color management.
     ///
-    /// This mode will use a proper sRGB texture for colored glyphs. This will
-    /// produce physically accurate color blending when rendering.
+    /// This mode will use a proper sRGB texture for colored glyphs, which are
+    /// therefore filtered in linear space.
+    ///
+    /// This mode should be used to render to an sRGB texture (e.g.
+    /// `BGRA8Unorm_sRGB` or `RGBA8Unorm_sRGB`) or to a float texture.
     Accurate,
 
     /// Web color management.
@@ -261,27 +510,301 @@ pub enum ColorMode {
     /// implemented by browsers.
     ///
     /// This entails storing glyphs colored using the sRGB color space in a
-    /// linear RGB texture. Blending will not be physically accurate, but will
-    /// produce the same results as most UI toolkits.
+    /// linear RGB texture, which are filtered in sRGB space like most UI
+    /// toolkits do.
     ///
     /// This mode should be used to render to a linear RGB texture containing
-    /// sRGB colors.
+    /// sRGB colors (e.g. `BGRA8Unorm`).
     Web,
 }
 
+impl ColorMode {
+    /// Returns the color mode suited to a render target of the given format:
+    /// [`ColorMode::Accurate`] for sRGB, float and wide formats, and [`ColorMode::Web`] for
+    /// linear 8-bit formats such as `BGRA8Unorm`, whose contents are displayed as sRGB.
+    pub fn for_format(format: MTLPixelFormat) -> Self {
+        match format {
+            MTLPixelFormat::BGRA8Unorm
+            | MTLPixelFormat::RGBA8Unorm
+            | MTLPixelFormat::RG8Unorm
+            | MTLPixelFormat::R8Unorm => ColorMode::Web,
+            _ => ColorMode::Accurate,
+        }
+    }
+}
+
+/// The channel order of the texture of the color atlas, see [`TextAtlasBuilder::color_format`].
+///
+/// Both orders hold the same colors, and glyphs are sampled the same from either: the order only
+/// matters to code that reads or binds the texture itself (see [`TextAtlas::color_texture`]).
+/// Whether the texture is sRGB-encoded follows the [`ColorMode`] of the atlas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ColorAtlasFormat {
+    /// `RGBA8Unorm`, or `RGBA8Unorm_sRGB` with [`ColorMode::Accurate`].
+    #[default]
+    Rgba8,
+    /// `BGRA8Unorm`, or `BGRA8Unorm_sRGB` with [`ColorMode::Accurate`].
+    Bgra8,
+}
+
+/// The color space of the render target that text is rendered into.
+///
+/// Glyph colors and color glyph bitmaps (e.g. emoji) are always specified in
+/// sRGB. When the target uses a wider gamut, they are converted so that they
+/// keep their intended appearance instead of being stretched to the larger
+/// gamut.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TargetColorSpace {
+    /// The render target uses the sRGB color space.
+    #[default]
+    Srgb,
+    /// The render target uses the Display P3 color space (e.g. a
+    /// `CAMetalLayer` configured with `kCGColorSpaceDisplayP3`).
+    DisplayP3,
+}
+
+/// The counters of [`AtlasStats`] for the atlas of one content type, which `prepare` records
+/// as it looks glyphs up, rasterizes and places them.
+#[derive(Debug, Default)]
+pub(crate) struct AtlasCounters {
+    pub hits: u64,
+    pub misses: u64,
+    pub rasterizations: u64,
+    pub rerasterizations: u64,
+    pub evictions: u64,
+    pub culled: u64,
+    pub strike_substitutions: u64,
+    pub tofu: u64,
+    /// Hashes of the keys of the most recently evicted glyphs, oldest first.
+    recently_evicted: VecDeque<u64>,
+}
+
+impl AtlasCounters {
+    /// The number of evicted glyphs remembered to detect re-rasterizations.
+    const RECENTLY_EVICTED: usize = 256;
+
+    /// Counts the rasterization of `key`, and whether it was evicted recently.
+    pub fn record_rasterization(&mut self, key: &GlyphonCacheKey) {
+        self.rasterizations += 1;
+
+        let hash = Hasher::default().hash_one(key);
+        if let Some(i) = self.recently_evicted.iter().position(|&h| h == hash) {
+            self.recently_evicted.remove(i);
+            self.rerasterizations += 1;
+        }
+    }
+
+    fn record_eviction(&mut self, key: &GlyphonCacheKey) {
+        self.evictions += 1;
+
+        if self.recently_evicted.len() == Self::RECENTLY_EVICTED {
+            self.recently_evicted.pop_front();
+        }
+        self.recently_evicted
+            .push_back(Hasher::default().hash_one(key));
+    }
+}
+
+/// Statistics of the atlas holding glyphs of one [`ContentType`], see [`TextAtlas::stats`].
+///
+/// Counters other than `grows` count from the creation of the atlas, or from the last call to
+/// [`TextAtlas::reset_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AtlasStats {
+    /// The width and height of the atlas texture, in pixels.
+    pub size: u32,
+    /// The number of cached glyphs, including glyphs that take up no space (e.g. whitespace).
+    pub glyph_count: usize,
+    /// The fraction of the atlas area allocated to cached glyphs, from `0.0` to `1.0`.
+    pub occupancy: f32,
+    /// The fraction of the allocated area covered by glyphs, from `0.0` to `1.0`, or `1.0` if
+    /// nothing is allocated.
+    ///
+    /// The rest is lost to the rounding of the packer (see [`crate::AtlasPacking`]), so this
+    /// compares packing strategies on the same text.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub packing_efficiency: f32,
+    /// The number of times the atlas grew since it was created.
+    pub grows: u32,
+    /// The number of glyphs evicted to make room for others.
+    pub evictions: u64,
+    /// The number of glyph lookups in `prepare` that found the glyph in the atlas.
+    pub hits: u64,
+    /// The number of glyph lookups in `prepare` that did not find the glyph in the atlas.
+    pub misses: u64,
+    /// The number of glyphs rasterized and uploaded into the atlas.
+    pub rasterizations: u64,
+    /// The number of rasterizations of glyphs that were among the last 256 evicted ones.
+    ///
+    /// A steadily increasing count means that glyphs are evicted while they are still needed,
+    /// i.e. that the atlas is too small for the text of a frame or trimmed too often.
+    pub rerasterizations: u64,
+    /// The number of glyphs that `prepare` culled because they lie entirely outside of their
+    /// [`crate::TextArea::bounds`] or of the viewport resolution.
+    ///
+    /// Culled glyphs emit no vertices and are not kept in use, so they can be evicted.
+    pub culled: u64,
+    /// The number of glyphs rasterized from a bitmap strike of another size than requested, see
+    /// [`crate::BitmapStrikePolicy`].
+    pub strike_substitutions: u64,
+    /// The number of glyphs missing from their font that `prepare` drew as boxes, see
+    /// [`crate::TextRenderer::set_tofu`]. Boxes appearing several times are counted each time.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tofu: u64,
+    /// The number of pinned glyphs, see [`crate::CachePriority::Pinned`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pinned_glyphs: usize,
+    /// The area of the atlas covered by pinned glyphs, in pixels. Compare it with the square of
+    /// `size` to tell how much of the atlas cannot be reclaimed by evictions.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pinned_area: u64,
+    /// The number of writes into the atlas texture.
+    ///
+    /// The glyphs rasterized by a `prepare` are written at its end, in as few writes as
+    /// possible, see [`TextAtlas::set_cpu_shadow`].
+    pub texture_writes: u64,
+    /// The number of bytes written into the atlas texture, including the texels that writes of
+    /// the CPU shadow re-write between new glyphs.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub uploaded_bytes: u64,
+}
+
+impl AtlasStats {
+    /// Returns the fraction of glyph lookups that found the glyph in the atlas, from `0.0` to
+    /// `1.0`, or `1.0` if there were no lookups.
+    pub fn hit_rate(&self) -> f32 {
+        let lookups = self.hits + self.misses;
+
+        if lookups == 0 {
+            1.0
+        } else {
+            (self.hits as f64 / lookups as f64) as f32
+        }
+    }
+}
+
+/// Where a cached glyph lies in an atlas texture, see [`TextAtlas::glyph`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasGlyph {
+    /// The content type of the glyph, which tells whether it lies in
+    /// [`TextAtlas::mask_texture`] or [`TextAtlas::color_texture`].
+    pub content_type: ContentType,
+    /// The left edge of the glyph in the texture, in texels.
+    pub x: u16,
+    /// The top edge of the glyph in the texture, in texels.
+    pub y: u16,
+    /// The width of the glyph, in texels.
+    pub width: u16,
+    /// The height of the glyph, in texels.
+    pub height: u16,
+    /// The offset of the left edge of the glyph from the pen position, in pixels.
+    pub left: i16,
+    /// The offset of the top edge of the glyph above the baseline, in pixels.
+    pub top: i16,
+    /// The rectangle of the glyph in normalized texture coordinates, as
+    /// `[u_min, v_min, u_max, v_max]`.
+    ///
+    /// These depend on the size of the texture, and must be looked up again when the atlas
+    /// grows (see [`TextAtlas::texture_generation`]).
+    pub uv: [f32; 4],
+    /// A number unique to this allocation of the glyph, across atlases. A glyph that is evicted
+    /// and cached again gets a new generation, likely at another position.
+    pub generation: u64,
+    /// The [`TextAtlas::texture_generation`] of the texture the glyph lies in, which `uv` are
+    /// relative to.
+    pub texture_generation: u64,
+}
+
+/// A glyph cached in a [`TextAtlas`], see [`TextAtlas::cached_glyphs`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CachedGlyph {
+    /// The glyph, including its font and size.
+    pub key: GlyphKey,
+    /// The key the glyph is cached under, which includes its raster config.
+    pub atlas_key: AtlasKey,
+    /// Where the glyph lies in the atlas textures, or `None` if it takes up no space (e.g.
+    /// whitespace).
+    pub atlas_glyph: Option<AtlasGlyph>,
+    /// Whether the glyph was used by a `prepare` since the last [`TextAtlas::trim`], which keeps
+    /// it from being evicted.
+    pub in_use: bool,
+    /// Whether the glyph was used by an area with [`crate::CachePriority::Pinned`] since the
+    /// last [`TextAtlas::unpin`].
+    pub pinned: bool,
+}
+
 /// An atlas containing a cache of rasterized glyphs that can be rendered.
+///
+/// Several [`crate::TextRenderer`]s can share an atlas, as long as they render into targets of
+/// the atlas format. [`TextAtlas::trim`] must then only be called once all of them have rendered
+/// the current frame, since it allows glyphs of every renderer to be evicted. Renderers that
+/// present independently (e.g. in separate windows) are simpler to manage with an atlas each,
+/// sharing only the [`Cache`]. Renderers prepared from different places can share an atlas
+/// through a [`SharedTextAtlas`].
+///
+/// A `TextAtlas` is `Send` but not `Sync`: it can be prepared against on a worker thread and
+/// moved to the render thread, as long as the GPU is done reading glyphs that `prepare` may
+/// evict or overwrite (see [`TextAtlas::trim`]).
 pub struct TextAtlas {
+    device: Retained<ProtocolObject<dyn MTLDevice>>,
     cache: Cache,
     pub(crate) color_atlas: InnerAtlas,
     pub(crate) mask_atlas: InnerAtlas,
     pub(crate) pixel_format: MTLPixelFormat,
     pub(crate) color_mode: ColorMode,
+    pub(crate) color_space: TargetColorSpace,
+    pub(crate) single_channel_output: SingleChannelOutput,
+    pub(crate) alpha_mode: AlphaMode,
+    trim_policy: TrimPolicy,
+    /// See [`TextAtlas::set_auto_trim`].
+    auto_trim: bool,
+    /// The renderers that prepared against the atlas since the last automatic trim.
+    prepared_renderers: HashSet<u64, Hasher>,
+    /// The number of `prepare` calls against the atlas since the last trim.
+    prepares_since_trim: u32,
+    /// Whether the atlas was trimmed since the last `prepare` against it.
+    trimmed: bool,
+    /// The number of `prepare` calls against the atlas that followed a trim, which may reuse
+    /// the space of released glyphs. Renderers compare it between `prepare` and `render`.
+    pub(crate) reuse_epoch: u64,
+    /// See [`TextAtlas::set_upload_budget`].
+    upload_budget: Option<usize>,
+    /// The bytes of glyphs uploaded by `prepare` since the last trim.
+    frame_upload_bytes: usize,
+    /// The glyphs deferred by a budget that were not uploaded yet, see
+    /// [`TextAtlas::upload_backlog`].
+    upload_backlog: HashMap<GlyphonCacheKey, DeferredGlyph, Hasher>,
+    /// The order of the next glyph added to `upload_backlog`.
+    next_deferred_order: u64,
+    /// Signaled with `upload_event_value` once glyphs are written into the textures, if enabled.
+    upload_event: Option<Retained<ProtocolObject<dyn MTLSharedEvent>>>,
+    upload_event_value: u64,
+    /// See [`TextAtlas::is_device_lost`].
+    device_lost: bool,
 }
 
+// SAFETY: Metal textures may be used from any thread. Every mutation of the atlas goes through
+// `&mut self`, and `&self` is never shared across threads since the atlas is not `Sync`.
+unsafe impl Send for TextAtlas {}
+
 impl TextAtlas {
-    /// Creates a new [`TextAtlas`].
+    /// Creates a new [`TextAtlas`] with [`ColorMode::Accurate`].
+    ///
+    /// `format` is the pixel format of the render target that text will be rendered into, which
+    /// must match the color attachment of the render pass (see
+    /// [`crate::TextRenderer::check_format`]). Use [`TextAtlas::with_color_mode`] and
+    /// [`ColorMode::for_format`] to pick the color mode suited to the format. Besides
+    /// the usual 8-bit formats, float and wide formats such as `RGBA16Float` and `BGR10A2Unorm`
+    /// are supported for extended dynamic range output (see [`crate::Viewport::set_headroom`]).
+    /// Single-channel formats such as `R8Unorm` render text as a coverage mask (see
+    /// [`TextAtlas::set_single_channel_output`]).
     pub fn new(
-        device: &Retained<ProtocolObject<dyn MTLDevice>>,
+        device: &ProtocolObject<dyn MTLDevice>,
         cache: &Cache,
         format: MTLPixelFormat,
     ) -> Self {
@@ -290,56 +813,718 @@ impl TextAtlas {
 
     /// Creates a new [`TextAtlas`] with the given [`ColorMode`].
     pub fn with_color_mode(
-        device: &Retained<ProtocolObject<dyn MTLDevice>>,
+        device: &ProtocolObject<dyn MTLDevice>,
         cache: &Cache,
         format: MTLPixelFormat,
         color_mode: ColorMode,
     ) -> Self {
-        let color_atlas = InnerAtlas::new(
+        Self::with_packing(
             device,
-            Kind::Color {
-                srgb: match color_mode {
-                    ColorMode::Accurate => true,
-                    ColorMode::Web => false,
-                },
-            },
-        );
+            cache,
+            format,
+            color_mode,
+            AtlasPacking::default(),
+            AtlasPacking::default(),
+        )
+    }
 
-        let mask_atlas = InnerAtlas::new(device, Kind::Mask);
+    /// Creates a new [`TextAtlas`] with the given [`ColorMode`], packing mask glyphs with
+    /// `mask_packing` and color glyphs with `color_packing`.
+    ///
+    /// The packing of an atlas cannot change afterwards, since glyphs are placed where its packer
+    /// put them.
+    pub fn with_packing(
+        device: &ProtocolObject<dyn MTLDevice>,
+        cache: &Cache,
+        format: MTLPixelFormat,
+        color_mode: ColorMode,
+        mask_packing: AtlasPacking,
+        color_packing: AtlasPacking,
+    ) -> Self {
+        Self::builder(device, cache, format)
+            .color_mode(color_mode)
+            .packing(ContentType::Mask, mask_packing)
+            .packing(ContentType::Color, color_packing)
+            .build()
+            .expect("the default atlas sizes are valid on every device")
+    }
+
+    /// Returns a [`TextAtlasBuilder`] to create a `TextAtlas` with non-default options, e.g.
+    /// separate size limits for the mask and color atlases.
+    pub fn builder<'a>(
+        device: &'a ProtocolObject<dyn MTLDevice>,
+        cache: &'a Cache,
+        format: MTLPixelFormat,
+    ) -> TextAtlasBuilder<'a> {
+        TextAtlasBuilder {
+            device,
+            cache,
+            format,
+            color_mode: ColorMode::Accurate,
+            color_format: ColorAtlasFormat::default(),
+            mask: AtlasOptions::default(),
+            color: AtlasOptions::default(),
+        }
+    }
 
+    fn from_inner(
+        device: &ProtocolObject<dyn MTLDevice>,
+        cache: &Cache,
+        format: MTLPixelFormat,
+        color_mode: ColorMode,
+        color_atlas: InnerAtlas,
+        mask_atlas: InnerAtlas,
+    ) -> Self {
         Self {
+            device: device.retain(),
             cache: cache.clone(),
             color_atlas,
             mask_atlas,
             pixel_format: format,
             color_mode,
+            color_space: TargetColorSpace::Srgb,
+            single_channel_output: SingleChannelOutput::Coverage,
+            alpha_mode: AlphaMode::Straight,
+            trim_policy: TrimPolicy::EveryFrame,
+            auto_trim: false,
+            prepared_renderers: HashSet::default(),
+            prepares_since_trim: 0,
+            trimmed: false,
+            reuse_epoch: 0,
+            upload_budget: None,
+            frame_upload_bytes: 0,
+            upload_backlog: HashMap::with_hasher(Hasher::default()),
+            next_deferred_order: 0,
+            upload_event: None,
+            upload_event_value: 0,
+            device_lost: false,
         }
     }
 
+    /// Sets the prefix of the labels of the atlas textures (e.g. `"World Labels"` produces
+    /// `"World Labels - Atlas"`), which tells atlases apart in GPU captures and memory reports.
+    ///
+    /// The label is kept when the atlas grows and its textures are recreated.
+    pub fn set_label(&mut self, label: &str) {
+        self.color_atlas.set_label(label);
+        self.mask_atlas.set_label(label);
+        if let Some(upload_event) = &self.upload_event {
+            upload_event.setLabel(Some(&resource_label(label, "Upload Event")));
+        }
+    }
+
+    /// Returns the prefix of the labels of the atlas textures.
+    pub fn label(&self) -> &str {
+        &self.color_atlas.label
+    }
+
+    /// Returns the pixel format of the render targets the atlas renders into.
+    pub fn format(&self) -> MTLPixelFormat {
+        self.pixel_format
+    }
+
+    /// Returns the device the atlas was created with, which creates its textures as it grows.
+    pub fn device(&self) -> &ProtocolObject<dyn MTLDevice> {
+        &self.device
+    }
+
+    /// Returns whether the device of the atlas failed to create a texture or staging buffer, as
+    /// it does once it is lost (e.g. when an external GPU is unplugged).
+    ///
+    /// Every `prepare` against a lost atlas returns [`PrepareError::DeviceLost`], and renderers
+    /// draw nothing with it, until it is recreated with [`TextAtlas::recreate_on`].
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost
+    }
+
+    /// Recreates the textures of the atlas on `device`, e.g. after its device was lost, with the
+    /// pipelines of `cache`, which must have been created for `device`.
+    ///
+    /// The atlas keeps its options (format, color mode, packing, size limits, policies and
+    /// budgets), but the cached glyphs are dropped and rasterized again by the next `prepare`.
+    /// Each atlas keeps its current size, unless the new device supports smaller textures.
+    /// Renderers using the atlas must be recreated too (see
+    /// [`crate::TextRenderer::recreate_on`]), as well as their [`crate::Viewport`]s (see
+    /// [`crate::Viewport::recreate_on`]). Font systems, swash caches and text buffers are not
+    /// tied to a device and are kept as they are.
+    ///
+    /// Returns [`BuildError::DeviceLost`] if `device` failed to create the textures too, in which
+    /// case the atlas stays lost.
+    pub fn recreate_on(
+        &mut self,
+        device: &ProtocolObject<dyn MTLDevice>,
+        cache: &Cache,
+    ) -> Result<(), BuildError> {
+        let max_texture_size = max_texture_size(device);
+        self.mask_atlas.recreate_on(device, max_texture_size)?;
+        self.color_atlas.recreate_on(device, max_texture_size)?;
+
+        self.device = device.retain();
+        self.cache = cache.clone();
+        self.upload_backlog.clear();
+        self.frame_upload_bytes = 0;
+        self.prepared_renderers.clear();
+        if self.upload_event.take().is_some() {
+            self.set_upload_event(true);
+        }
+        self.device_lost = false;
+
+        Ok(())
+    }
+
+    /// Returns the [`ColorMode`] of the atlas.
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    /// Sets the [`TargetColorSpace`] of the render target.
+    ///
+    /// This takes effect on the next call to `prepare`.
+    pub fn set_color_space(&mut self, color_space: TargetColorSpace) {
+        self.color_space = color_space;
+    }
+
+    /// Returns the [`TargetColorSpace`] of the render target.
+    pub fn color_space(&self) -> TargetColorSpace {
+        self.color_space
+    }
+
+    /// Sets what is written when the atlas format is a single-channel format such as `R8Unorm`.
+    ///
+    /// This has no effect on other formats, and only affects renderers created afterwards.
+    pub fn set_single_channel_output(&mut self, output: SingleChannelOutput) {
+        self.single_channel_output = output;
+    }
+
+    /// Returns what is written when the atlas format is a single-channel format.
+    pub fn single_channel_output(&self) -> SingleChannelOutput {
+        self.single_channel_output
+    }
+
+    /// Sets the [`AlphaMode`] used to blend text into the render target.
+    ///
+    /// This only affects renderers created afterwards.
+    pub fn set_alpha_mode(&mut self, alpha_mode: AlphaMode) {
+        self.alpha_mode = alpha_mode;
+    }
+
+    /// Returns the [`AlphaMode`] used to blend text into the render target.
+    pub fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    /// Returns the [`AtlasPacking`] of the atlas holding glyphs of the given [`ContentType`].
+    pub fn packing(&self, content_type: ContentType) -> AtlasPacking {
+        self.inner_for_content(content_type).allocator.packing
+    }
+
+    /// Returns the width and height in pixels of the atlas texture holding glyphs of the given
+    /// [`ContentType`]. Atlases are square and grow as glyphs are added.
+    pub fn size(&self, content_type: ContentType) -> u32 {
+        self.inner_for_content(content_type).allocator.size
+    }
+
+    /// Returns the largest size the atlas of `content_type` grows to, in texels per side (see
+    /// [`TextAtlasBuilder::max_size`]).
+    pub fn max_size(&self, content_type: ContentType) -> u32 {
+        self.inner_for_content(content_type).allocator.max_size
+    }
+
+    /// Returns the channel order of the texture of the color atlas.
+    pub fn color_format(&self) -> ColorAtlasFormat {
+        match self.color_atlas.kind {
+            Kind::Color { format, .. } => format,
+            Kind::Mask => unreachable!("the color atlas holds color glyphs"),
+        }
+    }
+
+    /// Returns the number of glyphs cached in the atlas holding glyphs of the given
+    /// [`ContentType`], including glyphs that take up no space (e.g. whitespace).
+    pub fn glyph_count(&self, content_type: ContentType) -> usize {
+        self.inner_for_content(content_type)
+            .allocator
+            .glyph_cache
+            .len()
+    }
+
+    /// Returns the texture of the atlas holding glyphs of [`ContentType::Mask`], e.g. to sample
+    /// glyphs from custom geometry (see [`TextAtlas::glyph`]).
+    ///
+    /// It is an `R8Unorm` texture holding the coverage of glyphs. The atlas replaces its textures
+    /// when it grows, so the returned texture must be bound again whenever
+    /// [`TextAtlas::texture_generation`] changes. It must only be read: glyphs are written into it
+    /// by `prepare`.
+    pub fn mask_texture(&self) -> Retained<ProtocolObject<dyn MTLTexture>> {
+        self.mask_atlas.texture.clone()
+    }
+
+    /// Returns the texture of the atlas holding glyphs of [`ContentType::Color`], see
+    /// [`TextAtlas::mask_texture`].
+    ///
+    /// It is an `RGBA8Unorm_sRGB` texture with [`ColorMode::Accurate`], and an `RGBA8Unorm`
+    /// texture with [`ColorMode::Web`].
+    pub fn color_texture(&self) -> Retained<ProtocolObject<dyn MTLTexture>> {
+        self.color_atlas.texture.clone()
+    }
+
+    /// Returns a number that increases whenever the texture of the atlas holding glyphs of the
+    /// given [`ContentType`] is replaced, i.e. when the atlas grows.
+    ///
+    /// Textures returned by [`TextAtlas::mask_texture`] and [`TextAtlas::color_texture`] remain
+    /// valid Metal objects afterwards, but no longer receive new glyphs.
+    pub fn texture_generation(&self, content_type: ContentType) -> u64 {
+        self.inner_for_content(content_type).texture_generation
+    }
+
+    /// Looks up where the text glyph `cache_key`, rasterized with `raster_config` (see
+    /// [`crate::TextRenderer::set_raster_config`]), lies in the atlas textures.
+    ///
+    /// Returns `None` if the glyph is not cached, or takes up no space (e.g. whitespace). Only
+    /// glyphs prepared for the current frame are kept from being evicted by later `prepare`
+    /// calls, so glyphs should be looked up after every `prepare`. Lookups do not count in
+    /// [`AtlasStats`] and do not mark glyphs as recently used.
+    pub fn glyph(
+        &self,
+        cache_key: CacheKey,
+        raster_config: GlyphRasterConfig,
+    ) -> Option<AtlasGlyph> {
+        self.glyph_by_key(AtlasKey::text(cache_key, raster_config))
+    }
+
+    /// Returns where the glyph of `key` lies in the atlas textures, like [`TextAtlas::glyph`]
+    /// does for text glyphs.
+    pub fn glyph_by_key(&self, key: AtlasKey) -> Option<AtlasGlyph> {
+        [&self.mask_atlas, &self.color_atlas]
+            .into_iter()
+            .find_map(|inner| inner.atlas_glyph(inner.allocator.glyph_cache.peek(&key.0)?))
+    }
+
+    /// Returns whether the glyph of `key` is cached, including glyphs that take up no space
+    /// (e.g. whitespace). Like lookups, this does not mark the glyph as recently used.
+    pub fn contains(&self, key: AtlasKey) -> bool {
+        [&self.mask_atlas, &self.color_atlas]
+            .into_iter()
+            .any(|inner| inner.allocator.glyph_cache.contains(&key.0))
+    }
+
+    /// Returns whether `glyph`, looked up earlier with [`TextAtlas::glyph`] for the same
+    /// `cache_key` and `raster_config`, still describes the glyph in the atlas, so that data
+    /// derived from it (e.g. the texture coordinates of custom geometry) can be reused.
+    ///
+    /// This only compares generations: the glyph must still be cached at the same allocation,
+    /// see [`AtlasGlyph::generation`], in the same texture, see
+    /// [`AtlasGlyph::texture_generation`]. Positions in texels stay valid when only the texture
+    /// changed, which `generation` alone tells.
+    pub fn is_current(
+        &self,
+        cache_key: CacheKey,
+        raster_config: GlyphRasterConfig,
+        glyph: &AtlasGlyph,
+    ) -> bool {
+        let key = GlyphonCacheKey::Text(cache_key, raster_config.key());
+
+        self.glyph_generation(&key, glyph.content_type) == Some(glyph.generation)
+            && self.texture_generation(glyph.content_type) == glyph.texture_generation
+    }
+
+    /// Returns the glyphs cached in both atlases, in no particular order, e.g. to draw an
+    /// overlay highlighting each allocation of [`TextAtlas::mask_texture`] and
+    /// [`TextAtlas::color_texture`].
+    ///
+    /// Iterating does not mark glyphs as recently used, so it does not change which glyphs are
+    /// evicted first.
+    pub fn cached_glyphs(&self) -> impl Iterator<Item = CachedGlyph> + '_ {
+        [&self.mask_atlas, &self.color_atlas]
+            .into_iter()
+            .flat_map(|inner| {
+                let allocator = &inner.allocator;
+
+                allocator
+                    .glyph_cache
+                    .iter()
+                    .map(move |(key, details)| CachedGlyph {
+                        key: (*key).into(),
+                        atlas_key: AtlasKey(*key),
+                        atlas_glyph: inner.atlas_glyph(details),
+                        in_use: allocator.glyphs_in_use.contains(key),
+                        pinned: allocator.pinned.contains(key),
+                    })
+            })
+    }
+
+    /// Returns statistics of the atlas holding glyphs of the given [`ContentType`], e.g. to
+    /// monitor how close it is to running out of space.
+    pub fn stats(&self, content_type: ContentType) -> AtlasStats {
+        let allocator = &self.inner_for_content(content_type).allocator;
+        let counters = &self.inner_for_content(content_type).counters;
+        let area = allocator.size as f64 * allocator.size as f64;
+        let allocated_space = allocator.packer.allocated_space() as f64;
+
+        AtlasStats {
+            size: allocator.size,
+            glyph_count: allocator.glyph_cache.len(),
+            occupancy: (allocated_space / area) as f32,
+            packing_efficiency: if allocated_space > 0.0 {
+                (allocator.glyph_area() as f64 / allocated_space) as f32
+            } else {
+                1.0
+            },
+            grows: allocator.grows,
+            evictions: counters.evictions,
+            hits: counters.hits,
+            misses: counters.misses,
+            rasterizations: counters.rasterizations,
+            rerasterizations: counters.rerasterizations,
+            culled: counters.culled,
+            strike_substitutions: counters.strike_substitutions,
+            tofu: counters.tofu,
+            pinned_glyphs: allocator.pinned.len(),
+            pinned_area: allocator.pinned_area(),
+            texture_writes: self.inner_for_content(content_type).uploads.texture_writes,
+            uploaded_bytes: self.inner_for_content(content_type).uploads.uploaded_bytes,
+        }
+    }
+
+    /// Returns the memory used by the atlas textures of both content types, and an estimate of
+    /// the memory used by the glyph caches.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.mask_atlas.memory_usage() + self.color_atlas.memory_usage()
+    }
+
+    /// Resets the lookup, rasterization, eviction, culling, substitution and upload counters of
+    /// [`AtlasStats`] for both content types, e.g. to measure them over a fixed number of frames.
+    pub fn reset_stats(&mut self) {
+        for inner in [&mut self.mask_atlas, &mut self.color_atlas] {
+            inner.counters = AtlasCounters::default();
+            inner.uploads.texture_writes = 0;
+            inner.uploads.uploaded_bytes = 0;
+        }
+    }
+
+    /// Sets whether the atlas keeps a copy of its textures in CPU memory, which is off by
+    /// default.
+    ///
+    /// Glyphs rasterized by a `prepare` are written into the textures at its end. Without a
+    /// copy, glyphs that were packed next to each other on the same row are written together.
+    /// With a copy, every band of rows that received glyphs is written at once, which takes
+    /// fewer writes when many glyphs are rasterized (e.g. on the first frame), and growing the
+    /// atlas no longer rasterizes every cached glyph again. The copy takes as much memory as the
+    /// textures, see [`TextAtlas::memory_usage`].
+    pub fn set_cpu_shadow(&mut self, enabled: bool) {
+        self.mask_atlas.set_cpu_shadow(enabled);
+        self.color_atlas.set_cpu_shadow(enabled);
+    }
+
+    /// Returns whether the atlas keeps a copy of its textures in CPU memory.
+    pub fn cpu_shadow(&self) -> bool {
+        self.mask_atlas.uploads.has_shadow()
+    }
+
+    /// Sets whether glyphs are written into the atlas textures with blit copies encoded by
+    /// [`TextAtlas::encode_uploads`], instead of being written by `prepare`. Off by default.
+    ///
+    /// This lets an app schedule the uploads of a frame in its own resource upload blit pass.
+    /// Each `prepare` that rasterizes glyphs copies them into a new staging buffer and records
+    /// the copies. Copies that were not encoded when disabling blit uploads are written into the
+    /// textures right away. The [`TextAtlas::upload_event`] is signaled when copies are recorded,
+    /// not when the GPU performs them.
+    pub fn set_blit_uploads(&mut self, enabled: bool) {
+        self.mask_atlas.uploads.set_blit(enabled);
+        self.color_atlas.uploads.set_blit(enabled);
+    }
+
+    /// Returns whether glyphs are written into the atlas textures with blit copies.
+    pub fn blit_uploads(&self) -> bool {
+        self.mask_atlas.uploads.has_blit()
+    }
+
+    /// Encodes the copies of the glyphs rasterized since the last call into `blit_encoder`, in
+    /// the order they were recorded, if blit uploads are enabled (see
+    /// [`TextAtlas::set_blit_uploads`]).
+    ///
+    /// The blit pass must be committed before the text is rendered. In debug builds, rendering
+    /// text while copies are pending panics, so that forgotten uploads don't show stale glyphs.
+    pub fn encode_uploads(&mut self, blit_encoder: &ProtocolObject<dyn MTLBlitCommandEncoder>) {
+        self.mask_atlas.uploads.encode(blit_encoder);
+        self.color_atlas.uploads.encode(blit_encoder);
+    }
+
+    /// Returns whether blit copies of glyphs are waiting for [`TextAtlas::encode_uploads`].
+    pub fn has_pending_uploads(&self) -> bool {
+        self.mask_atlas.uploads.has_pending_copies()
+            || self.color_atlas.uploads.has_pending_copies()
+    }
+
+    /// Releases the glyphs used by the previous `prepare` calls as the trim policy decides (see
+    /// [`TextAtlas::set_trim_policy`]), so that they can be evicted to make room for other
+    /// glyphs. Call it once per frame, after every renderer using the atlas has prepared.
+    ///
+    /// Trimming only updates the bookkeeping of the atlas. The texels of released and evicted
+    /// glyphs stay untouched until the next `prepare` against the atlas reuses their space, so
+    /// text prepared before the trim can be rendered any number of times until then, e.g. into
+    /// a reflection and then into the drawable. Rendering text after another renderer prepared
+    /// against the trimmed atlas may draw the wrong glyphs, which
+    /// [`crate::FrameValidation::trim_before_render`] catches in debug builds.
     pub fn trim(&mut self) {
-        self.mask_atlas.trim();
-        self.color_atlas.trim();
+        self.trim_with(self.trim_policy);
+    }
+
+    /// Trims the atlas with `policy` instead of the policy of the atlas, e.g. to release the
+    /// glyphs kept by [`TrimPolicy::Manual`].
+    pub fn trim_with(&mut self, policy: TrimPolicy) {
+        self.mask_atlas.trim(policy);
+        self.color_atlas.trim(policy);
+        self.trimmed = true;
+        self.prepares_since_trim = 0;
+
+        // A new frame starts, and glyphs no `prepare` deferred again since the last trim are no
+        // longer shown
+        self.frame_upload_bytes = 0;
+        self.upload_backlog
+            .retain(|_, deferred| mem::take(&mut deferred.wanted));
+    }
+
+    /// Sets the number of bytes of glyph bitmaps that `prepare` uploads into the atlas per frame,
+    /// or `None` for no limit (the default), e.g. to keep frames that show a lot of new text
+    /// from hitching on integrated GPUs, where the copies contend with rendering.
+    ///
+    /// A frame ends with [`TextAtlas::trim`]. Once the glyphs uploaded since then reach the
+    /// budget, glyphs that are not cached yet are deferred like by
+    /// [`crate::TextRenderer::prepare_with_budget`]: they are not drawn, and are counted in
+    /// [`crate::DroppedGlyphs::deferred`] and [`TextAtlas::upload_backlog`]. Prepare the same
+    /// text again on the next frames to draw them. The glyph that reaches the budget is still
+    /// uploaded, as is the first glyph of every frame. Glyphs deferred in earlier frames are
+    /// uploaded before new glyphs, so the backlog drains even if the text keeps changing.
+    /// Uploading the cached glyphs again when the atlas grows is not limited.
+    pub fn set_upload_budget(&mut self, budget: Option<usize>) {
+        self.upload_budget = budget;
+    }
+
+    /// Returns the number of bytes of glyph bitmaps that `prepare` uploads per frame.
+    pub fn upload_budget(&self) -> Option<usize> {
+        self.upload_budget
+    }
+
+    /// Returns the number of distinct glyphs that were deferred by the upload budget of the
+    /// atlas or the time budget of [`crate::TextRenderer::prepare_with_budget`] and are not in
+    /// the atlas yet, e.g. to show a loading state until it drops to zero.
+    ///
+    /// Glyphs that no `prepare` deferred again during a frame leave the backlog at the
+    /// following trim, since the text showing them is gone.
+    pub fn upload_backlog(&self) -> usize {
+        self.upload_backlog.len()
+    }
+
+    /// Returns whether the upload budget of the current frame allows uploading another glyph.
+    pub(crate) fn allows_upload(&self) -> bool {
+        match self.upload_budget {
+            Some(budget) => self.frame_upload_bytes < budget.max(1),
+            None => true,
+        }
+    }
+
+    /// Adds the glyph of `cache_key` to the backlog, or keeps it there.
+    pub(crate) fn defer_upload(&mut self, cache_key: GlyphonCacheKey) {
+        let next_order = &mut self.next_deferred_order;
+        let deferred = self.upload_backlog.entry(cache_key).or_insert_with(|| {
+            *next_order += 1;
+            DeferredGlyph {
+                order: *next_order - 1,
+                wanted: false,
+            }
+        });
+        deferred.wanted = true;
+    }
+
+    /// Returns the order the glyph of `cache_key` was deferred in, or `u64::MAX` if it is not in
+    /// the backlog, so that glyphs deferred first are uploaded first.
+    pub(crate) fn deferred_order(&self, cache_key: &GlyphonCacheKey) -> u64 {
+        self.upload_backlog
+            .get(cache_key)
+            .map_or(u64::MAX, |deferred| deferred.order)
+    }
+
+    /// Records that the glyph of `cache_key` was rasterized into `bytes` of uploads, and takes
+    /// it off the backlog.
+    pub(crate) fn record_upload(&mut self, cache_key: GlyphonCacheKey, bytes: usize) {
+        self.frame_upload_bytes = self.frame_upload_bytes.saturating_add(bytes);
+        self.upload_backlog.remove(&cache_key);
+    }
+
+    /// Sets whether `prepare` trims the atlas with its trim policy, instead of the app calling
+    /// [`TextAtlas::trim`] after every frame. Disabled by default.
+    ///
+    /// A new frame starts when a renderer prepares against the atlas a second time since the
+    /// last automatic trim, which trims the atlas before preparing. With one `prepare` per
+    /// renderer and frame, the glyphs of every renderer sharing the atlas are thus kept until
+    /// the renderer that prepares first prepares the next frame. Apps whose renderers prepare
+    /// several times per frame, or render a frame after another renderer prepared the next one,
+    /// should keep trimming manually.
+    pub fn set_auto_trim(&mut self, enabled: bool) {
+        self.auto_trim = enabled;
+        self.prepared_renderers.clear();
+    }
+
+    /// Returns whether `prepare` trims the atlas.
+    pub fn auto_trim(&self) -> bool {
+        self.auto_trim
+    }
+
+    /// Returns the number of `prepare` calls against the atlas since it was last trimmed, e.g.
+    /// to detect a frame loop that forgot to call [`TextAtlas::trim`]: no glyph can be evicted
+    /// until then, so the atlas grows until it is full.
+    pub fn prepares_since_trim(&self) -> u32 {
+        self.prepares_since_trim
+    }
+
+    /// Starts a `prepare` of the renderer `renderer_id` against the atlas, trimming it first if
+    /// it trims automatically and a new frame started.
+    pub(crate) fn auto_trim_for(&mut self, renderer_id: u64) {
+        if self.auto_trim && !self.prepared_renderers.insert(renderer_id) {
+            self.trim();
+            self.prepared_renderers.clear();
+            self.prepared_renderers.insert(renderer_id);
+        }
+    }
+
+    /// Starts a `prepare` against the atlas, which may reuse the space of glyphs released by a
+    /// trim since the previous one. Returns the reuse epoch the prepared text is valid in.
+    pub(crate) fn begin_prepare(&mut self) -> u64 {
+        if mem::take(&mut self.trimmed) {
+            self.reuse_epoch += 1;
+        }
+
+        self.prepares_since_trim = self.prepares_since_trim.saturating_add(1);
+
+        #[cfg(feature = "tracing")]
+        if self.prepares_since_trim == FORGOTTEN_TRIM_PREPARES
+            && self.trim_policy != TrimPolicy::Manual
+        {
+            tracing::warn!(
+                label = %self.label(),
+                prepares = self.prepares_since_trim,
+                "atlas was not trimmed in many prepares, call `TextAtlas::trim` after every \
+                 frame or enable `TextAtlas::set_auto_trim`"
+            );
+        }
+
+        self.reuse_epoch
+    }
+
+    /// Unpins every glyph pinned by areas with [`crate::CachePriority::Pinned`], so that they are
+    /// evicted like any other glyph, e.g. when the UI they belong to is closed.
+    ///
+    /// Glyphs are pinned again by the next `prepare` of a pinned area using them.
+    pub fn unpin(&mut self) {
+        self.mask_atlas.allocator.pinned.clear();
+        self.color_atlas.allocator.pinned.clear();
+    }
+
+    /// Sets which glyphs [`TextAtlas::trim`] releases. Defaults to [`TrimPolicy::EveryFrame`].
+    pub fn set_trim_policy(&mut self, policy: TrimPolicy) {
+        self.trim_policy = policy;
+    }
+
+    /// Returns which glyphs [`TextAtlas::trim`] releases.
+    pub fn trim_policy(&self) -> TrimPolicy {
+        self.trim_policy
+    }
+
+    /// Writes the glyphs rasterized since the last call into the atlas textures.
+    pub(crate) fn flush_uploads(&mut self) -> Result<(), PrepareError> {
+        let flushed = self
+            .mask_atlas
+            .flush_uploads()
+            .and(self.color_atlas.flush_uploads());
+        if flushed.is_err() {
+            self.device_lost = true;
+            return flushed;
+        }
+
+        if let Some(upload_event) = &self.upload_event {
+            self.upload_event_value += 1;
+            upload_event.setSignaledValue(self.upload_event_value);
+        }
+
+        Ok(())
+    }
+
+    /// Sets whether the atlas signals an event once `prepare` has written glyphs into its
+    /// textures, which is off by default.
+    ///
+    /// Metal tracks the writes into the atlas textures by default, so this is only needed when
+    /// the app disables hazard tracking or schedules its work on several queues: GPU work that
+    /// reads the atlas must then wait for [`TextAtlas::upload_event`] to reach
+    /// [`TextAtlas::upload_event_value`], e.g. with [`TextAtlas::encode_upload_wait`] or the
+    /// `waitForEvent:value:` of a Metal 4 command queue. Each `prepare` signals a new value,
+    /// whether or not it wrote glyphs.
+    pub fn set_upload_event(&mut self, enabled: bool) {
+        if enabled == self.upload_event.is_some() {
+            return;
+        }
+
+        self.upload_event = enabled.then(|| {
+            let upload_event = self
+                .device
+                .newSharedEvent()
+                .expect("Failed to create shared event");
+            upload_event.setLabel(Some(&resource_label(
+                &self.mask_atlas.label,
+                "Upload Event",
+            )));
+            upload_event.setSignaledValue(self.upload_event_value);
+
+            upload_event
+        });
+    }
+
+    /// Returns the event signaled once glyphs are written into the atlas textures, or `None` if
+    /// it is disabled (see [`TextAtlas::set_upload_event`]).
+    pub fn upload_event(&self) -> Option<&Retained<ProtocolObject<dyn MTLSharedEvent>>> {
+        self.upload_event.as_ref()
+    }
+
+    /// Returns the value of [`TextAtlas::upload_event`] signaled by the most recent `prepare`,
+    /// which GPU work reading the atlas must wait for.
+    pub fn upload_event_value(&self) -> u64 {
+        self.upload_event_value
+    }
+
+    /// Encodes a wait for the glyphs written by the most recent `prepare` into `command_buffer`,
+    /// if the upload event is enabled (see [`TextAtlas::set_upload_event`]).
+    ///
+    /// Waits apply to the commands encoded after them, so call it before creating the render
+    /// command encoder that text is rendered into.
+    pub fn encode_upload_wait(&self, command_buffer: &ProtocolObject<dyn MTLCommandBuffer>) {
+        if let Some(upload_event) = &self.upload_event {
+            command_buffer.encodeWaitForEvent_value(
+                ProtocolObject::from_ref(&**upload_event),
+                self.upload_event_value,
+            );
+        }
     }
 
     pub(crate) fn grow(
         &mut self,
-        device: &Retained<ProtocolObject<dyn MTLDevice>>,
         font_system: &mut FontSystem,
         cache: &mut SwashCache,
         content_type: ContentType,
+        new_size: u32,
         scale_factor: f32,
         rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
-    ) -> bool {
-        let did_grow = match content_type {
+    ) -> Result<(), PrepareError> {
+        let grown = match content_type {
             ContentType::Mask => self.mask_atlas.grow(
-                device,
+                &self.device,
+                new_size,
                 font_system,
                 cache,
                 scale_factor,
                 rasterize_custom_glyph,
             ),
             ContentType::Color => self.color_atlas.grow(
-                device,
+                &self.device,
+                new_size,
                 font_system,
                 cache,
                 scale_factor,
@@ -347,7 +1532,33 @@ impl TextAtlas {
             ),
         };
 
-        did_grow
+        if grown.is_err() {
+            self.device_lost = true;
+        }
+
+        grown
+    }
+
+    /// Returns the generation of the glyph of `key` cached in the atlas of `content_type`, see
+    /// [`AtlasGlyph::generation`].
+    pub(crate) fn glyph_generation(
+        &self,
+        key: &GlyphonCacheKey,
+        content_type: ContentType,
+    ) -> Option<u64> {
+        let allocator = &self.inner_for_content(content_type).allocator;
+
+        allocator
+            .glyph_cache
+            .peek(key)
+            .map(|details| details.generation)
+    }
+
+    pub(crate) fn inner_for_content(&self, content_type: ContentType) -> &InnerAtlas {
+        match content_type {
+            ContentType::Color => &self.color_atlas,
+            ContentType::Mask => &self.mask_atlas,
+        }
     }
 
     pub(crate) fn inner_for_content_mut(&mut self, content_type: ContentType) -> &mut InnerAtlas {
@@ -359,11 +1570,250 @@ impl TextAtlas {
 
     pub(crate) fn get_or_create_pipeline(
         &self,
-        device: &Retained<ProtocolObject<dyn MTLDevice>>,
-        depth_format: MTLPixelFormat,
-        sample_count: usize,
+        device: &ProtocolObject<dyn MTLDevice>,
+        options: RendererOptions,
+        content_type: Option<ContentType>,
+        vertex_stage: VertexStage,
+    ) -> Result<Retained<ProtocolObject<dyn MTLRenderPipelineState>>, Retained<NSError>> {
+        self.cache.get_or_create_pipeline(
+            device,
+            PipelineKey {
+                pixel_format: self.pixel_format,
+                depth_format: options.depth_format,
+                sample_count: options.sample_count,
+                single_channel_output: self.single_channel_output,
+                alpha_mode: options.alpha_mode,
+                blend_mode: options.blend_mode,
+                content_type,
+                mask_only: false,
+                vertex_stage,
+            },
+        )
+    }
+
+    /// Returns the number of times the shaders of the cache were reloaded.
+    #[cfg(feature = "dev-tools")]
+    pub(crate) fn shader_generation(&self) -> u64 {
+        self.cache.shader_generation()
+    }
+
+    /// Returns the pipeline of [`crate::TextRenderer::render_mask_only`] for single-sampled
+    /// targets of `pixel_format` without a depth attachment.
+    pub(crate) fn get_or_create_mask_pipeline(
+        &self,
+        device: &ProtocolObject<dyn MTLDevice>,
+        pixel_format: MTLPixelFormat,
+        content_type: ContentType,
     ) -> Retained<ProtocolObject<dyn MTLRenderPipelineState>> {
         self.cache
-            .get_or_create_pipeline(device, self.pixel_format, depth_format, sample_count)
+            .get_or_create_pipeline(
+                device,
+                PipelineKey {
+                    pixel_format,
+                    depth_format: MTLPixelFormat::Invalid,
+                    sample_count: 1,
+                    single_channel_output: self.single_channel_output,
+                    alpha_mode: AlphaMode::Premultiplied,
+                    blend_mode: BlendMode::Over,
+                    content_type: Some(content_type),
+                    mask_only: true,
+                    vertex_stage: VertexStage::Single,
+                },
+            )
+            .expect("Failed to create mask pipeline state")
+    }
+}
+
+/// A glyph in the upload backlog of a [`TextAtlas`].
+struct DeferredGlyph {
+    /// The order the glyph was first deferred in.
+    order: u64,
+    /// Whether a `prepare` deferred the glyph since the last trim.
+    wanted: bool,
+}
+
+/// The options of one of the two atlases of a [`TextAtlasBuilder`].
+#[derive(Clone, Copy, Default)]
+struct AtlasOptions {
+    packing: AtlasPacking,
+    initial_size: Option<u32>,
+    max_size: Option<u32>,
+}
+
+/// A builder for a [`TextAtlas`], created with [`TextAtlas::builder`].
+///
+/// Mask glyphs (most text) and color glyphs (e.g. emoji) are cached in separate atlases, which
+/// grow independently and can be given separate sizes: an application whose color glyphs are a
+/// handful of emoji can keep the color atlas small while its mask atlas grows as needed.
+pub struct TextAtlasBuilder<'a> {
+    device: &'a ProtocolObject<dyn MTLDevice>,
+    cache: &'a Cache,
+    format: MTLPixelFormat,
+    color_mode: ColorMode,
+    color_format: ColorAtlasFormat,
+    mask: AtlasOptions,
+    color: AtlasOptions,
+}
+
+impl TextAtlasBuilder<'_> {
+    /// Sets the [`ColorMode`] of the atlas. The default is [`ColorMode::Accurate`].
+    pub fn color_mode(mut self, color_mode: ColorMode) -> Self {
+        self.color_mode = color_mode;
+        self
+    }
+
+    /// Sets how glyphs are packed into the atlas of `content_type`. The default is
+    /// [`AtlasPacking::default`].
+    pub fn packing(mut self, content_type: ContentType, packing: AtlasPacking) -> Self {
+        self.options_mut(content_type).packing = packing;
+        self
+    }
+
+    /// Sets the size the atlas of `content_type` starts at, in texels per side. The default is
+    /// 256.
+    ///
+    /// The atlas doubles in size when it runs out of space, up to its maximum size (see
+    /// [`TextAtlasBuilder::max_size`]).
+    pub fn initial_size(mut self, content_type: ContentType, size: u32) -> Self {
+        self.options_mut(content_type).initial_size = Some(size);
+        self
+    }
+
+    /// Sets the largest size the atlas of `content_type` grows to, in texels per side. The
+    /// default is the largest texture size of the device: 16384 on Macs and A9 or later GPUs,
+    /// 8192 on earlier ones.
+    ///
+    /// Once an atlas has reached its maximum size, glyphs that do not fit are evicted or, if
+    /// they are all in use, `prepare` fails with [`crate::PrepareError::AtlasFull`]. The default
+    /// largest glyph size of renderers depends on the smaller of the two maximum sizes (see
+    /// [`crate::TextRenderer::set_max_glyph_size`]).
+    pub fn max_size(mut self, content_type: ContentType, size: u32) -> Self {
+        self.options_mut(content_type).max_size = Some(size);
+        self
+    }
+
+    /// Sets the channel order of the texture of the color atlas. The default is
+    /// [`ColorAtlasFormat::Rgba8`].
+    pub fn color_format(mut self, color_format: ColorAtlasFormat) -> Self {
+        self.color_format = color_format;
+        self
+    }
+
+    /// Creates the [`TextAtlas`].
+    ///
+    /// Fails with [`BuildError::InvalidAtlasSize`] if an initial size is zero or exceeds the
+    /// maximum size of its atlas, or a maximum size exceeds the largest texture size of the
+    /// device.
+    pub fn build(self) -> Result<TextAtlas, BuildError> {
+        let Self {
+            device,
+            cache,
+            format,
+            color_mode,
+            color_format,
+            mask,
+            color,
+        } = self;
+
+        let device_max_size = max_texture_size(device);
+        let sizes = |content_type, options: AtlasOptions| {
+            let max_size = options.max_size.unwrap_or(device_max_size);
+            let initial_size = options
+                .initial_size
+                .unwrap_or(InnerAtlas::INITIAL_SIZE.min(max_size));
+
+            if initial_size == 0 || initial_size > max_size || max_size > device_max_size {
+                return Err(BuildError::InvalidAtlasSize {
+                    content_type,
+                    initial_size,
+                    max_size,
+                    device_max_size,
+                });
+            }
+
+            Ok((initial_size, max_size))
+        };
+
+        let (mask_initial_size, mask_max_size) = sizes(ContentType::Mask, mask)?;
+        let (color_initial_size, color_max_size) = sizes(ContentType::Color, color)?;
+
+        let color_atlas = InnerAtlas::new(
+            device,
+            Kind::Color {
+                srgb: match color_mode {
+                    ColorMode::Accurate => true,
+                    ColorMode::Web => false,
+                },
+                format: color_format,
+            },
+            color.packing,
+            color_initial_size,
+            color_max_size,
+            DEFAULT_LABEL,
+        )
+        .ok_or(BuildError::DeviceLost)?;
+
+        let mask_atlas = InnerAtlas::new(
+            device,
+            Kind::Mask,
+            mask.packing,
+            mask_initial_size,
+            mask_max_size,
+            DEFAULT_LABEL,
+        )
+        .ok_or(BuildError::DeviceLost)?;
+
+        Ok(TextAtlas::from_inner(
+            device,
+            cache,
+            format,
+            color_mode,
+            color_atlas,
+            mask_atlas,
+        ))
+    }
+
+    fn options_mut(&mut self, content_type: ContentType) -> &mut AtlasOptions {
+        match content_type {
+            ContentType::Mask => &mut self.mask,
+            ContentType::Color => &mut self.color,
+        }
+    }
+}
+
+/// A [`TextAtlas`] shared between renderers that are prepared independently, e.g. by different
+/// subsystems or threads, without the caller managing exclusive access to the atlas.
+///
+/// The atlas is behind a single mutex. [`TextRenderer::prepare_shared`] holds the lock for the
+/// whole of `prepare`, since any glyph it caches may be evicted or grow the atlas, so preparing
+/// renderers still run one at a time. Only the preparation of text that shares no atlas
+/// (e.g. shaping buffers) runs in parallel. Lock the atlas with [`SharedTextAtlas::lock`] to
+/// create a renderer, render or call any other method of [`TextAtlas`].
+///
+/// [`TextRenderer::prepare_shared`]: crate::TextRenderer::prepare_shared
+#[derive(Clone)]
+pub struct SharedTextAtlas(Arc<Mutex<TextAtlas>>);
+
+impl SharedTextAtlas {
+    /// Shares `atlas`.
+    pub fn new(atlas: TextAtlas) -> Self {
+        Self(Arc::new(Mutex::new(atlas)))
+    }
+
+    /// Locks the atlas, blocking until no other renderer is preparing against it.
+    pub fn lock(&self) -> MutexGuard<'_, TextAtlas> {
+        self.0.lock().expect("Lock shared text atlas")
+    }
+
+    /// Trims the atlas, see [`TextAtlas::trim`]. Call it once every renderer sharing the atlas
+    /// has rendered the current frame.
+    pub fn trim(&self) {
+        self.lock().trim();
+    }
+}
+
+impl From<TextAtlas> for SharedTextAtlas {
+    fn from(atlas: TextAtlas) -> Self {
+        Self::new(atlas)
     }
 }
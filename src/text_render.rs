@@ -1,68 +1,820 @@
+#[cfg(feature = "signposts")]
+use crate::signpost;
+#[cfg(debug_assertions)]
+use crate::DroppedGlyph;
+#[cfg(feature = "debug-tools")]
+use crate::Snapshot;
 use crate::{
-    custom_glyph::CustomGlyphCacheKey, ColorMode, ContentType, FontSystem, GlyphDetails,
-    GlyphToRender, GpuCacheStatus, PrepareError, RasterizeCustomGlyphRequest,
-    RasterizedCustomGlyph, SwashCache, SwashContent, TextArea, TextAtlas, Viewport,
+    cache::{is_linear_format, RendererOptions, VertexStage},
+    custom_glyph::CustomGlyphCacheKey,
+    dedup::AreaDedup,
+    dropped::{DropReason, DropTracker},
+    font_usage::AreaFontUsage,
+    frame_state::FrameState,
+    geometry_cache::{self, AreaGlyph, GeometryCache},
+    glyph_allocator::{next_generation, AllocationStep, Hasher},
+    pixel_format,
+    profile::{Phase, PreparePhaseTimings, Profiler},
+    rasterize::{self, RasterConfigKey, TofuKey},
+    resource_label, AlphaMode, AreaOverdraw, BitmapStrikePolicy, BlendMode, BuildError,
+    CachePriority, ColorMode, ContentType, CustomGlyph, CustomGlyphId, DroppedGlyphs,
+    DuplicateAreas, EncoderViewport, FaceStyle, FontSynthesis, FontSystem, FontSystemAccess,
+    FrameValidation, GlyphAnimContext, GlyphArea, GlyphDetails, GlyphPlacement, GlyphRasterConfig,
+    GlyphToRender, GlyphTransform, GlyphVertex, GpuCacheStatus, MemoryUsage, Overdraw,
+    PrepareError, RasterizeCustomGlyphRequest, RasterizedCustomGlyph, RenderError, SharedTextAtlas,
+    StereoPath, SwashCache, SwashContent, TargetColorSpace, TextArea, TextAtlas, TextBindings,
+    TextBounds, TextRenderEncoder, ViewTransform, Viewport, DEFAULT_LABEL,
 };
-use cosmic_text::{Color, SubpixelBin};
-use objc2::{rc::Retained, runtime::ProtocolObject};
-use objc2_foundation::ns_string;
+use cosmic_text::{Attrs, Buffer, Color, LayoutGlyph, LayoutRun, Metrics, Shaping, SubpixelBin};
+use objc2::{rc::Retained, runtime::ProtocolObject, Message as _};
+use objc2_foundation::{ns_string, NSRange};
 use objc2_metal::{
-    MTLBuffer, MTLDevice, MTLOrigin, MTLPixelFormat, MTLPrimitiveType, MTLRegion,
-    MTLRenderCommandEncoder, MTLRenderPipelineState, MTLResource as _, MTLResourceOptions, MTLSize,
-    MTLTexture as _,
+    MTLBuffer, MTLCommandEncoder as _, MTLDevice, MTLIndirectCommandBuffer,
+    MTLIndirectRenderCommand as _, MTLPixelFormat, MTLPrimitiveType, MTLRenderCommandEncoder,
+    MTLRenderPassDescriptor, MTLRenderPipelineState, MTLRenderStages, MTLResource,
+    MTLResourceOptions, MTLResourceUsage, MTLTexture as _,
+};
+use std::{
+    cell::{Cell, OnceCell, RefCell},
+    collections::HashSet,
+    mem,
+    ops::Range,
+    ptr::{self, NonNull},
+    slice,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+#[cfg(feature = "mtl4")]
+use {
+    crate::encoder::residency_sets_available,
+    objc2_metal::{MTL4ArgumentTable, MTLAllocation, MTLResidencySet, MTLResidencySetDescriptor},
+};
+#[cfg(feature = "profiling")]
+use {
+    crate::profile::{FrameProfile, GpuTimer},
+    objc2_metal::MTLCommandBuffer,
 };
-use std::{ptr::NonNull, slice};
 
 const COPY_BUFFER_ALIGNMENT: u64 = 4;
+/// The number of glyphs known to be empty that a renderer remembers before starting over.
+const MAX_EMPTY_GLYPHS: usize = 4096;
+/// The largest size of the atlas textures divided by the default maximum glyph size, see
+/// [`TextRenderer::set_max_glyph_size`].
+const MAX_GLYPH_SIZE_DIVISOR: f32 = 16.0;
 
 /// A text renderer that uses cached glyphs to render text into an existing render pass.
+///
+/// A `TextRenderer` is `Send` but not `Sync`, so `prepare` can run on a worker thread while
+/// another thread only encodes: move the renderer, its [`TextAtlas`] and [`Viewport`] to the
+/// worker, prepare, and move them back to render. `prepare` overwrites the vertex buffer, which
+/// must not happen while the GPU still reads the previous frame.
 pub struct TextRenderer {
+    device: Retained<ProtocolObject<dyn MTLDevice>>,
     vertex_buffer: Retained<ProtocolObject<dyn MTLBuffer>>,
     vertex_buffer_size: u64,
+    vertex_storage: MTLResourceOptions,
+    pixel_format: MTLPixelFormat,
+    /// The options the pipelines were created with.
+    options: RendererOptions,
+    /// Draws glyphs of both content types, used by indirect command buffers.
     pipeline: Retained<ProtocolObject<dyn MTLRenderPipelineState>>,
+    /// Draws only color glyphs and only mask glyphs respectively, binding a single atlas.
+    content_pipelines: [Retained<ProtocolObject<dyn MTLRenderPipelineState>>; 2],
+    /// The format of the targets of `render_mask_only`.
+    mask_format: MTLPixelFormat,
+    /// Like `content_pipelines`, for `render_mask_only`, created when it is first called.
+    mask_pipelines: OnceCell<[Retained<ProtocolObject<dyn MTLRenderPipelineState>>; 2]>,
+    /// Like `content_pipelines`, for `render_to_layer`, if the renderer was built for layers.
+    layered_pipelines: Option<[Retained<ProtocolObject<dyn MTLRenderPipelineState>>; 2]>,
+    /// Like `content_pipelines`, for `render_stereo`, if the renderer was built for stereo.
+    stereo_pipelines: Option<(
+        StereoPath,
+        [Retained<ProtocolObject<dyn MTLRenderPipelineState>>; 2],
+    )>,
+    /// The [`crate::Cache::shader_generation`] the pipelines were created from.
+    #[cfg(feature = "dev-tools")]
+    shader_generation: u64,
+    #[cfg(feature = "mtl4")]
+    argument_table: OnceCell<Retained<ProtocolObject<dyn MTL4ArgumentTable>>>,
+    #[cfg(feature = "mtl4")]
+    residency_set: OnceCell<Option<Retained<ProtocolObject<dyn MTLResidencySet>>>>,
+    /// The vertex buffer, atlas textures and viewport buffer in the residency set.
+    #[cfg(feature = "mtl4")]
+    resident_resources: [Option<Retained<ProtocolObject<dyn MTLAllocation>>>; 4],
     glyph_vertices: Vec<GlyphToRender>,
+    /// The vertices of the previous `prepare`, only kept once indirect commands were encoded.
+    previous_glyph_vertices: Vec<GlyphToRender>,
+    /// `glyph_vertices` as written into the vertex buffer, see [`pack_vertices`].
+    vertex_data: Vec<u8>,
+    /// Consecutive ranges of `glyph_vertices` with the same content type, drawn one at a time.
+    draw_ranges: Vec<(ContentType, Range<usize>)>,
+    empty_glyphs: HashSet<GlyphonCacheKey, Hasher>,
+    geometry_cache: Option<GeometryCache>,
+    /// The geometry of the text areas of the `prepare` in progress, if it is shared between
+    /// areas showing the same buffer.
+    shared_geometry: Option<GeometryCache>,
+    /// The text areas of the `prepare` in progress, if duplicates are skipped.
+    area_dedup: Option<AreaDedup>,
+    duplicate_areas: DuplicateAreas,
+    frame_state: FrameState,
+    /// Tells the renderers sharing an atlas apart, see [`TextAtlas::set_auto_trim`].
+    id: u64,
+    /// The glyphs drawn by the text area being prepared, if its geometry is cached.
+    area_glyphs: Vec<AreaGlyph>,
+    /// The glyphs of the `prepare` in progress that are not in the atlas yet.
+    missing_glyphs: Vec<MissingGlyph>,
+    /// The ranges of `glyph_vertices` of the text areas with missing glyphs.
+    incomplete_areas: Vec<Range<usize>>,
+    /// The glyphs of the text area being grouped by content type, in their new order.
+    grouped_glyphs: Vec<GlyphToRender>,
+    /// The screen rectangles of the glyphs of the text area being grouped by content type.
+    glyph_rects: Vec<QuadRect>,
+    /// The fonts of each text area of the most recent `prepare`, if they are collected.
+    font_usage: Option<Vec<AreaFontUsage>>,
+    /// The pixels covered by the glyph quads of the most recent `prepare`, if they are counted.
+    overdraw: Option<Overdraw>,
+    /// The glyphs the most recent `prepare` did not draw.
+    drops: DropTracker,
+    bitmap_strike_policy: BitmapStrikePolicy,
+    raster_config: GlyphRasterConfig,
+    tofu: bool,
+    max_glyph_size: Option<f32>,
+    geometry_generation: u64,
+    /// The buffer of the viewport of the most recent `prepare`, see [`Viewport::buffer_id`].
+    geometry_viewport: Option<u64>,
+    /// Whether [`TextRenderer::encode_into_icb`] was called, so `prepare` compares geometry.
+    encoded_icb: Cell<bool>,
+    debug_markers: bool,
+    encoder_viewport: Option<EncoderViewport>,
+    atlas_grew: bool,
+    /// See [`TextRenderer::is_device_lost`].
+    device_lost: bool,
+    label: String,
+    profiler: Profiler,
+    #[cfg(feature = "profiling")]
+    gpu_timer: OnceCell<Option<GpuTimer>>,
 }
 
-impl TextRenderer {
-    /// Creates a new `TextRenderer`.
-    pub fn new(
-        atlas: &mut TextAtlas,
-        device: &Retained<ProtocolObject<dyn MTLDevice>>,
-        depth_format: MTLPixelFormat,
-        sample_count: usize,
-    ) -> Self {
+/// The progress of [`TextRenderer::prepare_with_budget`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[must_use]
+pub struct PrepareProgress {
+    /// The number of glyphs that were not rasterized because the budget or the upload budget of
+    /// the atlas (see [`TextAtlas::set_upload_budget`]) was exhausted, and are not drawn. Glyphs
+    /// appearing several times are counted each time.
+    pub deferred_glyphs: usize,
+}
+
+impl PrepareProgress {
+    /// Returns whether every glyph was rasterized, so the text is drawn completely.
+    pub fn is_complete(&self) -> bool {
+        self.deferred_glyphs == 0
+    }
+}
+
+/// The outcome of [`TextRenderer::prewarm`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrewarmStats {
+    /// The number of distinct glyphs that were rasterized into the atlas, including empty glyphs
+    /// like spaces, which are cached without taking up space.
+    pub added: usize,
+    /// The number of distinct glyphs that were already cached.
+    pub already_present: usize,
+}
+
+/// What a `render` of a [`TextRenderer`] encoded, e.g. to check that text is drawn with as few
+/// draw calls as expected.
+///
+/// A render that encodes nothing, because nothing was prepared or the viewport is not
+/// renderable, returns the default, all zeros.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    /// The number of draw calls.
+    pub draw_calls: usize,
+    /// The number of times the render pipeline state was set, including the first time.
+    pub pipeline_switches: usize,
+    /// The number of glyph quads drawn, one instance each, counted once per view of
+    /// [`TextRenderer::render_stereo`].
+    pub glyphs: usize,
+    /// The number of vertices drawn, four per glyph quad.
+    pub vertices: usize,
+    /// Whether the texture of the color atlas was bound.
+    pub color_atlas_bound: bool,
+    /// Whether the texture of the mask atlas was bound.
+    pub mask_atlas_bound: bool,
+}
+
+/// The layers of the render target a render draws into.
+#[derive(Debug, Clone, Copy)]
+enum RenderViews {
+    /// The render target, or its first layer.
+    Single,
+    /// A layer of the render target, see [`TextRenderer::render_to_layer`].
+    Layer(usize),
+    /// Both views of a stereo render, with the parameter slot of the second view.
+    Stereo(StereoPath, usize),
+}
+
+impl RenderViews {
+    /// Returns the vertex function of the pipelines drawing into the views.
+    #[cfg(feature = "dev-tools")]
+    fn vertex_stage(self) -> VertexStage {
+        match self {
+            RenderViews::Single => VertexStage::Single,
+            RenderViews::Layer(_) => VertexStage::Layered,
+            RenderViews::Stereo(path, _) => VertexStage::Stereo(path),
+        }
+    }
+}
+
+/// A builder for a [`TextRenderer`], created with [`TextRenderer::builder`].
+pub struct TextRendererBuilder<'a> {
+    atlas: &'a mut TextAtlas,
+    depth_format: MTLPixelFormat,
+    sample_count: usize,
+    alpha_mode: Option<AlphaMode>,
+    blend_mode: BlendMode,
+    label: String,
+    vertex_storage: MTLResourceOptions,
+    mask_format: MTLPixelFormat,
+    layered: bool,
+    stereo: bool,
+}
+
+impl<'a> TextRendererBuilder<'a> {
+    /// Sets the pixel format of the depth attachment of the render pass. The default is
+    /// `MTLPixelFormat::Invalid` (no depth attachment).
+    ///
+    /// Formats with a stencil aspect (e.g. `Depth32Float_Stencil8`) also set the stencil
+    /// attachment, and `Stencil8` sets only the stencil attachment, for passes that clip text
+    /// with a stencil test (see [`TextRenderer::render`]).
+    pub fn depth_format(mut self, depth_format: MTLPixelFormat) -> Self {
+        self.depth_format = depth_format;
+        self
+    }
+
+    /// Sets the sample count of the render pass. The default is `1`.
+    pub fn sample_count(mut self, sample_count: usize) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    /// Overrides the [`AlphaMode`] of the atlas for this renderer.
+    pub fn alpha_mode(mut self, alpha_mode: AlphaMode) -> Self {
+        self.alpha_mode = Some(alpha_mode);
+        self
+    }
+
+    /// Sets how text is combined with the render target. The default is [`BlendMode::Over`].
+    ///
+    /// Several renderers sharing an atlas can use different blend modes, e.g. one erasing
+    /// glyph-shaped holes with [`BlendMode::DestinationOut`] for a frosted label, and one
+    /// drawing regular text over the result.
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    /// Sets the prefix of the labels of the resources owned by the renderer (see
+    /// [`TextRenderer::set_label`]).
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = label.to_owned();
+        self
+    }
+
+    /// Sets the resource options of the vertex buffer, which apply whenever the buffer is
+    /// reallocated to fit more vertices as well.
+    ///
+    /// Vertices are written by the CPU, so the storage mode must be shared, or managed on macOS.
+    /// The default depends on the device: `StorageModeShared` with unified memory (Apple silicon,
+    /// integrated GPUs) and `StorageModeManaged` otherwise (discrete GPUs), both with
+    /// `CPUCacheModeWriteCombined`, since vertices are never read back by the CPU.
+    ///
+    /// `prepare` overwrites the vertex buffer in place, which Metal never synchronizes with GPU
+    /// work, whatever the options: the caller must make sure that the GPU is done with the
+    /// previous frame (e.g. by waiting on the command buffer or a shared event) before preparing
+    /// again. `HazardTrackingModeUntracked` needs no further synchronization, since the GPU only
+    /// reads the buffer.
+    pub fn vertex_storage(mut self, vertex_storage: MTLResourceOptions) -> Self {
+        self.vertex_storage = vertex_storage;
+        self
+    }
+
+    /// Sets the pixel format of the render targets of [`TextRenderer::render_mask_only`]. The
+    /// default is `MTLPixelFormat::R8Unorm`.
+    pub fn mask_format(mut self, mask_format: MTLPixelFormat) -> Self {
+        self.mask_format = mask_format;
+        self
+    }
+
+    /// Sets whether the renderer can draw into a chosen layer of a layered render target (e.g. a
+    /// texture array) with [`TextRenderer::render_to_layer`]. The default is `false`.
+    pub fn layered(mut self, layered: bool) -> Self {
+        self.layered = layered;
+        self
+    }
+
+    /// Sets whether the renderer can draw the same text into both views of a layered render
+    /// target with [`TextRenderer::render_stereo`], e.g. a HUD for both eyes. The default is
+    /// `false`.
+    ///
+    /// Devices supporting two views of vertex amplification draw each view in the same draw
+    /// call, other devices draw once per view (see [`TextRenderer::stereo_path`]).
+    pub fn stereo(mut self, stereo: bool) -> Self {
+        self.stereo = stereo;
+        self
+    }
+
+    /// Validates the options against the device and creates the [`TextRenderer`].
+    ///
+    /// Fails with [`BuildError::UnsupportedPixelFormat`] if text cannot be blended into the pixel
+    /// format of the atlas or the mask format on this device.
+    pub fn build(self) -> Result<TextRenderer, BuildError> {
+        let Self {
+            atlas,
+            depth_format,
+            sample_count,
+            alpha_mode,
+            blend_mode,
+            label,
+            vertex_storage,
+            mask_format,
+            layered,
+            stereo,
+        } = self;
+
+        let device = &*atlas.device().retain();
+
+        if sample_count == 0 || !device.supportsTextureSampleCount(sample_count) {
+            return Err(BuildError::UnsupportedSampleCount(sample_count));
+        }
+
+        if !is_cpu_writable(vertex_storage) {
+            return Err(BuildError::UnsupportedVertexStorage);
+        }
+
+        for format in [atlas.pixel_format, mask_format] {
+            pixel_format::check_render_format(device, format)
+                .map_err(|reason| BuildError::UnsupportedPixelFormat { format, reason })?;
+        }
+
         let vertex_buffer_size = next_copy_buffer_size(4096);
 
         let vertex_buffer = device
-            .newBufferWithLength_options(
-                vertex_buffer_size as usize,
-                MTLResourceOptions::StorageModeShared,
-            )
-            .unwrap();
-        vertex_buffer.setLabel(Some(ns_string!("Metalglyph - Vertex Buffer")));
+            .newBufferWithLength_options(vertex_buffer_size as usize, vertex_storage)
+            .ok_or(BuildError::DeviceLost)?;
+        vertex_buffer.setLabel(Some(&resource_label(&label, "Vertex Buffer")));
 
-        let pipeline = atlas.get_or_create_pipeline(&device, depth_format, sample_count);
+        let options = RendererOptions {
+            depth_format,
+            sample_count,
+            alpha_mode: alpha_mode.unwrap_or(atlas.alpha_mode),
+            blend_mode,
+        };
+        let pipeline = |content_type, vertex_stage| {
+            atlas
+                .get_or_create_pipeline(device, options, content_type, vertex_stage)
+                .map_err(|error| BuildError::PipelineCreation(error.to_string()))
+        };
+        let pipelines = |vertex_stage| -> Result<_, BuildError> {
+            Ok([
+                pipeline(Some(ContentType::Color), vertex_stage)?,
+                pipeline(Some(ContentType::Mask), vertex_stage)?,
+            ])
+        };
+        let content_pipelines = pipelines(VertexStage::Single)?;
+        let layered_pipelines = if layered {
+            Some(pipelines(VertexStage::Layered)?)
+        } else {
+            None
+        };
+        let stereo_pipelines = if stereo {
+            let path = StereoPath::for_device(device);
+            Some((path, pipelines(VertexStage::Stereo(path))?))
+        } else {
+            None
+        };
+        let pipeline = pipeline(None, VertexStage::Single)?;
 
-        Self {
+        Ok(TextRenderer {
+            device: device.retain(),
             vertex_buffer,
             vertex_buffer_size,
+            vertex_storage,
+            pixel_format: atlas.pixel_format,
+            options,
             pipeline,
+            content_pipelines,
+            mask_format,
+            mask_pipelines: OnceCell::new(),
+            layered_pipelines,
+            stereo_pipelines,
+            #[cfg(feature = "dev-tools")]
+            shader_generation: atlas.shader_generation(),
+            #[cfg(feature = "mtl4")]
+            argument_table: OnceCell::new(),
+            #[cfg(feature = "mtl4")]
+            residency_set: OnceCell::new(),
+            #[cfg(feature = "mtl4")]
+            resident_resources: Default::default(),
             glyph_vertices: Vec::new(),
+            previous_glyph_vertices: Vec::new(),
+            vertex_data: Vec::new(),
+            draw_ranges: Vec::new(),
+            empty_glyphs: HashSet::default(),
+            geometry_cache: None,
+            shared_geometry: None,
+            area_dedup: None,
+            duplicate_areas: DuplicateAreas::default(),
+            frame_state: FrameState::default(),
+            id: next_renderer_id(),
+            area_glyphs: Vec::new(),
+            missing_glyphs: Vec::new(),
+            incomplete_areas: Vec::new(),
+            grouped_glyphs: Vec::new(),
+            glyph_rects: Vec::new(),
+            font_usage: None,
+            overdraw: None,
+            drops: DropTracker::default(),
+            bitmap_strike_policy: BitmapStrikePolicy::default(),
+            raster_config: GlyphRasterConfig::default(),
+            tofu: false,
+            max_glyph_size: None,
+            geometry_generation: 0,
+            geometry_viewport: None,
+            encoded_icb: Cell::new(false),
+            debug_markers: true,
+            encoder_viewport: None,
+            atlas_grew: false,
+            device_lost: false,
+            label,
+            profiler: Profiler::default(),
+            #[cfg(feature = "profiling")]
+            gpu_timer: OnceCell::new(),
+        })
+    }
+}
+
+// SAFETY: Metal objects may be used from any thread, one at a time. Lazily created objects live
+// in `OnceCell`s, which is why the renderer is not `Sync`.
+unsafe impl Send for TextRenderer {}
+
+impl TextRenderer {
+    /// Creates a new `TextRenderer` for render passes with the given depth attachment format
+    /// (`MTLPixelFormat::Invalid` without a depth attachment) and sample count.
+    ///
+    /// If the device does not support `sample_count`, the nearest lower supported count is used
+    /// instead, which [`TextRenderer::sample_count`] returns. Use [`TextRenderer::builder`] to
+    /// get an error instead, and for more options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if text cannot be rendered into the pixel format of the atlas, see
+    /// [`BuildError::UnsupportedPixelFormat`].
+    pub fn new(atlas: &mut TextAtlas, depth_format: MTLPixelFormat, sample_count: usize) -> Self {
+        let device = atlas.device();
+
+        let supported_sample_count = (1..=sample_count.max(1))
+            .rev()
+            .find(|&count| device.supportsTextureSampleCount(count))
+            .unwrap_or(1);
+
+        #[cfg(feature = "tracing")]
+        if supported_sample_count != sample_count {
+            tracing::warn!(
+                requested = sample_count,
+                used = supported_sample_count,
+                "sample count is not supported by the device, falling back"
+            );
+        }
+
+        let sample_count = supported_sample_count;
+
+        Self::builder(atlas)
+            .depth_format(depth_format)
+            .sample_count(sample_count)
+            .build()
+            .expect("Failed to create text renderer")
+    }
+
+    /// Returns the sample count the renderer was created for, which the render pass must match.
+    pub fn sample_count(&self) -> usize {
+        self.options.sample_count
+    }
+
+    /// Returns how the renderer combines text with the render target, see
+    /// [`TextRendererBuilder::blend_mode`].
+    pub fn blend_mode(&self) -> BlendMode {
+        self.options.blend_mode
+    }
+
+    /// Returns the pixel format of the render target the renderer was created for, which is
+    /// the format of its [`TextAtlas`].
+    pub fn expected_format(&self) -> MTLPixelFormat {
+        self.pixel_format
+    }
+
+    /// Returns the device the renderer was created with, which prepares and renders its text.
+    pub fn device(&self) -> &ProtocolObject<dyn MTLDevice> {
+        &self.device
+    }
+
+    /// Returns an error naming both formats if `format` is not the pixel format the renderer was
+    /// created for.
+    ///
+    /// Metal render encoders cannot be queried for the formats of their attachments, so
+    /// `render` cannot check them. Rendering into a target of another format fails a Metal
+    /// validation assertion, or silently produces wrong colors (e.g. between `BGRA8Unorm` and
+    /// `BGRA8Unorm_sRGB`). A typical use is to assert the format in debug builds, whenever the
+    /// format of the target may change:
+    ///
+    /// ```ignore
+    /// debug_assert_eq!(text_renderer.check_format(layer.pixelFormat()), Ok(()));
+    /// ```
+    pub fn check_format(&self, format: MTLPixelFormat) -> Result<(), RenderError> {
+        if format == self.pixel_format {
+            Ok(())
+        } else {
+            Err(RenderError::FormatMismatch {
+                expected: self.pixel_format,
+                actual: format,
+            })
+        }
+    }
+
+    /// Checks the format of the texture of the first color attachment of `descriptor` with
+    /// [`TextRenderer::check_format`]. Attachments without a texture are not checked.
+    pub fn check_render_pass(
+        &self,
+        descriptor: &MTLRenderPassDescriptor,
+    ) -> Result<(), RenderError> {
+        let attachment = unsafe { descriptor.colorAttachments().objectAtIndexedSubscript(0) };
+
+        match attachment.texture() {
+            Some(texture) => self.check_format(texture.pixelFormat()),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns whether the device of the renderer failed to create its vertex buffer, as it does
+    /// once it is lost (e.g. when an external GPU is unplugged).
+    ///
+    /// Every `prepare` of a lost renderer returns [`PrepareError::DeviceLost`], and `render`
+    /// draws nothing, until it is recreated with [`TextRenderer::recreate_on`].
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost
+    }
+
+    /// Returns [`RenderError::DeviceLost`] if the device of the renderer or of `atlas` was lost
+    /// (see [`TextAtlas::is_device_lost`]), in which case `render` encodes nothing.
+    ///
+    /// `render` does not return errors, so a frame loop notices a lost device through the error
+    /// of `prepare`, or with this check before encoding.
+    pub fn check_device(&self, atlas: &TextAtlas) -> Result<(), RenderError> {
+        if self.device_lost_with(atlas) {
+            Err(RenderError::DeviceLost)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns whether the device of the renderer or of `atlas` was lost.
+    fn device_lost_with(&self, atlas: &TextAtlas) -> bool {
+        self.device_lost || atlas.is_device_lost()
+    }
+
+    /// Recreates the GPU resources of the renderer on the device of `atlas`, e.g. after its
+    /// device was lost, with the same options. `atlas` must have been recreated on the new device
+    /// first, see [`TextAtlas::recreate_on`].
+    ///
+    /// The settings of the renderer (raster config, caches, tofu, label, ...) are kept, but the
+    /// prepared text is dropped, so it must be prepared again before it is rendered.
+    ///
+    /// Fails like [`TextRendererBuilder::build`] if the options are not supported by the device,
+    /// in which case the renderer stays lost.
+    pub fn recreate_on(&mut self, atlas: &mut TextAtlas) -> Result<(), BuildError> {
+        let renderer = Self::builder(atlas)
+            .depth_format(self.options.depth_format)
+            .sample_count(self.options.sample_count)
+            .alpha_mode(self.options.alpha_mode)
+            .blend_mode(self.options.blend_mode)
+            .label(&self.label)
+            .vertex_storage(self.vertex_storage)
+            .mask_format(self.mask_format)
+            .layered(self.layered_pipelines.is_some())
+            .stereo(self.stereo_pipelines.is_some())
+            .build()?;
+
+        self.device = renderer.device;
+        self.vertex_buffer = renderer.vertex_buffer;
+        self.vertex_buffer_size = renderer.vertex_buffer_size;
+        self.pipeline = renderer.pipeline;
+        self.content_pipelines = renderer.content_pipelines;
+        self.mask_pipelines = renderer.mask_pipelines;
+        self.layered_pipelines = renderer.layered_pipelines;
+        self.stereo_pipelines = renderer.stereo_pipelines;
+        #[cfg(feature = "dev-tools")]
+        {
+            self.shader_generation = renderer.shader_generation;
+        }
+        #[cfg(feature = "mtl4")]
+        {
+            self.argument_table = renderer.argument_table;
+            self.residency_set = renderer.residency_set;
+            self.resident_resources = renderer.resident_resources;
+        }
+        #[cfg(feature = "profiling")]
+        {
+            self.gpu_timer = renderer.gpu_timer;
+        }
+
+        // The prepared glyphs lie in the textures of the old device
+        self.glyph_vertices.clear();
+        self.previous_glyph_vertices.clear();
+        self.draw_ranges.clear();
+        self.empty_glyphs.clear();
+        for geometry_cache in [&mut self.geometry_cache, &mut self.shared_geometry]
+            .into_iter()
+            .flatten()
+        {
+            geometry_cache.clear();
+        }
+        self.frame_state = FrameState::default();
+        self.geometry_generation = self.geometry_generation.wrapping_add(1);
+        self.atlas_grew = false;
+        self.device_lost = false;
+
+        Ok(())
+    }
+
+    /// Returns a [`TextRendererBuilder`] to create a `TextRenderer` with non-default options.
+    pub fn builder(atlas: &mut TextAtlas) -> TextRendererBuilder<'_> {
+        let vertex_storage = default_buffer_options(atlas.device());
+
+        TextRendererBuilder {
+            atlas,
+            depth_format: MTLPixelFormat::Invalid,
+            sample_count: 1,
+            alpha_mode: None,
+            blend_mode: BlendMode::Over,
+            label: DEFAULT_LABEL.to_owned(),
+            vertex_storage,
+            mask_format: MTLPixelFormat::R8Unorm,
+            layered: false,
+            stereo: false,
+        }
+    }
+
+    /// Returns a residency set containing every resource used by `render`, for use with Metal 4
+    /// command queues, or `None` if residency sets are not available on the device.
+    ///
+    /// The residency set is created on first access, so the classic `MTLCommandQueue` path pays
+    /// nothing for it. From then on, every `prepare` (and [`TextRenderer::prewarm`]) keeps it up
+    /// to date with the vertex buffer, the atlas textures and the viewport buffer: resources
+    /// replaced when they are reallocated or the atlas grows are removed from the set, their
+    /// replacements are added, and the set is committed and its residency requested. The set can
+    /// therefore be added to a command queue once. Request it before the first `prepare`, since
+    /// it is empty until then. In debug builds, rendering into a Metal 4 encoder asserts that
+    /// every resource it binds is in the set.
+    #[cfg(feature = "mtl4")]
+    pub fn residency_set(&self) -> Option<&Retained<ProtocolObject<dyn MTLResidencySet>>> {
+        self.residency_set
+            .get_or_init(|| {
+                if !residency_sets_available(&self.device) {
+                    return None;
+                }
+
+                let descriptor = MTLResidencySetDescriptor::new();
+                descriptor.setLabel(Some(&resource_label(&self.label, "Residency Set")));
+
+                let residency_set = self
+                    .device
+                    .newResidencySetWithDescriptor_error(&descriptor)
+                    .expect("Failed to create residency set");
+
+                Some(residency_set)
+            })
+            .as_ref()
+    }
+
+    /// Adds every resource used by `render` with the given `atlas` and `viewport` to
+    /// `residency_set`, without committing it.
+    ///
+    /// This is meant for apps that manage a single residency set themselves. Since `prepare` can
+    /// reallocate resources, it must be called again after every `prepare`.
+    #[cfg(feature = "mtl4")]
+    pub fn add_resources_to(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        residency_set: &ProtocolObject<dyn MTLResidencySet>,
+    ) {
+        residency_set.addAllocation(ProtocolObject::from_ref(&*self.vertex_buffer));
+        residency_set.addAllocation(ProtocolObject::from_ref(&*atlas.color_atlas.texture));
+        residency_set.addAllocation(ProtocolObject::from_ref(&*atlas.mask_atlas.texture));
+        residency_set.addAllocation(ProtocolObject::from_ref(&*viewport.buffer));
+    }
+
+    /// Brings the residency set (if it was ever requested) up to date with the current
+    /// resources, replacing the resources that were reallocated. The viewport buffer is kept as
+    /// it is if `viewport` is `None`.
+    #[cfg(feature = "mtl4")]
+    fn sync_residency_set(&mut self, atlas: &TextAtlas, viewport: Option<&Viewport>) {
+        let Some(Some(residency_set)) = self.residency_set.get() else {
+            return;
+        };
+
+        let resources: [Option<&ProtocolObject<dyn MTLAllocation>>; 4] = [
+            Some(ProtocolObject::from_ref(&*self.vertex_buffer)),
+            Some(ProtocolObject::from_ref(&*atlas.color_atlas.texture)),
+            Some(ProtocolObject::from_ref(&*atlas.mask_atlas.texture)),
+            viewport.map(|viewport| ProtocolObject::from_ref(&*viewport.buffer)),
+        ];
+
+        let mut changed = false;
+        for (resident, resource) in self.resident_resources.iter_mut().zip(resources) {
+            let Some(resource) = resource else {
+                continue;
+            };
+
+            if resident
+                .as_deref()
+                .is_some_and(|resident| ptr::eq(resident, resource))
+            {
+                continue;
+            }
+
+            if let Some(replaced) = resident.take() {
+                residency_set.removeAllocation(&replaced);
+            }
+            residency_set.addAllocation(resource);
+            *resident = Some(resource.retain());
+            changed = true;
+        }
+
+        if changed {
+            residency_set.commit();
+            residency_set.requestResidency();
+        }
+    }
+
+    /// Panics if a resource bound by `render` is missing from the residency set, if it was ever
+    /// requested.
+    #[cfg(all(feature = "mtl4", debug_assertions))]
+    fn assert_resident(&self, atlas: &TextAtlas, viewport: &Viewport) {
+        let Some(Some(residency_set)) = self.residency_set.get() else {
+            return;
+        };
+
+        let resources: [(&ProtocolObject<dyn MTLAllocation>, &str); 4] = [
+            (
+                ProtocolObject::from_ref(&*self.vertex_buffer),
+                "vertex buffer",
+            ),
+            (
+                ProtocolObject::from_ref(&*atlas.color_atlas.texture),
+                "color atlas",
+            ),
+            (
+                ProtocolObject::from_ref(&*atlas.mask_atlas.texture),
+                "mask atlas",
+            ),
+            (
+                ProtocolObject::from_ref(&*viewport.buffer),
+                "viewport buffer",
+            ),
+        ];
+
+        for (resource, name) in resources {
+            assert!(
+                residency_set.containsAllocation(resource),
+                "The {name} is not in the residency set, prepare with the same atlas and \
+                 viewport before rendering"
+            );
         }
     }
 
     /// Prepares all of the provided text areas for rendering.
+    ///
+    /// Preparing text whose glyphs are all cached does not allocate: the collections `prepare`
+    /// works with are kept across frames (see [`TextRenderer::shrink_scratch`]). Collecting the
+    /// fonts of each area (see [`TextRenderer::set_font_usage`]) allocates, and so does a `Vec`
+    /// of text areas built every frame, which a [`crate::TextAreaBatch`] avoids.
+    ///
+    /// Preparing is deterministic: the same text areas, prepared with the same settings by a
+    /// renderer and atlas in the same state (e.g. both newly created), produce the same vertices
+    /// and place each glyph at the same position in the atlas, so that snapshots can be compared
+    /// across runs. Glyphs missing from the atlas are cached in the order of their position on
+    /// screen, and glyphs are evicted in the order they were last used.
     pub fn prepare<'a>(
         &mut self,
-        device: &Retained<ProtocolObject<dyn MTLDevice>>,
-        font_system: &mut FontSystem,
+        font_system: impl FontSystemAccess,
         atlas: &mut TextAtlas,
         viewport: &Viewport,
         text_areas: impl IntoIterator<Item = TextArea<'a>>,
         cache: &mut SwashCache,
     ) -> Result<(), PrepareError> {
         self.prepare_with_depth_and_custom(
-            device,
             font_system,
             atlas,
             viewport,
@@ -73,11 +825,27 @@ impl TextRenderer {
         )
     }
 
+    /// Prepares all of the provided text areas for rendering against a [`SharedTextAtlas`],
+    /// holding its lock until preparation is done.
+    ///
+    /// Other renderers sharing the atlas may grow it afterwards, replacing its textures. With
+    /// Metal 4 encoders, the residency set of a renderer only picks up new textures in `prepare`,
+    /// so prepare every renderer sharing the atlas before rendering any of them.
+    pub fn prepare_shared<'a>(
+        &mut self,
+        font_system: impl FontSystemAccess,
+        atlas: &SharedTextAtlas,
+        viewport: &Viewport,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+        cache: &mut SwashCache,
+    ) -> Result<(), PrepareError> {
+        self.prepare(font_system, &mut atlas.lock(), viewport, text_areas, cache)
+    }
+
     /// Prepares all of the provided text areas for rendering.
     pub fn prepare_with_depth<'a>(
         &mut self,
-        device: &Retained<ProtocolObject<dyn MTLDevice>>,
-        font_system: &mut FontSystem,
+        font_system: impl FontSystemAccess,
         atlas: &mut TextAtlas,
         viewport: &Viewport,
         text_areas: impl IntoIterator<Item = TextArea<'a>>,
@@ -85,7 +853,6 @@ impl TextRenderer {
         metadata_to_depth: impl FnMut(usize) -> f32,
     ) -> Result<(), PrepareError> {
         self.prepare_with_depth_and_custom(
-            device,
             font_system,
             atlas,
             viewport,
@@ -99,8 +866,7 @@ impl TextRenderer {
     /// Prepares all of the provided text areas for rendering.
     pub fn prepare_with_custom<'a>(
         &mut self,
-        device: &Retained<ProtocolObject<dyn MTLDevice>>,
-        font_system: &mut FontSystem,
+        font_system: impl FontSystemAccess,
         atlas: &mut TextAtlas,
         viewport: &Viewport,
         text_areas: impl IntoIterator<Item = TextArea<'a>>,
@@ -108,7 +874,6 @@ impl TextRenderer {
         rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
     ) -> Result<(), PrepareError> {
         self.prepare_with_depth_and_custom(
-            device,
             font_system,
             atlas,
             viewport,
@@ -122,251 +887,2429 @@ impl TextRenderer {
     /// Prepares all of the provided text areas for rendering.
     pub fn prepare_with_depth_and_custom<'a>(
         &mut self,
-        device: &Retained<ProtocolObject<dyn MTLDevice>>,
-        font_system: &mut FontSystem,
+        font_system: impl FontSystemAccess,
         atlas: &mut TextAtlas,
         viewport: &Viewport,
         text_areas: impl IntoIterator<Item = TextArea<'a>>,
         cache: &mut SwashCache,
-        mut metadata_to_depth: impl FnMut(usize) -> f32,
-        mut rasterize_custom_glyph: impl FnMut(
-            RasterizeCustomGlyphRequest,
-        ) -> Option<RasterizedCustomGlyph>,
+        metadata_to_depth: impl FnMut(usize) -> f32,
+        rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
     ) -> Result<(), PrepareError> {
-        self.glyph_vertices.clear();
+        self.prepare_text_areas(
+            font_system,
+            atlas,
+            viewport,
+            text_areas,
+            cache,
+            metadata_to_depth,
+            rasterize_custom_glyph,
+            None,
+            &mut RasterBudget::default(),
+        )
+    }
 
-        let resolution = viewport.resolution();
-
-        for text_area in text_areas {
-            let bounds_min_x = text_area.bounds.left.max(0);
-            let bounds_min_y = text_area.bounds.top.max(0);
-            let bounds_max_x = text_area.bounds.right.min(resolution.width as i32);
-            let bounds_max_y = text_area.bounds.bottom.min(resolution.height as i32);
-
-            for glyph in text_area.custom_glyphs.iter() {
-                let x = text_area.left + (glyph.left * text_area.scale);
-                let y = text_area.top + (glyph.top * text_area.scale);
-                let width = (glyph.width * text_area.scale).round() as u16;
-                let height = (glyph.height * text_area.scale).round() as u16;
-
-                let (x, y, x_bin, y_bin) = if glyph.snap_to_physical_pixel {
-                    (
-                        x.round() as i32,
-                        y.round() as i32,
-                        SubpixelBin::Zero,
-                        SubpixelBin::Zero,
-                    )
-                } else {
-                    let (x, x_bin) = SubpixelBin::new(x);
-                    let (y, y_bin) = SubpixelBin::new(y);
-                    (x, y, x_bin, y_bin)
-                };
+    /// Prepares the provided text areas like [`TextRenderer::prepare`], calling `animate` for
+    /// each glyph of their buffers to move, scale or recolor its quad, e.g. for per-character
+    /// wave effects, fade-ins or emphasis, without changing the buffers.
+    ///
+    /// The transforms only apply to the quads: glyphs are rasterized and cached in the atlas as
+    /// usual, and scaled quads stretch the rasterized glyph. Custom glyphs are not animated.
+    /// Areas prepared with animations are never cached by the geometry cache (see
+    /// [`TextRenderer::set_geometry_cache`]). Returning [`GlyphTransform::IDENTITY`] for every
+    /// glyph prepares the same vertices as `prepare`.
+    pub fn prepare_with_animation<'a>(
+        &mut self,
+        font_system: impl FontSystemAccess,
+        atlas: &mut TextAtlas,
+        viewport: &Viewport,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+        cache: &mut SwashCache,
+        mut animate: impl FnMut(GlyphAnimContext) -> GlyphTransform,
+    ) -> Result<(), PrepareError> {
+        self.prepare_text_areas(
+            font_system,
+            atlas,
+            viewport,
+            text_areas,
+            cache,
+            zero_depth,
+            |_| None,
+            Some(&mut animate),
+            &mut RasterBudget::default(),
+        )
+    }
 
-                let cache_key = GlyphonCacheKey::Custom(CustomGlyphCacheKey {
-                    glyph_id: glyph.id,
-                    width,
-                    height,
-                    x_bin,
-                    y_bin,
-                });
+    /// Prepares the provided text areas like [`TextRenderer::prepare`], but stops rasterizing
+    /// glyphs that are not cached yet once `budget` has elapsed since the call, e.g. to keep a
+    /// screen full of new CJK text from blowing the frame budget.
+    ///
+    /// Glyphs that were not rasterized are not drawn. Prepare the same text again on the next
+    /// frames until the returned [`PrepareProgress`] is complete. Missing glyphs are rasterized
+    /// from the top of the viewport down and from left to right, so the text read first appears
+    /// first, after the glyphs deferred by earlier calls. At least one glyph is rasterized per
+    /// call (unless the upload budget of the atlas is exhausted, see
+    /// [`TextAtlas::set_upload_budget`]), so the text always converges. Growing
+    /// the atlas rasterizes its cached glyphs again, which is not interrupted by the budget.
+    pub fn prepare_with_budget<'a>(
+        &mut self,
+        font_system: impl FontSystemAccess,
+        atlas: &mut TextAtlas,
+        viewport: &Viewport,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+        cache: &mut SwashCache,
+        budget: Duration,
+    ) -> Result<PrepareProgress, PrepareError> {
+        let mut budget = RasterBudget {
+            deadline: Some(Instant::now() + budget),
+            ..RasterBudget::default()
+        };
 
-                let color = glyph.color.unwrap_or(text_area.default_color);
+        self.prepare_text_areas(
+            font_system,
+            atlas,
+            viewport,
+            text_areas,
+            cache,
+            zero_depth,
+            |_| None,
+            None,
+            &mut budget,
+        )?;
 
-                if let Some(glyph_to_render) = prepare_glyph(
-                    x,
-                    y,
-                    0.0,
-                    color,
-                    glyph.metadata,
-                    cache_key,
-                    atlas,
-                    device,
-                    cache,
-                    font_system,
-                    text_area.scale,
-                    bounds_min_x,
-                    bounds_min_y,
-                    bounds_max_x,
-                    bounds_max_y,
-                    |_cache, _font_system, rasterize_custom_glyph| -> Option<GetGlyphImageResult> {
-                        if width == 0 || height == 0 {
-                            return None;
-                        }
+        Ok(PrepareProgress {
+            deferred_glyphs: budget.deferred,
+        })
+    }
 
-                        let input = RasterizeCustomGlyphRequest {
-                            id: glyph.id,
-                            width,
-                            height,
-                            x_bin,
-                            y_bin,
-                            scale: text_area.scale,
-                        };
-
-                        let output = (rasterize_custom_glyph)(input)?;
-
-                        output.validate(&input, None);
-
-                        Some(GetGlyphImageResult {
-                            content_type: output.content_type,
-                            top: 0,
-                            left: 0,
-                            width,
-                            height,
-                            data: output.data,
-                        })
-                    },
-                    &mut metadata_to_depth,
-                    &mut rasterize_custom_glyph,
-                )? {
-                    self.glyph_vertices.push(glyph_to_render);
-                }
-            }
+    /// Rasterizes the glyphs of `charset` into the atlas up front, e.g. during a loading screen,
+    /// so that text using them does not hitch when it first appears. No geometry is prepared.
+    ///
+    /// The charset is shaped with `attrs` at each size of `metrics_list`, which are in physical
+    /// pixels: multiply them by the scale factor of the viewport (and the scale of the text
+    /// areas) the text is later prepared with. Glyphs are warmed in subpixel bin zero, which is
+    /// the bin of glyphs at whole-pixel positions, with the current
+    /// [`TextRenderer::raster_config`]. Glyphs in other bins are rasterized on first use.
+    ///
+    /// Warmed glyphs are kept in use until the next [`TextAtlas::trim`], so the atlas grows
+    /// instead of evicting them while warming.
+    pub fn prewarm(
+        &mut self,
+        font_system: impl FontSystemAccess,
+        atlas: &mut TextAtlas,
+        cache: &mut SwashCache,
+        attrs: &Attrs,
+        metrics_list: &[Metrics],
+        charset: &str,
+    ) -> Result<PrewarmStats, PrepareError> {
+        #[cfg(feature = "signposts")]
+        let _interval = signpost::interval(signpost::Name::Prewarm);
 
-            let is_run_visible = |run: &cosmic_text::LayoutRun| {
-                let start_y_physical = (text_area.top + (run.line_top * text_area.scale)) as i32;
-                let end_y_physical = start_y_physical + (run.line_height * text_area.scale) as i32;
+        if self.device_lost_with(atlas) {
+            return Err(PrepareError::DeviceLost);
+        }
 
-                start_y_physical <= text_area.bounds.bottom
-                    && text_area.bounds.top <= end_y_physical
-            };
+        let raster_key = self.raster_config.key();
+        let max_glyph_size = self.effective_max_glyph_size(atlas);
+        let mut stats = PrewarmStats::default();
+
+        // Warming may reuse the space of glyphs released by a trim, like a `prepare`
+        atlas.begin_prepare();
+
+        font_system.with_font_system(|font_system| {
+            // Glyphs are cached in the order they first appear, not in the order of a hash set,
+            // so that warming packs the atlas the same way every time
+            let mut seen = HashSet::with_hasher(Hasher::default());
+            let mut cache_keys = Vec::new();
 
-            let layout_runs = text_area
-                .buffer
-                .layout_runs()
-                .skip_while(|run| !is_run_visible(run))
-                .take_while(is_run_visible);
+            for &metrics in metrics_list {
+                let mut buffer = Buffer::new(font_system, metrics);
+                buffer.set_size(font_system, None, None);
+                buffer.set_text(font_system, charset, attrs, Shaping::Advanced);
+                buffer.shape_until_scroll(font_system, false);
+
+                for run in buffer.layout_runs() {
+                    for glyph in run.glyphs {
+                        let physical = glyph.physical((0.0, 0.0), 1.0);
+                        let (cache_key, _) = cap_glyph_size(
+                            cosmic_text::CacheKey {
+                                x_bin: SubpixelBin::Zero,
+                                y_bin: SubpixelBin::Zero,
+                                ..physical.cache_key
+                            },
+                            max_glyph_size,
+                        );
+
+                        let cache_key = GlyphonCacheKey::Text(cache_key, raster_key);
+                        if seen.insert(cache_key) {
+                            cache_keys.push(cache_key);
+                        }
+                    }
+                }
+            }
 
-            for run in layout_runs {
-                for glyph in run.glyphs.iter() {
-                    let physical_glyph =
-                        glyph.physical((text_area.left, text_area.top), text_area.scale);
+            for cache_key in cache_keys {
+                if atlas.mask_atlas.allocator.glyph_cache.contains(&cache_key)
+                    || atlas.color_atlas.allocator.glyph_cache.contains(&cache_key)
+                {
+                    stats.already_present += 1;
+                } else {
+                    let rasterization = self.profiler.start();
+                    let image = glyph_image(
+                        cache_key,
+                        1.0,
+                        cache,
+                        font_system,
+                        self.bitmap_strike_policy,
+                        |_| None,
+                    );
+                    self.profiler.record(Phase::Rasterization, rasterization);
 
-                    let color = match glyph.color_opt {
-                        Some(some) => some,
-                        None => text_area.default_color,
+                    let Some(image) = image else {
+                        continue;
                     };
 
-                    if let Some(glyph_to_render) = prepare_glyph(
-                        physical_glyph.x,
-                        physical_glyph.y,
-                        run.line_y,
-                        color,
-                        glyph.metadata,
-                        GlyphonCacheKey::Text(physical_glyph.cache_key),
+                    cache_glyph(
+                        cache_key,
+                        image,
+                        1.0,
                         atlas,
-                        device,
                         cache,
                         font_system,
-                        text_area.scale,
-                        bounds_min_x,
-                        bounds_min_y,
-                        bounds_max_x,
-                        bounds_max_y,
-                        |cache,
-                         font_system,
-                         _rasterize_custom_glyph|
-                         -> Option<GetGlyphImageResult> {
-                            let image =
-                                cache.get_image_uncached(font_system, physical_glyph.cache_key)?;
-
-                            let content_type = match image.content {
-                                SwashContent::Color => ContentType::Color,
-                                SwashContent::Mask => ContentType::Mask,
-                                SwashContent::SubpixelMask => {
-                                    // Not implemented yet, but don't panic if this happens.
-                                    ContentType::Mask
-                                }
-                            };
-
-                            Some(GetGlyphImageResult {
-                                content_type,
-                                top: image.placement.top as i16,
-                                left: image.placement.left as i16,
-                                width: image.placement.width as u16,
-                                height: image.placement.height as u16,
-                                data: image.data,
-                            })
-                        },
-                        &mut metadata_to_depth,
-                        &mut rasterize_custom_glyph,
-                    )? {
-                        self.glyph_vertices.push(glyph_to_render);
+                        |_| None,
+                        &mut self.profiler,
+                    )?;
+                    stats.added += 1;
+                }
+
+                // Keeps the glyph from being evicted to make room for the next ones
+                for inner in [&mut atlas.mask_atlas, &mut atlas.color_atlas] {
+                    if inner.allocator.glyph_cache.contains(&cache_key) {
+                        inner.allocator.glyphs_in_use.insert(cache_key);
                     }
                 }
             }
-        }
 
-        let will_render = !self.glyph_vertices.is_empty();
-        if !will_render {
-            return Ok(());
-        }
+            Ok::<_, PrepareError>(())
+        })?;
 
-        let vertices = self.glyph_vertices.as_slice();
-        let vertices_raw = unsafe {
-            slice::from_raw_parts(
-                vertices as *const _ as *const u8,
-                std::mem::size_of_val(vertices),
-            )
-        };
+        atlas.flush_uploads()?;
 
-        if self.vertex_buffer_size >= vertices_raw.len() as u64 {
-            unsafe {
-                self.vertex_buffer
-                    .contents()
-                    .copy_from(NonNull::from(vertices_raw).cast(), vertices_raw.len());
-            }
-        } else {
-            let (buffer, buffer_size) = create_oversized_buffer(device, vertices_raw);
-            buffer.setLabel(Some(ns_string!("Metalglyph - Vertex Buffer")));
+        #[cfg(feature = "mtl4")]
+        self.sync_residency_set(atlas, None);
+
+        Ok(stats)
+    }
+
+    fn prepare_text_areas<'a>(
+        &mut self,
+        font_system: impl FontSystemAccess,
+        atlas: &mut TextAtlas,
+        viewport: &Viewport,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+        cache: &mut SwashCache,
+        metadata_to_depth: impl FnMut(usize) -> f32,
+        rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
+        animate: Option<&mut dyn FnMut(GlyphAnimContext) -> GlyphTransform>,
+        budget: &mut RasterBudget,
+    ) -> Result<(), PrepareError> {
+        // The transforms of animated glyphs change from frame to frame, and between areas
+        let cache_geometry =
+            (self.geometry_cache.is_some() || self.shared_geometry.is_some()) && animate.is_none();
+        let mut area_dedup = self.area_dedup.take();
+        let mut dedup = area_dedup.as_mut();
+        let (color_mode, color_space) = (atlas.color_mode, atlas.color_space);
+        let font_usage = self.font_usage.is_some();
+        let animate = animate.map(RefCell::new);
+        let animate = animate.as_ref();
+
+        // Text areas are turned into glyph areas of their visible glyphs, identified by their
+        // buffer and fingerprint if their geometry is cached
+        let areas = (0..).zip(text_areas).map(move |(area_index, text_area)| {
+            // Duplicates prepare no glyphs, so that the areas keep their indices
+            let duplicate = dedup
+                .as_mut()
+                .is_some_and(|dedup| dedup.is_duplicate(&text_area));
+
+            // Scrolling only translates the text, the bounds stay in place
+            let left = text_area.left - text_area.scroll.0;
+            let top = text_area.top - text_area.scroll.1;
+            let placement =
+                AreaPlacement::new(viewport, left, top, text_area.scale, text_area.bounds);
+            let buffer = text_area.buffer;
+            let area_id = text_area.id.unwrap_or(area_index as u64);
+            // Requested faces are only looked up if they are reported or can change the glyphs
+            let requested_faces = font_usage || text_area.font_synthesis != FontSynthesis::None;
+            let visible_runs = move || {
+                buffer
+                    .layout_runs()
+                    .filter(move |_| !duplicate)
+                    .skip_while(move |run| !placement.is_run_visible(run))
+                    .take_while(move |run| placement.is_run_visible(run))
+            };
+
+            let cached_as = (cache_geometry && !duplicate).then(|| {
+                let fingerprint = geometry_cache::fingerprint(
+                    &text_area,
+                    visible_runs(),
+                    placement.fractional_offset(),
+                    placement.scale,
+                    placement.relative_bounds(),
+                    color_mode,
+                    color_space,
+                );
+
+                (buffer as *const Buffer as usize, fingerprint)
+            });
+
+            let glyphs = visible_runs().flat_map(move |run| {
+                let (line, line_y) = (run.line_i, run.line_y);
+
+                run.glyphs.iter().enumerate().map(move |(index, glyph)| {
+                    let mut glyph_placement = GlyphPlacement::from_layout_glyph(
+                        glyph,
+                        line_y,
+                        (placement.left, placement.top),
+                        placement.scale,
+                    );
+                    if requested_faces {
+                        glyph_placement.requested_face = FaceStyle::requested(buffer, line, glyph);
+                    }
+
+                    if let Some(animate) = animate {
+                        let x = glyph.x + glyph.font_size * glyph.x_offset;
+                        let y = line_y + glyph.y - glyph.font_size * glyph.y_offset;
+
+                        glyph_placement.transform = (*animate.borrow_mut())(GlyphAnimContext {
+                            area: area_index,
+                            area_id,
+                            line,
+                            index,
+                            cluster: glyph.start,
+                            position: (
+                                placement.left + x * placement.scale,
+                                placement.top + y * placement.scale,
+                            ),
+                        });
+                    }
+
+                    glyph_placement
+                })
+            });
+
+            let area = GlyphArea {
+                glyphs,
+                left,
+                top,
+                scale: text_area.scale,
+                bounds: text_area.bounds,
+                default_color: text_area.default_color,
+                custom_glyphs: if duplicate {
+                    &[]
+                } else {
+                    text_area.custom_glyphs
+                },
+                cache_priority: text_area.cache_priority,
+                font_synthesis: text_area.font_synthesis,
+                id: text_area.id,
+                background_hint: text_area.background_hint,
+            };
+
+            (area, cached_as)
+        });
+
+        let result = font_system.with_font_system(|font_system| {
+            self.prepare_inner(
+                font_system,
+                atlas,
+                viewport,
+                areas,
+                cache,
+                metadata_to_depth,
+                rasterize_custom_glyph,
+                budget,
+            )
+        });
+
+        if let Some(dedup) = &mut area_dedup {
+            self.duplicate_areas.skipped = dedup.finish();
+        }
+        self.area_dedup = area_dedup;
+
+        result
+    }
+
+    /// Prepares glyphs that the caller placed for rendering, e.g. while walking the layout runs
+    /// of a [`Buffer`] for its own purposes (see [`GlyphPlacement::from_layout_glyph`]).
+    ///
+    /// This is what the other `prepare` methods use after extracting the visible glyphs of each
+    /// [`TextArea`]. Unlike them, it does not skip layout runs outside of the bounds, so only
+    /// pass visible glyphs to keep invisible ones from being rasterized. Glyph areas are never
+    /// cached by the geometry cache (see [`TextRenderer::set_geometry_cache`]).
+    ///
+    /// The [`GlyphPlacement::transform`] of each glyph moves, scales and recolors its quad, as
+    /// the callback of [`TextRenderer::prepare_with_animation`] does.
+    pub fn prepare_glyph_areas<'a, G: IntoIterator<Item = GlyphPlacement>>(
+        &mut self,
+        font_system: impl FontSystemAccess,
+        atlas: &mut TextAtlas,
+        viewport: &Viewport,
+        areas: impl IntoIterator<Item = GlyphArea<'a, G>>,
+        cache: &mut SwashCache,
+        metadata_to_depth: impl FnMut(usize) -> f32,
+        rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
+    ) -> Result<(), PrepareError> {
+        font_system.with_font_system(|font_system| {
+            self.prepare_inner(
+                font_system,
+                atlas,
+                viewport,
+                areas.into_iter().map(|area| (area, None)),
+                cache,
+                metadata_to_depth,
+                rasterize_custom_glyph,
+                &mut RasterBudget::default(),
+            )
+        })
+    }
+
+    /// Prepares `areas`, along with the buffer address and fingerprint of those whose geometry
+    /// is cached.
+    ///
+    /// A failed `prepare` leaves nothing to render. The vertices it was writing would be drawn
+    /// with placeholders in place of the glyphs it did not rasterize, and the draw ranges and
+    /// vertex buffer of the previous `prepare` no longer match them.
+    fn prepare_inner<'a, G: IntoIterator<Item = GlyphPlacement>>(
+        &mut self,
+        font_system: &mut FontSystem,
+        atlas: &mut TextAtlas,
+        viewport: &Viewport,
+        areas: impl IntoIterator<Item = (GlyphArea<'a, G>, Option<(usize, u64)>)>,
+        cache: &mut SwashCache,
+        metadata_to_depth: impl FnMut(usize) -> f32,
+        rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
+        budget: &mut RasterBudget,
+    ) -> Result<(), PrepareError> {
+        let result = self.prepare_areas(
+            font_system,
+            atlas,
+            viewport,
+            areas,
+            cache,
+            metadata_to_depth,
+            rasterize_custom_glyph,
+            budget,
+        );
+
+        if result.is_err() {
+            self.glyph_vertices.clear();
+            self.draw_ranges.clear();
+            self.update_geometry_generation(viewport, false);
+        }
+
+        result
+    }
+
+    /// Prepares `areas` for [`TextRenderer::prepare_inner`], returning early on errors.
+    fn prepare_areas<'a, G: IntoIterator<Item = GlyphPlacement>>(
+        &mut self,
+        font_system: &mut FontSystem,
+        atlas: &mut TextAtlas,
+        viewport: &Viewport,
+        areas: impl IntoIterator<Item = (GlyphArea<'a, G>, Option<(usize, u64)>)>,
+        cache: &mut SwashCache,
+        mut metadata_to_depth: impl FnMut(usize) -> f32,
+        mut rasterize_custom_glyph: impl FnMut(
+            RasterizeCustomGlyphRequest,
+        ) -> Option<RasterizedCustomGlyph>,
+        budget: &mut RasterBudget,
+    ) -> Result<(), PrepareError> {
+        #[cfg(feature = "signposts")]
+        let _prepare_interval = signpost::interval(signpost::Name::Prepare);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("prepare", label = %self.label).entered();
+
+        if self.device_lost_with(atlas) {
+            return Err(PrepareError::DeviceLost);
+        }
+
+        atlas.auto_trim_for(self.id);
+        let reuse_epoch = atlas.begin_prepare();
+        self.frame_state.prepare(reuse_epoch);
+
+        #[cfg(feature = "dev-tools")]
+        if self.shader_generation != atlas.shader_generation() {
+            self.content_pipelines = self.reloaded_pipelines(atlas, VertexStage::Single);
+            self.pipeline = self.reloaded_pipeline(atlas, None, VertexStage::Single);
+            if self.layered_pipelines.is_some() {
+                self.layered_pipelines = Some(self.reloaded_pipelines(atlas, VertexStage::Layered));
+            }
+            if let Some((path, _)) = self.stereo_pipelines {
+                let pipelines = self.reloaded_pipelines(atlas, VertexStage::Stereo(path));
+                self.stereo_pipelines = Some((path, pipelines));
+            }
+            self.mask_pipelines = OnceCell::new();
+            self.shader_generation = atlas.shader_generation();
+        }
+
+        if self.encoded_icb.get() {
+            mem::swap(&mut self.glyph_vertices, &mut self.previous_glyph_vertices);
+        }
+        self.glyph_vertices.clear();
+        self.missing_glyphs.clear();
+        self.incomplete_areas.clear();
+        self.drops.reset();
+        self.duplicate_areas = DuplicateAreas::default();
+        if let Some(font_usage) = &mut self.font_usage {
+            font_usage.clear();
+        }
+        if let Some(overdraw) = &mut self.overdraw {
+            let resolution = viewport.render_resolution();
+            overdraw.reset(u64::from(resolution.width) * u64::from(resolution.height));
+        }
+
+        if self.empty_glyphs.len() > MAX_EMPTY_GLYPHS {
+            self.empty_glyphs.clear();
+        }
+
+        let atlas_sizes = (
+            atlas.color_atlas.allocator.size,
+            atlas.mask_atlas.allocator.size,
+        );
+
+        self.profiler.reset();
+
+        // Nothing is visible in an unrenderable viewport, e.g. that of a minimized window. Its
+        // areas are not even iterated, which skips deduplicating and fingerprinting them, and
+        // cached geometry is kept for when the viewport is restored
+        if !viewport.is_renderable() {
+            self.draw_ranges.clear();
+            self.atlas_grew = false;
+            self.update_geometry_generation(viewport, false);
+            return Ok(());
+        }
+
+        let shaping = self.profiler.start();
+        let max_glyph_size = self.effective_max_glyph_size(atlas);
+
+        for (index, (area, cached_as)) in areas.into_iter().enumerate() {
+            let area_start = self.glyph_vertices.len();
+            if let Some(font_usage) = &mut self.font_usage {
+                font_usage.push(AreaFontUsage {
+                    area_id: area.id.unwrap_or(index as u64),
+                    ..AreaFontUsage::default()
+                });
+            }
+            if let Some(overdraw) = &mut self.overdraw {
+                overdraw.areas.push(AreaOverdraw {
+                    area_id: area.id.unwrap_or(index as u64),
+                    ..AreaOverdraw::default()
+                });
+            }
+
+            let AreaPlacement {
+                left,
+                top,
+                scale,
+                bounds_min_x,
+                bounds_min_y,
+                bounds_max_x,
+                bounds_max_y,
+            } = AreaPlacement::new(viewport, area.left, area.top, area.scale, area.bounds);
+            let area_origin = [left.floor() as i32, top.floor() as i32];
+
+            // Geometry cached by an earlier frame, or else by an earlier area of this one
+            let mut cached = None;
+            if let Some((buffer_address, fingerprint)) = cached_as {
+                cached = self
+                    .geometry_cache
+                    .as_mut()
+                    .and_then(|cache| cache.get(buffer_address, fingerprint, atlas));
+                if cached.is_none() {
+                    cached = self
+                        .shared_geometry
+                        .as_mut()
+                        .and_then(|shared| shared.get(buffer_address, fingerprint, atlas));
+                    self.duplicate_areas.translated += cached.is_some() as usize;
+                }
+            }
+
+            if let Some(cached) = cached {
+                let dx = area_origin[0] - cached.origin[0];
+                let dy = area_origin[1] - cached.origin[1];
+
+                self.glyph_vertices
+                    .extend(cached.vertices.iter().map(|vertex| GlyphToRender {
+                        pos: [vertex.pos[0] + dx, vertex.pos[1] + dy],
+                        ..*vertex
+                    }));
+                if let Some(overdraw) = &mut self.overdraw {
+                    for vertex in &cached.vertices {
+                        overdraw.record(index, vertex);
+                    }
+                }
+
+                // Every glyph is still where it was when the geometry was cached
+                for (cache_key, content_type, _) in &cached.glyphs {
+                    let allocator = &mut atlas.inner_for_content_mut(*content_type).allocator;
+                    allocator.glyph_cache.promote(cache_key);
+                    allocator.glyphs_in_use.insert(*cache_key);
+                    if area.cache_priority == CachePriority::Pinned {
+                        allocator.pinned.insert(*cache_key);
+                    }
+                }
+
+                if let Some(usage) = self.font_usage.as_mut().and_then(|usage| usage.last_mut()) {
+                    for placement in area.glyphs {
+                        usage.record(&placement.cache_key, font_system);
+                        if let Some(face) = area.font_synthesis.resolve(&placement, font_system) {
+                            usage.record_face(&face);
+                        }
+                    }
+                }
+
+                continue;
+            }
+
+            self.area_glyphs.clear();
+            let missing_before_area = self.missing_glyphs.len();
+            let bounds = [bounds_min_x, bounds_min_y, bounds_max_x, bounds_max_y];
+            let pinned = area.cache_priority == CachePriority::Pinned;
+            let background = encode_background_hint(area.background_hint);
+
+            for glyph in area.custom_glyphs.iter() {
+                let (x, y, cache_key) = place_custom_glyph(glyph, (left, top), scale);
+
+                if self.empty_glyphs.contains(&cache_key) {
+                    self.drops.record(cache_key, DropReason::Empty);
+                    continue;
+                }
+
+                let position = GlyphPosition {
+                    x,
+                    y,
+                    line_y: 0.0,
+                    color: glyph.color.unwrap_or(area.default_color),
+                    metadata: glyph.metadata,
+                    scale,
+                    bounds,
+                    transform: GlyphTransform::IDENTITY,
+                    raster_scale: 1.0,
+                    pinned,
+                    background,
+                };
+
+                if !lookup_glyph(cache_key, atlas, &mut self.profiler) {
+                    self.missing_glyphs.push(MissingGlyph {
+                        index: self.glyph_vertices.len(),
+                        area: index,
+                        cache_key,
+                        position,
+                    });
+                    self.glyph_vertices.push(GlyphToRender::MISSING);
+                } else if let Some(glyph_to_render) = glyph_vertex(
+                    &position,
+                    cache_key,
+                    atlas,
+                    &mut metadata_to_depth,
+                    &mut self.empty_glyphs,
+                    &mut self.drops,
+                    &mut self.profiler,
+                ) {
+                    if cached_as.is_some() {
+                        let content_type = glyph_to_render.content_type();
+                        let generation = atlas.glyph_generation(&cache_key, content_type);
+                        self.area_glyphs.push((
+                            cache_key,
+                            content_type,
+                            generation.unwrap_or_default(),
+                        ));
+                    }
+                    if let Some(overdraw) = &mut self.overdraw {
+                        overdraw.record(index, &glyph_to_render);
+                    }
+                    self.glyph_vertices.push(glyph_to_render);
+                }
+            }
+
+            for placement in area.glyphs {
+                let face = area.font_synthesis.resolve(&placement, font_system);
+                if let Some(usage) = self.font_usage.as_mut().and_then(|usage| usage.last_mut()) {
+                    usage.record(&placement.cache_key, font_system);
+                    if let Some(face) = &face {
+                        usage.record_face(face);
+                    }
+                }
+
+                let (cache_key, raster_scale) = text_glyph_key(
+                    font_system,
+                    &placement,
+                    max_glyph_size,
+                    self.raster_config,
+                    face.map_or(0.0, |face| face.embolden),
+                    self.tofu,
+                );
+                if let GlyphonCacheKey::Tofu(_) = cache_key {
+                    atlas.mask_atlas.counters.tofu += 1;
+                }
+
+                // Whitespace and other glyphs without coverage skip even the atlas lookup
+                if self.empty_glyphs.contains(&cache_key) {
+                    self.drops.record(cache_key, DropReason::Empty);
+                    continue;
+                }
+
+                let position = GlyphPosition {
+                    x: placement.x,
+                    y: placement.y,
+                    line_y: placement.line_y,
+                    color: placement
+                        .transform
+                        .apply_color(placement.color.unwrap_or(area.default_color)),
+                    metadata: placement.metadata,
+                    scale,
+                    bounds,
+                    transform: placement.transform,
+                    raster_scale,
+                    pinned,
+                    background,
+                };
+
+                if !lookup_glyph(cache_key, atlas, &mut self.profiler) {
+                    self.missing_glyphs.push(MissingGlyph {
+                        index: self.glyph_vertices.len(),
+                        area: index,
+                        cache_key,
+                        position,
+                    });
+                    self.glyph_vertices.push(GlyphToRender::MISSING);
+                } else if let Some(glyph_to_render) = glyph_vertex(
+                    &position,
+                    cache_key,
+                    atlas,
+                    &mut metadata_to_depth,
+                    &mut self.empty_glyphs,
+                    &mut self.drops,
+                    &mut self.profiler,
+                ) {
+                    if cached_as.is_some() {
+                        let content_type = glyph_to_render.content_type();
+                        let generation = atlas.glyph_generation(&cache_key, content_type);
+                        self.area_glyphs.push((
+                            cache_key,
+                            content_type,
+                            generation.unwrap_or_default(),
+                        ));
+                    }
+                    if let Some(overdraw) = &mut self.overdraw {
+                        overdraw.record(index, &glyph_to_render);
+                    }
+                    self.glyph_vertices.push(glyph_to_render);
+                }
+            }
+
+            // Areas with missing glyphs are finished once the glyphs are rasterized, and their
+            // geometry is cached by a later `prepare`
+            if self.missing_glyphs.len() > missing_before_area {
+                self.incomplete_areas
+                    .push(area_start..self.glyph_vertices.len());
+                continue;
+            }
+
+            group_by_content_type(
+                &mut self.glyph_vertices[area_start..],
+                &mut self.grouped_glyphs,
+                &mut self.glyph_rects,
+            );
+
+            if let Some((buffer_address, fingerprint)) = cached_as {
+                for geometry_cache in [&mut self.geometry_cache, &mut self.shared_geometry]
+                    .into_iter()
+                    .flatten()
+                {
+                    geometry_cache.insert(
+                        buffer_address,
+                        fingerprint,
+                        area_origin,
+                        &self.glyph_vertices[area_start..],
+                        &self.area_glyphs,
+                    );
+                }
+            }
+        }
+
+        if !self.missing_glyphs.is_empty() {
+            self.rasterize_missing_glyphs(
+                font_system,
+                atlas,
+                cache,
+                &mut metadata_to_depth,
+                &mut rasterize_custom_glyph,
+                budget,
+            )?;
+        }
+
+        if let Some(geometry_cache) = &mut self.geometry_cache {
+            geometry_cache.retain_used();
+        }
+        if let Some(shared_geometry) = &mut self.shared_geometry {
+            shared_geometry.clear();
+        }
+
+        self.update_draw_ranges();
+
+        {
+            #[cfg(feature = "signposts")]
+            let _interval = signpost::interval(signpost::Name::UploadGlyphs);
+
+            let atlas_upload = self.profiler.start();
+            atlas.flush_uploads()?;
+            self.profiler.record(Phase::AtlasUpload, atlas_upload);
+        }
+
+        self.profiler.record(Phase::Shaping, shaping);
+
+        self.atlas_grew = atlas_sizes
+            != (
+                atlas.color_atlas.allocator.size,
+                atlas.mask_atlas.allocator.size,
+            );
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            glyphs = self.glyph_vertices.len(),
+            color_atlas_size = atlas.color_atlas.allocator.size,
+            mask_atlas_size = atlas.mask_atlas.allocator.size,
+            atlas_grew = self.atlas_grew,
+            "prepared text"
+        );
+
+        let will_render = !self.glyph_vertices.is_empty();
+        if !will_render {
+            self.update_geometry_generation(viewport, false);
+
+            #[cfg(feature = "mtl4")]
+            self.sync_residency_set(atlas, Some(viewport));
+
+            return Ok(());
+        }
+
+        #[cfg(feature = "signposts")]
+        let _interval = signpost::interval(signpost::Name::UploadVertices);
+
+        let vertex_write = self.profiler.start();
+        let reallocated = self.write_vertices()?;
+        self.profiler.record(Phase::VertexWrite, vertex_write);
+
+        self.update_geometry_generation(viewport, reallocated);
+
+        #[cfg(feature = "mtl4")]
+        self.sync_residency_set(atlas, Some(viewport));
+
+        Ok(())
+    }
+
+    /// Rasterizes the glyphs that were missing from the atlas and replaces their placeholders in
+    /// `glyph_vertices`, then finishes the areas that held them.
+    ///
+    /// Glyphs deferred by earlier calls are rasterized first, oldest first, so that the backlog
+    /// of the atlas drains. Then glyphs of pinned areas, then visible first: from the top of the
+    /// viewport down, and from left to right along a line, so that the text the user reads first
+    /// appears first when `budget` or the upload budget of the atlas runs out, instead of the
+    /// text of the first area.
+    fn rasterize_missing_glyphs(
+        &mut self,
+        font_system: &mut FontSystem,
+        atlas: &mut TextAtlas,
+        cache: &mut SwashCache,
+        mut metadata_to_depth: impl FnMut(usize) -> f32,
+        mut rasterize_custom_glyph: impl FnMut(
+            RasterizeCustomGlyphRequest,
+        ) -> Option<RasterizedCustomGlyph>,
+        budget: &mut RasterBudget,
+    ) -> Result<(), PrepareError> {
+        // Glyphs at the same position keep their order. Unlike a stable sort, sorting by index
+        // too does not allocate
+        self.missing_glyphs.sort_unstable_by_key(|missing| {
+            (
+                atlas.deferred_order(&missing.cache_key),
+                !missing.position.pinned,
+                missing.position.screen_position(),
+                missing.index,
+            )
+        });
+
+        for (i, missing) in self.missing_glyphs.iter().enumerate() {
+            let cache_key = missing.cache_key;
+
+            // An earlier occurrence of the glyph may have rasterized it already
+            if self.empty_glyphs.contains(&cache_key) {
+                self.drops.record(cache_key, DropReason::Empty);
+                continue;
+            }
+
+            if !lookup_glyph(cache_key, atlas, &mut self.profiler) {
+                if !budget.allows_rasterization(atlas.allows_upload()) {
+                    atlas.defer_upload(cache_key);
+                    self.drops.record(cache_key, DropReason::Deferred);
+                    continue;
+                }
+
+                let image = {
+                    #[cfg(feature = "signposts")]
+                    let _interval = signpost::interval(signpost::Name::RasterizeGlyph);
+
+                    let rasterization = self.profiler.start();
+                    let image = glyph_image(
+                        cache_key,
+                        missing.position.scale,
+                        cache,
+                        font_system,
+                        self.bitmap_strike_policy,
+                        &mut rasterize_custom_glyph,
+                    );
+                    self.profiler.record(Phase::Rasterization, rasterization);
+
+                    image
+                };
+                let Some(image) = image else {
+                    atlas.record_upload(cache_key, 0);
+                    let reason = match cache_key {
+                        GlyphonCacheKey::Custom(key) if key.width > 0 && key.height > 0 => {
+                            DropReason::CustomNotRasterized
+                        }
+                        _ => DropReason::Empty,
+                    };
+                    self.drops.record(cache_key, reason);
+                    continue;
+                };
+
+                let upload_bytes = image.data.len();
+                let cached = cache_glyph(
+                    cache_key,
+                    image,
+                    missing.position.scale,
+                    atlas,
+                    cache,
+                    font_system,
+                    &mut rasterize_custom_glyph,
+                    &mut self.profiler,
+                );
+
+                if let Err(error) = cached {
+                    // The glyphs after this one are dropped along with it
+                    if error == PrepareError::AtlasFull {
+                        for missing in &self.missing_glyphs[i..] {
+                            self.drops.record(missing.cache_key, DropReason::AtlasFull);
+                        }
+                    }
+
+                    return Err(error);
+                }
+                atlas.record_upload(cache_key, upload_bytes);
+            }
+
+            // Marks the glyph as in use right away, so that rasterizing the next glyphs can't
+            // evict it
+            if let Some(glyph_to_render) = glyph_vertex(
+                &missing.position,
+                cache_key,
+                atlas,
+                &mut metadata_to_depth,
+                &mut self.empty_glyphs,
+                &mut self.drops,
+                &mut self.profiler,
+            ) {
+                if let Some(overdraw) = &mut self.overdraw {
+                    overdraw.record(missing.area, &glyph_to_render);
+                }
+                self.glyph_vertices[missing.index] = glyph_to_render;
+            }
+        }
+
+        self.missing_glyphs.clear();
+
+        // Drop the placeholders of deferred, empty and culled glyphs. Areas are finished from the
+        // last to the first, so that the ranges of the earlier ones stay valid.
+        for range in self.incomplete_areas.drain(..).rev() {
+            let start = range.start;
+            let mut end = start;
+            for i in range.clone() {
+                let vertex = self.glyph_vertices[i];
+                if !vertex.is_missing() {
+                    self.glyph_vertices[end] = vertex;
+                    end += 1;
+                }
+            }
+
+            self.glyph_vertices.drain(end..range.end);
+            group_by_content_type(
+                &mut self.glyph_vertices[start..end],
+                &mut self.grouped_glyphs,
+                &mut self.glyph_rects,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Writes `glyph_vertices` into the vertex buffer, reallocating it if they do not fit.
+    /// Returns whether the buffer was reallocated.
+    fn write_vertices(&mut self) -> Result<bool, PrepareError> {
+        pack_vertices(&self.glyph_vertices, &mut self.vertex_data);
+        let vertices_raw = self.vertex_data.as_slice();
+
+        if self.vertex_buffer_size >= vertices_raw.len() as u64 {
+            unsafe {
+                self.vertex_buffer
+                    .contents()
+                    .copy_from(NonNull::from(vertices_raw).cast(), vertices_raw.len());
+            }
+
+            #[cfg(target_os = "macos")]
+            if is_managed(self.vertex_storage) {
+                self.vertex_buffer
+                    .didModifyRange(NSRange::new(0, vertices_raw.len()));
+            }
+
+            Ok(false)
+        } else {
+            let Some((buffer, buffer_size)) =
+                create_oversized_buffer(&self.device, vertices_raw, self.vertex_storage)
+            else {
+                self.device_lost = true;
+                return Err(PrepareError::DeviceLost);
+            };
+            buffer.setLabel(Some(&resource_label(&self.label, "Vertex Buffer")));
             self.vertex_buffer = buffer;
             self.vertex_buffer_size = buffer_size;
+
+            Ok(true)
+        }
+    }
+
+    /// Captures the glyphs cached in `atlas`, its statistics, the quads of the last `prepare` and
+    /// the parameters of the active slot of `viewport` into a [`Snapshot`], e.g. to attach to a
+    /// bug report about garbled text.
+    ///
+    /// With `atlas_pixels`, the snapshot includes the atlas textures, which lets
+    /// [`Snapshot::render_to_pixels`] replay it on another machine without the fonts it was
+    /// rendered with. Atlas pixels make the snapshot as large as the atlas textures (and JSON
+    /// makes it several times larger).
+    #[cfg(feature = "debug-tools")]
+    pub fn capture_snapshot(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        atlas_pixels: bool,
+    ) -> Snapshot {
+        Snapshot::capture(
+            atlas,
+            viewport,
+            &self.glyph_vertices,
+            self.options.alpha_mode,
+            atlas_pixels,
+        )
+    }
+
+    /// Replaces the prepared glyphs with `vertices`, as if `prepare` had produced them.
+    #[cfg(feature = "debug-tools")]
+    pub(crate) fn restore_vertices(&mut self, vertices: Vec<GlyphToRender>, atlas: &TextAtlas) {
+        self.glyph_vertices = vertices;
+        self.frame_state.prepare(atlas.reuse_epoch);
+        self.update_draw_ranges();
+
+        if !self.glyph_vertices.is_empty() {
+            self.write_vertices()
+                .expect("Failed to create vertex buffer");
+        }
+    }
+
+    /// Renders all layouts that were previously provided to `prepare`, using the active
+    /// parameter slot of the `viewport`.
+    ///
+    /// `encoder` can be either a classic `MTLRenderCommandEncoder` or a Metal 4
+    /// `MTL4RenderCommandEncoder` (see [`TextRenderEncoder`]).
+    ///
+    /// Rendering sets the render pipeline state, vertex and fragment buffers 0 and 1 and
+    /// textures 0 and 1 (on the Metal 4 path, the argument table of the vertex and fragment
+    /// stages instead), and leaves them set. It never sets any other encoder state unless asked
+    /// to set the viewport (see [`TextRenderer::set_encoder_viewport`]): the depth stencil
+    /// state, stencil reference, viewport, scissor rectangle, cull mode and so on set before
+    /// rendering apply to text, e.g. to clip it with a stencil test or test it against the
+    /// depth of a scene. The renderer must then have been built with the depth and stencil
+    /// format of the render pass (see [`TextRendererBuilder::depth_format`]).
+    ///
+    /// Rendering does not change anything a later render reads, so the text of one `prepare`
+    /// can be rendered any number of times, into any number of targets of the atlas format, e.g.
+    /// into a reflection and then into the drawable. Trimming the atlas in between is fine too,
+    /// see [`TextAtlas::trim`], as long as nothing is prepared against it until the last render.
+    ///
+    /// Returns the draw calls and resources that were encoded.
+    pub fn render<E: TextRenderEncoder + ?Sized>(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        encoder: &E,
+    ) -> RenderStats {
+        self.render_with_slot(atlas, viewport, encoder, viewport.active_slot())
+    }
+
+    /// Renders all layouts that were previously provided to `prepare`, using the given parameter
+    /// slot of the `viewport`.
+    ///
+    /// This allows rendering the same prepared text several times with different viewport
+    /// configurations that were all set up before encoding, e.g. once per eye in a stereo
+    /// renderer.
+    pub fn render_with_slot<E: TextRenderEncoder + ?Sized>(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        encoder: &E,
+        slot: usize,
+    ) -> RenderStats {
+        self.render_views(atlas, viewport, encoder, slot, RenderViews::Single)
+    }
+
+    /// Renders all layouts that were previously provided to `prepare` into the layer `layer` of
+    /// a layered render target, e.g. a slice of a texture array, using the active parameter slot
+    /// of the `viewport`.
+    ///
+    /// The render pass must have a render target array length greater than `layer`. Several
+    /// renderers can draw into different layers of the same pass, e.g. one per composited window.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the renderer was not built with [`TextRendererBuilder::layered`].
+    pub fn render_to_layer<E: TextRenderEncoder + ?Sized>(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        encoder: &E,
+        layer: usize,
+    ) -> RenderStats {
+        assert!(
+            self.layered_pipelines.is_some(),
+            "The renderer was not built with `TextRendererBuilder::layered`"
+        );
+
+        let slot = viewport.active_slot();
+        self.render_views(atlas, viewport, encoder, slot, RenderViews::Layer(layer))
+    }
+
+    /// Renders all layouts that were previously provided to `prepare` into both views of a
+    /// layered render target, the first view into layer `0` with the parameter slot `slots[0]`
+    /// of the `viewport` and the second into layer `1` with `slots[1]`.
+    ///
+    /// Text is prepared once, against the active slot, so the slots should only differ in ways
+    /// that move the prepared glyphs, e.g. by the transform or origin offsetting each eye. The
+    /// render pass must have a render target array length of at least `2`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the renderer was not built with [`TextRendererBuilder::stereo`].
+    pub fn render_stereo<E: TextRenderEncoder + ?Sized>(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        encoder: &E,
+        slots: [usize; 2],
+    ) -> RenderStats {
+        let Some((path, _)) = self.stereo_pipelines else {
+            panic!("The renderer was not built with `TextRendererBuilder::stereo`");
+        };
+
+        let views = RenderViews::Stereo(path, slots[1]);
+        self.render_views(atlas, viewport, encoder, slots[0], views)
+    }
+
+    /// Returns how [`TextRenderer::render_stereo`] draws into both views on the device, or
+    /// `None` if the renderer was not built with [`TextRendererBuilder::stereo`].
+    pub fn stereo_path(&self) -> Option<StereoPath> {
+        self.stereo_pipelines.as_ref().map(|&(path, _)| path)
+    }
+
+    /// Renders into `views`, with the parameters of `slot` for the first view.
+    fn render_views<E: TextRenderEncoder + ?Sized>(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        encoder: &E,
+        slot: usize,
+        views: RenderViews,
+    ) -> RenderStats {
+        self.frame_state.render(atlas.reuse_epoch);
+
+        if self.glyph_vertices.is_empty() || self.device_lost_with(atlas) {
+            return RenderStats::default();
+        }
+
+        let second_slot = match views {
+            RenderViews::Stereo(_, second_slot) => Some(second_slot),
+            _ => None,
+        };
+        for slot in [Some(slot), second_slot].into_iter().flatten() {
+            assert!(
+                slot < viewport.slot_count(),
+                "Viewport parameter slot {slot} does not exist"
+            );
+
+            if !viewport.is_slot_renderable(slot) {
+                return RenderStats::default();
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "render",
+            label = %self.label,
+            glyphs = self.glyph_vertices.len(),
+            slot
+        )
+        .entered();
+
+        if self.debug_markers {
+            encoder.push_debug_group(ns_string!("metalglyph: text pass"));
+            if self.atlas_grew {
+                encoder.insert_debug_signpost(ns_string!("metalglyph: atlas grow"));
+            }
+        }
+
+        let content_pipelines = match (views, &self.layered_pipelines, &self.stereo_pipelines) {
+            (RenderViews::Layer(_), Some(pipelines), _) => pipelines,
+            (RenderViews::Stereo(..), _, Some((_, pipelines))) => pipelines,
+            _ => &self.content_pipelines,
+        };
+
+        // Shaders reloaded since the last `prepare` are used right away
+        #[cfg(feature = "dev-tools")]
+        let reloaded = (self.shader_generation != atlas.shader_generation())
+            .then(|| self.reloaded_pipelines(atlas, views.vertex_stage()));
+        #[cfg(feature = "dev-tools")]
+        let content_pipelines = reloaded.as_ref().unwrap_or(content_pipelines);
+
+        let stats = self.draw_glyphs(atlas, viewport, encoder, slot, views, content_pipelines);
+
+        if self.debug_markers {
+            encoder.pop_debug_group();
+        }
+
+        stats
+    }
+
+    /// Renders the coverage of all layouts that were previously provided to `prepare`, using the
+    /// active parameter slot of the `viewport`, e.g. to blur it into a shadow or glow before
+    /// rendering the text itself with [`TextRenderer::render`].
+    ///
+    /// Every channel of the target receives the coverage of the glyphs multiplied by the alpha of
+    /// their color, including color glyphs, and coverage accumulates with the premultiplied
+    /// "over" operator. Both passes draw the same prepared vertices, so the coverage lines up
+    /// with the text exactly.
+    ///
+    /// The render pass must have a single sample and no depth attachment, and its color
+    /// attachment the format set with [`TextRendererBuilder::mask_format`] (`R8Unorm` by
+    /// default). The pipelines of the pass are created when it is first rendered.
+    pub fn render_mask_only<E: TextRenderEncoder + ?Sized>(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        encoder: &E,
+    ) -> RenderStats {
+        self.frame_state.render(atlas.reuse_epoch);
+
+        if self.glyph_vertices.is_empty()
+            || !viewport.is_renderable()
+            || self.device_lost_with(atlas)
+        {
+            return RenderStats::default();
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "render_mask_only",
+            label = %self.label,
+            glyphs = self.glyph_vertices.len()
+        )
+        .entered();
+
+        let mask_pipelines = || {
+            [ContentType::Color, ContentType::Mask].map(|content_type| {
+                atlas.get_or_create_mask_pipeline(&self.device, self.mask_format, content_type)
+            })
+        };
+        // Shaders reloaded since the last `prepare` are used right away
+        #[cfg(feature = "dev-tools")]
+        let reloaded = (self.shader_generation != atlas.shader_generation()).then(mask_pipelines);
+        #[cfg(feature = "dev-tools")]
+        let pipelines = match &reloaded {
+            Some(reloaded) => reloaded,
+            None => self.mask_pipelines.get_or_init(mask_pipelines),
+        };
+        #[cfg(not(feature = "dev-tools"))]
+        let pipelines = self.mask_pipelines.get_or_init(mask_pipelines);
+
+        if self.debug_markers {
+            encoder.push_debug_group(ns_string!("metalglyph: text mask pass"));
+        }
+
+        let slot = viewport.active_slot();
+        let views = RenderViews::Single;
+        let stats = self.draw_glyphs(atlas, viewport, encoder, slot, views, pipelines);
+
+        if self.debug_markers {
+            encoder.pop_debug_group();
+        }
+
+        stats
+    }
+
+    /// Returns the pipeline of the renderer for `content_type` and `vertex_stage` from the
+    /// current shaders of the cache of `atlas`, which were reloaded since the pipeline was
+    /// created.
+    #[cfg(feature = "dev-tools")]
+    fn reloaded_pipeline(
+        &self,
+        atlas: &TextAtlas,
+        content_type: Option<ContentType>,
+        vertex_stage: VertexStage,
+    ) -> Retained<ProtocolObject<dyn MTLRenderPipelineState>> {
+        // Reloading checks that the pipelines of the cache can be created again
+        atlas
+            .get_or_create_pipeline(&self.device, self.options, content_type, vertex_stage)
+            .expect("Failed to create reloaded pipeline state")
+    }
+
+    /// Like [`TextRenderer::reloaded_pipeline`], for the color and mask pipelines.
+    #[cfg(feature = "dev-tools")]
+    fn reloaded_pipelines(
+        &self,
+        atlas: &TextAtlas,
+        vertex_stage: VertexStage,
+    ) -> [Retained<ProtocolObject<dyn MTLRenderPipelineState>>; 2] {
+        [ContentType::Color, ContentType::Mask]
+            .map(|content_type| self.reloaded_pipeline(atlas, Some(content_type), vertex_stage))
+    }
+
+    /// Draws the prepared glyphs into `views` with the color and mask pipelines of `pipelines`.
+    fn draw_glyphs<E: TextRenderEncoder + ?Sized>(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        encoder: &E,
+        slot: usize,
+        views: RenderViews,
+        pipelines: &[Retained<ProtocolObject<dyn MTLRenderPipelineState>>; 2],
+    ) -> RenderStats {
+        #[cfg(all(feature = "mtl4", debug_assertions))]
+        if encoder.uses_residency_sets() {
+            self.assert_resident(atlas, viewport);
+        }
+        debug_assert!(
+            !atlas.has_pending_uploads(),
+            "Glyphs of the atlas were not uploaded, encode them with `TextAtlas::encode_uploads` \
+             before rendering"
+        );
+
+        if let Some(encoder_viewport) = self.encoder_viewport {
+            let (metal_viewport, scissor_rect) =
+                viewport.encoder_region(slot, encoder_viewport.origin);
+            encoder.set_viewport(metal_viewport);
+            if encoder_viewport.scissor {
+                encoder.set_scissor_rect(scissor_rect);
+            }
+        }
+
+        let bindings = TextBindings {
+            #[cfg(feature = "mtl4")]
+            device: &self.device,
+            params_buffer: &viewport.buffer,
+            params_offset: Viewport::slot_offset(slot),
+            second_params_offset: match views {
+                RenderViews::Stereo(_, second_slot) => Some(Viewport::slot_offset(second_slot)),
+                _ => None,
+            },
+            vertex_buffer: &self.vertex_buffer,
+            color_atlas: &atlas.color_atlas.texture,
+            mask_atlas: &atlas.mask_atlas.texture,
+            #[cfg(feature = "mtl4")]
+            argument_table: &self.argument_table,
+        };
+        encoder.bind_resources(&bindings);
+
+        let mut stats = RenderStats::default();
+
+        // Without vertex amplification, each range is drawn once per layer
+        let (layers, view_count, amplified) = match views {
+            RenderViews::Single => (0..1, 1, false),
+            RenderViews::Layer(layer) => (layer..layer + 1, 1, false),
+            RenderViews::Stereo(StereoPath::VertexAmplification, _) => (0..1, 2, true),
+            RenderViews::Stereo(StereoPath::DrawPerView, _) => (0..2, 2, false),
+        };
+        if amplified {
+            encoder.set_vertex_amplification_count(view_count);
+        }
+
+        // Each range is drawn with a pipeline specialized for its content type, which only
+        // samples the atlas of that type
+        for (content_type, range) in &self.draw_ranges {
+            let pipeline = match content_type {
+                ContentType::Color => &pipelines[0],
+                ContentType::Mask => &pipelines[1],
+            };
+            encoder.set_pipeline(pipeline);
+            encoder.bind_atlas(&bindings, *content_type);
+            for layer in layers.clone() {
+                encoder.draw_glyphs(range.start, range.len(), layer);
+            }
+
+            stats.draw_calls += layers.len();
+            stats.pipeline_switches += 1;
+            stats.glyphs += view_count * range.len();
+            stats.vertices += 4 * view_count * range.len();
+            match content_type {
+                ContentType::Color => stats.color_atlas_bound = true,
+                ContentType::Mask => stats.mask_atlas_bound = true,
+            }
+        }
+
+        if amplified {
+            encoder.set_vertex_amplification_count(1);
+        }
+
+        stats
+    }
+
+    /// Sets the prefix of the labels of the resources owned by the renderer (e.g. `"HUD"`
+    /// produces `"HUD - Vertex Buffer"`).
+    ///
+    /// The label is kept when the vertex buffer is reallocated. The residency set takes the label
+    /// in effect when it is first requested.
+    pub fn set_label(&mut self, label: &str) {
+        self.label = label.to_owned();
+        self.vertex_buffer
+            .setLabel(Some(&resource_label(&self.label, "Vertex Buffer")));
+    }
+
+    /// Returns the prefix of the labels of the resources owned by the renderer.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Times the render pass of `descriptor` on the GPU, reported by
+    /// [`TextRenderer::frame_profile`] once `command_buffer` completes. Call it before creating
+    /// the encoder of the pass the text is rendered in.
+    ///
+    /// Apple GPUs sample timestamps at the start and end of the stages of a pass rather than
+    /// around single draws, so the duration covers every draw of the pass. Render the text in a
+    /// pass of its own to time only the text. Up to three frames can be in flight at once.
+    ///
+    /// Does nothing if the device cannot sample timestamps at stage boundaries.
+    #[cfg(feature = "profiling")]
+    pub fn attach_gpu_timer(
+        &self,
+        descriptor: &MTLRenderPassDescriptor,
+        command_buffer: &ProtocolObject<dyn MTLCommandBuffer>,
+    ) {
+        if let Some(gpu_timer) = self.gpu_timer.get_or_init(|| GpuTimer::new(&self.device)) {
+            gpu_timer.attach(descriptor, command_buffer);
+        }
+    }
+
+    /// Returns the CPU timings of the most recent `prepare` and the GPU duration of the most
+    /// recently completed pass attached with [`TextRenderer::attach_gpu_timer`].
+    #[cfg(feature = "profiling")]
+    pub fn frame_profile(&self) -> FrameProfile {
+        let gpu = self
+            .gpu_timer
+            .get()
+            .and_then(Option::as_ref)
+            .and_then(GpuTimer::latest);
+
+        FrameProfile {
+            gpu,
+            ..self.profiler.profile()
+        }
+    }
+
+    /// Sets whether `prepare` times its phases, which [`TextRenderer::phase_timings`] returns.
+    /// Disabled by default.
+    ///
+    /// Unlike [`crate::FrameProfile`], this needs no feature and can be enabled in release
+    /// builds, e.g. from a debug menu, to tell whether slow frames come from rasterizing new
+    /// glyphs or from uploading them. Timing reads the clock several times per glyph, which is
+    /// why it is off by default. Disabled, it costs a branch per phase.
+    pub fn set_phase_timings(&mut self, enabled: bool) {
+        self.profiler.set_timings_enabled(enabled);
+    }
+
+    /// Returns the timings of the phases of the most recent `prepare`, or `None` if they are
+    /// disabled (see [`TextRenderer::set_phase_timings`]).
+    pub fn phase_timings(&self) -> Option<PreparePhaseTimings> {
+        self.profiler.timings()
+    }
+
+    /// Sets whether `prepare` collects the fonts that rendered each text area, which
+    /// [`TextRenderer::font_usage`] returns. Disabled by default.
+    ///
+    /// Font fallback may render text with other fonts than the requested one, or with the
+    /// `.notdef` glyph of a font when no font covers a character. Collecting the fonts costs a
+    /// lookup per glyph, and walks the glyphs of text areas whose geometry is cached.
+    pub fn set_font_usage(&mut self, enabled: bool) {
+        if enabled != self.font_usage.is_some() {
+            self.font_usage = enabled.then(Vec::new);
+        }
+    }
+
+    /// Returns the fonts that rendered the visible layout runs of each text area (or glyph
+    /// area) of the most recent `prepare`, in the order the areas were prepared, or `None` if
+    /// they are not collected (see [`TextRenderer::set_font_usage`]). Custom glyphs are not
+    /// included.
+    pub fn font_usage(&self) -> Option<&[AreaFontUsage]> {
+        self.font_usage.as_deref()
+    }
+
+    /// Sets whether `prepare` counts the pixels covered by the glyph quads of each text area,
+    /// which [`TextRenderer::overdraw`] returns. Disabled by default.
+    ///
+    /// Overlapping text areas, e.g. shadows and outlines drawn as areas of their own, cost
+    /// fragment work for every quad covering a pixel, which adds up on high resolution
+    /// displays. Counting adds a multiplication per glyph, and walks the glyphs of text areas
+    /// whose geometry is cached.
+    pub fn set_overdraw(&mut self, enabled: bool) {
+        if enabled != self.overdraw.is_some() {
+            self.overdraw = enabled.then(Overdraw::default);
+        }
+    }
+
+    /// Returns the pixels covered by the glyph quads of the most recent `prepare`, in total and
+    /// for each text area (or glyph area), or `None` if they are not counted (see
+    /// [`TextRenderer::set_overdraw`]).
+    ///
+    /// The counts are those of one view: [`TextRenderer::render_stereo`] covers them once per
+    /// view.
+    pub fn overdraw(&self) -> Option<&Overdraw> {
+        self.overdraw.as_ref()
+    }
+
+    /// Returns the number of glyphs that the most recent `prepare` did not draw, by reason.
+    ///
+    /// The counts are kept when `prepare` fails, so that they tell which glyphs were dropped
+    /// along with the atlas being full.
+    pub fn dropped_glyphs(&self) -> DroppedGlyphs {
+        self.drops.counts
+    }
+
+    /// Sets how many dropped glyphs `prepare` logs along with their reason, which
+    /// [`TextRenderer::dropped_glyph_log`] returns, e.g. to tell which character at which size
+    /// is missing. `0`, the default, disables the log. Only available in debug builds.
+    #[cfg(debug_assertions)]
+    pub fn set_dropped_glyph_log(&mut self, capacity: usize) {
+        self.drops.log_capacity = capacity;
+        self.drops.log.truncate(capacity);
+    }
+
+    /// Returns the first glyphs that the most recent `prepare` did not draw, up to the capacity
+    /// set with [`TextRenderer::set_dropped_glyph_log`]. Only available in debug builds.
+    #[cfg(debug_assertions)]
+    pub fn dropped_glyph_log(&self) -> &[DroppedGlyph] {
+        &self.drops.log
+    }
+
+    /// Sets whether `render` wraps its commands in a debug group and marks atlas grows with debug
+    /// signposts, so that text work is easy to find in GPU captures. Enabled by default.
+    ///
+    /// Atlas uploads are performed on the CPU and therefore show up in the CPU signposts of the
+    /// `signposts` feature rather than in GPU captures.
+    pub fn set_debug_markers(&mut self, enabled: bool) {
+        self.debug_markers = enabled;
+    }
+
+    /// Sets which misuses of the frame sequence panic: rendering before preparing, rendering after
+    /// the atlas was trimmed and prepared against since, and preparing again before rendering.
+    /// The first two do by default.
+    ///
+    /// The checks only run in builds with debug assertions, or with the `frame-validation`
+    /// feature, so release builds do not pay for them.
+    pub fn set_frame_validation(&mut self, validation: FrameValidation) {
+        self.frame_state.validation = validation;
+    }
+
+    /// Returns which misuses of the frame sequence panic.
+    pub fn frame_validation(&self) -> FrameValidation {
+        self.frame_state.validation
+    }
+
+    /// Sets where `render` places the viewport of the encoder, or `None`, the default, to leave
+    /// the viewport to the caller.
+    ///
+    /// Text is positioned against the resolution of the [`Viewport`], which Metal maps onto the
+    /// viewport of the encoder, the whole render target by default. When text only covers part
+    /// of a larger target (e.g. a panel of a UI atlas), this sets the encoder viewport to the
+    /// render resolution of the `Viewport` at `origin`, and the scissor rectangle too if
+    /// requested, before drawing. The region must lie within the render target. Neither is
+    /// restored afterwards, so they apply to whatever the caller draws next with the encoder.
+    ///
+    /// This applies to [`TextRenderer::render`], [`TextRenderer::render_with_slot`] and
+    /// [`TextRenderer::render_mask_only`], which set nothing when there is no text to draw.
+    /// [`TextRenderer::execute_icb`] leaves the viewport to the caller.
+    pub fn set_encoder_viewport(&mut self, encoder_viewport: Option<EncoderViewport>) {
+        self.encoder_viewport = encoder_viewport;
+    }
+
+    /// Returns where `render` places the viewport of the encoder, if it does.
+    pub fn encoder_viewport(&self) -> Option<EncoderViewport> {
+        self.encoder_viewport
+    }
+
+    /// Sets whether the vertices of each text area are cached across frames. Disabled by default.
+    ///
+    /// A text area whose buffer, scale, color, custom glyphs and bounds relative to its position
+    /// did not change since the previous `prepare` reuses its cached vertices, moved by the
+    /// whole pixels its position moved by, instead of looking up, clipping and placing each of
+    /// its glyphs. The visible layout runs are still walked to detect changes of the buffer. Any
+    /// glyph evicted from the atlas invalidates the geometry of all text areas, as do different
+    /// fractional parts of the position.
+    ///
+    /// Geometry is cached per [`Buffer`] (several text areas showing the same buffer replace each
+    /// other's geometry), and dropped when its buffer is not prepared. The depth computed by
+    /// `metadata_to_depth` is cached along with the vertices, so the function must return the
+    /// same depth for the same metadata across frames.
+    pub fn set_geometry_cache(&mut self, enabled: bool) {
+        if enabled != self.geometry_cache.is_some() {
+            self.geometry_cache = enabled.then(GeometryCache::default);
+        }
+    }
+
+    /// Returns whether the vertices of text areas are cached across frames.
+    pub fn geometry_cache(&self) -> bool {
+        self.geometry_cache.is_some()
+    }
+
+    /// Sets whether `prepare` skips text areas identical to an earlier area of the same call.
+    /// Disabled by default, so that intentional duplicates are drawn.
+    ///
+    /// Areas are identical when they show the same [`Buffer`] (the same object, not equal
+    /// contents) with the same custom glyphs slice and equal position, scale, scroll offset,
+    /// bounds, color and cache priority. A duplicate draws every glyph a second time over the
+    /// first, doubling its vertices and overdraw, so skipping it renders the same image, except
+    /// for text blended at partial opacity, which duplicates draw darker. Skipped areas keep
+    /// their place in the order of areas, with an empty [`TextRenderer::font_usage`], and are
+    /// counted by [`TextRenderer::duplicate_areas`]. Glyph areas are never skipped.
+    pub fn set_skip_duplicate_areas(&mut self, enabled: bool) {
+        if enabled != self.area_dedup.is_some() {
+            self.area_dedup = enabled.then(AreaDedup::default);
+        }
+    }
+
+    /// Returns whether `prepare` skips duplicate text areas.
+    pub fn skip_duplicate_areas(&self) -> bool {
+        self.area_dedup.is_some()
+    }
+
+    /// Sets whether text areas showing the same [`Buffer`] within a `prepare` share their
+    /// vertices. Disabled by default.
+    ///
+    /// A text area whose buffer, scale, color, custom glyphs and bounds relative to its position
+    /// match those of an earlier area of the same call copies its vertices, moved by the whole
+    /// pixels between the positions, like the geometry cache does across frames (see
+    /// [`TextRenderer::set_geometry_cache`]), e.g. for a label repeated at many positions. The
+    /// visible layout runs are still walked to compare the buffers. Areas prepared with
+    /// animations never share their vertices. Copies are counted by
+    /// [`TextRenderer::duplicate_areas`].
+    pub fn set_share_buffer_geometry(&mut self, enabled: bool) {
+        if enabled != self.shared_geometry.is_some() {
+            self.shared_geometry = enabled.then(GeometryCache::default);
+        }
+    }
+
+    /// Returns whether text areas showing the same buffer share their vertices.
+    pub fn share_buffer_geometry(&self) -> bool {
+        self.shared_geometry.is_some()
+    }
+
+    /// Returns the number of text areas of the most recent `prepare` that were skipped or
+    /// copied from an earlier area, see [`TextRenderer::set_skip_duplicate_areas`] and
+    /// [`TextRenderer::set_share_buffer_geometry`].
+    pub fn duplicate_areas(&self) -> DuplicateAreas {
+        self.duplicate_areas
+    }
+
+    /// Sets how glyphs of fonts made of bitmap strikes (e.g. emoji) are rasterized at sizes the
+    /// font has no strike for.
+    ///
+    /// This only affects glyphs rasterized afterwards. Glyphs already in the atlas, including
+    /// those rasterized by other renderers sharing it, are drawn as they were rasterized.
+    pub fn set_bitmap_strike_policy(&mut self, policy: BitmapStrikePolicy) {
+        self.bitmap_strike_policy = policy;
+    }
+
+    /// Returns how glyphs of fonts made of bitmap strikes are rasterized.
+    pub fn bitmap_strike_policy(&self) -> BitmapStrikePolicy {
+        self.bitmap_strike_policy
+    }
+
+    /// Sets the options text glyphs are rasterized with, e.g. synthetic bold and italic for
+    /// fonts without bold or italic faces.
+    ///
+    /// Glyphs rasterized with different options are cached separately, so renderers with
+    /// different options can share an atlas.
+    pub fn set_raster_config(&mut self, config: GlyphRasterConfig) {
+        if config == self.raster_config {
+            return;
+        }
+
+        self.raster_config = config;
+
+        // Cached geometry refers to the glyphs of the previous options
+        if let Some(geometry_cache) = &mut self.geometry_cache {
+            *geometry_cache = GeometryCache::default();
+        }
+    }
+
+    /// Returns the options text glyphs are rasterized with.
+    pub fn raster_config(&self) -> GlyphRasterConfig {
+        self.raster_config
+    }
+
+    /// Sets whether glyphs missing from their font (`.notdef`, glyph ID 0) are drawn as the
+    /// outline of a box, "tofu", instead of as the font draws them, often not at all.
+    ///
+    /// Boxes are as wide as the advance of the missing glyph and as high as the cap height of
+    /// its font. They are rasterized into the mask atlas once per size, and are colored and
+    /// clipped like the text around them. [`crate::AtlasStats::tofu`] counts them.
+    pub fn set_tofu(&mut self, enabled: bool) {
+        if enabled == self.tofu {
+            return;
+        }
+
+        self.tofu = enabled;
+
+        // Cached geometry refers to the glyphs drawn before
+        if let Some(geometry_cache) = &mut self.geometry_cache {
+            *geometry_cache = GeometryCache::default();
+        }
+    }
+
+    /// Returns whether glyphs missing from their font are drawn as boxes.
+    pub fn tofu(&self) -> bool {
+        self.tofu
+    }
+
+    /// Sets the largest physical font size text glyphs are rasterized at, or `None` for a
+    /// sixteenth of the largest size of the atlas textures (1024 pixels for textures of up to
+    /// 16384 texels).
+    ///
+    /// Larger glyphs, e.g. of a 48 pixel font in an area scaled by 40, are rasterized at this
+    /// size and their quads are scaled up, so that they neither take up most of the atlas nor
+    /// stall `prepare` while they are rasterized. The limit is soft: such glyphs are drawn
+    /// blurrier than rasterized glyphs, and at whole-pixel positions. Applications zooming
+    /// further can switch to drawing text as paths past
+    /// [`TextRenderer::effective_max_glyph_size`].
+    pub fn set_max_glyph_size(&mut self, max_size: Option<f32>) {
+        if max_size == self.max_glyph_size {
+            return;
+        }
+
+        self.max_glyph_size = max_size;
+
+        // Cached geometry refers to the glyphs of the previous size
+        if let Some(geometry_cache) = &mut self.geometry_cache {
+            *geometry_cache = GeometryCache::default();
+        }
+    }
+
+    /// Returns the largest physical font size text glyphs are rasterized at, or `None` for the
+    /// default.
+    pub fn max_glyph_size(&self) -> Option<f32> {
+        self.max_glyph_size
+    }
+
+    /// Returns the largest physical font size text glyphs are rasterized at into `atlas`, the
+    /// maximum glyph size or its default for the atlas.
+    pub fn effective_max_glyph_size(&self, atlas: &TextAtlas) -> f32 {
+        self.max_glyph_size.unwrap_or_else(|| {
+            let max_texture_size = atlas
+                .mask_atlas
+                .allocator
+                .max_size
+                .min(atlas.color_atlas.allocator.max_size);
+
+            max_texture_size as f32 / MAX_GLYPH_SIZE_DIVISOR
+        })
+    }
+
+    /// Returns the key under which `prepare` caches `glyph`, of the layout run of
+    /// `text_area.buffer` whose baseline is `line_y`, in `atlas` with the current settings of
+    /// the renderer, e.g. to check whether the glyph is cached before preparing it.
+    ///
+    /// The key depends on the position of the area in `viewport`, since glyphs are cached per
+    /// subpixel offset, on the [`TextRenderer::raster_config`], on the
+    /// [`TextRenderer::effective_max_glyph_size`] and, for glyphs missing from their font, on
+    /// [`TextRenderer::tofu`]. Glyphs emboldened by [`TextArea::font_synthesis`] are cached under
+    /// other keys.
+    pub fn text_glyph_key(
+        &self,
+        font_system: impl FontSystemAccess,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        text_area: &TextArea,
+        glyph: &LayoutGlyph,
+        line_y: f32,
+    ) -> AtlasKey {
+        let placement = AreaPlacement::new(
+            viewport,
+            text_area.left - text_area.scroll.0,
+            text_area.top - text_area.scroll.1,
+            text_area.scale,
+            text_area.bounds,
+        );
+        let glyph_placement = GlyphPlacement::from_layout_glyph(
+            glyph,
+            line_y,
+            (placement.left, placement.top),
+            placement.scale,
+        );
+
+        let (cache_key, _) = font_system.with_font_system(|font_system| {
+            text_glyph_key(
+                font_system,
+                &glyph_placement,
+                self.effective_max_glyph_size(atlas),
+                self.raster_config,
+                0.0,
+                self.tofu,
+            )
+        });
+
+        AtlasKey(cache_key)
+    }
+
+    /// Returns the key under which `prepare` caches the custom `glyph` of `text_area` in an
+    /// atlas, which depends on the position and scale of the area in `viewport`.
+    pub fn custom_glyph_key(
+        &self,
+        viewport: &Viewport,
+        text_area: &TextArea,
+        glyph: &CustomGlyph,
+    ) -> AtlasKey {
+        let placement = AreaPlacement::new(
+            viewport,
+            text_area.left - text_area.scroll.0,
+            text_area.top - text_area.scroll.1,
+            text_area.scale,
+            text_area.bounds,
+        );
+        let (_, _, cache_key) =
+            place_custom_glyph(glyph, (placement.left, placement.top), placement.scale);
+
+        AtlasKey(cache_key)
+    }
+
+    /// Returns whether `render` emits debug groups and signposts.
+    pub fn debug_markers(&self) -> bool {
+        self.debug_markers
+    }
+
+    /// Returns a counter that changes whenever `prepare` produces different geometry (or moves
+    /// it to a new vertex buffer), or is called with a different viewport buffer, e.g. of
+    /// another [`Viewport`] or after [`Viewport::set_active_slot`] created a slot.
+    ///
+    /// Commands encoded with [`TextRenderer::encode_into_icb`] with the viewport of the most
+    /// recent `prepare` only need to be re-encoded when this value changes. Until the first
+    /// command is encoded, the geometry is not compared and every `prepare` changes the value.
+    pub fn geometry_generation(&self) -> u64 {
+        self.geometry_generation
+    }
+
+    /// Returns the number of bytes of vertex data that the last `prepare` wrote into the vertex
+    /// buffer for its glyphs: 24 bytes per visible glyph, plus 8 bytes per glyph if any of their
+    /// quads is transformed, e.g. by [`TextRenderer::prepare_with_animation`]. The 64-byte header
+    /// of the buffer is not counted.
+    pub fn vertex_bytes(&self) -> usize {
+        if self.glyph_vertices.is_empty() {
+            return 0;
+        }
+
+        self.vertex_data.len() - mem::size_of::<VertexHeader>()
+    }
+
+    /// Releases the memory of the scratch collections that `prepare` keeps across frames to
+    /// avoid reallocating them, beyond what the glyphs of the most recent `prepare` need, e.g.
+    /// after a frame with far more text than usual.
+    ///
+    /// The next `prepare` allocates whatever it needs again.
+    pub fn shrink_scratch(&mut self) {
+        self.glyph_vertices.shrink_to_fit();
+        self.previous_glyph_vertices = Vec::new();
+        self.vertex_data.shrink_to_fit();
+        self.draw_ranges.shrink_to_fit();
+        self.empty_glyphs.shrink_to_fit();
+        self.area_glyphs = Vec::new();
+        self.missing_glyphs = Vec::new();
+        self.incomplete_areas = Vec::new();
+        self.grouped_glyphs = Vec::new();
+        self.glyph_rects = Vec::new();
+    }
+
+    /// Returns the memory used by the vertex buffer, and an estimate of the memory used by the
+    /// vertex data kept on the CPU.
+    ///
+    /// The parameters buffer belongs to the [`Viewport`] and the atlas textures to the
+    /// [`TextAtlas`], see their `memory_usage` methods.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let vertex_bytes = (self.glyph_vertices.capacity()
+            + self.previous_glyph_vertices.capacity())
+            * mem::size_of::<GlyphToRender>()
+            + self.vertex_data.capacity()
+            + self.draw_ranges.capacity() * mem::size_of::<(ContentType, Range<usize>)>();
+        let empty_glyph_bytes = self.empty_glyphs.capacity() * mem::size_of::<GlyphonCacheKey>();
+        let missing_glyph_bytes = self.missing_glyphs.capacity() * mem::size_of::<MissingGlyph>()
+            + self.incomplete_areas.capacity() * mem::size_of::<Range<usize>>()
+            + self.grouped_glyphs.capacity() * mem::size_of::<GlyphToRender>()
+            + self.glyph_rects.capacity() * mem::size_of::<QuadRect>();
+        let geometry_cache_bytes = self
+            .geometry_cache
+            .as_ref()
+            .map_or(0, GeometryCache::memory_bytes)
+            + self
+                .shared_geometry
+                .as_ref()
+                .map_or(0, GeometryCache::memory_bytes)
+            + self.area_glyphs.capacity() * mem::size_of::<AreaGlyph>();
+
+        MemoryUsage {
+            texture_bytes: 0,
+            buffer_bytes: MTLResource::allocatedSize(&*self.vertex_buffer) as u64,
+            cpu_bytes: (vertex_bytes
+                + empty_glyph_bytes
+                + missing_glyph_bytes
+                + geometry_cache_bytes) as u64,
+        }
+    }
+
+    /// Encodes the draw of all layouts that were previously provided to `prepare` into the
+    /// command at `command_index` of an indirect command buffer, using the given parameter slot
+    /// of the `viewport`. Returns `false` (and resets the command) if there is nothing to draw.
+    ///
+    /// The indirect command buffer must support `MTLIndirectCommandType::Draw`, must not inherit
+    /// the pipeline state or buffers, and must allow at least 2 vertex and 1 fragment buffer
+    /// bindings. Use [`TextRenderer::execute_icb`] to execute the command, and re-encode it
+    /// whenever [`TextRenderer::geometry_generation`] changes.
+    pub fn encode_into_icb(
+        &self,
+        viewport: &Viewport,
+        slot: usize,
+        icb: &ProtocolObject<dyn MTLIndirectCommandBuffer>,
+        command_index: usize,
+    ) -> bool {
+        let command = unsafe { icb.indirectRenderCommandAtIndex(command_index) };
+        self.encoded_icb.set(true);
+
+        if self.glyph_vertices.is_empty() || !viewport.is_slot_renderable(slot) {
+            command.reset();
+            return false;
+        }
+
+        let params_offset = Viewport::slot_offset(slot);
+
+        unsafe {
+            command.setRenderPipelineState(&self.pipeline);
+            command.setVertexBuffer_offset_atIndex(&viewport.buffer, params_offset, 0);
+            command.setVertexBuffer_offset_atIndex(&self.vertex_buffer, 0, 1);
+            command.setFragmentBuffer_offset_atIndex(&viewport.buffer, params_offset, 0);
+            command.drawPrimitives_vertexStart_vertexCount_instanceCount_baseInstance(
+                MTLPrimitiveType::TriangleStrip,
+                0,
+                4,
+                self.glyph_vertices.len(),
+                0,
+            );
+        }
+
+        true
+    }
+
+    /// Executes a command previously encoded with [`TextRenderer::encode_into_icb`].
+    ///
+    /// Indirect commands cannot bind textures, so this binds the atlas textures on `encoder` and
+    /// declares the buffers referenced by the command as used before executing it.
+    pub fn execute_icb(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        encoder: &ProtocolObject<dyn MTLRenderCommandEncoder>,
+        icb: &ProtocolObject<dyn MTLIndirectCommandBuffer>,
+        command_index: usize,
+    ) {
+        if self.debug_markers {
+            encoder.pushDebugGroup(ns_string!("metalglyph: text pass"));
+        }
+
+        unsafe {
+            encoder.setVertexTexture_atIndex(Some(&atlas.color_atlas.texture), 0);
+            encoder.setVertexTexture_atIndex(Some(&atlas.mask_atlas.texture), 1);
+            encoder.setFragmentTexture_atIndex(Some(&atlas.color_atlas.texture), 0);
+            encoder.setFragmentTexture_atIndex(Some(&atlas.mask_atlas.texture), 1);
+            encoder.useResource_usage_stages(
+                ProtocolObject::from_ref(&*self.vertex_buffer),
+                MTLResourceUsage::Read,
+                MTLRenderStages::Vertex,
+            );
+            encoder.useResource_usage_stages(
+                ProtocolObject::from_ref(&*viewport.buffer),
+                MTLResourceUsage::Read,
+                MTLRenderStages::Vertex | MTLRenderStages::Fragment,
+            );
+            encoder.executeCommandsInBuffer_withRange(icb, NSRange::new(command_index, 1));
+        }
+
+        if self.debug_markers {
+            encoder.popDebugGroup();
+        }
+    }
+
+    fn update_draw_ranges(&mut self) {
+        self.draw_ranges.clear();
+
+        for (i, glyph) in self.glyph_vertices.iter().enumerate() {
+            let content_type = glyph.content_type();
+
+            match self.draw_ranges.last_mut() {
+                Some((last_content_type, range)) if *last_content_type == content_type => {
+                    range.end = i + 1;
+                }
+                _ => self.draw_ranges.push((content_type, i..i + 1)),
+            }
+        }
+    }
+
+    fn update_geometry_generation(&mut self, viewport: &Viewport, reallocated: bool) {
+        // Encoded commands reference the viewport buffer as well as the vertex buffer
+        let viewport_changed =
+            self.geometry_viewport.replace(viewport.buffer_id()) != Some(viewport.buffer_id());
+
+        if reallocated
+            || viewport_changed
+            || !self.encoded_icb.get()
+            || as_bytes(&self.glyph_vertices) != as_bytes(&self.previous_glyph_vertices)
+        {
+            self.geometry_generation = self.geometry_generation.wrapping_add(1);
+        }
+    }
+}
+
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum TextColorConversion {
+    None = 0,
+    ConvertToLinear = 1,
+}
+
+/// The physical position and scale of a text area, and the rectangle its glyphs are clipped to.
+#[derive(Clone, Copy)]
+struct AreaPlacement {
+    left: f32,
+    top: f32,
+    scale: f32,
+    bounds_min_x: i32,
+    bounds_min_y: i32,
+    bounds_max_x: i32,
+    bounds_max_y: i32,
+}
+
+impl AreaPlacement {
+    fn new(viewport: &Viewport, left: f32, top: f32, scale: f32, bounds: TextBounds) -> Self {
+        let resolution = viewport.render_resolution();
+        let scale_factor = viewport.scale_factor() * viewport.render_scale();
+        let (origin_x, origin_y) = viewport.render_origin();
+        let has_transform = viewport.transform() != ViewTransform::IDENTITY;
+
+        // Convert logical coordinates into pixels of the render resolution
+        let bounds = bounds.scaled(scale_factor);
+
+        // The implicit clip to the resolution applies after the origin offset, and only when no
+        // view transform can move text into view after prepare.
+        let (bounds_min_x, bounds_min_y, bounds_max_x, bounds_max_y) = if has_transform {
+            (bounds.left, bounds.top, bounds.right, bounds.bottom)
+        } else {
+            (
+                bounds.left.max(-origin_x),
+                bounds.top.max(-origin_y),
+                bounds.right.min(resolution.width as i32 - origin_x),
+                bounds.bottom.min(resolution.height as i32 - origin_y),
+            )
+        };
+
+        Self {
+            left: left * scale_factor,
+            top: top * scale_factor,
+            scale: scale * scale_factor,
+            bounds_min_x,
+            bounds_min_y,
+            bounds_max_x,
+            bounds_max_y,
+        }
+    }
+
+    /// Returns whether `run` overlaps the clip rectangle vertically.
+    ///
+    /// Runs outside of the bounds, or of the resolution, are skipped before any glyph is looked
+    /// up, so that glyphs of invisible lines are not kept in use in the atlas.
+    fn is_run_visible(&self, run: &LayoutRun) -> bool {
+        let start_y_physical = (self.top + (run.line_top * self.scale)) as i32;
+        let end_y_physical = start_y_physical + (run.line_height * self.scale) as i32;
+
+        start_y_physical <= self.bounds_max_y && self.bounds_min_y <= end_y_physical
+    }
+
+    /// Returns the fractional part of the position.
+    fn fractional_offset(&self) -> (f32, f32) {
+        (self.left - self.left.floor(), self.top - self.top.floor())
+    }
+
+    /// Returns the clip rectangle relative to the integer part of the position.
+    fn relative_bounds(&self) -> [i32; 4] {
+        let (x, y) = (self.left.floor() as i32, self.top.floor() as i32);
+
+        [
+            self.bounds_min_x.wrapping_sub(x),
+            self.bounds_min_y.wrapping_sub(y),
+            self.bounds_max_x.wrapping_sub(x),
+            self.bounds_max_y.wrapping_sub(y),
+        ]
+    }
+}
+
+/// The bit of a 16-bit field of [`GlyphToRender`] that holds a flag.
+pub(crate) const FLAG_BIT: u16 = 1 << 15;
+
+/// The bit of a 16-bit field of [`GlyphVertex`] that holds a bit of the index of its background
+/// hint.
+const HINT_BIT: u16 = 1 << 14;
+
+/// The largest width and height of a quad, below [`HINT_BIT`].
+const MAX_QUAD_SIZE: i32 = HINT_BIT as i32 - 1;
+
+/// The number of distinct background hints of a vertex buffer.
+const MAX_BACKGROUND_HINTS: usize = 15;
+
+/// The `transform` of a [`GlyphToRender`] that leaves its quad in place: a scale of one and no
+/// rotation or offset, as half floats.
+pub(crate) const QUAD_IDENTITY: [u16; 4] = [0x3c00, 0, 0, 0];
+
+/// Converts `value` to the bits of the nearest half float, flushing values too small for a normal
+/// half float to zero.
+pub(crate) fn f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent <= 0 {
+        return sign;
+    }
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // Rounds to nearest even, a carry out of the mantissa increments the exponent
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let rest = mantissa & 0x1fff;
+    let half = if rest > 0x1000 || (rest == 0x1000 && half & 1 == 1) {
+        half + 1
+    } else {
+        half
+    };
+
+    sign | half as u16
+}
+
+/// Converts the bits of a half float to `f32`, reading subnormal half floats as zero.
+pub(crate) fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+
+    match exponent {
+        0 => f32::from_bits(sign),
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 127 - 15) << 23) | (mantissa << 13)),
+    }
+}
+
+impl GlyphToRender {
+    /// The placeholder of a glyph missing from the atlas, which no visible glyph is equal to as
+    /// culling leaves no empty quads.
+    const MISSING: Self = Self {
+        pos: [0, 0],
+        dim: [0, 0],
+        uv: [0, 0],
+        color: 0,
+        depth: 0.0,
+        transform: QUAD_IDENTITY,
+        background: 0,
+    };
+
+    fn is_missing(&self) -> bool {
+        self.dim == [0, 0]
+    }
+
+    /// Returns the data of the glyph that the GPU reads for every quad, with the index of its
+    /// background hint plus one, or `0` without a hint.
+    fn gpu_vertex(&self, hint: u16) -> GlyphVertex {
+        let hint_bit = |bit: u16| ((hint >> bit) & 1) * HINT_BIT;
+
+        GlyphVertex {
+            pos: self.pos,
+            dim: [self.dim[0] | hint_bit(2), self.dim[1] | hint_bit(3)],
+            uv: [self.uv[0] | hint_bit(0), self.uv[1] | hint_bit(1)],
+            color: self.color,
+            depth: self.depth,
+        }
+    }
+
+    /// Returns the width and height of the quad, without the flags stored in their high bits.
+    pub(crate) fn size(&self) -> [u16; 2] {
+        self.dim.map(|d| d & !FLAG_BIT)
+    }
+
+    fn content_type(&self) -> ContentType {
+        if self.dim[0] & FLAG_BIT != 0 {
+            ContentType::Mask
+        } else {
+            ContentType::Color
+        }
+    }
+
+    /// Returns the left, top, right and bottom edges of the rectangle covering the quad once
+    /// transformed.
+    fn screen_rect(&self) -> [i32; 4] {
+        let [x, y] = self.pos;
+        let [width, height] = self.size().map(i32::from);
+
+        if self.transform == QUAD_IDENTITY {
+            return [x, y, x + width, y + height];
         }
 
-        Ok(())
+        let [cos, sin, offset_x, offset_y] = self.transform.map(f16_to_f32);
+        let (half_width, half_height) = (width as f32 / 2.0, height as f32 / 2.0);
+        let center_x = x as f32 + offset_x + half_width;
+        let center_y = y as f32 + offset_y + half_height;
+        let extent_x = cos.abs() * half_width + sin.abs() * half_height;
+        let extent_y = sin.abs() * half_width + cos.abs() * half_height;
+
+        [
+            (center_x - extent_x).floor() as i32,
+            (center_y - extent_y).floor() as i32,
+            (center_x + extent_x).ceil() as i32,
+            (center_y + extent_y).ceil() as i32,
+        ]
+    }
+}
+
+/// The screen rectangle of a glyph quad, see [`GlyphToRender::screen_rect`].
+#[derive(Clone, Copy)]
+struct QuadRect {
+    content_type: ContentType,
+    rect: [i32; 4],
+}
+
+/// Moves the mask glyphs of a text area before its color glyphs, keeping their relative order,
+/// so that each content type is drawn with a single draw call. Areas in which a color glyph
+/// overlaps a mask glyph keep their order, since it decides which one is blended on top.
+///
+/// `grouped` and `rects` are scratch space, reused across areas and frames.
+fn group_by_content_type(
+    glyphs: &mut [GlyphToRender],
+    grouped: &mut Vec<GlyphToRender>,
+    rects: &mut Vec<QuadRect>,
+) {
+    let Some(first) = glyphs.first() else {
+        return;
+    };
+    let first_content_type = first.content_type();
+    if glyphs
+        .iter()
+        .all(|glyph| glyph.content_type() == first_content_type)
+    {
+        return;
+    }
+
+    let of_type = |content_type| {
+        glyphs
+            .iter()
+            .filter(move |glyph| glyph.content_type() == content_type)
+    };
+
+    rects.clear();
+    rects.extend(glyphs.iter().map(|glyph| QuadRect {
+        content_type: glyph.content_type(),
+        rect: glyph.screen_rect(),
+    }));
+    if content_types_overlap(rects) {
+        return;
+    }
+
+    grouped.clear();
+    grouped.extend(of_type(ContentType::Mask));
+    grouped.extend(of_type(ContentType::Color));
+    glyphs.copy_from_slice(grouped);
+}
+
+/// Returns whether a color quad overlaps a mask quad. Sorts `rects` by their left edge.
+fn content_types_overlap(rects: &mut [QuadRect]) -> bool {
+    let bounds = |content_type| {
+        rects
+            .iter()
+            .filter(|quad| quad.content_type == content_type)
+            .map(|quad| quad.rect)
+            .reduce(|a, b| {
+                [
+                    a[0].min(b[0]),
+                    a[1].min(b[1]),
+                    a[2].max(b[2]),
+                    a[3].max(b[3]),
+                ]
+            })
+    };
+
+    // The glyphs of each content type often lie apart, e.g. emoji at the end of a line
+    let (Some(color), Some(mask)) = (bounds(ContentType::Color), bounds(ContentType::Mask)) else {
+        return false;
+    };
+    if !rects_overlap(color, mask) {
+        return false;
+    }
+
+    // Sweep from left to right, comparing only quads whose horizontal extents overlap
+    rects.sort_unstable_by_key(|quad| quad.rect[0]);
+    rects.iter().enumerate().any(|(i, quad)| {
+        rects[i + 1..]
+            .iter()
+            .take_while(|other| other.rect[0] < quad.rect[2])
+            .any(|other| {
+                other.content_type != quad.content_type && rects_overlap(quad.rect, other.rect)
+            })
+    })
+}
+
+/// Returns whether the rectangles, given as left, top, right and bottom edges, overlap.
+fn rects_overlap(a: [i32; 4], b: [i32; 4]) -> bool {
+    a[0] < b[2] && b[0] < a[2] && a[1] < b[3] && b[1] < a[3]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum GlyphonCacheKey {
+    Text(cosmic_text::CacheKey, RasterConfigKey),
+    Custom(CustomGlyphCacheKey),
+    Tofu(TofuKey),
+}
+
+/// The key of a glyph rendered by a [`TextRenderer`], identifying its font or custom glyph, its
+/// size and its subpixel offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GlyphKey {
+    /// A glyph of text, rasterized from its font.
+    Text(cosmic_text::CacheKey),
+    /// A custom glyph, rasterized by the caller.
+    Custom {
+        /// The ID of the custom glyph.
+        id: CustomGlyphId,
+        /// The width of the glyph in pixels.
+        width: u16,
+        /// The height of the glyph in pixels.
+        height: u16,
+        /// The binning of the fractional x offset.
+        x_bin: SubpixelBin,
+        /// The binning of the fractional y offset.
+        y_bin: SubpixelBin,
+    },
+    /// A box drawn in place of a glyph missing from its font, see [`TextRenderer::set_tofu`].
+    Tofu {
+        /// The width of the advance of the missing glyph in pixels.
+        advance: u16,
+        /// The height of the box in pixels.
+        height: u16,
+    },
+}
+
+impl From<GlyphonCacheKey> for GlyphKey {
+    fn from(cache_key: GlyphonCacheKey) -> Self {
+        match cache_key {
+            GlyphonCacheKey::Text(cache_key, _) => Self::Text(cache_key),
+            GlyphonCacheKey::Custom(cache_key) => Self::Custom {
+                id: cache_key.glyph_id,
+                width: cache_key.width,
+                height: cache_key.height,
+                x_bin: cache_key.x_bin,
+                y_bin: cache_key.y_bin,
+            },
+            GlyphonCacheKey::Tofu(key) => Self::Tofu {
+                advance: key.advance,
+                height: key.height,
+            },
+        }
     }
+}
 
-    /// Renders all layouts that were previously provided to `prepare`.
-    pub fn render(
-        &self,
-        atlas: &TextAtlas,
-        viewport: &Viewport,
-        encoder: &Retained<ProtocolObject<dyn MTLRenderCommandEncoder>>,
-    ) {
-        if self.glyph_vertices.is_empty() {
-            return;
-        }
+/// The key a glyph is cached under in a [`TextAtlas`], e.g. to check whether
+/// [`TextAtlas::contains`] a glyph that `prepare` would render.
+///
+/// Unlike a [`GlyphKey`], it includes the [`GlyphRasterConfig`] of text glyphs, so it identifies
+/// exactly one glyph of the atlas. Keys built with the constructors of this type and the key
+/// methods of [`TextRenderer`] are equal to those `prepare` builds for the same glyphs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AtlasKey(pub(crate) GlyphonCacheKey);
 
-        encoder.setRenderPipelineState(&self.pipeline);
+impl AtlasKey {
+    /// Creates the key of a text glyph rasterized with `raster_config`.
+    ///
+    /// `cache_key` is the key of a physical glyph, e.g. [`GlyphPlacement::cache_key`]. `prepare`
+    /// rasterizes glyphs larger than [`TextRenderer::effective_max_glyph_size`] smaller, and
+    /// draws glyphs missing from their font as boxes if [`TextRenderer::tofu`] is set, which
+    /// changes their key. Use [`TextRenderer::text_glyph_key`] to account for both.
+    pub fn text(cache_key: cosmic_text::CacheKey, raster_config: GlyphRasterConfig) -> Self {
+        Self(GlyphonCacheKey::Text(cache_key, raster_config.key()))
+    }
 
-        unsafe {
-            encoder.setVertexBuffer_offset_atIndex(Some(&viewport.buffer), 0, 0);
-            encoder.setVertexBuffer_offset_atIndex(Some(&self.vertex_buffer), 0, 1);
-            encoder.setVertexTexture_atIndex(Some(&atlas.color_atlas.texture), 0);
-            encoder.setVertexTexture_atIndex(Some(&atlas.mask_atlas.texture), 1);
-            encoder.setFragmentTexture_atIndex(Some(&atlas.color_atlas.texture), 0);
-            encoder.setFragmentTexture_atIndex(Some(&atlas.mask_atlas.texture), 1);
+    /// Creates the key of a custom glyph rasterized at `width` x `height` physical pixels, at
+    /// the subpixel offsets of `x_bin` and `y_bin`, see [`RasterizeCustomGlyphRequest`].
+    ///
+    /// Use [`TextRenderer::custom_glyph_key`] to derive them from a [`CustomGlyph`] of a text
+    /// area as `prepare` does.
+    pub fn custom(
+        id: CustomGlyphId,
+        width: u16,
+        height: u16,
+        x_bin: SubpixelBin,
+        y_bin: SubpixelBin,
+    ) -> Self {
+        Self(GlyphonCacheKey::Custom(CustomGlyphCacheKey {
+            glyph_id: id,
+            width,
+            height,
+            x_bin,
+            y_bin,
+        }))
+    }
 
-            encoder.drawPrimitives_vertexStart_vertexCount_instanceCount(
-                MTLPrimitiveType::TriangleStrip,
-                0,
-                4,
-                self.glyph_vertices.len(),
-            );
-        }
+    /// Returns the [`GlyphKey`] of the glyph, which omits its raster config.
+    pub fn glyph_key(self) -> GlyphKey {
+        self.0.into()
     }
-}
 
-#[repr(u16)]
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-enum TextColorConversion {
-    None = 0,
-    ConvertToLinear = 1,
+    /// Returns the raster config of a text glyph, or `None` for other glyphs.
+    pub fn raster_config(self) -> Option<GlyphRasterConfig> {
+        match self.0 {
+            GlyphonCacheKey::Text(_, raster_key) => Some(raster_key.config()),
+            GlyphonCacheKey::Custom(_) | GlyphonCacheKey::Tofu(_) => None,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub(crate) enum GlyphonCacheKey {
-    Text(cosmic_text::CacheKey),
-    Custom(CustomGlyphCacheKey),
+impl From<AtlasKey> for GlyphKey {
+    fn from(key: AtlasKey) -> Self {
+        key.glyph_key()
+    }
 }
 
 fn next_copy_buffer_size(size: u64) -> u64 {
@@ -375,22 +3318,147 @@ fn next_copy_buffer_size(size: u64) -> u64 {
 }
 
 fn create_oversized_buffer(
-    device: &Retained<ProtocolObject<dyn MTLDevice>>,
+    device: &ProtocolObject<dyn MTLDevice>,
     contents: &[u8],
-) -> (Retained<ProtocolObject<dyn MTLBuffer>>, u64) {
+    options: MTLResourceOptions,
+) -> Option<(Retained<ProtocolObject<dyn MTLBuffer>>, u64)> {
     let size = next_copy_buffer_size(contents.len() as u64);
 
     let buffer = unsafe {
-        device
-            .newBufferWithBytes_length_options(
-                NonNull::from(contents).cast(),
-                size as usize,
-                MTLResourceOptions::StorageModeShared,
-            )
-            .unwrap()
+        device.newBufferWithBytes_length_options(
+            NonNull::from(contents).cast(),
+            size as usize,
+            options,
+        )?
+    };
+
+    Some((buffer, size))
+}
+
+fn storage_mode_mask() -> MTLResourceOptions {
+    MTLResourceOptions::StorageModeShared
+        | MTLResourceOptions::StorageModeManaged
+        | MTLResourceOptions::StorageModePrivate
+        | MTLResourceOptions::StorageModeMemoryless
+}
+
+/// Returns the resource options of buffers written by the CPU every frame on `device`.
+pub(crate) fn default_buffer_options(device: &ProtocolObject<dyn MTLDevice>) -> MTLResourceOptions {
+    let storage_mode = if cfg!(target_os = "macos") && !device.hasUnifiedMemory() {
+        MTLResourceOptions::StorageModeManaged
+    } else {
+        MTLResourceOptions::StorageModeShared
+    };
+
+    storage_mode | MTLResourceOptions::CPUCacheModeWriteCombined
+}
+
+/// Returns `true` if buffers created with `options` can be written by the CPU. Managed resources
+/// only exist on macOS.
+pub(crate) fn is_cpu_writable(options: MTLResourceOptions) -> bool {
+    let storage_mode = options & storage_mode_mask();
+
+    storage_mode == MTLResourceOptions::StorageModeShared
+        || (cfg!(target_os = "macos") && storage_mode == MTLResourceOptions::StorageModeManaged)
+}
+
+/// Returns `true` if writes of the CPU to buffers created with `options` must be flushed with
+/// `didModifyRange`.
+#[cfg(target_os = "macos")]
+pub(crate) fn is_managed(options: MTLResourceOptions) -> bool {
+    options & storage_mode_mask() == MTLResourceOptions::StorageModeManaged
+}
+
+/// Returns the bytes of `values`, which must be of a `#[repr(C)]` type without padding.
+fn as_bytes<T: Copy>(values: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(values.as_ptr().cast(), mem::size_of_val(values)) }
+}
+
+/// The start of the vertex buffer, read by `vertex_main`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct VertexHeader {
+    /// The offset in bytes of the transforms of the quads from the start of the buffer, or `0`
+    /// if no quad is transformed and the shader leaves them in place.
+    transform_offset: u32,
+    /// The sRGB-encoded luminances of the distinct background hints of the glyphs, see
+    /// [`BackgroundHints`].
+    background_hints: [u32; MAX_BACKGROUND_HINTS],
+}
+
+/// The background hints of the glyphs of a vertex buffer, which the glyphs refer to by index.
+/// Once it is full, hints are replaced by the closest one.
+#[derive(Default)]
+struct BackgroundHints {
+    luminances: [u32; MAX_BACKGROUND_HINTS],
+    len: usize,
+}
+
+impl BackgroundHints {
+    /// Returns the index plus one of `background`, encoded like [`GlyphToRender`] holds it, or
+    /// `0` without a hint.
+    fn index(&mut self, background: u32) -> u16 {
+        if background == 0 {
+            return 0;
+        }
+
+        let luminance = background & 0xff;
+        let luminances = &self.luminances[..self.len];
+        let index = match luminances.iter().position(|&l| l == luminance) {
+            Some(index) => index,
+            None if self.len < MAX_BACKGROUND_HINTS => {
+                self.luminances[self.len] = luminance;
+                self.len += 1;
+                self.len - 1
+            }
+            None => (0..self.len)
+                .min_by_key(|&index| luminances[index].abs_diff(luminance))
+                .expect("the hints are full"),
+        };
+
+        index as u16 + 1
+    }
+}
+
+/// Writes the contents of the vertex buffer for `vertices` into `bytes`: a [`VertexHeader`], a
+/// [`GlyphVertex`] per glyph and, only if a quad is transformed, the `transform` of each glyph
+/// in the same order.
+///
+/// Background hints are set per area, so a hint is only looked up when it differs from the hint
+/// of the previous glyph.
+fn pack_vertices(vertices: &[GlyphToRender], bytes: &mut Vec<u8>) {
+    let transformed = vertices
+        .iter()
+        .any(|vertex| vertex.transform != QUAD_IDENTITY);
+    let glyphs_end =
+        mem::size_of::<VertexHeader>() + vertices.len() * mem::size_of::<GlyphVertex>();
+
+    bytes.clear();
+    bytes.reserve(
+        glyphs_end + transformed as usize * vertices.len() * mem::size_of_val(&QUAD_IDENTITY),
+    );
+    bytes.resize(mem::size_of::<VertexHeader>(), 0);
+
+    let mut hints = BackgroundHints::default();
+    let mut previous = (0, 0);
+    for vertex in vertices {
+        if vertex.background != previous.0 {
+            previous = (vertex.background, hints.index(vertex.background));
+        }
+        bytes.extend_from_slice(as_bytes(&[vertex.gpu_vertex(previous.1)]));
+    }
+
+    let header = VertexHeader {
+        transform_offset: if transformed { glyphs_end as u32 } else { 0 },
+        background_hints: hints.luminances,
     };
+    bytes[..mem::size_of::<VertexHeader>()].copy_from_slice(as_bytes(&[header]));
 
-    (buffer, size)
+    if transformed {
+        for vertex in vertices {
+            bytes.extend_from_slice(as_bytes(&vertex.transform));
+        }
+    }
 }
 
 fn zero_depth(_: usize) -> f32 {
@@ -404,142 +3472,380 @@ struct GetGlyphImageResult {
     width: u16,
     height: u16,
     data: Vec<u8>,
+    strike_policy: BitmapStrikePolicy,
+    strike_substituted: bool,
 }
 
-fn prepare_glyph<R>(
+/// Where `prepare` places a glyph.
+struct GlyphPosition {
     x: i32,
     y: i32,
     line_y: f32,
     color: Color,
     metadata: usize,
+    /// The scale of the area of the glyph.
+    scale: f32,
+    /// The physical bounds of the area of the glyph: left, top, right and bottom.
+    bounds: [i32; 4],
+    /// How the quad of the glyph is moved and scaled, its color overrides are already applied.
+    transform: GlyphTransform,
+    /// The factor the glyph was rasterized smaller by, see [`cap_glyph_size`].
+    raster_scale: f32,
+    /// Whether the area of the glyph has [`CachePriority::Pinned`].
+    pinned: bool,
+    /// The background hint of the area of the glyph, encoded like [`GlyphToRender`] holds it.
+    background: u32,
+}
+
+impl GlyphPosition {
+    /// Returns the approximate physical position of the glyph, ordered by line then column.
+    fn screen_position(&self) -> (i32, i32) {
+        ((self.line_y * self.scale).round() as i32 + self.y, self.x)
+    }
+}
+
+/// A glyph that was not in the atlas when its area was prepared, see
+/// [`TextRenderer::rasterize_missing_glyphs`].
+struct MissingGlyph {
+    /// The index of the placeholder of the glyph in `glyph_vertices`.
+    index: usize,
+    /// The index of the area of the glyph among the areas of the `prepare`.
+    area: usize,
+    cache_key: GlyphonCacheKey,
+    position: GlyphPosition,
+}
+
+/// Limits the time spent rasterizing glyphs in [`TextRenderer::prepare_with_budget`], and counts
+/// the glyphs deferred by it or by the upload budget of the atlas.
+#[derive(Default)]
+struct RasterBudget {
+    /// The time after which glyphs are no longer rasterized, or `None` for no limit.
+    deadline: Option<Instant>,
+    /// The number of glyphs rasterized so far.
+    rasterized: usize,
+    /// The number of glyphs that were not rasterized because a budget was exhausted.
+    deferred: usize,
+}
+
+impl RasterBudget {
+    /// Returns whether a glyph that is not cached may be rasterized, counting it as rasterized
+    /// or deferred. The first glyph always is if `upload_allowed` by the upload budget of the
+    /// atlas, so that text converges.
+    fn allows_rasterization(&mut self, upload_allowed: bool) -> bool {
+        if !upload_allowed
+            || (self.rasterized > 0
+                && self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline))
+        {
+            self.deferred += 1;
+            false
+        } else {
+            self.rasterized += 1;
+            true
+        }
+    }
+}
+
+/// Looks `cache_key` up in both atlases, counting a hit and marking the glyph as recently used
+/// if it is cached. Glyphs are only marked as in use once they are known to be visible, see
+/// [`glyph_vertex`].
+fn lookup_glyph(
     cache_key: GlyphonCacheKey,
     atlas: &mut TextAtlas,
-    device: &Retained<ProtocolObject<dyn MTLDevice>>,
-    cache: &mut SwashCache,
-    font_system: &mut FontSystem,
-    scale_factor: f32,
-    bounds_min_x: i32,
-    bounds_min_y: i32,
-    bounds_max_x: i32,
-    bounds_max_y: i32,
-    get_glyph_image: impl FnOnce(
-        &mut SwashCache,
-        &mut FontSystem,
-        &mut R,
-    ) -> Option<GetGlyphImageResult>,
-    mut metadata_to_depth: impl FnMut(usize) -> f32,
-    mut rasterize_custom_glyph: R,
-) -> Result<Option<GlyphToRender>, PrepareError>
-where
-    R: FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
-{
-    let details = if let Some(details) = atlas.mask_atlas.glyph_cache.get(&cache_key) {
-        atlas.mask_atlas.glyphs_in_use.insert(cache_key);
-        details
-    } else if let Some(details) = atlas.color_atlas.glyph_cache.get(&cache_key) {
-        atlas.color_atlas.glyphs_in_use.insert(cache_key);
-        details
+    profiler: &mut Profiler,
+) -> bool {
+    let lookup = profiler.start();
+    let cached = if atlas
+        .mask_atlas
+        .allocator
+        .glyph_cache
+        .get(&cache_key)
+        .is_some()
+    {
+        atlas.mask_atlas.counters.hits += 1;
+        true
+    } else if atlas
+        .color_atlas
+        .allocator
+        .glyph_cache
+        .get(&cache_key)
+        .is_some()
+    {
+        atlas.color_atlas.counters.hits += 1;
+        true
     } else {
-        let Some(image) = (get_glyph_image)(cache, font_system, &mut rasterize_custom_glyph) else {
-            return Ok(None);
-        };
+        false
+    };
+    profiler.record(Phase::Lookup, lookup);
 
-        let should_rasterize = image.width > 0 && image.height > 0;
-
-        let (gpu_cache, atlas_id, inner) = if should_rasterize {
-            let mut inner = atlas.inner_for_content_mut(image.content_type);
-
-            // Find a position in the packer
-            let allocation = loop {
-                match inner.try_allocate(image.width as usize, image.height as usize) {
-                    Some(a) => break a,
-                    None => {
-                        if !atlas.grow(
-                            device,
-                            font_system,
-                            cache,
-                            image.content_type,
-                            scale_factor,
-                            &mut rasterize_custom_glyph,
-                        ) {
-                            return Err(PrepareError::AtlasFull);
-                        }
+    cached
+}
 
-                        inner = atlas.inner_for_content_mut(image.content_type);
-                    }
+/// Rasterizes the glyph of `cache_key` with the raster config of the key, or returns `None` if it
+/// has no image.
+fn glyph_image(
+    cache_key: GlyphonCacheKey,
+    scale: f32,
+    cache: &mut SwashCache,
+    font_system: &mut FontSystem,
+    strike_policy: BitmapStrikePolicy,
+    mut rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
+) -> Option<GetGlyphImageResult> {
+    match cache_key {
+        GlyphonCacheKey::Text(cache_key, raster_key) => {
+            let rasterize::StrikeImage { image, substituted } = rasterize::text_glyph(
+                cache,
+                font_system,
+                cache_key,
+                strike_policy,
+                raster_key.config(),
+            )?;
+
+            let content_type = match image.content {
+                SwashContent::Color => ContentType::Color,
+                SwashContent::Mask => ContentType::Mask,
+                SwashContent::SubpixelMask => {
+                    // Not implemented yet, but don't panic if this happens.
+                    ContentType::Mask
                 }
             };
-            let atlas_min = allocation.rectangle.min;
 
-            unsafe {
-                inner
-                    .texture
-                    .replaceRegion_mipmapLevel_withBytes_bytesPerRow(
-                        MTLRegion {
-                            origin: MTLOrigin {
-                                x: atlas_min.x as usize,
-                                y: atlas_min.y as usize,
-                                z: 0,
-                            },
-                            size: MTLSize {
-                                width: image.width as usize,
-                                height: image.height as usize,
-                                depth: 1,
-                            },
-                        },
-                        0,
-                        NonNull::from(image.data.as_slice()).cast(),
-                        image.width as usize * inner.num_channels(),
-                    );
+            Some(GetGlyphImageResult {
+                content_type,
+                top: image.placement.top as i16,
+                left: image.placement.left as i16,
+                width: image.placement.width as u16,
+                height: image.placement.height as u16,
+                data: image.data,
+                strike_policy,
+                strike_substituted: substituted,
+            })
+        }
+        GlyphonCacheKey::Custom(cache_key) => {
+            if cache_key.width == 0 || cache_key.height == 0 {
+                return None;
             }
 
-            (
-                GpuCacheStatus::InAtlas {
-                    x: atlas_min.x as u16,
-                    y: atlas_min.y as u16,
-                    content_type: image.content_type,
-                },
-                Some(allocation.id),
-                inner,
-            )
-        } else {
-            let inner = &mut atlas.color_atlas;
-            (GpuCacheStatus::SkipRasterization, None, inner)
+            let input = RasterizeCustomGlyphRequest {
+                id: cache_key.glyph_id,
+                width: cache_key.width,
+                height: cache_key.height,
+                x_bin: cache_key.x_bin,
+                y_bin: cache_key.y_bin,
+                scale,
+            };
+
+            let output = (rasterize_custom_glyph)(input)?;
+
+            output.validate(&input, None);
+
+            Some(GetGlyphImageResult {
+                content_type: output.content_type,
+                top: 0,
+                left: 0,
+                width: cache_key.width,
+                height: cache_key.height,
+                data: output.data,
+                strike_policy: BitmapStrikePolicy::default(),
+                strike_substituted: false,
+            })
+        }
+        GlyphonCacheKey::Tofu(key) => {
+            if key.width() == 0 || key.height == 0 {
+                return None;
+            }
+
+            // The box stands on the baseline
+            Some(GetGlyphImageResult {
+                content_type: ContentType::Mask,
+                top: key.height as i16,
+                left: key.inset() as i16,
+                width: key.width(),
+                height: key.height,
+                data: key.image(),
+                strike_policy: BitmapStrikePolicy::default(),
+                strike_substituted: false,
+            })
+        }
+    }
+}
+
+/// Allocates `image` in its atlas, growing the atlas if needed, and caches the glyph.
+fn cache_glyph(
+    cache_key: GlyphonCacheKey,
+    image: GetGlyphImageResult,
+    scale_factor: f32,
+    atlas: &mut TextAtlas,
+    cache: &mut SwashCache,
+    font_system: &mut FontSystem,
+    mut rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
+    profiler: &mut Profiler,
+) -> Result<(), PrepareError> {
+    let should_rasterize = image.width > 0 && image.height > 0;
+
+    let (gpu_cache, atlas_id, inner) = if should_rasterize {
+        let atlas_upload = profiler.start();
+        let mut inner = atlas.inner_for_content_mut(image.content_type);
+
+        // Find a position in the packer
+        let allocation = loop {
+            match inner.allocate(image.width as usize, image.height as usize) {
+                AllocationStep::Allocated(allocation) => break allocation,
+                AllocationStep::Grow(new_size) => {
+                    #[cfg(feature = "signposts")]
+                    let _interval = signpost::interval(signpost::Name::GrowAtlas);
+
+                    atlas.grow(
+                        font_system,
+                        cache,
+                        image.content_type,
+                        new_size,
+                        scale_factor,
+                        &mut rasterize_custom_glyph,
+                    )?;
+                    inner = atlas.inner_for_content_mut(image.content_type);
+                }
+                AllocationStep::Full => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(
+                        content_type = ?image.content_type,
+                        width = image.width,
+                        height = image.height,
+                        "failed to allocate glyph, the atlas is full"
+                    );
+
+                    return Err(PrepareError::AtlasFull);
+                }
+            }
         };
+        let atlas_min = allocation.rectangle.min;
+
+        // Written into the texture by `TextAtlas::flush_uploads` at the end of `prepare`
+        inner.push_upload(
+            atlas_min.x as usize,
+            atlas_min.y as usize,
+            image.width as usize,
+            image.height as usize,
+            image.data,
+        );
+
+        profiler.record(Phase::AtlasUpload, atlas_upload);
+
+        inner.counters.record_rasterization(&cache_key);
+        if image.strike_substituted {
+            inner.counters.strike_substitutions += 1;
+        }
+
+        (
+            GpuCacheStatus::InAtlas {
+                x: atlas_min.x as u16,
+                y: atlas_min.y as u16,
+                content_type: image.content_type,
+            },
+            Some(allocation.id),
+            inner,
+        )
+    } else {
+        let inner = &mut atlas.color_atlas;
+        (GpuCacheStatus::SkipRasterization, None, inner)
+    };
 
-        inner.glyphs_in_use.insert(cache_key);
-        // Insert the glyph into the cache and return the details reference
-        inner.glyph_cache.get_or_insert(cache_key, || GlyphDetails {
+    inner.counters.misses += 1;
+    inner.allocator.glyph_cache.put(
+        cache_key,
+        GlyphDetails {
             width: image.width,
             height: image.height,
             gpu_cache,
             atlas_id,
             top: image.top,
             left: image.left,
-        })
-    };
+            strike_policy: image.strike_policy,
+            generation: next_generation(),
+        },
+    );
+
+    Ok(())
+}
+
+/// Returns the vertex of the cached glyph of `cache_key` at `position`, clipped to the bounds of
+/// its area, or `None` if the glyph is empty or outside of the bounds.
+fn glyph_vertex(
+    position: &GlyphPosition,
+    cache_key: GlyphonCacheKey,
+    atlas: &mut TextAtlas,
+    mut metadata_to_depth: impl FnMut(usize) -> f32,
+    empty_glyphs: &mut HashSet<GlyphonCacheKey, Hasher>,
+    drops: &mut DropTracker,
+    profiler: &mut Profiler,
+) -> Option<GlyphToRender> {
+    let vertex_generation = profiler.start();
+
+    let details = atlas
+        .mask_atlas
+        .allocator
+        .glyph_cache
+        .peek(&cache_key)
+        .or_else(|| atlas.color_atlas.allocator.glyph_cache.peek(&cache_key))
+        .expect("glyph is cached");
+    let origin = [
+        position.x,
+        (position.line_y * position.scale).round() as i32 + position.y,
+    ];
+    let mut x = origin[0] + details.left as i32;
+    let mut y = origin[1] - details.top as i32;
+    let mut width = details.width as i32;
+    let mut height = details.height as i32;
 
-    let mut x = x + details.left as i32;
-    let mut y = (line_y * scale_factor).round() as i32 + y - details.top as i32;
+    // Transformed quads are culled and clipped against the bounds mapped back onto the
+    // untransformed quad
+    let quad_transform =
+        (position.transform.moves_quad() || position.raster_scale != 1.0).then(|| {
+            QuadTransform::new(
+                &position.transform,
+                origin,
+                [x, y, width, height],
+                position.raster_scale,
+            )
+        });
+    let quad_bounds = match &quad_transform {
+        Some(quad_transform) => quad_transform.quad_bounds(position.bounds, [x, y, width, height]),
+        None => Some(position.bounds),
+    };
 
     let (mut atlas_x, mut atlas_y, content_type) = match details.gpu_cache {
         GpuCacheStatus::InAtlas { x, y, content_type } => (x, y, content_type),
-        GpuCacheStatus::SkipRasterization => return Ok(None),
+        GpuCacheStatus::SkipRasterization => {
+            // Glyphs without content are cached in the color atlas
+            atlas.color_atlas.allocator.glyphs_in_use.insert(cache_key);
+            empty_glyphs.insert(cache_key);
+            drops.record(cache_key, DropReason::Empty);
+            profiler.record(Phase::VertexGeneration, vertex_generation);
+            return None;
+        }
     };
 
-    let mut width = details.width as i32;
-    let mut height = details.height as i32;
+    let inner = atlas.inner_for_content_mut(content_type);
 
-    // Starts beyond right edge or ends beyond left edge
+    // Glyphs entirely outside of the bounds (or only touching an edge, which would clip them to
+    // an empty quad) are culled, and so are glyphs scaled to nothing. They stay cached but are not
+    // kept in use, so that invisible glyphs can be evicted.
     let max_x = x + width;
-    if x > bounds_max_x || max_x < bounds_min_x {
-        return Ok(None);
-    }
-
-    // Starts beyond bottom edge or ends beyond top edge
     let max_y = y + height;
-    if y > bounds_max_y || max_y < bounds_min_y {
-        return Ok(None);
+    let visible_bounds = quad_bounds.filter(|&[left, top, right, bottom]| {
+        x < right && left < max_x && y < bottom && top < max_y
+    });
+    let Some([bounds_min_x, bounds_min_y, bounds_max_x, bounds_max_y]) = visible_bounds else {
+        inner.counters.culled += 1;
+        drops.record(cache_key, DropReason::Culled);
+        profiler.record(Phase::VertexGeneration, vertex_generation);
+        return None;
+    };
+
+    inner.allocator.glyphs_in_use.insert(cache_key);
+    if position.pinned {
+        inner.allocator.pinned.insert(cache_key);
     }
 
     // Clip left ege
@@ -570,20 +3876,308 @@ where
         height = bounds_max_y - y;
     }
 
-    let depth = metadata_to_depth(metadata);
+    // Only glyphs as large as the largest atlas textures are cut, by a pixel
+    width = width.min(MAX_QUAD_SIZE);
+    height = height.min(MAX_QUAD_SIZE);
+
+    let depth = metadata_to_depth(position.metadata);
+
+    // Colors are specified in sRGB, and converted for targets that store linear colors
+    let color_conversion = if is_linear_format(atlas.pixel_format) {
+        TextColorConversion::ConvertToLinear
+    } else {
+        TextColorConversion::None
+    };
+    let display_p3 = atlas.color_space == TargetColorSpace::DisplayP3;
+    let srgb_atlas = atlas.color_mode == ColorMode::Accurate;
+
+    let (pos, transform) = match &quad_transform {
+        Some(quad_transform) => quad_transform.vertex_placement([x, y, width, height]),
+        None => ([x, y], QUAD_IDENTITY),
+    };
 
-    Ok(Some(GlyphToRender {
-        pos: [x, y],
-        dim: [width as u16, height as u16],
-        uv: [atlas_x, atlas_y],
-        color: color.0,
-        content_type_with_srgb: [
-            content_type as u16,
-            match atlas.color_mode {
-                ColorMode::Accurate => TextColorConversion::ConvertToLinear,
-                ColorMode::Web => TextColorConversion::None,
-            } as u16,
+    profiler.record(Phase::VertexGeneration, vertex_generation);
+
+    Some(GlyphToRender {
+        pos,
+        dim: [
+            width as u16 | (content_type as u16 * FLAG_BIT),
+            height as u16 | (color_conversion as u16 * FLAG_BIT),
+        ],
+        uv: [
+            atlas_x | (display_p3 as u16 * FLAG_BIT),
+            atlas_y | (srgb_atlas as u16 * FLAG_BIT),
         ],
+        color: position.color.0,
         depth,
-    }))
+        transform,
+        background: position.background,
+    })
+}
+
+/// Encodes the [`TextArea::background_hint`] of an area for [`GlyphToRender`]: the sRGB-encoded
+/// luminance of `hint` with bit 8 set, or `0` without a hint.
+fn encode_background_hint(hint: Option<Color>) -> u32 {
+    let Some(hint) = hint else {
+        return 0;
+    };
+
+    let to_linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let luminance =
+        0.2126 * to_linear(hint.r()) + 0.7152 * to_linear(hint.g()) + 0.0722 * to_linear(hint.b());
+    let encoded = if luminance <= 0.0031308 {
+        luminance * 12.92
+    } else {
+        1.055 * luminance.powf(1.0 / 2.4) - 0.055
+    };
+
+    0x100 | (encoded * 255.0).round().clamp(0.0, 255.0) as u32
+}
+
+/// Returns an ID that no other renderer has.
+fn next_renderer_id() -> u64 {
+    static NEXT_RENDERER_ID: AtomicU64 = AtomicU64::new(0);
+
+    NEXT_RENDERER_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Returns the key of the text glyph of `cache_key` with its size limited to `max_size`, and the
+/// factor the glyph is rasterized smaller by, see [`TextRenderer::set_max_glyph_size`].
+///
+/// Glyphs rasterized smaller are scaled up with their quads, which would scale up their subpixel
+/// offsets too, so they are placed at whole pixels.
+fn cap_glyph_size(cache_key: cosmic_text::CacheKey, max_size: f32) -> (cosmic_text::CacheKey, f32) {
+    let size = f32::from_bits(cache_key.font_size_bits);
+    if size <= max_size || max_size.is_nan() || max_size <= 0.0 {
+        return (cache_key, 1.0);
+    }
+
+    let cache_key = cosmic_text::CacheKey {
+        font_size_bits: max_size.to_bits(),
+        x_bin: SubpixelBin::Zero,
+        y_bin: SubpixelBin::Zero,
+        ..cache_key
+    };
+
+    (cache_key, size / max_size)
+}
+
+/// Returns the key of the atlas glyph of the text glyph at `placement`, and the factor the glyph
+/// is rasterized smaller by, see [`cap_glyph_size`]. `embolden` is the strength of synthetic bold
+/// added to `raster_config`, as a fraction of the size the glyph is rasterized at.
+fn text_glyph_key(
+    font_system: &mut FontSystem,
+    placement: &GlyphPlacement,
+    max_glyph_size: f32,
+    raster_config: GlyphRasterConfig,
+    embolden: f32,
+    tofu: bool,
+) -> (GlyphonCacheKey, f32) {
+    let (text_key, raster_scale) = cap_glyph_size(placement.cache_key, max_glyph_size);
+    let raster_key = if embolden > 0.0 {
+        GlyphRasterConfig {
+            embolden: raster_config.embolden + embolden * f32::from_bits(text_key.font_size_bits),
+            ..raster_config
+        }
+        .key()
+    } else {
+        raster_config.key()
+    };
+
+    let cache_key = if tofu && text_key.glyph_id == 0 {
+        GlyphonCacheKey::Tofu(TofuKey::new(
+            font_system,
+            text_key,
+            placement.advance / raster_scale,
+        ))
+    } else {
+        GlyphonCacheKey::Text(text_key, raster_key)
+    };
+
+    (cache_key, raster_scale)
+}
+
+/// Places the custom `glyph` of an area at the physical `offset` with the physical `scale`.
+/// Returns its physical position without the subpixel offset, and the key of its atlas glyph.
+fn place_custom_glyph(
+    glyph: &CustomGlyph,
+    offset: (f32, f32),
+    scale: f32,
+) -> (i32, i32, GlyphonCacheKey) {
+    let x = offset.0 + (glyph.left * scale);
+    let y = offset.1 + (glyph.top * scale);
+    let width = (glyph.width * scale).round() as u16;
+    let height = (glyph.height * scale).round() as u16;
+
+    let (x, y, x_bin, y_bin) = if glyph.snap_to_physical_pixel {
+        (
+            x.round() as i32,
+            y.round() as i32,
+            SubpixelBin::Zero,
+            SubpixelBin::Zero,
+        )
+    } else {
+        let (x, x_bin) = SubpixelBin::new(x);
+        let (y, y_bin) = SubpixelBin::new(y);
+        (x, y, x_bin, y_bin)
+    };
+
+    let cache_key = GlyphonCacheKey::Custom(CustomGlyphCacheKey {
+        glyph_id: glyph.id,
+        width,
+        height,
+        x_bin,
+        y_bin,
+    });
+
+    (x, y, cache_key)
+}
+
+/// The affine transform of a [`GlyphTransform`] in physical pixels, moving each point `p` of a
+/// quad to `moved_pivot + linear * (p - pivot)`, where `linear` scales and rotates.
+struct QuadTransform {
+    pivot: [f32; 2],
+    moved_pivot: [f32; 2],
+    /// The scale times the cosine and the sine of the rotation.
+    linear: [f32; 2],
+}
+
+impl QuadTransform {
+    /// Returns the transform of the quad `[x, y, width, height]` of a glyph whose origin is at
+    /// `origin`, and which was rasterized `raster_scale` times smaller than it is drawn.
+    fn new(
+        transform: &GlyphTransform,
+        origin: [i32; 2],
+        quad: [i32; 4],
+        raster_scale: f32,
+    ) -> Self {
+        let [x, y, width, height] = quad.map(|value| value as f32);
+        let origin = origin.map(|value| value as f32);
+        let center = [x + width / 2.0, y + height / 2.0];
+        let pivot = match transform.pivot {
+            Some((pivot_x, pivot_y)) => [origin[0] + pivot_x, origin[1] + pivot_y],
+            None if raster_scale != 1.0 => {
+                [0, 1].map(|axis| origin[axis] + (center[axis] - origin[axis]) * raster_scale)
+            }
+            None => center,
+        };
+        let (sin, cos) = transform.rotation.sin_cos();
+        let moved_pivot = [pivot[0] + transform.offset.0, pivot[1] + transform.offset.1];
+        let linear = [transform.scale * cos, transform.scale * sin];
+
+        if raster_scale == 1.0 {
+            return Self {
+                pivot,
+                moved_pivot,
+                linear,
+            };
+        }
+
+        // The quad is first scaled up around the origin, then transformed: the origin is the
+        // pivot of both
+        let [cos, sin] = linear;
+        let (dx, dy) = (origin[0] - pivot[0], origin[1] - pivot[1]);
+
+        Self {
+            pivot: origin,
+            moved_pivot: [
+                moved_pivot[0] + cos * dx - sin * dy,
+                moved_pivot[1] + sin * dx + cos * dy,
+            ],
+            linear: linear.map(|value| value * raster_scale),
+        }
+    }
+
+    fn scale(&self) -> f32 {
+        self.linear[0].hypot(self.linear[1])
+    }
+
+    fn is_rotated(&self) -> bool {
+        self.linear[1] != 0.0 || self.linear[0] < 0.0
+    }
+
+    fn apply(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let [cos, sin] = self.linear;
+        let (dx, dy) = (x - self.pivot[0], y - self.pivot[1]);
+
+        [
+            self.moved_pivot[0] + cos * dx - sin * dy,
+            self.moved_pivot[1] + sin * dx + cos * dy,
+        ]
+    }
+
+    /// Returns the physical `bounds` mapped back onto the untransformed quad, rounded out to
+    /// whole texels, or `None` if the transformed quad is invisible.
+    ///
+    /// Rotated quads cannot be clipped to their bounds, they are only culled if the rectangle
+    /// covering them is outside of the bounds.
+    fn quad_bounds(&self, bounds: [i32; 4], [x, y, width, height]: [i32; 4]) -> Option<[i32; 4]> {
+        let scale = self.scale();
+        if scale.is_nan() || scale <= 0.0 {
+            return None;
+        }
+
+        let [min_x, min_y, max_x, max_y] = bounds.map(|edge| edge as f32);
+
+        if self.is_rotated() {
+            let corners = [
+                [x, y],
+                [x + width, y],
+                [x, y + height],
+                [x + width, y + height],
+            ]
+            .map(|[x, y]| self.apply([x as f32, y as f32]));
+            let covers = |axis: usize, min: f32, max: f32| {
+                corners.iter().any(|corner| corner[axis] > min)
+                    && corners.iter().any(|corner| corner[axis] < max)
+            };
+
+            return (covers(0, min_x, max_x) && covers(1, min_y, max_y)).then_some([
+                i32::MIN,
+                i32::MIN,
+                i32::MAX,
+                i32::MAX,
+            ]);
+        }
+
+        let untransformed =
+            |edge: f32, axis: usize| self.pivot[axis] + (edge - self.moved_pivot[axis]) / scale;
+
+        Some([
+            untransformed(min_x, 0).floor() as i32,
+            untransformed(min_y, 1).floor() as i32,
+            untransformed(max_x, 0).ceil() as i32,
+            untransformed(max_y, 1).ceil() as i32,
+        ])
+    }
+
+    /// Returns the position and the transform of the vertex of the clipped quad
+    /// `[x, y, width, height]`.
+    ///
+    /// The shader scales and rotates the quad around its own center, which is moved to where the
+    /// transform puts it. The whole pixels of the center, less half the size of the quad, go into
+    /// the position and the fraction into the transform.
+    fn vertex_placement(&self, [x, y, width, height]: [i32; 4]) -> ([i32; 2], [u16; 4]) {
+        let (half_width, half_height) = (width as f32 / 2.0, height as f32 / 2.0);
+        let [center_x, center_y] = self.apply([x as f32 + half_width, y as f32 + half_height]);
+        let (anchor_x, anchor_y) = (center_x - half_width, center_y - half_height);
+        let (pos_x, pos_y) = (anchor_x.floor(), anchor_y.floor());
+
+        (
+            [pos_x as i32, pos_y as i32],
+            [
+                f16_bits(self.linear[0]),
+                f16_bits(self.linear[1]),
+                f16_bits(anchor_x - pos_x),
+                f16_bits(anchor_y - pos_y),
+            ],
+        )
+    }
 }
@@ -0,0 +1,49 @@
+/// Which glyphs [`crate::TextAtlas::trim`] releases, see [`crate::TextAtlas::set_trim_policy`].
+///
+/// Glyphs used by a `prepare` are in use, and are never evicted to make room for other glyphs:
+/// the atlas grows instead. Trimming releases glyphs so that they can be evicted again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TrimPolicy {
+    /// Releases every glyph, so that only the glyphs of the current frame are kept from being
+    /// evicted. This suits most apps, e.g. a game HUD whose text changes all the time.
+    #[default]
+    EveryFrame,
+    /// Keeps glyphs in use for this many trims after the last `prepare` that used them, so that
+    /// text that disappears for a few frames (e.g. a blinking label) is not rasterized again.
+    ///
+    /// `KeepFrames(0)` behaves like [`TrimPolicy::EveryFrame`].
+    KeepFrames(u32),
+    /// Releases every glyph like [`TrimPolicy::EveryFrame`], then evicts the least recently used
+    /// glyphs until the texels of the cached glyphs take up at most this many bytes per atlas,
+    /// freeing their space in the atlas.
+    ///
+    /// The atlas textures are not shrunk, but the budget keeps them from growing while the
+    /// glyphs of a frame fit. Trimming iterates over all cached glyphs.
+    ByteBudget(usize),
+    /// Never releases glyphs, so that they are kept until trimmed explicitly with
+    /// [`crate::TextAtlas::trim_with`], e.g. when an editor closes a document.
+    ///
+    /// The atlas grows with every new glyph until `prepare` fails with
+    /// [`crate::PrepareError::AtlasFull`].
+    Manual,
+}
+
+/// How a [`crate::TextArea`] keeps its glyphs in the atlas, e.g. to keep the labels of menus and
+/// toolbars from being evicted by the text of a long document scrolling by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CachePriority {
+    /// The glyphs are evicted once they are no longer in use, least recently used first.
+    #[default]
+    Normal,
+    /// The glyphs are pinned in the atlas: they are only evicted when a glyph would not fit in
+    /// the atlas otherwise, even once grown to its maximum size, and are kept by
+    /// [`TrimPolicy::ByteBudget`] over the budget.
+    ///
+    /// Glyphs stay pinned until [`crate::TextAtlas::unpin`] is called, so pin only a small, fixed
+    /// set of glyphs. [`crate::AtlasStats::pinned_area`] tells how much of the atlas they take up.
+    Pinned,
+}
@@ -0,0 +1,100 @@
+use crate::{Attrs, Buffer, FontSystem, Shaping};
+
+/// Clamps the text of `buffer` to at most `max_lines` laid-out lines, ending the last one with
+/// `ellipsis` (e.g. `"…"`) if any text was cut. Returns `true` if the text was truncated.
+///
+/// The longest prefix of the text whose last line still fits the buffer width with the ellipsis
+/// appended is found by shaping candidates with cosmic-text, so the cut follows the wrapping of
+/// the buffer. Text is only cut at the boundaries of glyph clusters, so emoji sequences and
+/// combining marks are never split, and the prefix is the logical one, which ends on the left of
+/// right-to-left lines. Whitespace before the ellipsis is trimmed.
+///
+/// Text that already fits is left untouched. Otherwise, the text of the buffer is replaced with
+/// the truncated text in `attrs`, which drops rich text attributes. If not even the ellipsis fits
+/// on the last line, the text is cut at the end of the last line without an ellipsis.
+///
+/// Every line is shaped to count lines, so the height of the buffer should be `None`.
+pub fn truncate_lines(
+    font_system: &mut FontSystem,
+    buffer: &mut Buffer,
+    max_lines: usize,
+    ellipsis: &str,
+    attrs: &Attrs,
+    shaping: Shaping,
+) -> bool {
+    buffer.shape_until_scroll(font_system, false);
+
+    if buffer.layout_runs().count() <= max_lines {
+        return false;
+    }
+
+    if max_lines == 0 {
+        buffer.set_text(font_system, "", attrs, shaping);
+        return true;
+    }
+
+    let lines: Vec<String> = buffer
+        .lines
+        .iter()
+        .map(|line| line.text().to_owned())
+        .collect();
+
+    // Candidate cuts within the last line that is kept, at cluster boundaries
+    let (line_i, cuts) = {
+        let run = buffer
+            .layout_runs()
+            .nth(max_lines - 1)
+            .expect("Buffer has more than `max_lines` layout runs");
+        let start = run
+            .glyphs
+            .iter()
+            .map(|glyph| glyph.start)
+            .min()
+            .unwrap_or(0);
+
+        let mut cuts: Vec<usize> = std::iter::once(start)
+            .chain(run.glyphs.iter().map(|glyph| glyph.end))
+            .collect();
+        cuts.sort_unstable();
+        cuts.dedup();
+
+        (run.line_i, cuts)
+    };
+
+    let truncated_text = |cut: usize, suffix: &str| {
+        let mut text: String = lines[..line_i]
+            .iter()
+            .flat_map(|line| [line.as_str(), "\n"])
+            .collect();
+        text.push_str(lines[line_i][..cut].trim_end());
+        text.push_str(suffix);
+        text
+    };
+
+    let mut fits = |text: &str| {
+        buffer.set_text(font_system, text, attrs, shaping);
+        buffer.shape_until_scroll(font_system, false);
+        buffer.layout_runs().count() <= max_lines
+    };
+
+    if !fits(&truncated_text(cuts[0], ellipsis)) {
+        fits(&truncated_text(cuts[cuts.len() - 1], ""));
+        return true;
+    }
+
+    // Binary search for the last cut that fits, `cuts[low]` always fits
+    let (mut low, mut high) = (0, cuts.len() - 1);
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+
+        if fits(&truncated_text(cuts[mid], ellipsis)) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    fits(&truncated_text(cuts[low], ellipsis));
+
+    true
+}
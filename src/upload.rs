@@ -0,0 +1,528 @@
+//! Batches the glyph uploads of a `prepare` into few texture writes, or blit copies.
+
+use objc2::{rc::Retained, runtime::ProtocolObject, Message as _};
+use objc2_metal::{
+    MTLBlitCommandEncoder, MTLBuffer, MTLDevice as _, MTLOrigin, MTLRegion, MTLResource as _,
+    MTLResourceOptions, MTLSize, MTLTexture,
+};
+use std::{mem, ptr::NonNull, slice};
+
+/// Glyph bitmaps waiting to be written into an atlas texture.
+///
+/// Without a shadow, uploads are queued in rasterization order, and consecutive glyphs that the
+/// packer placed side by side on the same row with the same height are written with a single
+/// `replaceRegion`. With a shadow (a CPU copy of the whole texture), bitmaps are written into the
+/// shadow right away and each band of rows touched by new glyphs is written at once, re-writing
+/// the untouched texels in between from the shadow.
+///
+/// With blit uploads, the writes of a flush are recorded as copies out of a staging buffer
+/// instead, which [`UploadQueue::encode`] encodes into a blit command encoder.
+#[derive(Default)]
+pub(crate) struct UploadQueue {
+    pending: Vec<PendingUpload>,
+    shadow: Option<Shadow>,
+    /// The copies recorded by flushes, if uploads are blitted.
+    blit: Option<BlitUploads>,
+    /// Rows of coalesced uploads, reused across flushes.
+    staging: Vec<u8>,
+    /// The number of `replaceRegion` calls (or recorded copies) made by flushes.
+    pub texture_writes: u64,
+    /// The number of bytes written by flushes.
+    pub uploaded_bytes: u64,
+}
+
+struct PendingUpload {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+/// Copies recorded by flushes, waiting to be encoded into a blit command encoder.
+#[derive(Default)]
+struct BlitUploads {
+    /// The texels of the copies of the flush in progress, with tightly packed rows.
+    texels: Vec<u8>,
+    /// The copies of the flush in progress, as offsets into `texels` and texture rectangles.
+    copies: Vec<(usize, [usize; 4])>,
+    /// The copies of previous flushes, in order.
+    batches: Vec<BlitBatch>,
+    /// Whether the device failed to create a staging buffer, which drops the copies of the
+    /// flush.
+    device_lost: bool,
+}
+
+/// The copies of a flush out of a staging buffer into a texture.
+struct BlitBatch {
+    staging: Retained<ProtocolObject<dyn MTLBuffer>>,
+    texture: Retained<ProtocolObject<dyn MTLTexture>>,
+    copies: Vec<(usize, [usize; 4])>,
+    num_channels: usize,
+}
+
+impl BlitBatch {
+    fn staging_bytes(&self) -> &[u8] {
+        // SAFETY: The staging buffer has shared storage and is never written after its creation
+        unsafe {
+            slice::from_raw_parts(
+                self.staging.contents().as_ptr().cast::<u8>(),
+                self.staging.length(),
+            )
+        }
+    }
+}
+
+impl BlitUploads {
+    /// Records a copy of the `rect` region of `bytes`, whose rows are `bytes_per_row` apart.
+    fn record(
+        &mut self,
+        rect: [usize; 4],
+        bytes: &[u8],
+        bytes_per_row: usize,
+        num_channels: usize,
+    ) {
+        let [_, _, width, height] = rect;
+        let row_bytes = width * num_channels;
+
+        self.copies.push((self.texels.len(), rect));
+        for row in 0..height {
+            let start = row * bytes_per_row;
+            self.texels
+                .extend_from_slice(&bytes[start..start + row_bytes]);
+        }
+    }
+
+    /// Moves the copies of the flush in progress into a staging buffer.
+    fn finish_flush(&mut self, texture: &ProtocolObject<dyn MTLTexture>, num_channels: usize) {
+        if self.copies.is_empty() {
+            return;
+        }
+
+        let staging = unsafe {
+            texture.device().newBufferWithBytes_length_options(
+                NonNull::from(self.texels.as_slice()).cast(),
+                self.texels.len(),
+                MTLResourceOptions::StorageModeShared,
+            )
+        };
+        let Some(staging) = staging else {
+            self.device_lost = true;
+            self.copies.clear();
+            self.texels.clear();
+            return;
+        };
+
+        self.batches.push(BlitBatch {
+            staging,
+            texture: texture.retain(),
+            copies: mem::take(&mut self.copies),
+            num_channels,
+        });
+        self.texels.clear();
+    }
+}
+
+struct Shadow {
+    size: usize,
+    texels: Vec<u8>,
+    /// Rectangles written since the last flush, as `[min_x, min_y, max_x, max_y]`.
+    dirty: Vec<[usize; 4]>,
+}
+
+impl UploadQueue {
+    /// Queues the `width` x `height` bitmap `data` for the texture region at `x`, `y`.
+    pub fn push(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        data: Vec<u8>,
+        num_channels: usize,
+    ) {
+        let Some(shadow) = &mut self.shadow else {
+            self.pending.push(PendingUpload {
+                x,
+                y,
+                width,
+                height,
+                data,
+            });
+            return;
+        };
+
+        let row_bytes = width * num_channels;
+        for row in 0..height {
+            let start = ((y + row) * shadow.size + x) * num_channels;
+            shadow.texels[start..start + row_bytes]
+                .copy_from_slice(&data[row * row_bytes..(row + 1) * row_bytes]);
+        }
+
+        shadow.dirty.push([x, y, x + width, y + height]);
+    }
+
+    /// Writes all queued uploads into `texture`.
+    pub fn flush(&mut self, texture: &ProtocolObject<dyn MTLTexture>, num_channels: usize) {
+        if let Some(shadow) = &mut self.shadow {
+            if shadow.dirty.is_empty() {
+                return;
+            }
+
+            // Merge the dirty rectangles into bands of rows, which roughly follow the shelves
+            // of the packer
+            shadow.dirty.sort_unstable_by_key(|rect| rect[1]);
+
+            let mut bands = mem::take(&mut shadow.dirty);
+            let mut merged = 0;
+            for i in 1..bands.len() {
+                let rect = bands[i];
+                let band = &mut bands[merged];
+
+                if rect[1] <= band[3] {
+                    band[0] = band[0].min(rect[0]);
+                    band[2] = band[2].max(rect[2]);
+                    band[3] = band[3].max(rect[3]);
+                } else {
+                    merged += 1;
+                    bands[merged] = rect;
+                }
+            }
+            bands.truncate(merged + 1);
+
+            for &[min_x, min_y, max_x, max_y] in &bands {
+                let start = (min_y * shadow.size + min_x) * num_channels;
+
+                write_region(
+                    texture,
+                    [min_x, min_y, max_x - min_x, max_y - min_y],
+                    &shadow.texels[start..],
+                    shadow.size * num_channels,
+                    self.blit.as_mut(),
+                    num_channels,
+                );
+                self.texture_writes += 1;
+                self.uploaded_bytes += ((max_x - min_x) * (max_y - min_y) * num_channels) as u64;
+            }
+
+            bands.clear();
+            shadow.dirty = bands;
+
+            if let Some(blit) = &mut self.blit {
+                blit.finish_flush(texture, num_channels);
+            }
+            return;
+        }
+
+        let mut pending = mem::take(&mut self.pending);
+        let mut run_start = 0;
+
+        for i in 0..pending.len() {
+            let upload = &pending[i];
+            let continues_run = pending.get(i + 1).is_some_and(|next| {
+                next.y == upload.y
+                    && next.height == upload.height
+                    && next.x == upload.x + upload.width
+            });
+
+            if continues_run {
+                continue;
+            }
+
+            let run = &pending[run_start..=i];
+            run_start = i + 1;
+
+            if let [upload] = run {
+                write_region(
+                    texture,
+                    [upload.x, upload.y, upload.width, upload.height],
+                    &upload.data,
+                    upload.width * num_channels,
+                    self.blit.as_mut(),
+                    num_channels,
+                );
+                self.uploaded_bytes += upload.data.len() as u64;
+            } else {
+                let width: usize = run.iter().map(|upload| upload.width).sum();
+                let height = run[0].height;
+
+                self.staging.clear();
+                for row in 0..height {
+                    for upload in run {
+                        let row_bytes = upload.width * num_channels;
+                        self.staging.extend_from_slice(
+                            &upload.data[row * row_bytes..(row + 1) * row_bytes],
+                        );
+                    }
+                }
+
+                write_region(
+                    texture,
+                    [run[0].x, run[0].y, width, height],
+                    &self.staging,
+                    width * num_channels,
+                    self.blit.as_mut(),
+                    num_channels,
+                );
+                self.uploaded_bytes += self.staging.len() as u64;
+            }
+            self.texture_writes += 1;
+        }
+
+        pending.clear();
+        self.pending = pending;
+
+        if let Some(blit) = &mut self.blit {
+            blit.finish_flush(texture, num_channels);
+        }
+    }
+
+    /// Sets whether flushes record copies for [`UploadQueue::encode`] instead of writing into
+    /// the texture. Copies that were not encoded when disabling them are written right away.
+    pub fn set_blit(&mut self, enabled: bool) {
+        if enabled {
+            self.blit.get_or_insert_with(BlitUploads::default);
+            return;
+        }
+
+        let Some(blit) = self.blit.take() else {
+            return;
+        };
+
+        for batch in &blit.batches {
+            let staging = batch.staging_bytes();
+
+            for &(offset, rect) in &batch.copies {
+                let bytes_per_row = rect[2] * batch.num_channels;
+                write_region(
+                    &batch.texture,
+                    rect,
+                    &staging[offset..],
+                    bytes_per_row,
+                    None,
+                    batch.num_channels,
+                );
+            }
+        }
+    }
+
+    pub fn has_blit(&self) -> bool {
+        self.blit.is_some()
+    }
+
+    /// Returns whether a flush failed to create a staging buffer for its copies, because the
+    /// device was lost.
+    pub fn device_lost(&self) -> bool {
+        self.blit.as_ref().is_some_and(|blit| blit.device_lost)
+    }
+
+    /// Returns whether copies recorded by flushes are waiting to be encoded.
+    pub fn has_pending_copies(&self) -> bool {
+        self.blit
+            .as_ref()
+            .is_some_and(|blit| !blit.batches.is_empty())
+    }
+
+    /// Encodes the recorded copies into `blit_encoder` in the order they were recorded, and
+    /// forgets them.
+    pub fn encode(&mut self, blit_encoder: &ProtocolObject<dyn MTLBlitCommandEncoder>) {
+        let Some(blit) = &mut self.blit else {
+            return;
+        };
+
+        for batch in blit.batches.drain(..) {
+            for (offset, [x, y, width, height]) in batch.copies {
+                let bytes_per_row = width * batch.num_channels;
+
+                unsafe {
+                    blit_encoder.copyFromBuffer_sourceOffset_sourceBytesPerRow_sourceBytesPerImage_sourceSize_toTexture_destinationSlice_destinationLevel_destinationOrigin(
+                        &batch.staging,
+                        offset,
+                        bytes_per_row,
+                        bytes_per_row * height,
+                        MTLSize {
+                            width,
+                            height,
+                            depth: 1,
+                        },
+                        &batch.texture,
+                        0,
+                        0,
+                        MTLOrigin { x, y, z: 0 },
+                    );
+                }
+            }
+        }
+    }
+
+    /// Drops the queued uploads, e.g. because the texture was replaced and all cached glyphs are
+    /// uploaded again.
+    pub fn discard(&mut self) {
+        self.pending.clear();
+
+        if let Some(blit) = &mut self.blit {
+            blit.texels.clear();
+            blit.copies.clear();
+            blit.batches.clear();
+        }
+
+        if let Some(shadow) = &mut self.shadow {
+            shadow.dirty.clear();
+        }
+    }
+
+    pub fn has_shadow(&self) -> bool {
+        self.shadow.is_some()
+    }
+
+    /// Keeps a shadow of the `size` x `size` texture, initialized with its current texels, or
+    /// drops it.
+    pub fn set_shadow(
+        &mut self,
+        texture: Option<&ProtocolObject<dyn MTLTexture>>,
+        size: u32,
+        num_channels: usize,
+    ) {
+        let Some(texture) = texture else {
+            self.shadow = None;
+            return;
+        };
+
+        self.flush(texture, num_channels);
+
+        let size = size as usize;
+        let mut texels = vec![0; size * size * num_channels];
+
+        unsafe {
+            texture.getBytes_bytesPerRow_fromRegion_mipmapLevel(
+                NonNull::from(texels.as_mut_slice()).cast(),
+                size * num_channels,
+                region([0, 0, size, size]),
+                0,
+            );
+        }
+
+        // Copies that were not encoded yet are missing from the texture
+        if let Some(blit) = &self.blit {
+            for batch in &blit.batches {
+                let staging = batch.staging_bytes();
+
+                for &(offset, [x, y, width, height]) in &batch.copies {
+                    let row_bytes = width * num_channels;
+                    for row in 0..height {
+                        let source = offset + row * row_bytes;
+                        let start = ((y + row) * size + x) * num_channels;
+                        texels[start..start + row_bytes]
+                            .copy_from_slice(&staging[source..source + row_bytes]);
+                    }
+                }
+            }
+        }
+
+        self.shadow = Some(Shadow {
+            size,
+            texels,
+            dirty: Vec::new(),
+        });
+    }
+
+    /// Grows the shadow to `new_size`, keeping its texels in place, and writes it into the new
+    /// `texture`. Returns `false` if there is no shadow.
+    pub fn grow_shadow(
+        &mut self,
+        texture: &ProtocolObject<dyn MTLTexture>,
+        new_size: u32,
+        num_channels: usize,
+    ) -> bool {
+        let Some(shadow) = &mut self.shadow else {
+            return false;
+        };
+
+        let new_size = new_size as usize;
+        let row_bytes = shadow.size * num_channels;
+        let mut texels = vec![0; new_size * new_size * num_channels];
+
+        for (row, old_row) in shadow.texels.chunks_exact(row_bytes).enumerate() {
+            let start = row * new_size * num_channels;
+            texels[start..start + row_bytes].copy_from_slice(old_row);
+        }
+
+        shadow.size = new_size;
+        shadow.texels = texels;
+        shadow.dirty.clear();
+
+        write_region(
+            texture,
+            [0, 0, new_size, new_size],
+            &shadow.texels,
+            new_size * num_channels,
+            self.blit.as_mut(),
+            num_channels,
+        );
+        self.texture_writes += 1;
+        self.uploaded_bytes += shadow.texels.len() as u64;
+
+        if let Some(blit) = &mut self.blit {
+            blit.finish_flush(texture, num_channels);
+        }
+
+        true
+    }
+
+    /// Returns an estimate of the memory used by queued uploads and the shadow.
+    pub fn memory_bytes(&self) -> usize {
+        self.pending.capacity() * mem::size_of::<PendingUpload>()
+            + self
+                .pending
+                .iter()
+                .map(|upload| upload.data.capacity())
+                .sum::<usize>()
+            + self.staging.capacity()
+            + self.blit.as_ref().map_or(0, |blit| {
+                blit.texels.capacity()
+                    + blit
+                        .batches
+                        .iter()
+                        .map(|batch| batch.staging.length())
+                        .sum::<usize>()
+            })
+            + self.shadow.as_ref().map_or(0, |shadow| {
+                shadow.texels.capacity() + shadow.dirty.capacity() * mem::size_of::<[usize; 4]>()
+            })
+    }
+}
+
+fn region([x, y, width, height]: [usize; 4]) -> MTLRegion {
+    MTLRegion {
+        origin: MTLOrigin { x, y, z: 0 },
+        size: MTLSize {
+            width,
+            height,
+            depth: 1,
+        },
+    }
+}
+
+/// Writes the `rect` region of `bytes` into `texture`, or records a copy of it into `blit`.
+fn write_region(
+    texture: &ProtocolObject<dyn MTLTexture>,
+    rect: [usize; 4],
+    bytes: &[u8],
+    bytes_per_row: usize,
+    blit: Option<&mut BlitUploads>,
+    num_channels: usize,
+) {
+    if let Some(blit) = blit {
+        blit.record(rect, bytes, bytes_per_row, num_channels);
+        return;
+    }
+
+    unsafe {
+        texture.replaceRegion_mipmapLevel_withBytes_bytesPerRow(
+            region(rect),
+            0,
+            NonNull::from(bytes).cast(),
+            bytes_per_row,
+        );
+    }
+}
@@ -1,8 +1,99 @@
-use crate::{Params, Resolution};
-use objc2::{rc::Retained, runtime::ProtocolObject};
-use objc2_foundation::ns_string;
-use objc2_metal::{MTLBuffer, MTLDevice, MTLResource as _, MTLResourceOptions};
-use std::{mem, ptr::NonNull};
+#[cfg(target_os = "macos")]
+use crate::text_render::is_managed;
+use crate::{
+    resource_label,
+    text_render::{default_buffer_options, is_cpu_writable},
+    BuildError, Color, MemoryUsage, Params, Resolution, SlotError, DEFAULT_LABEL,
+};
+use objc2::{rc::Retained, runtime::ProtocolObject, Message as _};
+#[cfg(target_os = "macos")]
+use objc2_foundation::NSRange;
+use objc2_metal::{
+    MTLBuffer, MTLDevice, MTLResource as _, MTLResourceOptions, MTLScissorRect, MTLViewport,
+};
+use std::{
+    mem,
+    ptr::NonNull,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// The distance in bytes between two parameter slots in the viewport buffer, which satisfies
+/// the constant buffer offset alignment of every Metal device.
+const PARAMS_SLOT_STRIDE: usize = 256;
+
+/// A 2D affine transform applied to all text of a [`Viewport`] in the vertex shader.
+///
+/// The transform maps a physical pixel position `(x, y)` produced by `prepare` to
+/// `(xx * x + yx * y + tx, xy * x + yy * y + ty)`.
+///
+/// Glyphs are rasterized at the size they were prepared with, so zooming scales them linearly
+/// until the next `prepare`. Use [`ViewTransform::zoom`] to decide when to re-prepare (e.g. at
+/// zoom milestones) with a matching `TextArea::scale` for crisp text.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ViewTransform {
+    /// The x component of the transformed x axis.
+    pub xx: f32,
+    /// The y component of the transformed x axis.
+    pub xy: f32,
+    /// The x component of the transformed y axis.
+    pub yx: f32,
+    /// The y component of the transformed y axis.
+    pub yy: f32,
+    /// The translation along the x axis in physical pixels.
+    pub tx: f32,
+    /// The translation along the y axis in physical pixels.
+    pub ty: f32,
+}
+
+impl ViewTransform {
+    /// The identity transform.
+    pub const IDENTITY: Self = Self {
+        xx: 1.0,
+        xy: 0.0,
+        yx: 0.0,
+        yy: 1.0,
+        tx: 0.0,
+        ty: 0.0,
+    };
+
+    /// Creates a transform that uniformly scales by `scale` and then translates by
+    /// `(translate_x, translate_y)`.
+    pub fn scale_translate(scale: f32, translate_x: f32, translate_y: f32) -> Self {
+        Self {
+            xx: scale,
+            yy: scale,
+            tx: translate_x,
+            ty: translate_y,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Returns the effective zoom of the transform (the square root of the absolute value of
+    /// its determinant).
+    pub fn zoom(&self) -> f32 {
+        (self.xx * self.yy - self.yx * self.xy).abs().sqrt()
+    }
+}
+
+impl Default for ViewTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Where `render` places the viewport of the render command encoder, see
+/// [`crate::TextRenderer::set_encoder_viewport`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncoderViewport {
+    /// The pixel of the render target at which the top-left corner of the [`Viewport`] lies.
+    pub origin: (u32, u32),
+    /// Whether the scissor rectangle is set to the same region, so that nothing is drawn
+    /// outside of it.
+    pub scissor: bool,
+}
 
 /// Controls the visible area of all text for a given renderer. Any text outside of the visible
 /// area will be clipped.
@@ -10,49 +101,523 @@ use std::{mem, ptr::NonNull};
 /// Many projects will only ever need a single `Viewport`, but it is possible to create multiple
 /// `Viewport`s if you want to render text to specific areas within a window (without having to)
 /// bound each `TextArea`).
+///
+/// A `Viewport` holds one or more parameter slots. All setters and getters act on the active
+/// slot (see [`Viewport::set_active_slot`]), which lets the same prepared text be rendered with
+/// several configurations (e.g. split-screen panes with different origins) without overwriting
+/// parameters that in-flight draws still read.
+///
+/// Stereo rendering (e.g. with Compositor Services on visionOS, which provides a texture and a
+/// view per eye) uses one slot per eye: set up every slot, prepare once, then render each eye
+/// with [`crate::TextRenderer::render_with_slot`]. Slots should be created before `prepare`,
+/// since creating a slot reallocates the viewport buffer that the Metal 4 residency set of the
+/// renderer tracks from `prepare` on.
+///
+/// A `Viewport` is `Send`, so it can be updated on the thread that prepares text.
 #[derive(Debug)]
 pub struct Viewport {
-    params: Params,
+    device: Retained<ProtocolObject<dyn MTLDevice>>,
+    params: Vec<Params>,
+    /// The scale factor of each slot, see [`Viewport::update_with_scale`].
+    scale_factors: Vec<f32>,
+    active_slot: usize,
+    /// See [`Viewport::set_render_scale`].
+    render_scale: f32,
+    label: String,
+    options: MTLResourceOptions,
     pub(crate) buffer: Retained<ProtocolObject<dyn MTLBuffer>>,
+    /// Tells the buffers of all viewports apart, see [`Viewport::buffer_id`].
+    buffer_id: u64,
 }
 
+// SAFETY: Metal buffers may be used from any thread. The parameter buffer is only written
+// through `&mut self`.
+unsafe impl Send for Viewport {}
+
 impl Viewport {
+    /// The largest width and height of the render resolution that text is rendered at, which is
+    /// the largest texture dimension of every Metal device since the A9.
+    pub const MAX_DIMENSION: u32 = 16384;
+
+    /// The largest number of parameter slots a viewport holds, see [`Viewport::set_active_slot`].
+    ///
+    /// Each slot takes 256 bytes of the viewport buffer, so a full viewport buffer takes 16 KiB.
+    pub const MAX_SLOTS: usize = 64;
+
     /// Creates a new `Viewport` with the given `device`.
-    pub fn new(device: &Retained<ProtocolObject<dyn MTLDevice>>) -> Self {
+    ///
+    /// The viewport buffer is created with the same per-device default options as the vertex
+    /// buffer of a renderer, see [`crate::TextRendererBuilder::vertex_storage`].
+    pub fn new(device: &ProtocolObject<dyn MTLDevice>) -> Self {
+        Self::with_options(device, default_buffer_options(device))
+            .expect("Default buffer options are CPU writable")
+    }
+
+    /// Creates a new `Viewport` with the given `device`, whose buffer is created with `options`
+    /// (including when slots are added), or an error if the buffer would not be writable by the
+    /// CPU.
+    ///
+    /// Parameters are written by the CPU whenever a setter is called, so the storage mode must be
+    /// shared, or managed on macOS. As with the vertex buffer, Metal does not synchronize these
+    /// writes with GPU work that still reads the previous parameters.
+    pub fn with_options(
+        device: &ProtocolObject<dyn MTLDevice>,
+        options: MTLResourceOptions,
+    ) -> Result<Self, BuildError> {
+        if !is_cpu_writable(options) {
+            return Err(BuildError::UnsupportedViewportStorage);
+        }
+
         let params = Params {
             screen_resolution: Resolution {
                 width: 0,
                 height: 0,
             },
+            headroom: 1.0,
+            origin: [0, 0],
+            transform: ViewTransform::IDENTITY,
+            tint: [1.0; 4],
+        };
+
+        let buffer = create_params_buffer(device, 1, DEFAULT_LABEL, options)
+            .ok_or(BuildError::DeviceLost)?;
+
+        let viewport = Self {
+            device: device.retain(),
+            params: vec![params],
+            scale_factors: vec![1.0],
+            active_slot: 0,
+            render_scale: 1.0,
+            label: DEFAULT_LABEL.to_owned(),
+            options,
+            buffer,
+            buffer_id: next_buffer_id(),
         };
+        viewport.write_params();
 
-        let buffer = device
-            .newBufferWithLength_options(
-                mem::size_of::<Params>(),
-                MTLResourceOptions::StorageModeShared,
-            )
-            .unwrap();
-        buffer.setLabel(Some(ns_string!("Metalglyph - Viewport Buffer")));
+        Ok(viewport)
+    }
+
+    /// Sets the prefix of the label of the viewport buffer (e.g. `"Minimap"` produces
+    /// `"Minimap - Viewport Buffer"`).
+    pub fn set_label(&mut self, label: &str) {
+        self.label = label.to_owned();
+        self.buffer
+            .setLabel(Some(&resource_label(&self.label, "Viewport Buffer")));
+    }
 
-        Self { params, buffer }
+    /// Returns the prefix of the label of the viewport buffer.
+    pub fn label(&self) -> &str {
+        &self.label
     }
 
-    /// Updates the `Viewport` with the given `resolution`.
+    /// Selects the parameter slot that subsequent setters, getters and renders use, or returns
+    /// an error if the slot is not below [`Viewport::MAX_SLOTS`] or could not be created.
+    ///
+    /// Slots are created on demand, initialized with the parameters of the previously active
+    /// slot. Slot `0` always exists and is active by default.
+    ///
+    /// Creating a slot replaces the viewport buffer, which invalidates commands encoded with
+    /// [`crate::TextRenderer::encode_into_icb`]: they must be re-encoded after the next
+    /// `prepare`, which changes [`crate::TextRenderer::geometry_generation`]. Selecting an
+    /// existing slot keeps the buffer.
+    pub fn set_active_slot(&mut self, slot: usize) -> Result<(), SlotError> {
+        let slot_count = slot
+            .checked_add(1)
+            .filter(|&slot_count| slot_count <= Self::MAX_SLOTS)
+            .ok_or(SlotError::OutOfRange(slot))?;
+
+        if slot_count > self.params.len() {
+            let template = *self.params();
+            let scale_factor = self.scale_factor();
+
+            self.buffer = create_params_buffer(&self.device, slot_count, &self.label, self.options)
+                .ok_or(SlotError::DeviceLost)?;
+            self.buffer_id = next_buffer_id();
+            self.params.resize(slot_count, template);
+            self.scale_factors.resize(slot_count, scale_factor);
+
+            for slot in 0..self.params.len() {
+                self.write_slot(slot);
+            }
+        }
+
+        self.active_slot = slot;
+
+        Ok(())
+    }
+
+    /// Recreates the viewport buffer on `device`, keeping the parameters of every slot, e.g.
+    /// after the device of the viewport was lost (see [`crate::TextAtlas::recreate_on`]).
+    pub fn recreate_on(
+        &mut self,
+        device: &ProtocolObject<dyn MTLDevice>,
+    ) -> Result<(), BuildError> {
+        self.buffer = create_params_buffer(device, self.params.len(), &self.label, self.options)
+            .ok_or(BuildError::DeviceLost)?;
+        self.buffer_id = next_buffer_id();
+        self.device = device.retain();
+
+        for slot in 0..self.params.len() {
+            self.write_slot(slot);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the active parameter slot.
+    pub fn active_slot(&self) -> usize {
+        self.active_slot
+    }
+
+    /// Returns the number of parameter slots.
+    pub fn slot_count(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Returns an ID that changes whenever the viewport buffer is replaced, and that no other
+    /// viewport buffer has.
+    pub(crate) fn buffer_id(&self) -> u64 {
+        self.buffer_id
+    }
+
+    pub(crate) fn slot_offset(slot: usize) -> usize {
+        slot * PARAMS_SLOT_STRIDE
+    }
+
+    fn params(&self) -> &Params {
+        &self.params[self.active_slot]
+    }
+
+    fn params_mut(&mut self) -> &mut Params {
+        &mut self.params[self.active_slot]
+    }
+
+    /// Updates the `Viewport` with the given `resolution`, keeping the scale factor of the last
+    /// [`Viewport::update_with_scale`], which is `1.0` (`TextArea` coordinates in physical
+    /// pixels) until then.
+    ///
+    /// A resolution without area, e.g. the zero-sized drawable of a minimized window, is kept
+    /// but makes the viewport unrenderable until the next update, see [`Viewport::is_renderable`].
     pub fn update(&mut self, resolution: Resolution) {
-        if self.params.screen_resolution != resolution {
-            self.params.screen_resolution = resolution;
+        self.update_with_scale(resolution, self.scale_factor());
+    }
+
+    /// Updates the `Viewport` with the given physical `resolution` and `scale_factor`.
+    ///
+    /// With a scale factor other than `1.0`, the `left`, `top`, `scale` and `bounds` of every
+    /// `TextArea` prepared with this viewport are interpreted in logical points and converted
+    /// into physical pixels internally. Glyphs are still rasterized at their physical size, so
+    /// buffers can be laid out in logical units (e.g. `Buffer::set_size` with the logical window
+    /// size) and stay crisp on 1x, 2x and fractional displays.
+    ///
+    /// The scale factor must be finite and positive. Any other scale factor, which would place
+    /// text with NaNs or infinities, is replaced by `1.0`.
+    pub fn update_with_scale(&mut self, resolution: Resolution, scale_factor: f32) {
+        let scale_factor = if scale_factor.is_finite() && scale_factor > 0.0 {
+            scale_factor
+        } else {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(scale_factor, "invalid viewport scale factor, using 1.0");
+
+            1.0
+        };
+
+        self.scale_factors[self.active_slot] = scale_factor;
 
-            unsafe {
-                self.buffer.contents().copy_from(
-                    NonNull::from(&self.params).cast(),
-                    std::mem::size_of::<Params>(),
+        let params = self.params_mut();
+
+        if params.screen_resolution != resolution {
+            params.screen_resolution = resolution;
+            self.write_params();
+
+            #[cfg(feature = "tracing")]
+            if !self.is_renderable() {
+                tracing::debug!(
+                    width = resolution.width,
+                    height = resolution.height,
+                    "viewport is not renderable, text is skipped until the next update"
                 );
             }
         }
     }
 
+    /// Returns whether text can be prepared and rendered with the active slot.
+    ///
+    /// A slot is renderable if both dimensions of its render resolution are between `1` and
+    /// [`Viewport::MAX_DIMENSION`]. Anything else would set an encoder viewport Metal rejects.
+    /// `prepare` prepares nothing and `render` draws nothing with an unrenderable slot, so
+    /// callers can keep rendering while a window is minimized and pick up on the next update.
+    pub fn is_renderable(&self) -> bool {
+        self.is_slot_renderable(self.active_slot)
+    }
+
+    /// Returns whether text can be prepared and rendered with `slot`, see
+    /// [`Viewport::is_renderable`].
+    pub(crate) fn is_slot_renderable(&self, slot: usize) -> bool {
+        let resolution = scale_resolution(self.params[slot].screen_resolution, self.render_scale);
+        let dimensions = 1..=Self::MAX_DIMENSION;
+
+        dimensions.contains(&resolution.width) && dimensions.contains(&resolution.height)
+    }
+
+    /// Returns the memory used by the parameters buffer, which holds every parameter slot.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            texture_bytes: 0,
+            buffer_bytes: self.buffer.allocatedSize() as u64,
+            cpu_bytes: (self.params.capacity() * mem::size_of::<Params>()
+                + self.scale_factors.capacity() * mem::size_of::<f32>())
+                as u64,
+        }
+    }
+
     /// Returns the current resolution of the `Viewport`.
     pub fn resolution(&self) -> Resolution {
-        self.params.screen_resolution
+        self.params().screen_resolution
+    }
+
+    /// Returns the scale factor used to convert logical `TextArea` coordinates into physical
+    /// pixels.
+    pub fn scale_factor(&self) -> f32 {
+        self.scale_factors[self.active_slot]
+    }
+
+    /// Sets the factor by which text is supersampled, which is `1.0` by default.
+    ///
+    /// With a render scale other than `1.0`, text is prepared and rendered for a target of
+    /// [`Viewport::render_resolution`] instead of the resolution: glyphs are rasterized and
+    /// placed at `render_scale` times their size, and the origin and the translation of the
+    /// transform are scaled along. Render it into an intermediate texture of the render
+    /// resolution and downsample it into the target, e.g. with a [`crate::Supersampler`], for
+    /// smoother glyph edges than MSAA provides (which does not smooth the inside of glyphs).
+    ///
+    /// The intermediate texture and the extra pass cost memory and GPU time, which is why text
+    /// is not supersampled by default. The render scale applies to every parameter slot.
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        let render_scale = render_scale.max(1.0);
+
+        if self.render_scale != render_scale {
+            self.render_scale = render_scale;
+
+            for slot in 0..self.params.len() {
+                self.write_slot(slot);
+            }
+        }
+    }
+
+    /// Returns the factor by which text is supersampled.
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// Returns the resolution text is rendered at, which is the resolution multiplied by the
+    /// render scale (see [`Viewport::set_render_scale`]).
+    pub fn render_resolution(&self) -> Resolution {
+        scale_resolution(self.resolution(), self.render_scale)
+    }
+
+    /// Returns the origin in pixels of the render resolution.
+    pub(crate) fn render_origin(&self) -> (i32, i32) {
+        let (x, y) = self.origin();
+
+        (
+            (x as f32 * self.render_scale).round() as i32,
+            (y as f32 * self.render_scale).round() as i32,
+        )
+    }
+
+    /// Sets the origin of the `Viewport` in physical pixels.
+    ///
+    /// All text prepared with this viewport is translated by the origin in the vertex shader,
+    /// which is useful when rendering into a sub-region of a larger drawable. The implicit clip
+    /// to the viewport resolution is applied after the offset, so translated text never spills
+    /// outside of the drawable.
+    ///
+    /// To place the viewport in a larger render target without translating the text, set the
+    /// viewport of the encoder instead (see [`crate::TextRenderer::set_encoder_viewport`]).
+    pub fn set_origin(&mut self, x: i32, y: i32) {
+        let params = self.params_mut();
+
+        if params.origin != [x, y] {
+            params.origin = [x, y];
+            self.write_params();
+        }
+    }
+
+    /// Returns the origin of the `Viewport` in physical pixels.
+    pub fn origin(&self) -> (i32, i32) {
+        (self.params().origin[0], self.params().origin[1])
+    }
+
+    /// Sets the [`ViewTransform`] of the `Viewport` (e.g. a 2D camera's pan and zoom).
+    ///
+    /// The transform is applied in the vertex shader after glyph placement and before the
+    /// origin offset, so camera motion only requires updating the viewport rather than
+    /// re-preparing. While a transform other than [`ViewTransform::IDENTITY`] is set, `prepare`
+    /// no longer clips text to the resolution (only to each `TextArea::bounds`), since text that
+    /// is offscreen at prepare time can be panned into view later.
+    pub fn set_transform(&mut self, transform: ViewTransform) {
+        let params = self.params_mut();
+
+        if params.transform != transform {
+            params.transform = transform;
+            self.write_params();
+        }
+    }
+
+    /// Returns the [`ViewTransform`] of the `Viewport`.
+    pub fn transform(&self) -> ViewTransform {
+        self.params().transform
+    }
+
+    /// Sets the extended dynamic range headroom of the `Viewport`.
+    ///
+    /// The color of every glyph is multiplied by `headroom` in the shader, which allows text to
+    /// exceed SDR white when rendering to a float render target (e.g. `RGBA16Float`) with EDR
+    /// enabled. Passing the value of `NSScreen::maximumExtendedDynamicRangeColorComponentValue`
+    /// makes white text match EDR white. The default is `1.0`, which leaves colors untouched.
+    pub fn set_headroom(&mut self, headroom: f32) {
+        let headroom = headroom.max(0.0);
+
+        let params = self.params_mut();
+
+        if params.headroom != headroom {
+            params.headroom = headroom;
+            self.write_params();
+        }
+    }
+
+    /// Returns the current extended dynamic range headroom of the `Viewport`.
+    pub fn headroom(&self) -> f32 {
+        self.params().headroom
+    }
+
+    /// Sets a global tint that is multiplied with the final color of all text rendered with the
+    /// `Viewport`, for both mask and color glyphs.
+    ///
+    /// The tint is specified in sRGB like every other text color, and is converted along with
+    /// them according to the [`crate::ColorMode`] of the atlas. Useful for screen-wide effects
+    /// like dimming the UI behind a modal without re-preparing. The default is opaque white,
+    /// which leaves colors untouched.
+    pub fn set_tint(&mut self, tint: Color) {
+        let [r, g, b, a] = tint.as_rgba();
+        let tint = [r, g, b, a].map(|c| c as f32 / 255.0);
+        let params = self.params_mut();
+
+        if params.tint != tint {
+            params.tint = tint;
+            self.write_params();
+        }
+    }
+
+    /// Returns the global tint of the `Viewport`.
+    pub fn tint(&self) -> Color {
+        let [r, g, b, a] = self.params().tint.map(|c| (c * 255.0).round() as u8);
+        Color::rgba(r, g, b, a)
+    }
+
+    fn write_params(&self) {
+        self.write_slot(self.active_slot);
+    }
+
+    /// Returns the parameters of `slot` as the shader reads them.
+    pub(crate) fn shader_params(&self, slot: usize) -> Params {
+        let mut params = self.params[slot];
+
+        // The shader works in pixels of the render resolution
+        if self.render_scale != 1.0 {
+            let scale = self.render_scale;
+
+            params.screen_resolution = scale_resolution(params.screen_resolution, scale);
+            params.origin = params.origin.map(|c| (c as f32 * scale).round() as i32);
+            params.transform.tx *= scale;
+            params.transform.ty *= scale;
+        }
+
+        params
+    }
+
+    /// Returns the region of the render target covered by the render resolution of `slot` placed
+    /// at `origin`, as an encoder viewport and scissor rectangle.
+    pub(crate) fn encoder_region(
+        &self,
+        slot: usize,
+        origin: (u32, u32),
+    ) -> (MTLViewport, MTLScissorRect) {
+        let resolution = scale_resolution(self.params[slot].screen_resolution, self.render_scale);
+
+        let viewport = MTLViewport {
+            originX: origin.0 as f64,
+            originY: origin.1 as f64,
+            width: resolution.width as f64,
+            height: resolution.height as f64,
+            znear: 0.0,
+            zfar: 1.0,
+        };
+        let scissor_rect = MTLScissorRect {
+            x: origin.0 as usize,
+            y: origin.1 as usize,
+            width: resolution.width as usize,
+            height: resolution.height as usize,
+        };
+
+        (viewport, scissor_rect)
+    }
+
+    /// Replaces the parameters of the active slot with `params`, as the shader reads them.
+    #[cfg(feature = "debug-tools")]
+    pub(crate) fn restore_params(&mut self, params: Params) {
+        self.render_scale = 1.0;
+        *self.params_mut() = params;
+        self.write_params();
+    }
+
+    fn write_slot(&self, slot: usize) {
+        let params = self.shader_params(slot);
+
+        unsafe {
+            self.buffer
+                .contents()
+                .byte_add(Self::slot_offset(slot))
+                .copy_from(NonNull::from(&params).cast(), std::mem::size_of::<Params>());
+        }
+
+        #[cfg(target_os = "macos")]
+        if is_managed(self.options) {
+            self.buffer.didModifyRange(NSRange::new(
+                Self::slot_offset(slot),
+                std::mem::size_of::<Params>(),
+            ));
+        }
     }
 }
+
+fn scale_resolution(resolution: Resolution, scale: f32) -> Resolution {
+    Resolution {
+        width: (resolution.width as f32 * scale).ceil() as u32,
+        height: (resolution.height as f32 * scale).ceil() as u32,
+    }
+}
+
+/// Returns an ID that no other viewport buffer has.
+fn next_buffer_id() -> u64 {
+    static NEXT_BUFFER_ID: AtomicU64 = AtomicU64::new(0);
+
+    NEXT_BUFFER_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+fn create_params_buffer(
+    device: &ProtocolObject<dyn MTLDevice>,
+    slot_count: usize,
+    label: &str,
+    options: MTLResourceOptions,
+) -> Option<Retained<ProtocolObject<dyn MTLBuffer>>> {
+    let buffer = device.newBufferWithLength_options(
+        PARAMS_SLOT_STRIDE * (slot_count - 1) + mem::size_of::<Params>(),
+        options,
+    )?;
+    buffer.setLabel(Some(&resource_label(label, "Viewport Buffer")));
+
+    Some(buffer)
+}
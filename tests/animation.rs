@@ -0,0 +1,184 @@
+//! Tests of animating the quads of glyphs with `prepare_with_animation`, comparing the quads of
+//! renderer snapshots.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --features debug-tools --test animation -- --ignored
+//! ```
+
+use metalglyph::{
+    Buffer, Cache, FontSystem, GlyphAnimContext, GlyphTransform, Metrics, SnapshotQuad, SwashCache,
+    TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2_metal::MTLPixelFormat;
+
+mod common;
+
+fn text_area(buffer: &Buffer) -> TextArea<'_> {
+    TextArea {
+        left: 8.0,
+        top: 8.0,
+        ..TextArea::new(buffer)
+    }
+}
+
+struct Scene {
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    atlas: TextAtlas,
+    viewport: Viewport,
+    text_renderer: TextRenderer,
+    buffer: Buffer,
+}
+
+impl Scene {
+    fn new() -> Self {
+        let device = common::device();
+        let cache = Cache::new(&device);
+
+        let mut font_system = common::font_system();
+
+        let mut atlas = common::atlas(&device, &cache);
+        let viewport = common::viewport(&device);
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+        let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), "Wave");
+
+        Self {
+            font_system,
+            swash_cache: SwashCache::new(),
+            atlas,
+            viewport,
+            text_renderer,
+            buffer,
+        }
+    }
+
+    fn quads(&mut self) -> Vec<SnapshotQuad> {
+        self.text_renderer
+            .capture_snapshot(&self.atlas, &self.viewport, false)
+            .quads
+    }
+
+    fn prepare(&mut self) -> Vec<SnapshotQuad> {
+        let text_area = text_area(&self.buffer);
+        self.text_renderer
+            .prepare(
+                &mut self.font_system,
+                &mut self.atlas,
+                &self.viewport,
+                [text_area],
+                &mut self.swash_cache,
+            )
+            .expect("Prepare text");
+
+        self.quads()
+    }
+
+    fn prepare_animated(
+        &mut self,
+        animate: impl FnMut(GlyphAnimContext) -> GlyphTransform,
+    ) -> Vec<SnapshotQuad> {
+        let text_area = text_area(&self.buffer);
+        self.text_renderer
+            .prepare_with_animation(
+                &mut self.font_system,
+                &mut self.atlas,
+                &self.viewport,
+                [text_area],
+                &mut self.swash_cache,
+                animate,
+            )
+            .expect("Prepare text");
+
+        self.quads()
+    }
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn identity_transforms_prepare_the_same_quads() {
+    let mut scene = Scene::new();
+    let quads = scene.prepare();
+
+    let mut contexts = Vec::new();
+    let animated = scene.prepare_animated(|context| {
+        contexts.push(context);
+        GlyphTransform::IDENTITY
+    });
+
+    assert_eq!(animated, quads);
+    assert_eq!(
+        contexts
+            .iter()
+            .map(|context| (context.index, context.cluster))
+            .collect::<Vec<_>>(),
+        [(0, 0), (1, 1), (2, 2), (3, 3)]
+    );
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn transforms_only_change_the_quads() {
+    let mut scene = Scene::new();
+    let quads = scene.prepare();
+    let glyphs = scene
+        .text_renderer
+        .capture_snapshot(&scene.atlas, &scene.viewport, false)
+        .glyphs;
+
+    let animated = scene.prepare_animated(|context| GlyphTransform {
+        offset: (0.0, if context.index == 1 { 3.5 } else { 0.0 }),
+        scale: if context.index == 2 { 2.0 } else { 1.0 },
+        alpha: (context.index == 3).then_some(128),
+        ..GlyphTransform::IDENTITY
+    });
+
+    // Glyphs are not rasterized again
+    assert_eq!(
+        scene
+            .text_renderer
+            .capture_snapshot(&scene.atlas, &scene.viewport, false)
+            .glyphs,
+        glyphs
+    );
+
+    assert_eq!(animated[0], quads[0]);
+
+    // Moved by three and a half pixels
+    assert_eq!(animated[1].pos, [quads[1].pos[0], quads[1].pos[1] + 3]);
+    assert_eq!(animated[1].fraction, [0.0, 0.5]);
+    assert_eq!(animated[1].linear, [1.0, 0.0]);
+
+    // Scaled around its center, sampling the same atlas region
+    assert_eq!(animated[2].linear, [2.0, 0.0]);
+    assert_eq!(animated[2].size, quads[2].size);
+    assert_eq!(animated[2].uv, quads[2].uv);
+
+    assert_eq!(animated[3].color >> 24, 128);
+    assert_eq!(animated[3].color & 0xff_ffff, quads[3].color & 0xff_ffff);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn transforms_are_only_uploaded_for_moved_quads() {
+    let mut scene = Scene::new();
+    let quads = scene.prepare();
+    let vertex_bytes = scene.text_renderer.vertex_bytes();
+    assert_eq!(vertex_bytes, 24 * quads.len());
+
+    // Recoloring leaves every quad in place
+    scene.prepare_animated(|_| GlyphTransform {
+        alpha: Some(128),
+        ..GlyphTransform::IDENTITY
+    });
+    assert_eq!(scene.text_renderer.vertex_bytes(), vertex_bytes);
+
+    // Moving one quad by a fraction of a pixel uploads the transforms of all of them
+    scene.prepare_animated(|context| GlyphTransform {
+        offset: (if context.index == 0 { 2.5 } else { 0.0 }, 0.0),
+        ..GlyphTransform::IDENTITY
+    });
+    assert_eq!(scene.text_renderer.vertex_bytes(), 32 * quads.len());
+}
@@ -0,0 +1,167 @@
+//! Tests that the mask and color atlases of a `TextAtlas` can be sized and formatted separately.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test atlas_builder -- --ignored
+//! ```
+
+use metalglyph::{
+    BuildError, Cache, ColorAtlasFormat, ColorMode, ContentType, Metrics, Resolution, SwashCache,
+    TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2_metal::{MTLPixelFormat, MTLTexture};
+
+mod common;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn defaults_match_new() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let atlas = TextAtlas::builder(&device, &cache, MTLPixelFormat::BGRA8Unorm)
+        .build()
+        .expect("Build atlas");
+    let new = common::atlas(&device, &cache);
+
+    for content_type in [ContentType::Mask, ContentType::Color] {
+        assert_eq!(atlas.size(content_type), new.size(content_type));
+        assert_eq!(atlas.max_size(content_type), new.max_size(content_type));
+    }
+    assert_eq!(atlas.color_format(), ColorAtlasFormat::Rgba8);
+    assert_eq!(
+        atlas.color_texture().pixelFormat(),
+        MTLPixelFormat::RGBA8Unorm_sRGB
+    );
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn sizes_and_formats_are_separate() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let atlas = TextAtlas::builder(&device, &cache, MTLPixelFormat::BGRA8Unorm)
+        .color_mode(ColorMode::Web)
+        .initial_size(ContentType::Mask, 512)
+        .initial_size(ContentType::Color, 64)
+        .max_size(ContentType::Color, 1024)
+        .color_format(ColorAtlasFormat::Bgra8)
+        .build()
+        .expect("Build atlas");
+
+    assert_eq!(atlas.size(ContentType::Mask), 512);
+    assert_eq!(atlas.size(ContentType::Color), 64);
+    assert_eq!(atlas.max_size(ContentType::Color), 1024);
+    assert!(atlas.max_size(ContentType::Mask) >= 8192);
+    assert_eq!(atlas.color_format(), ColorAtlasFormat::Bgra8);
+    assert_eq!(
+        atlas.color_texture().pixelFormat(),
+        MTLPixelFormat::BGRA8Unorm
+    );
+    assert_eq!(atlas.mask_texture().pixelFormat(), MTLPixelFormat::R8Unorm);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn invalid_sizes_are_rejected() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let builder = || TextAtlas::builder(&device, &cache, MTLPixelFormat::BGRA8Unorm);
+    let device_max_size = common::atlas(&device, &cache).max_size(ContentType::Mask);
+
+    let error = |result: Result<TextAtlas, BuildError>| match result {
+        Err(BuildError::InvalidAtlasSize {
+            content_type,
+            initial_size,
+            max_size,
+            ..
+        }) => (content_type, initial_size, max_size),
+        Err(error) => panic!("Unexpected error {error}"),
+        Ok(_) => panic!("Invalid sizes were accepted"),
+    };
+
+    assert_eq!(
+        error(builder().initial_size(ContentType::Mask, 0).build()),
+        (ContentType::Mask, 0, device_max_size)
+    );
+    assert_eq!(
+        error(
+            builder()
+                .initial_size(ContentType::Color, 512)
+                .max_size(ContentType::Color, 256)
+                .build()
+        ),
+        (ContentType::Color, 512, 256)
+    );
+    assert_eq!(
+        error(builder().max_size(ContentType::Mask, 1 << 20).build()),
+        (ContentType::Mask, 256, 1 << 20)
+    );
+
+    // A maximum size below the default initial size also lowers the initial size
+    let atlas = builder()
+        .max_size(ContentType::Color, 128)
+        .build()
+        .expect("Build atlas");
+    assert_eq!(atlas.size(ContentType::Color), 128);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn mask_atlas_stops_growing_at_its_max_size() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::builder(&device, &cache, MTLPixelFormat::BGRA8Unorm)
+        .initial_size(ContentType::Mask, 64)
+        .max_size(ContentType::Mask, 128)
+        .build()
+        .expect("Build atlas");
+    let mut viewport = Viewport::new(&device);
+    viewport.update(Resolution {
+        width: 2048,
+        height: 2048,
+    });
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+    // The default would rasterize glyphs at a sixteenth of the small maximum size
+    text_renderer.set_max_glyph_size(Some(256.0));
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(
+        &mut font_system,
+        Metrics::new(96.0, 120.0),
+        "ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+    );
+
+    let small_buffer = common::buffer(&mut font_system, Metrics::new(12.0, 16.0), "A");
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea::new(&small_buffer)],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+    assert!(text_renderer.vertex_bytes() > 0);
+    let generation = text_renderer.geometry_generation();
+
+    let result = text_renderer.prepare(
+        &mut font_system,
+        &mut atlas,
+        &viewport,
+        [TextArea::new(&buffer)],
+        &mut swash_cache,
+    );
+
+    // 26 glyphs of 96 px cannot all be in use in a 128 x 128 atlas
+    assert!(result.is_err());
+    assert_eq!(atlas.size(ContentType::Mask), 128);
+    assert_eq!(atlas.size(ContentType::Color), 256);
+
+    // Neither the text of the failed prepare nor that of the previous one is rendered
+    assert_eq!(text_renderer.vertex_bytes(), 0);
+    assert_ne!(text_renderer.geometry_generation(), generation);
+}
@@ -0,0 +1,35 @@
+//! Randomized tests of the atlas bookkeeping, which need no Metal device. Run them with:
+//!
+//! ```sh
+//! cargo test --features atlas-invariants --test atlas_fuzz
+//! ```
+//!
+//! Set `METALGLYPH_FUZZ_SEEDS` to run more seeds.
+
+use metalglyph::{fuzz, AtlasPacking};
+
+#[test]
+fn small_atlas_grows_to_capacity() {
+    for seed in 1..=8 {
+        fuzz::run(seed, 200, 64);
+    }
+}
+
+#[test]
+fn guillotine_packing_grows_to_capacity() {
+    for seed in 1..=8 {
+        fuzz::run_with_packing(seed, 200, 64, AtlasPacking::Guillotine);
+    }
+}
+
+#[test]
+fn many_frames() {
+    let seeds = std::env::var("METALGLYPH_FUZZ_SEEDS")
+        .ok()
+        .and_then(|seeds| seeds.parse().ok())
+        .unwrap_or(4u64);
+
+    for seed in 1..=seeds {
+        fuzz::run(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15), 2000, 256);
+    }
+}
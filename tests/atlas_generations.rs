@@ -0,0 +1,59 @@
+//! Tests that glyphs looked up in the atlas tell when they are no longer current.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test atlas_generations -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, GlyphKey, GlyphRasterConfig, Metrics, SwashCache, TextAtlas, TextRenderer, TrimPolicy,
+};
+use objc2_metal::MTLPixelFormat;
+
+mod common;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn evicted_glyphs_are_no_longer_current() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+    let attrs = common::attrs();
+    let config = GlyphRasterConfig::default();
+
+    let mut prewarm = |atlas: &mut TextAtlas| {
+        text_renderer
+            .prewarm(
+                &mut font_system,
+                atlas,
+                &mut swash_cache,
+                &attrs,
+                &[Metrics::new(24.0, 32.0)],
+                "g",
+            )
+            .expect("Prewarm glyphs");
+    };
+
+    prewarm(&mut atlas);
+    let Some(GlyphKey::Text(cache_key)) = atlas.cached_glyphs().next().map(|glyph| glyph.key)
+    else {
+        panic!("Glyph not cached");
+    };
+    let glyph = atlas.glyph(cache_key, config).expect("Glyph in the atlas");
+    assert!(atlas.is_current(cache_key, config, &glyph));
+
+    atlas.trim_with(TrimPolicy::ByteBudget(0));
+    assert!(!atlas.is_current(cache_key, config, &glyph));
+
+    // Cached again, the glyph has a new generation even at the same position
+    prewarm(&mut atlas);
+    let cached_again = atlas.glyph(cache_key, config).expect("Glyph in the atlas");
+    assert_ne!(cached_again.generation, glyph.generation);
+    assert!(!atlas.is_current(cache_key, config, &glyph));
+    assert!(atlas.is_current(cache_key, config, &cached_again));
+}
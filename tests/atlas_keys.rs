@@ -0,0 +1,121 @@
+//! Tests that atlas keys built outside of `prepare` find the glyphs `prepare` cached.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test atlas_keys -- --ignored
+//! ```
+
+use metalglyph::{
+    AtlasKey, Cache, ContentType, CustomGlyph, GlyphPlacement, Metrics, RasterizedCustomGlyph,
+    Resolution, SubpixelBin, SwashCache, TextArea, TextRenderer, Viewport,
+};
+use objc2_metal::MTLPixelFormat;
+
+mod common;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn constructed_keys_find_prepared_glyphs() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let mut viewport = Viewport::new(&device);
+    viewport.update_with_scale(
+        Resolution {
+            width: 512,
+            height: 128,
+        },
+        2.0,
+    );
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+    text_renderer.set_tofu(true);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    // A private use character, which Inter has no glyph for, is drawn as a box
+    let buffer = common::buffer(&mut font_system, Metrics::new(20.0, 24.0), "Keys \u{e000}");
+
+    let custom_glyphs = [CustomGlyph {
+        id: 7,
+        left: 100.3,
+        top: 2.6,
+        width: 12.0,
+        height: 12.0,
+        color: None,
+        snap_to_physical_pixel: false,
+        metadata: 0,
+    }];
+    let text_area = TextArea {
+        left: 10.25,
+        top: 4.6,
+        scale: 1.5,
+        custom_glyphs: &custom_glyphs,
+        ..TextArea::new(&buffer)
+    };
+
+    text_renderer
+        .prepare_with_custom(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [text_area.clone()],
+            &mut swash_cache,
+            |request| {
+                Some(RasterizedCustomGlyph {
+                    data: vec![255; request.width as usize * request.height as usize],
+                    content_type: ContentType::Mask,
+                })
+            },
+        )
+        .expect("Prepare text");
+
+    let mut keys = Vec::new();
+    for run in buffer.layout_runs() {
+        for glyph in run.glyphs {
+            keys.push(text_renderer.text_glyph_key(
+                &mut font_system,
+                &atlas,
+                &viewport,
+                &text_area,
+                glyph,
+                run.line_y,
+            ));
+        }
+    }
+    keys.push(text_renderer.custom_glyph_key(&viewport, &text_area, &custom_glyphs[0]));
+
+    let cached: Vec<_> = atlas.cached_glyphs().map(|glyph| glyph.atlas_key).collect();
+    for key in &keys {
+        assert!(atlas.contains(*key), "{key:?}");
+        assert!(cached.contains(key), "{key:?}");
+    }
+    assert!(keys.iter().any(|key| key.raster_config().is_none()));
+
+    // The area is placed at (20.5, 9.2) physical pixels with a scale of 3
+    let (left, top, scale) = (10.25 * 2.0, 4.6 * 2.0, 3.0);
+
+    // The custom glyph is cached at its physical size and subpixel offsets
+    let (_, x_bin) = SubpixelBin::new(left + 100.3 * scale);
+    let (_, y_bin) = SubpixelBin::new(top + 2.6 * scale);
+    let custom_key = AtlasKey::custom(7, 36, 36, x_bin, y_bin);
+    assert_eq!(keys.last(), Some(&custom_key));
+    assert!(atlas.glyph_by_key(custom_key).is_some());
+
+    // Text glyphs within the maximum glyph size are keyed by their physical cache key
+    let run = buffer.layout_runs().next().expect("Layout run");
+    let placement =
+        GlyphPlacement::from_layout_glyph(&run.glyphs[0], run.line_y, (left, top), scale);
+    let text_key = AtlasKey::text(placement.cache_key, text_renderer.raster_config());
+    assert_eq!(keys[0], text_key);
+    assert_eq!(
+        atlas.glyph_by_key(text_key),
+        atlas.glyph(placement.cache_key, text_renderer.raster_config())
+    );
+
+    // A different raster config is a different glyph
+    let mut raster_config = text_renderer.raster_config();
+    raster_config.embolden += 1.0;
+    assert!(!atlas.contains(AtlasKey::text(placement.cache_key, raster_config)));
+}
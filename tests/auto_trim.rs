@@ -0,0 +1,89 @@
+//! Tests that atlases with automatic trimming release glyphs at the start of each frame.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test auto_trim -- --ignored
+//! ```
+
+use metalglyph::{
+    Buffer, Cache, GlyphKey, Metrics, Shaping, SwashCache, TextArea, TextAtlas, TextRenderer,
+};
+use objc2_metal::MTLPixelFormat;
+
+mod common;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn prepares_trim_once_per_frame_of_every_renderer() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let viewport = common::viewport(&device);
+    let mut hud = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+    let mut labels = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    assert!(!atlas.auto_trim());
+    atlas.set_auto_trim(true);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+    let attrs = common::attrs();
+
+    let mut buffer = |text: &str| {
+        let mut buffer = Buffer::new(&mut font_system, Metrics::new(24.0, 32.0));
+        buffer.set_text(&mut font_system, text, &attrs, Shaping::Advanced);
+        buffer.shape_until_scroll(&mut font_system, false);
+        buffer
+    };
+    let hud_text = buffer("HUD");
+    let label_text = buffer("xyz");
+
+    let mut prepare = |text_renderer: &mut TextRenderer, atlas: &mut TextAtlas, buffer| {
+        text_renderer
+            .prepare(
+                &mut font_system,
+                atlas,
+                &viewport,
+                [TextArea::new(buffer)],
+                &mut swash_cache,
+            )
+            .expect("Prepare text");
+    };
+    let in_use = |atlas: &TextAtlas| {
+        let mut glyphs: Vec<_> = atlas
+            .cached_glyphs()
+            .filter(|glyph| glyph.in_use)
+            .map(|glyph| match glyph.key {
+                GlyphKey::Text(cache_key) => cache_key.glyph_id,
+                key => panic!("Unexpected glyph {key:?}"),
+            })
+            .collect();
+        glyphs.sort_unstable();
+        glyphs
+    };
+
+    // The first frame keeps the glyphs of both renderers
+    prepare(&mut hud, &mut atlas, &hud_text);
+    let hud_glyphs = in_use(&atlas);
+    prepare(&mut labels, &mut atlas, &label_text);
+    assert_eq!(in_use(&atlas).len(), 6);
+    assert_eq!(atlas.prepares_since_trim(), 2);
+
+    // The second prepare of the HUD starts the next frame, which releases the labels
+    prepare(&mut hud, &mut atlas, &hud_text);
+    assert_eq!(in_use(&atlas), hud_glyphs);
+    assert_eq!(atlas.prepares_since_trim(), 1);
+    assert_eq!(atlas.cached_glyphs().count(), 6);
+
+    // Without automatic trimming, glyphs stay in use until trimmed
+    atlas.set_auto_trim(false);
+    prepare(&mut labels, &mut atlas, &label_text);
+    prepare(&mut labels, &mut atlas, &label_text);
+    assert_eq!(in_use(&atlas).len(), 6);
+    assert_eq!(atlas.prepares_since_trim(), 3);
+
+    atlas.trim();
+    assert!(in_use(&atlas).is_empty());
+    assert_eq!(atlas.prepares_since_trim(), 0);
+}
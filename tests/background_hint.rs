@@ -0,0 +1,111 @@
+//! Tests that [`TextArea::background_hint`] thickens dark text on a lighter background, and
+//! leaves light text on a darker background untouched.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test background_hint -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, Color, Metrics, Resolution, SwashCache, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2_metal::{MTLClearColor, MTLDevice as _, MTLPixelFormat};
+
+mod common;
+
+const SIZE: usize = 128;
+
+/// Prepares "Hint" in `color` with `background_hint` and renders it onto a background of
+/// `clear`, for each target format.
+fn render_text(color: Color, background_hint: Option<Color>, clear: f64) -> Vec<Vec<u8>> {
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+    let cache = Cache::new(&device);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(12.0, 16.0), "Hint");
+
+    [MTLPixelFormat::BGRA8Unorm_sRGB, MTLPixelFormat::BGRA8Unorm]
+        .into_iter()
+        .map(|format| {
+            let mut atlas = TextAtlas::new(&device, &cache, format);
+            let mut viewport = Viewport::new(&device);
+            viewport.update(Resolution {
+                width: SIZE as u32,
+                height: SIZE as u32,
+            });
+            let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+            text_renderer
+                .prepare(
+                    &mut font_system,
+                    &mut atlas,
+                    &viewport,
+                    [TextArea {
+                        left: 4.0,
+                        top: 4.0,
+                        default_color: color,
+                        background_hint,
+                        ..TextArea::new(&buffer)
+                    }],
+                    &mut swash_cache,
+                )
+                .expect("Prepare text");
+
+            common::render_pixels(
+                &text_renderer,
+                &atlas,
+                &viewport,
+                &queue,
+                format,
+                SIZE,
+                MTLClearColor {
+                    red: clear,
+                    green: clear,
+                    blue: clear,
+                    alpha: 1.0,
+                },
+            )
+        })
+        .collect()
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn hint_thickens_dark_text_on_light_background() {
+    let black = Color::rgb(0, 0, 0);
+    let plain = render_text(black, None, 1.0);
+    let hinted = render_text(black, Some(Color::rgb(255, 255, 255)), 1.0);
+
+    for (plain, hinted) in plain.iter().zip(&hinted) {
+        // Partly covered pixels get darker, and no pixel gets lighter
+        assert_ne!(plain, hinted);
+        assert!(plain
+            .iter()
+            .zip(hinted)
+            .all(|(plain, hinted)| hinted <= plain));
+    }
+
+    // Blending in linear space thins the text the most, so it is corrected the most
+    let darkening = |plain: &[u8], hinted: &[u8]| -> u64 {
+        plain
+            .iter()
+            .zip(hinted)
+            .map(|(&plain, &hinted)| u64::from(plain - hinted))
+            .sum()
+    };
+    assert!(darkening(&plain[0], &hinted[0]) > darkening(&plain[1], &hinted[1]));
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn hint_leaves_light_text_on_dark_background_unchanged() {
+    let white = Color::rgb(255, 255, 255);
+    let plain = render_text(white, None, 0.0);
+    let hinted = render_text(white, Some(Color::rgb(0, 0, 0)), 0.0);
+
+    assert_eq!(plain, hinted);
+}
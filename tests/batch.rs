@@ -0,0 +1,85 @@
+//! Tests of `TextAreaBatch`, which need no Metal device.
+
+use metalglyph::{Buffer, CustomGlyph, FontSystem, Metrics, TextArea, TextAreaBatch};
+
+fn text_area<'a>(buffer: &'a Buffer, left: f32, custom_glyphs: &'a [CustomGlyph]) -> TextArea<'a> {
+    TextArea {
+        left,
+        custom_glyphs,
+        ..TextArea::new(buffer)
+    }
+}
+
+fn custom_glyph(id: u16) -> CustomGlyph {
+    CustomGlyph {
+        id,
+        width: 16.0,
+        height: 16.0,
+        ..CustomGlyph::default()
+    }
+}
+
+/// Collects text areas the way `prepare` takes them.
+fn collect<'a>(text_areas: impl IntoIterator<Item = TextArea<'a>>) -> Vec<TextArea<'a>> {
+    text_areas.into_iter().collect()
+}
+
+fn ids(area: &TextArea) -> Vec<u16> {
+    area.custom_glyphs.iter().map(|glyph| glyph.id).collect()
+}
+
+#[test]
+fn areas_keep_their_custom_glyphs() {
+    let mut font_system =
+        FontSystem::new_with_locale_and_db("en-US".to_owned(), Default::default());
+    let buffer = Buffer::new(&mut font_system, Metrics::new(16.0, 20.0));
+    let icons = [custom_glyph(1), custom_glyph(2)];
+
+    let mut batch = TextAreaBatch::new();
+    batch
+        .push_area(text_area(&buffer, 0.0, &icons))
+        .with_custom_glyphs(&[custom_glyph(3)]);
+    batch.push_area(text_area(&buffer, 10.0, &[]));
+    batch
+        .push_area(text_area(&buffer, 20.0, &[]))
+        .with_custom_glyphs(&[custom_glyph(4)])
+        .with_custom_glyphs(&[custom_glyph(5)]);
+
+    let areas = collect(&batch);
+    assert_eq!(areas.len(), 3);
+    assert_eq!(
+        areas.iter().map(|area| area.left).collect::<Vec<_>>(),
+        [0.0, 10.0, 20.0]
+    );
+    assert_eq!(ids(&areas[0]), [1, 2, 3]);
+    assert!(areas[1].custom_glyphs.is_empty());
+    assert_eq!(ids(&areas[2]), [4, 5]);
+
+    batch.clear();
+    assert!(batch.is_empty());
+    assert_eq!(batch.iter().count(), 0);
+}
+
+#[test]
+fn recycled_batches_start_empty() {
+    let mut font_system =
+        FontSystem::new_with_locale_and_db("en-US".to_owned(), Default::default());
+    let mut batch: TextAreaBatch<'static> = TextAreaBatch::new();
+
+    for frame in 0..3 {
+        let buffer = Buffer::new(&mut font_system, Metrics::new(16.0, 20.0));
+
+        let mut frame_batch = batch.recycle();
+        assert!(frame_batch.is_empty());
+        frame_batch
+            .push_area(text_area(&buffer, frame as f32, &[]))
+            .with_custom_glyphs(&[custom_glyph(frame)]);
+
+        let areas = collect(&frame_batch);
+        assert_eq!(areas.len(), 1);
+        assert_eq!(areas[0].left, frame as f32);
+        assert_eq!(ids(&areas[0]), [frame]);
+
+        batch = frame_batch.recycle();
+    }
+}
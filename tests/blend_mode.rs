@@ -0,0 +1,89 @@
+//! Tests that renderers with [`BlendMode::DestinationOut`] erase the target under their text.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test blend_mode -- --ignored
+//! ```
+
+use metalglyph::{
+    BlendMode, Cache, Color, Metrics, Resolution, SwashCache, TextArea, TextAtlas, TextRenderer,
+    Viewport,
+};
+use objc2_metal::{MTLClearColor, MTLDevice as _, MTLPixelFormat};
+
+mod common;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+const SIZE: usize = 128;
+
+/// Opaque white, which the target is cleared to.
+const WHITE: MTLClearColor = MTLClearColor {
+    red: 1.0,
+    green: 1.0,
+    blue: 1.0,
+    alpha: 1.0,
+};
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn destination_out_erases_glyph_shapes() {
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+    let mut viewport = Viewport::new(&device);
+    viewport.update(Resolution {
+        width: SIZE as u32,
+        height: SIZE as u32,
+    });
+
+    let over = TextRenderer::builder(&mut atlas)
+        .build()
+        .expect("Build renderer");
+    assert_eq!(over.blend_mode(), BlendMode::Over);
+    let mut text_renderer = TextRenderer::builder(&mut atlas)
+        .blend_mode(BlendMode::DestinationOut)
+        .build()
+        .expect("Build renderer");
+    assert_eq!(text_renderer.blend_mode(), BlendMode::DestinationOut);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 28.0), "Frost");
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea {
+                left: 4.0,
+                top: 4.0,
+                default_color: Color::rgb(0, 0, 0),
+                ..TextArea::new(&buffer)
+            }],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+
+    let pixels = common::render_pixels(
+        &text_renderer,
+        &atlas,
+        &viewport,
+        &queue,
+        FORMAT,
+        SIZE,
+        WHITE,
+    );
+    // Glyph interiors are erased to transparent black, whatever the color of the text
+    assert!(pixels.chunks_exact(4).any(|pixel| pixel == [0, 0, 0, 0]));
+    // Edges are partly erased, with color and alpha scaled alike
+    assert!(pixels
+        .chunks_exact(4)
+        .any(|pixel| pixel[3] > 0 && pixel[3] < 255 && pixel[..3] == [pixel[3]; 3]));
+    // Pixels outside the text keep the cleared white
+    let last_row = &pixels[(SIZE - 1) * SIZE * 4..];
+    assert!(last_row.iter().all(|&byte| byte == 255));
+}
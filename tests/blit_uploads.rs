@@ -0,0 +1,61 @@
+//! Tests that glyphs are uploaded through the blit encoder passed by the caller.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test blit_uploads -- --ignored
+//! ```
+
+use metalglyph::{Cache, Metrics, SwashCache, TextArea, TextAtlas, TextRenderer};
+use objc2_metal::{
+    MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue as _, MTLDevice as _,
+    MTLPixelFormat,
+};
+
+mod common;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn uploads_wait_for_the_blit_encoder() {
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    atlas.set_blit_uploads(true);
+    let viewport = common::viewport(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), "abc");
+
+    let mut prepare = |atlas: &mut TextAtlas| {
+        text_renderer
+            .prepare(
+                &mut font_system,
+                atlas,
+                &viewport,
+                [TextArea::new(&buffer)],
+                &mut swash_cache,
+            )
+            .expect("Prepare text");
+    };
+
+    prepare(&mut atlas);
+    assert!(atlas.has_pending_uploads());
+
+    let command_buffer = queue.commandBuffer().expect("Create command buffer");
+    let blit_encoder = command_buffer
+        .blitCommandEncoder()
+        .expect("Create blit command encoder");
+    atlas.encode_uploads(&blit_encoder);
+    blit_encoder.endEncoding();
+    command_buffer.commit();
+    command_buffer.waitUntilCompleted();
+    assert!(!atlas.has_pending_uploads());
+
+    // Cached glyphs are not uploaded again
+    prepare(&mut atlas);
+    assert!(!atlas.has_pending_uploads());
+}
@@ -0,0 +1,178 @@
+//! Tests that preparation with a time or upload budget converges to the text prepared without a
+//! budget.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test budget -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, ContentType, Metrics, Resolution, SwashCache, TextArea, TextRenderer, Viewport,
+};
+use objc2_metal::MTLPixelFormat;
+use std::time::Duration;
+
+mod common;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn exhausted_budget_rasterizes_one_glyph_per_prepare() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let viewport = common::viewport(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), "abcabc");
+
+    let mut prepares = 0;
+    loop {
+        let progress = text_renderer
+            .prepare_with_budget(
+                &mut font_system,
+                &mut atlas,
+                &viewport,
+                [TextArea::new(&buffer)],
+                &mut swash_cache,
+                Duration::ZERO,
+            )
+            .expect("Prepare text");
+        prepares += 1;
+
+        // Each prepare rasterizes one of the glyphs still missing, and skips both copies of
+        // the others
+        let missing = 3 - atlas.glyph_count(ContentType::Mask);
+        assert_eq!(progress.deferred_glyphs, missing * 2);
+        assert_eq!(
+            text_renderer.dropped_glyphs().deferred,
+            progress.deferred_glyphs
+        );
+
+        if progress.is_complete() {
+            break;
+        }
+    }
+
+    assert_eq!(prepares, 3);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn exhausted_budget_rasterizes_the_topmost_glyph_first() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let mut viewport = Viewport::new(&device);
+    viewport.update(Resolution {
+        width: 256,
+        height: 128,
+    });
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let mut buffer = |text| common::buffer(&mut font_system, Metrics::new(24.0, 32.0), text);
+    let bottom = buffer("a");
+    let top = buffer("bb");
+
+    let text_area = |buffer, top| TextArea {
+        top,
+        ..TextArea::new(buffer)
+    };
+
+    // The area prepared first is lower on screen, so the glyph of the other one is rasterized
+    // and only "a" is deferred
+    let progress = text_renderer
+        .prepare_with_budget(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [text_area(&bottom, 64.0), text_area(&top, 0.0)],
+            &mut swash_cache,
+            Duration::ZERO,
+        )
+        .expect("Prepare text");
+
+    assert_eq!(progress.deferred_glyphs, 1);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn upload_budget_defers_glyphs_to_later_frames() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    // Every frame uploads a single glyph
+    atlas.set_upload_budget(Some(1));
+    let viewport = common::viewport(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+    let mut probe_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let mut buffer = |text| common::buffer(&mut font_system, Metrics::new(24.0, 32.0), text);
+    let first = buffer("abc");
+    let second = buffer("xbc");
+    let probe = buffer("b");
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea::new(&first)],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+    assert_eq!(atlas.glyph_count(ContentType::Mask), 1);
+    assert_eq!(text_renderer.dropped_glyphs().deferred, 2);
+    assert_eq!(atlas.upload_backlog(), 2);
+    atlas.trim();
+
+    // The text changed, but "b", deferred in the previous frame, is uploaded before the new "x"
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea::new(&second)],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+    assert_eq!(atlas.glyph_count(ContentType::Mask), 2);
+    assert_eq!(atlas.upload_backlog(), 2);
+    probe_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea::new(&probe)],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+    assert_eq!(probe_renderer.dropped_glyphs().deferred, 0);
+    atlas.trim();
+
+    let mut frames = 0;
+    while atlas.upload_backlog() > 0 {
+        text_renderer
+            .prepare(
+                &mut font_system,
+                &mut atlas,
+                &viewport,
+                [TextArea::new(&second)],
+                &mut swash_cache,
+            )
+            .expect("Prepare text");
+        atlas.trim();
+        frames += 1;
+    }
+    assert_eq!(frames, 2);
+    assert_eq!(text_renderer.dropped_glyphs().deferred, 0);
+}
@@ -0,0 +1,76 @@
+//! Tests that the glyphs of pinned text areas are pinned in the atlas until unpinned.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test cache_priority -- --ignored
+//! ```
+
+use metalglyph::{
+    Buffer, Cache, CachePriority, ContentType, Metrics, Shaping, SwashCache, TextArea,
+    TextRenderer, TrimPolicy,
+};
+use objc2_metal::MTLPixelFormat;
+
+mod common;
+
+fn text_area(buffer: &Buffer, cache_priority: CachePriority) -> TextArea<'_> {
+    TextArea {
+        cache_priority,
+        ..TextArea::new(buffer)
+    }
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn pinned_glyphs_outlive_byte_budgets_until_unpinned() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let viewport = common::viewport(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+    let attrs = common::attrs();
+
+    let mut buffer = |text: &str| {
+        let mut buffer = Buffer::new(&mut font_system, Metrics::new(24.0, 32.0));
+        buffer.set_text(&mut font_system, text, &attrs, Shaping::Advanced);
+        buffer.shape_until_scroll(&mut font_system, false);
+        buffer
+    };
+    let menu = buffer("File");
+    let document = buffer("xyz");
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [
+                text_area(&menu, CachePriority::Pinned),
+                text_area(&document, CachePriority::Normal),
+            ],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+
+    let stats = atlas.stats(ContentType::Mask);
+    assert_eq!(stats.pinned_glyphs, 4);
+    assert!(stats.pinned_area > 0);
+    assert!(atlas.cached_glyphs().any(|glyph| !glyph.pinned));
+
+    // A budget of nothing evicts every glyph but the pinned ones
+    atlas.trim_with(TrimPolicy::ByteBudget(0));
+    let glyphs: Vec<_> = atlas.cached_glyphs().collect();
+    assert_eq!(glyphs.len(), 4);
+    assert!(glyphs.iter().all(|glyph| glyph.pinned));
+
+    atlas.unpin();
+    assert_eq!(atlas.stats(ContentType::Mask).pinned_glyphs, 0);
+    assert_eq!(atlas.stats(ContentType::Mask).pinned_area, 0);
+
+    atlas.trim_with(TrimPolicy::ByteBudget(0));
+    assert_eq!(atlas.cached_glyphs().count(), 0);
+}
@@ -0,0 +1,194 @@
+//! Setup shared by the integration tests.
+//!
+//! Text is laid out with the Inter font of the examples and the `en-US` locale, so that results
+//! do not depend on system fonts.
+
+// Each test uses only part of the setup
+#![allow(dead_code)]
+
+use metalglyph::{
+    fontdb, Attrs, Buffer, Cache, Family, FontSystem, Metrics, Resolution, Shaping, TextAtlas,
+    TextRenderer, Viewport,
+};
+use objc2::{rc::Retained, runtime::ProtocolObject};
+use objc2_metal::{
+    MTLBlitCommandEncoder as _, MTLBuffer as _, MTLClearColor, MTLCommandBuffer,
+    MTLCommandEncoder as _, MTLCommandQueue, MTLCreateSystemDefaultDevice, MTLDevice,
+    MTLLoadAction, MTLOrigin, MTLPixelFormat, MTLRenderPassDescriptor, MTLResourceOptions, MTLSize,
+    MTLStoreAction, MTLTexture, MTLTextureDescriptor, MTLTextureUsage,
+};
+
+pub const FONT: &[u8] = include_bytes!("../../examples/Inter-Bold.ttf");
+
+/// Transparent black, which render targets are usually cleared to.
+pub const TRANSPARENT: MTLClearColor = MTLClearColor {
+    red: 0.0,
+    green: 0.0,
+    blue: 0.0,
+    alpha: 0.0,
+};
+
+/// Returns the default Metal device.
+pub fn device() -> Retained<ProtocolObject<dyn MTLDevice>> {
+    MTLCreateSystemDefaultDevice().expect("Create MTL device")
+}
+
+/// Returns an atlas for `BGRA8Unorm` render targets.
+pub fn atlas(device: &ProtocolObject<dyn MTLDevice>, cache: &Cache) -> TextAtlas {
+    TextAtlas::new(device, cache, MTLPixelFormat::BGRA8Unorm)
+}
+
+/// Returns a viewport of 256 × 64 pixels.
+pub fn viewport(device: &ProtocolObject<dyn MTLDevice>) -> Viewport {
+    let mut viewport = Viewport::new(device);
+    viewport.update(Resolution {
+        width: 256,
+        height: 64,
+    });
+    viewport
+}
+
+/// Returns a font system holding only Inter.
+pub fn font_system() -> FontSystem {
+    let mut db = fontdb::Database::new();
+    db.load_font_data(FONT.to_vec());
+
+    FontSystem::new_with_locale_and_db("en-US".to_owned(), db)
+}
+
+/// Returns the attributes of text in Inter.
+pub fn attrs() -> Attrs<'static> {
+    Attrs::new().family(Family::Name("Inter"))
+}
+
+/// Returns a buffer of `text` in Inter, shaped without a size limit.
+pub fn buffer(font_system: &mut FontSystem, metrics: Metrics, text: &str) -> Buffer {
+    let mut buffer = Buffer::new(font_system, metrics);
+    buffer.set_text(font_system, text, &attrs(), Shaping::Advanced);
+    buffer.shape_until_scroll(font_system, false);
+    buffer
+}
+
+/// Returns a square render target of `size` pixels in `format`.
+pub fn render_target(
+    device: &ProtocolObject<dyn MTLDevice>,
+    format: MTLPixelFormat,
+    size: usize,
+) -> Retained<ProtocolObject<dyn MTLTexture>> {
+    let descriptor = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            format, size, size, false,
+        )
+    };
+    descriptor.setUsage(MTLTextureUsage::RenderTarget);
+
+    device
+        .newTextureWithDescriptor(&descriptor)
+        .expect("Create texture")
+}
+
+/// Returns a render pass drawing into `texture`, cleared to `clear`.
+pub fn render_pass(
+    texture: &ProtocolObject<dyn MTLTexture>,
+    clear: MTLClearColor,
+) -> Retained<MTLRenderPassDescriptor> {
+    let render_pass_descriptor = MTLRenderPassDescriptor::new();
+    let color_attachment = unsafe {
+        render_pass_descriptor
+            .colorAttachments()
+            .objectAtIndexedSubscript(0)
+    };
+    color_attachment.setTexture(Some(texture));
+    color_attachment.setLoadAction(MTLLoadAction::Clear);
+    color_attachment.setClearColor(clear);
+    color_attachment.setStoreAction(MTLStoreAction::Store);
+
+    render_pass_descriptor
+}
+
+/// Renders the prepared text into a new square texture of `size` pixels in `format`, cleared to
+/// `clear`, and returns its pixels.
+pub fn render_pixels(
+    text_renderer: &TextRenderer,
+    atlas: &TextAtlas,
+    viewport: &Viewport,
+    queue: &ProtocolObject<dyn MTLCommandQueue>,
+    format: MTLPixelFormat,
+    size: usize,
+    clear: MTLClearColor,
+) -> Vec<u8> {
+    let texture = render_target(&queue.device(), format, size);
+
+    render_into(text_renderer, atlas, viewport, queue, &texture, clear)
+}
+
+/// Renders the prepared text into `texture`, cleared to `clear`, and returns its pixels.
+pub fn render_into(
+    text_renderer: &TextRenderer,
+    atlas: &TextAtlas,
+    viewport: &Viewport,
+    queue: &ProtocolObject<dyn MTLCommandQueue>,
+    texture: &ProtocolObject<dyn MTLTexture>,
+    clear: MTLClearColor,
+) -> Vec<u8> {
+    let command_buffer = queue.commandBuffer().expect("Create command buffer");
+    let encoder = command_buffer
+        .renderCommandEncoderWithDescriptor(&render_pass(texture, clear))
+        .expect("Create render command encoder");
+    text_renderer.render(atlas, viewport, &encoder);
+    encoder.endEncoding();
+
+    read_back(&command_buffer, texture)
+}
+
+/// Copies every layer of `texture`, whose pixels are 4 bytes, after the work already encoded
+/// into `command_buffer`, then commits it and returns the pixels once it completed.
+pub fn read_back(
+    command_buffer: &ProtocolObject<dyn MTLCommandBuffer>,
+    texture: &ProtocolObject<dyn MTLTexture>,
+) -> Vec<u8> {
+    let (width, height, layers) = (texture.width(), texture.height(), texture.arrayLength());
+    let bytes_per_row = width * 4;
+    let bytes_per_image = bytes_per_row * height;
+
+    let readback = command_buffer
+        .device()
+        .newBufferWithLength_options(
+            layers * bytes_per_image,
+            MTLResourceOptions::StorageModeShared,
+        )
+        .expect("Create readback buffer");
+    let blit_encoder = command_buffer
+        .blitCommandEncoder()
+        .expect("Create blit command encoder");
+    for layer in 0..layers {
+        unsafe {
+            blit_encoder.copyFromTexture_sourceSlice_sourceLevel_sourceOrigin_sourceSize_toBuffer_destinationOffset_destinationBytesPerRow_destinationBytesPerImage(
+                texture,
+                layer,
+                0,
+                MTLOrigin { x: 0, y: 0, z: 0 },
+                MTLSize {
+                    width,
+                    height,
+                    depth: 1,
+                },
+                &readback,
+                layer * bytes_per_image,
+                bytes_per_row,
+                bytes_per_image,
+            );
+        }
+    }
+    blit_encoder.endEncoding();
+    command_buffer.commit();
+    command_buffer.waitUntilCompleted();
+
+    unsafe {
+        std::slice::from_raw_parts(
+            readback.contents().as_ptr().cast::<u8>(),
+            layers * bytes_per_image,
+        )
+    }
+    .to_vec()
+}
@@ -0,0 +1,60 @@
+//! Tests of custom fragment functions registered with `Cache::with_custom_fragment`.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test custom_fragment -- --ignored
+//! ```
+
+use metalglyph::{BuildError, Cache, TextRenderer};
+use objc2_foundation::NSString;
+use objc2_metal::MTLDevice as _;
+
+mod common;
+
+const INVERT_FRAGMENT: &str = r#"
+fragment float4 invert_fragment(
+    VertexOutput in_frag [[stage_in]],
+    constant Params& params [[buffer(0)]],
+    texture2d<float> color_atlas_texture [[texture(0), function_constant(uses_color_atlas)]],
+    texture2d<float> mask_atlas_texture [[texture(1), function_constant(uses_mask_atlas)]]
+) {
+    float4 color = sample_glyph(in_frag, params, color_atlas_texture, mask_atlas_texture);
+    return float4(1.0 - color.rgb, color.a);
+}
+"#;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn custom_fragment_builds_renderer() {
+    let device = common::device();
+    let source = format!("{}\n{INVERT_FRAGMENT}", Cache::shader_source());
+    let library = device
+        .newLibraryWithSource_options_error(&NSString::from_str(&source), None)
+        .expect("Create library");
+
+    let cache = Cache::with_custom_fragment(&device, &library, "invert_fragment");
+    let mut atlas = common::atlas(&device, &cache);
+
+    TextRenderer::builder(&mut atlas)
+        .build()
+        .expect("Build renderer");
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn missing_custom_fragment_fails_to_build() {
+    let device = common::device();
+    let library = device
+        .newLibraryWithSource_options_error(&NSString::from_str(Cache::shader_source()), None)
+        .expect("Create library");
+
+    let cache = Cache::with_custom_fragment(&device, &library, "missing_fragment");
+    let mut atlas = common::atlas(&device, &cache);
+
+    let error = TextRenderer::builder(&mut atlas)
+        .build()
+        .err()
+        .expect("Building with a missing fragment function fails");
+    assert!(matches!(error, BuildError::PipelineCreation(_)));
+}
@@ -0,0 +1,121 @@
+//! Tests that preparing the same scene from a cold state produces the same vertices and atlas
+//! packing every time.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --features debug-tools --test determinism -- --ignored
+//! ```
+
+use metalglyph::{
+    Buffer, Cache, Color, Metrics, Resolution, Shaping, SnapshotGlyph, SnapshotQuad, SwashCache,
+    TextArea, TextRenderer, TrimPolicy, Viewport,
+};
+use objc2_metal::MTLPixelFormat;
+
+mod common;
+
+/// The quads of each frame, and the glyphs cached in the atlas after it.
+type Frames = Vec<(
+    Vec<SnapshotQuad>,
+    Vec<SnapshotGlyph>,
+    Vec<(u16, u16, u16, u16)>,
+)>;
+
+/// Runs the scene with a new renderer, atlas and font system.
+fn run_scene() -> Frames {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    // A small budget evicts glyphs between frames, so that packing reuses freed space
+    atlas.set_trim_policy(TrimPolicy::ByteBudget(64 * 64));
+    let mut viewport = Viewport::new(&device);
+    viewport.update(Resolution {
+        width: 512,
+        height: 256,
+    });
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+    let attrs = common::attrs();
+
+    text_renderer
+        .prewarm(
+            &mut font_system,
+            &mut atlas,
+            &mut swash_cache,
+            &attrs,
+            &[Metrics::new(14.0, 18.0), Metrics::new(20.0, 24.0)],
+            "0123456789",
+        )
+        .expect("Prewarm glyphs");
+    atlas.trim();
+
+    let scenes: [&[(&str, f32)]; 3] = [
+        &[("The quick brown fox", 18.0), ("jumps over", 32.0)],
+        &[
+            ("the lazy dog", 24.0),
+            ("0123456789", 14.0),
+            ("Sphinx", 48.0),
+        ],
+        &[("The quick brown fox", 18.0), ("WALTZ, bad nymph", 27.5)],
+    ];
+
+    let mut frames = Vec::new();
+    for areas in scenes {
+        let buffers: Vec<Buffer> = areas
+            .iter()
+            .map(|&(text, size)| {
+                let mut buffer = Buffer::new(&mut font_system, Metrics::new(size, size * 1.25));
+                buffer.set_text(&mut font_system, text, &attrs, Shaping::Advanced);
+                buffer.shape_until_scroll(&mut font_system, false);
+                buffer
+            })
+            .collect();
+
+        text_renderer
+            .prepare(
+                &mut font_system,
+                &mut atlas,
+                &viewport,
+                buffers.iter().enumerate().map(|(i, buffer)| TextArea {
+                    left: 10.25 + 3.0 * i as f32,
+                    top: 8.0 + 60.0 * i as f32,
+                    default_color: Color::rgb(200, 40, 90),
+                    ..TextArea::new(buffer)
+                }),
+                &mut swash_cache,
+            )
+            .expect("Prepare text");
+
+        let snapshot = text_renderer.capture_snapshot(&atlas, &viewport, false);
+        let rectangles = atlas
+            .cached_glyphs()
+            .filter_map(|glyph| glyph.atlas_glyph)
+            .map(|glyph| (glyph.x, glyph.y, glyph.width, glyph.height))
+            .collect();
+        frames.push((snapshot.quads, snapshot.glyphs, rectangles));
+
+        atlas.trim();
+    }
+
+    frames
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn identical_scenes_prepare_identically() {
+    let first = run_scene();
+    let second = run_scene();
+
+    assert!(first.iter().all(|(quads, _, _)| !quads.is_empty()));
+    for (frame, (first, second)) in first.iter().zip(&second).enumerate() {
+        assert_eq!(first.0, second.0, "The quads of frame {frame} differ");
+        assert_eq!(first.1, second.1, "The glyphs of frame {frame} differ");
+        assert_eq!(
+            first.2, second.2,
+            "The atlas rectangles of frame {frame} differ"
+        );
+    }
+}
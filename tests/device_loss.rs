@@ -0,0 +1,144 @@
+//! Tests that an atlas, a renderer and a viewport recreated on another device render the text
+//! of the same font system and buffers.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test device_loss -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, ContentType, Metrics, RenderStats, Resolution, SwashCache, TextArea, TextAtlas,
+    TextRenderer, Viewport,
+};
+use objc2::runtime::ProtocolObject;
+use objc2_metal::{
+    MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue, MTLCreateSystemDefaultDevice,
+    MTLDevice, MTLLoadAction, MTLPixelFormat, MTLRenderPassDescriptor, MTLStoreAction,
+    MTLTextureDescriptor, MTLTextureUsage,
+};
+use std::ptr;
+
+mod common;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+
+/// Renders the prepared text into a new texture of `device` and returns what was encoded.
+fn render(
+    text_renderer: &TextRenderer,
+    atlas: &TextAtlas,
+    viewport: &Viewport,
+    device: &ProtocolObject<dyn MTLDevice>,
+) -> RenderStats {
+    let queue = device.newCommandQueue().expect("Create command queue");
+    let descriptor = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            FORMAT, 256, 64, false,
+        )
+    };
+    descriptor.setUsage(MTLTextureUsage::RenderTarget);
+    let texture = device
+        .newTextureWithDescriptor(&descriptor)
+        .expect("Create texture");
+
+    let render_pass_descriptor = MTLRenderPassDescriptor::new();
+    let color_attachment = unsafe {
+        render_pass_descriptor
+            .colorAttachments()
+            .objectAtIndexedSubscript(0)
+    };
+    color_attachment.setTexture(Some(&texture));
+    color_attachment.setLoadAction(MTLLoadAction::Clear);
+    color_attachment.setStoreAction(MTLStoreAction::Store);
+
+    let command_buffer = queue.commandBuffer().expect("Create command buffer");
+    let encoder = command_buffer
+        .renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+        .expect("Create render command encoder");
+    let stats = text_renderer.render(atlas, viewport, &encoder);
+    encoder.endEncoding();
+    command_buffer.commit();
+    command_buffer.waitUntilCompleted();
+
+    stats
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn recreated_resources_render_the_same_buffers() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+    atlas.set_cpu_shadow(true);
+    let mut viewport = Viewport::new(&device);
+    viewport.set_active_slot(1).expect("Create viewport slot");
+    viewport.update(Resolution {
+        width: 256,
+        height: 64,
+    });
+    let mut text_renderer = TextRenderer::builder(&mut atlas)
+        .label("Recreated")
+        .build()
+        .expect("Build renderer");
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), "Unplugged");
+
+    let mut prepare =
+        |text_renderer: &mut TextRenderer, atlas: &mut TextAtlas, viewport: &Viewport| {
+            text_renderer.prepare(
+                &mut font_system,
+                atlas,
+                viewport,
+                [TextArea::new(&buffer)],
+                &mut swash_cache,
+            )
+        };
+
+    prepare(&mut text_renderer, &mut atlas, &viewport).expect("Prepare text");
+    let stats = render(&text_renderer, &atlas, &viewport, &device);
+    assert_eq!(stats.glyphs, 9);
+    let texture_generation = atlas.texture_generation(ContentType::Mask);
+
+    // A second handle stands in for the device the app moves to once the first one is lost
+    let new_device = MTLCreateSystemDefaultDevice().expect("Create second MTL device");
+    let new_cache = Cache::new(&new_device);
+    atlas
+        .recreate_on(&new_device, &new_cache)
+        .expect("Recreate atlas");
+    text_renderer
+        .recreate_on(&mut atlas)
+        .expect("Recreate renderer");
+    viewport
+        .recreate_on(&new_device)
+        .expect("Recreate viewport");
+
+    assert!(ptr::eq(atlas.device(), &*new_device));
+    assert!(!atlas.is_device_lost());
+    assert!(!text_renderer.is_device_lost());
+    assert_eq!(text_renderer.label(), "Recreated");
+    assert_eq!(text_renderer.check_device(&atlas), Ok(()));
+    assert!(atlas.cpu_shadow());
+    assert_eq!(atlas.glyph_count(ContentType::Mask), 0);
+    assert!(atlas.texture_generation(ContentType::Mask) > texture_generation);
+    assert_eq!(viewport.slot_count(), 2);
+    assert_eq!(
+        viewport.resolution(),
+        Resolution {
+            width: 256,
+            height: 64,
+        }
+    );
+
+    // The prepared text is dropped with the old resources
+    let stats = render(&text_renderer, &atlas, &viewport, &new_device);
+    assert_eq!(stats, RenderStats::default());
+
+    // The same font system and buffer prepare again, rasterizing the glyphs on the new device
+    prepare(&mut text_renderer, &mut atlas, &viewport).expect("Prepare text again");
+    let stats = render(&text_renderer, &atlas, &viewport, &new_device);
+    assert_eq!(stats.glyphs, 9);
+    assert!(atlas.glyph_count(ContentType::Mask) > 0);
+}
@@ -0,0 +1,131 @@
+//! Tests that text areas repeating an earlier area of the same `prepare` are skipped or share
+//! its vertices when enabled.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test duplicate_areas -- --ignored
+//! ```
+
+use metalglyph::{
+    Buffer, Cache, DuplicateAreas, FontSystem, Metrics, Resolution, SwashCache, TextArea,
+    TextAtlas, TextBounds, TextRenderer, Viewport,
+};
+use objc2_metal::MTLPixelFormat;
+
+mod common;
+
+fn text_area(buffer: &Buffer, left: f32, top: f32) -> TextArea<'_> {
+    TextArea {
+        left,
+        top,
+        // Bounds that move with the area, as those of a repeated label
+        bounds: TextBounds {
+            left: left as i32,
+            top: top as i32,
+            right: left as i32 + 200,
+            bottom: top as i32 + 40,
+        },
+        ..TextArea::new(buffer)
+    }
+}
+
+struct Setup {
+    atlas: TextAtlas,
+    viewport: Viewport,
+    text_renderer: TextRenderer,
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    buffer: Buffer,
+}
+
+impl Setup {
+    fn new() -> Self {
+        let device = common::device();
+        let cache = Cache::new(&device);
+        let mut atlas = common::atlas(&device, &cache);
+        let mut viewport = Viewport::new(&device);
+        viewport.update(Resolution {
+            width: 512,
+            height: 256,
+        });
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+        let mut font_system = common::font_system();
+
+        let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), "Duplicate");
+
+        Self {
+            atlas,
+            viewport,
+            text_renderer,
+            font_system,
+            swash_cache: SwashCache::new(),
+            buffer,
+        }
+    }
+
+    /// Prepares the buffer at each of `positions`, returning the vertex bytes written.
+    fn prepare(&mut self, positions: &[(f32, f32)]) -> usize {
+        self.text_renderer
+            .prepare(
+                &mut self.font_system,
+                &mut self.atlas,
+                &self.viewport,
+                positions
+                    .iter()
+                    .map(|&(left, top)| text_area(&self.buffer, left, top)),
+                &mut self.swash_cache,
+            )
+            .expect("Prepare text");
+        self.atlas.trim();
+
+        self.text_renderer.vertex_bytes()
+    }
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn identical_areas_are_skipped_when_enabled() {
+    let mut setup = Setup::new();
+    let once = setup.prepare(&[(0.0, 0.0)]);
+    assert!(once > 0);
+
+    // Intentional duplicates are drawn by default
+    assert!(!setup.text_renderer.skip_duplicate_areas());
+    assert_eq!(setup.prepare(&[(0.0, 0.0), (0.0, 0.0)]), 2 * once);
+    assert_eq!(
+        setup.text_renderer.duplicate_areas(),
+        DuplicateAreas::default()
+    );
+
+    setup.text_renderer.set_skip_duplicate_areas(true);
+    assert_eq!(setup.prepare(&[(0.0, 0.0), (0.0, 0.0), (0.0, 0.0)]), once);
+    assert_eq!(setup.text_renderer.duplicate_areas().skipped, 2);
+
+    // Areas at another position are not duplicates
+    assert_eq!(setup.prepare(&[(0.0, 0.0), (0.0, 40.0)]), 2 * once);
+    assert_eq!(setup.text_renderer.duplicate_areas().skipped, 0);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn areas_of_the_same_buffer_share_their_vertices_when_enabled() {
+    let positions = [(0.0, 0.0), (100.0, 40.0), (200.0, 80.0)];
+
+    let mut setup = Setup::new();
+    setup.prepare(&positions);
+    let walked = setup.text_renderer.vertex_bytes();
+    let cpu_bytes = setup.text_renderer.memory_usage().cpu_bytes;
+    assert_eq!(setup.text_renderer.duplicate_areas().translated, 0);
+
+    setup.text_renderer.set_share_buffer_geometry(true);
+    assert_eq!(setup.prepare(&positions), walked);
+    assert_eq!(setup.text_renderer.duplicate_areas().translated, 2);
+    // The shared geometry keeps its storage between prepares
+    assert!(setup.text_renderer.memory_usage().cpu_bytes > cpu_bytes);
+
+    // A fractional position differs from the shared geometry
+    setup.prepare(&[(0.0, 0.0), (100.5, 40.0)]);
+    assert_eq!(setup.text_renderer.duplicate_areas().translated, 0);
+}
@@ -0,0 +1,74 @@
+//! Tests that [`test_font_system`] contains only the embedded fonts, and that text shaped with it
+//! falls back to the color test font for the emoji it covers.
+
+use metalglyph::{
+    embedded_fonts::{INTER_FAMILY, TEST_EMOJI_FAMILY},
+    test_font_system, Attrs, Buffer, Family, FontSystem, Metrics, Shaping, SwashCache,
+    SwashContent,
+};
+
+/// Shapes `text` in the sans-serif family and returns the family and raster content of each
+/// glyph.
+fn shape(font_system: &mut FontSystem, text: &str) -> Vec<(String, SwashContent)> {
+    let mut swash_cache = SwashCache::new();
+    let mut buffer = Buffer::new(font_system, Metrics::new(32.0, 40.0));
+    buffer.set_text(
+        font_system,
+        text,
+        &Attrs::new().family(Family::SansSerif),
+        Shaping::Advanced,
+    );
+    buffer.shape_until_scroll(font_system, false);
+
+    let glyphs: Vec<_> = buffer
+        .layout_runs()
+        .flat_map(|run| run.glyphs.iter())
+        .map(|glyph| (glyph.font_id, glyph.physical((0.0, 0.0), 1.0).cache_key))
+        .collect();
+    glyphs
+        .into_iter()
+        .map(|(font_id, cache_key)| {
+            let family = font_system
+                .db()
+                .face(font_id)
+                .expect("Face of glyph")
+                .families[0]
+                .0
+                .clone();
+            let image = swash_cache
+                .get_image_uncached(font_system, cache_key)
+                .expect("Rasterize glyph");
+            (family, image.content)
+        })
+        .collect()
+}
+
+#[test]
+fn contains_only_embedded_fonts() {
+    let font_system = test_font_system();
+    let mut families: Vec<_> = font_system
+        .db()
+        .faces()
+        .map(|face| face.families[0].0.as_str())
+        .collect();
+    families.sort_unstable();
+
+    assert_eq!(families, [INTER_FAMILY, TEST_EMOJI_FAMILY]);
+    assert_eq!(font_system.locale(), "en-US");
+}
+
+#[test]
+fn emoji_fall_back_to_color_test_font() {
+    let mut font_system = test_font_system();
+    let glyphs = shape(&mut font_system, "A\u{1F600}\u{2764}\u{1F7E6}");
+
+    assert_eq!(
+        glyphs,
+        [
+            (INTER_FAMILY.to_owned(), SwashContent::Mask),
+            (TEST_EMOJI_FAMILY.to_owned(), SwashContent::Color),
+            (TEST_EMOJI_FAMILY.to_owned(), SwashContent::Color),
+            (TEST_EMOJI_FAMILY.to_owned(), SwashContent::Color),
+        ]
+    );
+}
@@ -0,0 +1,108 @@
+//! Tests that `render` places text at the encoder viewport set with
+//! `TextRenderer::set_encoder_viewport`.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test encoder_viewport -- --ignored
+//! ```
+
+use metalglyph::{
+    Buffer, Cache, EncoderViewport, Metrics, Resolution, Shaping, SwashCache, TextArea, TextAtlas,
+    TextRenderer, Viewport,
+};
+use objc2_metal::{MTLDevice as _, MTLPixelFormat};
+
+mod common;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+const TARGET_SIZE: usize = 256;
+const PANEL_SIZE: u32 = 128;
+
+/// Renders text filling a `PANEL_SIZE` viewport into a `TARGET_SIZE` target and returns the
+/// alpha of each pixel of the target.
+fn render_alpha(encoder_viewport: Option<EncoderViewport>) -> Vec<u8> {
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+    let mut viewport = Viewport::new(&device);
+    viewport.update(Resolution {
+        width: PANEL_SIZE,
+        height: PANEL_SIZE,
+    });
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+    text_renderer.set_encoder_viewport(encoder_viewport);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(32.0, 32.0));
+    buffer.set_size(
+        &mut font_system,
+        Some(PANEL_SIZE as f32),
+        Some(PANEL_SIZE as f32),
+    );
+    buffer.set_text(
+        &mut font_system,
+        "MMMM\nMMMM\nMMMM\nMMMM",
+        &common::attrs(),
+        Shaping::Advanced,
+    );
+    buffer.shape_until_scroll(&mut font_system, false);
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea::new(&buffer)],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+
+    let pixels = common::render_pixels(
+        &text_renderer,
+        &atlas,
+        &viewport,
+        &queue,
+        FORMAT,
+        TARGET_SIZE,
+        common::TRANSPARENT,
+    );
+
+    pixels.chunks_exact(4).map(|pixel| pixel[3]).collect()
+}
+
+/// Returns whether any pixel of the square of `PANEL_SIZE` at `origin` is covered.
+fn panel_has_text(alpha: &[u8], origin: (usize, usize)) -> bool {
+    let panel = PANEL_SIZE as usize;
+
+    (origin.1..origin.1 + panel)
+        .any(|y| (origin.0..origin.0 + panel).any(|x| alpha[y * TARGET_SIZE + x] > 0))
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn text_is_placed_at_the_encoder_viewport() {
+    let origin = (PANEL_SIZE, PANEL_SIZE);
+    let alpha = render_alpha(Some(EncoderViewport {
+        origin,
+        scissor: true,
+    }));
+
+    let panel = PANEL_SIZE as usize;
+    assert!(panel_has_text(&alpha, (panel, panel)));
+    assert!(!panel_has_text(&alpha, (0, 0)));
+    assert!(!panel_has_text(&alpha, (panel, 0)));
+    assert!(!panel_has_text(&alpha, (0, panel)));
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn encoder_viewport_is_left_alone_by_default() {
+    let alpha = render_alpha(None);
+
+    // The panel resolution is stretched over the whole target, so text starts in the top-left
+    assert!(panel_has_text(&alpha, (0, 0)));
+}
@@ -0,0 +1,133 @@
+//! Tests of the raw-pointer constructors of `metalglyph::interop`, passing objects the way other
+//! Metal bindings do: as `*mut c_void` pointers the caller keeps a reference to.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --features ffi-interop --test ffi_interop -- --ignored
+//! ```
+
+use metalglyph::{
+    Attrs, Buffer, Cache, ContentType, Family, FontSystem, Metrics, Resolution, Shaping,
+    SwashCache, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2::{msg_send, rc::Retained, runtime::NSObject, Message};
+use objc2_metal::{
+    MTLCommandBuffer as _, MTLCommandBufferStatus, MTLCommandEncoder as _, MTLCommandQueue as _,
+    MTLDevice as _, MTLLoadAction, MTLPixelFormat, MTLRenderPassDescriptor, MTLStoreAction,
+    MTLTextureDescriptor, MTLTextureUsage,
+};
+use std::ffi::c_void;
+
+mod common;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+
+fn as_raw<T: Message + ?Sized>(object: &Retained<T>) -> *mut c_void {
+    Retained::as_ptr(object) as *mut c_void
+}
+
+fn retain_count(object: *mut c_void) -> usize {
+    let object = unsafe { &*(object as *const NSObject) };
+    unsafe { msg_send![object, retainCount] }
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn constructors_retain_what_they_keep() {
+    let device = common::device();
+    let raw_device = as_raw(&device);
+    let retain_count_before = retain_count(raw_device);
+
+    let cache = unsafe { Cache::from_raw_device(raw_device) };
+    let mut atlas = unsafe { TextAtlas::from_raw(raw_device, &cache, FORMAT) };
+    let viewport = unsafe { Viewport::from_raw_device(raw_device) };
+    let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    drop((cache, atlas, viewport, text_renderer));
+
+    // Every reference taken by the constructors is released again
+    assert_eq!(retain_count(raw_device), retain_count_before);
+}
+
+#[test]
+#[should_panic(expected = "Device pointer is null")]
+fn null_device_panics() {
+    let _ = unsafe { Cache::from_raw_device(std::ptr::null_mut()) };
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn render_raw_borrows_the_encoder() {
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+    let raw_device = as_raw(&device);
+
+    let mut font_system = FontSystem::new();
+    let mut swash_cache = SwashCache::new();
+    let cache = unsafe { Cache::from_raw_device(raw_device) };
+    let mut atlas = unsafe { TextAtlas::from_raw(raw_device, &cache, FORMAT) };
+    let mut viewport = unsafe { Viewport::from_raw_device(raw_device) };
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(24.0, 32.0));
+    buffer.set_text(
+        &mut font_system,
+        "Hello from raw pointers",
+        &Attrs::new().family(Family::SansSerif),
+        Shaping::Advanced,
+    );
+
+    viewport.update(Resolution {
+        width: 256,
+        height: 64,
+    });
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea::new(&buffer)],
+            &mut swash_cache,
+        )
+        .unwrap();
+
+    assert!(!atlas.raw_texture(ContentType::Mask).is_null());
+
+    let descriptor = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            FORMAT, 256, 64, false,
+        )
+    };
+    descriptor.setUsage(MTLTextureUsage::RenderTarget);
+    let texture = device
+        .newTextureWithDescriptor(&descriptor)
+        .expect("Create texture");
+
+    let render_pass_descriptor = MTLRenderPassDescriptor::new();
+    let color_attachment = unsafe {
+        render_pass_descriptor
+            .colorAttachments()
+            .objectAtIndexedSubscript(0)
+    };
+    color_attachment.setTexture(Some(&texture));
+    color_attachment.setLoadAction(MTLLoadAction::Clear);
+    color_attachment.setStoreAction(MTLStoreAction::Store);
+
+    let command_buffer = queue.commandBuffer().expect("Create command buffer");
+    let encoder = command_buffer
+        .renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+        .expect("Create render command encoder");
+
+    let raw_encoder = as_raw(&encoder);
+    let retain_count_before = retain_count(raw_encoder);
+    unsafe { text_renderer.render_raw(&atlas, &viewport, raw_encoder) };
+    assert_eq!(retain_count(raw_encoder), retain_count_before);
+
+    encoder.endEncoding();
+    command_buffer.commit();
+    command_buffer.waitUntilCompleted();
+
+    assert_eq!(command_buffer.status(), MTLCommandBufferStatus::Completed);
+}
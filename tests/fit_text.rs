@@ -0,0 +1,91 @@
+//! Tests of fitting text into a rectangle, which need no Metal device.
+
+use metalglyph::{fit_text, measure, Wrap};
+
+mod common;
+
+#[test]
+fn short_text_is_laid_out_at_the_maximum_size() {
+    let mut font_system = common::font_system();
+    let fitted = fit_text(
+        &mut font_system,
+        "OK",
+        &common::attrs(),
+        (200.0, 100.0),
+        8.0,
+        32.0,
+        Wrap::Word,
+    );
+
+    assert!(fitted.fits);
+    assert_eq!(fitted.metrics.font_size, 32.0);
+}
+
+#[test]
+fn text_shrinks_to_fit() {
+    let mut font_system = common::font_system();
+    let rect = (120.0, 40.0);
+    let fitted = fit_text(
+        &mut font_system,
+        "Add to cart",
+        &common::attrs(),
+        rect,
+        6.0,
+        64.0,
+        Wrap::None,
+    );
+
+    assert!(fitted.fits);
+    assert!(fitted.metrics.font_size < 64.0);
+
+    let size = measure(&fitted.buffer);
+    assert!(size.width <= rect.0 && size.height <= rect.1);
+    assert_eq!(size.line_count, 1);
+
+    // A slightly larger size would overflow
+    let larger = fit_text(
+        &mut font_system,
+        "Add to cart",
+        &common::attrs(),
+        rect,
+        fitted.metrics.font_size + 0.5,
+        64.0,
+        Wrap::None,
+    );
+    assert!(!larger.fits);
+}
+
+#[test]
+fn wide_words_shrink_wrapped_text() {
+    let mut font_system = common::font_system();
+    let rect = (80.0, 200.0);
+    let fitted = fit_text(
+        &mut font_system,
+        "Incomprehensibilities abound",
+        &common::attrs(),
+        rect,
+        4.0,
+        48.0,
+        Wrap::Word,
+    );
+
+    assert!(fitted.fits);
+    assert!(measure(&fitted.buffer).width <= rect.0);
+}
+
+#[test]
+fn text_overflowing_at_the_minimum_size_is_flagged() {
+    let mut font_system = common::font_system();
+    let fitted = fit_text(
+        &mut font_system,
+        "This will never fit in such a tiny box",
+        &common::attrs(),
+        (20.0, 10.0),
+        12.0,
+        24.0,
+        Wrap::Word,
+    );
+
+    assert!(!fitted.fits);
+    assert_eq!(fitted.metrics.font_size, 12.0);
+}
@@ -0,0 +1,165 @@
+//! Tests that `prepare` reports the fonts that rendered each text area, and the faces that
+//! differ from the requested ones.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test font_usage -- --ignored
+//! ```
+
+use metalglyph::{
+    Buffer, Cache, FaceStyle, FontSynthesis, Metrics, Shaping, Style, SwashCache, TextArea,
+    TextRenderer, Weight,
+};
+use objc2_metal::MTLPixelFormat;
+
+mod common;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn uncovered_characters_are_reported_as_notdef() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let viewport = common::viewport(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+    text_renderer.set_font_usage(true);
+
+    // Inter is the only font, so nothing covers the CJK character
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), "ab漢");
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea::new(&buffer)],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+
+    let usage = text_renderer.font_usage().expect("Font usage is collected");
+    assert_eq!(usage.len(), 1);
+
+    let area = &usage[0];
+    assert!(area.has_missing_coverage());
+    assert_eq!(area.notdef_glyphs, 1);
+    assert_eq!(area.fonts.len(), 1);
+    assert_eq!(area.fonts[0].family.as_deref(), Some("Inter"));
+    assert_eq!(area.fonts[0].glyphs, 3);
+    assert_eq!(area.fonts[0].path, None);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn faces_lighter_than_requested_are_reported_and_emboldened() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let viewport = common::viewport(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+    text_renderer.set_font_usage(true);
+
+    // Inter Bold is the only face, so black text falls back to it, and so does regular text
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let mut buffer = |weight| {
+        let mut buffer = Buffer::new(&mut font_system, Metrics::new(24.0, 32.0));
+        buffer.set_text(
+            &mut font_system,
+            "Heavy",
+            &common::attrs().weight(weight),
+            Shaping::Advanced,
+        );
+        buffer.shape_until_scroll(&mut font_system, false);
+        buffer
+    };
+    let black = buffer(Weight::BLACK);
+    let regular = buffer(Weight::NORMAL);
+
+    let text_area = |buffer, font_synthesis| TextArea {
+        font_synthesis,
+        ..TextArea::new(buffer)
+    };
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [
+                text_area(&black, FontSynthesis::Embolden),
+                text_area(&regular, FontSynthesis::Embolden),
+            ],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+
+    let usage = text_renderer.font_usage().expect("Font usage is collected");
+    let bold = FaceStyle {
+        weight: Weight::BOLD,
+        style: Style::Normal,
+    };
+
+    // The bold face is lighter than black, so its glyphs are emboldened
+    let black_mismatches = &usage[0].face_mismatches;
+    assert_eq!(black_mismatches.len(), 1);
+    assert_eq!(black_mismatches[0].requested.weight, Weight::BLACK);
+    assert_eq!(black_mismatches[0].matched, bold);
+    assert_eq!(black_mismatches[0].glyphs, 5);
+    assert!(black_mismatches[0].emboldened);
+
+    // The bold face is heavier than regular, which synthesis cannot make up for
+    let regular_mismatches = &usage[1].face_mismatches;
+    assert_eq!(regular_mismatches.len(), 1);
+    assert_eq!(regular_mismatches[0].requested.weight, Weight::NORMAL);
+    assert!(!regular_mismatches[0].emboldened);
+
+    // Emboldened glyphs are cached apart from the same glyphs drawn as they are
+    let (emboldened, plain): (Vec<_>, Vec<_>) = atlas
+        .cached_glyphs()
+        .filter_map(|glyph| glyph.atlas_key.raster_config())
+        .partition(|config| config.embolden > 0.0);
+    assert_eq!(emboldened.len(), 5);
+    assert_eq!(plain.len(), 5);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn areas_are_reported_with_their_id_or_index() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let viewport = common::viewport(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+    text_renderer.set_font_usage(true);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), "Label");
+
+    let text_area = |top, id| TextArea {
+        top,
+        id,
+        ..TextArea::new(&buffer)
+    };
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [text_area(0.0, Some(42)), text_area(32.0, None)],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+
+    let usage = text_renderer.font_usage().expect("Font usage is collected");
+    let ids: Vec<_> = usage.iter().map(|area| area.area_id).collect();
+    assert_eq!(ids, [42, 1]);
+}
@@ -0,0 +1,207 @@
+//! Tests that misuses of the frame sequence of a renderer panic in debug builds.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test frame_validation -- --ignored
+//! ```
+//!
+//! Release builds only check the frame sequence with the `frame-validation` feature.
+
+use metalglyph::{
+    Buffer, Cache, FontSystem, FrameValidation, Metrics, Resolution, SwashCache, TextArea,
+    TextAtlas, TextRenderer, Viewport,
+};
+use objc2::{rc::Retained, runtime::ProtocolObject};
+use objc2_metal::{
+    MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue as _, MTLDevice, MTLPixelFormat,
+    MTLRenderPassDescriptor, MTLTextureDescriptor, MTLTextureUsage,
+};
+use std::mem;
+
+mod common;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+
+struct Frame {
+    device: Retained<ProtocolObject<dyn MTLDevice>>,
+    atlas: TextAtlas,
+    viewport: Viewport,
+    text_renderer: TextRenderer,
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    buffer: Buffer,
+}
+
+impl Frame {
+    fn new() -> Self {
+        let device = common::device();
+        let cache = Cache::new(&device);
+        let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+        let mut viewport = Viewport::new(&device);
+        viewport.update(Resolution {
+            width: 64,
+            height: 64,
+        });
+        let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+        let mut font_system = common::font_system();
+
+        let buffer = common::buffer(&mut font_system, Metrics::new(16.0, 20.0), "Frame");
+
+        Self {
+            device,
+            atlas,
+            viewport,
+            text_renderer,
+            font_system,
+            swash_cache: SwashCache::new(),
+            buffer,
+        }
+    }
+
+    fn prepare(&mut self) {
+        self.text_renderer
+            .prepare(
+                &mut self.font_system,
+                &mut self.atlas,
+                &self.viewport,
+                [TextArea::new(&self.buffer)],
+                &mut self.swash_cache,
+            )
+            .expect("Prepare text");
+    }
+
+    fn render(&self) {
+        let queue = self.device.newCommandQueue().expect("Create command queue");
+        let descriptor = unsafe {
+            MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+                FORMAT, 64, 64, false,
+            )
+        };
+        descriptor.setUsage(MTLTextureUsage::RenderTarget);
+        let texture = self
+            .device
+            .newTextureWithDescriptor(&descriptor)
+            .expect("Create texture");
+
+        let render_pass_descriptor = MTLRenderPassDescriptor::new();
+        let color_attachment = unsafe {
+            render_pass_descriptor
+                .colorAttachments()
+                .objectAtIndexedSubscript(0)
+        };
+        color_attachment.setTexture(Some(&texture));
+
+        let command_buffer = queue.commandBuffer().expect("Create command buffer");
+        let encoder = command_buffer
+            .renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            .expect("Create render command encoder");
+        self.text_renderer
+            .render(&self.atlas, &self.viewport, &encoder);
+        encoder.endEncoding();
+        command_buffer.commit();
+        command_buffer.waitUntilCompleted();
+    }
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn ordered_frames_pass() {
+    let mut frame = Frame::new();
+    frame.text_renderer.set_frame_validation(FrameValidation {
+        prepare_without_render: true,
+        ..FrameValidation::default()
+    });
+
+    for _ in 0..3 {
+        frame.prepare();
+        frame.render();
+        frame.render();
+        frame.atlas.trim();
+    }
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+#[should_panic(expected = "call `prepare` before rendering")]
+fn render_before_prepare_panics() {
+    Frame::new().render();
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn trim_between_renders_is_allowed() {
+    let mut frame = Frame::new();
+    frame.prepare();
+    frame.render();
+    frame.atlas.trim();
+    frame.render();
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+#[should_panic(
+    expected = "The atlas was trimmed and prepared against between `prepare` and `render`"
+)]
+fn prepare_after_trim_before_render_panics() {
+    let mut frame = Frame::new();
+    let other = TextRenderer::new(&mut frame.atlas, MTLPixelFormat::Invalid, 1);
+
+    frame.prepare();
+    let first = mem::replace(&mut frame.text_renderer, other);
+
+    // The other renderer may reuse the space of the glyphs of the first one
+    frame.atlas.trim();
+    frame.prepare();
+    frame.render();
+
+    frame.text_renderer = first;
+    frame.render();
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn skipped_frames_are_allowed_by_default() {
+    let mut frame = Frame::new();
+    assert!(
+        !frame
+            .text_renderer
+            .frame_validation()
+            .prepare_without_render
+    );
+
+    frame.prepare();
+    frame.prepare();
+    frame.render();
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+#[should_panic(expected = "never rendered")]
+fn prepare_without_render_panics_when_enabled() {
+    let mut frame = Frame::new();
+    frame.text_renderer.set_frame_validation(FrameValidation {
+        prepare_without_render: true,
+        ..FrameValidation::default()
+    });
+
+    frame.prepare();
+    frame.prepare();
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn disabled_checks_do_not_panic() {
+    let mut frame = Frame::new();
+    frame.text_renderer.set_frame_validation(FrameValidation {
+        render_before_prepare: false,
+        prepare_without_render: false,
+        trim_before_render: false,
+    });
+
+    frame.render();
+    frame.prepare();
+    frame.atlas.trim();
+    frame.render();
+}
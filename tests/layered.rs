@@ -0,0 +1,95 @@
+//! Tests that renderers built for layers draw into the chosen layer of a texture array.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test layered -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, Metrics, Resolution, SwashCache, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2_metal::{
+    MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue as _, MTLDevice as _,
+    MTLPixelFormat, MTLTextureDescriptor, MTLTextureType, MTLTextureUsage,
+};
+
+mod common;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+const SIZE: usize = 128;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn renderers_draw_into_their_layer() {
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+    let mut viewport = Viewport::new(&device);
+    viewport.update(Resolution {
+        width: SIZE as u32,
+        height: SIZE as u32,
+    });
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    // One renderer per composited window, each with its own text
+    let mut windows = Vec::new();
+    for text in ["Left", "Right"] {
+        let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 28.0), text);
+
+        let mut text_renderer = TextRenderer::builder(&mut atlas)
+            .layered(true)
+            .build()
+            .expect("Build renderer");
+        text_renderer
+            .prepare(
+                &mut font_system,
+                &mut atlas,
+                &viewport,
+                [TextArea {
+                    left: 4.0,
+                    top: 4.0,
+                    ..TextArea::new(&buffer)
+                }],
+                &mut swash_cache,
+            )
+            .expect("Prepare text");
+        windows.push(text_renderer);
+    }
+
+    let descriptor = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            FORMAT, SIZE, SIZE, false,
+        )
+    };
+    descriptor.setTextureType(MTLTextureType::Type2DArray);
+    unsafe { descriptor.setArrayLength(2) };
+    descriptor.setUsage(MTLTextureUsage::RenderTarget);
+    let texture = device
+        .newTextureWithDescriptor(&descriptor)
+        .expect("Create texture");
+
+    let render_pass_descriptor = common::render_pass(&texture, common::TRANSPARENT);
+    render_pass_descriptor.setRenderTargetArrayLength(2);
+
+    let command_buffer = queue.commandBuffer().expect("Create command buffer");
+    let encoder = command_buffer
+        .renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+        .expect("Create render command encoder");
+    for (layer, text_renderer) in windows.iter().enumerate() {
+        let stats = text_renderer.render_to_layer(&atlas, &viewport, &encoder, layer);
+        assert_eq!(stats.draw_calls, 1);
+    }
+    encoder.endEncoding();
+
+    let pixels = common::read_back(&command_buffer, &texture);
+    let (left, right) = pixels.split_at(pixels.len() / 2);
+
+    // Each layer holds the text of its renderer only
+    assert!(left.iter().any(|&byte| byte != 0));
+    assert!(right.iter().any(|&byte| byte != 0));
+    assert_ne!(left, right);
+}
@@ -0,0 +1,67 @@
+//! Tests that glyphs larger than the maximum glyph size are rasterized at that size.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test max_glyph_size -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, GlyphKey, Metrics, Resolution, SubpixelBin, SwashCache, TextArea, TextRenderer, Viewport,
+};
+use objc2_metal::MTLPixelFormat;
+
+mod common;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn large_glyphs_are_rasterized_at_the_maximum_size() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let mut viewport = Viewport::new(&device);
+    viewport.update(Resolution {
+        width: 4096,
+        height: 4096,
+    });
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    assert_eq!(text_renderer.max_glyph_size(), None);
+    assert!(text_renderer.effective_max_glyph_size(&atlas) > 0.0);
+    text_renderer.set_max_glyph_size(Some(128.0));
+    assert_eq!(text_renderer.effective_max_glyph_size(&atlas), 128.0);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(48.0, 56.0), "Zoom");
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea {
+                left: 0.3,
+                scale: 40.0,
+                ..TextArea::new(&buffer)
+            }],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+
+    let glyphs: Vec<_> = atlas.cached_glyphs().collect();
+    assert!(!glyphs.is_empty());
+
+    for glyph in glyphs {
+        let GlyphKey::Text(cache_key) = glyph.key else {
+            panic!("Unexpected glyph {:?}", glyph.key);
+        };
+
+        assert_eq!(f32::from_bits(cache_key.font_size_bits), 128.0);
+        assert_eq!(cache_key.x_bin, SubpixelBin::Zero);
+
+        let atlas_glyph = glyph.atlas_glyph.expect("Glyph in the atlas");
+        assert!(atlas_glyph.width <= 256 && atlas_glyph.height <= 256);
+    }
+}
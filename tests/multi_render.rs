@@ -0,0 +1,87 @@
+//! Tests that the text of one `prepare` can be rendered several times per frame.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test multi_render -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, Metrics, Resolution, SwashCache, TextArea, TextAtlas, TextRenderer, TrimPolicy, Viewport,
+};
+use objc2_metal::{MTLDevice as _, MTLPixelFormat};
+
+mod common;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+const SIZE: usize = 128;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn prepared_text_renders_identically_into_two_targets() {
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+    let mut viewport = Viewport::new(&device);
+    viewport.update(Resolution {
+        width: SIZE as u32,
+        height: SIZE as u32,
+    });
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 28.0), "Mirror\nmirror");
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea {
+                left: 4.0,
+                top: 4.0,
+                ..TextArea::new(&buffer)
+            }],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+
+    // A reflection first, then the drawable
+    let reflection = common::render_pixels(
+        &text_renderer,
+        &atlas,
+        &viewport,
+        &queue,
+        FORMAT,
+        SIZE,
+        common::TRANSPARENT,
+    );
+    let drawable = common::render_pixels(
+        &text_renderer,
+        &atlas,
+        &viewport,
+        &queue,
+        FORMAT,
+        SIZE,
+        common::TRANSPARENT,
+    );
+    assert!(reflection.iter().any(|&byte| byte > 0));
+    assert_eq!(reflection, drawable);
+
+    // Trimming, even evicting every glyph, leaves their texels alone until the next prepare
+    atlas.trim_with(TrimPolicy::ByteBudget(0));
+    assert_eq!(atlas.cached_glyphs().count(), 0);
+    let after_trim = common::render_pixels(
+        &text_renderer,
+        &atlas,
+        &viewport,
+        &queue,
+        FORMAT,
+        SIZE,
+        common::TRANSPARENT,
+    );
+    assert_eq!(reflection, after_trim);
+}
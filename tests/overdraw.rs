@@ -0,0 +1,139 @@
+//! Tests that `prepare` counts the pixels covered by the clipped glyph quads of each text area.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test overdraw -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, ContentType, Metrics, SwashCache, TextArea, TextAtlas, TextBounds, TextRenderer,
+};
+use objc2_metal::MTLPixelFormat;
+
+mod common;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn overlapping_and_clipped_areas_are_counted() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let viewport = common::viewport(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+    assert!(text_renderer.overdraw().is_none());
+    text_renderer.set_overdraw(true);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), "Overdraw");
+
+    let text_area = |left, top, bounds, id| TextArea {
+        left,
+        top,
+        bounds,
+        id,
+        ..TextArea::new(&buffer)
+    };
+    let clipped = TextBounds {
+        left: 0,
+        top: 0,
+        right: 20,
+        bottom: 20,
+    };
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [
+                text_area(0.0, 0.0, TextBounds::default(), Some(7)),
+                // A shadow offset by a whole pixel covers as many pixels as its text
+                text_area(1.0, 1.0, TextBounds::default(), None),
+                text_area(0.0, 0.0, clipped, None),
+            ],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+
+    let overdraw = text_renderer.overdraw().expect("Overdraw is counted");
+    assert_eq!(overdraw.viewport_pixels, 256 * 64);
+    assert_eq!(
+        overdraw
+            .areas
+            .iter()
+            .map(|area| area.area_id)
+            .collect::<Vec<_>>(),
+        [7, 1, 2]
+    );
+
+    let [text, shadow, clipped] = overdraw.areas[..] else {
+        panic!("Expected three areas");
+    };
+    assert_eq!(text.quads, 8);
+    assert_eq!(shadow.quads, 8);
+    assert_eq!(shadow.quad_pixels, text.quad_pixels);
+    assert!(clipped.quads > 0);
+    assert!(clipped.quad_pixels <= 20 * 20);
+
+    assert_eq!(overdraw.quads, text.quads + shadow.quads + clipped.quads);
+    assert_eq!(
+        overdraw.quad_pixels,
+        text.quad_pixels + shadow.quad_pixels + clipped.quad_pixels
+    );
+    assert_eq!(
+        overdraw.factor(),
+        (overdraw.quad_pixels as f64 / (256.0 * 64.0)) as f32
+    );
+    assert!(overdraw.area_factor(&text) < overdraw.factor());
+    assert_eq!(overdraw.largest_areas(2), [text, shadow]);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn unclipped_mask_glyph_counts_its_atlas_size() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), "W");
+
+    // Mask glyphs flag their width, and glyphs converted to linear colors flag their height
+    for format in [MTLPixelFormat::BGRA8Unorm, MTLPixelFormat::BGRA8Unorm_sRGB] {
+        let mut atlas = TextAtlas::new(&device, &cache, format);
+        let viewport = common::viewport(&device);
+        let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+        text_renderer.set_overdraw(true);
+
+        text_renderer
+            .prepare(
+                &mut font_system,
+                &mut atlas,
+                &viewport,
+                [TextArea {
+                    left: 8.0,
+                    top: 8.0,
+                    ..TextArea::new(&buffer)
+                }],
+                &mut swash_cache,
+            )
+            .expect("Prepare text");
+
+        let glyph = atlas
+            .cached_glyphs()
+            .find_map(|cached| cached.atlas_glyph)
+            .expect("Glyph is cached");
+        assert_eq!(glyph.content_type, ContentType::Mask);
+        let pixels = u64::from(glyph.width) * u64::from(glyph.height);
+        assert!(pixels > 0);
+
+        let overdraw = text_renderer.overdraw().expect("Overdraw is counted");
+        assert_eq!(overdraw.quads, 1);
+        assert_eq!(overdraw.quad_pixels, pixels);
+        assert_eq!(overdraw.areas[0].quad_pixels, pixels);
+    }
+}
@@ -0,0 +1,113 @@
+//! Tests of laying out text along a path, which need no Metal device.
+
+use metalglyph::{Buffer, GlyphPlacement, Metrics, PathOverflow, PathTextArea, Shaping, TextPath};
+use std::f32::consts::FRAC_PI_2;
+
+mod common;
+
+fn buffer(text: &str) -> Buffer {
+    let mut font_system = common::font_system();
+
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(20.0, 24.0));
+    buffer.set_size(&mut font_system, None, None);
+    buffer.set_text(&mut font_system, text, &common::attrs(), Shaping::Advanced);
+    buffer.shape_until_scroll(&mut font_system, false);
+    buffer
+}
+
+fn place(area: &PathTextArea) -> Vec<GlyphPlacement> {
+    let mut placements = Vec::new();
+    area.place_glyphs(1.0, &mut placements);
+    placements
+}
+
+/// Returns the physical point each placement is rotated around.
+fn pivots(placements: &[GlyphPlacement]) -> Vec<(f32, f32)> {
+    placements
+        .iter()
+        .map(|placement| {
+            let (x, y) = placement.transform.pivot.expect("path glyphs have a pivot");
+            (placement.x as f32 + x, placement.y as f32 + y)
+        })
+        .collect()
+}
+
+#[test]
+fn paths_measure_and_sample_their_segments() {
+    let path = TextPath::polyline([(0.0, 0.0), (30.0, 0.0), (30.0, 40.0)]);
+    assert_eq!(path.length(), 70.0);
+    assert_eq!(path.point_at(15.0), Some((15.0, 0.0)));
+    assert_eq!(path.point_at(50.0), Some((30.0, 20.0)));
+
+    // Distances outside of the path continue along its first and last segments
+    assert_eq!(path.point_at(-5.0), Some((-5.0, 0.0)));
+    assert_eq!(path.point_at(80.0), Some((30.0, 50.0)));
+
+    assert_eq!(TextPath::new((1.0, 2.0)).point_at(0.0), None);
+
+    let curve = TextPath::new((0.0, 0.0)).cubic_to((10.0, 0.0), (20.0, 0.0), (30.0, 0.0));
+    assert!((curve.length() - 30.0).abs() < 1e-3);
+}
+
+#[test]
+fn horizontal_paths_follow_the_layout() {
+    let buffer = buffer("Path");
+    let path = TextPath::polyline([(10.0, 50.0), (500.0, 50.0)]);
+    let placements = place(&PathTextArea::new(&buffer, &path));
+
+    let run = buffer.layout_runs().next().expect("Laid out line");
+    assert_eq!(placements.len(), run.glyphs.len());
+
+    for (placement, glyph) in placements.iter().zip(run.glyphs) {
+        assert_eq!(placement.transform.rotation, 0.0);
+        assert!((placement.x - (10.0 + glyph.x).floor() as i32).abs() <= 1);
+        assert_eq!(placement.y, 50);
+    }
+}
+
+#[test]
+fn glyphs_turn_with_the_path() {
+    let buffer = buffer("Down");
+    let path = TextPath::polyline([(50.0, 0.0), (50.0, 500.0)]);
+
+    for placement in place(&PathTextArea::new(&buffer, &path)) {
+        assert!((placement.transform.rotation - FRAC_PI_2).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn glyphs_past_the_end_are_dropped_or_extended() {
+    let buffer = buffer("Overflowing text");
+    let path = TextPath::polyline([(0.0, 0.0), (60.0, 0.0)]);
+    let glyph_count = buffer
+        .layout_runs()
+        .next()
+        .expect("Laid out line")
+        .glyphs
+        .len();
+
+    let dropped = place(&PathTextArea::new(&buffer, &path));
+    assert!(!dropped.is_empty());
+    assert!(dropped.len() < glyph_count);
+
+    let extended = place(&PathTextArea {
+        overflow: PathOverflow::Extend,
+        ..PathTextArea::new(&buffer, &path)
+    });
+    assert_eq!(extended.len(), glyph_count);
+}
+
+#[test]
+fn glyphs_keep_their_spacing_on_tight_curves() {
+    let buffer = buffer("WWWWWW");
+    let path = TextPath::polyline([(0.0, 100.0), (40.0, 100.0), (40.0, 0.0)]);
+    let run = buffer.layout_runs().next().expect("Laid out line");
+
+    let pivots = pivots(&place(&PathTextArea::new(&buffer, &path)));
+    assert!(pivots.len() > 2);
+
+    for (pair, glyph) in pivots.windows(2).zip(run.glyphs) {
+        let distance = (pair[1].0 - pair[0].0).hypot(pair[1].1 - pair[0].1);
+        assert!(distance >= glyph.w - 1.0, "{distance} < {}", glyph.w);
+    }
+}
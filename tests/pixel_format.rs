@@ -0,0 +1,76 @@
+//! Tests that renderers refuse pixel formats text cannot be rendered into.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test pixel_format -- --ignored
+//! ```
+
+use metalglyph::{BuildError, Cache, TextAtlas, TextRenderer, UnsupportedFormatReason};
+use objc2_metal::MTLPixelFormat;
+
+mod common;
+
+fn build_error(format: MTLPixelFormat) -> Option<BuildError> {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, format);
+
+    TextRenderer::builder(&mut atlas).build().err()
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn blendable_formats_build() {
+    for format in [
+        MTLPixelFormat::BGRA8Unorm,
+        MTLPixelFormat::BGRA8Unorm_sRGB,
+        MTLPixelFormat::RGBA16Float,
+        MTLPixelFormat::RGB10A2Unorm,
+    ] {
+        assert_eq!(build_error(format), None, "{format:?}");
+    }
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn unsupported_formats_name_the_reason() {
+    for (format, reason) in [
+        (MTLPixelFormat::RGBA8Uint, UnsupportedFormatReason::Integer),
+        (
+            MTLPixelFormat::Depth32Float,
+            UnsupportedFormatReason::DepthStencil,
+        ),
+        (
+            MTLPixelFormat::Invalid,
+            UnsupportedFormatReason::NotRenderable,
+        ),
+    ] {
+        let error = build_error(format).expect("Building with the format fails");
+        assert_eq!(error, BuildError::UnsupportedPixelFormat { format, reason });
+        assert!(
+            error.to_string().contains(&format!("{format:?}")),
+            "{error}"
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn unsupported_mask_formats_fail_to_build() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+
+    let error = TextRenderer::builder(&mut atlas)
+        .mask_format(MTLPixelFormat::R8Uint)
+        .build()
+        .err();
+    assert_eq!(
+        error,
+        Some(BuildError::UnsupportedPixelFormat {
+            format: MTLPixelFormat::R8Uint,
+            reason: UnsupportedFormatReason::Integer,
+        })
+    );
+}
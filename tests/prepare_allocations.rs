@@ -0,0 +1,135 @@
+//! Tests that preparing a static scene again performs no heap allocations, counting them with a
+//! global allocator.
+//!
+//! `prepare` writes into Metal buffers, so the tests need a Metal device and are ignored by
+//! default. Run them with:
+//!
+//! ```sh
+//! cargo test --test prepare_allocations -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, Metrics, Resolution, SwashCache, TextArea, TextAreaBatch, TextAtlas, TextBounds,
+    TextRenderer, Viewport,
+};
+use objc2_metal::MTLPixelFormat;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+mod common;
+
+/// Counts the allocations of the current thread while counting is enabled.
+struct CountingAllocator;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count(layout: Layout) {
+    if COUNTING.with(Cell::get) && layout.size() > 0 {
+        ALLOCATIONS.with(|allocations| allocations.set(allocations.get() + 1));
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout);
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(layout);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the number of allocations `f` performs on the current thread.
+fn allocations_of(f: impl FnOnce()) -> usize {
+    ALLOCATIONS.with(|allocations| allocations.set(0));
+    COUNTING.with(|counting| counting.set(true));
+    f();
+    COUNTING.with(|counting| counting.set(false));
+
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn preparing_a_static_scene_does_not_allocate() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let mut viewport = Viewport::new(&device);
+    viewport.update(Resolution {
+        width: 512,
+        height: 512,
+    });
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffers: Vec<_> = ["Score: 1200", "Lives: 3", "The quick brown fox\njumps over"]
+        .iter()
+        .map(|text| common::buffer(&mut font_system, Metrics::new(24.0, 32.0), text))
+        .collect();
+
+    let mut batch = TextAreaBatch::new();
+    for (i, buffer) in buffers.iter().enumerate() {
+        batch.push_area(TextArea {
+            left: 10.0,
+            top: 10.0 + 100.0 * i as f32,
+            bounds: TextBounds {
+                left: 0,
+                top: 0,
+                right: 300,
+                bottom: 512,
+            },
+            ..TextArea::new(buffer)
+        });
+    }
+
+    for geometry_cache in [false, true] {
+        text_renderer.set_geometry_cache(geometry_cache);
+
+        let mut prepare = |text_renderer: &mut TextRenderer, atlas: &mut TextAtlas| {
+            text_renderer
+                .prepare(&mut font_system, atlas, &viewport, &batch, &mut swash_cache)
+                .expect("Prepare text");
+        };
+
+        // The first frames rasterize the glyphs and size the scratch collections
+        for _ in 0..3 {
+            prepare(&mut text_renderer, &mut atlas);
+            atlas.trim();
+        }
+
+        let allocations = allocations_of(|| prepare(&mut text_renderer, &mut atlas));
+        assert_eq!(
+            allocations,
+            0,
+            "prepare allocated with the geometry cache {}",
+            if geometry_cache {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        atlas.trim();
+    }
+}
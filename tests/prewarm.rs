@@ -0,0 +1,102 @@
+//! Tests that prewarmed glyphs are not rasterized again when text using them is prepared.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test prewarm -- --ignored
+//! ```
+
+use metalglyph::{
+    Buffer, Cache, ContentType, GlyphKey, Metrics, PrewarmStats, Shaping, SwashCache, TextArea,
+    TextRenderer,
+};
+use objc2_metal::MTLPixelFormat;
+
+mod common;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn prewarmed_glyphs_are_not_rasterized_again() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let viewport = common::viewport(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+    let attrs = common::attrs();
+    let metrics = [Metrics::new(24.0, 32.0)];
+
+    let stats = text_renderer
+        .prewarm(
+            &mut font_system,
+            &mut atlas,
+            &mut swash_cache,
+            &attrs,
+            &metrics,
+            "abc",
+        )
+        .expect("Prewarm glyphs");
+    assert_eq!(
+        stats,
+        PrewarmStats {
+            added: 3,
+            already_present: 0,
+        }
+    );
+
+    let stats = text_renderer
+        .prewarm(
+            &mut font_system,
+            &mut atlas,
+            &mut swash_cache,
+            &attrs,
+            &metrics,
+            "cab",
+        )
+        .expect("Prewarm glyphs");
+    assert_eq!(
+        stats,
+        PrewarmStats {
+            added: 0,
+            already_present: 3,
+        }
+    );
+
+    // Warmed glyphs are kept in use until the next trim
+    let glyphs: Vec<_> = atlas.cached_glyphs().collect();
+    assert_eq!(glyphs.len(), 3);
+    for glyph in &glyphs {
+        assert!(glyph.in_use);
+        assert!(matches!(glyph.key, GlyphKey::Text(_)));
+        assert_eq!(
+            glyph
+                .atlas_glyph
+                .map(|atlas_glyph| atlas_glyph.content_type),
+            Some(ContentType::Mask)
+        );
+    }
+
+    let rasterizations = atlas.stats(ContentType::Mask).rasterizations;
+
+    // A single glyph at a whole-pixel position lands in the warmed subpixel bin
+    let mut buffer = Buffer::new(&mut font_system, metrics[0]);
+    buffer.set_text(&mut font_system, "b", &attrs, Shaping::Advanced);
+    buffer.shape_until_scroll(&mut font_system, false);
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea::new(&buffer)],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+
+    assert_eq!(
+        atlas.stats(ContentType::Mask).rasterizations,
+        rasterizations
+    );
+}
@@ -0,0 +1,84 @@
+//! Tests of the rectangles covering byte ranges of text areas, which need no Metal device.
+
+use metalglyph::{range_rects, Buffer, Cursor, Metrics, RangeRect, Shaping, TextArea};
+use std::ops::Range;
+
+mod common;
+
+fn buffer(text: &str, width: Option<f32>) -> Buffer {
+    let mut font_system = common::font_system();
+
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(20.0, 25.0));
+    buffer.set_size(&mut font_system, width, None);
+    buffer.set_text(&mut font_system, text, &common::attrs(), Shaping::Advanced);
+    buffer.shape_until_scroll(&mut font_system, false);
+    buffer
+}
+
+fn text_area(buffer: &Buffer) -> TextArea<'_> {
+    TextArea {
+        left: 10.5,
+        top: 20.0,
+        scale: 2.0,
+        ..TextArea::new(buffer)
+    }
+}
+
+fn range(line: usize, bytes: Range<usize>) -> Range<Cursor> {
+    Cursor::new(line, bytes.start)..Cursor::new(line, bytes.end)
+}
+
+fn rects(text_area: &TextArea, ranges: &[Range<Cursor>]) -> Vec<RangeRect> {
+    let mut rects = Vec::new();
+    range_rects(text_area, 1.0, ranges, &mut rects);
+    rects
+}
+
+#[test]
+fn ranges_cover_their_glyphs() {
+    let buffer = buffer("Hello world", None);
+    let area = text_area(&buffer);
+    let run = buffer.layout_runs().next().expect("Laid out line");
+    let world = run
+        .glyphs
+        .iter()
+        .find(|glyph| glyph.start == 6)
+        .expect("Glyph of 'w'");
+    let last = run.glyphs.last().expect("Glyph of 'd'");
+
+    let rects = rects(&area, &[range(0, 6..11), range(0, 3..3)]);
+    assert_eq!(rects.len(), 1);
+
+    let rect = rects[0];
+    assert_eq!(rect.range, 0);
+    assert_eq!(rect.line, 0);
+    assert!(!rect.rtl);
+    assert_eq!(rect.left, (10.5 + world.x * 2.0).floor() as i32);
+    assert_eq!(rect.right, (10.5 + (last.x + last.w) * 2.0).floor() as i32);
+    assert_eq!((rect.top, rect.bottom), (20, 70));
+    assert!(rect.top < rect.baseline && rect.baseline < rect.bottom);
+}
+
+#[test]
+fn ranges_are_split_at_line_breaks() {
+    let buffer = buffer("First line\nSecond line", None);
+    let area = text_area(&buffer);
+
+    let rects = rects(&area, &[Cursor::new(0, 6)..Cursor::new(1, 6)]);
+    assert_eq!(rects.len(), 2);
+    assert_eq!((rects[0].line, rects[1].line), (0, 1));
+    assert_eq!(rects[0].bottom, rects[1].top);
+    assert!(rects[1].left < rects[0].left);
+}
+
+#[test]
+fn ranges_are_split_at_wraps() {
+    let buffer = buffer("wrapped words wrapped words", Some(100.0));
+    let area = text_area(&buffer);
+    let lines = buffer.layout_runs().count();
+    assert!(lines > 1);
+
+    let rects = rects(&area, &[range(0, 0..27)]);
+    assert_eq!(rects.len(), lines);
+    assert!(rects.iter().all(|rect| rect.line == 0));
+}
@@ -0,0 +1,124 @@
+//! Tests that `render` reports the draw calls and resources it encoded.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test render_stats -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, ContentType, CustomGlyph, Metrics, RasterizedCustomGlyph, RenderStats, SwashCache,
+    TextArea, TextAtlas, TextRenderer,
+};
+use objc2_metal::{
+    MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue as _, MTLDevice as _,
+    MTLLoadAction, MTLPixelFormat, MTLRenderPassDescriptor, MTLStoreAction, MTLTextureDescriptor,
+    MTLTextureUsage,
+};
+
+mod common;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn render_reports_draw_calls_and_bound_atlases() {
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+    let viewport = common::viewport(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), "Stats");
+
+    let icon = [CustomGlyph {
+        id: 0,
+        left: 200.0,
+        top: 8.0,
+        width: 16.0,
+        height: 16.0,
+        color: None,
+        snap_to_physical_pixel: true,
+        metadata: 0,
+    }];
+
+    let texture = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            FORMAT, 256, 64, false,
+        )
+    };
+    texture.setUsage(MTLTextureUsage::RenderTarget);
+    let texture = device
+        .newTextureWithDescriptor(&texture)
+        .expect("Create texture");
+
+    let mut prepare_and_render = |custom_glyphs: &[CustomGlyph]| {
+        text_renderer
+            .prepare_with_custom(
+                &mut font_system,
+                &mut atlas,
+                &viewport,
+                [TextArea {
+                    custom_glyphs,
+                    ..TextArea::new(&buffer)
+                }],
+                &mut swash_cache,
+                |request| {
+                    Some(RasterizedCustomGlyph {
+                        data: vec![255; request.width as usize * request.height as usize * 4],
+                        content_type: ContentType::Color,
+                    })
+                },
+            )
+            .expect("Prepare text");
+
+        let render_pass_descriptor = MTLRenderPassDescriptor::new();
+        let color_attachment = unsafe {
+            render_pass_descriptor
+                .colorAttachments()
+                .objectAtIndexedSubscript(0)
+        };
+        color_attachment.setTexture(Some(&texture));
+        color_attachment.setLoadAction(MTLLoadAction::Clear);
+        color_attachment.setStoreAction(MTLStoreAction::Store);
+
+        let command_buffer = queue.commandBuffer().expect("Create command buffer");
+        let encoder = command_buffer
+            .renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            .expect("Create render command encoder");
+        let stats = text_renderer.render(&atlas, &viewport, &encoder);
+        encoder.endEncoding();
+        command_buffer.commit();
+        command_buffer.waitUntilCompleted();
+        atlas.trim();
+
+        stats
+    };
+
+    // Text alone only samples the mask atlas, in a single draw call
+    let stats = prepare_and_render(&[]);
+    assert_eq!(stats.draw_calls, 1);
+    assert_eq!(stats.pipeline_switches, 1);
+    assert_eq!(stats.glyphs, 5);
+    assert_eq!(stats.vertices, 20);
+    assert!(stats.mask_atlas_bound);
+    assert!(!stats.color_atlas_bound);
+
+    // A color glyph is drawn with the color pipeline in a draw call of its own
+    let stats = prepare_and_render(&icon);
+    assert_eq!(
+        stats,
+        RenderStats {
+            draw_calls: 2,
+            pipeline_switches: 2,
+            glyphs: 6,
+            vertices: 24,
+            color_atlas_bound: true,
+            mask_atlas_bound: true,
+        }
+    );
+}
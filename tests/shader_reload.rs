@@ -0,0 +1,116 @@
+//! Tests that the shaders of a `Cache` can be reloaded from source while renderers use it.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --features dev-tools --test shader_reload -- --ignored
+//! ```
+
+use metalglyph::{Cache, CacheError, Metrics, SwashCache, TextArea, TextAtlas, TextRenderer};
+use objc2_metal::{
+    MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue as _, MTLDevice as _,
+    MTLLoadAction, MTLPixelFormat, MTLRenderPassDescriptor, MTLStoreAction, MTLTextureDescriptor,
+    MTLTextureUsage,
+};
+
+mod common;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn invalid_sources_keep_the_current_shaders() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+
+    let error = cache
+        .reload_shader_from_source(&device, "fragment float4 fragment_main(")
+        .expect_err("Invalid source was accepted");
+    assert!(matches!(error, CacheError::Compilation(_)));
+
+    let renamed = Cache::shader_source().replace("fragment_luminance", "fragment_brightness");
+    assert_eq!(
+        cache.reload_shader_from_source(&device, &renamed),
+        Err(CacheError::MissingFunction("fragment_luminance".to_owned()))
+    );
+    assert_eq!(cache.shader_generation(), 0);
+
+    let edited = format!("{}\n// Edited\n", Cache::shader_source());
+    cache
+        .reload_shader_from_source(&device, &edited)
+        .expect("Reload shaders");
+    assert_eq!(cache.shader_generation(), 1);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn renderers_use_reloaded_shaders() {
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+    let viewport = common::viewport(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), "Reload");
+
+    let texture = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            FORMAT, 256, 64, false,
+        )
+    };
+    texture.setUsage(MTLTextureUsage::RenderTarget);
+    let texture = device
+        .newTextureWithDescriptor(&texture)
+        .expect("Create texture");
+
+    let render = |text_renderer: &TextRenderer, atlas: &TextAtlas| {
+        let render_pass_descriptor = MTLRenderPassDescriptor::new();
+        let color_attachment = unsafe {
+            render_pass_descriptor
+                .colorAttachments()
+                .objectAtIndexedSubscript(0)
+        };
+        color_attachment.setTexture(Some(&texture));
+        color_attachment.setLoadAction(MTLLoadAction::Clear);
+        color_attachment.setStoreAction(MTLStoreAction::Store);
+
+        let command_buffer = queue.commandBuffer().expect("Create command buffer");
+        let encoder = command_buffer
+            .renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+            .expect("Create render command encoder");
+        let stats = text_renderer.render(atlas, &viewport, &encoder);
+        encoder.endEncoding();
+        command_buffer.commit();
+        command_buffer.waitUntilCompleted();
+
+        stats
+    };
+
+    let edited = format!("{}\n// Edited\n", Cache::shader_source());
+    for frame in 0..2 {
+        text_renderer
+            .prepare(
+                &mut font_system,
+                &mut atlas,
+                &viewport,
+                [TextArea::new(&buffer)],
+                &mut swash_cache,
+            )
+            .expect("Prepare text");
+
+        // Reloading between prepare and render switches pipelines for that render
+        cache
+            .reload_shader_from_source(&device, &edited)
+            .expect("Reload shaders");
+        assert_eq!(cache.shader_generation(), frame + 1);
+
+        let stats = render(&text_renderer, &atlas);
+        assert_eq!(stats.draw_calls, 1);
+        assert_eq!(stats.glyphs, 6);
+        atlas.trim();
+    }
+}
@@ -0,0 +1,89 @@
+//! Tests of capturing renderer snapshots and replaying them offscreen.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --features debug-tools --test snapshot -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, Color, Metrics, Snapshot, SnapshotError, SwashCache, TextArea, TextAtlas, TextRenderer,
+};
+use objc2_metal::{MTLDevice as _, MTLPixelFormat};
+
+mod common;
+
+fn capture(atlas_pixels: bool) -> (Snapshot, Cache) {
+    let device = common::device();
+    let cache = Cache::new(&device);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let mut atlas = TextAtlas::new(&device, &cache, MTLPixelFormat::BGRA8Unorm_sRGB);
+    let viewport = common::viewport(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), "Hello");
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea {
+                left: 8.0,
+                top: 8.0,
+                default_color: Color::rgb(20, 90, 200),
+                ..TextArea::new(&buffer)
+            }],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+
+    (
+        text_renderer.capture_snapshot(&atlas, &viewport, atlas_pixels),
+        cache,
+    )
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn replay_survives_json_round_trip() {
+    let (snapshot, cache) = capture(true);
+
+    // "Hello" has 4 distinct glyphs
+    assert_eq!(snapshot.glyphs.len(), 4);
+    assert_eq!(snapshot.quads.len(), 5);
+
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+
+    let pixels = snapshot
+        .render_to_pixels(&device, &queue, &cache)
+        .expect("Replay snapshot");
+    assert_eq!((pixels.width, pixels.height), (256, 64));
+    assert!(pixels.data.chunks_exact(4).any(|pixel| pixel[3] > 0));
+
+    let loaded = Snapshot::from_json(&snapshot.to_json()).expect("Parse snapshot");
+    assert_eq!(loaded, snapshot);
+
+    let replayed = loaded
+        .render_to_pixels(&device, &queue, &cache)
+        .expect("Replay loaded snapshot");
+    assert_eq!(replayed, pixels);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn replay_needs_atlas_pixels() {
+    let (snapshot, cache) = capture(false);
+
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+
+    assert_eq!(
+        snapshot.render_to_pixels(&device, &queue, &cache),
+        Err(SnapshotError::MissingAtlasPixels)
+    );
+}
@@ -0,0 +1,141 @@
+//! Tests that a renderer built for stereo draws the same text into both layers of a layered
+//! render target.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test stereo -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, Metrics, Resolution, SlotError, StereoPath, SwashCache, TextArea, TextAtlas,
+    TextRenderer, Viewport,
+};
+use objc2_metal::{
+    MTLCommandBuffer as _, MTLCommandEncoder as _, MTLCommandQueue as _, MTLDevice as _,
+    MTLLoadAction, MTLPixelFormat, MTLRenderPassDescriptor, MTLStoreAction, MTLTextureDescriptor,
+    MTLTextureType, MTLTextureUsage,
+};
+
+mod common;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn stereo_renders_both_views() {
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+    let mut viewport = common::viewport(&device);
+    // The second eye sees the HUD a few pixels to the left
+    viewport.set_active_slot(1).expect("Create viewport slot");
+    viewport.update(Resolution {
+        width: 256,
+        height: 64,
+    });
+    viewport.set_origin(-4, 0);
+    viewport.set_active_slot(0).expect("Create viewport slot");
+
+    let mut text_renderer = TextRenderer::builder(&mut atlas)
+        .stereo(true)
+        .build()
+        .expect("Build renderer");
+    let path = StereoPath::for_device(&device);
+    assert_eq!(text_renderer.stereo_path(), Some(path));
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), "HUD");
+
+    text_renderer
+        .prepare(
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea {
+                left: 8.0,
+                top: 8.0,
+                ..TextArea::new(&buffer)
+            }],
+            &mut swash_cache,
+        )
+        .expect("Prepare text");
+
+    let texture = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            FORMAT, 256, 64, false,
+        )
+    };
+    texture.setTextureType(MTLTextureType::Type2DArray);
+    unsafe { texture.setArrayLength(2) };
+    texture.setUsage(MTLTextureUsage::RenderTarget);
+    let texture = device
+        .newTextureWithDescriptor(&texture)
+        .expect("Create texture");
+
+    let render_pass_descriptor = MTLRenderPassDescriptor::new();
+    render_pass_descriptor.setRenderTargetArrayLength(2);
+    let color_attachment = unsafe {
+        render_pass_descriptor
+            .colorAttachments()
+            .objectAtIndexedSubscript(0)
+    };
+    color_attachment.setTexture(Some(&texture));
+    color_attachment.setLoadAction(MTLLoadAction::Clear);
+    color_attachment.setStoreAction(MTLStoreAction::Store);
+
+    let command_buffer = queue.commandBuffer().expect("Create command buffer");
+    let encoder = command_buffer
+        .renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+        .expect("Create render command encoder");
+    let stats = text_renderer.render_stereo(&atlas, &viewport, &encoder, [0, 1]);
+    encoder.endEncoding();
+    command_buffer.commit();
+    command_buffer.waitUntilCompleted();
+
+    // Vertex amplification draws both views at once
+    let draw_calls = match path {
+        StereoPath::VertexAmplification => 1,
+        StereoPath::DrawPerView => 2,
+    };
+    assert_eq!(stats.draw_calls, draw_calls);
+    assert_eq!(stats.pipeline_switches, 1);
+    assert_eq!(stats.glyphs, 2 * 3);
+    assert_eq!(stats.vertices, 2 * 12);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn renderers_are_not_stereo_by_default() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+    let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    assert_eq!(text_renderer.stereo_path(), None);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn slots_beyond_the_maximum_are_rejected() {
+    let device = common::device();
+    let mut viewport = common::viewport(&device);
+
+    viewport
+        .set_active_slot(Viewport::MAX_SLOTS - 1)
+        .expect("Create viewport slot");
+    assert_eq!(viewport.slot_count(), Viewport::MAX_SLOTS);
+
+    for slot in [Viewport::MAX_SLOTS, usize::MAX] {
+        assert_eq!(
+            viewport.set_active_slot(slot),
+            Err(SlotError::OutOfRange(slot))
+        );
+    }
+    // A rejected slot keeps the active slot and the buffer
+    assert_eq!(viewport.active_slot(), Viewport::MAX_SLOTS - 1);
+    assert_eq!(viewport.slot_count(), Viewport::MAX_SLOTS);
+}
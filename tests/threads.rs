@@ -0,0 +1,123 @@
+//! Tests that text can be prepared on a worker thread and rendered on another.
+//!
+//! The rendering test needs a Metal device and is ignored by default. Run it with:
+//!
+//! ```sh
+//! cargo test --test threads -- --ignored
+//! ```
+//!
+//! or under the thread sanitizer with:
+//!
+//! ```sh
+//! RUSTFLAGS=-Zsanitizer=thread cargo +nightly test -Zbuild-std \
+//!     --target aarch64-apple-darwin --test threads -- --ignored
+//! ```
+
+use metalglyph::{
+    Attrs, Buffer, Cache, Family, FontSystem, Metrics, OffscreenRenderer, Resolution, Shaping,
+    SharedFontSystem, SharedTextAtlas, SwashCache, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2_metal::{
+    MTLCommandBuffer as _, MTLCommandBufferStatus, MTLCommandEncoder as _, MTLCommandQueue as _,
+    MTLDevice as _, MTLLoadAction, MTLPixelFormat, MTLRenderPassDescriptor, MTLStoreAction,
+    MTLTextureDescriptor, MTLTextureUsage,
+};
+use std::thread;
+
+mod common;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+
+#[test]
+fn send_and_sync() {
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    assert_send::<Cache>();
+    assert_sync::<Cache>();
+    assert_send::<TextAtlas>();
+    assert_send::<TextRenderer>();
+    assert_send::<SharedFontSystem>();
+    assert_sync::<SharedFontSystem>();
+    assert_send::<SharedTextAtlas>();
+    assert_sync::<SharedTextAtlas>();
+    assert_send::<Viewport>();
+    assert_send::<OffscreenRenderer>();
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn prepare_on_worker_thread() {
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+    let mut viewport = Viewport::new(&device);
+    let text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let worker = thread::spawn(move || {
+        let mut text_renderer = text_renderer;
+        let mut font_system = FontSystem::new();
+        let mut swash_cache = SwashCache::new();
+
+        let mut buffer = Buffer::new(&mut font_system, Metrics::new(24.0, 32.0));
+        buffer.set_text(
+            &mut font_system,
+            "Prepared on a worker thread",
+            &Attrs::new().family(Family::SansSerif),
+            Shaping::Advanced,
+        );
+
+        viewport.update(Resolution {
+            width: 256,
+            height: 64,
+        });
+
+        text_renderer
+            .prepare(
+                &mut font_system,
+                &mut atlas,
+                &viewport,
+                [TextArea::new(&buffer)],
+                &mut swash_cache,
+            )
+            .unwrap();
+
+        (text_renderer, atlas, viewport)
+    });
+    let (text_renderer, atlas, viewport) = worker.join().unwrap();
+
+    let descriptor = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            FORMAT, 256, 64, false,
+        )
+    };
+    descriptor.setUsage(MTLTextureUsage::RenderTarget);
+    let texture = device
+        .newTextureWithDescriptor(&descriptor)
+        .expect("Create texture");
+
+    let render_pass_descriptor = MTLRenderPassDescriptor::new();
+    let color_attachment = unsafe {
+        render_pass_descriptor
+            .colorAttachments()
+            .objectAtIndexedSubscript(0)
+    };
+    color_attachment.setTexture(Some(&texture));
+    color_attachment.setLoadAction(MTLLoadAction::Clear);
+    color_attachment.setStoreAction(MTLStoreAction::Store);
+
+    let command_buffer = queue.commandBuffer().expect("Create command buffer");
+    let encoder = command_buffer
+        .renderCommandEncoderWithDescriptor(&render_pass_descriptor)
+        .expect("Create render command encoder");
+
+    text_renderer.render(&atlas, &viewport, &encoder);
+
+    encoder.endEncoding();
+    command_buffer.commit();
+    command_buffer.waitUntilCompleted();
+
+    assert_eq!(command_buffer.status(), MTLCommandBufferStatus::Completed);
+}
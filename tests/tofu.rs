@@ -0,0 +1,75 @@
+//! Tests of drawing glyphs missing from their font as boxes.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test tofu -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, ContentType, GlyphKey, Metrics, SwashCache, TextArea, TextAtlas, TextRenderer,
+};
+use objc2_metal::MTLPixelFormat;
+
+mod common;
+
+/// Two private use characters, which Inter has no glyphs for, between letters.
+const TEXT: &str = "A\u{e000}\u{e001}B";
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn missing_glyphs_are_drawn_as_boxes() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = common::atlas(&device, &cache);
+    let viewport = common::viewport(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(24.0, 32.0), TEXT);
+
+    let mut prepare = |text_renderer: &mut TextRenderer, atlas: &mut TextAtlas| {
+        text_renderer
+            .prepare(
+                &mut font_system,
+                atlas,
+                &viewport,
+                [TextArea {
+                    left: 8.0,
+                    top: 8.0,
+                    ..TextArea::new(&buffer)
+                }],
+                &mut swash_cache,
+            )
+            .expect("Prepare text");
+    };
+
+    assert!(!text_renderer.tofu());
+    prepare(&mut text_renderer, &mut atlas);
+    assert_eq!(atlas.stats(ContentType::Mask).tofu, 0);
+
+    text_renderer.set_tofu(true);
+    prepare(&mut text_renderer, &mut atlas);
+    assert_eq!(atlas.stats(ContentType::Mask).tofu, 2);
+
+    // Both boxes have the same size, so they share a single rasterization
+    let boxes: Vec<_> = atlas
+        .cached_glyphs()
+        .filter(|glyph| matches!(glyph.key, GlyphKey::Tofu { .. }))
+        .collect();
+    assert_eq!(boxes.len(), 1);
+
+    let GlyphKey::Tofu { advance, height } = boxes[0].key else {
+        unreachable!();
+    };
+    let atlas_glyph = boxes[0].atlas_glyph.expect("Box in the atlas");
+    assert_eq!(atlas_glyph.content_type, ContentType::Mask);
+    assert_eq!(atlas_glyph.height, height);
+    assert_eq!(atlas_glyph.top, height as i16);
+    assert!(atlas_glyph.width < advance);
+
+    // Inter has a cap height of about 0.73 em
+    assert!((16..=19).contains(&height), "{height}");
+}
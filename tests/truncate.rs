@@ -0,0 +1,105 @@
+//! Tests of `truncate_lines`, laying out text with the embedded Inter font so that results do not
+//! depend on system fonts.
+
+use metalglyph::{measure, truncate_lines, Buffer, FontSystem, Metrics, Shaping};
+
+mod common;
+
+const TEXT: &str = "The quick brown fox jumps over the lazy dog and keeps running far away";
+
+fn buffer(font_system: &mut FontSystem, text: &str, width: f32) -> Buffer {
+    let mut buffer = Buffer::new(font_system, Metrics::new(16.0, 20.0));
+    buffer.set_size(font_system, Some(width), None);
+    buffer.set_text(font_system, text, &common::attrs(), Shaping::Advanced);
+    buffer.shape_until_scroll(font_system, false);
+    buffer
+}
+
+fn text(buffer: &Buffer) -> String {
+    buffer
+        .lines
+        .iter()
+        .map(|line| line.text())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn text_that_fits_is_untouched() {
+    let mut font_system = common::font_system();
+    let mut buffer = buffer(&mut font_system, "Short", 200.0);
+
+    let truncated = truncate_lines(
+        &mut font_system,
+        &mut buffer,
+        2,
+        "…",
+        &common::attrs(),
+        Shaping::Advanced,
+    );
+
+    assert!(!truncated);
+    assert_eq!(text(&buffer), "Short");
+}
+
+#[test]
+fn long_text_ends_with_ellipsis() {
+    let mut font_system = common::font_system();
+    let mut buffer = buffer(&mut font_system, TEXT, 120.0);
+    assert!(measure(&buffer).line_count > 2);
+
+    let truncated = truncate_lines(
+        &mut font_system,
+        &mut buffer,
+        2,
+        "…",
+        &common::attrs(),
+        Shaping::Advanced,
+    );
+
+    let truncated_text = text(&buffer);
+    assert!(truncated);
+    assert_eq!(measure(&buffer).line_count, 2);
+    assert!(truncated_text.ends_with('…'));
+    assert!(TEXT.starts_with(truncated_text.trim_end_matches('…').trim_end()));
+}
+
+#[test]
+fn clusters_are_not_split() {
+    let mut font_system = common::font_system();
+    let family = "👨‍👩‍👧‍👦".repeat(20);
+    let mut buffer = buffer(&mut font_system, &family, 60.0);
+
+    truncate_lines(
+        &mut font_system,
+        &mut buffer,
+        1,
+        "…",
+        &common::attrs(),
+        Shaping::Advanced,
+    );
+
+    let truncated_text = text(&buffer);
+    let kept = truncated_text.trim_end_matches('…');
+    assert_eq!(kept.len() % "👨‍👩‍👧‍👦".len(), 0);
+}
+
+#[test]
+fn ellipsis_that_does_not_fit_is_dropped() {
+    let mut font_system = common::font_system();
+    let mut buffer = buffer(&mut font_system, TEXT, 40.0);
+    let ellipsis = "and much more text than fits";
+
+    let truncated = truncate_lines(
+        &mut font_system,
+        &mut buffer,
+        1,
+        ellipsis,
+        &common::attrs(),
+        Shaping::Advanced,
+    );
+
+    assert!(truncated);
+    assert_eq!(measure(&buffer).line_count, 1);
+    assert!(!text(&buffer).ends_with(ellipsis));
+}
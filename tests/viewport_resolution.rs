@@ -0,0 +1,197 @@
+//! Tests that text is skipped while the viewport has no renderable resolution, e.g. while a
+//! window is minimized.
+//!
+//! The tests need a Metal device and are ignored by default. Run them with:
+//!
+//! ```sh
+//! cargo test --test viewport_resolution -- --ignored
+//! ```
+
+use metalglyph::{
+    Cache, Metrics, Resolution, SwashCache, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use objc2_metal::{MTLDevice as _, MTLPixelFormat};
+
+mod common;
+
+const FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm;
+const SIZE: u32 = 128;
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn degenerate_resolutions_are_not_renderable() {
+    let device = common::device();
+    let mut viewport = Viewport::new(&device);
+
+    assert!(!viewport.is_renderable());
+
+    for (width, height, renderable) in [
+        (SIZE, SIZE, true),
+        (0, SIZE, false),
+        (SIZE, 0, false),
+        (Viewport::MAX_DIMENSION, 1, true),
+        (Viewport::MAX_DIMENSION + 1, 1, false),
+        (u32::MAX, u32::MAX, false),
+    ] {
+        viewport.update(Resolution { width, height });
+        assert_eq!(viewport.is_renderable(), renderable, "{width}x{height}");
+    }
+
+    // A render scale that pushes the render resolution beyond the maximum
+    viewport.update(Resolution {
+        width: Viewport::MAX_DIMENSION,
+        height: SIZE,
+    });
+    viewport.set_render_scale(2.0);
+    assert!(!viewport.is_renderable());
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn degenerate_scale_factors_fall_back_to_one() {
+    let device = common::device();
+    let mut viewport = Viewport::new(&device);
+    let resolution = Resolution {
+        width: SIZE,
+        height: SIZE,
+    };
+
+    for scale_factor in [0.0, -2.0, f32::NAN, f32::INFINITY] {
+        viewport.update_with_scale(resolution, 2.0);
+        viewport.update_with_scale(resolution, scale_factor);
+
+        assert_eq!(viewport.scale_factor(), 1.0, "{scale_factor}");
+        assert!(viewport.is_renderable(), "{scale_factor}");
+    }
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn update_keeps_the_scale_factor() {
+    let device = common::device();
+    let mut viewport = Viewport::new(&device);
+
+    viewport.update_with_scale(
+        Resolution {
+            width: SIZE,
+            height: SIZE,
+        },
+        2.0,
+    );
+    viewport.update(Resolution {
+        width: 2 * SIZE,
+        height: 2 * SIZE,
+    });
+
+    assert_eq!(viewport.scale_factor(), 2.0);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn minimizing_and_restoring_skips_then_resumes_text() {
+    let device = common::device();
+    let queue = device.newCommandQueue().expect("Create command queue");
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+    let mut viewport = Viewport::new(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(32.0, 32.0), "MMMM\nMMMM");
+
+    let texture = common::render_target(&device, FORMAT, SIZE as usize);
+
+    let mut frame = |resolution: Resolution| {
+        viewport.update(resolution);
+        text_renderer
+            .prepare(
+                &mut font_system,
+                &mut atlas,
+                &viewport,
+                [TextArea::new(&buffer)],
+                &mut swash_cache,
+            )
+            .expect("Prepare text");
+
+        let pixels = common::render_into(
+            &text_renderer,
+            &atlas,
+            &viewport,
+            &queue,
+            &texture,
+            common::TRANSPARENT,
+        );
+        let alpha: Vec<u8> = pixels.chunks_exact(4).map(|pixel| pixel[3]).collect();
+        (text_renderer.vertex_bytes(), alpha)
+    };
+
+    let restored = Resolution {
+        width: SIZE,
+        height: SIZE,
+    };
+    let (vertex_bytes, before) = frame(restored);
+    assert!(vertex_bytes > 0);
+    assert!(before.iter().any(|&alpha| alpha > 0));
+
+    // Minimizing the window produces a zero-sized drawable
+    let (vertex_bytes, minimized) = frame(Resolution {
+        width: 0,
+        height: 0,
+    });
+    assert_eq!(vertex_bytes, 0);
+    assert!(minimized.iter().all(|&alpha| alpha == 0));
+
+    let (_, after) = frame(restored);
+    assert_eq!(before, after);
+}
+
+#[test]
+#[ignore = "needs a Metal device"]
+fn unrenderable_viewport_skips_duplicate_areas() {
+    let device = common::device();
+    let cache = Cache::new(&device);
+    let mut atlas = TextAtlas::new(&device, &cache, FORMAT);
+    let mut viewport = Viewport::new(&device);
+    let mut text_renderer = TextRenderer::new(&mut atlas, MTLPixelFormat::Invalid, 1);
+    text_renderer.set_skip_duplicate_areas(true);
+
+    let mut font_system = common::font_system();
+    let mut swash_cache = SwashCache::new();
+
+    let buffer = common::buffer(&mut font_system, Metrics::new(32.0, 32.0), "MMMM");
+
+    let text_area = TextArea::new(&buffer);
+
+    let mut skipped = |resolution: Resolution| {
+        viewport.update(resolution);
+        text_renderer
+            .prepare(
+                &mut font_system,
+                &mut atlas,
+                &viewport,
+                [text_area.clone(), text_area.clone()],
+                &mut swash_cache,
+            )
+            .expect("Prepare text");
+        text_renderer.duplicate_areas().skipped
+    };
+
+    assert_eq!(
+        skipped(Resolution {
+            width: SIZE,
+            height: SIZE,
+        }),
+        1
+    );
+
+    // The areas of an unrenderable viewport are not even compared
+    assert_eq!(
+        skipped(Resolution {
+            width: 0,
+            height: 0,
+        }),
+        0
+    );
+}